@@ -0,0 +1,65 @@
+//! Record pending-pod arrivals and departures from the current cluster
+//!
+//! Usage: cargo run --bin record_trace -- <trace.jsonl>
+//!
+//! Runs until interrupted (Ctrl-C). Replay the result with `growthrs simulate --trace`.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::jiff::Timestamp;
+use kube::runtime::watcher;
+use kube::{Api, Client};
+
+use growthrs::simulation::trace::{TraceRecorder, write_event};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: record_trace <trace.jsonl>");
+        std::process::exit(1);
+    };
+    let mut out = BufWriter::new(File::create(&path).with_context(|| format!("creating {path}"))?);
+
+    let client = Client::try_default().await?;
+    let pods: Api<Pod> = Api::all(client);
+    let mut stream = std::pin::pin!(watcher::watcher(pods, watcher::Config::default()));
+    let mut recorder = TraceRecorder::new(Timestamp::now());
+    let mut recorded = 0usize;
+
+    eprintln!("recording to {path}, Ctrl-C to stop");
+    loop {
+        tokio::select! {
+            item = stream.next() => {
+                let event = match item {
+                    Some(Ok(event)) => event,
+                    Some(Err(e)) => {
+                        eprintln!("watch error: {e}");
+                        continue;
+                    }
+                    None => break,
+                };
+                let now = Timestamp::now();
+                let trace_event = match event {
+                    watcher::Event::Apply(pod) | watcher::Event::InitApply(pod) => {
+                        recorder.observe_apply(&pod, now)
+                    }
+                    watcher::Event::Delete(pod) => recorder.observe_delete(&pod, now),
+                    watcher::Event::Init | watcher::Event::InitDone => None,
+                };
+                if let Some(trace_event) = trace_event {
+                    write_event(&mut out, &trace_event)?;
+                    out.flush()?;
+                    recorded += 1;
+                }
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    out.flush()?;
+    eprintln!("recorded {recorded} events");
+    Ok(())
+}
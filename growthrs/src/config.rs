@@ -1,26 +1,163 @@
 use envconfig::Envconfig;
 use kube::Client;
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::watch;
 
 use crate::{
     clock::{Clock, SystemClock},
+    controller::audit::AuditLog,
+    controller::backoff::ErrorBackoff,
+    controller::config_reload::ConfigReloader,
+    controller::cost_guard::{BudgetAdmissions, CostGuard},
     controller::errors::ConfigError,
-    providers::{
-        fake::FakeProvider,
-        hetzner::HetznerProvider,
-        kwok::KwokProvider,
-        provider::{Provider, ProviderName},
+    controller::metrics::Metrics,
+    controller::node_removal::executor::RemovalExecutor,
+    controller::offering_stats::OfferingStatsStore,
+    controller::pod_scope::PodScope,
+    controller::provider_config::ProviderReloader,
+    controller::provider_health::ProviderHealthMonitor,
+    controller::shard::Shard,
+    controller::webhook::WebhookConfig,
+    offering::stats::PenaltyWeights,
+    optimiser::{
+        CostObjective, SOLVERS, ScoredSolver, SolveOptions, SolverBackend, solver_by_name,
     },
+    providers::chaos::ChaosConfig,
+    providers::kwok::{KwokCatalog, KwokOptions},
+    providers::provider::{Provider, ProviderConfig, ProviderError, ProviderRegistry},
+    providers::recording::RecordingProvider,
+    providers::reloadable::ReloadableProvider,
+    providers::retry::RetryPolicy,
+    resources::ConversionService,
 };
 
 #[derive(Envconfig)]
 struct RawConfig {
     #[envconfig(from = "GROWTH_PROVIDER")]
-    /// Provider being used (kwok/fake/hetzner)
+    /// Provider(s) being used, comma-separated (kwok/fake/hetzner)
     pub provider: String,
+    #[envconfig(from = "GROWTH_PROVIDER_CONFIG", default = "default")]
+    /// Name of the cluster-scoped ProviderConfig that overrides `GROWTH_PROVIDER` when present
+    pub provider_config: String,
     #[envconfig(from = "GROWTH_PROVISIONING_TIMEOUT")]
     /// Provisioning timeout in seconds
     pub provisioning_timeout: u64,
+    #[envconfig(from = "GROWTH_MAX_INVENTORY_DRIFT", default = "5")]
+    /// Maximum number of instances the provider and cluster may disagree on at startup
+    pub max_inventory_drift: usize,
+    #[envconfig(from = "GROWTH_NAMESPACE")]
+    /// Namespace the controller runs in; offering stats are persisted here when set
+    pub namespace: Option<String>,
+    #[envconfig(from = "GROWTH_OFFERING_FAILURE_PENALTY", default = "0")]
+    /// Solver penalty per unit of historical create failure rate (0 disables)
+    pub offering_failure_penalty: f64,
+    #[envconfig(from = "GROWTH_OFFERING_JOIN_PENALTY", default = "0")]
+    /// Solver penalty per minute of mean node join time (0 disables)
+    pub offering_join_penalty: f64,
+    #[envconfig(from = "GROWTH_OFFERINGS_CACHE_TTL", default = "60")]
+    /// Seconds to cache provider offerings before refreshing in the background (0 disables)
+    pub offerings_cache_ttl: u64,
+    #[envconfig(from = "GROWTH_PROVIDER_RATE_LIMIT", default = "5")]
+    /// Provider API calls per second, per provider (0 disables)
+    pub provider_rate_limit: f64,
+    #[envconfig(from = "GROWTH_PROVIDER_MAX_RETRIES", default = "3")]
+    /// Retries for rate-limited or transient provider errors, with exponential backoff
+    pub provider_max_retries: u32,
+    #[envconfig(from = "GROWTH_SHARD")]
+    /// Manage only NodePools labelled `growth.vettrdev.com/shard=<value>`
+    pub shard: Option<String>,
+    #[envconfig(from = "GROWTH_SHARD_INDEX", default = "0")]
+    /// This replica's index when sharding pools by name hash
+    pub shard_index: u32,
+    #[envconfig(from = "GROWTH_SHARD_COUNT", default = "1")]
+    /// Number of replicas pools are hashed across (1 disables sharding)
+    pub shard_count: u32,
+    #[envconfig(from = "GROWTH_COST_ANOMALY_FACTOR", default = "0")]
+    /// Hold plans adding more than this multiple of the rolling hourly-cost baseline (0 disables)
+    pub cost_anomaly_factor: f64,
+    #[envconfig(from = "GROWTH_COST_ANOMALY_MIN_BASELINE", default = "1")]
+    /// Floor for the cost baseline in $/h, so small clusters can still grow
+    pub cost_anomaly_min_baseline: f64,
+    #[envconfig(from = "GROWTH_COST_ANOMALY_OVERRIDE", default = "false")]
+    /// Admit plans over the cost anomaly limit
+    pub cost_anomaly_override: bool,
+    #[envconfig(from = "GROWTH_PROVIDER_RECORD")]
+    /// Append every provider call and response to this file, for replay
+    pub provider_record: Option<String>,
+    #[envconfig(from = "GROWTH_AUDIT_LOG")]
+    /// Append a JSON-lines record of every solve to this file (`-` for stdout)
+    pub audit_log: Option<String>,
+    #[envconfig(from = "GROWTH_DRY_RUN", default = "false")]
+    /// Log the NodeRequests a solve would create instead of creating them
+    pub dry_run: bool,
+    #[envconfig(from = "GROWTH_KARPENTER_COMPAT", default = "false")]
+    /// Provision Karpenter NodeClaims and mirror Karpenter NodePools as pools
+    pub karpenter_compat: bool,
+    #[envconfig(from = "GROWTH_CONFIG_MAP")]
+    /// ConfigMap in `GROWTH_NAMESPACE` whose `config.yaml` is watched for [`Tunables`]
+    pub config_map: Option<String>,
+    #[envconfig(from = "GROWTH_INSTALL_CRDS", default = "false")]
+    /// Install or update the CRDs at startup
+    pub install_crds: bool,
+    #[envconfig(from = "GROWTH_CRD_TIMEOUT", default = "60")]
+    /// Seconds to wait for the CRDs to be established before giving up
+    pub crd_timeout: u64,
+    #[envconfig(from = "GROWTH_WEBHOOK_CERT")]
+    /// PEM certificate chain the admission webhook serves; unset disables it
+    pub webhook_cert: Option<String>,
+    #[envconfig(from = "GROWTH_WEBHOOK_KEY")]
+    /// PEM private key for `GROWTH_WEBHOOK_CERT`
+    pub webhook_key: Option<String>,
+    #[envconfig(from = "GROWTH_WEBHOOK_PORT", default = "8443")]
+    /// Port the admission webhook listens on
+    pub webhook_port: u16,
+    #[envconfig(from = "GROWTH_CONVERSION_SERVICE")]
+    /// Service in `GROWTH_NAMESPACE` fronting the webhook; set to serve
+    /// NodeRequest `v1beta1` through it when installing the CRDs
+    pub conversion_service: Option<String>,
+    #[envconfig(from = "GROWTH_CONVERSION_CA")]
+    /// PEM file of the CA that signed `GROWTH_WEBHOOK_CERT`
+    pub conversion_ca: Option<String>,
+}
+
+/// The settings behind [`Tunables`], which can change while running.
+#[derive(Envconfig)]
+struct TunablesEnv {
+    #[envconfig(from = "GROWTH_SOLVER", default = "scored")]
+    /// Placement algorithm (scored/first-fit)
+    pub solver: String,
+    #[envconfig(from = "GROWTH_MAX_NEW_NODES")]
+    /// Most NodeRequests one reconcile may create across all pools
+    pub max_new_nodes: Option<usize>,
+    #[envconfig(from = "GROWTH_SOLVE_CONCURRENCY", default = "0")]
+    /// Most independent pool groups solved at once (0 for one thread per group)
+    pub solve_concurrency: usize,
+    #[envconfig(from = "GROWTH_MAX_HOURLY_COST")]
+    /// Cap on the managed fleet's combined $/h; NodeRequests beyond it are held
+    pub max_hourly_cost: Option<f64>,
+    #[envconfig(from = "GROWTH_POD_NAMESPACES")]
+    /// Comma-separated namespaces whose pods may trigger scaling (default all)
+    pub pod_namespaces: Option<String>,
+    #[envconfig(from = "GROWTH_POD_EXCLUDE_NAMESPACES")]
+    /// Comma-separated namespaces whose pods never trigger scaling
+    pub pod_exclude_namespaces: Option<String>,
+    #[envconfig(from = "GROWTH_POD_SELECTOR")]
+    /// Label selector pods must match to trigger scaling
+    pub pod_selector: Option<String>,
+    #[envconfig(from = "GROWTH_MIN_POD_PRIORITY")]
+    /// Lowest pod priority that may trigger a new node (default no cutoff)
+    pub min_pod_priority: Option<i32>,
+    #[envconfig(from = "GROWTH_BATCH_QUIET_MS", default = "500")]
+    /// Milliseconds without a new unschedulable pod before a batch is solved
+    pub batch_quiet_ms: u64,
+    #[envconfig(from = "GROWTH_BATCH_MAX_WINDOW", default = "10")]
+    /// Most seconds a batch of unschedulable pods waits before being solved
+    pub batch_max_window: u64,
 }
 
 #[derive(Envconfig)]
@@ -30,6 +167,128 @@ struct HetznerConfig {
     // SSH keys, image, etc.
 }
 
+/// Simulated provisioning for the `kwok` provider.
+#[derive(Envconfig)]
+struct KwokEnv {
+    #[envconfig(from = "GROWTH_KWOK_JOIN_DELAY", default = "0")]
+    join_delay: u64,
+    #[envconfig(from = "GROWTH_KWOK_JOIN_FAILURE_PERCENT", default = "0")]
+    join_failure_percent: f64,
+    /// Offering catalog: a YAML file path, or
+    /// `configmap:<namespace>/<name>[/<key>]`.
+    #[envconfig(from = "GROWTH_KWOK_CATALOG")]
+    catalog: Option<String>,
+}
+
+impl KwokEnv {
+    fn build(self) -> Result<KwokOptions, ConfigError> {
+        let catalog = self
+            .catalog
+            .map(|c| {
+                KwokCatalog::parse(&c)
+                    .ok_or_else(|| ConfigError::Other(format!("invalid GROWTH_KWOK_CATALOG: {c}")))
+            })
+            .transpose()?;
+        Ok(KwokOptions {
+            join_delay: Duration::from_secs(self.join_delay),
+            join_failure_percent: self.join_failure_percent,
+            catalog,
+        })
+    }
+}
+
+/// Failure injection for the `chaos` provider.
+#[derive(Envconfig)]
+struct ChaosEnv {
+    #[envconfig(from = "GROWTH_CHAOS_SEED", default = "0")]
+    seed: u64,
+    #[envconfig(from = "GROWTH_CHAOS_UNAVAILABLE_RATE", default = "0")]
+    unavailable_rate: f64,
+    #[envconfig(from = "GROWTH_CHAOS_DELAYED_JOIN_RATE", default = "0")]
+    delayed_join_rate: f64,
+    #[envconfig(from = "GROWTH_CHAOS_JOIN_DELAY", default = "60")]
+    join_delay: u64,
+    #[envconfig(from = "GROWTH_CHAOS_DISAPPEAR_RATE", default = "0")]
+    disappear_rate: f64,
+}
+
+impl ChaosEnv {
+    fn build(&self) -> ChaosConfig {
+        ChaosConfig {
+            seed: self.seed,
+            unavailable_rate: self.unavailable_rate,
+            delayed_join_rate: self.delayed_join_rate,
+            join_delay: Duration::from_secs(self.join_delay),
+            disappear_rate: self.disappear_rate,
+        }
+    }
+}
+
+/// Solver options for NodePools that leave them unset.
+#[derive(Envconfig)]
+struct PoolDefaultsEnv {
+    #[envconfig(from = "GROWTH_POOL_DEFAULTS_FRAGMENTATION_PENALTY")]
+    fragmentation_penalty: Option<f64>,
+    #[envconfig(from = "GROWTH_POOL_DEFAULTS_TARGET_UTILIZATION")]
+    target_utilization: Option<f64>,
+    #[envconfig(from = "GROWTH_POOL_DEFAULTS_INTERRUPTION_PENALTY")]
+    interruption_penalty: Option<f64>,
+    /// `perVcpu`, `hourlyCost` or `pricePerformance`, as in the NodePool spec.
+    #[envconfig(from = "GROWTH_POOL_DEFAULTS_COST_OBJECTIVE")]
+    cost_objective: Option<String>,
+    #[envconfig(from = "GROWTH_POOL_DEFAULTS_MAX_ADDITIONAL_COST_PER_HOUR")]
+    max_additional_cost_per_hour: Option<f64>,
+    #[envconfig(from = "GROWTH_POOL_DEFAULTS_MAX_NEW_NODES")]
+    max_new_nodes: Option<u32>,
+    /// Comma-separated instance-type patterns.
+    #[envconfig(from = "GROWTH_POOL_DEFAULTS_INCLUDE_INSTANCE_TYPES")]
+    include_instance_types: Option<String>,
+    #[envconfig(from = "GROWTH_POOL_DEFAULTS_EXCLUDE_INSTANCE_TYPES")]
+    exclude_instance_types: Option<String>,
+}
+
+impl PoolDefaultsEnv {
+    fn build(self) -> Result<SolveOptions, ConfigError> {
+        let defaults = SolveOptions::default();
+        let cost_objective = self
+            .cost_objective
+            .map(|o| {
+                serde_yaml::from_str::<CostObjective>(&o).map_err(|_| {
+                    ConfigError::Other(format!(
+                        "invalid GROWTH_POOL_DEFAULTS_COST_OBJECTIVE {o:?}, expected perVcpu, hourlyCost or pricePerformance"
+                    ))
+                })
+            })
+            .transpose()?;
+        let patterns = |list: Option<String>| -> Vec<String> {
+            list.iter()
+                .flat_map(|l| l.split(','))
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        Ok(SolveOptions {
+            fragmentation_penalty: self
+                .fragmentation_penalty
+                .unwrap_or(defaults.fragmentation_penalty),
+            target_utilization: self
+                .target_utilization
+                .filter(|u| *u > 0.0 && *u <= 1.0)
+                .unwrap_or(defaults.target_utilization),
+            interruption_penalty: self
+                .interruption_penalty
+                .unwrap_or(defaults.interruption_penalty),
+            cost_objective: cost_objective.unwrap_or(defaults.cost_objective),
+            max_additional_cost_per_hour: self.max_additional_cost_per_hour,
+            max_new_nodes: self.max_new_nodes,
+            deadline: None,
+            include_instance_types: patterns(self.include_instance_types),
+            exclude_instance_types: patterns(self.exclude_instance_types),
+        })
+    }
+}
+
 /// Configuration for scale-down behavior.
 #[derive(Envconfig)]
 struct ScaleDownConfigBuilder {
@@ -39,6 +298,10 @@ struct ScaleDownConfigBuilder {
     max_removal_attempts: u32,
     #[envconfig(from = "GROWTH_UNMET_TTL")]
     unmet_ttl: u64,
+    #[envconfig(from = "GROWTH_READY_TTL", default = "3600")]
+    ready_ttl: u64,
+    #[envconfig(from = "GROWTH_REBALANCE_REPLACEMENT", default = "false")]
+    rebalance_replacement: bool,
 }
 
 impl ScaleDownConfigBuilder {
@@ -47,6 +310,8 @@ impl ScaleDownConfigBuilder {
             cooling_off_duration: Duration::from_secs(self.cooling_off_duration),
             max_removal_attempts: self.max_removal_attempts,
             unmet_ttl: Duration::from_secs(self.unmet_ttl),
+            ready_ttl: (self.ready_ttl > 0).then(|| Duration::from_secs(self.ready_ttl)),
+            rebalance_replacement: self.rebalance_replacement,
         }
     }
 }
@@ -56,8 +321,16 @@ pub struct ScaleDownConfig {
     pub cooling_off_duration: Duration,
     /// Maximum number of provider.delete() retries before giving up.
     pub max_removal_attempts: u32,
-    /// How long an Unmet NodeRequest keeps its pods claimed before deletion (default 120s).
+    /// How long an Unmet NodeRequest is kept before deletion (default 120s).
+    /// Its pods are released as soon as it lands in Unmet; until then its
+    /// offering is kept out of its pool's solves.
     pub unmet_ttl: Duration,
+    /// How long a Ready NodeRequest is kept once its node joined (default
+    /// 1h). Deleting it leaves the node alone. `None` keeps them forever.
+    pub ready_ttl: Option<Duration>,
+    /// Replace nodes given a spot rebalance recommendation before draining
+    /// them, instead of waiting for the interruption (default false).
+    pub rebalance_replacement: bool,
 }
 
 impl Default for ScaleDownConfig {
@@ -66,42 +339,584 @@ impl Default for ScaleDownConfig {
             cooling_off_duration: Duration::from_secs(15),
             max_removal_attempts: 5,
             unmet_ttl: Duration::from_secs(120),
+            ready_ttl: Some(Duration::from_secs(3600)),
+            rebalance_replacement: false,
+        }
+    }
+}
+
+/// How the CRDs are checked for at startup.
+#[derive(Debug, Clone)]
+pub struct CrdSetup {
+    /// Install or update them with server-side apply before checking.
+    pub install: bool,
+    /// How long to wait for them to be established (default 60s).
+    pub timeout: Duration,
+    /// Where the installed NodeRequest CRD sends `v1beta1` conversions;
+    /// `None` leaves that version unserved.
+    pub conversion: Option<ConversionService>,
+}
+
+impl Default for CrdSetup {
+    fn default() -> Self {
+        Self {
+            install: false,
+            timeout: Duration::from_secs(60),
+            conversion: None,
+        }
+    }
+}
+
+/// How often periodic work reruns, how failed reconciles back off, and how
+/// many reconciles each controller runs at once.
+#[derive(Envconfig)]
+struct RequeueConfigBuilder {
+    #[envconfig(from = "GROWTH_REQUEUE_INTERVAL", default = "30")]
+    interval: u64,
+    #[envconfig(from = "GROWTH_ERROR_REQUEUE", default = "5")]
+    error_requeue: u64,
+    #[envconfig(from = "GROWTH_ERROR_REQUEUE_MAX", default = "300")]
+    error_requeue_max: u64,
+    #[envconfig(from = "GROWTH_RECONCILE_CONCURRENCY", default = "0")]
+    concurrency: u16,
+}
+
+impl RequeueConfigBuilder {
+    pub fn build(&self) -> Result<RequeueConfig, ConfigError> {
+        if self.interval == 0 || self.error_requeue == 0 {
+            return Err(ConfigError::Other(
+                "GROWTH_REQUEUE_INTERVAL and GROWTH_ERROR_REQUEUE must be at least 1s".into(),
+            ));
+        }
+        Ok(RequeueConfig {
+            interval: Duration::from_secs(self.interval),
+            errors: ErrorBackoff::new(
+                Duration::from_secs(self.error_requeue),
+                Duration::from_secs(self.error_requeue_max),
+            ),
+            concurrency: self.concurrency,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RequeueConfig {
+    /// Between idle-node, claim GC and evacuation scans, and between
+    /// retries of a provider delete (default 30s).
+    pub interval: Duration,
+    /// Requeue delay after a failed reconcile, doubling per consecutive
+    /// failure (default 5s, capped at 5m).
+    pub errors: ErrorBackoff,
+    /// Objects each NodeRequest, Node and NodeRemovalRequest controller
+    /// reconciles at once (default 0, no limit).
+    pub concurrency: u16,
+}
+
+impl Default for RequeueConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            errors: ErrorBackoff::default(),
+            concurrency: 0,
+        }
+    }
+}
+
+/// How the pod watcher coalesces unschedulable-pod events into one solve.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchWindow {
+    /// Solve once no new event has arrived for this long.
+    pub quiet: Duration,
+    /// Solve at the latest this long after a batch's first event.
+    pub max: Duration,
+}
+
+impl Default for BatchWindow {
+    fn default() -> Self {
+        Self {
+            quiet: Duration::from_millis(500),
+            max: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Settings that take effect without a restart: solving, scoping and
+/// limits. They are re-read when the `GROWTH_CONFIG_MAP` ConfigMap changes.
+#[derive(Clone)]
+pub struct Tunables {
+    /// Placement algorithm pending pods are solved with.
+    pub solver: &'static dyn SolverBackend,
+    /// Most NodeRequests one reconcile may create across all pools;
+    /// `None` means no cap.
+    pub max_new_nodes: Option<usize>,
+    /// Most pool groups solved at once; 0 solves each on its own thread.
+    pub solve_concurrency: usize,
+    /// Cap on the managed fleet's combined hourly cost; `None` means no cap.
+    pub max_hourly_cost: Option<f64>,
+    /// Debounce window of the pod watcher.
+    pub pod_batch: BatchWindow,
+    /// The pending pods that may trigger scaling.
+    pub pod_scope: PodScope,
+    /// Solver options for NodePools that leave them unset.
+    pub pool_defaults: SolveOptions,
+}
+
+impl Default for Tunables {
+    fn default() -> Self {
+        Self {
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
+            max_hourly_cost: None,
+            pod_batch: BatchWindow::default(),
+            pod_scope: PodScope::default(),
+            pool_defaults: SolveOptions::default(),
+        }
+    }
+}
+
+impl Tunables {
+    /// Build from `settings`, keyed by environment variable name.
+    pub fn from_settings(settings: &HashMap<String, String>) -> Result<Self, ConfigError> {
+        let raw = TunablesEnv::init_from_hashmap(settings)?;
+        let solver = solver_by_name(&raw.solver).ok_or_else(|| {
+            let known: Vec<&str> = SOLVERS.iter().map(|s| s.name()).collect();
+            ConfigError::Other(format!(
+                "unknown GROWTH_SOLVER {:?}, expected one of {}",
+                raw.solver,
+                known.join(", ")
+            ))
+        })?;
+        Ok(Self {
+            solver,
+            max_new_nodes: raw.max_new_nodes,
+            solve_concurrency: raw.solve_concurrency,
+            max_hourly_cost: raw.max_hourly_cost,
+            pod_batch: BatchWindow {
+                quiet: Duration::from_millis(raw.batch_quiet_ms),
+                max: Duration::from_secs(raw.batch_max_window),
+            },
+            pod_scope: PodScope::from_config(
+                raw.pod_namespaces.as_deref(),
+                raw.pod_exclude_namespaces.as_deref(),
+                raw.pod_selector,
+                raw.min_pod_priority,
+            )?,
+            pool_defaults: PoolDefaultsEnv::init_from_hashmap(settings)?.build()?,
+        })
+    }
+}
+
+/// The current [`Tunables`], shared by every controller. Readers take a
+/// snapshot per reconcile; the pod watcher also waits for changes.
+#[derive(Default)]
+pub struct SharedTunables(watch::Sender<Arc<Tunables>>);
+
+impl SharedTunables {
+    pub fn new(tunables: Tunables) -> Self {
+        Self(watch::Sender::new(Arc::new(tunables)))
+    }
+
+    pub fn current(&self) -> Arc<Tunables> {
+        self.0.borrow().clone()
+    }
+
+    pub fn replace(&self, tunables: Tunables) {
+        self.0.send_replace(Arc::new(tunables));
+    }
+
+    /// A receiver that is notified of every [`Self::replace`].
+    pub fn subscribe(&self) -> watch::Receiver<Arc<Tunables>> {
+        self.0.subscribe()
+    }
+}
+
+/// Read a YAML config file into settings keyed by environment variable
+/// name.
+///
+/// Keys are the setting names without the `GROWTH_` prefix, in camelCase:
+/// `maxNewNodes: 20` is `GROWTH_MAX_NEW_NODES=20`. Nested maps join their
+/// keys, so `poolDefaults: {targetUtilization: 0.8}` is
+/// `GROWTH_POOL_DEFAULTS_TARGET_UTILIZATION`. Lists are joined with commas.
+pub fn settings_from_yaml(yaml: &str) -> Result<HashMap<String, String>, ConfigError> {
+    let doc: serde_yaml::Value = serde_yaml::from_str(yaml)
+        .map_err(|e| ConfigError::Other(format!("invalid config file: {e}")))?;
+    let mut settings = HashMap::new();
+    match doc {
+        serde_yaml::Value::Null => {}
+        serde_yaml::Value::Mapping(map) => flatten_settings("GROWTH", map, &mut settings)?,
+        _ => return Err(ConfigError::Other("config file must be a mapping".into())),
+    }
+    Ok(settings)
+}
+
+fn flatten_settings(
+    prefix: &str,
+    map: serde_yaml::Mapping,
+    settings: &mut HashMap<String, String>,
+) -> Result<(), ConfigError> {
+    use serde_yaml::Value;
+
+    let scalar = |key: &str, value: Value| match value {
+        Value::Bool(b) => Ok(b.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::String(s) => Ok(s),
+        _ => Err(ConfigError::Other(format!(
+            "config file: {key} must be a scalar or a list of scalars"
+        ))),
+    };
+    for (key, value) in map {
+        let Value::String(key) = key else {
+            return Err(ConfigError::Other(format!(
+                "config file: non-string key under {prefix}"
+            )));
+        };
+        let mut name = format!("{prefix}_");
+        for c in key.chars() {
+            if c.is_ascii_uppercase() {
+                name.push('_');
+            }
+            name.push(c.to_ascii_uppercase());
+        }
+        match value {
+            Value::Null => {}
+            Value::Mapping(nested) => flatten_settings(&name, nested, settings)?,
+            Value::Sequence(items) => {
+                let items = items
+                    .into_iter()
+                    .map(|v| scalar(&key, v))
+                    .collect::<Result<Vec<_>, _>>()?;
+                settings.insert(name, items.join(","));
+            }
+            value => {
+                let value = scalar(&key, value)?;
+                settings.insert(name, value);
+            }
         }
     }
+    Ok(())
+}
+
+/// The controller's settings: the config file at `config_file`, or at
+/// `GROWTH_CONFIG` if not given, with the environment laid over it.
+pub fn layered_settings(
+    config_file: Option<&Path>,
+) -> Result<HashMap<String, String>, ConfigError> {
+    let env: HashMap<String, String> = std::env::vars().collect();
+    let path = config_file
+        .map(Path::to_path_buf)
+        .or_else(|| env.get("GROWTH_CONFIG").map(PathBuf::from));
+    let mut settings = match path {
+        Some(path) => {
+            let yaml = std::fs::read_to_string(&path).map_err(|e| {
+                ConfigError::Other(format!("reading config file {}: {e}", path.display()))
+            })?;
+            settings_from_yaml(&yaml)?
+        }
+        None => HashMap::new(),
+    };
+    settings.extend(env);
+    Ok(settings)
 }
 
 /// Shared context for the controller reconciler.
 pub struct ControllerContext {
     pub client: Client,
-    pub provider: Provider,
+    pub provider: Arc<dyn Provider>,
     pub provisioning_timeout: Duration,
+    /// Startup refuses to run if provider inventory and cluster state differ by more than this.
+    pub max_inventory_drift: usize,
+    /// Historical create/join statistics per offering, fed back into the solver.
+    pub offering_stats: OfferingStatsStore,
+    /// Holds back plans whose hourly cost spikes above the recent baseline.
+    pub cost_guard: CostGuard,
+    /// NodeRequests let through the hourly budget but not yet Provisioning.
+    pub budget_admissions: tokio::sync::Mutex<BudgetAdmissions>,
+    /// Controller self-metrics, served on `/metrics`.
+    pub metrics: Metrics,
+    /// Rebuilds `provider` from a ProviderConfig resource; `None` disables reloading.
+    pub provider_reloader: Option<ProviderReloader>,
+    /// Providers failing their health probe; their offerings are skipped.
+    pub provider_health: ProviderHealthMonitor,
+    /// The NodePools this replica manages.
+    pub shard: Shard,
     pub scale_down: ScaleDownConfig,
+    pub requeue: RequeueConfig,
+    pub crds: CrdSetup,
+    /// Serves the validating admission webhook; `None` disables it.
+    pub webhook: Option<WebhookConfig>,
+    /// Batches provider deletes and enforces pool disruption budgets.
+    pub removals: RemovalExecutor,
+    /// Solving, scoping and limits, which may change while running.
+    pub tunables: SharedTunables,
+    /// Re-reads `tunables` from a ConfigMap; `None` disables reloading.
+    pub config_reloader: Option<ConfigReloader>,
+    /// Where each solve's decisions are recorded; `None` disables the audit log.
+    pub audit_log: Option<AuditLog>,
+    /// Recommend only: solves are logged and audited, but nothing is
+    /// created, drained or removed.
+    pub dry_run: bool,
+    /// Serve Karpenter NodePools and NodeClaims alongside growth-rs's own
+    /// resources (see [`karpenter`](crate::controller::karpenter)).
+    pub karpenter_compat: bool,
     pub clock: Arc<dyn Clock>,
 }
 
 impl ControllerContext {
+    /// Build the context from environment, using the built-in providers.
     pub fn new(client: kube::Client) -> Result<Self, ConfigError> {
-        let raw = RawConfig::init_from_env()?;
-        let scale_down = ScaleDownConfigBuilder::init_from_env()?.build();
-        let provisioning_timeout = std::time::Duration::from_secs(raw.provisioning_timeout);
+        Self::with_registry(client, ProviderRegistry::default())
+    }
 
-        let provider = match ProviderName::from_name(&raw.provider)
-            .map_err(|e| ConfigError::Other(e.to_string()))?
-        {
-            ProviderName::Kwok => Provider::Kwok(KwokProvider::new(client.clone())),
-            ProviderName::Fake => Provider::Fake(FakeProvider::new()),
-            ProviderName::Hetzner => {
-                let hetzner_config = HetznerConfig::init_from_env()?;
-                Provider::Hetzner(HetznerProvider::new(hetzner_config.token))
+    /// Build the context from the config file named by `GROWTH_CONFIG` and
+    /// the environment, resolving `GROWTH_PROVIDER` against the given
+    /// registry. Several comma-separated names federate
+    /// the providers into one. The registry is kept so a ProviderConfig
+    /// resource can rebuild the provider later.
+    pub fn with_registry(
+        client: kube::Client,
+        registry: ProviderRegistry,
+    ) -> Result<Self, ConfigError> {
+        Self::from_settings(client, registry, &layered_settings(None)?)
+    }
+
+    /// Build the context from `settings`, keyed by environment variable
+    /// name, e.g. [`layered_settings`] with command-line flags laid over it.
+    pub fn from_settings(
+        client: kube::Client,
+        registry: ProviderRegistry,
+        settings: &HashMap<String, String>,
+    ) -> Result<Self, ConfigError> {
+        let raw = RawConfig::init_from_hashmap(settings)?;
+        let scale_down = ScaleDownConfigBuilder::init_from_hashmap(settings)?.build();
+        let requeue = RequeueConfigBuilder::init_from_hashmap(settings)?.build()?;
+        let provisioning_timeout = std::time::Duration::from_secs(raw.provisioning_timeout);
+        let shard = Shard::from_config(raw.shard, raw.shard_index, raw.shard_count)?;
+        let tunables = Tunables::from_settings(settings)?;
+        let config_reloader = match (raw.config_map, &raw.namespace) {
+            (Some(name), Some(namespace)) => Some(ConfigReloader::new(
+                namespace.clone(),
+                name,
+                tunables.clone(),
+            )),
+            (Some(_), None) => {
+                return Err(ConfigError::Other(
+                    "GROWTH_CONFIG_MAP needs GROWTH_NAMESPACE".into(),
+                ));
+            }
+            (None, _) => None,
+        };
+        let webhook = match (raw.webhook_cert, raw.webhook_key) {
+            (Some(cert), Some(key)) => Some(WebhookConfig {
+                port: raw.webhook_port,
+                cert: cert.into(),
+                key: key.into(),
+            }),
+            (None, None) => None,
+            _ => {
+                return Err(ConfigError::Other(
+                    "GROWTH_WEBHOOK_CERT and GROWTH_WEBHOOK_KEY must be set together".into(),
+                ));
+            }
+        };
+        let conversion = match raw.conversion_service {
+            None => None,
+            Some(_) if !raw.install_crds || webhook.is_none() => {
+                return Err(ConfigError::Other(
+                    "GROWTH_CONVERSION_SERVICE needs GROWTH_INSTALL_CRDS and the webhook".into(),
+                ));
             }
+            Some(name) => {
+                let (Some(namespace), Some(ca)) = (&raw.namespace, &raw.conversion_ca) else {
+                    return Err(ConfigError::Other(
+                        "GROWTH_CONVERSION_SERVICE needs GROWTH_NAMESPACE and GROWTH_CONVERSION_CA"
+                            .into(),
+                    ));
+                };
+                let ca_bundle = std::fs::read(ca).map_err(|e| {
+                    ConfigError::Other(format!("reading GROWTH_CONVERSION_CA {ca}: {e}"))
+                })?;
+                Some(ConversionService {
+                    namespace: namespace.clone(),
+                    name,
+                    ca_bundle,
+                })
+            }
+        };
+
+        let metrics = Metrics::new();
+        let provider_config = ProviderConfig {
+            kube_client: client.clone(),
+            hcloud_token: HetznerConfig::init_from_hashmap(settings)
+                .ok()
+                .map(|c| c.token),
+            offerings_ttl: Duration::from_secs(raw.offerings_cache_ttl),
+            retry: RetryPolicy {
+                max_retries: raw.provider_max_retries,
+                ..RetryPolicy::default()
+            },
+            requests_per_second: raw.provider_rate_limit,
+            metrics: Some(metrics.providers.clone()),
+            kwok: KwokEnv::init_from_hashmap(settings)?.build()?,
+            chaos: ChaosEnv::init_from_hashmap(settings)?.build(),
+        };
+        let provider = registry
+            .build_list(&raw.provider, &provider_config)
+            .map_err(|e| match e {
+                ProviderError::UnknownProvider(name) => {
+                    let mut known: Vec<&str> = registry.names().collect();
+                    known.sort();
+                    ConfigError::Other(format!(
+                        "unknown provider {name:?} in GROWTH_PROVIDER {:?}, expected one of {}",
+                        raw.provider,
+                        known.join(", ")
+                    ))
+                }
+                e => ConfigError::Other(e.to_string()),
+            })?;
+        let provider = Arc::new(ReloadableProvider::new(provider));
+        let provider_reloader = ProviderReloader::new(
+            raw.provider_config,
+            registry,
+            raw.provider,
+            provider_config,
+            provider.clone(),
+        );
+        let provider: Arc<dyn Provider> = match raw.provider_record {
+            Some(path) => Arc::new(
+                RecordingProvider::to_file(provider, Path::new(&path))
+                    .map_err(|e| ConfigError::Other(format!("GROWTH_PROVIDER_RECORD: {e}")))?,
+            ),
+            None => provider,
         };
+        let audit_log = raw
+            .audit_log
+            .map(|path| {
+                AuditLog::open(&path)
+                    .map_err(|e| ConfigError::Other(format!("GROWTH_AUDIT_LOG: {e}")))
+            })
+            .transpose()?;
 
         Ok(ControllerContext {
-            client,
+            client: client.clone(),
             provider,
             provisioning_timeout,
+            max_inventory_drift: raw.max_inventory_drift,
+            offering_stats: OfferingStatsStore::new(
+                raw.namespace,
+                PenaltyWeights {
+                    failure: raw.offering_failure_penalty,
+                    join_minutes: raw.offering_join_penalty,
+                },
+            )
+            .for_shard(&shard),
+            cost_guard: CostGuard::new(
+                raw.cost_anomaly_factor,
+                raw.cost_anomaly_min_baseline,
+                raw.cost_anomaly_override,
+            )
+            .with_events(client),
+            budget_admissions: Default::default(),
+            metrics,
+            provider_reloader: Some(provider_reloader),
+            provider_health: ProviderHealthMonitor::new(),
+            shard,
             scale_down,
+            requeue,
+            crds: CrdSetup {
+                install: raw.install_crds,
+                timeout: Duration::from_secs(raw.crd_timeout),
+                conversion,
+            },
+            webhook,
+            removals: RemovalExecutor::default(),
+            tunables: SharedTunables::new(tunables),
+            config_reloader,
+            audit_log,
+            dry_run: raw.dry_run,
+            karpenter_compat: raw.karpenter_compat,
             clock: Arc::new(SystemClock),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_file_keys_become_settings() {
+        let settings = settings_from_yaml(
+            r#"
+provider: kwok
+maxNewNodes: 20
+dryRun: true
+podNamespaces: [payments, checkout]
+shard: null
+kwok:
+  joinDelay: 30
+poolDefaults:
+  targetUtilization: 0.8
+  costObjective: hourlyCost
+  excludeInstanceTypes: ["gpu-*"]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(settings["GROWTH_PROVIDER"], "kwok");
+        assert_eq!(settings["GROWTH_MAX_NEW_NODES"], "20");
+        assert_eq!(settings["GROWTH_DRY_RUN"], "true");
+        assert_eq!(settings["GROWTH_POD_NAMESPACES"], "payments,checkout");
+        assert_eq!(settings["GROWTH_KWOK_JOIN_DELAY"], "30");
+        assert!(!settings.contains_key("GROWTH_SHARD"));
+
+        let defaults = PoolDefaultsEnv::init_from_hashmap(&settings)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(defaults.target_utilization, 0.8);
+        assert_eq!(defaults.cost_objective, CostObjective::HourlyCost);
+        assert_eq!(defaults.exclude_instance_types, ["gpu-*"]);
+        assert_eq!(defaults.fragmentation_penalty, 0.0);
+
+        assert!(settings_from_yaml("- not\n- a mapping\n").is_err());
+        assert!(
+            settings_from_yaml("poolDefaults: {costObjective: cheapest}").is_ok_and(|s| {
+                PoolDefaultsEnv::init_from_hashmap(&s)
+                    .unwrap()
+                    .build()
+                    .is_err()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_providers_are_named_with_the_known_ones() {
+        let (mock_svc, _handle) = tower_test::mock::pair::<
+            http::Request<kube::client::Body>,
+            http::Response<kube::client::Body>,
+        >();
+        let settings = HashMap::from(
+            [
+                ("GROWTH_PROVIDER", "kwok,aws"),
+                ("GROWTH_PROVISIONING_TIMEOUT", "60"),
+                ("GROWTH_COOLING_DURATION", "15"),
+                ("GROWTH_REMOVAL_ATTEMPTS", "5"),
+                ("GROWTH_UNMET_TTL", "120"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+
+        let Err(e) = ControllerContext::from_settings(
+            Client::new(mock_svc, "default"),
+            ProviderRegistry::default(),
+            &settings,
+        ) else {
+            panic!("aws is not a provider");
+        };
+        assert_eq!(
+            e.to_string(),
+            r#"unknown provider "aws" in GROWTH_PROVIDER "kwok,aws", expected one of chaos, fake, hetzner, kwok"#
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! A JSON-lines audit trail of provisioning decisions.
+//!
+//! Every solve of the pending pods appends one [`SolveAudit`] line: the pods
+//! and pools it was given, the nodes it chose and what they cost, and where
+//! each pod ended up. Unlike the logs, the records are meant to be read by
+//! tools, for after-the-fact cost and capacity analysis.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::Mutex;
+
+use k8s_openapi::jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::controller::pods::{NodeRequestDemand, ReconcileResult};
+use crate::offering::{PodResources, Resources};
+
+/// A pending pod as the solver saw it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditedPod {
+    /// `namespace/name`.
+    pub pod: String,
+    /// The pool the pod asked for, if any.
+    pub pool: Option<String>,
+    pub resources: Resources,
+    pub priority: i32,
+}
+
+/// A node the solve chose to provision.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditedNode {
+    pub pool: String,
+    pub provider: String,
+    pub instance_type: String,
+    pub region: String,
+    pub zone: Option<String>,
+    pub cost_per_hour: f64,
+    /// Pods planned onto the node, as `namespace/name`.
+    pub pods: Vec<String>,
+    /// Held back by the cost anomaly guard rather than requested.
+    pub held: bool,
+}
+
+/// Where the solve put one pod.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "on", rename_all = "snake_case")]
+pub enum Placement {
+    /// On `nodes[node]` of the record.
+    NewNode { node: usize },
+    /// On spare capacity of a node that exists or is already on its way.
+    Existing,
+    /// Nowhere; the pod stays pending.
+    Unmet { reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PodPlacement {
+    pub pod: String,
+    #[serde(flatten)]
+    pub placement: Placement,
+}
+
+/// One solve of the pending pods, a line in the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SolveAudit {
+    /// RFC 3339 time of the solve.
+    pub time: String,
+    pub pods: Vec<AuditedPod>,
+    /// Pools solved for, by name.
+    pub pools: Vec<String>,
+    /// Offerings available to the solve, after unhealthy providers were
+    /// dropped.
+    pub offerings: usize,
+    pub nodes: Vec<AuditedNode>,
+    pub placements: Vec<PodPlacement>,
+    /// Hourly cost of the nodes requested, leaving out held ones.
+    pub cost_per_hour: f64,
+    /// Pods left pending.
+    pub unmet: usize,
+}
+
+impl SolveAudit {
+    /// Describe the solve of `pods` that produced `result`. `held` tells
+    /// which of the planned nodes the cost guard is holding back.
+    pub fn new(
+        now: Timestamp,
+        pods: &[PodResources],
+        pools: Vec<String>,
+        offerings: usize,
+        result: &ReconcileResult,
+        held: impl Fn(&NodeRequestDemand) -> bool,
+    ) -> Self {
+        let ids: HashMap<&str, String> = pods
+            .iter()
+            .map(|p| (p.uid.as_str(), p.id.to_string()))
+            .collect();
+        let nodes: Vec<AuditedNode> = result
+            .demands
+            .iter()
+            .map(|d| AuditedNode {
+                pool: d.pool.clone(),
+                provider: d.target_offering.provider.clone(),
+                instance_type: d.target_offering.instance_type.0.clone(),
+                region: d.target_offering.location.region.0.clone(),
+                zone: d
+                    .target_offering
+                    .location
+                    .zone
+                    .as_ref()
+                    .map(|z| z.0.clone()),
+                cost_per_hour: d.target_offering.cost_per_hour,
+                pods: d
+                    .claimed_pods
+                    .iter()
+                    .filter_map(|uid| ids.get(uid.as_str()).cloned())
+                    .collect(),
+                held: held(d),
+            })
+            .collect();
+
+        let on_node: HashMap<&str, usize> = nodes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, n)| n.pods.iter().map(move |p| (p.as_str(), i)))
+            .collect();
+        let unplaced: HashSet<String> = result.unplaced.iter().map(|p| p.to_string()).collect();
+        let errors: HashMap<String, String> = result
+            .pod_errors
+            .iter()
+            .map(|e| (e.pod_id.to_string(), e.reason.to_string()))
+            .collect();
+        let placements: Vec<PodPlacement> = pods
+            .iter()
+            .map(|p| {
+                let pod = p.id.to_string();
+                let placement = if let Some(&node) = on_node.get(pod.as_str()) {
+                    Placement::NewNode { node }
+                } else if let Some(reason) = errors.get(&pod) {
+                    Placement::Unmet {
+                        reason: reason.clone(),
+                    }
+                } else if unplaced.contains(&pod) {
+                    Placement::Unmet {
+                        reason: "no capacity in its pool".into(),
+                    }
+                } else {
+                    Placement::Existing
+                };
+                PodPlacement { pod, placement }
+            })
+            .collect();
+
+        Self {
+            time: now.to_string(),
+            pods: pods
+                .iter()
+                .map(|p| AuditedPod {
+                    pod: p.id.to_string(),
+                    pool: p.pool.clone(),
+                    resources: p.resources.clone(),
+                    priority: p.priority,
+                })
+                .collect(),
+            pools,
+            offerings,
+            cost_per_hour: nodes
+                .iter()
+                .filter(|n| !n.held)
+                .map(|n| n.cost_per_hour)
+                .sum(),
+            unmet: placements
+                .iter()
+                .filter(|p| matches!(p.placement, Placement::Unmet { .. }))
+                .count(),
+            nodes,
+            placements,
+        }
+    }
+}
+
+/// Where [`SolveAudit`] records are appended.
+pub struct AuditLog {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AuditLog {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self {
+            out: Mutex::new(out),
+        }
+    }
+
+    /// Append to the file at `path`, or write to stdout if it is `-`.
+    pub fn open(path: &str) -> std::io::Result<Self> {
+        if path == "-" {
+            return Ok(Self::new(Box::new(std::io::stdout())));
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(Box::new(file)))
+    }
+
+    /// Records are flushed as they are written. Write failures are logged,
+    /// never returned: a full disk shouldn't stop provisioning.
+    pub fn write(&self, record: &SolveAudit) {
+        let line = serde_json::to_string(record).expect("audit records serialize");
+        let mut out = self.out.lock().unwrap();
+        if let Err(e) = writeln!(out, "{line}").and_then(|()| out.flush()) {
+            warn!(error = %e, "failed to write solve audit record");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::controller::pods::{PodPoolError, PodPoolReason};
+    use crate::offering::PodId;
+    use crate::testing::offering;
+
+    fn res(cpu_millis: u64) -> Resources {
+        Resources {
+            cpu_millis,
+            memory_mib: 1024,
+            ephemeral_storage_gib: None,
+            gpu: 0,
+            gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
+        }
+    }
+
+    fn pod(name: &str) -> PodResources {
+        PodResources {
+            id: PodId::new("default", name),
+            uid: format!("uid-{name}"),
+            resources: res(500),
+            pool: None,
+            pod_labels: BTreeMap::new(),
+            affinity_constraints: vec![],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
+        }
+    }
+
+    fn node(pool: &str, cost: f64, pods: &[&str]) -> NodeRequestDemand {
+        NodeRequestDemand {
+            pool: pool.into(),
+            pool_uid: format!("{pool}-uid"),
+            target_offering: offering("cpx22")
+                .with_resources(res(2000))
+                .with_cost(cost)
+                .build(),
+            claimed_pods: pods.iter().map(|p| format!("uid-{p}")).collect(),
+            labels: BTreeMap::new(),
+            taints: vec![],
+        }
+    }
+
+    #[test]
+    fn records_where_every_pod_went() {
+        let pods = [pod("a"), pod("b"), pod("c"), pod("d"), pod("e"), pod("f")];
+        let result = ReconcileResult {
+            demands: vec![node("web", 0.02, &["a", "b"]), node("gpu", 1.5, &["c"])],
+            pod_errors: vec![PodPoolError {
+                pod_id: PodId::new("default", "d"),
+                reason: PodPoolReason::NoPoolSelector,
+            }],
+            unplaced: vec![PodId::new("default", "e")],
+            solver_stats: BTreeMap::new(),
+            in_flight_placements: BTreeMap::new(),
+        };
+
+        let audit = SolveAudit::new(
+            Timestamp::UNIX_EPOCH,
+            &pods,
+            vec!["gpu".into(), "web".into()],
+            12,
+            &result,
+            |d| d.pool == "gpu",
+        );
+
+        assert_eq!(audit.nodes[0].pods, ["default/a", "default/b"]);
+        assert!(audit.nodes[1].held);
+        assert_eq!(audit.cost_per_hour, 0.02);
+        assert_eq!(audit.unmet, 2);
+        let placed: Vec<(&str, &Placement)> = audit
+            .placements
+            .iter()
+            .map(|p| (p.pod.as_str(), &p.placement))
+            .collect();
+        assert_eq!(placed[1], ("default/b", &Placement::NewNode { node: 0 }));
+        assert_eq!(placed[2], ("default/c", &Placement::NewNode { node: 1 }));
+        assert!(matches!(placed[3].1, Placement::Unmet { reason } if reason.contains("pool")));
+        assert!(matches!(placed[4].1, Placement::Unmet { .. }));
+        // Placed on capacity already there.
+        assert_eq!(placed[5], ("default/f", &Placement::Existing));
+
+        let path = std::env::temp_dir().join(format!("growth-{}.jsonl", uuid::Uuid::new_v4()));
+        let log = AuditLog::open(path.to_str().unwrap()).unwrap();
+        log.write(&audit);
+        log.write(&audit);
+        drop(log);
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(serde_json::from_str::<SolveAudit>(lines[0]).unwrap(), audit);
+        assert!(lines[0].contains(r#""on":"new_node","node":0"#));
+    }
+}
@@ -0,0 +1,110 @@
+//! Requeue delays for reconciles that failed.
+//!
+//! Each object's consecutive failures double its delay, from the initial one
+//! up to a cap, so an object failing through a provider outage is retried
+//! less and less often instead of every few seconds. A successful reconcile
+//! starts it over.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Per-object exponential backoff with jitter.
+#[derive(Debug)]
+pub struct ErrorBackoff {
+    /// Delay after the first failure.
+    initial: Duration,
+    /// Longest delay, however many failures.
+    max: Duration,
+    /// Consecutive failures, by controller and object name.
+    failures: Mutex<HashMap<(&'static str, String), u32>>,
+}
+
+impl Default for ErrorBackoff {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(5), Duration::from_secs(300))
+    }
+}
+
+impl ErrorBackoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max: max.max(initial),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Delay before retrying a failure that isn't tracked per object.
+    pub fn initial(&self) -> Duration {
+        self.initial
+    }
+
+    /// Count a failed reconcile of `name` by `controller` and return how long
+    /// to wait before the next one: between half and all of the exponential
+    /// bound, so objects that failed together don't retry together.
+    pub fn failed(&self, controller: &'static str, name: &str) -> Duration {
+        let attempt = {
+            let mut failures = self.failures.lock().unwrap();
+            let count = failures.entry((controller, name.to_string())).or_insert(0);
+            let attempt = *count;
+            *count = count.saturating_add(1);
+            attempt
+        };
+        let bound = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max);
+        let half = bound / 2;
+        if half.is_zero() {
+            return bound;
+        }
+        half + rand::thread_rng().gen_range(Duration::ZERO..=bound - half)
+    }
+
+    /// How many objects are failing, by controller.
+    pub fn failing(&self) -> HashMap<&'static str, usize> {
+        let mut failing = HashMap::new();
+        for (controller, _) in self.failures.lock().unwrap().keys() {
+            *failing.entry(*controller).or_insert(0) += 1;
+        }
+        failing
+    }
+
+    /// Forget the failures of `name`, after it reconciled.
+    pub fn succeeded(&self, controller: &'static str, name: &str) {
+        self.failures
+            .lock()
+            .unwrap()
+            .remove(&(controller, name.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_grow_per_object_up_to_the_cap_and_reset_on_success() {
+        let backoff = ErrorBackoff::new(Duration::from_secs(4), Duration::from_secs(20));
+        let delays: Vec<Duration> = (0..5).map(|_| backoff.failed("nr", "a")).collect();
+        let bounds = [4, 8, 16, 20, 20].map(Duration::from_secs);
+        for (delay, bound) in delays.iter().zip(bounds) {
+            assert!(
+                *delay >= bound / 2 && *delay <= bound,
+                "{delay:?} vs {bound:?}"
+            );
+        }
+
+        // Other objects, and the same name in another controller, start afresh.
+        assert!(backoff.failed("nr", "b") <= Duration::from_secs(4));
+        assert!(backoff.failed("node", "a") <= Duration::from_secs(4));
+
+        assert_eq!(backoff.failing(), HashMap::from([("nr", 2), ("node", 1)]));
+
+        backoff.succeeded("nr", "a");
+        assert!(backoff.failed("nr", "a") <= Duration::from_secs(4));
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::{HashMap, HashSet};
+
+use k8s_openapi::api::core::v1::Pod;
+
+use crate::controller::pods::{is_daemonset_pod, is_pod_unschedulable};
+use crate::offering::{NOMINATED_ANNOTATION, PodId, UNCLAIMED_ANNOTATION, pod_pool_selector};
+use crate::resources::node_request::{NodeRequest, NodeRequestPhase};
+
+/// What to do with one NodeRequest's pod claims.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClaimAction {
+    /// Every claim is still live.
+    Keep,
+    /// Some claims are stale; keep the NodeRequest with the rest.
+    Release { remaining: Vec<String> },
+    /// Nothing needs the node and the provider hasn't been called yet.
+    Cancel,
+    /// Nothing needs the node, but it's already being created: remove it as
+    /// soon as it joins.
+    MarkUnclaimed,
+}
+
+/// A claim is live while its pod exists, isn't finished or terminating, and
+/// hasn't been bound to any node other than the one being provisioned for it.
+fn is_claim_live(uid: &str, node_id: &str, pods_by_uid: &HashMap<&str, &Pod>) -> bool {
+    let Some(pod) = pods_by_uid.get(uid) else {
+        return false;
+    };
+    let finished = pod
+        .status
+        .as_ref()
+        .and_then(|s| s.phase.as_deref())
+        .is_some_and(|p| p == "Succeeded" || p == "Failed");
+    let bound_elsewhere = pod
+        .spec
+        .as_ref()
+        .and_then(|s| s.node_name.as_deref())
+        .is_some_and(|n| n != node_id);
+    pod.metadata.deletion_timestamp.is_none() && !finished && !bound_elsewhere
+}
+
+/// Whether `pool` has unschedulable pods that no in-flight NodeRequest claims.
+///
+/// The solver counts in-flight capacity against new demand without claiming
+/// it, so such pods may be relying on a NodeRequest whose own claims are gone.
+fn has_unclaimed_demand(pool: &str, pods: &[Pod], nrs: &[NodeRequest]) -> bool {
+    let claimed: HashSet<&str> = nrs
+        .iter()
+        .filter(|nr| nr.phase() != NodeRequestPhase::Unmet)
+        .flat_map(|nr| nr.spec.claimed_pods.iter().map(String::as_str))
+        .collect();
+    pods.iter().any(|pod| {
+        is_pod_unschedulable(pod)
+            && !is_daemonset_pod(pod)
+            && pod.metadata.deletion_timestamp.is_none()
+            && pod_pool_selector(pod).unwrap_or("default") == pool
+            && pod
+                .metadata
+                .uid
+                .as_deref()
+                .is_some_and(|uid| !claimed.contains(uid))
+    })
+}
+
+/// Decide how to garbage-collect the claims of a NodeRequest owned by `pool`.
+///
+/// Only NodeRequests still on their way are considered: once Ready, the
+/// node's workload is judged by the idle scanner instead.
+pub fn decide_claims(
+    nr: &NodeRequest,
+    pool: &str,
+    pods: &[Pod],
+    nrs: &[NodeRequest],
+) -> ClaimAction {
+    let phase = nr.phase();
+    if nr.spec.claimed_pods.is_empty()
+        || !matches!(
+            phase,
+            NodeRequestPhase::Pending | NodeRequestPhase::Provisioning
+        )
+    {
+        return ClaimAction::Keep;
+    }
+
+    let pods_by_uid: HashMap<&str, &Pod> = pods
+        .iter()
+        .filter_map(|p| Some((p.metadata.uid.as_deref()?, p)))
+        .collect();
+    let remaining: Vec<String> = nr
+        .spec
+        .claimed_pods
+        .iter()
+        .filter(|uid| is_claim_live(uid, &nr.spec.node_id, &pods_by_uid))
+        .cloned()
+        .collect();
+
+    if remaining.len() == nr.spec.claimed_pods.len() {
+        ClaimAction::Keep
+    } else if !remaining.is_empty() || has_unclaimed_demand(pool, pods, nrs) {
+        ClaimAction::Release { remaining }
+    } else if phase == NodeRequestPhase::Pending {
+        ClaimAction::Cancel
+    } else {
+        ClaimAction::MarkUnclaimed
+    }
+}
+
+/// Ready NodeRequests marked by [`ClaimAction::MarkUnclaimed`].
+pub fn unclaimed_ready(nrs: &[NodeRequest]) -> impl Iterator<Item = &NodeRequest> {
+    nrs.iter().filter(|nr| {
+        nr.phase() == NodeRequestPhase::Ready
+            && nr
+                .metadata
+                .annotations
+                .as_ref()
+                .is_some_and(|a| a.contains_key(UNCLAIMED_ANNOTATION))
+    })
+}
+
+/// Live pods whose [`NOMINATED_ANNOTATION`] names a NodeRequest that is gone
+/// or no longer claims them, e.g. cancelled once the pods disappeared, or
+/// deleted after its Unmet or Ready TTL.
+pub fn stale_nominations(pods: &[Pod], nrs: &[NodeRequest]) -> Vec<PodId> {
+    let claims: HashMap<&str, HashSet<&str>> = nrs
+        .iter()
+        .filter_map(|nr| {
+            let claimed = nr.spec.claimed_pods.iter().map(String::as_str).collect();
+            Some((nr.metadata.name.as_deref()?, claimed))
+        })
+        .collect();
+    pods.iter()
+        .filter(|pod| {
+            let finished = pod
+                .status
+                .as_ref()
+                .and_then(|s| s.phase.as_deref())
+                .is_some_and(|p| p == "Succeeded" || p == "Failed");
+            pod.metadata.deletion_timestamp.is_none() && !finished
+        })
+        .filter_map(|pod| {
+            let nr_name = pod
+                .metadata
+                .annotations
+                .as_ref()?
+                .get(NOMINATED_ANNOTATION)?;
+            let uid = pod.metadata.uid.as_deref()?;
+            let claimed = claims
+                .get(nr_name.as_str())
+                .is_some_and(|claimed| claimed.contains(uid));
+            (!claimed).then(|| {
+                PodId::new(
+                    pod.metadata.namespace.as_deref().unwrap_or("default"),
+                    pod.metadata.name.clone().unwrap_or_default(),
+                )
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::{PodCondition, PodSpec, PodStatus};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use kube::api::ObjectMeta;
+
+    use crate::offering::{InstanceType, POOL_LABEL, Region, Resources};
+    use crate::resources::node_request::{NodeRequestSpec, NodeRequestStatus};
+
+    fn nr(name: &str, phase: NodeRequestPhase, claims: &[&str]) -> NodeRequest {
+        let mut nr = NodeRequest::new(
+            name,
+            NodeRequestSpec {
+                target_offering: InstanceType("cpx22".into()),
+                location: Region("eu-central".into()),
+                zone: None,
+                resources: Resources {
+                    cpu_millis: 2000,
+                    memory_mib: 4096,
+                    ephemeral_storage_gib: None,
+                    gpu: 0,
+                    gpu_model: None,
+                    arch: None,
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
+                },
+                node_id: format!("growth-{name}"),
+                provider: None,
+                claimed_pods: claims.iter().map(|s| s.to_string()).collect(),
+                labels: None,
+                taints: None,
+            },
+        );
+        nr.status = Some(NodeRequestStatus {
+            phase,
+            ..Default::default()
+        });
+        nr
+    }
+
+    fn pending_pod(uid: &str, pool: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(uid.into()),
+                namespace: Some("default".into()),
+                uid: Some(uid.into()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                node_selector: Some(BTreeMap::from([(POOL_LABEL.into(), pool.into())])),
+                containers: vec![],
+                ..Default::default()
+            }),
+            status: Some(PodStatus {
+                phase: Some("Pending".into()),
+                conditions: Some(vec![PodCondition {
+                    type_: "PodScheduled".into(),
+                    status: "False".into(),
+                    reason: Some("Unschedulable".into()),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn bound_pod(uid: &str, pool: &str, node: &str) -> Pod {
+        let mut pod = pending_pod(uid, pool);
+        pod.spec.as_mut().unwrap().node_name = Some(node.into());
+        pod.status = Some(PodStatus {
+            phase: Some("Running".into()),
+            ..Default::default()
+        });
+        pod
+    }
+
+    #[test]
+    fn live_claims_are_kept() {
+        let a = nr("a", NodeRequestPhase::Provisioning, &["p1", "p2"]);
+        let pods = vec![pending_pod("p1", "web"), bound_pod("p2", "web", "growth-a")];
+        assert_eq!(decide_claims(&a, "web", &pods, &[]), ClaimAction::Keep);
+    }
+
+    #[test]
+    fn deleted_and_rescheduled_pods_are_released() {
+        let a = nr(
+            "a",
+            NodeRequestPhase::Provisioning,
+            &["gone", "moved", "p3", "p4"],
+        );
+        let mut terminating = pending_pod("p4", "web");
+        terminating.metadata.deletion_timestamp =
+            Some(Time(k8s_openapi::jiff::Timestamp::UNIX_EPOCH));
+        let pods = vec![
+            bound_pod("moved", "web", "existing-node"),
+            pending_pod("p3", "web"),
+            terminating,
+        ];
+        assert_eq!(
+            decide_claims(&a, "web", &pods, &[]),
+            ClaimAction::Release {
+                remaining: vec!["p3".into()]
+            }
+        );
+    }
+
+    #[test]
+    fn fully_stale_request_is_cancelled_or_marked_by_phase() {
+        let pods = vec![bound_pod("p1", "web", "existing-node")];
+        let pending = nr("a", NodeRequestPhase::Pending, &["p1"]);
+        assert_eq!(
+            decide_claims(&pending, "web", &pods, &[]),
+            ClaimAction::Cancel
+        );
+
+        let provisioning = nr("b", NodeRequestPhase::Provisioning, &["p1"]);
+        assert_eq!(
+            decide_claims(&provisioning, "web", &pods, &[]),
+            ClaimAction::MarkUnclaimed
+        );
+    }
+
+    #[test]
+    fn unclaimed_pool_demand_keeps_the_node() {
+        let a = nr("a", NodeRequestPhase::Provisioning, &["gone"]);
+        let other = nr("b", NodeRequestPhase::Provisioning, &["claimed"]);
+        let nrs = vec![a.clone(), other];
+
+        // Pending pods claimed by another NodeRequest, or in another pool, don't count.
+        let pods = vec![pending_pod("claimed", "web"), pending_pod("batch", "batch")];
+        assert_eq!(
+            decide_claims(&a, "web", &pods, &nrs),
+            ClaimAction::MarkUnclaimed
+        );
+
+        let pods = vec![pending_pod("newcomer", "web")];
+        assert_eq!(
+            decide_claims(&a, "web", &pods, &nrs),
+            ClaimAction::Release { remaining: vec![] }
+        );
+    }
+
+    #[test]
+    fn ready_and_unclaimed_requests_are_left_alone() {
+        let ready = nr("a", NodeRequestPhase::Ready, &["gone"]);
+        assert_eq!(decide_claims(&ready, "web", &[], &[]), ClaimAction::Keep);
+
+        let legacy = nr("b", NodeRequestPhase::Pending, &[]);
+        assert_eq!(decide_claims(&legacy, "web", &[], &[]), ClaimAction::Keep);
+    }
+
+    #[test]
+    fn nominations_of_pods_no_longer_claimed_are_stale() {
+        let nominated = |uid: &str, nr_name: &str| {
+            let mut pod = pending_pod(uid, "web");
+            pod.metadata.annotations = Some(BTreeMap::from([(
+                NOMINATED_ANNOTATION.to_string(),
+                nr_name.to_string(),
+            )]));
+            pod
+        };
+        let nrs = [nr("a", NodeRequestPhase::Provisioning, &["p1"])];
+        let pods = [
+            nominated("p1", "a"),
+            // Released from its claims.
+            nominated("p2", "a"),
+            // Its NodeRequest is gone.
+            nominated("p3", "cancelled"),
+            pending_pod("p4", "web"),
+        ];
+
+        assert_eq!(
+            stale_nominations(&pods, &nrs),
+            [PodId::new("default", "p2"), PodId::new("default", "p3")]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use k8s_openapi::api::core::v1::Pod;
+use kube::Client;
+use kube::api::{Api, Patch, PatchParams};
+
+use crate::offering::{NOMINATED_ANNOTATION, PodId, UNCLAIMED_ANNOTATION};
+use crate::resources::node_request::NodeRequest;
+
+/// Reads a claims update makes before leaving a NodeRequest that keeps
+/// changing under it to the next reconcile.
+const CLAIM_ATTEMPTS: usize = 5;
+
+/// Rewrite a NodeRequest's claimed pods with `update`, given the claims it
+/// holds now. The patch is pinned to the resourceVersion those claims were
+/// read at, so a nomination or release written in between fails it with a
+/// conflict instead of being overwritten; the NodeRequest is then re-read
+/// and `update` applied to its new claims. With `unclaimed_when_empty`, a
+/// NodeRequest left without claims is also marked with
+/// [`UNCLAIMED_ANNOTATION`], so its node is removed on join.
+pub async fn update_claims(
+    client: &Client,
+    name: &str,
+    unclaimed_when_empty: bool,
+    mut update: impl FnMut(&[String]) -> Vec<String>,
+) -> Result<(), kube::Error> {
+    let api: Api<NodeRequest> = Api::all(client.clone());
+    let mut conflict = None;
+    for _ in 0..CLAIM_ATTEMPTS {
+        let nr = api.get(name).await?;
+        let claims = update(&nr.spec.claimed_pods);
+        let mut patch = serde_json::json!({
+            "metadata": { "resourceVersion": nr.metadata.resource_version },
+            "spec": { "claimedPods": claims },
+        });
+        if unclaimed_when_empty && claims.is_empty() {
+            patch["metadata"]["annotations"] = serde_json::json!({ UNCLAIMED_ANNOTATION: "true" });
+        }
+        match api
+            .patch(name, &PatchParams::default(), &Patch::Merge(patch))
+            .await
+        {
+            Ok(_) => return Ok(()),
+            Err(kube::Error::Api(resp)) if resp.code == 409 => {
+                conflict = Some(kube::Error::Api(resp));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(conflict.expect("every attempt conflicted"))
+}
+
+/// Point the pod's [`NOMINATED_ANNOTATION`] at `nr_name`, or remove it.
+pub async fn set_nomination(
+    client: &Client,
+    pod: &PodId,
+    nr_name: Option<&str>,
+) -> Result<(), kube::Error> {
+    let api: Api<Pod> = Api::namespaced(client.clone(), &pod.namespace);
+    let patch = serde_json::json!({
+        "metadata": { "annotations": { NOMINATED_ANNOTATION: nr_name } }
+    });
+    api.patch(&pod.name, &PatchParams::default(), &Patch::Merge(patch))
+        .await?;
+    Ok(())
+}
+
+/// Remove the mark `update_claims` sets on a NodeRequest left unclaimed.
+pub async fn clear_unclaimed(client: &Client, name: &str) -> Result<(), kube::Error> {
+    let api: Api<NodeRequest> = Api::all(client.clone());
+    let patch = serde_json::json!({
+        "metadata": { "annotations": { UNCLAIMED_ANNOTATION: null } }
+    });
+    api.patch(name, &PatchParams::default(), &Patch::Merge(patch))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn claims_are_rewritten_on_the_version_they_were_read_at() {
+        let (mock_svc, mut handle) = tower_test::mock::pair::<
+            http::Request<kube::client::Body>,
+            http::Response<kube::client::Body>,
+        >();
+        let object = |resource_version: &str, claims: &[&str]| {
+            serde_json::to_vec(&serde_json::json!({
+                "apiVersion": "growth.vettrdev.com/v1alpha1",
+                "kind": "NodeRequest",
+                "metadata": { "name": "nr-a", "resourceVersion": resource_version },
+                "spec": {
+                    "nodeID": "growth-a",
+                    "targetOffering": "cpx22",
+                    "location": "eu-central",
+                    "resources": { "cpuMillis": 2000, "memoryMib": 4096, "gpu": 0 },
+                    "claimedPods": claims,
+                },
+            }))
+            .unwrap()
+        };
+        let server = tokio::spawn(async move {
+            let mut patches = vec![];
+            // A pod is nominated between the first read and its write.
+            for (resource_version, claims, code) in [
+                ("41", &["a", "b"][..], 409),
+                ("42", &["a", "b", "c"][..], 200),
+            ] {
+                let (request, send) = handle.next_request().await.unwrap();
+                assert_eq!(request.method(), http::Method::GET);
+                send.send_response(
+                    http::Response::builder()
+                        .body(object(resource_version, claims).into())
+                        .unwrap(),
+                );
+                let (request, send) = handle.next_request().await.unwrap();
+                assert_eq!(request.method(), http::Method::PATCH);
+                let body = request.into_body().collect_bytes().await.unwrap();
+                let patch: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                assert_eq!(patch["metadata"]["resourceVersion"], resource_version);
+                patches.push(patch);
+                let body = if code == 409 {
+                    serde_json::to_vec(&serde_json::json!({
+                        "kind": "Status", "apiVersion": "v1", "status": "Failure",
+                        "reason": "Conflict", "message": "object was modified", "code": 409
+                    }))
+                    .unwrap()
+                } else {
+                    object(resource_version, claims)
+                };
+                send.send_response(
+                    http::Response::builder()
+                        .status(code)
+                        .body(body.into())
+                        .unwrap(),
+                );
+            }
+            patches
+        });
+
+        let client = Client::new(mock_svc, "default");
+        // Release `a`, the one stale claim.
+        update_claims(&client, "nr-a", true, |claims| {
+            claims.iter().filter(|c| *c != "a").cloned().collect()
+        })
+        .await
+        .unwrap();
+        let patches = server.await.unwrap();
+        assert_eq!(
+            patches[1]["spec"]["claimedPods"],
+            serde_json::json!(["b", "c"])
+        );
+        // Claims remain, so the node isn't marked for removal.
+        assert!(patches[1]["metadata"].get("annotations").is_none());
+    }
+}
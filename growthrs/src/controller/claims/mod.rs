@@ -0,0 +1,248 @@
+//! Garbage collection of stale pod claims on in-flight NodeRequests.
+//!
+//! A NodeRequest records the pods it was created for. When those pods are
+//! deleted or scheduled elsewhere before the node arrives, their claims are
+//! released; if none remain and nothing else in the pool needs the node, a
+//! Pending NodeRequest is cancelled and a Provisioning one is marked so its
+//! node is removed as soon as it joins, skipping the idle cooling-off.
+//!
+//! Pods are annotated with the NodeRequest provisioning for them; the
+//! annotation is removed once no NodeRequest claims the pod any more.
+//!
+//! A Pending NodeRequest deleted mid-provision may still yield a node. It
+//! joins with Growth labels and no workload, so the idle scanner removes it.
+pub mod decision;
+pub(crate) mod helpers;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use k8s_openapi::api::core::v1::{Node, Pod};
+use kube::api::ListParams;
+use kube::{Api, ResourceExt};
+use tracing::{debug, info, warn};
+
+use crate::controller::node_removal::create_node_removal_request;
+use crate::controller::node_removal::decision::{PoolMinCounts, find_idle_nodes};
+use crate::controller::node_removal::helpers::apply_scale_down_taint;
+use crate::controller::node_requests::helpers::delete_node_request;
+use crate::controller::node_requests::pool_name_from_nr;
+use crate::controller::shard::Shard;
+use crate::offering::{MANAGED_BY_SELECTOR, PodId};
+use crate::resources::node_pool::NodePool;
+use crate::resources::node_removal_request::{NodeRemovalRequest, NodeRemovalRequestPhase};
+use crate::resources::node_request::NodeRequest;
+
+use super::{ControllerContext, ControllerError, is_kube_not_found};
+
+use decision::{ClaimAction, decide_claims, stale_nominations, unclaimed_ready};
+use helpers::{clear_unclaimed, set_nomination, update_claims};
+
+/// Cluster state snapshot for one claim GC pass.
+struct ClaimScanState {
+    nrs: Vec<NodeRequest>,
+    pods: Vec<Pod>,
+    nodes: Vec<Node>,
+    nrrs: Vec<NodeRemovalRequest>,
+    pool_mins: Vec<PoolMinCounts>,
+    /// Pods still annotated with a NodeRequest that no longer claims them,
+    /// across every shard's NodeRequests.
+    stale_nominations: Vec<PodId>,
+}
+
+impl ClaimScanState {
+    async fn collect(client: &kube::Client, shard: &Shard) -> Result<Self, kube::Error> {
+        let lp = ListParams::default();
+        let (mut nrs, pods, mut nodes, nrrs, pools) = tokio::try_join!(
+            async {
+                Api::<NodeRequest>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<Pod>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<Node>::all(client.clone())
+                    .list(&ListParams::default().labels(MANAGED_BY_SELECTOR))
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<NodeRemovalRequest>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<NodePool>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+        )?;
+        shard.retain_owned_nodes(&mut nodes, &pools);
+        let stale_nominations = stale_nominations(&pods, &nrs);
+        if shard.is_sharded() {
+            let owned = shard.owned_pools(&pools);
+            nrs.retain(|nr| pool_name_from_nr(nr).is_ok_and(|pool| owned.contains(pool.as_str())));
+        }
+        Ok(Self {
+            nrs,
+            pods,
+            nodes,
+            nrrs,
+            pool_mins: PoolMinCounts::from_node_pools(&pools),
+            stale_nominations,
+        })
+    }
+}
+
+/// Periodically release stale pod claims.
+pub(super) async fn run_claim_gc(ctx: Arc<ControllerContext>) -> Result<(), ControllerError> {
+    loop {
+        tokio::time::sleep(ctx.requeue.interval).await;
+        let gc = gc_claims(&ctx);
+        if let Err(e) = ctx.metrics.reconciles.measure("claim_gc", gc).await {
+            warn!(error = %e, "claim GC failed, will retry next interval");
+        }
+    }
+}
+
+async fn gc_claims(ctx: &ControllerContext) -> Result<(), ControllerError> {
+    let state = ClaimScanState::collect(&ctx.client, &ctx.shard).await?;
+
+    let growth_nodes: HashSet<&str> = state
+        .nodes
+        .iter()
+        .filter_map(|n| n.metadata.name.as_deref())
+        .collect();
+    ctx.metrics
+        .demand_latency
+        .observe(&state.pods, &growth_nodes);
+
+    for nr in &state.nrs {
+        let Some(name) = nr.metadata.name.as_deref() else {
+            continue;
+        };
+        let pool = match pool_name_from_nr(nr) {
+            Ok(pool) => pool,
+            Err(e) => {
+                warn!(nr = name, error = %e, "skipping claim GC");
+                continue;
+            }
+        };
+        let result = match decide_claims(nr, &pool, &state.pods, &state.nrs) {
+            ClaimAction::Keep => continue,
+            ClaimAction::Release { remaining } => {
+                info!(
+                    nr = name,
+                    released = nr.spec.claimed_pods.len() - remaining.len(),
+                    remaining = remaining.len(),
+                    "releasing stale pod claims"
+                );
+                release_claims(&ctx.client, nr, &remaining, false).await
+            }
+            ClaimAction::Cancel => {
+                info!(nr = name, "all claimed pods gone, cancelling NodeRequest");
+                delete_node_request(ctx.client.clone(), name).await
+            }
+            ClaimAction::MarkUnclaimed => {
+                info!(nr = name, node = %nr.spec.node_id, "all claimed pods gone, node will be removed on join");
+                release_claims(&ctx.client, nr, &[], true).await
+            }
+        };
+        if let Err(e) = result {
+            warn!(nr = name, error = %e, "failed to garbage-collect claims");
+        }
+    }
+
+    for pod in &state.stale_nominations {
+        debug!(%pod, "removing stale NodeRequest nomination");
+        if let Err(e) = set_nomination(&ctx.client, pod, None).await
+            && !is_kube_not_found(&e)
+        {
+            warn!(%pod, error = %e, "failed to remove stale nomination");
+        }
+    }
+
+    // Pool minimums and existing removals still apply, so only nodes the idle
+    // scanner would also pick are removed.
+    let idle: HashSet<String> =
+        find_idle_nodes(&state.nodes, &state.pods, &state.nrrs, &state.pool_mins)
+            .into_iter()
+            .map(|n| n.node_name)
+            .collect();
+    for nr in unclaimed_ready(&state.nrs) {
+        let Some(name) = nr.metadata.name.as_deref() else {
+            continue;
+        };
+        let node = &nr.spec.node_id;
+        if idle.contains(node) {
+            info!(nr = name, %node, "unclaimed node joined idle, removing");
+            if let Err(e) = remove_unclaimed_node(ctx, nr).await {
+                warn!(nr = name, %node, error = %e, "failed to remove unclaimed node");
+                continue;
+            }
+        }
+        // Either removal is underway, or the node picked up other workload.
+        if let Err(e) = clear_unclaimed(&ctx.client, name).await {
+            warn!(nr = name, error = %e, "failed to clear unclaimed mark");
+        }
+    }
+    Ok(())
+}
+
+/// Drop the claims of `nr` that aren't in `remaining`. Pods claimed since
+/// `nr` was read are kept, and only leave the NodeRequest unclaimed (see
+/// [`update_claims`]) if none are.
+async fn release_claims(
+    client: &kube::Client,
+    nr: &NodeRequest,
+    remaining: &[String],
+    unclaimed_when_empty: bool,
+) -> Result<(), kube::Error> {
+    let released: HashSet<&String> = nr
+        .spec
+        .claimed_pods
+        .iter()
+        .filter(|uid| !remaining.contains(uid))
+        .collect();
+    update_claims(client, &nr.name_any(), unclaimed_when_empty, |claims| {
+        claims
+            .iter()
+            .filter(|uid| !released.contains(uid))
+            .cloned()
+            .collect()
+    })
+    .await
+}
+
+/// Taint the node and hand it straight to the NodeRemovalRequest controller
+/// in Deprovisioning, as for orphaned nodes.
+async fn remove_unclaimed_node(
+    ctx: &ControllerContext,
+    nr: &NodeRequest,
+) -> Result<(), ControllerError> {
+    let pool = pool_name_from_nr(nr)?;
+    apply_scale_down_taint(&ctx.client, &nr.spec.node_id).await?;
+    match create_node_removal_request(
+        ctx.client.clone(),
+        &nr.spec.node_id,
+        None,
+        &pool,
+        &nr.spec.target_offering.0,
+        NodeRemovalRequestPhase::Deprovisioning,
+        ctx.clock.now(),
+    )
+    .await
+    {
+        Ok(_) => Ok(()),
+        Err(kube::Error::Api(ref resp)) if resp.code == 409 => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
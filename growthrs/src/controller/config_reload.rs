@@ -0,0 +1,174 @@
+//! Runtime reload of [`Tunables`] from a ConfigMap.
+//!
+//! The ConfigMap's `config.yaml` is in the config file format (see
+//! [`settings_from_yaml`]), and the environment still overrides it, as at
+//! startup. Edits take effect on the next solve, without restarting the
+//! controller: in-flight batches, unconfirmed creates and the other watches
+//! are kept. Settings read only at startup (provider, sharding, timeouts)
+//! are ignored here.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures_util::StreamExt;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::Api;
+use kube::runtime::watcher;
+use tracing::{info, warn};
+
+use crate::config::{SharedTunables, Tunables, settings_from_yaml};
+use crate::controller::errors::ConfigError;
+use crate::controller::{ControllerContext, ControllerError};
+
+/// Key of the ConfigMap holding the settings.
+pub const CONFIG_KEY: &str = "config.yaml";
+
+/// Applies the watched ConfigMap to the controller's [`SharedTunables`].
+pub struct ConfigReloader {
+    pub namespace: String,
+    pub name: String,
+    /// What the controller started with, restored if the ConfigMap goes.
+    startup: Tunables,
+    /// resourceVersion of the ConfigMap last acted on.
+    observed: Mutex<Option<String>>,
+}
+
+impl ConfigReloader {
+    pub fn new(namespace: String, name: String, startup: Tunables) -> Self {
+        Self {
+            namespace,
+            name,
+            startup,
+            observed: Mutex::new(None),
+        }
+    }
+
+    /// Apply `cm` unless this version was already acted on. Invalid settings
+    /// are logged and the current ones kept.
+    fn apply(&self, tunables: &SharedTunables, cm: &ConfigMap) {
+        let version = cm.metadata.resource_version.clone();
+        {
+            let mut observed = self.observed.lock().unwrap();
+            if version.is_some() && *observed == version {
+                return;
+            }
+            *observed = version;
+        }
+        match tunables_from_config_map(cm, std::env::vars()) {
+            Ok(new) => {
+                info!(
+                    config_map = %self.name,
+                    solver = new.solver.name(),
+                    "applied settings from ConfigMap"
+                );
+                tunables.replace(new);
+            }
+            Err(e) => {
+                warn!(config_map = %self.name, error = %e, "invalid settings in ConfigMap, keeping current ones");
+            }
+        }
+    }
+
+    /// Return to the settings the controller started with.
+    fn reset(&self, tunables: &SharedTunables) {
+        if self.observed.lock().unwrap().take().is_some() {
+            info!(config_map = %self.name, "ConfigMap removed, reverted to startup settings");
+            tunables.replace(self.startup.clone());
+        }
+    }
+}
+
+/// The tunables `cm` describes, with `env` laid over them.
+fn tunables_from_config_map(
+    cm: &ConfigMap,
+    env: impl IntoIterator<Item = (String, String)>,
+) -> Result<Tunables, ConfigError> {
+    let yaml = cm
+        .data
+        .as_ref()
+        .and_then(|d| d.get(CONFIG_KEY))
+        .ok_or_else(|| ConfigError::Other(format!("ConfigMap has no {CONFIG_KEY} key")))?;
+    let mut settings: HashMap<String, String> = settings_from_yaml(yaml)?;
+    settings.extend(env);
+    Tunables::from_settings(&settings)
+}
+
+/// Watch the configured ConfigMap and apply its settings as it changes.
+/// Never resolves when reloading is disabled.
+pub async fn run_config_map_watcher(ctx: Arc<ControllerContext>) -> Result<(), ControllerError> {
+    let Some(reloader) = &ctx.config_reloader else {
+        return std::future::pending().await;
+    };
+    let api: Api<ConfigMap> = Api::namespaced(ctx.client.clone(), &reloader.namespace);
+    let config = watcher::Config::default().fields(&format!("metadata.name={}", reloader.name));
+    let mut stream = std::pin::pin!(watcher::watcher(api, config));
+
+    let mut seen_in_relist = false;
+    while let Some(event) = stream.next().await {
+        match event {
+            Ok(watcher::Event::Init) => seen_in_relist = false,
+            Ok(watcher::Event::InitApply(cm)) => {
+                seen_in_relist = true;
+                reloader.apply(&ctx.tunables, &cm);
+            }
+            Ok(watcher::Event::InitDone) => {
+                if !seen_in_relist {
+                    reloader.reset(&ctx.tunables);
+                }
+            }
+            Ok(watcher::Event::Apply(cm)) => reloader.apply(&ctx.tunables, &cm),
+            Ok(watcher::Event::Delete(_)) => reloader.reset(&ctx.tunables),
+            Err(e) => warn!(error = %e, "ConfigMap watcher stream error"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use kube::api::ObjectMeta;
+
+    use super::*;
+
+    fn config_map(version: &str, yaml: &str) -> ConfigMap {
+        ConfigMap {
+            metadata: ObjectMeta {
+                name: Some("growth-config".into()),
+                resource_version: Some(version.into()),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from([(CONFIG_KEY.to_string(), yaml.to_string())])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn edits_replace_the_tunables_and_bad_ones_are_ignored() {
+        let cm = config_map(
+            "1",
+            "solver: first-fit\nmaxNewNodes: 3\npoolDefaults: {targetUtilization: 0.5}\n",
+        );
+        let env = [("GROWTH_MAX_NEW_NODES".to_string(), "10".to_string())];
+        let tunables = tunables_from_config_map(&cm, env).unwrap();
+        assert_eq!(tunables.solver.name(), "first-fit");
+        // The environment wins, as at startup.
+        assert_eq!(tunables.max_new_nodes, Some(10));
+        assert_eq!(tunables.pool_defaults.target_utilization, 0.5);
+
+        let shared = SharedTunables::default();
+        let reloader =
+            ConfigReloader::new("growth".into(), "growth-config".into(), Tunables::default());
+        reloader.apply(&shared, &config_map("2", "podNamespaces: [payments]\n"));
+        let changes = shared.subscribe();
+        assert!(!shared.current().pod_scope.admits(&Default::default()));
+
+        reloader.apply(&shared, &config_map("3", "solver: quantum\n"));
+        assert!(!changes.has_changed().unwrap());
+
+        reloader.reset(&shared);
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(shared.current().pod_scope, Default::default());
+    }
+}
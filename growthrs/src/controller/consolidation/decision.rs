@@ -0,0 +1,650 @@
+use std::collections::HashSet;
+
+use k8s_openapi::api::core::v1::{Node, Pod};
+use k8s_openapi::jiff::{SignedDuration, Timestamp};
+
+use crate::controller::evacuation::decision::{
+    EvacuationStep, ReplacementProgress, decide_step, displaced_pods,
+};
+use crate::controller::karpenter::backs_node_claim;
+use crate::controller::pdb::{autoscaler_opt_out, do_not_disrupt};
+use crate::controller::pods::{NodeRequestDemand, ReconcileResult};
+use crate::offering::{
+    CONSOLIDATING_ANNOTATION, EVACUATING_ANNOTATION, INSTANCE_TYPE_LABEL, NodeReference, Offering,
+    REBALANCE_CONDITION, REBALANCE_TAINT_KEY, REGION_LABEL, ZONE_LABEL,
+};
+use crate::resources::node_pool::NodePool;
+use crate::resources::node_removal_request::NodeRemovalRequest;
+
+/// Whether consolidation must leave a pod or node alone: it is marked
+/// do-not-disrupt, or opted out of cluster-autoscaler's scale-down.
+pub fn pinned(meta: &kube::api::ObjectMeta) -> bool {
+    do_not_disrupt(meta) || autoscaler_opt_out(meta)
+}
+
+/// How a node being consolidated goes away, as recorded in its
+/// [`CONSOLIDATING_ANNOTATION`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsolidationKind {
+    /// Its pods fit on the pool's other nodes.
+    Delete,
+    /// A cheaper node is provisioned for its pods first.
+    Replace,
+}
+
+impl ConsolidationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Delete => "delete",
+            Self::Replace => "replace",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "delete" => Some(Self::Delete),
+            "replace" => Some(Self::Replace),
+            _ => None,
+        }
+    }
+
+    /// The consolidation a node is cordoned for, if any.
+    pub fn of(node: &Node) -> Option<Self> {
+        node.metadata
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get(CONSOLIDATING_ANNOTATION))
+            .and_then(|v| Self::parse(v))
+    }
+}
+
+/// What a solve of a candidate's pods says to do with the node.
+#[derive(Debug)]
+pub enum ConsolidationPlan {
+    Delete,
+    Replace(Box<NodeRequestDemand>),
+}
+
+impl ConsolidationPlan {
+    pub fn kind(&self) -> ConsolidationKind {
+        match self {
+            Self::Delete => ConsolidationKind::Delete,
+            Self::Replace(_) => ConsolidationKind::Replace,
+        }
+    }
+}
+
+/// A busy node in a consolidating pool, and the pods that would move.
+#[derive(Debug)]
+pub struct Candidate<'a> {
+    pub node: NodeReference,
+    pub pods: Vec<&'a Pod>,
+}
+
+/// Nodes worth trying to consolidate: Growth-managed, in a pool with
+/// `spec.consolidation` set and no other consolidation under way, older than
+/// `min_age`, schedulable, not already being removed, and running workload
+/// pods (idle nodes are the idle scanner's), none of them, nor the node,
+/// marked do-not-disrupt. Fewest pods first, as those are the cheapest to
+/// move.
+pub fn consolidation_candidates<'a>(
+    nodes: &[Node],
+    pods: &'a [Pod],
+    nrrs: &[NodeRemovalRequest],
+    pools: &[NodePool],
+    min_age: SignedDuration,
+    now: Timestamp,
+) -> Vec<Candidate<'a>> {
+    let tracked: HashSet<&str> = nrrs.iter().map(|n| n.spec.node_name.as_str()).collect();
+    let busy_pools: HashSet<String> = nodes
+        .iter()
+        .filter(|n| ConsolidationKind::of(n).is_some())
+        .filter_map(NodeReference::from_node)
+        .map(|n| n.pool)
+        .collect();
+    let consolidating = |pool: &str| {
+        !busy_pools.contains(pool)
+            && pools
+                .iter()
+                .any(|p| p.metadata.name.as_deref() == Some(pool) && p.spec.consolidation.is_some())
+    };
+
+    let mut candidates: Vec<Candidate<'a>> = nodes
+        .iter()
+        .filter(|node| {
+            let old_enough = node
+                .metadata
+                .creation_timestamp
+                .as_ref()
+                .is_some_and(|t| now.duration_since(t.0) >= min_age);
+            old_enough && movable(node)
+        })
+        .filter_map(NodeReference::from_node)
+        .filter(|n| consolidating(&n.pool) && !tracked.contains(n.node_name.as_str()))
+        .filter_map(|node| candidate(node, pods))
+        .collect();
+    candidates.sort_by(|a, b| {
+        a.pods
+            .len()
+            .cmp(&b.pods.len())
+            .then_with(|| a.node.node_name.cmp(&b.node.node_name))
+    });
+    candidates
+}
+
+/// Whether a node may be consolidated at all: schedulable, not already
+/// consolidating or evacuating, not pinned, not backing a Karpenter NodeClaim
+/// and not being deleted.
+fn movable(node: &Node) -> bool {
+    let cordoned = node
+        .spec
+        .as_ref()
+        .and_then(|s| s.unschedulable)
+        .unwrap_or(false);
+    let annotated = node.metadata.annotations.as_ref().is_some_and(|a| {
+        a.contains_key(EVACUATING_ANNOTATION) || a.contains_key(CONSOLIDATING_ANNOTATION)
+    });
+    !cordoned
+        && !annotated
+        && !pinned(&node.metadata)
+        && !backs_node_claim(&node.metadata)
+        && node.metadata.deletion_timestamp.is_none()
+}
+
+/// `node` with its workload pods, unless it has none or one is pinned.
+fn candidate<'a>(node: NodeReference, pods: &'a [Pod]) -> Option<Candidate<'a>> {
+    let pods = displaced_pods(&HashSet::from([node.node_name.as_str()]), pods);
+    let held = pods.iter().any(|p| pinned(&p.metadata));
+    (!pods.is_empty() && !held).then_some(Candidate { node, pods })
+}
+
+/// Whether `node` carries a spot rebalance recommendation: the
+/// [`REBALANCE_TAINT_KEY`] taint or a true [`REBALANCE_CONDITION`].
+pub fn rebalance_recommended(node: &Node) -> bool {
+    let tainted = node
+        .spec
+        .as_ref()
+        .and_then(|s| s.taints.as_ref())
+        .is_some_and(|t| t.iter().any(|t| t.key == REBALANCE_TAINT_KEY));
+    let conditioned = node
+        .status
+        .as_ref()
+        .and_then(|s| s.conditions.as_ref())
+        .is_some_and(|c| {
+            c.iter()
+                .any(|c| c.type_ == REBALANCE_CONDITION && c.status == "True")
+        });
+    tainted || conditioned
+}
+
+/// Nodes to replace ahead of a spot interruption: those with a rebalance
+/// recommendation that could otherwise be consolidated, whatever their pool's
+/// settings or age. Idle ones are left to the idle scanner.
+pub fn rebalance_candidates<'a>(
+    nodes: &[Node],
+    pods: &'a [Pod],
+    nrrs: &[NodeRemovalRequest],
+) -> Vec<Candidate<'a>> {
+    let tracked: HashSet<&str> = nrrs.iter().map(|n| n.spec.node_name.as_str()).collect();
+    nodes
+        .iter()
+        .filter(|node| rebalance_recommended(node) && movable(node))
+        .filter_map(NodeReference::from_node)
+        .filter(|n| !tracked.contains(n.node_name.as_str()))
+        .filter_map(|node| candidate(node, pods))
+        .collect()
+}
+
+/// A plan for a node with a rebalance recommendation: its pods move whatever
+/// the replacement costs, since the node is going away regardless.
+pub fn choose_rebalance_plan(result: ReconcileResult) -> Option<ConsolidationPlan> {
+    choose_plan(result, f64::INFINITY, 0.0)
+}
+
+/// Hourly cost of `node`, from the offering of its instance type in its
+/// region (and zone, when the offering has one).
+pub fn node_cost(node: &Node, offerings: &[Offering]) -> Option<f64> {
+    let labels = node.metadata.labels.as_ref()?;
+    let instance_type = labels.get(INSTANCE_TYPE_LABEL)?;
+    let region = labels.get(REGION_LABEL)?;
+    let zone = labels.get(ZONE_LABEL);
+    offerings
+        .iter()
+        .find(|o| {
+            &o.instance_type.0 == instance_type
+                && &o.location.region.0 == region
+                && o.location.zone.as_ref().is_none_or(|z| Some(&z.0) == zone)
+        })
+        .map(|o| o.cost_per_hour)
+}
+
+/// Turn a solve of a candidate's pods into a plan. Every pod must be placed:
+/// with no new node the pods fit on the pool's other nodes; with exactly one,
+/// it must cost less than `current_cost` by at least `min_savings` of it.
+pub fn choose_plan(
+    result: ReconcileResult,
+    current_cost: f64,
+    min_savings: f64,
+) -> Option<ConsolidationPlan> {
+    let unmet = result.solver_stats.values().map(|s| s.unmet).sum::<usize>();
+    if !result.pod_errors.is_empty() || unmet > 0 {
+        return None;
+    }
+    let mut demands = result.demands;
+    match demands.len() {
+        0 => Some(ConsolidationPlan::Delete),
+        1 => {
+            let replacement = demands.pop()?;
+            let cost = replacement.target_offering.cost_per_hour;
+            let limit = current_cost * (1.0 - min_savings.clamp(0.0, 1.0));
+            (cost < current_cost && cost <= limit)
+                .then_some(ConsolidationPlan::Replace(Box::new(replacement)))
+        }
+        _ => None,
+    }
+}
+
+/// Next action for a node cordoned for consolidation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsolidationStep {
+    /// The replacement is gone before the node was drained: uncordon it.
+    Release,
+    /// The replacement hasn't joined yet.
+    Wait,
+    /// Evict the node's pods, or remove it once empty.
+    Drain,
+}
+
+/// Like an evacuation, a replaced node is only drained once its replacement
+/// is Ready; a node whose pods fit elsewhere is drained straight away.
+pub fn consolidation_step(
+    kind: ConsolidationKind,
+    displaced: usize,
+    progress: ReplacementProgress,
+) -> ConsolidationStep {
+    match (kind, decide_step(displaced, progress)) {
+        (ConsolidationKind::Delete, _) => ConsolidationStep::Drain,
+        (ConsolidationKind::Replace, EvacuationStep::Provision) => ConsolidationStep::Release,
+        (ConsolidationKind::Replace, EvacuationStep::Wait) => ConsolidationStep::Wait,
+        (ConsolidationKind::Replace, EvacuationStep::Drain) => ConsolidationStep::Drain,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::{NodeCondition, NodeSpec, NodeStatus, PodSpec, Taint};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use kube::api::ObjectMeta;
+
+    use crate::offering::{
+        DO_NOT_DISRUPT_ANNOTATION, MANAGED_BY_LABEL, MANAGED_BY_VALUE, POOL_LABEL,
+        SAFE_TO_EVICT_ANNOTATION, SCALE_DOWN_DISABLED_ANNOTATION,
+    };
+    use crate::optimiser::SolveStats;
+    use crate::resources::node_pool::{Consolidation, NodePoolSpec};
+    use crate::testing::offering;
+
+    fn now() -> Timestamp {
+        Timestamp::from_second(100_000).unwrap()
+    }
+
+    fn node(name: &str, pool: &str, age_secs: i64) -> Node {
+        Node {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                uid: Some(format!("uid-{name}")),
+                labels: Some(BTreeMap::from([
+                    (MANAGED_BY_LABEL.to_string(), MANAGED_BY_VALUE.to_string()),
+                    (POOL_LABEL.to_string(), pool.to_string()),
+                    (INSTANCE_TYPE_LABEL.to_string(), "cx32".to_string()),
+                    (REGION_LABEL.to_string(), "eu-central".to_string()),
+                    (ZONE_LABEL.to_string(), "fsn1-dc14".to_string()),
+                ])),
+                creation_timestamp: Some(Time(now() - SignedDuration::from_secs(age_secs))),
+                ..Default::default()
+            },
+            spec: Some(NodeSpec::default()),
+            status: None,
+        }
+    }
+
+    fn pod(name: &str, node: &str, pool: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                namespace: Some("default".into()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                node_name: Some(node.into()),
+                node_selector: Some(BTreeMap::from([(POOL_LABEL.into(), pool.into())])),
+                containers: vec![],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn pool(name: &str, consolidation: bool) -> NodePool {
+        let mut pool = NodePool::new(
+            name,
+            NodePoolSpec {
+                server_types: vec![],
+                min_nodes: 0,
+                max_nodes: None,
+                headroom: None,
+                labels: BTreeMap::new(),
+                taints: vec![],
+                kubelet_args: vec![],
+                join_strategy: None,
+                locations: None,
+                node_class_ref: None,
+                evacuation: None,
+                consolidation: consolidation.then(Consolidation::default),
+                disruption_budget: None,
+                fragmentation_penalty: None,
+                target_utilization: None,
+                interruption_penalty: None,
+                cost_objective: None,
+                instance_families: None,
+                max_additional_cost_per_hour: None,
+                max_new_nodes: None,
+                include_instance_types: None,
+                exclude_instance_types: None,
+            },
+        );
+        pool.metadata.uid = Some(format!("{name}-uid"));
+        pool
+    }
+
+    fn result(new_nodes: &[f64], unmet: usize) -> ReconcileResult {
+        ReconcileResult {
+            demands: new_nodes
+                .iter()
+                .map(|&cost| NodeRequestDemand {
+                    pool: "web".into(),
+                    pool_uid: "web-uid".into(),
+                    target_offering: offering("cpx22")
+                        .with_cost(cost)
+                        .with_zone("fsn1-dc14")
+                        .build(),
+                    claimed_pods: vec![],
+                    labels: BTreeMap::new(),
+                    taints: vec![],
+                })
+                .collect(),
+            pod_errors: vec![],
+            unplaced: vec![],
+            solver_stats: BTreeMap::from([(
+                "web".to_string(),
+                SolveStats {
+                    unmet,
+                    ..Default::default()
+                },
+            )]),
+            in_flight_placements: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn candidates_are_busy_settled_nodes_in_consolidating_pools() {
+        let mut cordoned = node("cordoned", "web", 3600);
+        cordoned.spec.as_mut().unwrap().unschedulable = Some(true);
+        let nodes = vec![
+            node("busy", "web", 3600),
+            node("busier", "web", 3600),
+            node("idle", "web", 3600),
+            node("young", "web", 60),
+            node("batch", "batch", 3600),
+            cordoned,
+        ];
+        let pods = vec![
+            pod("a", "busy", "web"),
+            pod("b", "busier", "web"),
+            pod("c", "busier", "web"),
+            pod("d", "young", "web"),
+            pod("e", "batch", "batch"),
+            pod("f", "cordoned", "web"),
+        ];
+        let pools = vec![pool("web", true), pool("batch", false)];
+
+        let candidates = consolidation_candidates(
+            &nodes,
+            &pods,
+            &[],
+            &pools,
+            SignedDuration::from_secs(600),
+            now(),
+        );
+        let names: Vec<_> = candidates
+            .iter()
+            .map(|c| c.node.node_name.as_str())
+            .collect();
+        assert_eq!(names, ["busy", "busier"]);
+        assert_eq!(candidates[1].pods.len(), 2);
+    }
+
+    #[test]
+    fn one_consolidation_per_pool_at_a_time() {
+        let mut replacing = node("replacing", "web", 3600);
+        replacing.metadata.annotations = Some(BTreeMap::from([(
+            CONSOLIDATING_ANNOTATION.to_string(),
+            "replace".to_string(),
+        )]));
+        let nodes = vec![replacing, node("busy", "web", 3600)];
+        let pods = vec![pod("a", "busy", "web")];
+
+        let candidates = consolidation_candidates(
+            &nodes,
+            &pods,
+            &[],
+            &[pool("web", true)],
+            SignedDuration::from_secs(600),
+            now(),
+        );
+        assert!(candidates.is_empty());
+    }
+
+    #[test]
+    fn do_not_disrupt_pods_and_nodes_are_never_candidates() {
+        let pinned = BTreeMap::from([(DO_NOT_DISRUPT_ANNOTATION.to_string(), "true".to_string())]);
+        let mut pinned_node = node("pinned-node", "web", 3600);
+        pinned_node.metadata.annotations = Some(pinned.clone());
+        let nodes = vec![
+            pinned_node,
+            node("batch", "web", 3600),
+            node("free", "web", 3600),
+        ];
+        let mut job = pod("job", "batch", "web");
+        job.metadata.annotations = Some(pinned);
+        let pods = vec![
+            pod("a", "pinned-node", "web"),
+            job,
+            pod("b", "batch", "web"),
+            pod("c", "free", "web"),
+        ];
+
+        let candidates = consolidation_candidates(
+            &nodes,
+            &pods,
+            &[],
+            &[pool("web", true)],
+            SignedDuration::from_secs(600),
+            now(),
+        );
+        let names: Vec<&str> = candidates
+            .iter()
+            .map(|c| c.node.node_name.as_str())
+            .collect();
+        assert_eq!(names, ["free"]);
+    }
+
+    #[test]
+    fn node_claim_nodes_are_never_candidates() {
+        let mut claimed = node("claimed", "web", 3600);
+        claimed.metadata.labels.as_mut().unwrap().insert(
+            crate::controller::karpenter::NODECLAIM_LABEL.into(),
+            "claim-1".into(),
+        );
+        let nodes = vec![claimed, node("free", "web", 3600)];
+        let pods = vec![pod("a", "claimed", "web"), pod("b", "free", "web")];
+
+        let candidates = consolidation_candidates(
+            &nodes,
+            &pods,
+            &[],
+            &[pool("web", true)],
+            SignedDuration::from_secs(600),
+            now(),
+        );
+        let names: Vec<&str> = candidates
+            .iter()
+            .map(|c| c.node.node_name.as_str())
+            .collect();
+        assert_eq!(names, ["free"]);
+    }
+
+    #[test]
+    fn cluster_autoscaler_opt_outs_are_honoured() {
+        let mut disabled = node("disabled", "web", 3600);
+        disabled.metadata.annotations = Some(BTreeMap::from([(
+            SCALE_DOWN_DISABLED_ANNOTATION.to_string(),
+            "true".to_string(),
+        )]));
+        let nodes = vec![
+            disabled,
+            node("batch", "web", 3600),
+            node("free", "web", 3600),
+        ];
+        let mut job = pod("job", "batch", "web");
+        job.metadata.annotations = Some(BTreeMap::from([(
+            SAFE_TO_EVICT_ANNOTATION.to_string(),
+            "false".to_string(),
+        )]));
+        let mut evictable = pod("c", "free", "web");
+        evictable.metadata.annotations = Some(BTreeMap::from([(
+            SAFE_TO_EVICT_ANNOTATION.to_string(),
+            "true".to_string(),
+        )]));
+        let pods = vec![pod("a", "disabled", "web"), job, evictable];
+
+        let candidates = consolidation_candidates(
+            &nodes,
+            &pods,
+            &[],
+            &[pool("web", true)],
+            SignedDuration::from_secs(600),
+            now(),
+        );
+        let names: Vec<&str> = candidates
+            .iter()
+            .map(|c| c.node.node_name.as_str())
+            .collect();
+        assert_eq!(names, ["free"]);
+    }
+
+    #[test]
+    fn node_cost_comes_from_its_offering() {
+        let offerings = vec![
+            offering("cpx22")
+                .with_cost(0.01)
+                .with_zone("fsn1-dc14")
+                .build(),
+            offering("cx32")
+                .with_cost(0.03)
+                .with_zone("fsn1-dc14")
+                .build(),
+        ];
+        assert_eq!(node_cost(&node("n", "web", 0), &offerings), Some(0.03));
+        assert_eq!(node_cost(&node("n", "web", 0), &offerings[..1]), None);
+    }
+
+    #[test]
+    fn plan_needs_every_pod_placed_and_a_real_saving() {
+        assert!(matches!(
+            choose_plan(result(&[], 0), 0.03, 0.0),
+            Some(ConsolidationPlan::Delete)
+        ));
+        assert!(matches!(
+            choose_plan(result(&[0.01], 0), 0.03, 0.5),
+            Some(ConsolidationPlan::Replace(_))
+        ));
+        // Not cheap enough.
+        assert!(choose_plan(result(&[0.02], 0), 0.03, 0.5).is_none());
+        assert!(choose_plan(result(&[0.03], 0), 0.03, 0.0).is_none());
+        // Pods left over, or more than one node needed.
+        assert!(choose_plan(result(&[], 1), 0.03, 0.0).is_none());
+        assert!(choose_plan(result(&[0.01, 0.01], 0), 0.03, 0.0).is_none());
+    }
+
+    #[test]
+    fn rebalance_recommended_nodes_are_replaced_at_any_price() {
+        let mut tainted = node("tainted", "batch", 0);
+        tainted.spec.as_mut().unwrap().taints = Some(vec![Taint {
+            key: REBALANCE_TAINT_KEY.into(),
+            effect: "PreferNoSchedule".into(),
+            ..Default::default()
+        }]);
+        let mut conditioned = node("conditioned", "batch", 3600);
+        conditioned.status = Some(NodeStatus {
+            conditions: Some(vec![NodeCondition {
+                type_: REBALANCE_CONDITION.into(),
+                status: "True".into(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+        let mut idle = node("idle", "batch", 3600);
+        idle.spec.as_mut().unwrap().taints = tainted.spec.as_ref().unwrap().taints.clone();
+        let nodes = vec![tainted, conditioned, idle, node("healthy", "batch", 3600)];
+        let pods = vec![
+            pod("a", "tainted", "batch"),
+            pod("b", "conditioned", "batch"),
+            pod("c", "healthy", "batch"),
+        ];
+
+        // The pool doesn't consolidate, and one node is brand new.
+        let names: Vec<String> = rebalance_candidates(&nodes, &pods, &[])
+            .into_iter()
+            .map(|c| c.node.node_name)
+            .collect();
+        assert_eq!(names, ["tainted", "conditioned"]);
+
+        assert!(matches!(
+            choose_rebalance_plan(result(&[0.5], 0)),
+            Some(ConsolidationPlan::Replace(_))
+        ));
+        assert!(choose_rebalance_plan(result(&[], 1)).is_none());
+    }
+
+    #[test]
+    fn replaced_nodes_drain_only_once_the_replacement_is_ready() {
+        use ConsolidationKind::*;
+        use ReplacementProgress::*;
+
+        assert_eq!(
+            consolidation_step(Delete, 2, NotStarted),
+            ConsolidationStep::Drain
+        );
+        assert_eq!(
+            consolidation_step(Replace, 2, InFlight),
+            ConsolidationStep::Wait
+        );
+        assert_eq!(
+            consolidation_step(Replace, 2, Ready),
+            ConsolidationStep::Drain
+        );
+        assert_eq!(
+            consolidation_step(Replace, 2, NotStarted),
+            ConsolidationStep::Release
+        );
+        assert_eq!(
+            consolidation_step(Replace, 0, NotStarted),
+            ConsolidationStep::Drain
+        );
+    }
+}
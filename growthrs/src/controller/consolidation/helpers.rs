@@ -0,0 +1,35 @@
+use k8s_openapi::api::core::v1::Node;
+use kube::Client;
+use kube::api::{Api, Patch, PatchParams};
+
+use crate::offering::CONSOLIDATING_ANNOTATION;
+
+use super::decision::ConsolidationKind;
+
+/// Mark a node unschedulable and record how it is being consolidated.
+pub async fn cordon_for_consolidation(
+    client: &Client,
+    node_name: &str,
+    kind: ConsolidationKind,
+) -> Result<(), kube::Error> {
+    let api: Api<Node> = Api::all(client.clone());
+    let patch = serde_json::json!({
+        "metadata": { "annotations": { CONSOLIDATING_ANNOTATION: kind.as_str() } },
+        "spec": { "unschedulable": true }
+    });
+    api.patch(node_name, &PatchParams::default(), &Patch::Merge(patch))
+        .await?;
+    Ok(())
+}
+
+/// Undo `cordon_for_consolidation` when the node is kept after all.
+pub async fn release_node(client: &Client, node_name: &str) -> Result<(), kube::Error> {
+    let api: Api<Node> = Api::all(client.clone());
+    let patch = serde_json::json!({
+        "metadata": { "annotations": { CONSOLIDATING_ANNOTATION: null } },
+        "spec": { "unschedulable": null }
+    });
+    api.patch(node_name, &PatchParams::default(), &Patch::Merge(patch))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,437 @@
+//! Cost consolidation of busy nodes.
+//!
+//! For NodePools with `spec.consolidation` set, each scan re-solves the pods
+//! of the pool's nodes one node at a time, as if that node were gone. If they
+//! fit on the pool's other nodes, the node is cordoned and drained; if one
+//! cheaper node would hold them, the node is cordoned, the replacement is
+//! provisioned, and the pods are only evicted once it is Ready. Emptied nodes
+//! are handed to the NodeRemovalRequest controller. Each pool consolidates one
+//! node at a time.
+pub mod decision;
+pub(crate) mod helpers;
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use k8s_openapi::api::core::v1::{Node, Pod};
+use k8s_openapi::jiff::SignedDuration;
+use kube::Api;
+use kube::api::ListParams;
+use tracing::{debug, info, warn};
+
+use crate::controller::evacuation::decision::{displaced_pods, replacement_progress};
+use crate::controller::evacuation::helpers::evict_pod;
+use crate::controller::node_removal::create_node_removal_request;
+use crate::controller::node_requests::helpers::create_node_request;
+use crate::controller::pdb::PdbBudgets;
+use crate::controller::pods::{ReconcileResult, solve_consolidations};
+use crate::controller::provider_health::drop_unhealthy_offerings;
+use crate::controller::shard::Shard;
+use crate::offering::{
+    CONSOLIDATION_LABEL, MANAGED_BY_SELECTOR, NodeReference, Offering, PodResources,
+    QuantityParseError,
+};
+use crate::resources::node_pool::NodePool;
+use crate::resources::node_removal_request::{NodeRemovalRequest, NodeRemovalRequestPhase};
+use crate::resources::node_request::{NodeRequest, NodeRequestSpec};
+
+use super::{ControllerContext, ControllerError};
+
+use decision::{
+    Candidate, ConsolidationKind, ConsolidationPlan, ConsolidationStep, choose_plan,
+    choose_rebalance_plan, consolidation_candidates, consolidation_step, node_cost, pinned,
+    rebalance_candidates, rebalance_recommended,
+};
+use helpers::{cordon_for_consolidation, release_node};
+
+const CONSOLIDATION_SCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Cluster state snapshot for one consolidation scan.
+struct ConsolidationScanState {
+    nodes: Vec<Node>,
+    pods: Vec<Pod>,
+    nrrs: Vec<NodeRemovalRequest>,
+    nrs: Vec<NodeRequest>,
+    pools: Vec<NodePool>,
+    pdbs: PdbBudgets,
+}
+
+impl ConsolidationScanState {
+    async fn collect(client: &kube::Client, shard: &Shard) -> Result<Self, kube::Error> {
+        let lp = ListParams::default();
+        let (mut nodes, pods, nrrs, nrs, mut pools, pdbs) = tokio::try_join!(
+            async {
+                Api::<Node>::all(client.clone())
+                    .list(&ListParams::default().labels(MANAGED_BY_SELECTOR))
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<Pod>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<NodeRemovalRequest>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<NodeRequest>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<NodePool>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            PdbBudgets::list(client),
+        )?;
+        shard.retain_owned_nodes(&mut nodes, &pools);
+        pools.retain(|p| shard.owns(p));
+        Ok(Self {
+            nodes,
+            pods,
+            nrrs,
+            nrs,
+            pools,
+            pdbs,
+        })
+    }
+}
+
+/// Periodically advance consolidations under way and start new ones.
+pub(super) async fn run_consolidation_scanner(
+    ctx: Arc<ControllerContext>,
+) -> Result<(), ControllerError> {
+    loop {
+        tokio::time::sleep(CONSOLIDATION_SCAN_INTERVAL).await;
+        let scan = scan_consolidations(&ctx);
+        if let Err(e) = ctx.metrics.reconciles.measure("consolidation", scan).await {
+            warn!(error = %e, "consolidation scan failed, will retry next interval");
+        }
+    }
+}
+
+async fn scan_consolidations(ctx: &ControllerContext) -> Result<(), ControllerError> {
+    let state = ConsolidationScanState::collect(&ctx.client, &ctx.shard).await?;
+
+    let mut budgets = state.pdbs.clone();
+    for node in &state.nodes {
+        let Some(kind) = ConsolidationKind::of(node) else {
+            continue;
+        };
+        if let Err(e) = advance_consolidation(ctx, &state, &mut budgets, node, kind).await {
+            warn!(node = ?node.metadata.name, error = %e, "consolidation step failed");
+        }
+    }
+
+    // Nodes with a rebalance recommendation are replaced whatever it costs,
+    // and left out of cost consolidation.
+    let mut rebalancing: HashSet<&str> = HashSet::new();
+    if ctx.scale_down.rebalance_replacement {
+        rebalancing = state
+            .nodes
+            .iter()
+            .filter(|n| rebalance_recommended(n))
+            .filter_map(|n| n.metadata.name.as_deref())
+            .collect();
+        let candidates = rebalance_candidates(&state.nodes, &state.pods, &state.nrrs);
+        if !candidates.is_empty() {
+            start_rebalances(ctx, candidates).await?;
+        }
+    }
+
+    // Nodes younger than the scale-down cooling-off are left to settle.
+    let min_age = SignedDuration::try_from(ctx.scale_down.cooling_off_duration)
+        .unwrap_or(SignedDuration::ZERO);
+    let mut candidates = consolidation_candidates(
+        &state.nodes,
+        &state.pods,
+        &state.nrrs,
+        &state.pools,
+        min_age,
+        ctx.clock.now(),
+    );
+    // A node only goes once every one of its pods may be disrupted;
+    // otherwise it would sit cordoned, half drained.
+    candidates.retain(|c| {
+        if rebalancing.contains(c.node.node_name.as_str()) {
+            return false;
+        }
+        let allowed = state.pdbs.allows_all(&c.pods);
+        if !allowed {
+            debug!(node = %c.node.node_name, "disruption budgets hold the node's pods, not consolidating it");
+        }
+        allowed
+    });
+    if candidates.is_empty() {
+        debug!("no consolidation candidates");
+        return Ok(());
+    }
+    start_consolidations(ctx, &state, candidates).await
+}
+
+/// Move a node cordoned for consolidation along: wait for its replacement,
+/// evict its pods, then request its removal.
+async fn advance_consolidation(
+    ctx: &ControllerContext,
+    state: &ConsolidationScanState,
+    budgets: &mut PdbBudgets,
+    node: &Node,
+    kind: ConsolidationKind,
+) -> Result<(), ControllerError> {
+    let held = pinned(&node.metadata);
+    let Some(node) = NodeReference::from_node(node) else {
+        return Ok(());
+    };
+    if state
+        .nrrs
+        .iter()
+        .any(|r| r.spec.node_name == node.node_name)
+    {
+        return Ok(());
+    }
+    if held {
+        info!(node = %node.node_name, "node marked do-not-disrupt or scale-down-disabled, abandoning its consolidation");
+        release_node(&ctx.client, &node.node_name).await?;
+        return Ok(());
+    }
+    let displaced = displaced_pods(&HashSet::from([node.node_name.as_str()]), &state.pods);
+    let progress = replacement_progress(&state.nrs, CONSOLIDATION_LABEL, &node.node_name);
+
+    match consolidation_step(kind, displaced.len(), progress) {
+        ConsolidationStep::Release => {
+            info!(node = %node.node_name, "consolidation replacement gone, uncordoning node");
+            release_node(&ctx.client, &node.node_name).await?;
+        }
+        ConsolidationStep::Wait => {
+            debug!(node = %node.node_name, "waiting for consolidation replacement to become Ready");
+        }
+        ConsolidationStep::Drain if displaced.is_empty() => {
+            info!(node = %node.node_name, "consolidated node empty, requesting removal");
+            match create_node_removal_request(
+                ctx.client.clone(),
+                &node.node_name,
+                Some(&node.node_uid),
+                &node.pool,
+                &node.instance_type,
+                NodeRemovalRequestPhase::Pending,
+                ctx.clock.now(),
+            )
+            .await
+            {
+                Ok(_) => {}
+                Err(kube::Error::Api(ref resp)) if resp.code == 409 => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        ConsolidationStep::Drain => {
+            for pod in &displaced {
+                if pinned(&pod.metadata) {
+                    debug!(
+                        node = %node.node_name,
+                        pod = pod.metadata.name.as_deref().unwrap_or("<unknown>"),
+                        "pod marked do-not-disrupt or not safe to evict, leaving it in place"
+                    );
+                    continue;
+                }
+                if !budgets.take(pod) {
+                    debug!(
+                        node = %node.node_name,
+                        pod = pod.metadata.name.as_deref().unwrap_or("<unknown>"),
+                        pdbs = ?budgets.blocking(pod),
+                        "disruption budget spent, leaving pod for a later scan"
+                    );
+                    continue;
+                }
+                if evict_pod(&ctx.client, pod).await? {
+                    info!(
+                        node = %node.node_name,
+                        pod = pod.metadata.name.as_deref().unwrap_or("<unknown>"),
+                        "evicted pod for consolidation"
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Solve the pods of each node with a rebalance recommendation without it,
+/// and start replacing the first node in each pool whose pods can move, so
+/// they are drained onto Ready capacity before the interruption rather than
+/// after. Every candidate is solved against the same snapshot, so a second
+/// node in the pool could be planned onto room the first one's pods take;
+/// it waits for a later scan.
+async fn start_rebalances(
+    ctx: &ControllerContext,
+    candidates: Vec<Candidate<'_>>,
+) -> Result<(), ControllerError> {
+    let offerings = healthy_offerings(ctx).await;
+    let results = solve_candidates(ctx, &offerings, &candidates).await?;
+    let mut started: HashSet<String> = HashSet::new();
+    for (candidate, result) in candidates.into_iter().zip(results) {
+        let node = candidate.node;
+        if started.contains(&node.pool) {
+            continue;
+        }
+        let Some(plan) = choose_rebalance_plan(result) else {
+            debug!(node = %node.node_name, "no replacement holds the pods of the node to rebalance");
+            continue;
+        };
+        info!(
+            node = %node.node_name,
+            pool = %node.pool,
+            plan = plan.kind().as_str(),
+            pods = candidate.pods.len(),
+            "rebalance recommended, replacing node ahead of interruption"
+        );
+        start_plan(ctx, &node, plan).await?;
+        started.insert(node.pool);
+    }
+    Ok(())
+}
+
+async fn healthy_offerings(ctx: &ControllerContext) -> Vec<Offering> {
+    let mut offerings = ctx.provider.offerings().await;
+    drop_unhealthy_offerings(&mut offerings, &ctx.provider_health.unhealthy_providers());
+    offerings
+}
+
+/// Solve each candidate's pods without its node, in candidate order.
+async fn solve_candidates(
+    ctx: &ControllerContext,
+    offerings: &[Offering],
+    candidates: &[Candidate<'_>],
+) -> Result<Vec<ReconcileResult>, ControllerError> {
+    let demands = candidates
+        .iter()
+        .map(|c| {
+            let pods = c
+                .pods
+                .iter()
+                .copied()
+                .map(PodResources::from_pod)
+                .collect::<Result<Vec<_>, QuantityParseError>>()?;
+            Ok((c.node.node_name.clone(), pods))
+        })
+        .collect::<Result<Vec<_>, QuantityParseError>>()
+        .map_err(|e| ControllerError::ConfigError(e.into()))?;
+    let tunables = ctx.tunables.current();
+    solve_consolidations(
+        &ctx.client,
+        ctx.provider.as_ref(),
+        offerings,
+        ctx.offering_stats.penalties(),
+        tunables.solver,
+        demands,
+        &ctx.shard,
+        &tunables.pool_defaults,
+    )
+    .await
+}
+
+/// Cordon `node` for `plan`, and provision its replacement if it has one.
+async fn start_plan(
+    ctx: &ControllerContext,
+    node: &NodeReference,
+    plan: ConsolidationPlan,
+) -> Result<(), ControllerError> {
+    cordon_for_consolidation(&ctx.client, &node.node_name, plan.kind()).await?;
+    let ConsolidationPlan::Replace(demand) = plan else {
+        return Ok(());
+    };
+    let labels = BTreeMap::from([(CONSOLIDATION_LABEL.to_string(), node.node_name.clone())]);
+    create_node_request(
+        ctx.client.clone(),
+        &demand.pool,
+        &demand.pool_uid,
+        NodeRequestSpec {
+            target_offering: demand.target_offering.instance_type.clone(),
+            location: demand.target_offering.location.region.clone(),
+            zone: demand.target_offering.location.zone.clone(),
+            resources: demand.target_offering.resources.clone(),
+            node_id: format!("growth-{}", uuid::Uuid::new_v4()),
+            provider: Some(demand.target_offering.provider.clone()),
+            // The pods are still bound to the old node, which claim
+            // GC would read as scheduled elsewhere.
+            claimed_pods: vec![],
+            labels: Some(demand.labels),
+            taints: Some(demand.taints),
+        },
+        Some(labels),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Solve each candidate's pods without its node and start the first
+/// worthwhile consolidation in each pool.
+async fn start_consolidations(
+    ctx: &ControllerContext,
+    state: &ConsolidationScanState,
+    candidates: Vec<Candidate<'_>>,
+) -> Result<(), ControllerError> {
+    let offerings = healthy_offerings(ctx).await;
+
+    let priced: Vec<(Candidate<'_>, f64)> = candidates
+        .into_iter()
+        .filter_map(|c| {
+            let node = state
+                .nodes
+                .iter()
+                .find(|n| n.metadata.name.as_deref() == Some(c.node.node_name.as_str()))?;
+            let cost = node_cost(node, &offerings);
+            if cost.is_none() {
+                debug!(node = %c.node.node_name, "no offering prices the node, not consolidating it");
+            }
+            Some((c, cost?))
+        })
+        .collect();
+    let (priced, costs): (Vec<Candidate<'_>>, Vec<f64>) = priced.into_iter().unzip();
+    let results = solve_candidates(ctx, &offerings, &priced).await?;
+
+    let mut started: HashSet<String> = HashSet::new();
+    for ((candidate, cost), result) in priced.into_iter().zip(costs).zip(results) {
+        let node = candidate.node;
+        if started.contains(&node.pool) {
+            continue;
+        }
+        let min_savings = state
+            .pools
+            .iter()
+            .find(|p| p.metadata.name.as_deref() == Some(node.pool.as_str()))
+            .and_then(|p| p.spec.consolidation.as_ref())
+            .and_then(|c| c.min_savings)
+            .unwrap_or(0.0);
+        let Some(plan) = choose_plan(result, cost, min_savings) else {
+            continue;
+        };
+        info!(
+            node = %node.node_name,
+            pool = %node.pool,
+            plan = plan.kind().as_str(),
+            pods = candidate.pods.len(),
+            "consolidating node"
+        );
+        if let ConsolidationPlan::Replace(demand) = &plan {
+            info!(
+                node = %node.node_name,
+                replacement = %demand.target_offering.instance_type.0,
+                cost_per_hour = demand.target_offering.cost_per_hour,
+                was = cost,
+                "provisioning cheaper replacement"
+            );
+        }
+        start_plan(ctx, &node, plan).await?;
+        started.insert(node.pool);
+    }
+    Ok(())
+}
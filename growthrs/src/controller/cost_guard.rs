@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use k8s_openapi::api::core::v1::ObjectReference;
+use kube::Resource;
+use kube::runtime::events::{Event, EventType, Recorder};
+use tracing::warn;
+
+use crate::controller::status::FIELD_MANAGER;
+use crate::resources::node_pool::NodePool;
+
+/// Weight of the newest plan in the rolling baseline.
+const BASELINE_WEIGHT: f64 = 0.2;
+
+/// Reason of the Event recorded on a NodePool whose nodes a held plan left
+/// out.
+pub const COST_HOLD_REASON: &str = "CostAnomalyHeld";
+
+/// A plan whose hourly cost exceeded the guardrail.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostAnomaly {
+    /// Incremental $/h the plan would add.
+    pub cost: f64,
+    /// Largest increase allowed without an override.
+    pub limit: f64,
+}
+
+/// Guards against a single reconcile planning a surprise spend increase.
+///
+/// Each plan's incremental hourly cost is compared to a rolling average of
+/// previously accepted plans. A plan costing more than `factor` times that
+/// baseline (or `min_baseline`, whichever is larger) is held back unless the
+/// guard is overridden. Held plans don't move the baseline.
+pub struct CostGuard {
+    factor: f64,
+    min_baseline: f64,
+    override_all: bool,
+    baseline: Mutex<Option<f64>>,
+    /// Where holds are recorded as Events; `None` only logs them.
+    recorder: Option<Recorder>,
+}
+
+impl CostGuard {
+    /// `factor` of 0 disables the guard; `override_all` admits every plan
+    /// while still tracking the baseline.
+    pub fn new(factor: f64, min_baseline: f64, override_all: bool) -> Self {
+        Self {
+            factor,
+            min_baseline,
+            override_all,
+            baseline: Mutex::new(None),
+            recorder: None,
+        }
+    }
+
+    /// Record holds as Events on the NodePools they leave waiting.
+    pub fn with_events(self, client: kube::Client) -> Self {
+        Self {
+            recorder: Some(Recorder::new(client, FIELD_MANAGER.into())),
+            ..self
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(0.0, 0.0, false)
+    }
+
+    /// Current rolling baseline in $/h, if any plan has been accepted.
+    pub fn baseline(&self) -> Option<f64> {
+        *self.baseline.lock().unwrap()
+    }
+
+    /// Check a plan adding `cost` $/h. Accepted non-empty plans feed the baseline.
+    pub fn admit(&self, cost: f64) -> Result<(), CostAnomaly> {
+        if cost <= 0.0 {
+            return Ok(());
+        }
+        let mut baseline = self.baseline.lock().unwrap();
+        if self.factor > 0.0 && !self.override_all {
+            let limit = self.factor * baseline.unwrap_or(0.0).max(self.min_baseline);
+            if cost > limit {
+                return Err(CostAnomaly { cost, limit });
+            }
+        }
+        *baseline = Some(match *baseline {
+            Some(b) => b + BASELINE_WEIGHT * (cost - b),
+            None => cost,
+        });
+        Ok(())
+    }
+
+    /// Record `anomaly` as a Warning Event on each pool, by name and uid,
+    /// whose new nodes it holds. Repeats of the same hold are folded into
+    /// one Event series.
+    pub async fn record_hold<'a>(
+        &self,
+        anomaly: &CostAnomaly,
+        pools: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) {
+        let Some(recorder) = &self.recorder else {
+            return;
+        };
+        let event = Event {
+            type_: EventType::Warning,
+            reason: COST_HOLD_REASON.into(),
+            note: Some(format!(
+                "plan adding ${:.4}/h is over the ${:.4}/h cost anomaly limit; \
+                 its nodes are held until the guard is overridden",
+                anomaly.cost, anomaly.limit
+            )),
+            action: "HoldNodeRequests".into(),
+            secondary: None,
+        };
+        for (name, uid) in pools {
+            let pool = ObjectReference {
+                api_version: Some(NodePool::api_version(&()).into_owned()),
+                kind: Some(NodePool::kind(&()).into_owned()),
+                name: Some(name.to_string()),
+                uid: Some(uid.to_string()),
+                ..Default::default()
+            };
+            if let Err(e) = recorder.publish(&event, &pool).await {
+                warn!(pool = name, error = %e, "failed to record cost anomaly hold");
+            }
+        }
+    }
+}
+
+/// A NodeRequest held back by the cluster-wide hourly budget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BudgetExceeded {
+    /// $/h of the managed fleet, including nodes still provisioning and
+    /// requests already admitted.
+    pub fleet: f64,
+    /// $/h the held node would add.
+    pub cost: f64,
+    /// The cap in $/h.
+    pub budget: f64,
+}
+
+/// Leaves out the fleet's cost, which moves with every node that comes and
+/// goes: the message is the held request's `Accepted` condition, rewritten
+/// whenever it changes.
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "this node would add ${:.4}/h and take the fleet over its ${:.4}/h budget",
+            self.cost, self.budget
+        )
+    }
+}
+
+/// Check a node adding `cost` $/h to a fleet costing `fleet` $/h against the
+/// cluster-wide `budget`, if there is one. A node that would take the fleet
+/// over the budget is held.
+pub fn check_budget(fleet: f64, cost: f64, budget: Option<f64>) -> Result<(), BudgetExceeded> {
+    match budget {
+        Some(budget) if fleet + cost > budget => Err(BudgetExceeded {
+            fleet,
+            cost,
+            budget,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// NodeRequests let through the hourly budget that are still Pending, with
+/// the $/h each adds. The fleet's cost only counts a request once it is
+/// Provisioning, so without these two requests admitted back to back would
+/// each be checked against a fleet missing the other. Kept behind an async
+/// mutex held across the listing and the check, which serialises admission.
+#[derive(Debug, Default)]
+pub struct BudgetAdmissions(HashMap<String, f64>);
+
+impl BudgetAdmissions {
+    /// Check the NodeRequest `name`, adding `cost` $/h, against `budget`,
+    /// counting the fleet's `fleet` $/h and every other admitted request
+    /// still among the `pending` ones, and record it if it fits. Requests no
+    /// longer Pending are forgotten: they are in the fleet's cost, or gone.
+    pub fn admit(
+        &mut self,
+        name: &str,
+        cost: f64,
+        fleet: f64,
+        pending: &HashSet<&str>,
+        budget: Option<f64>,
+    ) -> Result<(), BudgetExceeded> {
+        self.0.retain(|n, _| pending.contains(n.as_str()));
+        let admitted: f64 = self
+            .0
+            .iter()
+            .filter(|(n, _)| n.as_str() != name)
+            .map(|(_, c)| c)
+            .sum();
+        check_budget(fleet + admitted, cost, budget)?;
+        self.0.insert(name.to_string(), cost);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_guard_admits_everything() {
+        let guard = CostGuard::disabled();
+        assert!(guard.admit(1_000.0).is_ok());
+        assert_eq!(guard.baseline(), Some(1_000.0));
+    }
+
+    #[test]
+    fn spike_over_factor_is_held_and_leaves_baseline() {
+        let guard = CostGuard::new(3.0, 0.5, false);
+        assert!(guard.admit(1.0).is_ok());
+        assert_eq!(
+            guard.admit(4.0),
+            Err(CostAnomaly {
+                cost: 4.0,
+                limit: 3.0
+            })
+        );
+        assert_eq!(guard.baseline(), Some(1.0));
+        assert!(guard.admit(2.0).is_ok());
+        assert_eq!(guard.baseline(), Some(1.2));
+    }
+
+    #[test]
+    fn min_baseline_bounds_the_first_plan() {
+        let guard = CostGuard::new(2.0, 1.0, false);
+        assert!(guard.admit(2.5).is_err());
+        assert!(guard.admit(2.0).is_ok());
+    }
+
+    #[test]
+    fn override_admits_spikes() {
+        let guard = CostGuard::new(2.0, 1.0, true);
+        assert!(guard.admit(50.0).is_ok());
+        assert_eq!(guard.baseline(), Some(50.0));
+    }
+
+    #[test]
+    fn budget_holds_nodes_that_would_exceed_it() {
+        assert!(check_budget(100.0, 10.0, None).is_ok());
+        assert!(check_budget(8.0, 2.0, Some(10.0)).is_ok());
+        let held = check_budget(8.5, 2.0, Some(10.0)).unwrap_err();
+        assert_eq!(
+            held.to_string(),
+            "this node would add $2.0000/h and take the fleet over its $10.0000/h budget"
+        );
+    }
+
+    #[test]
+    fn admitted_pending_requests_count_against_the_budget() {
+        let mut admissions = BudgetAdmissions::default();
+        let mut admit = |name: &str, fleet: f64, pending: &[&str]| {
+            let pending = pending.iter().copied().collect();
+            admissions.admit(name, 4.0, fleet, &pending, Some(10.0))
+        };
+        assert!(admit("a", 2.0, &["a", "b", "c"]).is_ok());
+        // Re-checking a request doesn't count it against itself.
+        assert!(admit("a", 2.0, &["a", "b", "c"]).is_ok());
+        assert!(admit("b", 2.0, &["a", "b", "c"]).is_ok());
+        let held = admit("c", 2.0, &["a", "b", "c"]).unwrap_err();
+        assert_eq!(held.fleet, 10.0);
+        // Once a is Provisioning it is in the fleet's cost instead.
+        assert!(admit("c", 6.0, &["b", "c"]).is_err());
+        assert!(admit("c", 2.0, &["b", "c"]).is_ok());
+    }
+}
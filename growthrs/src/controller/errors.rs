@@ -1,4 +1,5 @@
 use std::fmt;
+use std::time::Duration;
 
 use kube::runtime::controller::RunnerError;
 
@@ -30,6 +31,8 @@ pub enum ConfigError {
     #[error("{0}")]
     ServerTypeUnavailableError(String),
     #[error("{0}")]
+    InventoryDriftError(String),
+    #[error("{0}")]
     Other(String),
 }
 
@@ -44,6 +47,10 @@ pub enum ControllerError {
     MissingName(&'static str),
     #[error(transparent)]
     Other(#[from] anyhow::Error),
+    #[error(
+        "CRDs not established after {waited:?}: {missing}; install them with `growthrs crd --apply` or set GROWTH_INSTALL_CRDS=true"
+    )]
+    MissingCrds { missing: String, waited: Duration },
     #[error("fault injection triggered after {0} NodeRequest creates")]
     FaultInjected(usize),
     #[error(transparent)]
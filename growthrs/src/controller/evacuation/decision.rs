@@ -0,0 +1,454 @@
+use std::collections::HashSet;
+
+use k8s_openapi::api::core::v1::{Node, Pod};
+
+use crate::controller::pdb::do_not_disrupt;
+use crate::controller::pods::is_daemonset_pod;
+use crate::controller::shard::fnv1a;
+use crate::offering::{
+    EVACUATING_ANNOTATION, NODE_REQUEST_LABEL, NodeReference, ZONE_LABEL, pod_pool_selector,
+};
+use crate::resources::node_pool::{Evacuation, NodePool};
+use crate::resources::node_removal_request::NodeRemovalRequest;
+use crate::resources::node_request::{NodeRequest, NodeRequestPhase};
+
+/// Label value tying replacement NodeRequests to one evacuation of a pool.
+///
+/// Includes a hash of the evacuated zones, so that evacuating other zones of
+/// the same pool doesn't mistake old, Ready replacements for its own, while
+/// unrelated edits to the pool keep the evacuation's replacements.
+pub fn evacuation_id(pool: &NodePool) -> Option<String> {
+    let name = pool.metadata.name.as_deref()?;
+    let evacuation = pool.spec.evacuation.as_ref()?;
+    let mut zones: Vec<&str> = evacuation.zones.iter().map(String::as_str).collect();
+    zones.sort_unstable();
+    zones.dedup();
+    Some(format!("{name}.{:016x}", fnv1a(&zones.join(","))))
+}
+
+/// Growth-managed nodes in `pool` covered by `evacuation`, not already
+/// being removed and not marked do-not-disrupt.
+///
+/// Nodes of the NodeRequests named in `replacements`, this evacuation's own
+/// replacements, are never covered: evacuating a whole pool places them in
+/// the same pool, and cordoning them would leave the pods nowhere to go.
+pub fn evacuating_nodes(
+    nodes: &[Node],
+    pool: &str,
+    evacuation: &Evacuation,
+    nrrs: &[NodeRemovalRequest],
+    replacements: &HashSet<&str>,
+) -> Vec<NodeReference> {
+    let tracked: HashSet<&str> = nrrs.iter().map(|n| n.spec.node_name.as_str()).collect();
+    nodes
+        .iter()
+        .filter(|node| {
+            let labels = node.metadata.labels.as_ref();
+            let zone = labels.and_then(|l| l.get(ZONE_LABEL)).map(String::as_str);
+            let replacement = labels
+                .and_then(|l| l.get(NODE_REQUEST_LABEL))
+                .is_some_and(|nr| replacements.contains(nr.as_str()));
+            evacuation.covers(zone) && !replacement && !do_not_disrupt(&node.metadata)
+        })
+        .filter_map(NodeReference::from_node)
+        .filter(|n| n.pool == pool && !tracked.contains(n.node_name.as_str()))
+        .collect()
+}
+
+/// Workload pods running on the given nodes: everything `is_node_idle` would
+/// count, excluding pods already terminating.
+pub fn displaced_pods<'a>(node_names: &HashSet<&str>, pods: &'a [Pod]) -> Vec<&'a Pod> {
+    pods.iter()
+        .filter(|pod| {
+            pod.spec
+                .as_ref()
+                .and_then(|s| s.node_name.as_deref())
+                .is_some_and(|n| node_names.contains(n))
+                && !is_daemonset_pod(pod)
+                && pod_pool_selector(pod).is_some()
+                && pod.metadata.deletion_timestamp.is_none()
+        })
+        .collect()
+}
+
+/// State of the replacement NodeRequests for one evacuation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplacementProgress {
+    /// No replacements requested yet.
+    NotStarted,
+    /// At least one replacement hasn't joined yet.
+    InFlight,
+    /// Every replacement is Ready.
+    Ready,
+    /// At least one replacement came back Unmet; its pods still need a node.
+    Failed,
+}
+
+/// The replacement NodeRequests labelled `label=value`, e.g.
+/// [`EVACUATION_LABEL`](crate::offering::EVACUATION_LABEL) and an
+/// [`evacuation_id`].
+pub fn replacements<'a>(
+    nrs: &'a [NodeRequest],
+    label: &'a str,
+    value: &'a str,
+) -> impl Iterator<Item = &'a NodeRequest> {
+    nrs.iter().filter(move |nr| {
+        nr.metadata
+            .labels
+            .as_ref()
+            .and_then(|l| l.get(label))
+            .is_some_and(|v| v == value)
+    })
+}
+
+/// Progress of the [`replacements`] labelled `label=value`.
+pub fn replacement_progress(nrs: &[NodeRequest], label: &str, value: &str) -> ReplacementProgress {
+    let mut replacements = replacements(nrs, label, value).peekable();
+    if replacements.peek().is_none() {
+        return ReplacementProgress::NotStarted;
+    }
+    let phases: Vec<NodeRequestPhase> = replacements.map(|nr| nr.phase()).collect();
+    if phases.contains(&NodeRequestPhase::Unmet) {
+        ReplacementProgress::Failed
+    } else if phases.iter().all(|p| *p == NodeRequestPhase::Ready) {
+        ReplacementProgress::Ready
+    } else {
+        ReplacementProgress::InFlight
+    }
+}
+
+/// Nodes cordoned by an evacuation that no longer covers them — the
+/// evacuation was removed or narrowed to other zones.
+pub fn nodes_to_release(nodes: &[Node], pools: &[NodePool]) -> Vec<String> {
+    nodes
+        .iter()
+        .filter_map(|node| {
+            let annotations = node.metadata.annotations.as_ref()?;
+            let pool_name = annotations.get(EVACUATING_ANNOTATION)?;
+            let zone = node
+                .metadata
+                .labels
+                .as_ref()
+                .and_then(|l| l.get(ZONE_LABEL))
+                .map(String::as_str);
+            let still_covered = pools
+                .iter()
+                .find(|p| p.metadata.name.as_ref() == Some(pool_name))
+                .and_then(|p| p.spec.evacuation.as_ref())
+                .is_some_and(|e| e.covers(zone));
+            (!still_covered).then(|| node.metadata.name.clone())?
+        })
+        .collect()
+}
+
+/// Next action for an evacuating pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvacuationStep {
+    /// Request replacement capacity for the displaced pods.
+    Provision,
+    /// Replacements are on their way; leave the pods where they are.
+    Wait,
+    /// Evict displaced pods, and remove nodes that are already empty.
+    Drain,
+}
+
+/// Replacements come before draining: pods are only evicted once every
+/// replacement node is Ready, or if there's nothing left to replace. Unmet
+/// replacements are provisioned again.
+pub fn decide_step(displaced: usize, progress: ReplacementProgress) -> EvacuationStep {
+    match (displaced, progress) {
+        (0, _) => EvacuationStep::Drain,
+        (_, ReplacementProgress::NotStarted | ReplacementProgress::Failed) => {
+            EvacuationStep::Provision
+        }
+        (_, ReplacementProgress::InFlight) => EvacuationStep::Wait,
+        (_, ReplacementProgress::Ready) => EvacuationStep::Drain,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::{NodeSpec, PodSpec};
+    use kube::api::ObjectMeta;
+
+    use crate::offering::{
+        EVACUATION_LABEL, INSTANCE_TYPE_LABEL, InstanceType, MANAGED_BY_LABEL, MANAGED_BY_VALUE,
+        POOL_LABEL, Region, Resources,
+    };
+    use crate::resources::node_pool::NodePoolSpec;
+    use crate::resources::node_removal_request::NodeRemovalRequestSpec;
+    use crate::resources::node_request::{NodeRequestSpec, NodeRequestStatus};
+
+    fn node(name: &str, pool: &str, zone: Option<&str>) -> Node {
+        let mut labels = BTreeMap::from([
+            (MANAGED_BY_LABEL.to_string(), MANAGED_BY_VALUE.to_string()),
+            (POOL_LABEL.to_string(), pool.to_string()),
+            (INSTANCE_TYPE_LABEL.to_string(), "cpx22".to_string()),
+        ]);
+        if let Some(z) = zone {
+            labels.insert(ZONE_LABEL.to_string(), z.to_string());
+        }
+        Node {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                uid: Some(format!("uid-{name}")),
+                labels: Some(labels),
+                ..Default::default()
+            },
+            spec: Some(NodeSpec::default()),
+            status: None,
+        }
+    }
+
+    fn pod(name: &str, node: &str, pool: Option<&str>) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                namespace: Some("default".into()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                node_name: Some(node.into()),
+                node_selector: pool.map(|p| BTreeMap::from([(POOL_LABEL.into(), p.into())])),
+                containers: vec![],
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn replacement(name: &str, id: &str, phase: NodeRequestPhase) -> NodeRequest {
+        let mut nr = NodeRequest::new(
+            name,
+            NodeRequestSpec {
+                target_offering: InstanceType("cpx22".into()),
+                location: Region("eu-central".into()),
+                zone: None,
+                resources: Resources {
+                    cpu_millis: 2000,
+                    memory_mib: 4096,
+                    ephemeral_storage_gib: None,
+                    gpu: 0,
+                    gpu_model: None,
+                    arch: None,
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
+                },
+                node_id: format!("growth-{name}"),
+                provider: None,
+                claimed_pods: vec![],
+                labels: None,
+                taints: None,
+            },
+        );
+        nr.metadata.labels = Some(BTreeMap::from([(EVACUATION_LABEL.into(), id.into())]));
+        nr.status = Some(NodeRequestStatus {
+            phase,
+            ..Default::default()
+        });
+        nr
+    }
+
+    fn pool(evacuation: Option<Evacuation>) -> NodePool {
+        NodePool::new(
+            "web",
+            NodePoolSpec {
+                server_types: vec![],
+                min_nodes: 0,
+                max_nodes: None,
+                headroom: None,
+                labels: BTreeMap::new(),
+                taints: vec![],
+                kubelet_args: vec![],
+                join_strategy: None,
+                locations: None,
+                node_class_ref: None,
+                evacuation,
+                consolidation: None,
+                disruption_budget: None,
+                fragmentation_penalty: None,
+                target_utilization: None,
+                interruption_penalty: None,
+                cost_objective: None,
+                instance_families: None,
+                max_additional_cost_per_hour: None,
+                max_new_nodes: None,
+                include_instance_types: None,
+                exclude_instance_types: None,
+            },
+        )
+    }
+
+    fn zones(z: &[&str]) -> Evacuation {
+        Evacuation {
+            zones: z.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn evacuating_nodes_match_pool_and_zone() {
+        let nodes = vec![
+            node("a", "web", Some("fsn1-dc14")),
+            node("b", "web", Some("nbg1-dc3")),
+            node("c", "batch", Some("fsn1-dc14")),
+            node("d", "web", None),
+        ];
+        let none = HashSet::new();
+        let names: Vec<_> = evacuating_nodes(&nodes, "web", &zones(&["fsn1-dc14"]), &[], &none)
+            .into_iter()
+            .map(|n| n.node_name)
+            .collect();
+        assert_eq!(names, ["a"]);
+
+        let whole_pool = evacuating_nodes(&nodes, "web", &zones(&[]), &[], &none);
+        assert_eq!(whole_pool.len(), 3);
+    }
+
+    #[test]
+    fn whole_pool_evacuation_spares_its_own_replacements() {
+        let mut new_node = node("new", "web", Some("z1"));
+        new_node
+            .metadata
+            .labels
+            .as_mut()
+            .unwrap()
+            .insert(NODE_REQUEST_LABEL.into(), "r1".into());
+        let mut earlier = node("earlier", "web", Some("z1"));
+        earlier
+            .metadata
+            .labels
+            .as_mut()
+            .unwrap()
+            .insert(NODE_REQUEST_LABEL.into(), "old".into());
+        let nodes = vec![node("a", "web", Some("z1")), new_node, earlier];
+        let nrs = vec![
+            replacement("r1", "web.2", NodeRequestPhase::Ready),
+            replacement("old", "web.1", NodeRequestPhase::Ready),
+        ];
+        let ours: HashSet<&str> = replacements(&nrs, EVACUATION_LABEL, "web.2")
+            .filter_map(|nr| nr.metadata.name.as_deref())
+            .collect();
+
+        // A previous evacuation's replacement is evacuated like any other node.
+        let names: Vec<_> = evacuating_nodes(&nodes, "web", &zones(&[]), &[], &ours)
+            .into_iter()
+            .map(|n| n.node_name)
+            .collect();
+        assert_eq!(names, ["a", "earlier"]);
+    }
+
+    #[test]
+    fn nodes_already_being_removed_are_skipped() {
+        let nodes = vec![node("a", "web", Some("z1"))];
+        let nrr = NodeRemovalRequest::new(
+            "nrr-a",
+            NodeRemovalRequestSpec {
+                node_name: "a".into(),
+                pool: "web".into(),
+                instance_type: InstanceType("cpx22".into()),
+            },
+        );
+        assert!(
+            evacuating_nodes(&nodes, "web", &zones(&["z1"]), &[nrr], &HashSet::new()).is_empty()
+        );
+    }
+
+    #[test]
+    fn displaced_pods_exclude_unpooled_and_other_nodes() {
+        let pods = vec![
+            pod("w1", "a", Some("web")),
+            pod("sys", "a", None),
+            pod("w2", "b", Some("web")),
+        ];
+        let on = HashSet::from(["a"]);
+        let displaced = displaced_pods(&on, &pods);
+        assert_eq!(displaced.len(), 1);
+        assert_eq!(displaced[0].metadata.name.as_deref(), Some("w1"));
+    }
+
+    #[test]
+    fn progress_tracks_only_this_evacuation() {
+        let nrs = vec![
+            replacement("r1", "web.2", NodeRequestPhase::Ready),
+            replacement("old", "web.1", NodeRequestPhase::Pending),
+        ];
+        assert_eq!(
+            replacement_progress(&nrs, EVACUATION_LABEL, "web.2"),
+            ReplacementProgress::Ready
+        );
+        assert_eq!(
+            replacement_progress(&nrs, EVACUATION_LABEL, "web.3"),
+            ReplacementProgress::NotStarted
+        );
+
+        let nrs = vec![
+            replacement("r1", "web.2", NodeRequestPhase::Ready),
+            replacement("r2", "web.2", NodeRequestPhase::Provisioning),
+        ];
+        assert_eq!(
+            replacement_progress(&nrs, EVACUATION_LABEL, "web.2"),
+            ReplacementProgress::InFlight
+        );
+    }
+
+    #[test]
+    fn evacuation_id_follows_the_evacuated_zones() {
+        let mut np = pool(Some(zones(&["z1", "z2"])));
+        let id = evacuation_id(&np).unwrap();
+        assert!(id.starts_with("web."));
+
+        np.metadata.generation = Some(7);
+        np.spec.max_nodes = Some(10);
+        assert_eq!(evacuation_id(&np).unwrap(), id);
+        np.spec.evacuation = Some(zones(&["z2", "z1"]));
+        assert_eq!(evacuation_id(&np).unwrap(), id);
+
+        np.spec.evacuation = Some(zones(&["z1"]));
+        assert_ne!(evacuation_id(&np).unwrap(), id);
+        np.spec.evacuation = None;
+        assert_eq!(evacuation_id(&np), None);
+    }
+
+    #[test]
+    fn unmet_replacements_are_provisioned_again() {
+        let nrs = vec![
+            replacement("r1", "web.2", NodeRequestPhase::Ready),
+            replacement("r2", "web.2", NodeRequestPhase::Unmet),
+            replacement("r3", "web.2", NodeRequestPhase::Provisioning),
+        ];
+        let progress = replacement_progress(&nrs, EVACUATION_LABEL, "web.2");
+        assert_eq!(progress, ReplacementProgress::Failed);
+        assert_eq!(decide_step(3, progress), EvacuationStep::Provision);
+    }
+
+    #[test]
+    fn drain_waits_for_replacements() {
+        use EvacuationStep::*;
+        use ReplacementProgress::*;
+        assert_eq!(decide_step(3, NotStarted), Provision);
+        assert_eq!(decide_step(3, InFlight), Wait);
+        assert_eq!(decide_step(3, Ready), Drain);
+        assert_eq!(decide_step(0, NotStarted), Drain);
+    }
+
+    #[test]
+    fn cordons_released_when_evacuation_ends() {
+        let mut cordoned = node("a", "web", Some("z1"));
+        cordoned.metadata.annotations = Some(BTreeMap::from([(
+            EVACUATING_ANNOTATION.into(),
+            "web".into(),
+        )]));
+        let untouched = node("b", "web", Some("z1"));
+        let nodes = vec![cordoned, untouched];
+
+        let mut pool = pool(Some(zones(&["z1"])));
+        assert!(nodes_to_release(&nodes, std::slice::from_ref(&pool)).is_empty());
+
+        pool.spec.evacuation = Some(zones(&["z2"]));
+        assert_eq!(nodes_to_release(&nodes, &[pool]), ["a"]);
+    }
+}
@@ -0,0 +1,44 @@
+use k8s_openapi::api::core::v1::{Node, Pod};
+use kube::Client;
+use kube::api::{Api, EvictParams, Patch, PatchParams};
+
+use crate::offering::EVACUATING_ANNOTATION;
+
+/// Mark a node unschedulable and record which pool's evacuation did it.
+pub async fn cordon_node(client: &Client, node_name: &str, pool: &str) -> Result<(), kube::Error> {
+    let api: Api<Node> = Api::all(client.clone());
+    let patch = serde_json::json!({
+        "metadata": { "annotations": { EVACUATING_ANNOTATION: pool } },
+        "spec": { "unschedulable": true }
+    });
+    api.patch(node_name, &PatchParams::default(), &Patch::Merge(patch))
+        .await?;
+    Ok(())
+}
+
+/// Undo `cordon_node` once an evacuation no longer covers the node.
+pub async fn uncordon_node(client: &Client, node_name: &str) -> Result<(), kube::Error> {
+    let api: Api<Node> = Api::all(client.clone());
+    let patch = serde_json::json!({
+        "metadata": { "annotations": { EVACUATING_ANNOTATION: null } },
+        "spec": { "unschedulable": null }
+    });
+    api.patch(node_name, &PatchParams::default(), &Patch::Merge(patch))
+        .await?;
+    Ok(())
+}
+
+/// Evict a pod through the Eviction API, so PodDisruptionBudgets are honoured.
+/// A 429 (budget exhausted) or 404 (already gone) is not an error.
+pub async fn evict_pod(client: &Client, pod: &Pod) -> Result<bool, kube::Error> {
+    let Some(name) = pod.metadata.name.as_deref() else {
+        return Ok(false);
+    };
+    let namespace = pod.metadata.namespace.as_deref().unwrap_or("default");
+    let api: Api<Pod> = Api::namespaced(client.clone(), namespace);
+    match api.evict(name, &EvictParams::default()).await {
+        Ok(_) => Ok(true),
+        Err(kube::Error::Api(ref resp)) if resp.code == 429 || resp.code == 404 => Ok(false),
+        Err(e) => Err(e),
+    }
+}
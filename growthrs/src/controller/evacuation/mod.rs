@@ -0,0 +1,311 @@
+//! Operator-triggered evacuation of a NodePool, or some of its zones.
+//!
+//! Setting `spec.evacuation` on a NodePool cordons the covered nodes,
+//! provisions replacement capacity for their workload outside the evacuated
+//! zones, and only once every replacement is Ready evicts the pods and hands
+//! the emptied nodes to the NodeRemovalRequest controller.
+pub mod decision;
+pub(crate) mod helpers;
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use k8s_openapi::api::core::v1::{Node, Pod};
+use kube::api::ListParams;
+use kube::{Api, ResourceExt};
+use tracing::{debug, info, warn};
+
+use crate::controller::node_removal::create_node_removal_request;
+use crate::controller::node_requests::helpers::{create_node_request, delete_node_request};
+use crate::controller::pdb::{PdbBudgets, do_not_disrupt};
+use crate::controller::pods::solve_displaced_pods;
+use crate::controller::shard::Shard;
+use crate::offering::{
+    EVACUATING_ANNOTATION, EVACUATION_LABEL, MANAGED_BY_SELECTOR, PodResources, QuantityParseError,
+};
+use crate::resources::node_pool::{Evacuation, NodePool};
+use crate::resources::node_removal_request::{NodeRemovalRequest, NodeRemovalRequestPhase};
+use crate::resources::node_request::{NodeRequest, NodeRequestPhase, NodeRequestSpec};
+
+use super::{ControllerContext, ControllerError};
+
+use decision::{
+    EvacuationStep, decide_step, displaced_pods, evacuating_nodes, evacuation_id, nodes_to_release,
+    replacement_progress, replacements,
+};
+use helpers::{cordon_node, evict_pod, uncordon_node};
+
+/// Cluster state snapshot for one evacuation scan.
+struct EvacuationScanState {
+    nodes: Vec<Node>,
+    pods: Vec<Pod>,
+    nrrs: Vec<NodeRemovalRequest>,
+    nrs: Vec<NodeRequest>,
+    pools: Vec<NodePool>,
+    pdbs: PdbBudgets,
+}
+
+impl EvacuationScanState {
+    async fn collect(client: &kube::Client, shard: &Shard) -> Result<Self, kube::Error> {
+        let lp = ListParams::default();
+        let (mut nodes, pods, nrrs, nrs, mut pools, pdbs) = tokio::try_join!(
+            async {
+                Api::<Node>::all(client.clone())
+                    .list(&ListParams::default().labels(MANAGED_BY_SELECTOR))
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<Pod>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<NodeRemovalRequest>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<NodeRequest>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            async {
+                Api::<NodePool>::all(client.clone())
+                    .list(&lp)
+                    .await
+                    .map(|l| l.items)
+            },
+            PdbBudgets::list(client),
+        )?;
+        shard.retain_owned_nodes(&mut nodes, &pools);
+        pools.retain(|p| shard.owns(p));
+        Ok(Self {
+            nodes,
+            pods,
+            nrrs,
+            nrs,
+            pools,
+            pdbs,
+        })
+    }
+}
+
+/// Periodically advance every NodePool evacuation.
+pub(super) async fn run_evacuation_scanner(
+    ctx: Arc<ControllerContext>,
+) -> Result<(), ControllerError> {
+    loop {
+        tokio::time::sleep(ctx.requeue.interval).await;
+        let scan = scan_evacuations(&ctx);
+        if let Err(e) = ctx.metrics.reconciles.measure("evacuation", scan).await {
+            warn!(error = %e, "evacuation scan failed, will retry next interval");
+        }
+    }
+}
+
+async fn scan_evacuations(ctx: &ControllerContext) -> Result<(), ControllerError> {
+    let state = EvacuationScanState::collect(&ctx.client, &ctx.shard).await?;
+
+    for node_name in nodes_to_release(&state.nodes, &state.pools) {
+        info!(node = %node_name, "evacuation no longer covers node, uncordoning");
+        if let Err(e) = uncordon_node(&ctx.client, &node_name).await {
+            warn!(node = %node_name, error = %e, "failed to uncordon node");
+        }
+    }
+
+    let mut budgets = state.pdbs.clone();
+    for pool in &state.pools {
+        let (Some(name), Some(evacuation)) = (&pool.metadata.name, &pool.spec.evacuation) else {
+            continue;
+        };
+        if let Err(e) = evacuate_pool(ctx, &state, &mut budgets, pool, name, evacuation).await {
+            warn!(pool = %name, error = %e, "evacuation step failed");
+        }
+    }
+    Ok(())
+}
+
+async fn evacuate_pool(
+    ctx: &ControllerContext,
+    state: &EvacuationScanState,
+    budgets: &mut PdbBudgets,
+    pool: &NodePool,
+    pool_name: &str,
+    evacuation: &Evacuation,
+) -> Result<(), ControllerError> {
+    let id = evacuation_id(pool).ok_or(ControllerError::MissingName("NodePool"))?;
+    let own_replacements: HashSet<&str> = replacements(&state.nrs, EVACUATION_LABEL, &id)
+        .filter_map(|nr| nr.metadata.name.as_deref())
+        .collect();
+    let affected = evacuating_nodes(
+        &state.nodes,
+        pool_name,
+        evacuation,
+        &state.nrrs,
+        &own_replacements,
+    );
+    if affected.is_empty() {
+        debug!(pool = %pool_name, "no nodes left to evacuate");
+        return Ok(());
+    }
+
+    for node in &affected {
+        let already_cordoned = state
+            .nodes
+            .iter()
+            .find(|n| n.metadata.name.as_deref() == Some(node.node_name.as_str()))
+            .and_then(|n| n.metadata.annotations.as_ref())
+            .is_some_and(|a| a.contains_key(EVACUATING_ANNOTATION));
+        if !already_cordoned {
+            info!(pool = %pool_name, node = %node.node_name, "cordoning node for evacuation");
+            cordon_node(&ctx.client, &node.node_name, pool_name).await?;
+        }
+    }
+
+    let node_names: HashSet<&str> = affected.iter().map(|n| n.node_name.as_str()).collect();
+    let displaced = displaced_pods(&node_names, &state.pods);
+
+    match decide_step(
+        displaced.len(),
+        replacement_progress(&state.nrs, EVACUATION_LABEL, &id),
+    ) {
+        EvacuationStep::Provision => {
+            let demands = displaced
+                .iter()
+                .copied()
+                .map(PodResources::from_pod)
+                .collect::<Result<Vec<_>, QuantityParseError>>()
+                .map_err(|e| ControllerError::ConfigError(e.into()))?;
+            let tunables = ctx.tunables.current();
+            let (result, held) = solve_displaced_pods(
+                &ctx.client,
+                ctx.provider.as_ref(),
+                ctx.offering_stats.penalties(),
+                &ctx.provider_health.unhealthy_providers(),
+                tunables.solver,
+                demands,
+                &ctx.cost_guard,
+                &ctx.shard,
+                &tunables.pool_defaults,
+            )
+            .await?;
+            ctx.metrics.solver.record(&result.solver_stats);
+            if result.demands.is_empty() {
+                match held {
+                    // Already recorded as an Event on the pool by the guard.
+                    Some(anomaly) => warn!(
+                        pool = %pool_name,
+                        displaced = displaced.len(),
+                        cost_per_hour = anomaly.cost,
+                        limit = anomaly.limit,
+                        "evacuation replacements held by the cost anomaly guard, not draining"
+                    ),
+                    None => warn!(
+                        pool = %pool_name,
+                        displaced = displaced.len(),
+                        "no replacement capacity available outside the evacuated zones, not draining"
+                    ),
+                }
+                return Ok(());
+            }
+            info!(
+                pool = %pool_name,
+                displaced = displaced.len(),
+                replacements = result.demands.len(),
+                "provisioning evacuation replacements"
+            );
+            let labels = BTreeMap::from([(EVACUATION_LABEL.to_string(), id.clone())]);
+            for demand in result.demands {
+                create_node_request(
+                    ctx.client.clone(),
+                    &demand.pool,
+                    &demand.pool_uid,
+                    NodeRequestSpec {
+                        target_offering: demand.target_offering.instance_type.clone(),
+                        location: demand.target_offering.location.region.clone(),
+                        zone: demand.target_offering.location.zone.clone(),
+                        resources: demand.target_offering.resources.clone(),
+                        node_id: format!("growth-{}", uuid::Uuid::new_v4()),
+                        provider: Some(demand.target_offering.provider.clone()),
+                        // Displaced pods are still bound to their old nodes,
+                        // which claim GC would read as scheduled elsewhere.
+                        claimed_pods: vec![],
+                        labels: Some(demand.labels),
+                        taints: Some(demand.taints),
+                    },
+                    Some(labels.clone()),
+                )
+                .await?;
+            }
+            // Only once their pods have new replacements, so a failed solve
+            // leaves them to trigger the next attempt.
+            for nr in replacements(&state.nrs, EVACUATION_LABEL, &id)
+                .filter(|nr| nr.phase() == NodeRequestPhase::Unmet)
+            {
+                info!(pool = %pool_name, node_request = %nr.name_any(), "replaced Unmet evacuation replacement");
+                delete_node_request(ctx.client.clone(), &nr.name_any()).await?;
+            }
+        }
+        EvacuationStep::Wait => {
+            debug!(pool = %pool_name, "waiting for evacuation replacements to become Ready");
+        }
+        EvacuationStep::Drain => {
+            for pod in &displaced {
+                if do_not_disrupt(&pod.metadata) {
+                    debug!(
+                        pool = %pool_name,
+                        pod = pod.metadata.name.as_deref().unwrap_or("<unknown>"),
+                        "pod marked do-not-disrupt, leaving it in place"
+                    );
+                    continue;
+                }
+                if !budgets.take(pod) {
+                    debug!(
+                        pool = %pool_name,
+                        pod = pod.metadata.name.as_deref().unwrap_or("<unknown>"),
+                        pdbs = ?budgets.blocking(pod),
+                        "disruption budget spent, leaving pod for a later scan"
+                    );
+                    continue;
+                }
+                if evict_pod(&ctx.client, pod).await? {
+                    info!(
+                        pool = %pool_name,
+                        pod = pod.metadata.name.as_deref().unwrap_or("<unknown>"),
+                        "evicted pod for evacuation"
+                    );
+                }
+            }
+            let occupied: HashSet<&str> = displaced
+                .iter()
+                .filter_map(|p| p.spec.as_ref()?.node_name.as_deref())
+                .collect();
+            for node in affected
+                .iter()
+                .filter(|n| !occupied.contains(n.node_name.as_str()))
+            {
+                info!(pool = %pool_name, node = %node.node_name, "evacuated node empty, requesting removal");
+                match create_node_removal_request(
+                    ctx.client.clone(),
+                    &node.node_name,
+                    Some(&node.node_uid),
+                    &node.pool,
+                    &node.instance_type,
+                    NodeRemovalRequestPhase::Pending,
+                    ctx.clock.now(),
+                )
+                .await
+                {
+                    Ok(_) => {}
+                    Err(kube::Error::Api(ref resp)) if resp.code == 409 => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+    }
+    Ok(())
+}
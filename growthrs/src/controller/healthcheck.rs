@@ -1,15 +1,33 @@
-use axum::http::StatusCode;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::{StatusCode, header};
 use axum::response::IntoResponse;
 use axum::{Router, routing::get};
 
+use crate::config::ControllerContext;
+
 async fn healthcheck_handler() -> impl IntoResponse {
     (StatusCode::OK, "OK")
 }
 
-pub async fn healthcheck() -> anyhow::Result<()> {
+async fn metrics_handler(State(ctx): State<Arc<ControllerContext>>) -> impl IntoResponse {
+    ctx.metrics
+        .reconciles
+        .set_backing_off(&ctx.requeue.errors.failing());
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+        ctx.metrics.encode(),
+    )
+}
+
+pub async fn healthcheck(ctx: Arc<ControllerContext>) -> anyhow::Result<()> {
     let app = Router::new()
         .route("/healthz", get(healthcheck_handler))
-        .route("/readyz", get(healthcheck_handler));
+        .route("/readyz", get(healthcheck_handler))
+        .route("/metrics", get(metrics_handler))
+        .with_state(ctx);
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await.unwrap();
     axum::serve(listener, app).await?;
     Ok(())
@@ -1,25 +1,29 @@
 use std::collections::HashSet;
 use std::time::Duration;
 
-use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use k8s_openapi::api::core::v1::{ConfigMap, Node, Secret};
 use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
 use kube::api::ListParams;
-use kube::{Api, Client, api::PatchParams};
+use kube::{Api, Client};
 use tokio::time::sleep;
 use tracing::{info, warn};
 
 use crate::controller::errors::{ConfigError, ControllerError};
+use crate::controller::status::{apply_status, apply_status_at};
+use crate::offering::MANAGED_BY_SELECTOR;
 use crate::resources::user_data::UserDataError;
 
-use crate::providers::provider::Provider;
+use crate::providers::provider::{NodeId, Provider};
 use crate::resources::node_pool::NodePool;
 use crate::resources::node_request::{NodeRequest, NodeRequestPhase, NodeRequestStatus};
 
-const CUSTOM_RESOURCE_DEFINITIONS: [&str; 4] = [
+const CUSTOM_RESOURCE_DEFINITIONS: [&str; 5] = [
     "noderequests.growth.vettrdev.com",
     "nodepools.growth.vettrdev.com",
     "noderemovalrequests.growth.vettrdev.com",
     "hetznernodeclasses.growth.vettrdev.com",
+    "providerconfigs.growth.vettrdev.com",
 ];
 
 /// Check whether a `kube::Error` is a 404 Not Found API response.
@@ -27,32 +31,49 @@ pub(crate) fn is_kube_not_found(err: &kube::Error) -> bool {
     matches!(err, kube::Error::Api(resp) if resp.code == 404)
 }
 
-pub(crate) async fn update_node_request_phase(
+/// Write a NodeRequest's status, typically one from
+/// [`NodeRequest::transitioned`].
+pub(crate) async fn update_node_request_status(
     client: &Client,
     name: &str,
-    phase: NodeRequestPhase,
-    now: k8s_openapi::jiff::Timestamp,
+    status: NodeRequestStatus,
 ) -> Result<(), kube::Error> {
+    let api: Api<NodeRequest> = Api::all(client.clone());
+    apply_status(&api, name, &status).await
+}
+
+/// Write only a NodeRequest's conditions, against the version `nr` was read
+/// at. A write in between (a phase transition) wins over this one, and the
+/// next reconcile sees it.
+pub(crate) async fn update_node_request_conditions(
+    client: &Client,
+    nr: &NodeRequest,
+    conditions: Vec<Condition>,
+) -> Result<(), kube::Error> {
+    let (Some(name), Some(resource_version)) = (
+        nr.metadata.name.as_deref(),
+        nr.metadata.resource_version.as_deref(),
+    ) else {
+        return Ok(());
+    };
     let api: Api<NodeRequest> = Api::all(client.clone());
     let status = NodeRequestStatus {
-        phase,
-        last_transition_time: Some(k8s_openapi::apimachinery::pkg::apis::meta::v1::Time(now)),
-        ..Default::default()
+        conditions,
+        ..nr.status.clone().unwrap_or_default()
     };
-    let patch = serde_json::json!({ "status": status });
-    api.patch_status(
-        name,
-        &PatchParams::apply("growthrs"),
-        &kube::api::Patch::Merge(patch),
-    )
-    .await?;
+    apply_status_at(&api, name, resource_version, &status).await?;
     Ok(())
 }
 
-/// Loop until CRDs are installed on Cluster
-pub(super) async fn wait_for_crds(client: Client) -> Result<(), ControllerError> {
+/// Wait until every CRD is installed and established, giving up after
+/// `timeout` with an error naming the missing ones.
+pub(super) async fn wait_for_crds(
+    client: Client,
+    timeout: Duration,
+) -> Result<(), ControllerError> {
     let api: Api<CustomResourceDefinition> = Api::all(client);
     let mut crd_stablised: Vec<bool> = vec![false; CUSTOM_RESOURCE_DEFINITIONS.len()];
+    let deadline = tokio::time::Instant::now() + timeout;
     loop {
         for (crd_idx, crd_name) in CUSTOM_RESOURCE_DEFINITIONS.iter().enumerate() {
             if crd_stablised[crd_idx] {
@@ -89,6 +110,12 @@ pub(super) async fn wait_for_crds(client: Client) -> Result<(), ControllerError>
             })
             .collect::<Vec<_>>()
             .join(", ");
+        if tokio::time::Instant::now() >= deadline {
+            return Err(ControllerError::MissingCrds {
+                missing: missing_crds,
+                waited: timeout,
+            });
+        }
         warn!(missing = %missing_crds, "CRDs not yet established, retrying in 5s");
         sleep(Duration::from_secs(5)).await;
     }
@@ -98,7 +125,7 @@ pub(super) async fn wait_for_crds(client: Client) -> Result<(), ControllerError>
 /// types or locations that don't exist in the provider catalog.
 pub(super) async fn validate_pool_offerings(
     client: &Client,
-    provider: &Provider,
+    provider: &dyn Provider,
 ) -> Result<(), ControllerError> {
     let offerings = provider.offerings().await;
     let known_types: HashSet<&str> = offerings
@@ -160,6 +187,117 @@ pub(super) async fn validate_pool_offerings(
     Ok(())
 }
 
+/// Disagreement between the provider's instances and the cluster's view of them.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct InventoryDrift {
+    /// Provider instances with no matching Node or NodeRequest.
+    pub unknown_to_cluster: Vec<String>,
+    /// Nodes or started NodeRequests the provider has no instance for.
+    pub missing_from_provider: Vec<String>,
+}
+
+impl InventoryDrift {
+    pub(crate) fn total(&self) -> usize {
+        self.unknown_to_cluster.len() + self.missing_from_provider.len()
+    }
+}
+
+/// Compare provider inventory against Growth-managed Nodes and NodeRequests.
+///
+/// Pending and Unmet NodeRequests may or may not have an instance behind them,
+/// so they only count towards what the cluster knows about — never towards
+/// what the provider is expected to hold.
+pub(crate) fn compare_inventory(
+    provider_instances: &[NodeId],
+    nodes: &[Node],
+    node_requests: &[NodeRequest],
+) -> InventoryDrift {
+    let at_provider: HashSet<&str> = provider_instances.iter().map(|id| id.0.as_str()).collect();
+    let node_names: HashSet<&str> = nodes
+        .iter()
+        .filter_map(|n| n.metadata.name.as_deref())
+        .collect();
+
+    let mut known: HashSet<&str> = node_names.clone();
+    let mut expected: HashSet<&str> = node_names;
+    for nr in node_requests {
+        known.insert(nr.spec.node_id.as_str());
+        if matches!(
+            nr.phase(),
+            NodeRequestPhase::Provisioning | NodeRequestPhase::Ready
+        ) {
+            expected.insert(nr.spec.node_id.as_str());
+        }
+    }
+
+    let mut unknown_to_cluster: Vec<String> = at_provider
+        .difference(&known)
+        .map(|s| s.to_string())
+        .collect();
+    let mut missing_from_provider: Vec<String> = expected
+        .difference(&at_provider)
+        .map(|s| s.to_string())
+        .collect();
+    unknown_to_cluster.sort();
+    missing_from_provider.sort();
+
+    InventoryDrift {
+        unknown_to_cluster,
+        missing_from_provider,
+    }
+}
+
+/// Refuse to start if the provider and the cluster disagree on more than
+/// `max_drift` instances.
+///
+/// A large mismatch usually means the credentials point at the wrong
+/// project or cluster, and acting on it would mass-provision or mass-delete.
+pub(super) async fn check_inventory(
+    client: &Client,
+    provider: &dyn Provider,
+    max_drift: usize,
+) -> Result<(), ControllerError> {
+    let provider_instances = provider.list_instances().await.map_err(|e| {
+        ConfigError::InventoryDriftError(format!("failed to list provider instances: {e}"))
+    })?;
+    let nodes: Vec<Node> = Api::<Node>::all(client.clone())
+        .list(&ListParams::default().labels(MANAGED_BY_SELECTOR))
+        .await?
+        .into_iter()
+        .collect();
+    let node_requests: Vec<NodeRequest> = Api::<NodeRequest>::all(client.clone())
+        .list(&ListParams::default())
+        .await?
+        .into_iter()
+        .collect();
+
+    let drift = compare_inventory(&provider_instances, &nodes, &node_requests);
+    info!(
+        provider_instances = provider_instances.len(),
+        nodes = nodes.len(),
+        node_requests = node_requests.len(),
+        drift = drift.total(),
+        "compared provider inventory with cluster"
+    );
+    if drift.total() > max_drift {
+        return Err(ConfigError::InventoryDriftError(format!(
+            "provider and cluster disagree on {} instances (max {max_drift}); \
+             unknown to cluster: [{}], missing from provider: [{}]",
+            drift.total(),
+            drift.unknown_to_cluster.join(", "),
+            drift.missing_from_provider.join(", "),
+        )))?;
+    }
+    if drift.total() > 0 {
+        warn!(
+            unknown_to_cluster = ?drift.unknown_to_cluster,
+            missing_from_provider = ?drift.missing_from_provider,
+            "provider inventory drift within tolerance"
+        );
+    }
+    Ok(())
+}
+
 /// Read a single key from a Kubernetes Secret.
 pub(crate) async fn read_secret_key(
     client: &Client,
@@ -221,3 +359,159 @@ pub(crate) async fn read_configmap_key(
             reason: format!("key {key:?} not found in configmap"),
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use kube::api::ObjectMeta;
+
+    use crate::offering::{InstanceType, Region, Resources};
+    use crate::resources::node_request::NodeRequestSpec;
+
+    use super::*;
+
+    fn node(name: &str) -> Node {
+        Node {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn nr(node_id: &str, phase: NodeRequestPhase) -> NodeRequest {
+        let mut nr = NodeRequest::new(
+            &format!("nr-{node_id}"),
+            NodeRequestSpec {
+                node_id: node_id.into(),
+                target_offering: InstanceType("cpx22".into()),
+                location: Region("eu-central".into()),
+                zone: None,
+                resources: Resources {
+                    cpu_millis: 2000,
+                    memory_mib: 4096,
+                    ephemeral_storage_gib: None,
+                    gpu: 0,
+                    gpu_model: None,
+                    arch: None,
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
+                },
+                provider: None,
+                claimed_pods: vec![],
+                labels: None,
+                taints: None,
+            },
+        );
+        nr.status = Some(NodeRequestStatus {
+            phase,
+            ..Default::default()
+        });
+        nr
+    }
+
+    fn ids(names: &[&str]) -> Vec<NodeId> {
+        names.iter().map(|n| NodeId(n.to_string())).collect()
+    }
+
+    #[test]
+    fn matching_inventory_has_no_drift() {
+        let drift = compare_inventory(
+            &ids(&["a", "b"]),
+            &[node("a")],
+            &[nr("b", NodeRequestPhase::Provisioning)],
+        );
+        assert_eq!(drift, InventoryDrift::default());
+    }
+
+    #[test]
+    fn provider_instances_unknown_to_cluster_are_reported() {
+        let drift = compare_inventory(&ids(&["a", "x", "y"]), &[node("a")], &[]);
+        assert_eq!(drift.unknown_to_cluster, vec!["x", "y"]);
+        assert!(drift.missing_from_provider.is_empty());
+    }
+
+    #[test]
+    fn nodes_and_started_requests_missing_from_provider_are_reported() {
+        let drift = compare_inventory(
+            &[],
+            &[node("a")],
+            &[
+                nr("b", NodeRequestPhase::Ready),
+                nr("c", NodeRequestPhase::Provisioning),
+            ],
+        );
+        assert_eq!(drift.missing_from_provider, vec!["a", "b", "c"]);
+        assert_eq!(drift.total(), 3);
+    }
+
+    #[test]
+    fn pending_and_unmet_requests_are_known_but_not_expected() {
+        let drift = compare_inventory(
+            &ids(&["p"]),
+            &[],
+            &[
+                nr("p", NodeRequestPhase::Pending),
+                nr("u", NodeRequestPhase::Unmet),
+            ],
+        );
+        assert_eq!(drift, InventoryDrift::default());
+    }
+
+    #[tokio::test]
+    async fn missing_crds_fail_startup_once_the_timeout_passes() {
+        use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+            CustomResourceDefinitionCondition, CustomResourceDefinitionStatus,
+        };
+        use kube::CustomResourceExt;
+
+        let (mock_svc, mut handle) = tower_test::mock::pair::<
+            http::Request<kube::client::Body>,
+            http::Response<kube::client::Body>,
+        >();
+        tokio::spawn(async move {
+            while let Some((request, send)) = handle.next_request().await {
+                let response = if request
+                    .uri()
+                    .path()
+                    .ends_with("/nodepools.growth.vettrdev.com")
+                {
+                    let mut crd = NodePool::crd();
+                    crd.status = Some(CustomResourceDefinitionStatus {
+                        conditions: Some(vec![CustomResourceDefinitionCondition {
+                            type_: "Established".into(),
+                            status: "True".into(),
+                            ..Default::default()
+                        }]),
+                        ..Default::default()
+                    });
+                    http::Response::builder()
+                        .body(serde_json::to_vec(&crd).unwrap().into())
+                        .unwrap()
+                } else {
+                    let status = serde_json::json!({
+                        "kind": "Status", "apiVersion": "v1", "status": "Failure",
+                        "reason": "NotFound", "code": 404, "message": "not found"
+                    });
+                    http::Response::builder()
+                        .status(404)
+                        .body(serde_json::to_vec(&status).unwrap().into())
+                        .unwrap()
+                };
+                send.send_response(response);
+            }
+        });
+
+        let err = wait_for_crds(Client::new(mock_svc, "default"), Duration::ZERO)
+            .await
+            .unwrap_err();
+        let ControllerError::MissingCrds { missing, .. } = &err else {
+            panic!("expected MissingCrds, got {err}");
+        };
+        assert!(missing.starts_with("noderequests.growth.vettrdev.com, noderemovalrequests"));
+        assert!(!missing.contains("nodepools"));
+        assert!(err.to_string().contains("GROWTH_INSTALL_CRDS"));
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use k8s_openapi::api::core::v1::Node;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
+use kube::ResourceExt;
+
+use crate::offering::helper::{parse_count, parse_cpu_millis, parse_memory_mib};
+use crate::offering::{
+    INSTANCE_TYPE_LABEL, NodeReference, Offering, REGION_LABEL, Resources, ZONE_LABEL,
+    requirement_matches,
+};
+use crate::resources::karpenter::{
+    self, CAPACITY_TYPE_LABEL, NODEPOOL_LABEL, NodeClaimStatus, WELL_KNOWN_INSTANCE_TYPE_LABEL,
+};
+use crate::resources::node_pool::{
+    Consolidation, LocationConstraint, NodePoolSpec, ServerTypeConfig,
+};
+use crate::resources::node_request::{NodeRequest, NodeRequestConditionType, NodeRequestPhase};
+
+/// A server type's `max` when the Karpenter NodePool sets no CPU or memory
+/// limit to derive it from.
+pub const UNLIMITED_TYPE_MAX: u32 = 1000;
+
+/// The labels a Karpenter requirement is matched against for `offering`:
+/// its node labels, plus the well-known instance-type label and the
+/// capacity type (`spot` for offerings with an interruption risk).
+fn offering_labels(offering: &Offering) -> BTreeMap<String, String> {
+    let mut labels = offering.labels();
+    labels.insert(
+        WELL_KNOWN_INSTANCE_TYPE_LABEL.into(),
+        offering.instance_type.0.clone(),
+    );
+    let capacity = if offering.interruption_risk > 0.0 {
+        "spot"
+    } else {
+        "on-demand"
+    };
+    labels.insert(CAPACITY_TYPE_LABEL.into(), capacity.into());
+    labels
+}
+
+/// Whether `offering` meets every requirement, skipping those on labels no
+/// offering carries (provider-specific keys such as
+/// `karpenter.k8s.aws/instance-family`), which can't be judged here.
+fn meets(
+    requirements: &[k8s_openapi::api::core::v1::NodeSelectorRequirement],
+    offering: &Offering,
+) -> bool {
+    let labels = offering_labels(offering);
+    requirements
+        .iter()
+        .filter(|r| is_known_key(&r.key))
+        .all(|r| requirement_matches(r, &labels))
+}
+
+fn is_known_key(key: &str) -> bool {
+    key.starts_with("kubernetes.io/")
+        || key.starts_with("topology.kubernetes.io/")
+        || key.starts_with("nvidia.com/")
+        || [
+            WELL_KNOWN_INSTANCE_TYPE_LABEL,
+            CAPACITY_TYPE_LABEL,
+            INSTANCE_TYPE_LABEL,
+        ]
+        .contains(&key)
+}
+
+/// Requirement keys of `requirements` that growth-rs ignores.
+pub fn ignored_requirements(
+    requirements: &[k8s_openapi::api::core::v1::NodeSelectorRequirement],
+) -> Vec<&str> {
+    requirements
+        .iter()
+        .map(|r| r.key.as_str())
+        .filter(|k| !is_known_key(k))
+        .collect()
+}
+
+/// The nodes of growth-rs NodePool `pool` and its Pending or Provisioning
+/// NodeRequests, by instance type: what already counts against a Karpenter
+/// NodePool's limits.
+pub fn pool_occupancy(pool: &str, nodes: &[Node], nrs: &[NodeRequest]) -> BTreeMap<String, u32> {
+    let mut occupied: BTreeMap<String, u32> = BTreeMap::new();
+    let node_types = nodes
+        .iter()
+        .filter_map(NodeReference::from_node)
+        .filter(|n| n.pool == pool)
+        .map(|n| n.instance_type);
+    let in_flight = nrs
+        .iter()
+        .filter(|nr| {
+            matches!(
+                nr.phase(),
+                NodeRequestPhase::Pending | NodeRequestPhase::Provisioning
+            ) && nr
+                .owner_references()
+                .iter()
+                .any(|r| r.kind == "NodePool" && r.name == pool)
+        })
+        .map(|nr| nr.spec.target_offering.0.clone());
+    for instance_type in node_types.chain(in_flight) {
+        *occupied.entry(instance_type).or_insert(0) += 1;
+    }
+    occupied
+}
+
+/// The growth-rs NodePool standing in for Karpenter NodePool `name`, whose
+/// nodes and in-flight NodeRequests are `occupied` (see [`pool_occupancy`]).
+///
+/// Server types are the offerings meeting the template's requirements; each
+/// type's `max` is its occupied count plus as many more as fit in what the
+/// pool's CPU and memory limits leave. Since every type is offered that same
+/// room, `maxNodes` only allows as many new nodes as the room holds of the
+/// largest type still fitting, so no mix of types can exceed the limits;
+/// a `nodes` limit caps it further. Zone and region requirements
+/// narrow `locations` to where those offerings are. Nodes carry the template's
+/// labels, taints and [`NODEPOOL_LABEL`], so pods selecting a Karpenter pool
+/// land on its stand-in. `WhenEmptyOrUnderutilized` turns on consolidation,
+/// and the smallest absolute disruption budget becomes `disruptionBudget`;
+/// percentage budgets are not carried over.
+pub fn pool_spec(
+    name: &str,
+    pool: &karpenter::NodePoolSpec,
+    offerings: &[Offering],
+    occupied: &BTreeMap<String, u32>,
+) -> Result<NodePoolSpec, String> {
+    let requirements = &pool.template.spec.requirements;
+    let matching: Vec<&Offering> = offerings
+        .iter()
+        .filter(|o| meets(requirements, o))
+        .collect();
+    if matching.is_empty() {
+        return Err("no offering meets the NodePool's requirements".into());
+    }
+
+    let limit = |key: &str| pool.limits.get(key);
+    let cpu_limit = limit("cpu")
+        .map(parse_cpu_millis)
+        .transpose()
+        .map_err(|e| format!("invalid cpu limit: {e}"))?;
+    let memory_limit = limit("memory")
+        .map(parse_memory_mib)
+        .transpose()
+        .map_err(|e| format!("invalid memory limit: {e}"))?;
+    let nodes_limit = limit("nodes")
+        .map(parse_count)
+        .transpose()
+        .map_err(|e| format!("invalid nodes limit: {e}"))?;
+
+    let size = |instance_type: &str| {
+        offerings
+            .iter()
+            .find(|o| o.instance_type.0 == instance_type)
+            .map(|o| &o.resources)
+    };
+    let used = |each: fn(&Resources) -> u64| -> u64 {
+        occupied
+            .iter()
+            .filter_map(|(t, n)| Some(each(size(t)?) * u64::from(*n)))
+            .sum()
+    };
+    let room_cpu = cpu_limit.map(|l| l.saturating_sub(used(|r| r.cpu_millis)));
+    let room_memory = memory_limit.map(|l| l.saturating_sub(used(|r| r.memory_mib)));
+    // How many more of a type fit in the room left; `None` without limits.
+    let more = |resources: &Resources| -> Option<u64> {
+        let fits = |room: Option<u64>, each: u64| room.map(|r| r / each.max(1));
+        [
+            fits(room_cpu, resources.cpu_millis),
+            fits(room_memory, resources.memory_mib),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    };
+    let to_u32 = |n: u64| n.min(u64::from(u32::MAX)) as u32;
+    let type_max = |offering: &Offering| -> u32 {
+        let present = occupied
+            .get(&offering.instance_type.0)
+            .copied()
+            .unwrap_or(0);
+        more(&offering.resources).map_or(UNLIMITED_TYPE_MAX, |n| to_u32(u64::from(present) + n))
+    };
+    let largest = |each: fn(&Resources) -> u64| {
+        matching
+            .iter()
+            .map(|o| &o.resources)
+            .filter(|r| more(r).is_some_and(|n| n > 0))
+            .map(each)
+            .max()
+    };
+    let new_nodes = [
+        room_cpu.map(|room| largest(|r| r.cpu_millis).map_or(0, |l| room / l.max(1))),
+        room_memory.map(|room| largest(|r| r.memory_mib).map_or(0, |l| room / l.max(1))),
+    ]
+    .into_iter()
+    .flatten()
+    .min();
+    let present: u64 = occupied.values().map(|n| u64::from(*n)).sum();
+    let max_nodes = [nodes_limit, new_nodes.map(|n| to_u32(present + n))]
+        .into_iter()
+        .flatten()
+        .min();
+
+    let mut server_types: Vec<ServerTypeConfig> = Vec::new();
+    for offering in &matching {
+        if server_types
+            .iter()
+            .any(|st| st.name == offering.instance_type.0)
+        {
+            continue;
+        }
+        server_types.push(ServerTypeConfig {
+            name: offering.instance_type.0.clone(),
+            max: type_max(offering),
+            min: 0,
+        });
+    }
+    server_types.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let places = requirements
+        .iter()
+        .any(|r| r.key == ZONE_LABEL || r.key == REGION_LABEL);
+    let locations = places.then(|| {
+        let mut zones: BTreeMap<&str, Option<BTreeSet<String>>> = BTreeMap::new();
+        for offering in &matching {
+            let entry = zones
+                .entry(offering.location.region.0.as_str())
+                .or_insert_with(|| Some(BTreeSet::new()));
+            match (&offering.location.zone, entry.as_mut()) {
+                (Some(zone), Some(zones)) => {
+                    zones.insert(zone.0.clone());
+                }
+                // An offering without a zone only matches a region-wide entry.
+                _ => *entry = None,
+            }
+        }
+        zones
+            .into_iter()
+            .map(|(region, zones)| LocationConstraint {
+                region: region.to_string(),
+                zones: zones.map(|z| z.into_iter().collect()),
+            })
+            .collect()
+    });
+
+    let mut labels = pool.template.metadata.labels.clone();
+    labels.insert(NODEPOOL_LABEL.into(), name.into());
+
+    let disruption = pool.disruption.as_ref();
+    Ok(NodePoolSpec {
+        server_types,
+        min_nodes: 0,
+        max_nodes,
+        headroom: None,
+        labels,
+        taints: pool.template.spec.taints.clone(),
+        kubelet_args: vec![],
+        join_strategy: None,
+        locations,
+        node_class_ref: None,
+        evacuation: None,
+        consolidation: disruption
+            .and_then(|d| d.consolidation_policy.as_deref())
+            .filter(|p| *p == "WhenEmptyOrUnderutilized")
+            .map(|_| Consolidation::default()),
+        disruption_budget: disruption
+            .into_iter()
+            .flat_map(|d| &d.budgets)
+            .filter_map(|b| b.nodes.parse::<u32>().ok())
+            .min(),
+        fragmentation_penalty: None,
+        target_utilization: None,
+        interruption_penalty: None,
+        cost_objective: None,
+        instance_families: None,
+        max_additional_cost_per_hour: None,
+        max_new_nodes: None,
+        include_instance_types: None,
+        exclude_instance_types: None,
+    })
+}
+
+/// The offering to provision a NodeClaim with: the cheapest one of the
+/// pool's server types meeting its requirements, with room for the
+/// resources it requests. Types the pool's `occupied` nodes and requests
+/// (see [`pool_occupancy`]) already have at their `max`, or all types once
+/// the pool is at `maxNodes`, are not chosen.
+pub fn claim_offering<'a>(
+    claim: &karpenter::NodeClaimSpec,
+    pool: &NodePoolSpec,
+    offerings: &'a [Offering],
+    occupied: &BTreeMap<String, u32>,
+) -> Result<Option<&'a Offering>, String> {
+    let requests = &claim.resources.requests;
+    let need = Resources {
+        cpu_millis: requests
+            .get("cpu")
+            .map(parse_cpu_millis)
+            .transpose()
+            .map_err(|e| format!("invalid cpu request: {e}"))?
+            .unwrap_or(0),
+        memory_mib: requests
+            .get("memory")
+            .map(parse_memory_mib)
+            .transpose()
+            .map_err(|e| format!("invalid memory request: {e}"))?
+            .unwrap_or(0),
+        ephemeral_storage_gib: None,
+        gpu: 0,
+        gpu_model: None,
+        arch: None,
+        mig: BTreeMap::new(),
+        extended: BTreeMap::new(),
+    };
+    let present = |name: &str| occupied.get(name).copied().unwrap_or(0);
+    if pool
+        .max_nodes
+        .is_some_and(|max| occupied.values().sum::<u32>() >= max)
+    {
+        return Ok(None);
+    }
+    Ok(offerings
+        .iter()
+        .filter(|o| {
+            pool.server_types
+                .iter()
+                .any(|st| st.name == o.instance_type.0 && present(&st.name) < st.max)
+        })
+        .filter(|o| meets(&claim.requirements, o) && o.satisfies(&need))
+        .min_by(|a, b| a.cost_per_hour.total_cmp(&b.cost_per_hour)))
+}
+
+/// The status a NodeClaim should show for the NodeRequest provisioning it.
+/// The NodeRequest's `Launched` and `Registered` conditions carry over;
+/// its `Ready` becomes both `Initialized` and `Ready`.
+pub fn claim_status(nr: &NodeRequest, generation: Option<i64>) -> NodeClaimStatus {
+    let status = nr.status.clone().unwrap_or_default();
+    let carried = [
+        (NodeRequestConditionType::Launched, "Launched"),
+        (NodeRequestConditionType::Registered, "Registered"),
+        (NodeRequestConditionType::Ready, "Initialized"),
+        (NodeRequestConditionType::Ready, "Ready"),
+    ];
+    let conditions = carried
+        .into_iter()
+        .filter_map(|(kind, as_type)| {
+            status.condition(kind).map(|c| Condition {
+                type_: as_type.into(),
+                observed_generation: generation,
+                ..c.clone()
+            })
+        })
+        .collect();
+    NodeClaimStatus {
+        provider_id: status.node_id.clone(),
+        node_name: (status.phase == NodeRequestPhase::Ready).then(|| nr.spec.node_id.clone()),
+        conditions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::karpenter::{Disruption, DisruptionBudget};
+    use crate::testing;
+    use k8s_openapi::api::core::v1::NodeSelectorRequirement;
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+    fn offering(name: &str, cpus: u64, memory_mib: u64, cost: f64, zone: &str) -> Offering {
+        testing::offering(name)
+            .with_cpus(cpus)
+            .with_memory_mib(memory_mib)
+            .with_cost(cost)
+            .with_zone(zone)
+            .build()
+    }
+
+    fn requirement(key: &str, operator: &str, values: &[&str]) -> NodeSelectorRequirement {
+        NodeSelectorRequirement {
+            key: key.into(),
+            operator: operator.into(),
+            values: Some(values.iter().map(|v| v.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn karpenter_pool_maps_onto_matching_server_types() {
+        let offerings = [
+            offering("cpx22", 2, 4096, 0.01, "fsn1"),
+            offering("cpx22", 2, 4096, 0.01, "nbg1"),
+            offering("cpx32", 4, 8192, 0.02, "fsn1"),
+            offering("ccx13", 2, 8192, 0.03, "fsn1"),
+        ];
+        let mut pool = karpenter::NodePoolSpec::default();
+        pool.template.spec.requirements = vec![
+            requirement(WELL_KNOWN_INSTANCE_TYPE_LABEL, "In", &["cpx22", "cpx32"]),
+            requirement(ZONE_LABEL, "In", &["fsn1"]),
+            requirement(CAPACITY_TYPE_LABEL, "In", &["on-demand"]),
+            requirement("karpenter.k8s.aws/instance-family", "In", &["m5"]),
+        ];
+        pool.template
+            .metadata
+            .labels
+            .insert("team".into(), "web".into());
+        pool.limits = BTreeMap::from([
+            ("cpu".to_string(), Quantity("16".into())),
+            ("nodes".to_string(), Quantity("6".into())),
+        ]);
+        pool.disruption = Some(Disruption {
+            consolidation_policy: Some("WhenEmptyOrUnderutilized".into()),
+            budgets: vec![
+                DisruptionBudget {
+                    nodes: "10%".into(),
+                },
+                DisruptionBudget { nodes: "2".into() },
+            ],
+        });
+
+        let spec = pool_spec("web", &pool, &offerings, &BTreeMap::new()).unwrap();
+        let types: Vec<(&str, u32)> = spec
+            .server_types
+            .iter()
+            .map(|st| (st.name.as_str(), st.max))
+            .collect();
+        assert_eq!(types, [("cpx22", 8), ("cpx32", 4)]);
+        // 16 CPUs hold 4 of the largest type; more smaller ones could
+        // still add up past the limit alongside them.
+        assert_eq!(spec.max_nodes, Some(4));
+        assert_eq!(
+            spec.locations.unwrap(),
+            [LocationConstraint {
+                region: "eu-central".into(),
+                zones: Some(vec!["fsn1".into()]),
+            }]
+        );
+        assert_eq!(spec.labels[NODEPOOL_LABEL], "web");
+        assert_eq!(spec.labels["team"], "web");
+        assert!(spec.consolidation.is_some());
+        assert_eq!(spec.disruption_budget, Some(2));
+        assert_eq!(
+            ignored_requirements(&pool.template.spec.requirements),
+            ["karpenter.k8s.aws/instance-family"]
+        );
+
+        pool.template.spec.requirements = vec![requirement(
+            WELL_KNOWN_INSTANCE_TYPE_LABEL,
+            "In",
+            &["m5.large"],
+        )];
+        assert!(pool_spec("web", &pool, &offerings, &BTreeMap::new()).is_err());
+    }
+
+    #[test]
+    fn limits_hold_across_the_whole_pool() {
+        let offerings = [
+            offering("cpx22", 2, 4096, 0.01, "fsn1"),
+            offering("cpx32", 4, 8192, 0.02, "fsn1"),
+        ];
+        let pool = karpenter::NodePoolSpec {
+            limits: BTreeMap::from([("cpu".to_string(), Quantity("16".into()))]),
+            ..Default::default()
+        };
+        // 10 of the 16 CPUs are taken.
+        let occupied = BTreeMap::from([("cpx22".to_string(), 1), ("cpx32".to_string(), 2)]);
+
+        let spec = pool_spec("web", &pool, &offerings, &occupied).unwrap();
+        let types: Vec<(&str, u32)> = spec
+            .server_types
+            .iter()
+            .map(|st| (st.name.as_str(), st.max))
+            .collect();
+        assert_eq!(types, [("cpx22", 4), ("cpx32", 3)]);
+        assert_eq!(spec.max_nodes, Some(4));
+
+        let mut claim = karpenter::NodeClaimSpec::default();
+        claim.resources.requests = BTreeMap::from([("cpu".to_string(), Quantity("1".into()))]);
+        let chosen = claim_offering(&claim, &spec, &offerings, &occupied).unwrap();
+        assert_eq!(chosen.unwrap().instance_type.0, "cpx22");
+
+        // A claim provisioned since leaves the pool at its limit.
+        let full = BTreeMap::from([("cpx22".to_string(), 1), ("cpx32".to_string(), 3)]);
+        assert!(
+            claim_offering(&claim, &spec, &offerings, &full)
+                .unwrap()
+                .is_none()
+        );
+        // The 2 CPUs left still hold one more of the smaller type.
+        let spec = pool_spec("web", &pool, &offerings, &full).unwrap();
+        let types: Vec<(&str, u32)> = spec
+            .server_types
+            .iter()
+            .map(|st| (st.name.as_str(), st.max))
+            .collect();
+        assert_eq!(types, [("cpx22", 2), ("cpx32", 3)]);
+        assert_eq!(spec.max_nodes, Some(5));
+    }
+
+    #[test]
+    fn claims_get_the_cheapest_fitting_pool_type() {
+        let offerings = [
+            offering("cpx22", 2, 4096, 0.01, "fsn1"),
+            offering("cpx32", 4, 8192, 0.02, "fsn1"),
+            offering("cpx42", 8, 16384, 0.04, "fsn1"),
+        ];
+        let pool = pool_spec(
+            "web",
+            &karpenter::NodePoolSpec::default(),
+            &offerings[..2],
+            &BTreeMap::new(),
+        )
+        .unwrap();
+        let mut claim = karpenter::NodeClaimSpec::default();
+        claim.resources.requests = BTreeMap::from([("cpu".to_string(), Quantity("3".into()))]);
+
+        let none = BTreeMap::new();
+        let chosen = claim_offering(&claim, &pool, &offerings, &none)
+            .unwrap()
+            .unwrap();
+        assert_eq!(chosen.instance_type.0, "cpx32");
+
+        claim.resources.requests = BTreeMap::from([("cpu".to_string(), Quantity("6".into()))]);
+        assert!(
+            claim_offering(&claim, &pool, &offerings, &none)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Karpenter compatibility, for clusters migrating off Karpenter.
+//!
+//! With `GROWTH_KARPENTER_COMPAT` set, every requeue interval each Karpenter
+//! NodePool is mirrored as a growth-rs NodePool of the same name (see
+//! [`decision::pool_spec`]), owned by it and labelled [`MIRRORED_LABEL`], and
+//! each NodeClaim gets a NodeRequest in that pool for the cheapest offering
+//! that satisfies it, within the Karpenter NodePool's limits. The claim's
+//! status follows the NodeRequest's, and the NodeRequest is deleted with the
+//! claim. Its node carries [`NODECLAIM_LABEL`] too, so the idle and
+//! consolidation scanners leave it to the claim. Karpenter's own controller must be
+//! stopped first; only its CRDs and objects are used. Without them installed
+//! the scan does nothing.
+pub mod decision;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use k8s_openapi::api::core::v1::Node;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference};
+use kube::api::{ListParams, Patch, PatchParams};
+use kube::{Api, Resource, ResourceExt};
+use tracing::{debug, info, warn};
+
+use crate::controller::node_requests::helpers::{create_node_request, delete_node_request};
+use crate::controller::status::{FIELD_MANAGER, apply_status};
+use crate::offering::MANAGED_BY_SELECTOR;
+use crate::resources::karpenter::{self, NODEPOOL_LABEL, NodeClaim};
+use crate::resources::node_pool::NodePool;
+use crate::resources::node_request::{NodeRequest, NodeRequestSpec};
+
+use super::{ControllerContext, ControllerError};
+
+/// Label on growth-rs NodePools mirrored from a Karpenter NodePool. A
+/// NodePool of the same name without it is left alone.
+pub const MIRRORED_LABEL: &str = "growth.vettrdev.com/karpenter-nodepool";
+/// Label on NodeRequests, and their nodes, naming the NodeClaim they
+/// provision.
+pub const NODECLAIM_LABEL: &str = "growth.vettrdev.com/karpenter-nodeclaim";
+
+/// Whether a node backs a Karpenter NodeClaim. Removing it is up to the
+/// claim: deleting the claim deletes its NodeRequest, and with it the node.
+pub fn backs_node_claim(meta: &ObjectMeta) -> bool {
+    meta.labels
+        .as_ref()
+        .is_some_and(|l| l.contains_key(NODECLAIM_LABEL))
+}
+
+/// Periodically mirror Karpenter NodePools and provision their NodeClaims.
+pub(super) async fn run_karpenter_scanner(
+    ctx: Arc<ControllerContext>,
+) -> Result<(), ControllerError> {
+    if !ctx.karpenter_compat {
+        return std::future::pending().await;
+    }
+    loop {
+        tokio::time::sleep(ctx.requeue.interval).await;
+        let scan = scan_karpenter(&ctx);
+        if let Err(e) = ctx.metrics.reconciles.measure("karpenter", scan).await {
+            warn!(error = %e, "Karpenter scan failed, will retry next interval");
+        }
+    }
+}
+
+async fn scan_karpenter(ctx: &ControllerContext) -> Result<(), ControllerError> {
+    let karpenter_pools = match Api::<karpenter::NodePool>::all(ctx.client.clone())
+        .list(&ListParams::default())
+        .await
+    {
+        Ok(list) => list.items,
+        Err(kube::Error::Api(resp)) if resp.code == 404 => {
+            debug!("Karpenter CRDs not installed, nothing to mirror");
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let offerings = ctx.provider.offerings().await;
+    let nodes = Api::<Node>::all(ctx.client.clone())
+        .list(&ListParams::default().labels(MANAGED_BY_SELECTOR))
+        .await?
+        .items;
+    let nrs = Api::<NodeRequest>::all(ctx.client.clone())
+        .list(&ListParams::default())
+        .await?
+        .items;
+    let mut occupancy: BTreeMap<String, BTreeMap<String, u32>> = karpenter_pools
+        .iter()
+        .map(|kp| {
+            let name = kp.name_any();
+            let occupied = decision::pool_occupancy(&name, &nodes, &nrs);
+            (name, occupied)
+        })
+        .collect();
+    let pools = mirror_pools(ctx, &karpenter_pools, &offerings, &occupancy).await?;
+
+    let claims = Api::<NodeClaim>::all(ctx.client.clone())
+        .list(&ListParams::default())
+        .await?
+        .items;
+    let by_claim: HashMap<&str, &NodeRequest> = nrs
+        .iter()
+        .filter_map(|nr| Some((nr.labels().get(NODECLAIM_LABEL)?.as_str(), nr)))
+        .collect();
+
+    for claim in &claims {
+        let name = claim.name_any();
+        if claim.metadata.deletion_timestamp.is_some() {
+            continue;
+        }
+        let Some(pool) = claim
+            .labels()
+            .get(NODEPOOL_LABEL)
+            .and_then(|p| pools.get(p.as_str()))
+        else {
+            continue;
+        };
+        match by_claim.get(name.as_str()) {
+            Some(nr) => sync_claim_status(ctx, claim, nr).await?,
+            None => {
+                let occupied = occupancy.entry(pool.name_any()).or_default();
+                provision_claim(ctx, claim, pool, &offerings, occupied).await?
+            }
+        }
+    }
+
+    let live: HashSet<String> = claims
+        .iter()
+        .filter(|c| c.metadata.deletion_timestamp.is_none())
+        .map(|c| c.name_any())
+        .collect();
+    for (claim, nr) in by_claim {
+        if !live.contains(claim) {
+            info!(nodeclaim = claim, node_request = %nr.name_any(), "NodeClaim gone, deleting its NodeRequest");
+            delete_node_request(ctx.client.clone(), &nr.name_any()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Apply a growth-rs NodePool for each Karpenter NodePool, returning those
+/// this replica manages by name.
+async fn mirror_pools(
+    ctx: &ControllerContext,
+    karpenter_pools: &[karpenter::NodePool],
+    offerings: &[crate::offering::Offering],
+    occupancy: &BTreeMap<String, BTreeMap<String, u32>>,
+) -> Result<BTreeMap<String, NodePool>, ControllerError> {
+    let api: Api<NodePool> = Api::all(ctx.client.clone());
+    let mut mirrored = BTreeMap::new();
+    for kp in karpenter_pools {
+        let name = kp.name_any();
+        if let Some(existing) = api.get_opt(&name).await?
+            && !existing.labels().contains_key(MIRRORED_LABEL)
+        {
+            warn!(nodepool = %name, "a NodePool of the same name already exists, not mirroring the Karpenter NodePool");
+            continue;
+        }
+        let ignored = decision::ignored_requirements(&kp.spec.template.spec.requirements);
+        if !ignored.is_empty() {
+            debug!(nodepool = %name, ?ignored, "ignoring requirements on labels offerings don't carry");
+        }
+        let occupied = occupancy.get(&name).cloned().unwrap_or_default();
+        let spec = match decision::pool_spec(&name, &kp.spec, offerings, &occupied) {
+            Ok(spec) => spec,
+            Err(reason) => {
+                warn!(nodepool = %name, %reason, "cannot mirror Karpenter NodePool");
+                continue;
+            }
+        };
+        let mut pool = NodePool::new(&name, spec);
+        pool.metadata = ObjectMeta {
+            name: Some(name.clone()),
+            labels: Some(BTreeMap::from([(MIRRORED_LABEL.to_string(), name.clone())])),
+            owner_references: Some(vec![OwnerReference {
+                api_version: karpenter::NodePool::api_version(&()).into_owned(),
+                kind: karpenter::NodePool::kind(&()).into_owned(),
+                name: name.clone(),
+                uid: kp.uid().unwrap_or_default(),
+                controller: Some(true),
+                block_owner_deletion: None,
+            }]),
+            ..Default::default()
+        };
+        let applied = api
+            .patch(
+                &name,
+                &PatchParams::apply(FIELD_MANAGER).force(),
+                &Patch::Apply(&pool),
+            )
+            .await?;
+        if ctx.shard.owns(&applied) {
+            mirrored.insert(name, applied);
+        }
+    }
+    Ok(mirrored)
+}
+
+/// Request a node for `claim`, counting it into the pool's `occupied` types
+/// so later claims in the same scan see it against the limits.
+async fn provision_claim(
+    ctx: &ControllerContext,
+    claim: &NodeClaim,
+    pool: &NodePool,
+    offerings: &[crate::offering::Offering],
+    occupied: &mut BTreeMap<String, u32>,
+) -> Result<(), ControllerError> {
+    let name = claim.name_any();
+    let offering = match decision::claim_offering(&claim.spec, &pool.spec, offerings, occupied) {
+        Ok(Some(offering)) => offering,
+        Ok(None) => {
+            warn!(nodeclaim = %name, pool = %pool.name_any(), "no offering in the pool satisfies the NodeClaim within its limits");
+            return Ok(());
+        }
+        Err(reason) => {
+            warn!(nodeclaim = %name, %reason, "cannot provision NodeClaim");
+            return Ok(());
+        }
+    };
+    let taints = if claim.spec.taints.is_empty() {
+        pool.spec.taints.clone()
+    } else {
+        claim.spec.taints.clone()
+    };
+    let mut labels = pool.spec.labels.clone();
+    labels.insert(NODECLAIM_LABEL.into(), name.clone());
+    info!(nodeclaim = %name, offering = %offering.instance_type, "provisioning NodeClaim");
+    create_node_request(
+        ctx.client.clone(),
+        &pool.name_any(),
+        &pool.uid().unwrap_or_default(),
+        NodeRequestSpec {
+            target_offering: offering.instance_type.clone(),
+            location: offering.location.region.clone(),
+            zone: offering.location.zone.clone(),
+            resources: offering.resources.clone(),
+            node_id: format!("growth-{}", uuid::Uuid::new_v4()),
+            provider: Some(offering.provider.clone()),
+            claimed_pods: vec![],
+            labels: Some(labels),
+            taints: Some(taints),
+        },
+        Some(BTreeMap::from([(NODECLAIM_LABEL.to_string(), name)])),
+    )
+    .await?;
+    *occupied
+        .entry(offering.instance_type.0.clone())
+        .or_insert(0) += 1;
+    Ok(())
+}
+
+async fn sync_claim_status(
+    ctx: &ControllerContext,
+    claim: &NodeClaim,
+    nr: &NodeRequest,
+) -> Result<(), ControllerError> {
+    let status = decision::claim_status(nr, claim.metadata.generation);
+    if claim.status.as_ref() != Some(&status) {
+        apply_status(
+            &Api::<NodeClaim>::all(ctx.client.clone()),
+            &claim.name_any(),
+            &status,
+        )
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,437 @@
+//! Controller self-metrics, served in Prometheus text format on `/metrics`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::jiff::Timestamp;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::controller::pods::{is_pod_unschedulable, pod_scheduled_since};
+use crate::optimiser::SolveStats;
+use crate::providers::instrumented::ProviderMetrics;
+
+/// Buckets for time-to-capacity, in seconds: 10s up to an hour.
+const DEMAND_LATENCY_BUCKETS: &[f64] = &[
+    10.0, 30.0, 60.0, 90.0, 120.0, 180.0, 300.0, 600.0, 900.0, 1800.0, 3600.0,
+];
+
+/// Buckets for solve time, in seconds: 1ms up to a minute.
+const SOLVE_DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// Buckets for reconcile time, in seconds: 5ms up to two minutes.
+const RECONCILE_DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0,
+];
+
+/// Registry of every metric the controller exports.
+pub struct Metrics {
+    registry: Registry,
+    pub demand_latency: DemandLatency,
+    pub solver: SolverMetrics,
+    pub reconciles: ReconcileMetrics,
+    /// Shared with the providers, which record their own calls.
+    pub providers: Arc<ProviderMetrics>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let demand_latency = DemandLatency::new();
+        registry
+            .register(Box::new(demand_latency.histogram.clone()))
+            .expect("metric names are unique");
+        let solver = SolverMetrics::new();
+        solver.register(&registry).expect("metric names are unique");
+        let reconciles = ReconcileMetrics::new();
+        reconciles
+            .register(&registry)
+            .expect("metric names are unique");
+        let providers = Arc::new(ProviderMetrics::new());
+        providers
+            .register(&registry)
+            .expect("metric names are unique");
+        Self {
+            registry,
+            demand_latency,
+            solver,
+            reconciles,
+            providers,
+        }
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    pub fn encode(&self) -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .expect("text encoding into a Vec cannot fail");
+        String::from_utf8(buf).expect("text encoding is UTF-8")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-pool solve time and size, from each reconcile's [`SolveStats`], so a
+/// solver slowing down as the cluster grows shows up before it hurts.
+pub struct SolverMetrics {
+    duration: HistogramVec,
+    candidates: IntGaugeVec,
+    unmet: IntGaugeVec,
+}
+
+impl SolverMetrics {
+    fn new() -> Self {
+        let duration = HistogramVec::new(
+            HistogramOpts::new(
+                "growth_solve_duration_seconds",
+                "Time spent placing a pool's pending pods",
+            )
+            .buckets(SOLVE_DURATION_BUCKETS.to_vec()),
+            &["pool", "backend"],
+        )
+        .expect("valid histogram options");
+        let candidates = IntGaugeVec::new(
+            Opts::new(
+                "growth_solve_candidates_scored",
+                "Node and offering candidates scored in a pool's last solve",
+            ),
+            &["pool"],
+        )
+        .expect("valid gauge options");
+        let unmet = IntGaugeVec::new(
+            Opts::new(
+                "growth_solve_unmet_pods",
+                "Pods a pool's last solve could not place",
+            ),
+            &["pool"],
+        )
+        .expect("valid gauge options");
+        Self {
+            duration,
+            candidates,
+            unmet,
+        }
+    }
+
+    fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.duration.clone()))?;
+        registry.register(Box::new(self.candidates.clone()))?;
+        registry.register(Box::new(self.unmet.clone()))
+    }
+
+    /// Record one reconcile's solves, keyed by pool name.
+    pub fn record(&self, stats: &BTreeMap<String, SolveStats>) {
+        for (pool, s) in stats {
+            self.duration
+                .with_label_values(&[pool.as_str(), s.backend])
+                .observe(s.duration.as_secs_f64());
+            self.candidates
+                .with_label_values(&[pool.as_str()])
+                .set(s.candidates_scored as i64);
+            self.unmet
+                .with_label_values(&[pool.as_str()])
+                .set(s.unmet as i64);
+        }
+    }
+}
+
+/// Reconcile counts, durations and errors per controller loop, and how many
+/// are running or waiting out an error backoff, so one saturated loop stands
+/// out from the rest.
+pub struct ReconcileMetrics {
+    total: IntCounterVec,
+    duration: HistogramVec,
+    in_flight: IntGaugeVec,
+    backing_off: IntGaugeVec,
+}
+
+/// Counts a reconcile as in flight until dropped, so a cancelled one is
+/// still taken off.
+struct InFlight<'a>(&'a IntGaugeVec, &'static str);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.with_label_values(&[self.1]).dec();
+    }
+}
+
+impl ReconcileMetrics {
+    fn new() -> Self {
+        let total = IntCounterVec::new(
+            Opts::new(
+                "growth_reconciles_total",
+                "Reconciles run, by controller and result (ok or error)",
+            ),
+            &["controller", "result"],
+        )
+        .expect("valid counter options");
+        let duration = HistogramVec::new(
+            HistogramOpts::new(
+                "growth_reconcile_duration_seconds",
+                "Time spent in one reconcile",
+            )
+            .buckets(RECONCILE_DURATION_BUCKETS.to_vec()),
+            &["controller"],
+        )
+        .expect("valid histogram options");
+        let in_flight = IntGaugeVec::new(
+            Opts::new(
+                "growth_reconciles_in_flight",
+                "Reconciles currently running",
+            ),
+            &["controller"],
+        )
+        .expect("valid gauge options");
+        let backing_off = IntGaugeVec::new(
+            Opts::new(
+                "growth_reconcile_backoff_objects",
+                "Objects whose last reconcile failed, waiting to be retried",
+            ),
+            &["controller"],
+        )
+        .expect("valid gauge options");
+        Self {
+            total,
+            duration,
+            in_flight,
+            backing_off,
+        }
+    }
+
+    fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.total.clone()))?;
+        registry.register(Box::new(self.duration.clone()))?;
+        registry.register(Box::new(self.in_flight.clone()))?;
+        registry.register(Box::new(self.backing_off.clone()))
+    }
+
+    /// Run one of `controller`'s reconciles, recording it.
+    pub async fn measure<T, E>(
+        &self,
+        controller: &'static str,
+        reconcile: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        self.in_flight.with_label_values(&[controller]).inc();
+        let _in_flight = InFlight(&self.in_flight, controller);
+        let started = Instant::now();
+        let result = reconcile.await;
+        self.duration
+            .with_label_values(&[controller])
+            .observe(started.elapsed().as_secs_f64());
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        self.total.with_label_values(&[controller, outcome]).inc();
+        result
+    }
+
+    /// Set the objects in error backoff, by controller.
+    pub fn set_backing_off(&self, failing: &HashMap<&'static str, usize>) {
+        self.backing_off.reset();
+        for (controller, count) in failing {
+            self.backing_off
+                .with_label_values(&[controller])
+                .set(*count as i64);
+        }
+    }
+}
+
+/// Time from a pod first becoming unschedulable to being scheduled onto a
+/// growth-managed node.
+///
+/// The pod watcher starts tracking pods as they turn unschedulable; claim GC
+/// completes them once they land on a growth node. Both ends come from the
+/// pod's `PodScheduled` condition, so how often they are checked doesn't
+/// skew the measurement.
+pub struct DemandLatency {
+    histogram: Histogram,
+    /// Pod UID → when it became unschedulable.
+    pending: Mutex<HashMap<String, Timestamp>>,
+}
+
+impl DemandLatency {
+    fn new() -> Self {
+        let histogram = Histogram::with_opts(
+            HistogramOpts::new(
+                "growth_demand_latency_seconds",
+                "Time from a pod becoming unschedulable to its scheduling on a growth-provisioned node",
+            )
+            .buckets(DEMAND_LATENCY_BUCKETS.to_vec()),
+        )
+        .expect("valid histogram options");
+        Self {
+            histogram,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start tracking an unschedulable pod. Pods already tracked keep their
+    /// original start, so re-failed scheduling attempts don't reset it.
+    pub fn track(&self, pod: &Pod) {
+        if !is_pod_unschedulable(pod) {
+            return;
+        }
+        let (Some(uid), Some(since)) =
+            (pod.metadata.uid.as_ref(), pod_scheduled_since(pod, "False"))
+        else {
+            return;
+        };
+        self.pending
+            .lock()
+            .unwrap()
+            .entry(uid.clone())
+            .or_insert(since);
+    }
+
+    /// Record tracked pods now scheduled on one of `growth_nodes` and forget
+    /// those that were deleted or scheduled elsewhere.
+    pub fn observe(&self, pods: &[Pod], growth_nodes: &HashSet<&str>) {
+        let by_uid: HashMap<&str, &Pod> = pods
+            .iter()
+            .filter_map(|p| Some((p.metadata.uid.as_deref()?, p)))
+            .collect();
+        self.pending.lock().unwrap().retain(|uid, since| {
+            let Some(pod) = by_uid.get(uid.as_str()) else {
+                return false;
+            };
+            let Some(node) = pod.spec.as_ref().and_then(|s| s.node_name.as_deref()) else {
+                return true;
+            };
+            if growth_nodes.contains(node)
+                && let Some(scheduled) = pod_scheduled_since(pod, "True")
+            {
+                let secs = scheduled.duration_since(*since).as_secs_f64();
+                self.histogram.observe(secs.max(0.0));
+            }
+            false
+        });
+    }
+
+    /// Pods currently being tracked.
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{PodCondition, PodSpec, PodStatus};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+    use kube::api::ObjectMeta;
+
+    fn pod(uid: &str, node: Option<&str>, condition: PodCondition) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                uid: Some(uid.into()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                node_name: node.map(Into::into),
+                ..Default::default()
+            }),
+            status: Some(PodStatus {
+                conditions: Some(vec![condition]),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn scheduled(status: &str, at_secs: i64) -> PodCondition {
+        PodCondition {
+            type_: "PodScheduled".into(),
+            status: status.into(),
+            reason: (status == "False").then(|| "Unschedulable".into()),
+            last_transition_time: Some(Time(Timestamp::from_second(at_secs).unwrap())),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn latency_observed_when_pod_lands_on_growth_node() {
+        let metrics = Metrics::new();
+        let latency = &metrics.demand_latency;
+        latency.track(&pod("a", None, scheduled("False", 1_000)));
+        latency.track(&pod("a", None, scheduled("False", 1_050)));
+        latency.track(&pod("b", None, scheduled("False", 1_000)));
+        latency.track(&pod("c", None, scheduled("False", 1_000)));
+        assert_eq!(latency.pending(), 3);
+
+        let growth_nodes = HashSet::from(["growth-1"]);
+        let pods = vec![
+            pod("a", Some("growth-1"), scheduled("True", 1_090)),
+            pod("b", Some("other"), scheduled("True", 1_010)),
+            pod("c", None, scheduled("False", 1_000)),
+        ];
+        latency.observe(&pods, &growth_nodes);
+
+        // `a` is recorded from its first unschedulable time, `b` went to a
+        // node we didn't provision, `c` is still waiting.
+        assert_eq!(latency.histogram.get_sample_count(), 1);
+        assert_eq!(latency.histogram.get_sample_sum(), 90.0);
+        assert_eq!(latency.pending(), 1);
+
+        latency.observe(&[], &growth_nodes);
+        assert_eq!(latency.pending(), 0);
+        assert!(
+            metrics
+                .encode()
+                .contains("growth_demand_latency_seconds_count 1")
+        );
+    }
+
+    #[test]
+    fn solver_stats_are_exported_per_pool() {
+        let metrics = Metrics::new();
+        metrics.solver.record(&BTreeMap::from([(
+            "gpu".to_string(),
+            SolveStats {
+                backend: "scored",
+                candidates_scored: 42,
+                unmet: 3,
+                ..SolveStats::default()
+            },
+        )]));
+        let text = metrics.encode();
+        assert!(
+            text.contains(r#"growth_solve_duration_seconds_count{backend="scored",pool="gpu"} 1"#)
+        );
+        assert!(text.contains(r#"growth_solve_candidates_scored{pool="gpu"} 42"#));
+        assert!(text.contains(r#"growth_solve_unmet_pods{pool="gpu"} 3"#));
+    }
+
+    #[tokio::test]
+    async fn reconciles_are_counted_by_result() {
+        let metrics = Metrics::new();
+        let reconciles = &metrics.reconciles;
+        reconciles
+            .measure("pods", async { Ok::<_, ()>(()) })
+            .await
+            .unwrap();
+        reconciles
+            .measure("pods", async { Err::<(), _>("boom") })
+            .await
+            .unwrap_err();
+        reconciles
+            .measure("node_request", async { Ok::<_, ()>(()) })
+            .await
+            .unwrap();
+        reconciles.set_backing_off(&HashMap::from([("node_request", 2)]));
+
+        let text = metrics.encode();
+        assert!(text.contains(r#"growth_reconciles_total{controller="pods",result="ok"} 1"#));
+        assert!(text.contains(r#"growth_reconciles_total{controller="pods",result="error"} 1"#));
+        assert!(text.contains(r#"growth_reconcile_duration_seconds_count{controller="pods"} 2"#));
+        assert!(text.contains(r#"growth_reconciles_in_flight{controller="pods"} 0"#));
+        assert!(text.contains(r#"growth_reconcile_backoff_objects{controller="node_request"} 2"#));
+    }
+}
@@ -1,6 +1,15 @@
+pub mod audit;
+pub mod backoff;
+pub(crate) mod claims;
+pub mod config_reload;
+pub(crate) mod consolidation;
+pub mod cost_guard;
 pub mod errors;
+pub(crate) mod evacuation;
 pub mod healthcheck;
 pub(crate) mod helpers;
+pub(crate) mod karpenter;
+pub mod metrics;
 #[cfg(not(feature = "testing"))]
 pub(crate) mod node;
 #[cfg(feature = "testing")]
@@ -10,10 +19,21 @@ pub(crate) mod node_removal;
 #[cfg(feature = "testing")]
 pub mod node_removal;
 pub(crate) mod node_requests;
+pub mod offering_stats;
+pub(crate) mod pdb;
+pub mod pod_scope;
 pub mod pods;
+pub(crate) mod pool_floor;
+pub mod provider_config;
+pub mod provider_health;
+pub mod shard;
+pub mod status;
+pub mod webhook;
 pub use errors::ControllerError;
 use helpers::wait_for_crds;
-pub(crate) use helpers::{is_kube_not_found, update_node_request_phase};
+pub(crate) use helpers::{
+    is_kube_not_found, update_node_request_conditions, update_node_request_status,
+};
 pub use pods::PodPoolError;
 
 use std::sync::Arc;
@@ -21,9 +41,12 @@ use std::sync::Arc;
 use tracing::info;
 
 use crate::config::ControllerContext;
+use crate::controller::config_reload::run_config_map_watcher;
 use crate::controller::node::node_controller;
 use crate::controller::node_removal::run_node_removal_request_controller;
 use crate::controller::node_requests::run_node_request_controller;
+use crate::controller::provider_config::run_provider_config_watcher;
+use crate::controller::provider_health::run_provider_health_monitor;
 
 // Re-export for external consumers (integration tests, main.rs).
 pub use pods::watcher::run_pod_watcher;
@@ -34,12 +57,38 @@ pub use pods::{
 
 /// Run the event-driven controllers + watchers.
 ///
-/// Starts watches for Pending Pods, NodeRequests, Ready Nodes, NodeRemovalRequests, and Node Removals concurrently.
-/// Also runs the periodic idle-node scanner for scale-down.
+/// Starts watches for Pending Pods, NodeRequests, Ready Nodes, NodeRemovalRequests, Node Removals,
+/// the ProviderConfig and the config ConfigMap concurrently. Also runs the periodic idle-node
+/// scanner for scale-down, the evacuation, consolidation and pool floor scanners, and probes
+/// provider health.
+///
+/// The CRDs must be established first; with [`CrdSetup::install`](crate::config::CrdSetup)
+/// they are installed or updated beforehand.
+///
+/// In dry-run mode only the pod watcher, ProviderConfig and ConfigMap
+/// watchers and health probes run: solves are logged and audited, and
+/// nothing else is touched.
 pub async fn run(ctx: Arc<ControllerContext>) -> Result<(), ControllerError> {
-    wait_for_crds(ctx.client.clone()).await?;
+    if ctx.crds.install {
+        let applied = crate::resources::apply_crds(&ctx.client, ctx.crds.conversion.as_ref())
+            .await
+            .map_err(|e| ControllerError::from(e).with_context("installing CRDs failed"))?;
+        info!(crds = applied.len(), "installed CRDs");
+    }
+    wait_for_crds(ctx.client.clone(), ctx.crds.timeout).await?;
+    if let Some(reloader) = &ctx.provider_reloader {
+        reloader.load(&ctx.client).await?;
+    }
     info!("all CRDs established, validating pools against provider offerings");
-    helpers::validate_pool_offerings(&ctx.client, &ctx.provider).await?;
+    helpers::validate_pool_offerings(&ctx.client, ctx.provider.as_ref()).await?;
+    helpers::check_inventory(&ctx.client, ctx.provider.as_ref(), ctx.max_inventory_drift).await?;
+    if let Err(e) = ctx.offering_stats.load(&ctx.client).await {
+        tracing::warn!(error = %e, "failed to load offering stats, starting fresh");
+    }
+
+    if ctx.dry_run {
+        info!("dry run: logging planned NodeRequests, creating and removing nothing");
+    }
 
     tokio::select! {
         // Watch for unschedulable pods, creating NodeRequests as appropriate
@@ -48,25 +97,77 @@ pub async fn run(ctx: Arc<ControllerContext>) -> Result<(), ControllerError> {
             tracing::warn!("Pod Watcher exited unexpectedly");
         }
         // Provision nodes from NodeRequests
-        res = run_node_request_controller(ctx.clone()) => {
+        res = unless_dry_run(&ctx, run_node_request_controller(ctx.clone())) => {
             res.map_err(|e| e.with_context("node_request controller failed"))?;
             tracing::warn!("Node Request Watcher exited unexpectedly");
         }
         // Mark NodeRequests as Ready as Nodes join Cluster
-        res = node_controller(ctx.clone()) => {
+        res = unless_dry_run(&ctx, node_controller(ctx.clone())) => {
             res.map_err(|e| e.with_context("node controller failed"))?;
             tracing::warn!("Node Controller Watcher exited unexpectedly");
         }
         // Scale down idle Nodes by creating NodeRemovalRequests
-        res = node_removal::run_idle_node_scanner(ctx.clone()) => {
+        res = unless_dry_run(&ctx, node_removal::run_idle_node_scanner(ctx.clone())) => {
             res.map_err(|e| e.with_context("idle node scanner failed"))?;
             tracing::warn!("Node Removal Request Watcher exited unexpectedly");
         }
+        // Release claims of pods that no longer need in-flight NodeRequests.
+        res = unless_dry_run(&ctx, claims::run_claim_gc(ctx.clone())) => {
+            res.map_err(|e| e.with_context("claim GC failed"))?;
+            tracing::warn!("Claim GC exited unexpectedly");
+        }
+        // Move workloads off evacuating pools/zones before removing their nodes.
+        res = unless_dry_run(&ctx, evacuation::run_evacuation_scanner(ctx.clone())) => {
+            res.map_err(|e| e.with_context("evacuation scanner failed"))?;
+            tracing::warn!("Evacuation Scanner exited unexpectedly");
+        }
+        // Keep each pool's minimum of warm nodes provisioned.
+        res = unless_dry_run(&ctx, pool_floor::run_pool_floor_scanner(ctx.clone())) => {
+            res.map_err(|e| e.with_context("pool floor scanner failed"))?;
+            tracing::warn!("Pool Floor Scanner exited unexpectedly");
+        }
+        // Provision Karpenter NodeClaims, when Karpenter compatibility is on.
+        res = unless_dry_run(&ctx, karpenter::run_karpenter_scanner(ctx.clone())) => {
+            res.map_err(|e| e.with_context("Karpenter scanner failed"))?;
+            tracing::warn!("Karpenter Scanner exited unexpectedly");
+        }
+        // Replace or remove busy nodes whose pods could run cheaper.
+        res = unless_dry_run(&ctx, consolidation::run_consolidation_scanner(ctx.clone())) => {
+            res.map_err(|e| e.with_context("consolidation scanner failed"))?;
+            tracing::warn!("Consolidation Scanner exited unexpectedly");
+        }
         // Drive NodeRemovalRequests through until Node deletion.
-        res = run_node_removal_request_controller(ctx.clone()) => {
+        res = unless_dry_run(&ctx, run_node_removal_request_controller(ctx.clone())) => {
             res.map_err(|e| e.with_context("node_removal_request controller failed"))?;
             tracing::warn!("Node Removal Watcher exited unexpectedly");
         }
+        // Reconfigure the provider when the ProviderConfig changes.
+        res = run_provider_config_watcher(ctx.clone()) => {
+            res.map_err(|e| e.with_context("provider config watcher failed"))?;
+            tracing::warn!("ProviderConfig Watcher exited unexpectedly");
+        }
+        // Apply solver, scoping and limit changes from the config ConfigMap.
+        res = run_config_map_watcher(ctx.clone()) => {
+            res.map_err(|e| e.with_context("config map watcher failed"))?;
+            tracing::warn!("ConfigMap Watcher exited unexpectedly");
+        }
+        // Probe providers and stop planning nodes on unhealthy ones.
+        res = run_provider_health_monitor(ctx.clone()) => {
+            res.map_err(|e| e.with_context("provider health monitor failed"))?;
+            tracing::warn!("Provider Health Monitor exited unexpectedly");
+        }
     }
     Ok(())
 }
+
+/// Run `controller`, unless in dry-run mode, where it never starts.
+async fn unless_dry_run(
+    ctx: &ControllerContext,
+    controller: impl Future<Output = Result<(), ControllerError>>,
+) -> Result<(), ControllerError> {
+    if ctx.dry_run {
+        std::future::pending().await
+    } else {
+        controller.await
+    }
+}
@@ -2,19 +2,28 @@ use futures_util::StreamExt;
 use kube::runtime::reflector::ObjectRef;
 use kube::runtime::{Controller, watcher};
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use std::time::Duration;
 
-use k8s_openapi::api::core::v1::Node;
+use k8s_openapi::api::core::v1::{Node, Taint};
 use kube::Api;
 use kube::api::PatchParams;
-use kube::runtime::controller::Action;
+use kube::runtime::controller::{self, Action};
 use tracing::{debug, info, instrument, warn};
 
+use crate::controller::node_requests::helpers::{node_metadata, requested_offering};
+use crate::controller::shard::UNOWNED_REQUEUE;
+use crate::offering::stats::offering_key;
 use crate::offering::{MANAGED_BY_SELECTOR, NODE_REQUEST_LABEL, STARTUP_TAINT_KEY};
-use crate::resources::node_request::{NodeRequest, NodeRequestPhase};
+use crate::providers::provider::InstanceConfig;
+use crate::resources::node_request::{NodeRequest, NodeRequestConditionType, NodeRequestPhase};
 
-use super::{ControllerContext, ControllerError, update_node_request_phase};
+use super::{
+    ControllerContext, ControllerError, update_node_request_conditions, update_node_request_status,
+};
+
+/// Key of this controller's failures in the shared error backoff.
+const CONTROLLER: &str = "node";
 
 /// Map Node events to NodeRequest reconciles.
 ///
@@ -23,102 +32,157 @@ use super::{ControllerContext, ControllerError, update_node_request_phase};
 fn is_growth_node_ready(node: Node) -> Option<ObjectRef<NodeRequest>> {
     let labels = node.metadata.labels.as_ref()?;
     let nr_name = labels.get(NODE_REQUEST_LABEL)?;
+    node_is_ready(&node).then(|| ObjectRef::new(nr_name.as_str()))
+}
 
-    let ready = node
-        .status
+/// Whether the node reports the `Ready=True` condition.
+fn node_is_ready(node: &Node) -> bool {
+    node.status
         .as_ref()
         .and_then(|s| s.conditions.as_ref())
-        .map(|conditions| {
+        .is_some_and(|conditions| {
             conditions
                 .iter()
                 .any(|c| c.type_ == "Ready" && c.status == "True")
         })
-        .unwrap_or(false);
-
-    ready.then(|| ObjectRef::new(nr_name.as_str()))
 }
 
 pub(crate) async fn node_controller(ctx: Arc<ControllerContext>) -> Result<(), ControllerError> {
     let nrs = Api::<NodeRequest>::all(ctx.client.clone());
     let mut stream = std::pin::pin!(
         Controller::new(nrs, watcher::Config::default())
+            .with_config(controller::Config::default().concurrency(ctx.requeue.concurrency))
             .watches(
                 Api::<Node>::all(ctx.client.clone()),
                 watcher::Config::default().labels(MANAGED_BY_SELECTOR),
                 is_growth_node_ready,
             )
-            .run(reconcile_node_request, error_policy, ctx)
+            .run(
+                |nr, ctx: Arc<ControllerContext>| async move {
+                    let metrics = &ctx.metrics.reconciles;
+                    metrics
+                        .measure(CONTROLLER, reconcile_node_request(nr, ctx.clone()))
+                        .await
+                },
+                error_policy,
+                ctx.clone(),
+            )
     );
     while let Some(result) = stream.next().await {
-        let (obj, _) = result.map_err(ControllerError::from_controller_error)?;
-        debug!(name = %obj.name, "reconciled node");
+        match result {
+            Ok((obj, _)) => {
+                ctx.requeue.errors.succeeded(CONTROLLER, &obj.name);
+                debug!(name = %obj.name, "reconciled node");
+            }
+            // Already logged and requeued with backoff by `error_policy`.
+            Err(controller::Error::ReconcilerFailed(..)) => {}
+            Err(e) => return Err(ControllerError::from_controller_error(e)),
+        }
     }
     Ok(())
 }
 
 // Reconcile paths:
 //  - NR not Provisioning (e.g. Ready on re-trigger) → phase guard, no-op
-//  - NR Provisioning + Node Ready (first event)     → mapper fires, we transition below
-//  - NR missing or Node not Ready                   → mapper returns None, never reaches here
+//  - NR Provisioning + Node Ready                   → we transition below
+//  - NR Provisioning, Node absent or not Ready      → wait; the Node watch re-triggers
+//    us once it turns Ready, so NR-side changes never mark a missing node Ready
+//  - Node Ready but NR missing                      → reconcile of a missing object, skipped
 #[instrument(skip_all, fields(nr = obj.metadata.name.as_deref().unwrap_or("<unknown>")))]
 pub async fn reconcile_node_request(
     obj: Arc<NodeRequest>,
     ctx: Arc<ControllerContext>,
 ) -> Result<Action, ControllerError> {
     let name = obj.metadata.name.as_deref().unwrap_or("<unknown>");
-    if obj.phase() != NodeRequestPhase::Provisioning {
+    if obj.phase() != NodeRequestPhase::Provisioning || obj.metadata.deletion_timestamp.is_some() {
+        return Ok(Action::await_change());
+    }
+    if !ctx.shard.owns_node_request(&ctx.client, &obj).await? {
+        return Ok(Action::requeue(UNOWNED_REQUEUE));
+    }
+
+    let nodes: Api<Node> = Api::all(ctx.client.clone());
+    let Some(node) = nodes.get_opt(&obj.spec.node_id).await? else {
+        debug!(name, node_id = %obj.spec.node_id, "node not registered yet, waiting for it");
+        return Ok(Action::await_change());
+    };
+    if !node_is_ready(&node) {
+        debug!(name, node_id = %obj.spec.node_id, "node registered but not Ready, waiting for it");
+        if !obj.condition_is_true(NodeRequestConditionType::Registered) {
+            let conditions = obj.conditions_with(
+                NodeRequestConditionType::Registered,
+                "NodeRegistered",
+                "node joined the cluster",
+                ctx.clock.now(),
+            );
+            update_node_request_conditions(&ctx.client, &obj, conditions).await?;
+        }
         return Ok(Action::await_change());
     }
 
-    // is_growth_node_ready already verified Ready=True before dispatching here.
-    // Remove the startup taint before transitioning — unblocks the scheduler.
+    // Fill in labels and taints the bootstrap didn't apply, then remove the
+    // startup taint before transitioning — unblocks the scheduler.
+    let config = node_metadata(&ctx.client, &obj)
+        .await
+        .unwrap_or_else(|e| {
+            warn!(name, error = %e, "could not resolve node labels and taints, only removing startup taint");
+            InstanceConfig::default()
+        });
     info!(name, node_id = %obj.spec.node_id, "node ready, removing startup taint and transitioning NodeRequest");
-    remove_startup_taint(&ctx.client, &obj.spec.node_id).await?;
+    apply_node_metadata(&nodes, &node, &obj.spec.node_id, &config).await?;
     let now = ctx.clock.now();
-    update_node_request_phase(&ctx.client, name, NodeRequestPhase::Ready, now).await?;
+    let status = obj.transitioned(
+        NodeRequestPhase::Ready,
+        Some("node registered and Ready".into()),
+        now,
+    );
+    update_node_request_status(&ctx.client, name, status).await?;
+    record_join_time(&obj, &ctx, now).await;
     Ok(Action::await_change())
 }
 
-/// Remove the `growth.vettrdev.com/unregistered: NoExecute` startup taint from a node.
-///
-/// Reads the current taints, filters out the startup taint, and patches back.
-/// 404 is treated as success (node already gone).
-async fn remove_startup_taint(
-    client: &kube::Client,
-    node_name: &str,
-) -> Result<(), ControllerError> {
-    let api: Api<Node> = Api::all(client.clone());
-    let node = match api.get(node_name).await {
-        Ok(n) => n,
-        Err(kube::Error::Api(ref resp)) if resp.code == 404 => {
-            debug!(
-                node_name,
-                "node already deleted, skipping startup taint removal"
-            );
-            return Ok(());
-        }
-        Err(e) => return Err(e.into()),
+/// Feed the NodeRequest's create-to-Ready time into the offering stats,
+/// under the provider that created it. NodeRequests that predate the
+/// `provider` field fall back to the provider of the offering they asked
+/// for; the join is not recorded if that offering is gone.
+async fn record_join_time(
+    nr: &NodeRequest,
+    ctx: &ControllerContext,
+    now: k8s_openapi::jiff::Timestamp,
+) {
+    let Some(created) = nr.metadata.creation_timestamp.as_ref() else {
+        return;
     };
-
-    let remaining: Vec<_> = node
-        .spec
-        .as_ref()
-        .and_then(|s| s.taints.as_ref())
-        .map(|ts| {
-            ts.iter()
-                .filter(|t| !(t.key == STARTUP_TAINT_KEY && t.effect == "NoExecute"))
-                .cloned()
-                .collect()
-        })
-        .unwrap_or_default();
-
-    let taints_value = if remaining.is_empty() {
-        serde_json::Value::Null
-    } else {
-        serde_json::to_value(&remaining).expect("taint serialization cannot fail")
+    let join_secs = now.duration_since(created.0).as_secs().max(0) as u64;
+    let provider = match &nr.spec.provider {
+        Some(provider) => provider.clone(),
+        None => {
+            let offerings = ctx.provider.offerings().await;
+            let Some(offering) = requested_offering(nr, &offerings) else {
+                let name = nr.metadata.name.as_deref().unwrap_or("<unknown>");
+                debug!(name, "offering no longer listed, not recording join time");
+                return;
+            };
+            offering.provider.clone()
+        }
     };
+    let key = offering_key(&provider, &nr.spec.target_offering.0, &nr.spec.location.0);
+    ctx.offering_stats
+        .record_join(&ctx.client, key, join_secs)
+        .await;
+}
 
-    let patch = serde_json::json!({ "spec": { "taints": taints_value } });
+/// Lift the `growth.vettrdev.com/unregistered: NoExecute` startup taint from a
+/// node, first adding any of `config`'s labels and taints it registered without.
+///
+/// 404 is treated as success (node deleted since it was read).
+async fn apply_node_metadata(
+    api: &Api<Node>,
+    node: &Node,
+    node_name: &str,
+    config: &InstanceConfig,
+) -> Result<(), ControllerError> {
+    let patch = node_metadata_patch(node, config);
     match api
         .patch(
             node_name,
@@ -139,11 +203,154 @@ async fn remove_startup_taint(
     }
 }
 
+/// Merge patch adding `config`'s missing labels and taints to `node` and
+/// dropping the startup taint. Existing labels with a different value are
+/// left alone.
+fn node_metadata_patch(node: &Node, config: &InstanceConfig) -> serde_json::Value {
+    let is_startup = |t: &Taint| t.key == STARTUP_TAINT_KEY && t.effect == "NoExecute";
+    let existing = node.metadata.labels.clone().unwrap_or_default();
+    let labels: BTreeMap<&String, &String> = config
+        .labels
+        .iter()
+        .filter(|(k, _)| !existing.contains_key(*k))
+        .collect();
+
+    let mut taints: Vec<Taint> = node
+        .spec
+        .as_ref()
+        .and_then(|s| s.taints.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|t| !is_startup(t))
+        .collect();
+    for wanted in config.taints.iter().filter(|t| !is_startup(t)) {
+        if !taints
+            .iter()
+            .any(|t| t.key == wanted.key && t.effect == wanted.effect)
+        {
+            taints.push(wanted.clone());
+        }
+    }
+
+    let taints_value = if taints.is_empty() {
+        serde_json::Value::Null
+    } else {
+        serde_json::to_value(&taints).expect("taint serialization cannot fail")
+    };
+    let mut patch = serde_json::json!({ "spec": { "taints": taints_value } });
+    if !labels.is_empty() {
+        patch["metadata"] = serde_json::json!({ "labels": labels });
+    }
+    patch
+}
+
 pub(super) fn error_policy(
-    _nr: Arc<NodeRequest>,
+    nr: Arc<NodeRequest>,
     error: &ControllerError,
-    _ctx: Arc<ControllerContext>,
+    ctx: Arc<ControllerContext>,
 ) -> Action {
-    warn!(%error, "node reconcile failed, requeuing");
-    Action::requeue(Duration::from_secs(5))
+    let name = nr.metadata.name.as_deref().unwrap_or("<unknown>");
+    let delay = ctx.requeue.errors.failed(CONTROLLER, name);
+    warn!(name, %error, ?delay, "node reconcile failed, requeuing");
+    Action::requeue(delay)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{NodeCondition, NodeSpec, NodeStatus};
+    use kube::api::ObjectMeta;
+
+    fn taint(key: &str, effect: &str) -> Taint {
+        Taint {
+            key: key.into(),
+            effect: effect.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn patch_adds_missing_metadata_and_lifts_startup_taint() {
+        let node = Node {
+            metadata: ObjectMeta {
+                labels: Some(BTreeMap::from([
+                    ("growth.vettrdev.com/pool".into(), "gpu".into()),
+                    ("zone".into(), "set-by-kubelet".into()),
+                ])),
+                ..Default::default()
+            },
+            spec: Some(NodeSpec {
+                taints: Some(vec![
+                    taint(STARTUP_TAINT_KEY, "NoExecute"),
+                    taint("gpu", "NoSchedule"),
+                ]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let config = InstanceConfig {
+            labels: BTreeMap::from([
+                ("growth.vettrdev.com/pool".into(), "gpu".into()),
+                ("zone".into(), "from-pool".into()),
+                ("node-role.kubernetes.io/gpu".into(), "".into()),
+            ]),
+            taints: vec![
+                taint(STARTUP_TAINT_KEY, "NoExecute"),
+                taint("gpu", "NoSchedule"),
+                taint("dedicated", "NoExecute"),
+            ],
+            ..Default::default()
+        };
+
+        let patch = node_metadata_patch(&node, &config);
+        assert_eq!(
+            patch["metadata"]["labels"],
+            serde_json::json!({ "node-role.kubernetes.io/gpu": "" })
+        );
+        let taints: Vec<Taint> = serde_json::from_value(patch["spec"]["taints"].clone()).unwrap();
+        assert_eq!(
+            taints,
+            vec![taint("gpu", "NoSchedule"), taint("dedicated", "NoExecute")]
+        );
+    }
+
+    #[test]
+    fn only_ready_growth_nodes_map_to_their_node_request() {
+        let node = |labelled: bool, ready: &str| Node {
+            metadata: ObjectMeta {
+                labels: labelled
+                    .then(|| BTreeMap::from([(NODE_REQUEST_LABEL.to_string(), "nr-a".into())])),
+                ..Default::default()
+            },
+            status: Some(NodeStatus {
+                conditions: Some(vec![NodeCondition {
+                    type_: "Ready".into(),
+                    status: ready.into(),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            is_growth_node_ready(node(true, "True")),
+            Some(ObjectRef::new("nr-a"))
+        );
+        assert_eq!(is_growth_node_ready(node(true, "False")), None);
+        assert_eq!(is_growth_node_ready(node(false, "True")), None);
+        assert!(!node_is_ready(&Node::default()));
+    }
+
+    #[test]
+    fn patch_with_nothing_to_add_clears_taints() {
+        let node = Node {
+            spec: Some(NodeSpec {
+                taints: Some(vec![taint(STARTUP_TAINT_KEY, "NoExecute")]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let patch = node_metadata_patch(&node, &InstanceConfig::default());
+        assert_eq!(patch, serde_json::json!({ "spec": { "taints": null } }));
+    }
 }
@@ -1,17 +1,24 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use k8s_openapi::api::core::v1::{Node, Pod};
 
+use crate::controller::karpenter::backs_node_claim;
+use crate::controller::pdb::do_not_disrupt;
 use crate::controller::pods::is_daemonset_pod;
 use crate::offering::{INSTANCE_TYPE_LABEL, NodeReference, POOL_LABEL, pod_pool_selector};
-use crate::resources::node_pool::{NodePool, ServerTypeConfig};
+use crate::planner::Capacity;
+use crate::resources::node_pool::{Headroom, NodePool, NodePoolStatus, ServerTypeConfig};
 use crate::resources::node_removal_request::{NodeRemovalRequest, NodeRemovalRequestPhase};
 
 /// Information about a pool's server types, used for min-count enforcement.
 #[derive(Debug)]
 pub struct PoolMinCounts {
     pub pool_name: String,
+    /// The pool's `minNodes`, across all server types.
+    pub min_nodes: u32,
     pub server_types: Vec<ServerTypeConfig>,
+    /// The pool's headroom, kept on its idle nodes.
+    pub headroom: Option<Headroom>,
 }
 
 impl PoolMinCounts {
@@ -21,14 +28,17 @@ impl PoolMinCounts {
             .filter_map(|np| {
                 np.metadata.name.as_ref().map(|name| Self {
                     pool_name: name.clone(),
+                    min_nodes: np.spec.min_nodes,
                     server_types: np.spec.server_types.clone(),
+                    headroom: np.spec.headroom.clone(),
                 })
             })
             .collect()
     }
 }
 
-/// Tracks per-(pool, instance_type) removal capacity to enforce min-count constraints.
+/// Tracks per-(pool, instance_type) and per-pool removal capacity to enforce
+/// min-count constraints.
 ///
 /// Prevents removing more nodes than the pool allows by accounting for both
 /// existing in-flight removals (active NRRs) and nodes selected during this scan.
@@ -36,6 +46,7 @@ struct RemovalBudget {
     node_counts: HashMap<(String, String), u32>, // (Pool, InstanceType) -> count
     min_lookup: HashMap<(String, String), u32>,  // (Pool, InstanceType) -> min count
     removal_count: HashMap<(String, String), u32>, // (Pool, InstanceType) -> removal count
+    pool_min: HashMap<String, u32>,              // Pool -> minNodes
 }
 
 impl RemovalBudget {
@@ -63,6 +74,10 @@ impl RemovalBudget {
                 min_lookup.insert((pm.pool_name.clone(), st.name.clone()), st.min);
             }
         }
+        let pool_min = pool_mins
+            .iter()
+            .map(|pm| (pm.pool_name.clone(), pm.min_nodes))
+            .collect();
 
         let mut removal_count: HashMap<(String, String), u32> = HashMap::new();
         for nrr in existing_nrrs {
@@ -78,9 +93,22 @@ impl RemovalBudget {
             node_counts,
             min_lookup,
             removal_count,
+            pool_min,
         }
     }
 
+    /// Nodes of `pool` left once every removal reserved so far is done.
+    fn pool_remaining(&self, pool: &str) -> u32 {
+        let sum = |counts: &HashMap<(String, String), u32>| -> u32 {
+            counts
+                .iter()
+                .filter(|((p, _), _)| p == pool)
+                .map(|(_, n)| n)
+                .sum()
+        };
+        sum(&self.node_counts).saturating_sub(sum(&self.removal_count))
+    }
+
     /// Check if removing one more node of this (pool, instance_type) is allowed,
     /// and if so, reserve the slot.
     fn can_reserve(&mut self, pool: &str, instance_type: &str) -> bool {
@@ -92,12 +120,65 @@ impl RemovalBudget {
         if current.saturating_sub(already_removing) <= min {
             return false;
         }
+        let pool_min = self.pool_min.get(pool).copied().unwrap_or(0);
+        if self.pool_remaining(pool) <= pool_min {
+            return false;
+        }
 
         *self.removal_count.entry(key).or_insert(0) += 1;
         true
     }
 }
 
+/// Spare room on each headroom pool's idle nodes beyond what its headroom
+/// needs, as (CPU millicores, memory MiB). Room on busy nodes is not
+/// counted, so a pool may keep an idle node it could do without.
+struct HeadroomSpare(HashMap<String, (i64, i64)>);
+
+impl HeadroomSpare {
+    fn new(idle: &[(&Node, NodeReference)], pods: &[Pod], pool_mins: &[PoolMinCounts]) -> Self {
+        let mut spare: HashMap<String, (i64, i64)> = pool_mins
+            .iter()
+            .filter_map(|pm| {
+                let h = pm.headroom.as_ref()?;
+                let need = |per_replica: u64| -((per_replica * u64::from(h.replicas)) as i64);
+                Some((
+                    pm.pool_name.clone(),
+                    (need(h.cpu_millis), need(h.memory_mib)),
+                ))
+            })
+            .collect();
+        for (node, candidate) in idle {
+            if let Some((cpu, memory)) = spare.get_mut(&candidate.pool) {
+                let (node_cpu, node_memory) = Self::room(node, pods);
+                *cpu += node_cpu;
+                *memory += node_memory;
+            }
+        }
+        Self(spare)
+    }
+
+    fn room(node: &Node, pods: &[Pod]) -> (i64, i64) {
+        Capacity::from_node(node, pods)
+            .map(|c| (c.resources.cpu_millis as i64, c.resources.memory_mib as i64))
+            .unwrap_or_default()
+    }
+
+    /// Whether `pool`'s headroom still fits without `room`.
+    fn fits_without(&self, pool: &str, room: (i64, i64)) -> bool {
+        self.0
+            .get(pool)
+            .is_none_or(|&(cpu, memory)| cpu >= room.0 && memory >= room.1)
+    }
+
+    fn take(&mut self, pool: &str, room: (i64, i64)) {
+        if let Some((cpu, memory)) = self.0.get_mut(pool) {
+            *cpu -= room.0;
+            *memory -= room.1;
+        }
+    }
+}
+
 /// Check whether a node is idle: no non-DaemonSet pods with a Growth pool selector
 /// are running on it.
 ///
@@ -129,18 +210,85 @@ pub fn find_idle_nodes(
 
     let mut budget = RemovalBudget::new(nodes, existing_nrrs, pool_mins);
 
-    nodes
+    let idle: Vec<(&Node, NodeReference)> = nodes
         .iter()
         .filter_map(|node| {
             let candidate = NodeReference::from_node(node)?;
             // It's not already being removed
             (!tracked_nodes.contains(candidate.node_name.as_str())
+            // Nor marked to be left alone
+            && !do_not_disrupt(&node.metadata)
+            // Nor backing a NodeClaim, which removes it itself
+            && !backs_node_claim(&node.metadata)
             // It's considered idle based on the pods on it
-            && is_node_idle(&candidate.node_name, pods)
-            // And we're not going to go below the pool min for this instance type
-            //  by removing it
-            && budget.can_reserve(&candidate.pool, &candidate.instance_type))
-            .then_some(candidate)
+            && is_node_idle(&candidate.node_name, pods))
+            .then_some((node, candidate))
+        })
+        .collect();
+    let mut spare = HeadroomSpare::new(&idle, pods, pool_mins);
+
+    idle.into_iter()
+        .filter(|(node, candidate)| {
+            let room = HeadroomSpare::room(node, pods);
+            // We're not going to leave the pool short of its headroom, nor
+            //  go below the pool min for this instance type by removing it
+            if !spare.fits_without(&candidate.pool, room)
+                || !budget.can_reserve(&candidate.pool, &candidate.instance_type)
+            {
+                return false;
+            }
+            spare.take(&candidate.pool, room);
+            true
+        })
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// `pool`'s NRRs as `(name, phase)`, for its disruption budget. A Pending
+/// NRR whose delete was already issued counts as deprovisioning.
+pub fn budget_phases(
+    nrrs: Vec<NodeRemovalRequest>,
+    pool: &str,
+) -> Vec<(String, NodeRemovalRequestPhase)> {
+    nrrs.into_iter()
+        .filter(|r| r.spec.pool == pool)
+        .filter_map(|r| {
+            let phase = match r.phase() {
+                NodeRemovalRequestPhase::Pending if r.delete_requested() => {
+                    NodeRemovalRequestPhase::Deprovisioning
+                }
+                phase => phase,
+            };
+            Some((r.metadata.name?, phase))
+        })
+        .collect()
+}
+
+/// The statuses `pools` should have given `nodes`, for the pools whose
+/// status says otherwise.
+pub fn changed_pool_statuses(pools: &[NodePool], nodes: &[Node]) -> Vec<(String, NodePoolStatus)> {
+    let mut counts: HashMap<&str, BTreeMap<String, u32>> = HashMap::new();
+    for node in nodes {
+        let labels = node.metadata.labels.as_ref();
+        if let (Some(pool), Some(instance_type)) = (
+            labels.and_then(|l| l.get(POOL_LABEL)),
+            labels.and_then(|l| l.get(INSTANCE_TYPE_LABEL)),
+        ) {
+            *counts
+                .entry(pool.as_str())
+                .or_default()
+                .entry(instance_type.clone())
+                .or_insert(0) += 1;
+        }
+    }
+    pools
+        .iter()
+        .filter_map(|pool| {
+            let name = pool.metadata.name.as_deref()?;
+            let status = NodePoolStatus {
+                nodes: counts.remove(name).unwrap_or_default(),
+            };
+            (pool.status.as_ref() != Some(&status)).then(|| (name.to_string(), status))
         })
         .collect()
 }
@@ -273,16 +421,40 @@ mod tests {
         assert!(idle.is_empty());
     }
 
+    #[test]
+    fn do_not_disrupt_node_is_skipped() {
+        let mut node = growth_node("node-1", "default", "cpx22");
+        node.metadata.annotations = Some(BTreeMap::from([(
+            crate::offering::DO_NOT_DISRUPT_ANNOTATION.into(),
+            "true".into(),
+        )]));
+        let idle = find_idle_nodes(&[node], &[], &[], &[]);
+        assert!(idle.is_empty());
+    }
+
+    #[test]
+    fn node_claim_node_is_skipped() {
+        let mut node = growth_node("node-1", "default", "cpx22");
+        node.metadata.labels.as_mut().unwrap().insert(
+            crate::controller::karpenter::NODECLAIM_LABEL.into(),
+            "claim-1".into(),
+        );
+        let idle = find_idle_nodes(&[node], &[], &[], &[]);
+        assert!(idle.is_empty());
+    }
+
     #[test]
     fn min_count_prevents_removal() {
         let nodes = vec![growth_node("node-1", "default", "cpx22")];
         let pool_mins = vec![PoolMinCounts {
             pool_name: "default".into(),
+            min_nodes: 0,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".into(),
                 max: 10,
                 min: 1,
             }],
+            headroom: None,
         }];
         // Only 1 node, min=1 → can't remove.
         let idle = find_idle_nodes(&nodes, &[], &[], &pool_mins);
@@ -297,11 +469,13 @@ mod tests {
         ];
         let pool_mins = vec![PoolMinCounts {
             pool_name: "default".into(),
+            min_nodes: 0,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".into(),
                 max: 10,
                 min: 1,
             }],
+            headroom: None,
         }];
         // 2 nodes, min=1 → can remove 1.
         let idle = find_idle_nodes(&nodes, &[], &[], &pool_mins);
@@ -317,17 +491,75 @@ mod tests {
         ];
         let pool_mins = vec![PoolMinCounts {
             pool_name: "default".into(),
+            min_nodes: 0,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".into(),
                 max: 10,
                 min: 2,
             }],
+            headroom: None,
         }];
         // 3 nodes, min=2 → can remove 1.
         let idle = find_idle_nodes(&nodes, &[], &[], &pool_mins);
         assert_eq!(idle.len(), 1);
     }
 
+    #[test]
+    fn pool_min_nodes_counts_every_type() {
+        let nodes = vec![
+            growth_node("node-1", "default", "cpx22"),
+            growth_node("node-2", "default", "cpx32"),
+            growth_node("node-3", "default", "cpx32"),
+        ];
+        let pool_mins = |min_nodes| {
+            vec![PoolMinCounts {
+                pool_name: "default".into(),
+                min_nodes,
+                server_types: vec![],
+                headroom: None,
+            }]
+        };
+        // 3 nodes across two types, minNodes=2 → only 1 may go.
+        assert_eq!(find_idle_nodes(&nodes, &[], &[], &pool_mins(2)).len(), 1);
+        // minNodes=0 lets the pool scale to zero.
+        assert_eq!(find_idle_nodes(&nodes, &[], &[], &pool_mins(0)).len(), 3);
+    }
+
+    #[test]
+    fn headroom_keeps_enough_idle_nodes() {
+        use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+        let nodes: Vec<Node> = (1..=3)
+            .map(|i| {
+                let mut node = growth_node(&format!("node-{i}"), "default", "cpx22");
+                node.status = Some(NodeStatus {
+                    allocatable: Some(BTreeMap::from([
+                        ("cpu".into(), Quantity("2".into())),
+                        ("memory".into(), Quantity("4Gi".into())),
+                    ])),
+                    ..Default::default()
+                });
+                node
+            })
+            .collect();
+        let pool_mins = |cpu_millis| {
+            vec![PoolMinCounts {
+                pool_name: "default".into(),
+                min_nodes: 0,
+                server_types: vec![],
+                headroom: Some(Headroom {
+                    replicas: 2,
+                    cpu_millis,
+                    memory_mib: 1024,
+                }),
+            }]
+        };
+        // 6 idle cores, 2 kept for the headroom → one node stays.
+        assert_eq!(find_idle_nodes(&nodes, &[], &[], &pool_mins(1000)).len(), 2);
+        // 3 cores kept → two nodes stay.
+        assert_eq!(find_idle_nodes(&nodes, &[], &[], &pool_mins(1500)).len(), 1);
+    }
+
     #[test]
     fn mixed_idle_and_busy_nodes() {
         let nodes = vec![
@@ -361,11 +593,13 @@ mod tests {
         ];
         let pool_mins = vec![PoolMinCounts {
             pool_name: "default".into(),
+            min_nodes: 0,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".into(),
                 max: 10,
                 min: 1,
             }],
+            headroom: None,
         }];
         // node-1 has a CouldNotRemove NRR — it should NOT count as an in-flight removal.
         let mut nrr = make_nrr("node-1", "default", "cpx22");
@@ -373,6 +607,7 @@ mod tests {
             phase: NodeRemovalRequestPhase::CouldNotRemove,
             removal_attempts: 3,
             last_transition_time: None,
+            delete_requested_at: None,
         });
         // 2 nodes, min=1, 1 CouldNotRemove NRR (excluded) → can still remove 1.
         let idle = find_idle_nodes(&nodes, &[], &[nrr], &pool_mins);
@@ -380,4 +615,68 @@ mod tests {
         assert_eq!(idle.len(), 1);
         assert_eq!(idle[0].node_name, "node-2");
     }
+
+    #[test]
+    fn issued_deletes_count_against_the_budget() {
+        use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+        use k8s_openapi::jiff::Timestamp;
+
+        use crate::resources::node_removal_request::NodeRemovalRequestStatus;
+
+        let mut issued = make_nrr("node-1", "default", "cpx22");
+        issued.status = Some(NodeRemovalRequestStatus {
+            delete_requested_at: Some(Time(Timestamp::UNIX_EPOCH)),
+            ..Default::default()
+        });
+        let nrrs = vec![
+            issued,
+            make_nrr("node-2", "default", "cpx22"),
+            make_nrr("node-3", "other", "cpx22"),
+        ];
+        assert_eq!(
+            budget_phases(nrrs, "default"),
+            [
+                (
+                    "nrr-node-1".to_string(),
+                    NodeRemovalRequestPhase::Deprovisioning
+                ),
+                ("nrr-node-2".to_string(), NodeRemovalRequestPhase::Pending),
+            ]
+        );
+    }
+
+    #[test]
+    fn pool_statuses_are_only_rewritten_when_counts_change() {
+        let pool = |name: &str, nodes: &[(&str, u32)]| {
+            let mut pool: NodePool = serde_json::from_value(serde_json::json!({
+                "apiVersion": "growth.vettrdev.com/v1alpha1",
+                "kind": "NodePool",
+                "metadata": { "name": name },
+                "spec": { "serverTypes": [] },
+            }))
+            .unwrap();
+            pool.status = Some(NodePoolStatus {
+                nodes: nodes.iter().map(|(t, n)| (t.to_string(), *n)).collect(),
+            });
+            pool
+        };
+        let pools = [
+            pool("default", &[("cpx22", 2)]),
+            pool("gpu", &[("gpu-a", 1)]),
+            pool("empty", &[]),
+        ];
+        let nodes = vec![
+            growth_node("node-1", "default", "cpx22"),
+            growth_node("node-2", "default", "cpx22"),
+            growth_node("node-3", "gpu", "gpu-b"),
+        ];
+
+        let changed = changed_pool_statuses(&pools, &nodes);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].0, "gpu");
+        assert_eq!(
+            changed[0].1.nodes,
+            BTreeMap::from([("gpu-b".to_string(), 1)])
+        );
+    }
 }
@@ -0,0 +1,226 @@
+//! Coordinated execution of node removals.
+//!
+//! Each NodeRemovalRequest is reconciled on its own, but when consolidation
+//! marks many nodes at once their deletes should not hit the provider one by
+//! one, nor drain a whole pool at the same moment. The executor sits between
+//! the NRR reconciler and the provider:
+//!
+//! - Deletes issued within a short window are coalesced into
+//!   `Provider::delete_batch` calls, chunked by `max_delete_batch`.
+//! - A pool's `disruptionBudget` caps how many of its NRRs may be
+//!   deprovisioning at once; the rest wait in `Pending`.
+//!
+//! Progress lives in the NRR phases themselves, so a restarted controller
+//! resumes where the previous one stopped.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+use tracing::{debug, info};
+
+use crate::providers::provider::{NodeId, Provider, ProviderError};
+use crate::resources::node_removal_request::NodeRemovalRequestPhase;
+
+/// How long the first delete in a batch waits for others to join it.
+pub const DELETE_BATCH_WINDOW: Duration = Duration::from_secs(1);
+
+type Waiter = (NodeId, oneshot::Sender<Result<(), ProviderError>>);
+
+pub struct RemovalExecutor {
+    window: Duration,
+    /// Deletes waiting for the current batch leader to flush them.
+    queue: Mutex<Vec<Waiter>>,
+    /// Per pool, NRRs admitted by the budget that haven't yet been seen in
+    /// `Deprovisioning`.
+    starting: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+impl Default for RemovalExecutor {
+    fn default() -> Self {
+        Self::new(DELETE_BATCH_WINDOW)
+    }
+}
+
+impl RemovalExecutor {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            queue: Mutex::new(Vec::new()),
+            starting: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Delete `node_id`, batched with any other deletes issued within the
+    /// window.
+    ///
+    /// The first caller into an empty queue becomes the leader: it waits out
+    /// the window, then flushes everything queued so far. Other callers just
+    /// wait for their own result.
+    pub async fn delete(
+        &self,
+        provider: &dyn Provider,
+        node_id: NodeId,
+    ) -> Result<(), ProviderError> {
+        let (tx, rx) = oneshot::channel();
+        let leader = {
+            let mut queue = self.queue.lock().unwrap();
+            queue.push((node_id, tx));
+            queue.len() == 1
+        };
+        if leader {
+            let guard = LeaderGuard(Some(&self.queue));
+            tokio::time::sleep(self.window).await;
+            flush(provider, guard.take()).await;
+        }
+        rx.await.unwrap_or_else(|_| {
+            Err(ProviderError::Transient(
+                "batched delete was abandoned".into(),
+            ))
+        })
+    }
+
+    /// Whether NRR `nrr_name` in `pool` may start deprovisioning under
+    /// `budget`.
+    ///
+    /// `nrrs` are the pool's current NRRs as `(name, phase)`. Admitted NRRs
+    /// hold their slot until they leave `Pending`, so two reconciles racing
+    /// past the check can't both take the last one. `None` means unlimited.
+    pub fn admit(
+        &self,
+        pool: &str,
+        nrr_name: &str,
+        nrrs: &[(String, NodeRemovalRequestPhase)],
+        budget: Option<u32>,
+    ) -> bool {
+        let Some(budget) = budget else {
+            return true;
+        };
+        let mut starting = self.starting.lock().unwrap();
+        let admitted = starting.entry(pool.to_string()).or_default();
+        admitted.retain(|name| {
+            nrrs.iter()
+                .any(|(n, phase)| n == name && *phase == NodeRemovalRequestPhase::Pending)
+        });
+        if admitted.contains(nrr_name) {
+            return true;
+        }
+        let deprovisioning = nrrs
+            .iter()
+            .filter(|(_, phase)| *phase == NodeRemovalRequestPhase::Deprovisioning)
+            .count();
+        if deprovisioning + admitted.len() >= budget as usize {
+            debug!(pool, nrr = nrr_name, budget, "disruption budget exhausted");
+            return false;
+        }
+        admitted.insert(nrr_name.to_string());
+        true
+    }
+}
+
+/// Held by the batch leader while it waits. If the leader is cancelled before
+/// taking the batch, the queue is dropped so its waiters fail (and retry)
+/// instead of waiting on a leader that will never flush.
+struct LeaderGuard<'a>(Option<&'a Mutex<Vec<Waiter>>>);
+
+impl LeaderGuard<'_> {
+    fn take(mut self) -> Vec<Waiter> {
+        let queue = self.0.take().expect("guard holds the queue until taken");
+        std::mem::take(&mut *queue.lock().unwrap())
+    }
+}
+
+impl Drop for LeaderGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(queue) = self.0 {
+            queue.lock().unwrap().clear();
+        }
+    }
+}
+
+/// Delete a drained queue in provider-sized chunks and hand each waiter its
+/// result.
+async fn flush(provider: &dyn Provider, batch: Vec<Waiter>) {
+    let chunk_size = provider.max_delete_batch().max(1);
+    let mut batch = batch.into_iter().peekable();
+    while batch.peek().is_some() {
+        let (ids, senders): (Vec<NodeId>, Vec<_>) = batch.by_ref().take(chunk_size).unzip();
+        if ids.len() > 1 {
+            info!(nodes = ids.len(), "deleting nodes in batch");
+        }
+        let results = provider.delete_batch(&ids).await;
+        for (tx, result) in senders.into_iter().zip(results) {
+            let _ = tx.send(result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::providers::fake::{DeleteBehavior, FakeProvider};
+
+    fn nrrs(entries: &[(&str, NodeRemovalRequestPhase)]) -> Vec<(String, NodeRemovalRequestPhase)> {
+        entries
+            .iter()
+            .map(|(n, p)| (n.to_string(), p.clone()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn concurrent_deletes_share_a_batch() {
+        let provider = Arc::new(
+            FakeProvider::new()
+                .with_max_delete_batch(2)
+                .on_next_delete(DeleteBehavior::Succeed)
+                .on_next_delete(DeleteBehavior::Fail("boom".into())),
+        );
+        let executor = Arc::new(RemovalExecutor::new(Duration::from_millis(50)));
+        let handles: Vec<_> = ["a", "b", "c"]
+            .into_iter()
+            .map(|id| {
+                let (provider, executor) = (provider.clone(), executor.clone());
+                tokio::spawn(
+                    async move { executor.delete(provider.as_ref(), NodeId(id.into())).await },
+                )
+            })
+            .collect();
+        let mut results = Vec::new();
+        for handle in handles {
+            results.push(handle.await.unwrap());
+        }
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 2);
+        let batches = provider.delete_batch_calls();
+        assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 1]);
+    }
+
+    #[test]
+    fn budget_counts_deprovisioning_and_admitted() {
+        use NodeRemovalRequestPhase::*;
+        let executor = RemovalExecutor::default();
+        let listed = nrrs(&[("a", Deprovisioning), ("b", Pending), ("c", Pending)]);
+
+        assert!(executor.admit("p", "b", &listed, Some(2)));
+        // Re-checking an admitted NRR doesn't take a second slot.
+        assert!(executor.admit("p", "b", &listed, Some(2)));
+        assert!(!executor.admit("p", "c", &listed, Some(2)));
+        assert!(executor.admit("p", "c", &listed, None));
+
+        // Once `a` is gone and `b` is deprovisioning, `c` fits.
+        let listed = nrrs(&[("b", Deprovisioning), ("c", Pending)]);
+        assert!(executor.admit("p", "c", &listed, Some(2)));
+    }
+
+    #[test]
+    fn budgets_are_per_pool() {
+        use NodeRemovalRequestPhase::*;
+        let executor = RemovalExecutor::default();
+        assert!(executor.admit("p", "a", &nrrs(&[("a", Pending)]), Some(1)));
+        assert!(executor.admit("q", "b", &nrrs(&[("b", Pending)]), Some(1)));
+        assert!(!executor.admit("p", "c", &nrrs(&[("a", Pending), ("c", Pending)]), Some(1)));
+    }
+}
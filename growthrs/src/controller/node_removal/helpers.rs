@@ -6,6 +6,7 @@ use kube::api::{Api, DeleteParams, ObjectMeta, PatchParams, PostParams};
 use serde_json;
 use tracing::info;
 
+use crate::controller::status::{StatusWrite, apply_status, update_status};
 use crate::offering::{
     DELETE_AT_ANNOTATION, InstanceType, NRR_FINALIZER, REMOVAL_CANDIDATE_ANNOTATION,
     SCALE_DOWN_TAINT_KEY,
@@ -143,12 +144,15 @@ pub async fn remove_removal_candidate_annotation(
     Ok(())
 }
 
-/// Update the phase (and optionally removal_attempts) of a NodeRemovalRequest via SSA Merge Patch.
+/// Update the phase (and optionally removal_attempts) of a NodeRemovalRequest via server-side apply.
+///
+/// `delete_requested_at` is carried over from the NRR's current status.
 pub async fn update_nrr_phase(
     client: &Client,
     name: &str,
     phase: NodeRemovalRequestPhase,
     removal_attempts: Option<u32>,
+    delete_requested_at: Option<Time>,
     now: Timestamp,
 ) -> Result<(), kube::Error> {
     let api: Api<NodeRemovalRequest> = Api::all(client.clone());
@@ -156,15 +160,24 @@ pub async fn update_nrr_phase(
         phase,
         removal_attempts: removal_attempts.unwrap_or(0),
         last_transition_time: Some(Time(now)),
+        delete_requested_at,
     };
-    let patch = serde_json::json!({ "status": status });
-    api.patch_status(
-        name,
-        &PatchParams::apply("growthrs"),
-        &kube::api::Patch::Merge(patch),
-    )
-    .await?;
-    Ok(())
+    apply_status(&api, name, &status).await
+}
+
+/// Record on the NRR when its node's provider delete was issued, or clear it
+/// with `None`, keeping the rest of its status. The write is pinned to the
+/// version read, so a phase written meanwhile isn't reverted.
+pub async fn set_delete_requested(
+    client: &Client,
+    name: &str,
+    at: Option<Timestamp>,
+) -> Result<StatusWrite, kube::Error> {
+    let api: Api<NodeRemovalRequest> = Api::all(client.clone());
+    update_status(&api, name, |status: &mut NodeRemovalRequestStatus| {
+        status.delete_requested_at = at.map(Time);
+    })
+    .await
 }
 
 /// Delete a Kubernetes Node object.
@@ -266,14 +279,9 @@ pub async fn create_node_removal_request(
                 0
             },
             last_transition_time: Some(Time(now)),
+            delete_requested_at: None,
         };
-        let patch = serde_json::json!({ "status": status });
-        api.patch_status(
-            &name,
-            &PatchParams::apply("growthrs"),
-            &kube::api::Patch::Merge(patch),
-        )
-        .await?;
+        apply_status(&api, &name, &status).await?;
     }
 
     info!(
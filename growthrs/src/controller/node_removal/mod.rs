@@ -1,4 +1,5 @@
 pub mod decision;
+pub mod executor;
 pub(crate) mod helpers;
 
 pub use helpers::create_node_removal_request;
@@ -8,12 +9,17 @@ use std::time::Duration;
 
 use futures_util::StreamExt;
 use k8s_openapi::api::core::v1::{Node, Pod};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
 use kube::Api;
 use kube::api::ListParams;
-use kube::runtime::controller::Action;
+use kube::runtime::controller::{self, Action};
 use kube::runtime::{Controller, watcher};
 use tracing::{debug, info, instrument, warn};
 
+use crate::controller::pdb::{PdbBudgets, do_not_disrupt};
+use crate::controller::pods::is_daemonset_pod;
+use crate::controller::shard::{Shard, UNOWNED_REQUEUE};
+use crate::controller::status::{StatusWrite, apply_status};
 use crate::offering::{MANAGED_BY_SELECTOR, NRR_FINALIZER};
 use crate::providers::provider::{NodeId, ProviderStatus};
 use crate::resources::node_pool::NodePool;
@@ -22,17 +28,18 @@ use crate::resources::node_removal_request::{NodeRemovalRequest, NodeRemovalRequ
 use super::{ControllerContext, ControllerError, is_kube_not_found};
 
 use crate::offering::NodeReference;
-use decision::{PoolMinCounts, find_idle_nodes, is_node_idle};
+use decision::{
+    PoolMinCounts, budget_phases, changed_pool_statuses, find_idle_nodes, is_node_idle,
+};
 use helpers::{
     annotate_delete_at, annotate_removal_candidate, apply_scale_down_taint, delete_kubernetes_node,
     delete_nrr, remove_delete_at_annotation, remove_nrr_finalizer,
-    remove_removal_candidate_annotation, update_nrr_phase,
+    remove_removal_candidate_annotation, set_delete_requested, update_nrr_phase,
 };
 
-const IDLE_SCAN_INTERVAL: Duration = Duration::from_secs(30);
-const PROVIDER_DELETE_RETRY: Duration = Duration::from_secs(30);
 const DEPROVISIONING_REQUEUE: Duration = Duration::from_secs(15);
-const NRR_ERROR_REQUEUE: Duration = Duration::from_secs(10);
+/// Key of this controller's failures in the shared error backoff.
+const CONTROLLER: &str = "node_removal_request";
 
 /// Phase transition returned by `decide_phase`.
 type StateChange = Option<(NodeRemovalRequestPhase, Option<u32>)>;
@@ -43,19 +50,21 @@ struct IdleScanState {
     pods: Vec<Pod>,
     nrrs: Vec<NodeRemovalRequest>,
     pool_mins: Vec<PoolMinCounts>,
+    /// The pools this shard owns.
+    pools: Vec<NodePool>,
 }
 
 impl IdleScanState {
     // TODO: Verify each resource type is listed exactly once per scan cycle.
     //       If additional callers appear, consider caching or sharing the result
     //       to avoid redundant API round-trips.
-    async fn collect(client: &kube::Client) -> Result<Self, kube::Error> {
+    async fn collect(client: &kube::Client, shard: &Shard) -> Result<Self, kube::Error> {
         let nodes_api: Api<Node> = Api::all(client.clone());
         let pods_api: Api<Pod> = Api::all(client.clone());
         let nrr_api: Api<NodeRemovalRequest> = Api::all(client.clone());
         let np_api: Api<NodePool> = Api::all(client.clone());
 
-        let (nodes, pods, nrrs, node_pools) = tokio::try_join!(
+        let (mut nodes, pods, nrrs, node_pools) = tokio::try_join!(
             async {
                 nodes_api
                     .list(&ListParams::default().labels(MANAGED_BY_SELECTOR))
@@ -82,11 +91,13 @@ impl IdleScanState {
             },
         )?;
 
+        shard.retain_owned_nodes(&mut nodes, &node_pools);
         Ok(Self {
             nodes,
             pods,
             nrrs,
             pool_mins: PoolMinCounts::from_node_pools(&node_pools),
+            pools: node_pools.into_iter().filter(|p| shard.owns(p)).collect(),
         })
     }
 }
@@ -97,14 +108,30 @@ pub(crate) async fn run_node_removal_request_controller(
 ) -> Result<(), ControllerError> {
     let nrrs: Api<NodeRemovalRequest> = Api::all(ctx.client.clone());
     let config = watcher::Config::default();
-    let mut stream = std::pin::pin!(Controller::new(nrrs, config).run(
-        reconcile_node_removal_request,
-        error_policy,
-        ctx.clone(),
-    ));
+    let mut stream = std::pin::pin!(
+        Controller::new(nrrs, config)
+            .with_config(controller::Config::default().concurrency(ctx.requeue.concurrency))
+            .run(
+                |nrr, ctx: Arc<ControllerContext>| async move {
+                    let metrics = &ctx.metrics.reconciles;
+                    metrics
+                        .measure(CONTROLLER, reconcile_node_removal_request(nrr, ctx.clone()))
+                        .await
+                },
+                error_policy,
+                ctx.clone(),
+            )
+    );
     while let Some(result) = stream.next().await {
-        let (obj, _) = result.map_err(ControllerError::from_controller_error)?;
-        debug!(name = %obj.name, "reconciled NodeRemovalRequest")
+        match result {
+            Ok((obj, _)) => {
+                ctx.requeue.errors.succeeded(CONTROLLER, &obj.name);
+                debug!(name = %obj.name, "reconciled NodeRemovalRequest")
+            }
+            // Already logged and requeued with backoff by `error_policy`.
+            Err(controller::Error::ReconcilerFailed(..)) => {}
+            Err(e) => return Err(ControllerError::from_controller_error(e)),
+        }
     }
     Ok(())
 }
@@ -133,6 +160,14 @@ async fn reconcile_node_removal_request_inner(
 ) -> Result<Action, ControllerError> {
     let name = nrr.metadata.name.as_deref().unwrap_or("<unknown>");
 
+    if !ctx
+        .shard
+        .owns_pool_named(&ctx.client, &nrr.spec.pool)
+        .await?
+    {
+        return Ok(Action::requeue(UNOWNED_REQUEUE));
+    }
+
     // Finalizer gate: if the NRR is being deleted (by GC or explicitly) and our
     // finalizer is still present, ensure provider cleanup runs before allowing
     // the deletion to complete.
@@ -150,13 +185,13 @@ async fn reconcile_node_removal_request_inner(
         // Best-effort provider delete — the VM may already be gone.
         if let Err(e) = ctx.provider.delete(&node_id).await {
             warn!(name, %e, "provider delete during finalizer cleanup failed, will retry");
-            return Ok(Action::requeue(PROVIDER_DELETE_RETRY));
+            return Ok(Action::requeue(ctx.requeue.interval));
         }
 
         // Best-effort K8s node delete — may already be gone (which triggered GC).
         if let Err(e) = delete_kubernetes_node(&ctx.client, node_name).await {
             warn!(name, %e, "k8s node delete during finalizer cleanup failed, will retry");
-            return Ok(Action::requeue(NRR_ERROR_REQUEUE));
+            return Ok(Action::requeue(ctx.requeue.errors.initial()));
         }
 
         remove_nrr_finalizer(&ctx.client, name).await?;
@@ -168,7 +203,21 @@ async fn reconcile_node_removal_request_inner(
 
     if let Some((phase, removal_attempts)) = state_change {
         info!(name, from = %nrr.phase(), to = %phase, "transitioning NodeRemovalRequest");
-        update_nrr_phase(&ctx.client, name, phase, removal_attempts, now).await?;
+        let delete_requested_at = nrr
+            .status
+            .as_ref()
+            .and_then(|s| s.delete_requested_at.clone())
+            // Entering Deprovisioning means the delete was issued.
+            .or_else(|| (phase == NodeRemovalRequestPhase::Deprovisioning).then_some(Time(now)));
+        update_nrr_phase(
+            &ctx.client,
+            name,
+            phase,
+            removal_attempts,
+            delete_requested_at,
+            now,
+        )
+        .await?;
     }
 
     Ok(action)
@@ -178,11 +227,12 @@ async fn reconcile_node_removal_request_inner(
 pub(super) fn error_policy(
     nrr: Arc<NodeRemovalRequest>,
     error: &ControllerError,
-    _ctx: Arc<ControllerContext>,
+    ctx: Arc<ControllerContext>,
 ) -> Action {
     let name = nrr.metadata.name.as_deref().unwrap_or("<unknown>");
-    warn!(name, %error, "NodeRemovalRequest reconcile failed, requeuing");
-    Action::requeue(NRR_ERROR_REQUEUE)
+    let delay = ctx.requeue.errors.failed(CONTROLLER, name);
+    warn!(name, %error, ?delay, "NodeRemovalRequest reconcile failed, requeuing");
+    Action::requeue(delay)
 }
 
 /// Periodic batch scan that detects newly-idle nodes and creates NRRs,
@@ -191,13 +241,24 @@ pub(super) async fn run_idle_node_scanner(
     ctx: Arc<ControllerContext>,
 ) -> Result<(), ControllerError> {
     loop {
-        tokio::time::sleep(IDLE_SCAN_INTERVAL).await;
-        if let Err(e) = scan_idle_nodes(ctx.clone()).await {
+        tokio::time::sleep(ctx.requeue.interval).await;
+        let scan = scan_idle_nodes(ctx.clone());
+        if let Err(e) = ctx.metrics.reconciles.measure("idle_nodes", scan).await {
             warn!(error = %e, "idle node scan failed, will retry next interval");
         }
     }
 }
 
+/// Record each pool's node counts on its status, where they changed.
+async fn update_pool_statuses(client: &kube::Client, pools: &[NodePool], nodes: &[Node]) {
+    let api: Api<NodePool> = Api::all(client.clone());
+    for (name, status) in changed_pool_statuses(pools, nodes) {
+        if let Err(e) = apply_status(&api, &name, &status).await {
+            warn!(pool = %name, error = %e, "failed to update NodePool status");
+        }
+    }
+}
+
 /// Run a single idle-node scan cycle.
 #[cfg(not(feature = "testing"))]
 async fn scan_idle_nodes(ctx: Arc<ControllerContext>) -> Result<(), ControllerError> {
@@ -211,7 +272,8 @@ pub async fn scan_idle_nodes(ctx: Arc<ControllerContext>) -> Result<(), Controll
 }
 
 async fn scan_idle_nodes_inner(ctx: Arc<ControllerContext>) -> Result<(), ControllerError> {
-    let state = IdleScanState::collect(&ctx.client).await?;
+    let state = IdleScanState::collect(&ctx.client, &ctx.shard).await?;
+    update_pool_statuses(&ctx.client, &state.pools, &state.nodes).await;
 
     // Find newly idle nodes and create NodeRemovalRequest.
     let idle_nodes: Vec<NodeReference> =
@@ -292,6 +354,17 @@ async fn decide_phase(
     let node_name = &nrr.spec.node_name;
 
     match nrr.phase() {
+        NodeRemovalRequestPhase::Pending if nrr.delete_requested() => {
+            // A previous reconcile issued the delete but stopped before
+            // recording Deprovisioning. Poll the provider rather than
+            // tainting and deleting again.
+            info!(name, node = %node_name, "delete already requested, resuming deprovisioning");
+            Ok((
+                Some((NodeRemovalRequestPhase::Deprovisioning, Some(1))),
+                Action::requeue(DEPROVISIONING_REQUEUE),
+            ))
+        }
+
         NodeRemovalRequestPhase::Pending => {
             // Check if the cooling-off period has elapsed.
             let created = nrr.metadata.creation_timestamp.as_ref();
@@ -303,6 +376,12 @@ async fn decide_phase(
                 ctx.scale_down.cooling_off_duration.as_secs() as i64,
             );
 
+            if is_node_pinned(&ctx.client, node_name).await? {
+                info!(name, node = %node_name, "node marked do-not-disrupt during cooling-off, cancelling NRR");
+                cancel_nrr(&ctx.client, name, node_name).await?;
+                return Ok((None, Action::await_change()));
+            }
+
             // At each check-in, ensure node remains idle. If no longer idle, cancel removal request.
             if !check_node_still_idle(&ctx.client, node_name).await? {
                 info!(name, node = %node_name, "node no longer idle during cooling-off, cancelling NRR");
@@ -319,6 +398,16 @@ async fn decide_phase(
                 return Ok((None, Action::requeue(Duration::from_secs(requeue_secs))));
             }
 
+            if !admit_within_budget(ctx, name, &nrr.spec.pool).await? {
+                info!(name, pool = %nrr.spec.pool, "pool disruption budget exhausted, deferring removal");
+                return Ok((None, Action::requeue(DEPROVISIONING_REQUEUE)));
+            }
+
+            if !node_disruption_allowed(&ctx.client, node_name).await? {
+                info!(name, node = %node_name, "PodDisruptionBudgets forbid evicting the node's pods, deferring removal");
+                return Ok((None, Action::requeue(DEPROVISIONING_REQUEUE)));
+            }
+
             // Cooling-off elapsed: apply taint, call provider.delete(),
             // and transition directly to Deprovisioning.
             // (delete-at annotation was already set at NRR creation time by the scanner.)
@@ -328,9 +417,9 @@ async fn decide_phase(
                     info!(name, "node already deleted, ensuring provider cleanup");
                     // Node gone from K8s — ensure the provider VM is cleaned up too.
                     let node_id = NodeId(node_name.to_string());
-                    if let Err(e) = ctx.provider.delete(&node_id).await {
+                    if let Err(e) = ctx.removals.delete(ctx.provider.as_ref(), node_id).await {
                         warn!(name, %e, "provider delete failed during node-404 cleanup, will retry");
-                        return Ok((None, Action::requeue(PROVIDER_DELETE_RETRY)));
+                        return Ok((None, Action::requeue(ctx.requeue.interval)));
                     }
                     remove_nrr_finalizer(&ctx.client, name).await?;
                     delete_nrr(&ctx.client, name).await?;
@@ -339,15 +428,24 @@ async fn decide_phase(
                 return Err(e.into());
             }
 
+            if set_delete_requested(&ctx.client, name, Some(now)).await? == StatusWrite::Stale {
+                info!(name, "NRR kept changing, retrying before the delete");
+                return Ok((None, Action::requeue(DEPROVISIONING_REQUEUE)));
+            }
             let node_id = NodeId(node_name.to_string());
-            match ctx.provider.delete(&node_id).await {
+            match ctx.removals.delete(ctx.provider.as_ref(), node_id).await {
                 Ok(()) => Ok((
                     Some((NodeRemovalRequestPhase::Deprovisioning, Some(1))),
                     Action::await_change(),
                 )),
                 Err(e) => {
+                    // The delete didn't go out: the retry re-checks the node
+                    // like a first attempt.
+                    if set_delete_requested(&ctx.client, name, None).await? == StatusWrite::Stale {
+                        warn!(name, "deleteRequestedAt left set, polling retries");
+                    }
                     warn!(name, %e, "provider delete failed, will retry");
-                    Ok((None, Action::requeue(PROVIDER_DELETE_RETRY)))
+                    Ok((None, Action::requeue(ctx.requeue.interval)))
                 }
             }
         }
@@ -376,7 +474,7 @@ async fn decide_phase(
                             Action::await_change(),
                         ));
                     }
-                    if let Err(e) = ctx.provider.delete(&node_id).await {
+                    if let Err(e) = ctx.removals.delete(ctx.provider.as_ref(), node_id).await {
                         warn!(name, %e, "provider delete retry failed");
                     }
                     Ok((
@@ -386,7 +484,7 @@ async fn decide_phase(
                 }
                 Err(e) => {
                     warn!(name, %e, "provider status check failed during deprovisioning");
-                    Ok((None, Action::requeue(PROVIDER_DELETE_RETRY)))
+                    Ok((None, Action::requeue(ctx.requeue.interval)))
                 }
             }
         }
@@ -409,6 +507,54 @@ async fn check_node_still_idle(
     Ok(is_node_idle(node_name, &pods))
 }
 
+/// Check whether the node has been marked do-not-disrupt since the NRR was created.
+async fn is_node_pinned(client: &kube::Client, node_name: &str) -> Result<bool, ControllerError> {
+    let node_api: Api<Node> = Api::all(client.clone());
+    Ok(node_api
+        .get_opt(node_name)
+        .await?
+        .is_some_and(|n| do_not_disrupt(&n.metadata)))
+}
+
+/// Check that the PodDisruptionBudgets allow losing every pod still on the
+/// node, and that none is marked do-not-disrupt. Idle nodes can carry pods
+/// outside any pool, which go with the node.
+async fn node_disruption_allowed(
+    client: &kube::Client,
+    node_name: &str,
+) -> Result<bool, ControllerError> {
+    let pods_api: Api<Pod> = Api::all(client.clone());
+    let lp = ListParams::default().fields(&format!("spec.nodeName={node_name}"));
+    let pods: Vec<Pod> = pods_api.list(&lp).await?.into_iter().collect();
+    let disrupted: Vec<&Pod> = pods.iter().filter(|p| !is_daemonset_pod(p)).collect();
+    if disrupted.iter().any(|p| do_not_disrupt(&p.metadata)) {
+        return Ok(false);
+    }
+    if disrupted.is_empty() {
+        return Ok(true);
+    }
+    Ok(PdbBudgets::list(client).await?.allows_all(&disrupted))
+}
+
+/// Check the NRR's pool disruption budget against the pool's other NRRs.
+async fn admit_within_budget(
+    ctx: &ControllerContext,
+    nrr_name: &str,
+    pool: &str,
+) -> Result<bool, ControllerError> {
+    let np_api: Api<NodePool> = Api::all(ctx.client.clone());
+    let budget = np_api
+        .get_opt(pool)
+        .await?
+        .and_then(|np| np.spec.disruption_budget);
+    if budget.is_none() {
+        return Ok(true);
+    }
+    let nrr_api: Api<NodeRemovalRequest> = Api::all(ctx.client.clone());
+    let nrrs = budget_phases(nrr_api.list(&ListParams::default()).await?.items, pool);
+    Ok(ctx.removals.admit(pool, nrr_name, &nrrs, budget))
+}
+
 /// Cancel a Node Removal Request.
 ///
 /// Removes annotations on the node, clears the finalizer, and deletes the NRR.
@@ -0,0 +1,209 @@
+//! kubeadm bootstrap tokens for joining provisioned nodes.
+//!
+//! Tokens are stored the way kubeadm stores them: a
+//! `bootstrap.kubernetes.io/token` Secret named `bootstrap-token-<id>` in
+//! `kube-system`. The controller manager's token cleaner deletes them once
+//! they expire, so minted tokens need no cleanup of our own. A node's token
+//! ID is derived from its node ID, so retried creates reuse one Secret.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::jiff::{SignedDuration, Timestamp};
+use kube::api::{ObjectMeta, PostParams};
+use kube::{Api, Client};
+use rand::Rng;
+
+use crate::controller::shard::fnv1a;
+use crate::offering::{MANAGED_BY_LABEL, MANAGED_BY_VALUE};
+
+const TOKEN_NAMESPACE: &str = "kube-system";
+const TOKEN_SECRET_TYPE: &str = "bootstrap.kubernetes.io/token";
+/// Group kubeadm's RBAC lets bootstrap tokens join nodes as.
+const NODE_BOOTSTRAP_GROUP: &str = "system:bootstrappers:kubeadm:default-node-token";
+const TOKEN_CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// A kubeadm bootstrap token, `<id>.<secret>` (`[a-z0-9]{6}.[a-z0-9]{16}`).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BootstrapToken {
+    pub id: String,
+    pub secret: String,
+}
+
+impl BootstrapToken {
+    /// A fresh token for `node_id`, under the ID [`token_id`] gives it.
+    pub fn generate(node_id: &str, rng: &mut impl Rng) -> Self {
+        Self {
+            id: token_id(node_id),
+            secret: (0..16)
+                .map(|_| TOKEN_CHARSET[rng.gen_range(0..TOKEN_CHARSET.len())] as char)
+                .collect(),
+        }
+    }
+
+    /// The token a Secret holds, if it is still valid at `now`.
+    pub fn from_secret(secret: &Secret, now: Timestamp) -> Option<Self> {
+        let data = secret.data.as_ref()?;
+        let field = |key: &str| String::from_utf8(data.get(key)?.0.clone()).ok();
+        let expires: Timestamp = field("expiration")?.parse().ok()?;
+        (expires > now).then_some(Self {
+            id: field("token-id")?,
+            secret: field("token-secret")?,
+        })
+    }
+
+    /// The Secret that makes this token valid until `expires`.
+    pub fn to_secret(&self, expires: Timestamp, node_id: &str) -> Secret {
+        Secret {
+            metadata: ObjectMeta {
+                name: Some(format!("bootstrap-token-{}", self.id)),
+                namespace: Some(TOKEN_NAMESPACE.into()),
+                labels: Some(BTreeMap::from([(
+                    MANAGED_BY_LABEL.into(),
+                    MANAGED_BY_VALUE.into(),
+                )])),
+                ..Default::default()
+            },
+            type_: Some(TOKEN_SECRET_TYPE.into()),
+            string_data: Some(BTreeMap::from([
+                ("token-id".into(), self.id.clone()),
+                ("token-secret".into(), self.secret.clone()),
+                ("expiration".into(), expires.to_string()),
+                ("usage-bootstrap-authentication".into(), "true".into()),
+                ("usage-bootstrap-signing".into(), "true".into()),
+                ("auth-extra-groups".into(), NODE_BOOTSTRAP_GROUP.into()),
+                (
+                    "description".into(),
+                    format!("Growth join token for {node_id}"),
+                ),
+            ])),
+            ..Default::default()
+        }
+    }
+}
+
+impl std::fmt::Display for BootstrapToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.id, self.secret)
+    }
+}
+
+/// Six base-36 characters of `node_id`'s hash: the token ID, and so the
+/// Secret name, every create attempt for the node uses.
+fn token_id(node_id: &str) -> String {
+    let mut hash = fnv1a(node_id);
+    (0..6)
+        .map(|_| {
+            let c = TOKEN_CHARSET[(hash % TOKEN_CHARSET.len() as u64) as usize];
+            hash /= TOKEN_CHARSET.len() as u64;
+            c as char
+        })
+        .collect()
+}
+
+/// The token for `node_id`: the one an earlier attempt minted while it is
+/// still valid, otherwise a fresh one that expires `ttl` after `now`.
+pub(crate) async fn mint_bootstrap_token(
+    client: &Client,
+    node_id: &str,
+    ttl: Duration,
+    now: Timestamp,
+) -> Result<BootstrapToken, kube::Error> {
+    let api: Api<Secret> = Api::namespaced(client.clone(), TOKEN_NAMESPACE);
+    let name = format!("bootstrap-token-{}", token_id(node_id));
+    let existing = api.get_opt(&name).await?;
+    if let Some(token) = existing
+        .as_ref()
+        .and_then(|s| BootstrapToken::from_secret(s, now))
+    {
+        return Ok(token);
+    }
+    let token = BootstrapToken::generate(node_id, &mut rand::thread_rng());
+    let expires = now + SignedDuration::from_secs(ttl.as_secs() as i64);
+    let mut secret = token.to_secret(expires, node_id);
+    match existing {
+        // Expired but not yet cleaned up: take it over.
+        Some(old) => {
+            secret.metadata.resource_version = old.metadata.resource_version;
+            api.replace(&name, &PostParams::default(), &secret).await?;
+        }
+        None => {
+            api.create(&PostParams::default(), &secret).await?;
+        }
+    }
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn generated_token_matches_kubeadm_format() {
+        let token = BootstrapToken::generate("growth-1", &mut StdRng::seed_from_u64(7));
+        let s = token.to_string();
+        let (id, secret) = s.split_once('.').unwrap();
+        assert_eq!(id.len(), 6);
+        assert_eq!(secret.len(), 16);
+        assert!(
+            s.chars()
+                .all(|c| c == '.' || c.is_ascii_lowercase() || c.is_ascii_digit())
+        );
+    }
+
+    #[test]
+    fn token_secret_carries_expiry_and_join_group() {
+        let token = BootstrapToken {
+            id: "abc123".into(),
+            secret: "0123456789abcdef".into(),
+        };
+        let expires = Timestamp::from_second(1_700_000_000).unwrap();
+        let secret = token.to_secret(expires, "growth-1");
+        assert_eq!(
+            secret.metadata.name.as_deref(),
+            Some("bootstrap-token-abc123")
+        );
+        assert_eq!(secret.metadata.namespace.as_deref(), Some("kube-system"));
+        assert_eq!(secret.type_.as_deref(), Some(TOKEN_SECRET_TYPE));
+        let data = secret.string_data.unwrap();
+        assert_eq!(data["token-secret"], "0123456789abcdef");
+        assert_eq!(data["expiration"], "2023-11-14T22:13:20Z");
+        assert_eq!(data["auth-extra-groups"], NODE_BOOTSTRAP_GROUP);
+        assert_eq!(data["usage-bootstrap-authentication"], "true");
+    }
+
+    #[test]
+    fn retries_for_a_node_share_its_token_id() {
+        let first = BootstrapToken::generate("growth-1", &mut StdRng::seed_from_u64(1));
+        let retry = BootstrapToken::generate("growth-1", &mut StdRng::seed_from_u64(2));
+        assert_eq!(first.id, retry.id);
+        assert_ne!(first.secret, retry.secret);
+        assert_ne!(first.id, token_id("growth-2"));
+    }
+
+    #[test]
+    fn secret_token_is_reused_until_it_expires() {
+        let token = BootstrapToken {
+            id: "abc123".into(),
+            secret: "0123456789abcdef".into(),
+        };
+        let expires = Timestamp::from_second(1_700_000_000).unwrap();
+        let mut secret = token.to_secret(expires, "growth-1");
+        // The API server returns `stringData` folded into `data`.
+        secret.data = Some(
+            secret
+                .string_data
+                .take()
+                .unwrap()
+                .into_iter()
+                .map(|(k, v)| (k, k8s_openapi::ByteString(v.into_bytes())))
+                .collect(),
+        );
+        let before = Timestamp::from_second(1_699_999_000).unwrap();
+        assert_eq!(BootstrapToken::from_secret(&secret, before), Some(token));
+        assert_eq!(BootstrapToken::from_secret(&secret, expires), None);
+    }
+}
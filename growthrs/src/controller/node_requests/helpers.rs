@@ -1,39 +1,44 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
 
-use kube::{Api, Client};
+use k8s_openapi::api::core::v1::{Node, Pod, Taint};
+use kube::{Api, Client, ResourceExt};
 use tracing::{debug, info, warn};
 
-use kube::api::{ObjectMeta, PostParams};
+use kube::api::{ListParams, ObjectMeta, Patch, PatchParams, PostParams};
 
+use crate::controller::consolidation::decision::node_cost;
+use crate::controller::cost_guard::BudgetExceeded;
+use crate::controller::evacuation::decision::displaced_pods;
+use crate::controller::evacuation::helpers::evict_pod;
 use crate::controller::helpers::{read_configmap_key, read_secret_key};
+use crate::controller::node_removal::decision::is_node_idle;
+use crate::controller::node_requests::bootstrap::mint_bootstrap_token;
+use crate::controller::pdb::PdbBudgets;
 use crate::offering::{
-    INSTANCE_TYPE_LABEL, MANAGED_BY_LABEL, MANAGED_BY_VALUE, NODE_REQUEST_LABEL, Offering,
-    POOL_LABEL,
+    INSTANCE_TYPE_LABEL, MANAGED_BY_LABEL, MANAGED_BY_SELECTOR, MANAGED_BY_VALUE,
+    NODE_REQUEST_LABEL, NR_FINALIZER, Offering, POOL_LABEL, STARTUP_TAINT_KEY,
 };
 use crate::providers::hetzner::config::HetznerCreateConfig;
-use crate::providers::provider::{InstanceConfig, Provider, ProviderCreateConfig, ProviderError};
+use crate::providers::provider::{InstanceConfig, NodeId, ProviderCreateConfig, ProviderError};
 use crate::resources::hetzner_node_class::HetznerNodeClass;
-use crate::resources::node_pool::NodePool;
-use crate::resources::node_request::{NodeRequest, NodeRequestSpec};
+use crate::resources::node_pool::{NodeClassRef, NodePool};
+use crate::resources::node_request::{NodeRequest, NodeRequestPhase, NodeRequestSpec};
 use crate::resources::user_data::{
-    RESERVED_DYNAMIC_VARS, UserDataConfig, UserDataError, build_dynamic_vars, resolve_template,
+    JoinStrategy, RESERVED_DYNAMIC_VARS, UserDataConfig, UserDataError, UserDataTemplate,
 };
 
 use super::ControllerContext;
 
 impl UserDataConfig {
-    /// Resolve the user-data template: read ConfigMap + Secret values, merge
-    /// dynamic per-node variables, and perform template substitution.
+    /// Resolve the user-data template: read the ConfigMap template and the
+    /// Secret values of its declared variables.
     ///
     /// This is the imperative-shell entry point for template resolution.
-    /// All K8s I/O (ConfigMap/Secret reads) happens here; the actual
-    /// substitution is delegated to the pure `resolve_template()`.
-    pub(crate) async fn resolve(
-        &self,
-        client: &Client,
-        offering: &Offering,
-        labels: &BTreeMap<String, String>,
-    ) -> Result<String, UserDataError> {
+    /// All K8s I/O (ConfigMap/Secret reads) happens here; the provider renders
+    /// the result with the node's dynamic variables at create time (see
+    /// `UserDataTemplate::render`).
+    pub(crate) async fn resolve(&self, client: &Client) -> Result<UserDataTemplate, UserDataError> {
         let tpl_ref = &self.template_ref;
         let template =
             read_configmap_key(client, &tpl_ref.namespace, &tpl_ref.name, &tpl_ref.key).await?;
@@ -55,31 +60,64 @@ impl UserDataConfig {
             secret_vars.push((var.name.clone(), value));
         }
 
-        let dynamic_vars = build_dynamic_vars(offering, labels);
-        let mut all_vars = secret_vars;
-        for (name, value) in dynamic_vars {
-            let marker = format!("{{{{ {name} }}}}");
-            if template.contains(&marker) {
-                all_vars.push((name, value));
-            }
-        }
+        Ok(UserDataTemplate {
+            template,
+            variables: secret_vars,
+            join_strategy: self.join_strategy,
+            k3s_server_url: self.k3s_server_url.clone(),
+        })
+    }
 
-        resolve_template(&template, &all_vars)
+    /// The join token for `node_id` when a bootstrap token is configured:
+    /// read from its Secret, or minted for this node alone (and reused by
+    /// later attempts to create it).
+    pub(crate) async fn join_token(
+        &self,
+        client: &Client,
+        node_id: &str,
+        now: k8s_openapi::jiff::Timestamp,
+    ) -> Result<Option<String>, UserDataError> {
+        let Some(bootstrap) = &self.bootstrap_token else {
+            return Ok(None);
+        };
+        if let Some(secret_ref) = &bootstrap.secret_ref {
+            let token = read_secret_key(
+                client,
+                &secret_ref.namespace,
+                &secret_ref.name,
+                &secret_ref.key,
+            )
+            .await?;
+            return Ok(Some(token.trim().to_string()));
+        }
+        let ttl = Duration::from_secs(bootstrap.ttl_seconds);
+        let token = mint_bootstrap_token(client, node_id, ttl, now)
+            .await
+            .map_err(|e| UserDataError::BootstrapTokenFailed {
+                reason: e.to_string(),
+            })?;
+        info!(node_id, token_id = %token.id, "minted bootstrap token");
+        Ok(Some(token.to_string()))
     }
 }
 
 #[derive(Debug)]
 pub enum ProvisionOutcome {
-    Created,
+    /// The provider accepted the create and gave the node this ID.
+    Created(NodeId),
     NoMatchingOffering,
-    OfferingUnavailable,
+    /// The provider had no capacity for the offering, for this reason.
+    OfferingUnavailable(String),
 }
 use crate::controller::errors::ControllerError;
 
 /// Information extracted from the owning NodePool.
 struct PoolInfo {
     labels: BTreeMap<String, String>,
-    node_class_ref: Option<crate::resources::node_pool::NodeClassRef>,
+    taints: Vec<Taint>,
+    kubelet_args: Vec<String>,
+    join_strategy: Option<JoinStrategy>,
+    node_class_ref: Option<NodeClassRef>,
 }
 
 /// Look up the owning NodePool's labels and node_class_ref from the NodeRequest's ownerReference.
@@ -106,6 +144,9 @@ async fn get_pool_info(client: &Client, nr: &NodeRequest) -> Result<PoolInfo, Co
     match api.get_opt(&name).await {
         Ok(Some(np)) => Ok(PoolInfo {
             labels: np.spec.labels,
+            taints: np.spec.taints,
+            kubelet_args: np.spec.kubelet_args,
+            join_strategy: np.spec.join_strategy,
             node_class_ref: np.spec.node_class_ref,
         }),
         Ok(None) => Err(ControllerError::Other(anyhow::anyhow!(
@@ -115,22 +156,56 @@ async fn get_pool_info(client: &Client, nr: &NodeRequest) -> Result<PoolInfo, Co
     }
 }
 
+/// The labels and taints a NodeRequest's node should carry, as passed to the
+/// provider at create time. The node controller re-applies them after the
+/// node joins, for bootstraps that couldn't set them all.
+///
+/// They are the pool's as recorded in the spec; the NodePool is only read
+/// for NodeRequests created before that.
+pub(crate) async fn node_metadata(
+    client: &Client,
+    nr: &NodeRequest,
+) -> Result<InstanceConfig, ControllerError> {
+    if let (Some(labels), Some(taints)) = (&nr.spec.labels, &nr.spec.taints) {
+        return Ok(build_labels(nr, labels, taints));
+    }
+    let pool_info = get_pool_info(client, nr).await?;
+    let (labels, taints) = pool_metadata(nr, &pool_info);
+    Ok(build_labels(nr, labels, taints))
+}
+
+/// The pool labels and taints for `nr`'s node: those recorded in its spec,
+/// else the owning pool's current ones.
+fn pool_metadata<'a>(
+    nr: &'a NodeRequest,
+    pool_info: &'a PoolInfo,
+) -> (&'a BTreeMap<String, String>, &'a [Taint]) {
+    (
+        nr.spec.labels.as_ref().unwrap_or(&pool_info.labels),
+        nr.spec.taints.as_deref().unwrap_or(&pool_info.taints),
+    )
+}
+
 /// Create a NodeRequest in Pending phase for a given pool and offering.
 ///
 /// The name is generated as `{pool}-{uuid}` per the RFC naming convention.
 /// An ownerReference is set pointing to the NodePool so that garbage collection
-/// cleans up NodeRequests when the pool is deleted.
+/// cleans up NodeRequests when the pool is deleted, and the
+/// `growth.vettrdev.com/node-cleanup` finalizer makes that deletion take the
+/// node with it.
 pub async fn create_node_request(
     client: Client,
     pool: &str,
     pool_uid: &str,
     spec: NodeRequestSpec,
+    labels: Option<BTreeMap<String, String>>,
 ) -> kube::Result<NodeRequest> {
     let api: Api<NodeRequest> = Api::all(client);
     let name = format!("{pool}-{}", uuid::Uuid::new_v4());
     let mut nr = NodeRequest::new(&name, spec);
     nr.metadata = ObjectMeta {
         name: Some(name.clone()),
+        labels,
         owner_references: Some(vec![
             k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference {
                 api_version: "growth.vettrdev.com/v1alpha1".to_string(),
@@ -141,6 +216,7 @@ pub async fn create_node_request(
                 block_owner_deletion: Some(true),
             },
         ]),
+        finalizers: Some(vec![NR_FINALIZER.to_string()]),
         ..Default::default()
     };
     let created = api.create(&PostParams::default(), &nr).await?;
@@ -154,6 +230,92 @@ pub async fn create_node_request(
     Ok(created)
 }
 
+/// The offering `nr` asks for, in its region, zone and provider.
+pub(crate) fn requested_offering<'a>(
+    nr: &NodeRequest,
+    offerings: &'a [Offering],
+) -> Option<&'a Offering> {
+    offerings.iter().find(|o| {
+        o.instance_type == nr.spec.target_offering
+            && o.location.region == nr.spec.location
+            && nr
+                .spec
+                .zone
+                .as_ref()
+                .is_none_or(|z| o.location.zone.as_ref() == Some(z))
+            && nr.spec.provider.as_ref().is_none_or(|p| *p == o.provider)
+    })
+}
+
+/// Hourly cost of the managed fleet: every Growth-managed node, priced by
+/// its offering, plus every Provisioning NodeRequest whose node hasn't
+/// joined yet.
+pub(crate) fn fleet_cost(nodes: &[Node], nrs: &[NodeRequest], offerings: &[Offering]) -> f64 {
+    let joined: HashSet<&str> = nodes
+        .iter()
+        .filter_map(|n| n.metadata.labels.as_ref()?.get(NODE_REQUEST_LABEL))
+        .map(String::as_str)
+        .collect();
+    let running: f64 = nodes.iter().filter_map(|n| node_cost(n, offerings)).sum();
+    let provisioning: f64 = nrs
+        .iter()
+        .filter(|nr| nr.phase() == NodeRequestPhase::Provisioning)
+        .filter(|nr| {
+            nr.metadata
+                .name
+                .as_deref()
+                .is_none_or(|name| !joined.contains(name))
+        })
+        .filter_map(|nr| requested_offering(nr, offerings))
+        .map(|o| o.cost_per_hour)
+        .sum();
+    running + provisioning
+}
+
+/// Why `nr` must be held back, if provisioning it would take the managed
+/// fleet over the cluster-wide hourly budget. Requests for an offering no
+/// longer sold are let through to be marked Unmet. Requests let through
+/// count against the budget while still Pending (see
+/// [`BudgetAdmissions`](crate::controller::cost_guard::BudgetAdmissions)),
+/// and are checked one at a time.
+pub(super) async fn over_fleet_budget(
+    nr: &NodeRequest,
+    ctx: &ControllerContext,
+) -> Result<Option<BudgetExceeded>, kube::Error> {
+    let budget = ctx.tunables.current().max_hourly_cost;
+    if budget.is_none() {
+        return Ok(None);
+    }
+    let offerings = ctx.provider.offerings().await;
+    let Some(cost) = requested_offering(nr, &offerings).map(|o| o.cost_per_hour) else {
+        return Ok(None);
+    };
+    let mut admissions = ctx.budget_admissions.lock().await;
+    let (nodes, nrs) = tokio::try_join!(
+        async {
+            Api::<Node>::all(ctx.client.clone())
+                .list(&ListParams::default().labels(MANAGED_BY_SELECTOR))
+                .await
+                .map(|l| l.items)
+        },
+        async {
+            Api::<NodeRequest>::all(ctx.client.clone())
+                .list(&ListParams::default())
+                .await
+                .map(|l| l.items)
+        },
+    )?;
+    let pending: HashSet<&str> = nrs
+        .iter()
+        .filter(|r| r.phase() == NodeRequestPhase::Pending)
+        .filter_map(|r| r.metadata.name.as_deref())
+        .collect();
+    let fleet = fleet_cost(&nodes, &nrs, &offerings);
+    Ok(admissions
+        .admit(&nr.name_any(), cost, fleet, &pending, budget)
+        .err())
+}
+
 pub(super) async fn attempt_provision(
     nr: &NodeRequest,
     ctx: &ControllerContext,
@@ -164,9 +326,7 @@ pub(super) async fn attempt_provision(
         .as_deref()
         .ok_or(ControllerError::MissingName("NodeRequest"))?;
     let offerings = ctx.provider.offerings().await;
-    let Some(offering) = offerings.iter().find(|o| {
-        o.instance_type == nr.spec.target_offering && o.location.region == nr.spec.location
-    }) else {
+    let Some(offering) = requested_offering(nr, &offerings) else {
         warn!(
             name,
             target_offering = %nr.spec.target_offering,
@@ -177,21 +337,77 @@ pub(super) async fn attempt_provision(
     };
 
     let pool_info = get_pool_info(&ctx.client, nr).await?;
-    // Build generic labels (provider-agnostic).
-    let config = build_labels(nr, &pool_info.labels);
 
     // Resolve provider-specific config from CRDs at the controller level.
-    // The controller matches on the provider variant to decide which
-    // NodeClass CRD to fetch, keeping provider-specific types out of
-    // the generic Provider interface.
-    let provider_config = match &ctx.provider {
-        Provider::Hetzner(_) => {
-            let class_ref = pool_info
-                .node_class_ref
-                .as_ref()
-                .ok_or(ControllerError::Other(anyhow::anyhow!(
-                    "Hetzner pools require a nodeClassRef"
-                )))?;
+    let (provider_config, join_token) = resolve_provider_config(
+        ctx,
+        pool_info.node_class_ref.as_ref(),
+        pool_info.join_strategy,
+        &nr.spec.node_id,
+    )
+    .await?;
+
+    // Build the generic (provider-agnostic) instance config.
+    let (labels, taints) = pool_metadata(nr, &pool_info);
+    let config = InstanceConfig {
+        kubelet_args: pool_info.kubelet_args.clone(),
+        join_token,
+        join_strategy: pool_info.join_strategy,
+        ..build_labels(nr, labels, taints)
+    };
+
+    info!(
+        name,
+        offering = %offering.instance_type,
+        node_id = %nr.spec.node_id,
+        "requesting node from provider"
+    );
+
+    let result = ctx
+        .provider
+        .create(nr.spec.node_id.clone(), offering, &config, &provider_config)
+        .await;
+    ctx.offering_stats
+        .record_create(&ctx.client, offering.stats_key(), result.is_ok())
+        .await;
+
+    match result {
+        Ok(node_id) => {
+            debug!(name, node_id = %nr.spec.node_id, "provider accepted create request");
+            Ok(ProvisionOutcome::Created(node_id))
+        }
+        Err(ProviderError::OfferingUnavailable(reason)) => {
+            warn!(
+                name,
+                offering = %offering.instance_type,
+                reason = %reason,
+                "offering unavailable from provider"
+            );
+            Ok(ProvisionOutcome::OfferingUnavailable(reason))
+        }
+        Err(e) => Err(ControllerError::Other(e.into())),
+    }
+}
+
+/// Resolve provider-specific create config, and the node's join token if its
+/// user-data wants one under the pool's `join_strategy`, from the pool's
+/// NodeClass.
+///
+/// The `nodeClassRef` kind decides which NodeClass CRD to fetch, keeping
+/// provider-specific types out of the generic Provider interface. Pools
+/// without a NodeClass get `ProviderCreateConfig::None`; providers that
+/// need one reject it at create time.
+async fn resolve_provider_config(
+    ctx: &ControllerContext,
+    class_ref: Option<&NodeClassRef>,
+    join_strategy: Option<JoinStrategy>,
+    node_id: &str,
+) -> Result<(ProviderCreateConfig, Option<String>), ControllerError> {
+    let Some(class_ref) = class_ref else {
+        return Ok((ProviderCreateConfig::None, None));
+    };
+    match class_ref.kind.as_str() {
+        "HetznerNodeClass" => {
             let node_class = kube::Api::<HetznerNodeClass>::all(ctx.client.clone())
                 .get(&class_ref.name)
                 .await
@@ -205,11 +421,23 @@ pub(super) async fn attempt_provision(
             let user_data = node_class
                 .spec
                 .user_data
-                .resolve(&ctx.client, offering, &config.labels)
+                .resolve(&ctx.client)
                 .await
                 .map_err(|e| {
                     ControllerError::Other(anyhow::anyhow!("user-data resolution failed: {e}"))
                 })?;
+            let join_token = if user_data.uses_join_token(join_strategy) {
+                node_class
+                    .spec
+                    .user_data
+                    .join_token(&ctx.client, node_id, ctx.clock.now())
+                    .await
+                    .map_err(|e| {
+                        ControllerError::Other(anyhow::anyhow!("join token resolution failed: {e}"))
+                    })?
+            } else {
+                None
+            };
 
             if node_class.spec.network_ids.is_empty() {
                 warn!(
@@ -217,7 +445,7 @@ pub(super) async fn attempt_provision(
                 );
             }
 
-            ProviderCreateConfig::Hetzner(HetznerCreateConfig {
+            let create_config = ProviderCreateConfig::Hetzner(Box::new(HetznerCreateConfig {
                 user_data: Some(user_data),
                 image: node_class.spec.image.clone(),
                 ssh_key_names: node_class.spec.ssh_key_names.clone(),
@@ -226,44 +454,25 @@ pub(super) async fn attempt_provision(
                 enable_ipv4: node_class.spec.enable_ipv4,
                 enable_ipv6: node_class.spec.enable_ipv6,
                 hetzner_labels: node_class.spec.hetzner_labels.clone(),
-            })
+            }));
+            Ok((create_config, join_token))
         }
-        Provider::Kwok(_) | Provider::Fake(_) => ProviderCreateConfig::None,
-    };
-
-    info!(
-        name,
-        offering = %offering.instance_type,
-        node_id = %nr.spec.node_id,
-        "requesting node from provider"
-    );
-
-    match ctx
-        .provider
-        .create(nr.spec.node_id.clone(), offering, &config, &provider_config)
-        .await
-    {
-        Ok(_) => {
-            debug!(name, node_id = %nr.spec.node_id, "provider accepted create request");
-            Ok(ProvisionOutcome::Created)
-        }
-        Err(ProviderError::OfferingUnavailable(reason)) => {
-            warn!(
-                name,
-                offering = %offering.instance_type,
-                reason = %reason,
-                "offering unavailable from provider"
-            );
-            Ok(ProvisionOutcome::OfferingUnavailable)
-        }
-        Err(e) => Err(ControllerError::Other(e.into())),
+        other => Err(ControllerError::Other(anyhow::anyhow!(
+            "unsupported nodeClassRef kind {other:?} for NodeClass {:?}",
+            class_ref.name
+        ))),
     }
 }
 
-/// Build the generic InstanceConfig (labels only) for a NodeRequest.
+/// Build the generic InstanceConfig (labels, the pool's taints and the
+/// startup taint) for a NodeRequest.
 ///
 /// Pure function — no I/O, no ControllerContext dependency.
-fn build_labels(nr: &NodeRequest, pool_labels: &BTreeMap<String, String>) -> InstanceConfig {
+fn build_labels(
+    nr: &NodeRequest,
+    pool_labels: &BTreeMap<String, String>,
+    pool_taints: &[Taint],
+) -> InstanceConfig {
     let mut labels = pool_labels.clone();
     labels.insert(MANAGED_BY_LABEL.to_string(), MANAGED_BY_VALUE.to_string());
     if let Some(pool_name) = nr
@@ -288,10 +497,90 @@ fn build_labels(nr: &NodeRequest, pool_labels: &BTreeMap<String, String>) -> Ins
         nr.spec.target_offering.0.clone(),
     );
 
-    InstanceConfig { labels }
+    let mut taints = pool_taints.to_vec();
+    taints.push(Taint {
+        key: STARTUP_TAINT_KEY.to_string(),
+        effect: "NoExecute".to_string(),
+        ..Default::default()
+    });
+
+    InstanceConfig {
+        labels,
+        taints,
+        kubelet_args: vec![],
+        join_token: None,
+        join_strategy: None,
+    }
+}
+
+/// Remove the node-cleanup finalizer from a NodeRequest, letting its
+/// deletion complete. Safe if the finalizer is already absent.
+pub(super) async fn remove_nr_finalizer(client: &Client, name: &str) -> Result<(), kube::Error> {
+    let api: Api<NodeRequest> = Api::all(client.clone());
+    let nr = api.get(name).await?;
+    let finalizers: Vec<String> = nr
+        .metadata
+        .finalizers
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|f| f != NR_FINALIZER)
+        .collect();
+    let patch = serde_json::json!({
+        "metadata": {
+            "finalizers": if finalizers.is_empty() { serde_json::Value::Null } else { serde_json::json!(finalizers) }
+        }
+    });
+    api.patch(name, &PatchParams::apply("growthrs"), &Patch::Merge(patch))
+        .await?;
+    Ok(())
+}
+
+/// Cordon a node and evict its pool pods through the Eviction API, as far as
+/// their PodDisruptionBudgets allow. Returns whether it is drained: no pool
+/// pods other than DaemonSet ones are left on it. A node that never
+/// registered is drained.
+pub(super) async fn drain_node(client: &Client, node_name: &str) -> Result<bool, kube::Error> {
+    let nodes: Api<Node> = Api::all(client.clone());
+    let cordon = serde_json::json!({ "spec": { "unschedulable": true } });
+    match nodes
+        .patch(node_name, &PatchParams::default(), &Patch::Merge(cordon))
+        .await
+    {
+        Ok(_) => {}
+        Err(kube::Error::Api(ref resp)) if resp.code == 404 => return Ok(true),
+        Err(e) => return Err(e),
+    }
+    let pods: Api<Pod> = Api::all(client.clone());
+    let lp = ListParams::default().fields(&format!("spec.nodeName={node_name}"));
+    let pods = pods.list(&lp).await?.items;
+    let displaced = displaced_pods(&HashSet::from([node_name]), &pods);
+    let mut budgets = if displaced.is_empty() {
+        PdbBudgets::default()
+    } else {
+        PdbBudgets::list(client).await?
+    };
+    for pod in displaced {
+        if !budgets.take(pod) {
+            debug!(
+                node = node_name,
+                pod = pod.metadata.name.as_deref().unwrap_or("<unknown>"),
+                pdbs = ?budgets.blocking(pod),
+                "disruption budget spent, leaving pod for the next drain"
+            );
+            continue;
+        }
+        if evict_pod(client, pod).await? {
+            info!(
+                node = node_name,
+                pod = pod.metadata.name.as_deref().unwrap_or("<unknown>"),
+                "evicted pod from deleted NodeRequest's node"
+            );
+        }
+    }
+    Ok(is_node_idle(node_name, &pods))
 }
 
-pub(super) async fn delete_node_request(client: Client, name: &str) -> Result<(), kube::Error> {
+pub(crate) async fn delete_node_request(client: Client, name: &str) -> Result<(), kube::Error> {
     let api: Api<NodeRequest> = Api::all(client);
     match api.delete(name, &Default::default()).await {
         Ok(_) => Ok(()),
@@ -320,26 +609,81 @@ mod tests {
                 node_id: "node-1".into(),
                 target_offering: InstanceType("cpx22".into()),
                 location: Region("fsn1".into()),
+                zone: None,
                 resources: Resources {
-                    cpu: 3,
+                    cpu_millis: 3000,
                     memory_mib: 4096,
                     ephemeral_storage_gib: None,
                     gpu: 0,
                     gpu_model: None,
+                    arch: None,
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
                 },
+                provider: None,
+                claimed_pods: vec![],
+                labels: None,
+                taints: None,
             },
             status: Some(NodeRequestStatus {
                 phase: NodeRequestPhase::Pending,
                 events: vec![],
                 last_transition_time: None,
+                node_id: None,
+                conditions: vec![],
             }),
         }
     }
 
+    #[test]
+    fn fleet_cost_counts_nodes_and_requests_still_provisioning() {
+        let offering = |name: &str, cost: f64| Offering {
+            instance_type: InstanceType(name.into()),
+            provider: "fake".into(),
+            resources: test_nr().spec.resources,
+            cost_per_hour: cost,
+            interruption_risk: 0.0,
+            location: crate::offering::Location {
+                region: Region("fsn1".into()),
+                zone: None,
+            },
+        };
+        let offerings = [offering("cpx22", 0.01), offering("cx42", 0.04)];
+        let node = |name: &str, nr: &str| Node {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                labels: Some(BTreeMap::from([
+                    (INSTANCE_TYPE_LABEL.to_string(), "cx42".to_string()),
+                    (
+                        crate::offering::REGION_LABEL.to_string(),
+                        "fsn1".to_string(),
+                    ),
+                    (NODE_REQUEST_LABEL.to_string(), nr.to_string()),
+                ])),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let request = |name: &str, phase: NodeRequestPhase| {
+            let mut nr = test_nr();
+            nr.metadata.name = Some(name.into());
+            nr.status.as_mut().unwrap().phase = phase;
+            nr
+        };
+        let nodes = [node("n-1", "nr-joined")];
+        let nrs = [
+            // Already counted through its node.
+            request("nr-joined", NodeRequestPhase::Provisioning),
+            request("nr-booting", NodeRequestPhase::Provisioning),
+            request("nr-held", NodeRequestPhase::Pending),
+        ];
+        assert!((fleet_cost(&nodes, &nrs, &offerings) - 0.05).abs() < 1e-9);
+    }
+
     #[test]
     fn build_labels_includes_managed_by() {
         let nr = test_nr();
-        let config = build_labels(&nr, &BTreeMap::new());
+        let config = build_labels(&nr, &BTreeMap::new(), &[]);
         assert_eq!(
             config.labels.get(MANAGED_BY_LABEL).unwrap(),
             MANAGED_BY_VALUE
@@ -349,7 +693,7 @@ mod tests {
     #[test]
     fn build_labels_includes_instance_type() {
         let nr = test_nr();
-        let config = build_labels(&nr, &BTreeMap::new());
+        let config = build_labels(&nr, &BTreeMap::new(), &[]);
         assert_eq!(
             config
                 .labels
@@ -364,14 +708,53 @@ mod tests {
         let nr = test_nr();
         let mut pool_labels = BTreeMap::new();
         pool_labels.insert("custom-label".into(), "custom-value".into());
-        let config = build_labels(&nr, &pool_labels);
+        let config = build_labels(&nr, &pool_labels, &[]);
         assert_eq!(config.labels.get("custom-label").unwrap(), "custom-value");
     }
 
+    #[test]
+    fn build_labels_adds_pool_taints_before_startup_taint() {
+        let nr = test_nr();
+        let gpu = Taint {
+            key: "gpu".into(),
+            value: Some("true".into()),
+            effect: "NoSchedule".into(),
+            ..Default::default()
+        };
+        let config = build_labels(&nr, &BTreeMap::new(), std::slice::from_ref(&gpu));
+        assert_eq!(config.taints.len(), 2);
+        assert_eq!(config.taints[0], gpu);
+        assert_eq!(config.taints[1].key, STARTUP_TAINT_KEY);
+    }
+
+    #[test]
+    fn recorded_pool_metadata_wins_over_the_pool() {
+        let pool = PoolInfo {
+            labels: BTreeMap::from([("team".into(), "now".into())]),
+            taints: vec![],
+            kubelet_args: vec![],
+            join_strategy: None,
+            node_class_ref: None,
+        };
+        let mut nr = test_nr();
+        assert_eq!(pool_metadata(&nr, &pool).0["team"], "now");
+
+        let gpu = Taint {
+            key: "gpu".into(),
+            effect: "NoSchedule".into(),
+            ..Default::default()
+        };
+        nr.spec.labels = Some(BTreeMap::from([("team".into(), "at-create".into())]));
+        nr.spec.taints = Some(vec![gpu.clone()]);
+        let (labels, taints) = pool_metadata(&nr, &pool);
+        assert_eq!(labels["team"], "at-create");
+        assert_eq!(taints, std::slice::from_ref(&gpu));
+    }
+
     #[test]
     fn build_labels_includes_node_request_name() {
         let nr = test_nr();
-        let config = build_labels(&nr, &BTreeMap::new());
+        let config = build_labels(&nr, &BTreeMap::new(), &[]);
         assert_eq!(
             config
                 .labels
@@ -1,3 +1,4 @@
+pub(crate) mod bootstrap;
 pub(crate) mod helpers;
 
 use std::sync::Arc;
@@ -5,22 +6,36 @@ use std::time::Duration;
 
 use futures_util::StreamExt;
 use kube::Api;
-use kube::runtime::controller::Action;
+use kube::runtime::controller::{self, Action};
 use kube::runtime::{Controller, watcher};
 use tracing::{debug, error, info, instrument, warn};
 
 use crate::config::ControllerContext;
-use crate::controller::node_removal::helpers::create_node_removal_request;
-use crate::controller::node_requests::helpers::delete_node_request;
-use crate::controller::{ControllerError, is_kube_not_found, update_node_request_phase};
+use crate::controller::karpenter::NODECLAIM_LABEL;
+use crate::controller::node_removal::helpers::{
+    create_node_removal_request, delete_kubernetes_node,
+};
+use crate::controller::node_requests::helpers::{
+    delete_node_request, drain_node, remove_nr_finalizer,
+};
+use crate::controller::shard::UNOWNED_REQUEUE;
+use crate::controller::{
+    ControllerError, is_kube_not_found, update_node_request_conditions, update_node_request_status,
+};
+use crate::offering::{CONSOLIDATION_LABEL, EVACUATION_LABEL, NR_FINALIZER, UNCLAIMED_ANNOTATION};
 use crate::providers::provider::{NodeId, ProviderStatus};
 use crate::resources::node_removal_request::NodeRemovalRequestPhase;
-use crate::resources::node_request::{NodeRequest, NodeRequestPhase};
+use crate::resources::node_request::{NodeRequest, NodeRequestConditionType, NodeRequestPhase};
 
-use helpers::{ProvisionOutcome, attempt_provision};
+use helpers::{ProvisionOutcome, attempt_provision, over_fleet_budget};
 
 const PROVISIONING_REQUEUE: Duration = Duration::from_secs(60);
-const NR_ERROR_REQUEUE: Duration = Duration::from_secs(5);
+const DRAIN_REQUEUE: Duration = Duration::from_secs(10);
+/// Reason on the `Accepted` condition of a NodeRequest held by the
+/// cluster-wide hourly budget.
+pub const BUDGET_HOLD_REASON: &str = "PendingBudget";
+/// Key of this controller's failures in the shared error backoff.
+const CONTROLLER: &str = "node_request";
 
 /// Run the per-object NodeRequest controller.
 pub(crate) async fn run_node_request_controller(
@@ -28,14 +43,30 @@ pub(crate) async fn run_node_request_controller(
 ) -> Result<(), ControllerError> {
     let nrs: Api<NodeRequest> = Api::all(ctx.client.clone());
     let config = watcher::Config::default();
-    let mut stream = std::pin::pin!(Controller::new(nrs, config).run(
-        crate::controller::node_requests::reconcile_node_request,
-        crate::controller::node_requests::error_policy,
-        ctx.clone(),
-    ));
+    let mut stream = std::pin::pin!(
+        Controller::new(nrs, config)
+            .with_config(controller::Config::default().concurrency(ctx.requeue.concurrency))
+            .run(
+                |nr, ctx: Arc<ControllerContext>| async move {
+                    let metrics = &ctx.metrics.reconciles;
+                    metrics
+                        .measure(CONTROLLER, reconcile_node_request(nr, ctx.clone()))
+                        .await
+                },
+                crate::controller::node_requests::error_policy,
+                ctx.clone(),
+            )
+    );
     while let Some(result) = stream.next().await {
-        let (obj, _) = result.map_err(ControllerError::from_controller_error)?;
-        debug!(name = %obj.name, "reconciled NodeRequest")
+        match result {
+            Ok((obj, _)) => {
+                ctx.requeue.errors.succeeded(CONTROLLER, &obj.name);
+                debug!(name = %obj.name, "reconciled NodeRequest")
+            }
+            // Already logged and requeued with backoff by `error_policy`.
+            Err(controller::Error::ReconcilerFailed(..)) => {}
+            Err(e) => return Err(ControllerError::from_controller_error(e)),
+        }
     }
     Ok(())
 }
@@ -51,7 +82,23 @@ pub(super) async fn reconcile_node_request(
         .as_deref()
         .ok_or(ControllerError::MissingName("NodeRequest"))?;
 
-    let (next_phase, action) = match decide_phase(&nr, &ctx).await {
+    if !ctx.shard.owns_node_request(&ctx.client, &nr).await? {
+        return Ok(Action::requeue(UNOWNED_REQUEUE));
+    }
+
+    if nr.metadata.deletion_timestamp.is_some() {
+        let has_finalizer = nr
+            .metadata
+            .finalizers
+            .as_ref()
+            .is_some_and(|f| f.iter().any(|s| s == NR_FINALIZER));
+        if !has_finalizer {
+            return Ok(Action::await_change());
+        }
+        return finalize_node_request(&nr, name, &ctx).await;
+    }
+
+    let (transition, action) = match decide_phase(&nr, &ctx).await {
         Ok(result) => result,
         Err(ControllerError::Kube(ref e)) if is_kube_not_found(e) => {
             warn!(
@@ -63,10 +110,14 @@ pub(super) async fn reconcile_node_request(
         Err(e) => return Err(e),
     };
 
-    if let Some(phase) = next_phase {
-        info!(name, from = %nr.phase(), to = %phase, "transitioning NodeRequest");
+    if let Some(transition) = transition {
+        info!(name, from = %nr.phase(), to = %transition.phase, reason = %transition.reason, "transitioning NodeRequest");
         let now = ctx.clock.now();
-        if let Err(e) = update_node_request_phase(&ctx.client, name, phase, now).await {
+        let mut status = nr.transitioned(transition.phase, Some(transition.reason), now);
+        if let Some(node_id) = transition.node_id {
+            status.node_id = Some(node_id.0);
+        }
+        if let Err(e) = update_node_request_status(&ctx.client, name, status).await {
             if is_kube_not_found(&e) {
                 warn!(
                     name,
@@ -80,10 +131,62 @@ pub(super) async fn reconcile_node_request(
     Ok(action)
 }
 
+/// Run the node-cleanup finalizer of a NodeRequest being deleted, by hand or
+/// by garbage collection of its NodePool. If a node was created for it, the
+/// node is cordoned and drained, deleted at the provider and removed from
+/// the cluster before the finalizer is lifted.
+async fn finalize_node_request(
+    nr: &NodeRequest,
+    name: &str,
+    ctx: &ControllerContext,
+) -> Result<Action, ControllerError> {
+    if matches!(
+        nr.phase(),
+        NodeRequestPhase::Provisioning | NodeRequestPhase::Ready
+    ) {
+        let node_id = &nr.spec.node_id;
+        if !drain_node(&ctx.client, node_id).await? {
+            info!(name, node = %node_id, "NodeRequest being deleted, waiting for its node to drain");
+            return Ok(Action::requeue(DRAIN_REQUEUE));
+        }
+        info!(name, node = %node_id, "NodeRequest being deleted, removing its node");
+        if let Err(e) = ctx
+            .removals
+            .delete(ctx.provider.as_ref(), NodeId(node_id.clone()))
+            .await
+        {
+            warn!(name, %e, "provider delete during finalizer cleanup failed, will retry");
+            return Ok(Action::requeue(ctx.requeue.errors.initial()));
+        }
+        delete_kubernetes_node(&ctx.client, node_id).await?;
+    }
+    remove_nr_finalizer(&ctx.client, name).await?;
+    Ok(Action::await_change())
+}
+
+/// A phase change decided by [`decide_phase`], recorded as a status event.
+#[derive(Debug, PartialEq)]
+struct Transition {
+    phase: NodeRequestPhase,
+    reason: String,
+    /// Provider's ID for the node, when the transition learned it.
+    node_id: Option<NodeId>,
+}
+
+impl Transition {
+    fn to(phase: NodeRequestPhase, reason: impl Into<String>) -> Self {
+        Self {
+            phase,
+            reason: reason.into(),
+            node_id: None,
+        }
+    }
+}
+
 async fn decide_phase(
     nr: &NodeRequest,
     ctx: &ControllerContext,
-) -> Result<(Option<NodeRequestPhase>, Action), ControllerError> {
+) -> Result<(Option<Transition>, Action), ControllerError> {
     let name = nr
         .metadata
         .name
@@ -91,24 +194,68 @@ async fn decide_phase(
         .ok_or(ControllerError::MissingName("NodeRequest"))?;
 
     match nr.phase() {
-        NodeRequestPhase::Pending => match attempt_provision(nr, ctx).await? {
-            ProvisionOutcome::Created => Ok((
-                Some(NodeRequestPhase::Provisioning),
-                Action::requeue(PROVISIONING_REQUEUE),
-            )),
-            ProvisionOutcome::NoMatchingOffering | ProvisionOutcome::OfferingUnavailable => {
-                Ok((Some(NodeRequestPhase::Unmet), Action::await_change()))
+        NodeRequestPhase::Pending => {
+            if let Some(exceeded) = over_fleet_budget(nr, ctx).await? {
+                let message = exceeded.to_string();
+                let held = nr.status.as_ref().and_then(|s| {
+                    s.condition(NodeRequestConditionType::Accepted)
+                        .filter(|c| c.reason == BUDGET_HOLD_REASON && c.message == message)
+                });
+                if held.is_none() {
+                    info!(
+                        name,
+                        %exceeded,
+                        fleet_cost = exceeded.fleet,
+                        "NodeRequest held by the hourly budget"
+                    );
+                    let conditions =
+                        nr.held_conditions(BUDGET_HOLD_REASON, &message, ctx.clock.now());
+                    update_node_request_conditions(&ctx.client, nr, conditions).await?;
+                }
+                return Ok((None, Action::requeue(ctx.requeue.interval)));
+            }
+            match attempt_provision(nr, ctx).await? {
+                ProvisionOutcome::Created(node_id) => Ok((
+                    Some(Transition {
+                        node_id: Some(node_id),
+                        ..Transition::to(NodeRequestPhase::Provisioning, "provider accepted create")
+                    }),
+                    Action::requeue(PROVISIONING_REQUEUE),
+                )),
+                ProvisionOutcome::NoMatchingOffering => Ok((
+                    Some(Transition::to(
+                        NodeRequestPhase::Unmet,
+                        "no matching offering in the provider catalogue",
+                    )),
+                    Action::await_change(),
+                )),
+                ProvisionOutcome::OfferingUnavailable(reason) => Ok((
+                    Some(Transition::to(
+                        NodeRequestPhase::Unmet,
+                        format!("offering unavailable: {reason}"),
+                    )),
+                    Action::await_change(),
+                )),
             }
-        },
+        }
         NodeRequestPhase::Provisioning => {
             let now = ctx.clock.now();
             match ctx.provider.status(&NodeId(nr.spec.node_id.clone())).await {
-                Ok(ProviderStatus::Failed { .. })
-                | Ok(ProviderStatus::Removing)
-                | Ok(ProviderStatus::NotFound) => {
-                    Ok((Some(NodeRequestPhase::Unmet), Action::await_change()))
-                }
-                Ok(ProviderStatus::Creating) | Ok(ProviderStatus::Running) => {
+                Ok(ProviderStatus::Failed { reason }) => Ok((
+                    Some(Transition::to(
+                        NodeRequestPhase::Unmet,
+                        format!("provider reported failure: {reason}"),
+                    )),
+                    Action::await_change(),
+                )),
+                Ok(ProviderStatus::Removing) | Ok(ProviderStatus::NotFound) => Ok((
+                    Some(Transition::to(
+                        NodeRequestPhase::Unmet,
+                        "instance gone at the provider",
+                    )),
+                    Action::await_change(),
+                )),
+                Ok(status @ (ProviderStatus::Creating | ProviderStatus::Running)) => {
                     if is_provisioning_expired(nr, ctx.provisioning_timeout, now) {
                         warn!(name, node_id = %nr.spec.node_id, "provisioning timeout exceeded, creating NRR for orphan node");
                         let pool = pool_name_from_nr(nr)?;
@@ -126,8 +273,28 @@ async fn decide_phase(
                             warn!(name, %e, "failed to create NRR for orphan node, will retry");
                             return Ok((None, Action::requeue(PROVISIONING_REQUEUE)));
                         }
-                        Ok((Some(NodeRequestPhase::Unmet), Action::await_change()))
+                        Ok((
+                            Some(Transition::to(
+                                NodeRequestPhase::Unmet,
+                                format!(
+                                    "provisioning timed out after {}s",
+                                    ctx.provisioning_timeout.as_secs()
+                                ),
+                            )),
+                            Action::await_change(),
+                        ))
                     } else {
+                        if matches!(status, ProviderStatus::Running)
+                            && !nr.condition_is_true(NodeRequestConditionType::Launched)
+                        {
+                            let conditions = nr.conditions_with(
+                                NodeRequestConditionType::Launched,
+                                "InstanceRunning",
+                                "provider reports the instance running",
+                                now,
+                            );
+                            update_node_request_conditions(&ctx.client, nr, conditions).await?;
+                        }
                         Ok((None, Action::requeue(PROVISIONING_REQUEUE)))
                     }
                 }
@@ -137,15 +304,30 @@ async fn decide_phase(
                 }
             }
         }
-        NodeRequestPhase::Ready => Ok((None, Action::await_change())),
+        NodeRequestPhase::Ready => {
+            let Some(ttl) = ctx.scale_down.ready_ttl.filter(|_| !outlives_ready_ttl(nr)) else {
+                return Ok((None, Action::await_change()));
+            };
+            let now = ctx.clock.now();
+            if is_ttl_expired(nr, ttl, now) {
+                // The node stays: lift the finalizer first so the deletion
+                // doesn't take it along.
+                info!(name, "Ready TTL expired, deleting NodeRequest");
+                remove_nr_finalizer(&ctx.client, name).await?;
+                delete_node_request(ctx.client.clone(), name).await?;
+                Ok((None, Action::await_change()))
+            } else {
+                Ok((None, Action::requeue(remaining_ttl(nr, ttl, now))))
+            }
+        }
         NodeRequestPhase::Unmet => {
             let now = ctx.clock.now();
-            if is_unmet_expired(nr, ctx.scale_down.unmet_ttl, now) {
+            if is_ttl_expired(nr, ctx.scale_down.unmet_ttl, now) {
                 info!(name, "Unmet TTL expired, deleting NodeRequest");
                 delete_node_request(ctx.client.clone(), name).await?;
                 Ok((None, Action::await_change()))
             } else {
-                let remaining = remaining_unmet_ttl(nr, ctx.scale_down.unmet_ttl, now);
+                let remaining = remaining_ttl(nr, ctx.scale_down.unmet_ttl, now);
                 Ok((None, Action::requeue(remaining)))
             }
         }
@@ -155,23 +337,44 @@ async fn decide_phase(
 pub(super) fn error_policy(
     nr: Arc<NodeRequest>,
     error: &ControllerError,
-    _ctx: Arc<ControllerContext>,
+    ctx: Arc<ControllerContext>,
 ) -> Action {
     match nr.metadata.name.as_deref() {
         Some(name) => {
-            warn!(name, %error, "NodeRequest reconcile failed, requeuing");
+            let delay = ctx.requeue.errors.failed(CONTROLLER, name);
+            warn!(name, %error, ?delay, "NodeRequest reconcile failed, requeuing");
+            Action::requeue(delay)
         }
         None => {
             error!(
                 "NodeRequest reconcile failed on a NodeRequest that lacks a name, indicating an issue with the CRD"
-            )
+            );
+            Action::requeue(ctx.requeue.errors.initial())
         }
     }
-    Action::requeue(NR_ERROR_REQUEUE)
+}
+
+/// Ready NodeRequests still read after their node joined: evacuation and
+/// consolidation replacements (their phase is the drain's progress), Karpenter
+/// NodeClaims' (the scanner provisions any claim without one) and unclaimed
+/// ones (claim GC removes their node once idle). They are kept past the Ready
+/// TTL.
+fn outlives_ready_ttl(nr: &NodeRequest) -> bool {
+    let labelled = nr.metadata.labels.as_ref().is_some_and(|l| {
+        l.contains_key(EVACUATION_LABEL)
+            || l.contains_key(CONSOLIDATION_LABEL)
+            || l.contains_key(NODECLAIM_LABEL)
+    });
+    let unclaimed = nr
+        .metadata
+        .annotations
+        .as_ref()
+        .is_some_and(|a| a.contains_key(UNCLAIMED_ANNOTATION));
+    labelled || unclaimed
 }
 
 /// Extract the pool name from the NodeRequest's ownerReference to NodePool.
-fn pool_name_from_nr(nr: &NodeRequest) -> Result<String, ControllerError> {
+pub(crate) fn pool_name_from_nr(nr: &NodeRequest) -> Result<String, ControllerError> {
     nr.metadata
         .owner_references
         .as_ref()
@@ -197,11 +400,11 @@ fn is_provisioning_expired(
     elapsed > k8s_openapi::jiff::SignedDuration::from_secs(timeout.as_secs() as i64)
 }
 
-/// Check whether an Unmet NodeRequest's TTL has expired.
+/// Check whether a terminal (Unmet or Ready) NodeRequest's TTL has expired.
 ///
-/// Uses `last_transition_time` (set when phase changes to Unmet).
+/// Uses `last_transition_time` (set when the phase last changed).
 /// Missing timestamp is treated as expired — GC immediately.
-pub(crate) fn is_unmet_expired(
+pub(crate) fn is_ttl_expired(
     nr: &NodeRequest,
     ttl: Duration,
     now: k8s_openapi::jiff::Timestamp,
@@ -217,12 +420,9 @@ pub(crate) fn is_unmet_expired(
     elapsed > k8s_openapi::jiff::SignedDuration::from_secs(ttl.as_secs() as i64)
 }
 
-/// Compute remaining time before the Unmet TTL expires, for requeue scheduling.
-fn remaining_unmet_ttl(
-    nr: &NodeRequest,
-    ttl: Duration,
-    now: k8s_openapi::jiff::Timestamp,
-) -> Duration {
+/// Compute remaining time before a terminal NodeRequest's TTL expires, for
+/// requeue scheduling.
+fn remaining_ttl(nr: &NodeRequest, ttl: Duration, now: k8s_openapi::jiff::Timestamp) -> Duration {
     let Some(transition_time) = nr
         .status
         .as_ref()
@@ -240,6 +440,8 @@ fn remaining_unmet_ttl(
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use http::{Request, Response};
     use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
     use kube::Client;
@@ -248,10 +450,16 @@ mod tests {
 
     use crate::clock::SystemClock;
     use crate::config::ScaleDownConfig;
+    use crate::controller::cost_guard::CostGuard;
+    use crate::controller::metrics::Metrics;
+    use crate::controller::node_removal::executor::RemovalExecutor;
+    use crate::controller::offering_stats::OfferingStatsStore;
+    use crate::controller::provider_health::ProviderHealthMonitor;
+    use crate::controller::shard::Shard;
     use crate::offering::Resources;
     use crate::offering::{InstanceType, Region};
     use crate::providers::fake::{FakeProvider, StatusBehavior};
-    use crate::providers::provider::{Provider, ProviderStatus};
+    use crate::providers::provider::ProviderStatus;
     use crate::resources::node_request::{
         NodeRequest, NodeRequestPhase, NodeRequestSpec, NodeRequestStatus,
     };
@@ -288,18 +496,28 @@ mod tests {
                 node_id: node_id.into(),
                 target_offering: InstanceType("cpx22".into()),
                 location: Region("eu-central".into()),
+                zone: None,
                 resources: Resources {
-                    cpu: 2,
+                    cpu_millis: 2000,
                     memory_mib: 4096,
                     ephemeral_storage_gib: None,
                     gpu: 0,
                     gpu_model: None,
+                    arch: None,
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
                 },
+                provider: None,
+                claimed_pods: vec![],
+                labels: None,
+                taints: None,
             },
             status: Some(NodeRequestStatus {
                 phase: NodeRequestPhase::Provisioning,
                 events: vec![],
                 last_transition_time: None,
+                node_id: None,
+                conditions: vec![],
             }),
         }
     }
@@ -307,9 +525,26 @@ mod tests {
     fn make_ctx_with_provider(provider: FakeProvider) -> ControllerContext {
         ControllerContext {
             client: mock_client(),
-            provider: Provider::Fake(provider),
+            provider: Arc::new(provider),
             provisioning_timeout: Duration::from_secs(300),
+            max_inventory_drift: 5,
+            offering_stats: OfferingStatsStore::disabled(),
+            cost_guard: CostGuard::disabled(),
+            budget_admissions: Default::default(),
+            metrics: Metrics::new(),
+            provider_reloader: None,
+            provider_health: ProviderHealthMonitor::new(),
+            shard: Shard::All,
             scale_down: ScaleDownConfig::default(),
+            removals: RemovalExecutor::new(Duration::ZERO),
+            requeue: Default::default(),
+            crds: Default::default(),
+            webhook: None,
+            tunables: Default::default(),
+            config_reloader: None,
+            audit_log: None,
+            dry_run: false,
+            karpenter_compat: false,
             clock: Arc::new(SystemClock),
         }
     }
@@ -355,19 +590,135 @@ mod tests {
         let ctx = make_ctx_with_provider(provider.clone());
 
         let nr = make_provisioning_nr("nr-failed", "node-4", Duration::from_secs(360));
-        let (phase, action) = decide_phase(&nr, &ctx).await.unwrap();
+        let (transition, action) = decide_phase(&nr, &ctx).await.unwrap();
 
-        assert_eq!(phase, Some(NodeRequestPhase::Unmet));
+        assert_eq!(
+            transition,
+            Some(Transition::to(
+                NodeRequestPhase::Unmet,
+                "provider reported failure: vm crashed"
+            ))
+        );
         assert_eq!(action, Action::await_change());
         assert!(provider.delete_calls().is_empty());
     }
 
+    #[tokio::test]
+    async fn ready_node_requests_wait_out_their_ttl() {
+        let ctx = make_ctx_with_provider(FakeProvider::new());
+        let mut nr = make_provisioning_nr("nr-ready", "node-6", Duration::from_secs(900));
+        let joined = k8s_openapi::jiff::Timestamp::now()
+            .checked_sub(k8s_openapi::jiff::SignedDuration::from_secs(600))
+            .unwrap();
+        nr.status = Some(NodeRequestStatus {
+            phase: NodeRequestPhase::Ready,
+            last_transition_time: Some(Time(joined)),
+            ..Default::default()
+        });
+
+        let (transition, action) = decide_phase(&nr, &ctx).await.unwrap();
+        assert_eq!(transition, None);
+        // Requeued for the remaining 50 minutes of the default 1h TTL.
+        assert!(
+            [2999, 3000]
+                .map(|s| Action::requeue(Duration::from_secs(s)))
+                .contains(&action),
+            "{action:?}"
+        );
+
+        // Evacuation replacements are still read after joining.
+        nr.metadata.labels = Some(BTreeMap::from([(
+            EVACUATION_LABEL.to_string(),
+            "web.1".into(),
+        )]));
+        let (_, action) = decide_phase(&nr, &ctx).await.unwrap();
+        assert_eq!(action, Action::await_change());
+    }
+
+    /// A NodeClaim's NodeRequest outlives the Ready TTL: deleting it would
+    /// leave the claim without one, and the Karpenter scanner would
+    /// provision it a second node.
+    #[tokio::test]
+    async fn nodeclaim_node_requests_outlive_the_ready_ttl() {
+        let ctx = make_ctx_with_provider(FakeProvider::new());
+        let mut nr = make_provisioning_nr("nr-claim", "node-7", Duration::from_secs(3 * 3600));
+        let joined = k8s_openapi::jiff::Timestamp::now()
+            .checked_sub(k8s_openapi::jiff::SignedDuration::from_secs(2 * 3600))
+            .unwrap();
+        nr.status = Some(NodeRequestStatus {
+            phase: NodeRequestPhase::Ready,
+            last_transition_time: Some(Time(joined)),
+            ..Default::default()
+        });
+        nr.metadata.labels = Some(BTreeMap::from([(
+            NODECLAIM_LABEL.to_string(),
+            "default-abcde".into(),
+        )]));
+
+        // Neither deleted (the mock client would fail the calls) nor requeued
+        // towards a deletion.
+        let (transition, action) = decide_phase(&nr, &ctx).await.unwrap();
+        assert_eq!(transition, None);
+        assert_eq!(action, Action::await_change());
+    }
+
+    /// Deleting a Provisioning NodeRequest whose node never registered skips
+    /// the drain, deletes the instance at the provider and lifts the
+    /// finalizer.
+    #[tokio::test]
+    async fn deleted_node_request_takes_its_node_with_it() {
+        let (mock_svc, mut handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let provider = FakeProvider::new();
+        let mut ctx = make_ctx_with_provider(provider.clone());
+        ctx.client = Client::new(mock_svc, "default");
+
+        let mut nr = make_provisioning_nr("nr-deleted", "node-5", Duration::from_secs(60));
+        nr.metadata.deletion_timestamp = Some(Time(k8s_openapi::jiff::Timestamp::now()));
+        nr.metadata.finalizers = Some(vec![NR_FINALIZER.to_string()]);
+        let nr_json = serde_json::to_vec(&nr).unwrap();
+
+        let server = tokio::spawn(async move {
+            let mut seen = Vec::new();
+            while let Some((request, send)) = handle.next_request().await {
+                let path = request.uri().path().to_string();
+                seen.push(format!("{} {path}", request.method()));
+                let (status, body) = if path.contains("/nodes/") {
+                    let body = r#"{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","reason":"NotFound","code":404}"#;
+                    (404, body.as_bytes().to_vec())
+                } else {
+                    (200, nr_json.clone())
+                };
+                send.send_response(
+                    Response::builder()
+                        .status(status)
+                        .header("content-type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                );
+            }
+            seen
+        });
+
+        let action = reconcile_node_request(Arc::new(nr), Arc::new(ctx))
+            .await
+            .unwrap();
+        assert_eq!(action, Action::await_change());
+        assert_eq!(provider.delete_calls().len(), 1);
+        assert_eq!(provider.delete_calls()[0].node_id, NodeId("node-5".into()));
+
+        let seen = server.await.unwrap();
+        assert_eq!(seen.len(), 4, "{seen:?}");
+        assert!(seen[0].starts_with("PATCH") && seen[0].ends_with("/nodes/node-5"));
+        assert!(seen[1].starts_with("DELETE") && seen[1].ends_with("/nodes/node-5"));
+        assert!(seen[3].starts_with("PATCH") && seen[3].ends_with("/noderequests/nr-deleted"));
+    }
+
     /// When the kube API returns a 500 for the NodePool GET (e.g. network
     /// blip), `attempt_provision` propagates the error so the controller
     /// can requeue and retry rather than silently provisioning with no config.
     #[tokio::test]
     async fn provision_returns_error_on_pool_fetch_failure() {
-        use crate::offering::{InstanceType, Location, Offering, Region, Zone};
+        use crate::testing::offering;
         use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
 
         type ApiServerHandle = tower_test::mock::Handle<Request<Body>, Response<Body>>;
@@ -378,27 +729,31 @@ mod tests {
         }
 
         let (client, mut handle) = mock_client_with_handle();
-        let provider = FakeProvider::new().with_offerings(vec![Offering {
-            instance_type: InstanceType("cpx22".into()),
-            resources: Resources {
-                cpu: 2,
-                memory_mib: 4096,
-                ephemeral_storage_gib: None,
-                gpu: 0,
-                gpu_model: None,
-            },
-            cost_per_hour: 0.01,
-            location: Location {
-                region: Region("eu-central".into()),
-                zone: Some(Zone("fsn1-dc14".into())),
-            },
-        }]);
+        let provider = FakeProvider::new()
+            .with_offerings(vec![offering("cpx22").with_zone("fsn1-dc14").build()]);
 
         let ctx = ControllerContext {
             client,
-            provider: Provider::Fake(provider),
+            provider: Arc::new(provider),
             provisioning_timeout: Duration::from_secs(300),
+            max_inventory_drift: 5,
+            offering_stats: OfferingStatsStore::disabled(),
+            cost_guard: CostGuard::disabled(),
+            budget_admissions: Default::default(),
+            metrics: Metrics::new(),
+            provider_reloader: None,
+            provider_health: ProviderHealthMonitor::new(),
+            shard: Shard::All,
             scale_down: ScaleDownConfig::default(),
+            removals: RemovalExecutor::new(Duration::ZERO),
+            requeue: Default::default(),
+            crds: Default::default(),
+            webhook: None,
+            tunables: Default::default(),
+            config_reloader: None,
+            audit_log: None,
+            dry_run: false,
+            karpenter_compat: false,
             clock: Arc::new(SystemClock),
         };
 
@@ -418,13 +773,21 @@ mod tests {
                 node_id: "growth-test-node".into(),
                 target_offering: InstanceType("cpx22".into()),
                 location: Region("eu-central".into()),
+                zone: None,
                 resources: Resources {
-                    cpu: 2,
+                    cpu_millis: 2000,
                     memory_mib: 4096,
                     ephemeral_storage_gib: None,
                     gpu: 0,
                     gpu_model: None,
+                    arch: None,
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
                 },
+                provider: None,
+                claimed_pods: vec![],
+                labels: None,
+                taints: None,
             },
             status: None,
         };
@@ -0,0 +1,128 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::{ObjectMeta, Patch, PatchParams};
+use kube::{Api, Client};
+use tracing::{debug, warn};
+
+use crate::controller::shard::Shard;
+use crate::offering::stats::{OfferingStats, PenaltyWeights};
+
+/// ConfigMap holding persisted offering statistics.
+pub const OFFERING_STATS_CONFIGMAP: &str = "growth-offering-stats";
+const OFFERING_STATS_KEY: &str = "stats.json";
+const FIELD_MANAGER: &str = "growth-offering-stats";
+
+/// Shared, optionally persisted, per-offering create/join statistics.
+///
+/// The NodeRequest controller records create outcomes, the node controller
+/// records join times, and the pod watcher turns them into solver penalties.
+/// With no namespace configured the stats live in memory only.
+pub struct OfferingStatsStore {
+    stats: Mutex<OfferingStats>,
+    weights: PenaltyWeights,
+    namespace: Option<String>,
+    configmap: String,
+}
+
+impl OfferingStatsStore {
+    pub fn new(namespace: Option<String>, weights: PenaltyWeights) -> Self {
+        Self {
+            stats: Mutex::new(OfferingStats::default()),
+            weights,
+            namespace,
+            configmap: OFFERING_STATS_CONFIGMAP.into(),
+        }
+    }
+
+    /// Persist to a ConfigMap of the shard's own, e.g.
+    /// `growth-offering-stats-1-of-3`, so replicas don't overwrite each other.
+    pub fn for_shard(mut self, shard: &Shard) -> Self {
+        if let Some(id) = shard.id() {
+            self.configmap = format!("{OFFERING_STATS_CONFIGMAP}-{id}");
+        }
+        self
+    }
+
+    /// In-memory store with penalties disabled.
+    pub fn disabled() -> Self {
+        Self::new(None, PenaltyWeights::default())
+    }
+
+    /// Solver penalties keyed by `Offering::stats_key()`.
+    pub fn penalties(&self) -> HashMap<String, f64> {
+        self.stats.lock().unwrap().penalties(self.weights)
+    }
+
+    pub fn snapshot(&self) -> OfferingStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Replace in-memory stats with the persisted copy, if any.
+    pub async fn load(&self, client: &Client) -> Result<(), kube::Error> {
+        let Some(ns) = &self.namespace else {
+            return Ok(());
+        };
+        let api: Api<ConfigMap> = Api::namespaced(client.clone(), ns);
+        let Some(cm) = api.get_opt(&self.configmap).await? else {
+            debug!(namespace = %ns, "no persisted offering stats");
+            return Ok(());
+        };
+        let raw = cm
+            .data
+            .as_ref()
+            .and_then(|d| d.get(OFFERING_STATS_KEY))
+            .map(String::as_str)
+            .unwrap_or("{}");
+        match serde_json::from_str::<OfferingStats>(raw) {
+            Ok(stats) => {
+                debug!(offerings = stats.records.len(), "loaded offering stats");
+                *self.stats.lock().unwrap() = stats;
+            }
+            Err(e) => warn!(error = %e, "ignoring unparseable offering stats"),
+        }
+        Ok(())
+    }
+
+    /// Record a create attempt and persist. Persistence failures are logged,
+    /// never propagated — stats are advisory.
+    pub async fn record_create(&self, client: &Client, key: String, succeeded: bool) {
+        self.stats.lock().unwrap().record_create(key, succeeded);
+        self.persist(client).await;
+    }
+
+    /// Record a create-to-Ready duration and persist.
+    pub async fn record_join(&self, client: &Client, key: String, join_secs: u64) {
+        self.stats.lock().unwrap().record_join(key, join_secs);
+        self.persist(client).await;
+    }
+
+    async fn persist(&self, client: &Client) {
+        let Some(ns) = &self.namespace else {
+            return;
+        };
+        let json =
+            serde_json::to_string(&self.snapshot()).expect("stats serialization cannot fail");
+        let cm = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(self.configmap.clone()),
+                namespace: Some(ns.clone()),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from([(OFFERING_STATS_KEY.into(), json)])),
+            ..Default::default()
+        };
+        let api: Api<ConfigMap> = Api::namespaced(client.clone(), ns);
+        if let Err(e) = api
+            .patch(
+                &self.configmap,
+                &PatchParams::apply(FIELD_MANAGER).force(),
+                &Patch::Apply(&cm),
+            )
+            .await
+        {
+            warn!(error = %e, "failed to persist offering stats");
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! PodDisruptionBudgets and the do-not-disrupt annotation, checked before
+//! growth-rs disrupts pods.
+//!
+//! The Eviction API already refuses evictions a budget doesn't allow, but
+//! firing them anyway makes a drain look like progress when it isn't. Drains
+//! (evacuation, consolidation, NodeRequest cleanup) take one disruption per
+//! evicted pod from a snapshot of the budgets and leave the rest for later
+//! scans; consolidation and idle-node removal don't start on nodes whose pods
+//! the budgets couldn't all lose at once. Pods and nodes annotated
+//! [`DO_NOT_DISRUPT_ANNOTATION`] are left alone altogether; consolidation
+//! also honours cluster-autoscaler's opt-outs ([`autoscaler_opt_out`]).
+
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kube::api::{ListParams, ObjectMeta};
+use kube::{Api, Client};
+
+use crate::offering::{
+    DO_NOT_DISRUPT_ANNOTATION, SAFE_TO_EVICT_ANNOTATION, SCALE_DOWN_DISABLED_ANNOTATION,
+};
+
+/// One PodDisruptionBudget's remaining allowance.
+#[derive(Debug, Clone)]
+struct Budget {
+    name: String,
+    namespace: String,
+    selector: Option<LabelSelector>,
+    allowed: i32,
+}
+
+impl Budget {
+    fn covers(&self, pod: &Pod) -> bool {
+        pod.metadata.namespace.as_deref().unwrap_or("default") == self.namespace
+            && self.selector.as_ref().is_some_and(|s| {
+                selector_matches(s, pod.metadata.labels.as_ref().unwrap_or(&BTreeMap::new()))
+            })
+    }
+}
+
+/// Remaining disruptions of the cluster's PodDisruptionBudgets, as of when
+/// they were listed.
+#[derive(Debug, Clone, Default)]
+pub struct PdbBudgets {
+    budgets: Vec<Budget>,
+}
+
+impl PdbBudgets {
+    pub fn new(pdbs: &[PodDisruptionBudget]) -> Self {
+        let budgets = pdbs
+            .iter()
+            .map(|pdb| Budget {
+                name: pdb.metadata.name.clone().unwrap_or_default(),
+                namespace: pdb
+                    .metadata
+                    .namespace
+                    .clone()
+                    .unwrap_or_else(|| "default".into()),
+                selector: pdb.spec.as_ref().and_then(|s| s.selector.clone()),
+                // A budget the disruption controller hasn't processed yet
+                // allows nothing.
+                allowed: pdb.status.as_ref().map_or(0, |s| s.disruptions_allowed),
+            })
+            .collect();
+        Self { budgets }
+    }
+
+    /// Every PodDisruptionBudget in the cluster.
+    pub async fn list(client: &Client) -> Result<Self, kube::Error> {
+        let api: Api<PodDisruptionBudget> = Api::all(client.clone());
+        let pdbs = api.list(&ListParams::default()).await?.items;
+        Ok(Self::new(&pdbs))
+    }
+
+    /// Whether evicting all of `pods` at once stays within every budget.
+    pub fn allows_all(&self, pods: &[&Pod]) -> bool {
+        self.budgets.iter().all(|b| {
+            let covered = pods.iter().filter(|p| b.covers(p)).count();
+            i32::try_from(covered).is_ok_and(|c| c <= b.allowed)
+        })
+    }
+
+    /// The budgets that stop `pod` being evicted right now.
+    pub fn blocking(&self, pod: &Pod) -> Vec<&str> {
+        self.budgets
+            .iter()
+            .filter(|b| b.allowed <= 0 && b.covers(pod))
+            .map(|b| b.name.as_str())
+            .collect()
+    }
+
+    /// Take one disruption for `pod` from every budget covering it. Returns
+    /// false, taking nothing, if any of them is spent.
+    pub fn take(&mut self, pod: &Pod) -> bool {
+        if !self.blocking(pod).is_empty() {
+            return false;
+        }
+        for budget in self.budgets.iter_mut().filter(|b| b.covers(pod)) {
+            budget.allowed -= 1;
+        }
+        true
+    }
+}
+
+/// Whether a pod or node is annotated [`DO_NOT_DISRUPT_ANNOTATION`]`: "true"`.
+pub fn do_not_disrupt(meta: &ObjectMeta) -> bool {
+    meta.annotations
+        .as_ref()
+        .and_then(|a| a.get(DO_NOT_DISRUPT_ANNOTATION))
+        .is_some_and(|v| v == "true")
+}
+
+/// Whether a pod is annotated [`SAFE_TO_EVICT_ANNOTATION`]`: "false"` or a
+/// node [`SCALE_DOWN_DISABLED_ANNOTATION`]`: "true"`, as workloads set up for
+/// cluster-autoscaler often are.
+pub fn autoscaler_opt_out(meta: &ObjectMeta) -> bool {
+    meta.annotations.as_ref().is_some_and(|a| {
+        a.get(SAFE_TO_EVICT_ANNOTATION)
+            .is_some_and(|v| v == "false")
+            || a.get(SCALE_DOWN_DISABLED_ANNOTATION)
+                .is_some_and(|v| v == "true")
+    })
+}
+
+/// Kubernetes label selector matching. Every `matchLabels` entry and
+/// `matchExpressions` requirement must hold; an empty selector matches
+/// everything.
+pub fn selector_matches(selector: &LabelSelector, labels: &BTreeMap<String, String>) -> bool {
+    let labels_ok = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .all(|(k, v)| labels.get(k) == Some(v));
+    let expressions_ok = selector.match_expressions.iter().flatten().all(|req| {
+        let values = req.values.as_deref().unwrap_or_default();
+        let value = labels.get(&req.key);
+        match req.operator.as_str() {
+            "In" => value.is_some_and(|v| values.contains(v)),
+            "NotIn" => value.is_none_or(|v| !values.contains(v)),
+            "Exists" => value.is_some(),
+            "DoesNotExist" => value.is_none(),
+            _ => false,
+        }
+    });
+    labels_ok && expressions_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use k8s_openapi::api::policy::v1::{PodDisruptionBudgetSpec, PodDisruptionBudgetStatus};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelectorRequirement;
+
+    fn pod(name: &str, app: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                namespace: Some("default".into()),
+                labels: Some(BTreeMap::from([("app".to_string(), app.to_string())])),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn pdb(name: &str, app: &str, allowed: i32) -> PodDisruptionBudget {
+        PodDisruptionBudget {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                namespace: Some("default".into()),
+                ..Default::default()
+            },
+            spec: Some(PodDisruptionBudgetSpec {
+                selector: Some(LabelSelector {
+                    match_labels: Some(BTreeMap::from([("app".to_string(), app.to_string())])),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            status: Some(PodDisruptionBudgetStatus {
+                disruptions_allowed: allowed,
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn selectors_match_labels_and_expressions() {
+        let labels = BTreeMap::from([
+            ("app".to_string(), "web".to_string()),
+            ("tier".to_string(), "frontend".to_string()),
+        ]);
+        let expr = |key: &str, op: &str, values: &[&str]| LabelSelector {
+            match_expressions: Some(vec![LabelSelectorRequirement {
+                key: key.into(),
+                operator: op.into(),
+                values: Some(values.iter().map(|v| v.to_string()).collect()),
+            }]),
+            ..Default::default()
+        };
+
+        assert!(selector_matches(&LabelSelector::default(), &labels));
+        assert!(selector_matches(
+            &expr("tier", "In", &["frontend", "api"]),
+            &labels
+        ));
+        assert!(!selector_matches(
+            &expr("tier", "NotIn", &["frontend"]),
+            &labels
+        ));
+        assert!(selector_matches(
+            &expr("zone", "DoesNotExist", &[]),
+            &labels
+        ));
+        assert!(!selector_matches(&expr("zone", "Exists", &[]), &labels));
+    }
+
+    #[test]
+    fn evictions_are_paced_by_remaining_disruptions() {
+        let mut budgets = PdbBudgets::new(&[pdb("web-pdb", "web", 1)]);
+        let (a, b, other) = (pod("a", "web"), pod("b", "web"), pod("c", "batch"));
+
+        assert!(budgets.allows_all(&[&a, &other]));
+        assert!(!budgets.allows_all(&[&a, &b]));
+
+        assert!(budgets.take(&a));
+        assert!(!budgets.take(&b));
+        assert_eq!(budgets.blocking(&b), ["web-pdb"]);
+        // Uncovered pods are never held back.
+        assert!(budgets.take(&other));
+    }
+}
@@ -0,0 +1,149 @@
+//! Which pending pods may trigger scaling.
+//!
+//! On a shared cluster growth-rs can be limited to one team's workloads:
+//! pods outside the allowed namespaces, in an excluded one, or not matching
+//! the label selector are never solved for, so they never cause a
+//! NodeRequest. The selector is passed to the API server as is, so it takes
+//! the usual `kubectl -l` syntax. Pods below a minimum priority (best-effort
+//! batch work) likewise queue for existing capacity rather than buy more.
+
+use std::collections::HashSet;
+
+use k8s_openapi::api::core::v1::Pod;
+
+use crate::controller::errors::ConfigError;
+
+/// The pods this controller provisions capacity for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PodScope {
+    /// Only these namespaces, when set.
+    namespaces: Option<HashSet<String>>,
+    /// Never these namespaces.
+    excluded_namespaces: HashSet<String>,
+    /// Label selector pods must match, e.g. `team=payments,tier!=batch`.
+    selector: Option<String>,
+    /// Lowest `spec.priority` that may trigger a new node.
+    min_priority: Option<i32>,
+}
+
+impl PodScope {
+    /// Build from the `GROWTH_POD_*` and `GROWTH_MIN_POD_PRIORITY` settings:
+    /// comma-separated namespace lists, a label selector and a priority
+    /// cutoff. A namespace both allowed and excluded is a contradiction, so
+    /// it is an error.
+    pub fn from_config(
+        namespaces: Option<&str>,
+        excluded_namespaces: Option<&str>,
+        selector: Option<String>,
+        min_priority: Option<i32>,
+    ) -> Result<Self, ConfigError> {
+        let namespaces = namespaces.map(split_list).filter(|n| !n.is_empty());
+        let excluded_namespaces = excluded_namespaces.map(split_list).unwrap_or_default();
+        if let Some(both) = namespaces
+            .iter()
+            .flatten()
+            .find(|n| excluded_namespaces.contains(*n))
+        {
+            return Err(ConfigError::Other(format!(
+                "namespace {both:?} is in both GROWTH_POD_NAMESPACES and GROWTH_POD_EXCLUDE_NAMESPACES"
+            )));
+        }
+        let selector = selector
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty());
+        Ok(Self {
+            namespaces,
+            excluded_namespaces,
+            selector,
+            min_priority,
+        })
+    }
+
+    /// Label selector to list pods with, if pods are scoped by label.
+    pub fn label_selector(&self) -> Option<&str> {
+        self.selector.as_deref()
+    }
+
+    /// Whether `pod`'s namespace and priority are in scope. Labels are left
+    /// to the API server, through [`Self::label_selector`]. Pods without a
+    /// resolved priority count as priority 0, as the scheduler treats them.
+    pub fn admits(&self, pod: &Pod) -> bool {
+        let namespace = pod.metadata.namespace.as_deref().unwrap_or("default");
+        let priority = pod.spec.as_ref().and_then(|s| s.priority).unwrap_or(0);
+        !self.excluded_namespaces.contains(namespace)
+            && self
+                .namespaces
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(namespace))
+            && self.min_priority.is_none_or(|min| priority >= min)
+    }
+}
+
+fn split_list(list: &str) -> HashSet<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use k8s_openapi::api::core::v1::PodSpec;
+    use kube::api::ObjectMeta;
+
+    fn pod_in(namespace: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some("p".into()),
+                namespace: Some(namespace.into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn namespaces_are_allowed_and_excluded() {
+        let everything = PodScope::default();
+        assert!(everything.admits(&pod_in("kube-system")));
+
+        let team = PodScope::from_config(Some("payments, checkout"), None, None, None).unwrap();
+        assert!(team.admits(&pod_in("payments")));
+        assert!(!team.admits(&pod_in("search")));
+
+        let shared =
+            PodScope::from_config(None, Some("kube-system,monitoring"), None, None).unwrap();
+        assert!(shared.admits(&pod_in("payments")));
+        assert!(!shared.admits(&pod_in("monitoring")));
+    }
+
+    #[test]
+    fn pods_below_the_minimum_priority_are_out_of_scope() {
+        let scope = PodScope::from_config(None, None, None, Some(100)).unwrap();
+        let with_priority = |priority| {
+            let mut pod = pod_in("default");
+            pod.spec = Some(PodSpec {
+                priority,
+                ..Default::default()
+            });
+            pod
+        };
+
+        assert!(scope.admits(&with_priority(Some(100))));
+        assert!(scope.admits(&with_priority(Some(1_000_000))));
+        assert!(!scope.admits(&with_priority(Some(-10))));
+        assert!(!scope.admits(&with_priority(None)));
+    }
+
+    #[test]
+    fn contradictory_or_empty_settings() {
+        assert!(PodScope::from_config(Some("a,b"), Some("b"), None, None).is_err());
+
+        let blank = PodScope::from_config(Some(""), Some(""), Some("  ".into()), None).unwrap();
+        assert_eq!(blank, PodScope::default());
+        assert_eq!(blank.label_selector(), None);
+    }
+}
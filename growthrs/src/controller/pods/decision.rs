@@ -1,17 +1,27 @@
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use k8s_openapi::api::core::v1::{Taint, Toleration};
 
 use tracing::{debug, warn};
 
-use crate::offering::{Offering, PodResources};
-use crate::optimiser::{BoundedOffering, ExistingNode, PlacementSolution, solve};
-use crate::resources::node_pool::{LocationConstraint, ServerTypeConfig};
+use crate::offering::{
+    InstanceType, Location, Offering, POOL_LABEL, PodId, PodResources, Resources,
+};
+use crate::optimiser::{
+    BoundedOffering, ExistingNode, PlacementSolution, QuotaBudget, SolveOptions, SolveStats,
+    SolverBackend,
+};
+use crate::providers::provider::Quota;
+use crate::resources::node_pool::{Headroom, LocationConstraint, ServerTypeConfig};
 
 /// Why a pod could not be assigned to any pool.
 #[derive(Debug)]
 pub enum PodPoolReason {
     /// Pod selected a pool that does not exist.
     PoolNotFound { requested: String },
-    /// Pod has no pool selector and no "default" pool exists.
+    /// Pod has no pool selector, no pool can run it and no "default" pool
+    /// exists.
     NoPoolSelector,
 }
 
@@ -20,7 +30,10 @@ impl std::fmt::Display for PodPoolReason {
         match self {
             Self::PoolNotFound { requested } => write!(f, "pool {requested:?} not found"),
             Self::NoPoolSelector => {
-                write!(f, "no pool selector and no \"default\" pool exists")
+                write!(
+                    f,
+                    "no pool selector, no pool fits the pod and no \"default\" pool exists"
+                )
             }
         }
     }
@@ -39,6 +52,11 @@ pub struct NodeRequestDemand {
     pub pool: String,
     pub pool_uid: String,
     pub target_offering: Offering,
+    /// UIDs of the pods the solver placed on this node.
+    pub claimed_pods: Vec<String>,
+    /// The pool's labels and taints, for the node to carry.
+    pub labels: BTreeMap<String, String>,
+    pub taints: Vec<Taint>,
 }
 
 /// Configuration for a single pool, derived from a NodePool CRD.
@@ -46,11 +64,31 @@ pub struct NodeRequestDemand {
 pub struct PoolConfig {
     pub name: String,
     pub uid: String,
+    /// Nodes the pool keeps at all times, whatever the demand.
+    pub min_nodes: u32,
+    /// Most nodes across all server types; `None` means no pool-wide limit.
+    pub max_nodes: Option<u32>,
+    /// Spare capacity kept provisioned ahead of demand.
+    pub headroom: Option<Headroom>,
     pub server_types: Vec<ServerTypeConfig>,
     /// Labels from the NodePool spec, applied to every node in this pool.
     pub labels: BTreeMap<String, String>,
+    /// Taints from the NodePool spec, applied to every node in this pool.
+    pub taints: Vec<Taint>,
     /// If set, only offerings matching at least one entry are eligible.
     pub locations: Option<Vec<LocationConstraint>>,
+    /// Zones being evacuated; offerings in these zones are never chosen.
+    pub evacuating_zones: Vec<String>,
+    /// Offerings, by instance type and location, this pool's NodeRequests
+    /// came back Unmet for. They are never chosen while those requests
+    /// remain, so a stocked-out offering isn't asked for again straight away.
+    pub unmet_offerings: Vec<(InstanceType, Location)>,
+    /// Exempt from the cost anomaly guardrail.
+    pub allow_cost_spike: bool,
+    /// Objective tuning for this pool's solve.
+    pub solve_options: SolveOptions,
+    /// If set, only offerings of these instance families are eligible.
+    pub instance_families: Option<Vec<String>>,
 }
 
 /// Result of a reconciliation pass.
@@ -58,6 +96,14 @@ pub struct PoolConfig {
 pub struct ReconcileResult {
     pub demands: Vec<NodeRequestDemand>,
     pub pod_errors: Vec<PodPoolError>,
+    /// Pods assigned a pool but given no capacity this reconcile: unmet by
+    /// the pool's solve, or on a node dropped by the new-node cap.
+    pub unplaced: Vec<PodId>,
+    /// How each pool's solve went, by pool name.
+    pub solver_stats: BTreeMap<String, SolveStats>,
+    /// Pods the solve placed on each pool's in-flight nodes, by pool name:
+    /// one list per node, in [`ClusterState::in_flight_nodes`] order.
+    pub in_flight_placements: BTreeMap<String, Vec<Vec<PodId>>>,
 }
 
 /// Current state of Cluster demands and offerings.
@@ -75,21 +121,50 @@ pub struct ClusterState {
     /// capacity: pods are placed on them first (zero marginal cost),
     /// and they are excluded from the output.
     pub in_flight_nodes: HashMap<String, Vec<ExistingNode>>,
+    /// Reliability penalties keyed by `Offering::stats_key()`. Missing
+    /// offerings are unpenalised.
+    pub offering_penalties: HashMap<String, f64>,
+    /// Remaining provider quota; regions not listed are unconstrained.
+    pub quotas: Vec<Quota>,
+    /// Pod templates of the cluster's DaemonSets (see
+    /// `daemonset_pod_resources`), reserved on every new node they would
+    /// land on.
+    pub daemonsets: Vec<PodResources>,
+    /// Spare allocatable capacity on each pool's schedulable nodes. Like
+    /// in-flight nodes, pods are placed here first at no cost, but the
+    /// DaemonSets already running on them are not reserved again.
+    pub cluster_nodes: HashMap<String, Vec<ExistingNode>>,
+    /// Placement algorithm every pool is solved with.
+    pub solver: &'static dyn SolverBackend,
+    /// Most new nodes to plan across all pools; `None` means no cap.
+    pub max_new_nodes: Option<usize>,
+    /// Most independent pool groups solved at once; 0 solves every group on
+    /// its own thread.
+    pub solve_concurrency: usize,
 }
 
-/// Assign pod demands to offered pools based on their `pool` selector.
+/// Assign pod demands to pools.
 ///
 /// - Pod has `pool: Some(name)` and pool exists -> assigned
 /// - Pod has `pool: Some(name)` and pool doesn't exist -> PodPoolError
-/// - Pod has `pool: None` and "default" pool exists -> assigned to "default"
-/// - Pod has `pool: None` and no "default" -> PodPoolError
+/// - Pod has `pool: None` -> the pool that fits it (see [`pool_fits`]):
+///   "default" if it does, otherwise the one with the cheapest fitting
+///   offering, ties broken by name
+/// - Pod has `pool: None`, no pool fits and "default" exists -> assigned to
+///   "default", where it is reported unmet
+/// - Pod has `pool: None`, no pool fits and no "default" -> PodPoolError
 pub fn assign_pods_to_pools(
     demands: &[PodResources],
     pools: &[PoolConfig],
+    offerings: &[Offering],
 ) -> (HashMap<String, Vec<PodResources>>, Vec<PodPoolError>) {
     let pool_names: HashMap<&str, &PoolConfig> =
         pools.iter().map(|p| (p.name.as_str(), p)).collect();
     let has_default = pool_names.contains_key("default");
+    let pool_offerings: Vec<(&PoolConfig, Vec<Offering>)> = pools
+        .iter()
+        .map(|p| (p, filter_offerings_for_pool(offerings, p)))
+        .collect();
 
     let mut assigned: HashMap<String, Vec<PodResources>> = HashMap::new();
     let mut errors = Vec::new();
@@ -109,7 +184,12 @@ pub fn assign_pods_to_pools(
                 }
             }
             None => {
-                if has_default {
+                if let Some(name) = best_fitting_pool(pod, &pool_offerings) {
+                    assigned
+                        .entry(name.to_string())
+                        .or_default()
+                        .push(pod.clone());
+                } else if has_default {
                     assigned
                         .entry("default".to_string())
                         .or_default()
@@ -127,6 +207,83 @@ pub fn assign_pods_to_pools(
     (assigned, errors)
 }
 
+/// The demands a shard owning the pools named `owned` should solve, out of
+/// every pool in `pools`. Pods are assigned across all pools first, so one
+/// without a pool selector reaches whichever shard owns the pool that fits
+/// it best. A pod no pool takes stays with the shard owning the pool it
+/// names, or "default", which reports it.
+pub fn shard_demands(
+    demands: Vec<PodResources>,
+    pools: &[PoolConfig],
+    owned: &HashSet<&str>,
+    offerings: &[Offering],
+) -> Vec<PodResources> {
+    let (assigned, _) = assign_pods_to_pools(&demands, pools, offerings);
+    let pool_of: HashMap<&PodId, &str> = assigned
+        .iter()
+        .flat_map(|(pool, pods)| pods.iter().map(move |p| (&p.id, pool.as_str())))
+        .collect();
+    demands
+        .into_iter()
+        .filter(|d| {
+            let pool = pool_of
+                .get(&d.id)
+                .copied()
+                .unwrap_or_else(|| d.pool.as_deref().unwrap_or("default"));
+            owned.contains(pool)
+        })
+        .collect()
+}
+
+/// The cheapest offering of `pool_offerings` a node of which could run
+/// `pod`: it tolerates the pool's taints, and the node's labels (pool labels
+/// included) satisfy its nodeSelector, required node affinity and zones.
+fn pool_fits<'a>(
+    pod: &PodResources,
+    pool: &PoolConfig,
+    pool_offerings: &'a [Offering],
+) -> Option<&'a Offering> {
+    if !pod.tolerates(&pool.taints) {
+        return None;
+    }
+    pool_offerings
+        .iter()
+        .filter(|o| {
+            let zone = o.location.zone.as_ref().map(|z| z.0.as_str());
+            o.satisfies(&pod.resources)
+                && pod.allows_zone(zone)
+                && pod.allows_labels(&pool_node_labels(pool, o))
+        })
+        .min_by(|a, b| a.cost_per_hour.total_cmp(&b.cost_per_hour))
+}
+
+/// The pool a pod without a pool selector should be solved in.
+fn best_fitting_pool<'a>(
+    pod: &PodResources,
+    pool_offerings: &'a [(&PoolConfig, Vec<Offering>)],
+) -> Option<&'a str> {
+    let fits: Vec<(&str, f64)> = pool_offerings
+        .iter()
+        .filter_map(|(pool, offerings)| {
+            pool_fits(pod, pool, offerings).map(|o| (pool.name.as_str(), o.cost_per_hour))
+        })
+        .collect();
+    if fits.iter().any(|(name, _)| *name == "default") {
+        return Some("default");
+    }
+    fits.into_iter()
+        .min_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(b.0)))
+        .map(|(name, _)| name)
+}
+
+/// The labels a node of `offering` in `pool` will carry, topology included.
+fn pool_node_labels(pool: &PoolConfig, offering: &Offering) -> BTreeMap<String, String> {
+    let mut labels = pool.labels.clone();
+    labels.insert(POOL_LABEL.into(), pool.name.clone());
+    labels.extend(offering.labels());
+    labels
+}
+
 /// Filter provider offerings to only those whose instance_type appears in the pool's server_types.
 pub fn filter_offerings_for_pool(offerings: &[Offering], pool: &PoolConfig) -> Vec<Offering> {
     let allowed: HashSet<&str> = pool
@@ -138,15 +295,44 @@ pub fn filter_offerings_for_pool(offerings: &[Offering], pool: &PoolConfig) -> V
     offerings
         .iter()
         .filter(|o| allowed.contains(o.instance_type.0.as_str()))
+        .filter(|o| {
+            pool.instance_families
+                .as_ref()
+                .is_none_or(|families| families.iter().any(|f| f == o.instance_type.family()))
+        })
         .cloned()
         .collect()
 }
 
+/// Summed requests of the DaemonSets whose pods would run on a node with
+/// `labels` and `taints`.
+pub fn daemonset_overhead(
+    daemonsets: &[PodResources],
+    labels: &BTreeMap<String, String>,
+    taints: &[Taint],
+) -> Resources {
+    let mut overhead = Resources::default();
+    for ds in daemonsets
+        .iter()
+        .filter(|ds| ds.allows_labels(labels) && ds.tolerates(taints))
+    {
+        overhead.cpu_millis += ds.resources.cpu_millis;
+        overhead.memory_mib += ds.resources.memory_mib;
+        if let Some(gib) = ds.resources.ephemeral_storage_gib {
+            *overhead.ephemeral_storage_gib.get_or_insert(0) += gib;
+        }
+    }
+    overhead
+}
+
 /// Build pre-seeded existing nodes for a pool, enriched with pool labels.
+/// Their capacity is net of the DaemonSets that will run on them.
 fn build_existing_nodes(
     in_flight_nodes: &HashMap<String, Vec<ExistingNode>>,
     pool_name: &str,
     pool_labels: &BTreeMap<String, String>,
+    pool_taints: &[Taint],
+    daemonsets: &[PodResources],
 ) -> Vec<ExistingNode> {
     in_flight_nodes
         .get(pool_name)
@@ -155,10 +341,18 @@ fn build_existing_nodes(
                 .iter()
                 .map(|n| {
                     let mut labels = pool_labels.clone();
+                    labels.insert(POOL_LABEL.into(), pool_name.into());
                     labels.extend(n.labels.clone());
+                    let mut resources = n.resources.clone();
+                    resources.saturating_subtract(&daemonset_overhead(
+                        daemonsets,
+                        &labels,
+                        pool_taints,
+                    ));
                     ExistingNode {
-                        resources: n.resources.clone(),
+                        resources,
                         labels,
+                        taints: pool_taints.to_vec(),
                     }
                 })
                 .collect()
@@ -166,6 +360,32 @@ fn build_existing_nodes(
         .unwrap_or_default()
 }
 
+/// Whether `pool` may put a new node where `offering` lives: at least one
+/// of its locations (none means everywhere), not a zone being evacuated, and
+/// not an offering the pool's requests just came back Unmet for.
+fn places_in(pool: &PoolConfig, offering: &Offering) -> bool {
+    let in_locations = pool.locations.as_ref().is_none_or(|locations| {
+        locations.iter().any(|loc| {
+            loc.region == offering.location.region.0
+                && match (&loc.zones, &offering.location.zone) {
+                    (Some(zones), Some(z)) => zones.iter().any(|a| a == &z.0),
+                    (Some(_), None) => false,
+                    (None, _) => true,
+                }
+        })
+    });
+    in_locations
+        && offering
+            .location
+            .zone
+            .as_ref()
+            .is_none_or(|z| !pool.evacuating_zones.contains(&z.0))
+        && !pool
+            .unmet_offerings
+            .iter()
+            .any(|(t, l)| *t == offering.instance_type && *l == offering.location)
+}
+
 /// Build `BoundedOffering`s from pool offerings, filtering by demand satisfaction,
 /// location constraints, and remaining capacity per instance type.
 fn build_bounded_offerings(
@@ -174,58 +394,58 @@ fn build_bounded_offerings(
     pool: &PoolConfig,
     max_by_type: &HashMap<&str, u32>,
     pool_name: &str,
+    offering_penalties: &HashMap<String, f64>,
+    daemonsets: &[PodResources],
 ) -> Vec<BoundedOffering> {
     pool_offerings
         .iter()
-        .filter(|o| pool_demands.iter().any(|d| o.satisfies(&d.resources)))
         .filter(|o| {
-            // Filter by location constraints: offering must match at least one entry.
-            // No locations = all regions/zones allowed.
-            if let Some(locations) = &pool.locations {
-                locations.iter().any(|loc| {
-                    if loc.region != o.location.region.0 {
-                        return false;
-                    }
-                    match (&loc.zones, &o.location.zone) {
-                        (Some(zones), Some(z)) => zones.iter().any(|a| a == &z.0),
-                        (Some(_), None) => false,
-                        (None, _) => true,
-                    }
-                })
-            } else {
-                true
-            }
+            let zone = o.location.zone.as_ref().map(|z| z.0.as_str());
+            pool_demands.iter().any(|d| {
+                o.satisfies(&d.resources) && d.allows_zone(zone) && d.tolerates(&pool.taints)
+            })
         })
+        .filter(|o| places_in(pool, o))
         .map(|o| {
             let remaining_max = max_by_type
                 .get(o.instance_type.0.as_str())
                 .copied()
                 .unwrap_or(0);
 
-            // Derive topology labels from offering location.
-            let mut labels = pool.labels.clone();
-            labels.insert(
-                "topology.kubernetes.io/region".into(),
-                o.location.region.0.clone(),
-            );
-            if let Some(ref z) = o.location.zone {
-                labels.insert("topology.kubernetes.io/zone".into(), z.0.clone());
-            }
+            let labels = pool_node_labels(pool, o);
+
+            let overhead = daemonset_overhead(daemonsets, &labels, &pool.taints);
 
             BoundedOffering {
                 max_instances: remaining_max,
                 offering: o.clone(),
                 labels,
                 type_group: Some(format!("{}/{}", pool_name, o.instance_type.0)),
+                penalty: offering_penalties
+                    .get(&o.stats_key())
+                    .copied()
+                    .unwrap_or(0.0),
+                taints: pool.taints.clone(),
+                overhead,
             }
         })
         .collect()
 }
 
+/// What [`solve_pool`] returns for one pool.
+type PoolSolve = (
+    Vec<NodeRequestDemand>,
+    Vec<PodId>,
+    Vec<Vec<PodId>>,
+    SolveStats,
+);
+
 /// Solve placement for a single pool: filter offerings, apply capacity limits,
-/// run the solver, and return the resulting node request demands.
+/// run the solver, and return the resulting node request demands, the pods
+/// left unmet, the pods placed on each in-flight node and the solver's stats.
 ///
 /// Each Pool is separable as pods cannot be set to run on multiple pools.
+#[allow(clippy::too_many_arguments)]
 fn solve_pool(
     pool_name: &str,
     pool_demands: &[PodResources],
@@ -233,7 +453,12 @@ fn solve_pool(
     offerings: &[Offering],
     occupied_counts: &HashMap<String, HashMap<String, u32>>,
     in_flight_nodes: &HashMap<String, Vec<ExistingNode>>,
-) -> Vec<NodeRequestDemand> {
+    offering_penalties: &HashMap<String, f64>,
+    daemonsets: &[PodResources],
+    cluster_nodes: &HashMap<String, Vec<ExistingNode>>,
+    solver: &dyn SolverBackend,
+    quotas: &mut QuotaBudget,
+) -> PoolSolve {
     let pool_offerings = filter_offerings_for_pool(offerings, pool);
 
     // Subtract occupied slots (existing nodes + pending/provisioning NodeRequests)
@@ -254,15 +479,53 @@ fn solve_pool(
         })
         .collect();
 
-    let suitable =
-        build_bounded_offerings(&pool_offerings, pool_demands, pool, &max_by_type, pool_name);
-
-    let existing = build_existing_nodes(in_flight_nodes, pool_name, &pool.labels);
+    let suitable = build_bounded_offerings(
+        &pool_offerings,
+        pool_demands,
+        pool,
+        &max_by_type,
+        pool_name,
+        offering_penalties,
+        daemonsets,
+    );
+
+    let mut existing = build_existing_nodes(
+        in_flight_nodes,
+        pool_name,
+        &pool.labels,
+        &pool.taints,
+        daemonsets,
+    );
+    let in_flight_count = existing.len();
+    existing.extend(cluster_nodes.get(pool_name).cloned().unwrap_or_default());
+
+    // `maxNodes` leaves room for only so many more nodes, on top of each
+    // type's own limit.
+    let capped;
+    let options = match pool.max_nodes {
+        Some(max_nodes) => {
+            let total: u32 = occupied.map_or(0, |m| m.values().sum());
+            let room = max_nodes.saturating_sub(total);
+            capped = SolveOptions {
+                max_new_nodes: Some(
+                    pool.solve_options
+                        .max_new_nodes
+                        .map_or(room, |m| m.min(room)),
+                ),
+                ..pool.solve_options.clone()
+            };
+            &capped
+        }
+        None => &pool.solve_options,
+    };
 
-    let solution = solve(pool_demands, &suitable, &existing);
+    let outcome = solver.solve(pool_demands, &suitable, &existing, quotas, options);
+    let stats = outcome.stats;
+    let mut in_flight = outcome.existing_pods;
+    in_flight.truncate(in_flight_count);
 
-    let (nodes, unmet) = match solution {
-        PlacementSolution::NoDemands => return vec![],
+    let (nodes, unmet) = match outcome.solution {
+        PlacementSolution::NoDemands => return (vec![], vec![], in_flight, stats),
         PlacementSolution::AllPlaced(nodes) => (nodes, vec![]),
         PlacementSolution::IncompletePlacement { nodes, unmet } => (nodes, unmet),
     };
@@ -275,45 +538,318 @@ fn solve_pool(
         );
     }
 
-    nodes
+    let uids: HashMap<&PodId, &str> = pool_demands
+        .iter()
+        .map(|d| (&d.id, d.uid.as_str()))
+        .collect();
+
+    let demands = nodes
         .into_iter()
         .map(|node| NodeRequestDemand {
             pool: pool_name.to_string(),
             pool_uid: pool.uid.clone(),
+            labels: pool.labels.clone(),
+            taints: pool.taints.clone(),
+            claimed_pods: node
+                .pods
+                .iter()
+                .filter_map(|id| uids.get(id).map(|uid| uid.to_string()))
+                .collect(),
             target_offering: node.offering,
         })
+        .collect();
+    (
+        demands,
+        unmet.into_iter().map(|p| p.id).collect(),
+        in_flight,
+        stats,
+    )
+}
+
+/// Nodes to provision so every pool reaches its floor, counting existing
+/// nodes and NodeRequests in flight (`occupied_counts`). Each server type's
+/// `min` is met with that type; what `min_nodes` still asks for after that
+/// goes to the cheapest offering the pool can add another node of. Neither
+/// a type's `max` nor the pool's `max_nodes` is exceeded.
+pub fn floor_demands(
+    pools: &[PoolConfig],
+    offerings: &[Offering],
+    occupied_counts: &HashMap<String, HashMap<String, u32>>,
+) -> Vec<NodeRequestDemand> {
+    let mut demands = Vec::new();
+    for pool in pools {
+        let mut counts = occupied_counts.get(&pool.name).cloned().unwrap_or_default();
+        let total = |counts: &HashMap<String, u32>| counts.values().sum::<u32>();
+        let room =
+            |counts: &HashMap<String, u32>| pool.max_nodes.is_none_or(|max| total(counts) < max);
+        let candidates: Vec<Offering> = filter_offerings_for_pool(offerings, pool)
+            .into_iter()
+            .filter(|o| places_in(pool, o) && pool.solve_options.admits(&o.instance_type))
+            .collect();
+        let cheapest = |instance_type: Option<&str>, counts: &HashMap<String, u32>| {
+            candidates
+                .iter()
+                .filter(|o| instance_type.is_none_or(|t| o.instance_type.0 == t))
+                .filter(|o| {
+                    pool.server_types.iter().any(|st| {
+                        st.name == o.instance_type.0
+                            && counts.get(&st.name).copied().unwrap_or(0) < st.max
+                    })
+                })
+                .min_by(|a, b| a.cost_per_hour.total_cmp(&b.cost_per_hour))
+                .cloned()
+        };
+        let mut add = |offering: Offering, counts: &mut HashMap<String, u32>| {
+            *counts.entry(offering.instance_type.0.clone()).or_insert(0) += 1;
+            demands.push(NodeRequestDemand {
+                pool: pool.name.clone(),
+                pool_uid: pool.uid.clone(),
+                labels: pool.labels.clone(),
+                taints: pool.taints.clone(),
+                claimed_pods: vec![],
+                target_offering: offering,
+            });
+        };
+
+        for st in &pool.server_types {
+            while counts.get(&st.name).copied().unwrap_or(0) < st.min && room(&counts) {
+                let Some(offering) = cheapest(Some(&st.name), &counts) else {
+                    warn!(pool = %pool.name, instance_type = %st.name, "no offering available to keep the type's minimum warm");
+                    break;
+                };
+                add(offering, &mut counts);
+            }
+        }
+        while total(&counts) < pool.min_nodes && room(&counts) {
+            let Some(offering) = cheapest(None, &counts) else {
+                warn!(pool = %pool.name, "no offering available to keep the pool's minimum warm");
+                break;
+            };
+            add(offering, &mut counts);
+        }
+    }
+    demands
+}
+
+/// Placeholder pods standing in for each pool's headroom: `replicas` pods of
+/// the headroom's size, pinned to the pool, tolerating its taints, and at the
+/// lowest priority so any real pod is placed before them.
+pub fn headroom_demands(pools: &[PoolConfig]) -> Vec<PodResources> {
+    pools
+        .iter()
+        .filter_map(|pool| Some((pool, pool.headroom.as_ref()?)))
+        .flat_map(|(pool, headroom)| {
+            (0..headroom.replicas).map(move |i| PodResources {
+                id: PodId::new("growth-headroom", format!("{}-{i}", pool.name)),
+                uid: format!("headroom-{}-{i}", pool.name),
+                resources: Resources {
+                    cpu_millis: headroom.cpu_millis,
+                    memory_mib: headroom.memory_mib,
+                    ..Default::default()
+                },
+                pool: Some(pool.name.clone()),
+                pod_labels: BTreeMap::new(),
+                affinity_constraints: vec![],
+                zones: None,
+                tolerations: vec![Toleration {
+                    operator: Some("Exists".into()),
+                    ..Default::default()
+                }],
+                node_selector: BTreeMap::new(),
+                node_affinity: vec![],
+                priority: i32::MIN,
+                unmet_penalty: None,
+            })
+        })
         .collect()
 }
 
+/// Pools solved together, by name.
+type PoolGroup<'a> = Vec<(&'a str, &'a PoolConfig)>;
+
+/// Split pools into groups that can be solved independently. Pools only
+/// interact through provider quota, so two pools share a group when their
+/// offerings draw on the same quota-constrained provider region. Groups and
+/// the pools within them keep `pools`' order.
+fn partition_pools<'a>(
+    pools: &[(&'a str, &'a PoolConfig)],
+    offerings: &[Offering],
+    quotas: &QuotaBudget,
+) -> Vec<PoolGroup<'a>> {
+    let mut groups: Vec<(BTreeSet<(String, String)>, PoolGroup)> = Vec::new();
+    for &(name, pool) in pools {
+        let mut keys = quotas.constrained_keys(&filter_offerings_for_pool(offerings, pool));
+        let mut members = vec![(name, pool)];
+        // Absorb every earlier group this pool competes with.
+        let mut i = 0;
+        while i < groups.len() {
+            if groups[i].0.is_disjoint(&keys) {
+                i += 1;
+                continue;
+            }
+            let (shared, mut earlier) = groups.remove(i);
+            keys.extend(shared);
+            earlier.append(&mut members);
+            members = earlier;
+        }
+        groups.push((keys, members));
+    }
+    groups.into_iter().map(|(_, members)| members).collect()
+}
+
+/// Flatten each pool's planned nodes, keeping at most `max` of them. Pools
+/// take turns, so one runaway pool can't crowd the others out; the pods of
+/// dropped nodes stay pending for the next reconcile.
+fn cap_new_nodes(
+    by_pool: Vec<Vec<NodeRequestDemand>>,
+    max: Option<usize>,
+) -> Vec<NodeRequestDemand> {
+    let total: usize = by_pool.iter().map(Vec::len).sum();
+    let Some(max) = max.filter(|&max| max < total) else {
+        return by_pool.into_iter().flatten().collect();
+    };
+    warn!(
+        planned = total,
+        max, "plan exceeds the new-node limit, deferring the rest"
+    );
+    let mut queues: Vec<_> = by_pool.into_iter().map(Vec::into_iter).collect();
+    let mut kept = Vec::with_capacity(max);
+    while kept.len() < max {
+        for queue in &mut queues {
+            if kept.len() < max
+                && let Some(demand) = queue.next()
+            {
+                kept.push(demand);
+            }
+        }
+    }
+    kept
+}
+
 /// Reconcile pod demands against the cluster state, returning demands for nodes to fulfill them.
+///
+/// Independent groups of pools (see [`partition_pools`]) are solved on
+/// their own threads, at most `solve_concurrency` at a time, so reconcile
+/// latency tracks the largest group rather than the whole cluster.
 pub fn reconcile_pod_demand(state: ClusterState) -> ReconcileResult {
-    let (pods_by_pool, pod_errors) = assign_pods_to_pools(&state.demands, &state.pools);
+    let (pods_by_pool, pod_errors) =
+        assign_pods_to_pools(&state.demands, &state.pools, &state.offerings);
 
     let pool_map: HashMap<&str, &PoolConfig> =
         state.pools.iter().map(|p| (p.name.as_str(), p)).collect();
 
-    let mut all_demands = Vec::new();
-
-    for (pool_name, pool_demands) in &pods_by_pool {
-        debug!(pool = %pool_name, pods = pool_demands.len(), "pool demand");
-        if pool_demands.is_empty() {
-            continue;
+    let mut pools: Vec<(&str, &PoolConfig)> = pods_by_pool
+        .iter()
+        .filter(|(_, pool_demands)| !pool_demands.is_empty())
+        .map(|(pool_name, _)| (pool_name.as_str(), pool_map[pool_name.as_str()]))
+        .collect();
+    pools.sort_by_key(|(name, _)| *name);
+
+    let quotas = QuotaBudget::new(&state.quotas);
+    let groups = partition_pools(&pools, &state.offerings, &quotas);
+
+    let solve_group = |group: &[(&str, &PoolConfig)]| {
+        // Groups share no constrained quota, so each can spend its own copy.
+        let mut quotas = quotas.clone();
+        let mut solved = Vec::new();
+        for &(pool_name, pool) in group {
+            let pool_demands = &pods_by_pool[pool_name];
+            debug!(pool = %pool_name, pods = pool_demands.len(), "pool demand");
+            let (demands, unmet, in_flight, stats) = solve_pool(
+                pool_name,
+                pool_demands,
+                pool,
+                &state.offerings,
+                &state.occupied_counts,
+                &state.in_flight_nodes,
+                &state.offering_penalties,
+                &state.daemonsets,
+                &state.cluster_nodes,
+                state.solver,
+                &mut quotas,
+            );
+            solved.push((pool_name.to_string(), (demands, unmet, in_flight, stats)));
         }
+        solved
+    };
 
-        let pool = pool_map[pool_name.as_str()];
-        all_demands.extend(solve_pool(
-            pool_name,
-            pool_demands,
-            pool,
-            &state.offerings,
-            &state.occupied_counts,
-            &state.in_flight_nodes,
-        ));
+    let workers = match state.solve_concurrency {
+        0 => groups.len(),
+        n => n.min(groups.len()),
+    };
+    let solved: Vec<(String, PoolSolve)> = if workers <= 1 {
+        groups.iter().flat_map(|g| solve_group(g)).collect()
+    } else {
+        debug!(
+            groups = groups.len(),
+            workers, "solving independent pool groups in parallel"
+        );
+        // Each worker takes the next unsolved group, so one slow group only
+        // holds up its own worker.
+        let next = AtomicUsize::new(0);
+        let mut by_group: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(group) = groups.get(i) else {
+                                break done;
+                            };
+                            done.push((i, solve_group(group)));
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("pool solver panicked"))
+                .collect()
+        });
+        // Back in group order, so the new-node cap is shared out the same way
+        // however the groups were scheduled.
+        by_group.sort_by_key(|(i, _)| *i);
+        by_group
+            .into_iter()
+            .flat_map(|(_, solved)| solved)
+            .collect()
+    };
+
+    let mut by_pool = Vec::new();
+    let mut unplaced = Vec::new();
+    let mut solver_stats = BTreeMap::new();
+    let mut in_flight_placements = BTreeMap::new();
+    for (pool_name, (demands, unmet, in_flight, stats)) in solved {
+        by_pool.push(demands);
+        unplaced.extend(unmet);
+        in_flight_placements.insert(pool_name.clone(), in_flight);
+        solver_stats.insert(pool_name, stats);
     }
+    let planned: HashSet<String> = by_pool
+        .iter()
+        .flatten()
+        .flat_map(|d| d.claimed_pods.iter().cloned())
+        .collect();
+    let all_demands = cap_new_nodes(by_pool, state.max_new_nodes);
+    let kept: HashSet<&str> = all_demands
+        .iter()
+        .flat_map(|d| d.claimed_pods.iter().map(String::as_str))
+        .collect();
+    unplaced.extend(
+        state
+            .demands
+            .iter()
+            .filter(|d| planned.contains(&d.uid) && !kept.contains(d.uid.as_str()))
+            .map(|d| d.id.clone()),
+    );
 
     ReconcileResult {
         demands: all_demands,
         pod_errors,
+        unplaced,
+        solver_stats,
+        in_flight_placements,
     }
 }
 
@@ -323,19 +859,24 @@ mod tests {
 
     use std::collections::BTreeMap;
 
-    use crate::offering::{InstanceType, PodId, Resources};
+    use crate::offering::{PodId, Resources};
+    use crate::optimiser::ScoredSolver;
+    use crate::testing;
 
-    fn res(cpu: u32, memory_mib: u32) -> Resources {
+    fn res(cpu: u32, memory_mib: u64) -> Resources {
         Resources {
-            cpu,
+            cpu_millis: u64::from(cpu) * 1000,
             memory_mib,
             ephemeral_storage_gib: None,
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         }
     }
 
-    fn pod(name: &str, cpu: u32, memory_mib: u32) -> PodResources {
+    fn pod(name: &str, cpu: u32, memory_mib: u64) -> PodResources {
         PodResources {
             id: PodId::new("default", name),
             uid: format!("uid-{name}"),
@@ -343,10 +884,16 @@ mod tests {
             pool: None,
             pod_labels: BTreeMap::new(),
             affinity_constraints: vec![],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
         }
     }
 
-    fn pod_with_pool(name: &str, cpu: u32, memory_mib: u32, pool: &str) -> PodResources {
+    fn pod_with_pool(name: &str, cpu: u32, memory_mib: u64, pool: &str) -> PodResources {
         PodResources {
             id: PodId::new("default", name),
             uid: format!("uid-{name}"),
@@ -354,26 +901,26 @@ mod tests {
             pool: Some(pool.to_string()),
             pod_labels: BTreeMap::new(),
             affinity_constraints: vec![],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
         }
     }
 
-    fn offering(name: &str, cpu: u32, memory_mib: u32, cost: f64) -> Offering {
-        use crate::offering::{Location, Region, Zone};
-        Offering {
-            instance_type: InstanceType(name.into()),
-            resources: res(cpu, memory_mib),
-            cost_per_hour: cost,
-            location: Location {
-                region: Region("eu-central".into()),
-                zone: Some(Zone("fsn1-dc14".into())),
-            },
-        }
+    fn offering(name: &str, cpu: u32, memory_mib: u64, cost: f64) -> Offering {
+        offering_in(name, cpu, memory_mib, cost, "eu-central", Some("fsn1-dc14"))
     }
 
     fn default_pool(server_types: Vec<(&str, u32)>) -> PoolConfig {
         PoolConfig {
             name: "default".to_string(),
             uid: "default-uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: server_types
                 .into_iter()
                 .map(|(name, max)| ServerTypeConfig {
@@ -383,7 +930,13 @@ mod tests {
                 })
                 .collect(),
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         }
     }
 
@@ -400,6 +953,13 @@ mod tests {
             occupied_counts: HashMap::new(),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         }
     }
 
@@ -423,24 +983,42 @@ mod tests {
             PoolConfig {
                 name: "gpu".to_string(),
                 uid: "gpu-uid".to_string(),
+                min_nodes: 0,
+                max_nodes: None,
+                headroom: None,
                 server_types: vec![],
                 labels: BTreeMap::new(),
+                taints: vec![],
                 locations: None,
+                evacuating_zones: vec![],
+                unmet_offerings: vec![],
+                allow_cost_spike: false,
+                solve_options: SolveOptions::default(),
+                instance_families: None,
             },
             PoolConfig {
                 name: "cpu".to_string(),
                 uid: "cpu-uid".to_string(),
+                min_nodes: 0,
+                max_nodes: None,
+                headroom: None,
                 server_types: vec![],
                 labels: BTreeMap::new(),
+                taints: vec![],
                 locations: None,
+                evacuating_zones: vec![],
+                unmet_offerings: vec![],
+                allow_cost_spike: false,
+                solve_options: SolveOptions::default(),
+                instance_families: None,
             },
         ];
         let demands = vec![pod_with_pool("a", 1, 1024, "gpu")];
-        let (assigned, errors) = assign_pods_to_pools(&demands, &pools);
+        let (assigned, errors) = assign_pods_to_pools(&demands, &pools, &[]);
 
         assert!(errors.is_empty());
         assert_eq!(assigned.get("gpu").unwrap().len(), 1);
-        assert!(assigned.get("cpu").is_none());
+        assert!(!assigned.contains_key("cpu"));
     }
 
     #[test]
@@ -448,12 +1026,21 @@ mod tests {
         let pools = vec![PoolConfig {
             name: "cpu".to_string(),
             uid: "cpu-uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         }];
         let demands = vec![pod_with_pool("a", 1, 1024, "nonexistent")];
-        let (assigned, errors) = assign_pods_to_pools(&demands, &pools);
+        let (assigned, errors) = assign_pods_to_pools(&demands, &pools, &[]);
 
         assert!(assigned.is_empty());
         assert_eq!(errors.len(), 1);
@@ -467,12 +1054,21 @@ mod tests {
         let pools = vec![PoolConfig {
             name: "default".to_string(),
             uid: "default-uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         }];
         let demands = vec![pod("a", 1, 1024)];
-        let (assigned, errors) = assign_pods_to_pools(&demands, &pools);
+        let (assigned, errors) = assign_pods_to_pools(&demands, &pools, &[]);
 
         assert!(errors.is_empty());
         assert_eq!(assigned.get("default").unwrap().len(), 1);
@@ -483,17 +1079,79 @@ mod tests {
         let pools = vec![PoolConfig {
             name: "gpu-only".to_string(),
             uid: "gpu-uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         }];
         let demands = vec![pod("a", 1, 1024)];
-        let (_, errors) = assign_pods_to_pools(&demands, &pools);
+        let (_, errors) = assign_pods_to_pools(&demands, &pools, &[]);
 
         assert_eq!(errors.len(), 1);
         assert!(matches!(errors[0].reason, PodPoolReason::NoPoolSelector));
     }
 
+    #[test]
+    fn pod_without_selector_goes_to_a_pool_that_fits() {
+        use k8s_openapi::api::core::v1::Toleration;
+
+        let mut default = default_pool(vec![("cpx22", 100)]);
+        default.taints = vec![Taint {
+            key: "dedicated".into(),
+            effect: "NoSchedule".into(),
+            ..Default::default()
+        }];
+        let labelled = |name: &str, disk: &str, server_type: &str| PoolConfig {
+            name: name.to_string(),
+            uid: format!("{name}-uid"),
+            labels: BTreeMap::from([("disk".to_string(), disk.to_string())]),
+            ..default_pool(vec![(server_type, 100)])
+        };
+        let pools = vec![
+            default,
+            labelled("ssd", "ssd", "cpx22"),
+            labelled("hdd", "hdd", "cx32"),
+        ];
+        let offerings = vec![
+            offering("cpx22", 2, 4096, 0.01),
+            offering("cx32", 4, 8192, 0.02),
+        ];
+        let archive = PodResources {
+            node_selector: BTreeMap::from([("disk".into(), "hdd".into())]),
+            ..pod("archive", 1, 1024)
+        };
+        let batch = PodResources {
+            tolerations: vec![Toleration {
+                key: Some("dedicated".into()),
+                operator: Some("Exists".into()),
+                ..Default::default()
+            }],
+            ..pod("batch", 1, 1024)
+        };
+        let too_big = pod("too-big", 16, 1024);
+        let demands = vec![pod("web", 1, 1024), archive, batch, too_big];
+        let (assigned, errors) = assign_pods_to_pools(&demands, &pools, &offerings);
+
+        assert!(errors.is_empty());
+        let names = |pool: &str| -> Vec<String> {
+            assigned[pool].iter().map(|p| p.id.name.clone()).collect()
+        };
+        // The cheapest untainted pool, the pool with the selected label, and
+        // "default" for the pod that tolerates it.
+        assert_eq!(names("ssd"), vec!["web"]);
+        assert_eq!(names("hdd"), vec!["archive"]);
+        // Nothing fits the last pod; it is reported unmet from "default".
+        assert_eq!(names("default"), vec!["batch", "too-big"]);
+    }
+
     // --- Offering filter tests ---
 
     #[test]
@@ -501,13 +1159,22 @@ mod tests {
         let pool = PoolConfig {
             name: "small".to_string(),
             uid: "uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 10,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         let offerings = vec![
             offering("cpx22", 2, 4096, 0.01),
@@ -518,6 +1185,27 @@ mod tests {
         assert_eq!(filtered[0].instance_type.0, "cpx22");
     }
 
+    #[test]
+    fn filter_offerings_honours_instance_families() {
+        let mut pool = default_pool(vec![("cpx22", 10), ("gpu-a100-1", 10)]);
+        let offerings = vec![
+            offering("cpx22", 2, 4096, 0.01),
+            offering("gpu-a100-1", 12, 131072, 2.21),
+        ];
+        let types = |pool: &PoolConfig| {
+            filter_offerings_for_pool(&offerings, pool)
+                .into_iter()
+                .map(|o| o.instance_type.0)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(types(&pool), vec!["cpx22", "gpu-a100-1"]);
+        pool.instance_families = Some(vec!["gpu".into()]);
+        assert_eq!(types(&pool), vec!["gpu-a100-1"]);
+        pool.instance_families = Some(vec!["cpx".into(), "ccx".into()]);
+        assert_eq!(types(&pool), vec!["cpx22"]);
+    }
+
     // --- Full reconcile with pools ---
 
     #[test]
@@ -525,13 +1213,22 @@ mod tests {
         let pool = PoolConfig {
             name: "workers".to_string(),
             uid: "workers-uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 10,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         let state = ClusterState {
             demands: vec![pod_with_pool("a", 1, 1024, "workers")],
@@ -539,11 +1236,19 @@ mod tests {
             occupied_counts: HashMap::new(),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         assert_eq!(result.demands.len(), 1);
         assert_eq!(result.demands[0].pool, "workers");
         assert_eq!(result.demands[0].pool_uid, "workers-uid");
+        assert_eq!(result.demands[0].claimed_pods, ["uid-a"]);
         assert!(result.pod_errors.is_empty());
     }
 
@@ -552,13 +1257,22 @@ mod tests {
         let pool = PoolConfig {
             name: "default".to_string(),
             uid: "uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 10,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         let state = ClusterState {
             demands: vec![pod_with_pool("a", 1, 1024, "nonexistent")],
@@ -566,6 +1280,13 @@ mod tests {
             occupied_counts: HashMap::new(),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         assert!(result.demands.is_empty());
@@ -579,13 +1300,22 @@ mod tests {
         let pool = PoolConfig {
             name: "default".to_string(),
             uid: "uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 2,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         let state = ClusterState {
             demands: vec![pod("a", 2, 4096), pod("b", 2, 4096), pod("c", 2, 4096)],
@@ -596,6 +1326,13 @@ mod tests {
             )]),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         // max=2, occupied=1 → solver may only provision 1 more node
@@ -607,21 +1344,20 @@ mod tests {
     fn offering_in(
         name: &str,
         cpu: u32,
-        memory_mib: u32,
+        memory_mib: u64,
         cost: f64,
         region: &str,
         zone: Option<&str>,
     ) -> Offering {
-        use crate::offering::{Location, Region, Zone};
-        Offering {
-            instance_type: InstanceType(name.into()),
-            resources: res(cpu, memory_mib),
-            cost_per_hour: cost,
-            location: Location {
-                region: Region(region.into()),
-                zone: zone.map(|z| Zone(z.into())),
-            },
+        let offering = testing::offering(name)
+            .with_resources(res(cpu, memory_mib))
+            .with_cost(cost)
+            .with_region(region);
+        match zone {
+            Some(zone) => offering.with_zone(zone),
+            None => offering,
         }
+        .build()
     }
 
     #[test]
@@ -630,16 +1366,25 @@ mod tests {
         let pool = PoolConfig {
             name: "default".to_string(),
             uid: "uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 100,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: Some(vec![LocationConstraint {
                 region: "us-west".to_string(),
                 zones: None,
             }]),
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         let offerings = vec![
             offering_in("cpx22", 2, 4096, 0.01, "us-west", Some("a")),
@@ -652,6 +1397,13 @@ mod tests {
             occupied_counts: HashMap::new(),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         assert_eq!(result.demands.len(), 1);
@@ -672,16 +1424,25 @@ mod tests {
         let pool = PoolConfig {
             name: "default".to_string(),
             uid: "uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 100,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: Some(vec![LocationConstraint {
                 region: "us-west".to_string(),
                 zones: Some(vec!["a".to_string()]),
             }]),
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         let offerings = vec![
             offering_in("cpx22", 2, 4096, 0.01, "us-west", Some("a")),
@@ -694,6 +1455,13 @@ mod tests {
             occupied_counts: HashMap::new(),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         assert_eq!(result.demands.len(), 1);
@@ -709,12 +1477,16 @@ mod tests {
         let pool = PoolConfig {
             name: "default".to_string(),
             uid: "uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 100,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: Some(vec![
                 LocationConstraint {
                     region: "us-west4".to_string(),
@@ -725,6 +1497,11 @@ mod tests {
                     zones: Some(vec!["a".to_string()]),
                 },
             ]),
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         let offerings = vec![
             offering_in("cpx22", 2, 4096, 0.01, "us-west4", Some("a")),
@@ -740,6 +1517,13 @@ mod tests {
             occupied_counts: HashMap::new(),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         // All placed offerings must be in the allowed set
@@ -763,13 +1547,22 @@ mod tests {
         let pool = PoolConfig {
             name: "default".to_string(),
             uid: "uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 100,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         let offerings = vec![
             offering_in("cpx22", 2, 4096, 0.01, "us-west", Some("a")),
@@ -781,6 +1574,13 @@ mod tests {
             occupied_counts: HashMap::new(),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         assert_eq!(result.demands.len(), 1);
@@ -792,16 +1592,25 @@ mod tests {
         let pool = PoolConfig {
             name: "default".to_string(),
             uid: "uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 100,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: Some(vec![LocationConstraint {
                 region: "us-west".to_string(),
                 zones: Some(vec!["a".to_string()]),
             }]),
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         // Offering has no zone — should not pass a constraint that lists specific zones
         let offerings = vec![offering_in("cpx22", 2, 4096, 0.01, "us-west", None)];
@@ -811,6 +1620,13 @@ mod tests {
             occupied_counts: HashMap::new(),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         assert!(
@@ -825,16 +1641,25 @@ mod tests {
         let pool = PoolConfig {
             name: "default".to_string(),
             uid: "uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 100,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: Some(vec![LocationConstraint {
                 region: "us-west".to_string(),
                 zones: None,
             }]),
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         // Offering has no zone — region-only constraint should accept it
         let offerings = vec![offering_in("cpx22", 2, 4096, 0.01, "us-west", None)];
@@ -844,11 +1669,278 @@ mod tests {
             occupied_counts: HashMap::new(),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         assert_eq!(result.demands.len(), 1);
     }
 
+    #[test]
+    fn evacuating_zone_is_never_chosen() {
+        let mut pool = default_pool(vec![("cpx22", 100)]);
+        pool.evacuating_zones = vec!["a".to_string()];
+        // The evacuated zone is cheaper, but must still be skipped.
+        let offerings = vec![
+            offering_in("cpx22", 2, 4096, 0.01, "us-west", Some("a")),
+            offering_in("cpx22", 2, 4096, 0.02, "us-west", Some("b")),
+        ];
+        let state = ClusterState {
+            demands: vec![pod("a", 1, 1024)],
+            offerings,
+            occupied_counts: HashMap::new(),
+            pools: vec![pool],
+            in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
+        };
+        let result = reconcile_pod_demand(state);
+        assert_eq!(result.demands.len(), 1);
+        let zone = result.demands[0].target_offering.location.zone.as_ref();
+        assert_eq!(zone.map(|z| z.0.as_str()), Some("b"));
+    }
+
+    #[test]
+    fn unmet_offering_is_not_chosen_again() {
+        let mut pool = default_pool(vec![("cpx22", 100)]);
+        let offerings = vec![
+            offering_in("cpx22", 2, 4096, 0.01, "us-west", Some("a")),
+            offering_in("cpx22", 2, 4096, 0.02, "us-west", Some("b")),
+        ];
+        // The pool's last request for the cheaper zone came back Unmet.
+        pool.unmet_offerings = vec![(
+            offerings[0].instance_type.clone(),
+            offerings[0].location.clone(),
+        )];
+        let state = ClusterState {
+            demands: vec![pod("a", 1, 1024)],
+            offerings,
+            occupied_counts: HashMap::new(),
+            pools: vec![pool],
+            in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
+        };
+        let result = reconcile_pod_demand(state);
+        assert_eq!(result.demands.len(), 1);
+        let zone = result.demands[0].target_offering.location.zone.as_ref();
+        assert_eq!(zone.map(|z| z.0.as_str()), Some("b"));
+    }
+
+    #[test]
+    fn sharded_pods_go_to_the_shard_owning_their_best_pool() {
+        let mut big = default_pool(vec![("cx52", 100)]);
+        big.name = "big".into();
+        let pools = vec![default_pool(vec![("cpx22", 100)]), big];
+        let offerings = vec![
+            offering("cpx22", 2, 4096, 0.01),
+            offering("cx52", 16, 32768, 0.1),
+        ];
+        let demands = || {
+            vec![
+                pod("small", 1, 1024),
+                pod("large", 8, 8192),
+                pod_with_pool("pinned", 1, 1024, "big"),
+            ]
+        };
+        let names = |demands: Vec<PodResources>| -> Vec<String> {
+            demands.into_iter().map(|d| d.id.name).collect()
+        };
+
+        // The shard owning "big" gets the selectorless pod only it fits.
+        let owned = HashSet::from(["big"]);
+        assert_eq!(
+            names(shard_demands(demands(), &pools, &owned, &offerings)),
+            vec!["large", "pinned"]
+        );
+        let owned = HashSet::from(["default"]);
+        assert_eq!(
+            names(shard_demands(demands(), &pools, &owned, &offerings)),
+            vec!["small"]
+        );
+
+        // Without a "default" pool, selectorless pods are still solved.
+        let owned = HashSet::from(["big"]);
+        assert_eq!(
+            names(shard_demands(demands(), &pools[1..], &owned, &offerings)),
+            vec!["small", "large", "pinned"]
+        );
+    }
+
+    #[test]
+    fn node_selector_picks_the_selected_instance_type() {
+        use crate::offering::INSTANCE_TYPE_LABEL;
+
+        let pinned = PodResources {
+            node_selector: BTreeMap::from([(INSTANCE_TYPE_LABEL.into(), "cpx32".into())]),
+            ..pod("pinned", 1, 1024)
+        };
+        let state = ClusterState {
+            demands: vec![pinned],
+            offerings: vec![
+                offering("cpx22", 2, 4096, 0.01),
+                offering("cpx32", 4, 8192, 0.02),
+            ],
+            occupied_counts: HashMap::new(),
+            pools: vec![default_pool(vec![("cpx22", 100), ("cpx32", 100)])],
+            in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
+        };
+        let result = reconcile_pod_demand(state);
+        assert_eq!(result.demands.len(), 1);
+        assert_eq!(result.demands[0].target_offering.instance_type.0, "cpx32");
+    }
+
+    #[test]
+    fn dominated_instance_types_are_skipped_unless_selected() {
+        use crate::offering::INSTANCE_TYPE_LABEL;
+
+        let state = |demand: PodResources| {
+            let mut pool = default_pool(vec![("cpx32", 100), ("cx32", 100)]);
+            pool.labels = BTreeMap::from([("team".into(), "web".into())]);
+            let mut state = default_state(
+                vec![demand],
+                vec![
+                    // Smaller and dearer than cx32.
+                    offering("cpx32", 2, 4096, 0.03),
+                    offering("cx32", 4, 8192, 0.02),
+                ],
+            );
+            state.pools = vec![pool];
+            state
+        };
+        let result = reconcile_pod_demand(state(pod("a", 1, 1024)));
+        assert_eq!(result.solver_stats["default"].dominated, 1);
+        assert_eq!(result.solver_stats["default"].candidates_scored, 1);
+        assert_eq!(result.demands[0].target_offering.instance_type.0, "cx32");
+
+        // A pod selecting the dominated type by its instance-type label keeps it.
+        let pinned = PodResources {
+            node_selector: BTreeMap::from([(INSTANCE_TYPE_LABEL.into(), "cpx32".into())]),
+            ..pod("pinned", 1, 1024)
+        };
+        let result = reconcile_pod_demand(state(pinned));
+        assert_eq!(result.solver_stats["default"].dominated, 0);
+        assert_eq!(result.demands[0].target_offering.instance_type.0, "cpx32");
+    }
+
+    #[test]
+    fn tainted_pool_only_provisions_for_tolerating_pods() {
+        use k8s_openapi::api::core::v1::Toleration;
+
+        let mut pool = default_pool(vec![("cpx22", 100)]);
+        pool.taints = vec![Taint {
+            key: "dedicated".into(),
+            value: Some("batch".into()),
+            effect: "NoSchedule".into(),
+            ..Default::default()
+        }];
+        let tolerating = PodResources {
+            tolerations: vec![Toleration {
+                key: Some("dedicated".into()),
+                operator: Some("Exists".into()),
+                ..Default::default()
+            }],
+            ..pod("batch", 1, 1024)
+        };
+        let state = ClusterState {
+            demands: vec![pod("web", 1, 1024), tolerating],
+            offerings: vec![offering("cpx22", 2, 4096, 0.01)],
+            occupied_counts: HashMap::new(),
+            pools: vec![pool],
+            in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
+        };
+        let result = reconcile_pod_demand(state);
+        assert_eq!(result.demands.len(), 1);
+        assert_eq!(
+            result.demands[0].claimed_pods,
+            vec!["uid-batch".to_string()]
+        );
+    }
+
+    #[test]
+    fn daemonset_overhead_is_reserved_on_new_nodes() {
+        let everywhere = pod("kube-proxy", 1, 512);
+        let other_pool = PodResources {
+            node_selector: BTreeMap::from([(POOL_LABEL.into(), "gpu".into())]),
+            ..pod("dcgm-exporter", 2, 1024)
+        };
+        let state = ClusterState {
+            demands: vec![pod("web", 2, 2048)],
+            offerings: vec![
+                offering("cpx22", 2, 4096, 0.01),
+                offering("cx32", 4, 8192, 0.02),
+            ],
+            occupied_counts: HashMap::new(),
+            pools: vec![default_pool(vec![("cpx22", 100), ("cx32", 100)])],
+            in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![everywhere, other_pool],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
+        };
+        let result = reconcile_pod_demand(state);
+        // kube-proxy leaves a cpx22 one core short; the GPU pool's exporter
+        // doesn't run here.
+        assert_eq!(result.demands.len(), 1);
+        assert_eq!(result.demands[0].target_offering.instance_type.0, "cx32");
+        // The NodeRequest still records the offering's full capacity.
+        assert_eq!(result.demands[0].target_offering.resources.cpu_millis, 4000);
+    }
+
+    #[test]
+    fn spare_capacity_on_cluster_nodes_is_used_first() {
+        let node = |cpu, memory_mib| ExistingNode {
+            resources: res(cpu, memory_mib),
+            labels: BTreeMap::from([(POOL_LABEL.into(), "default".into())]),
+            taints: vec![],
+        };
+        let mut state = default_state(
+            vec![pod("fits", 1, 1024), pod("too-big", 2, 2048)],
+            vec![offering("cpx22", 2, 4096, 0.01)],
+        );
+        state.cluster_nodes = HashMap::from([("default".into(), vec![node(1, 2048)])]);
+
+        let result = reconcile_pod_demand(state);
+        // Only the pod that doesn't fit the free core gets a new node.
+        assert_eq!(result.demands.len(), 1);
+        assert_eq!(
+            result.demands[0].claimed_pods,
+            vec!["uid-too-big".to_string()]
+        );
+    }
+
     // --- In-flight pre-seeded tests ---
 
     #[test]
@@ -859,24 +1951,42 @@ mod tests {
         let gpu_pool = PoolConfig {
             name: "gpu".to_string(),
             uid: "gpu-uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 100,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         let cpu_pool = PoolConfig {
             name: "cpu".to_string(),
             uid: "cpu-uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "cpx22".to_string(),
                 max: 100,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
 
         let state = ClusterState {
@@ -889,8 +1999,16 @@ mod tests {
                 vec![ExistingNode {
                     resources: res(4, 8192),
                     labels: BTreeMap::new(),
+                    taints: vec![],
                 }],
             )]),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         assert_eq!(
@@ -900,6 +2018,256 @@ mod tests {
         );
     }
 
+    #[test]
+    fn penalised_offering_loses_to_reliable_alternative() {
+        let cheap = offering("flaky", 2, 4096, 0.010);
+        let reliable = offering("steady", 2, 4096, 0.012);
+        let mut state = default_state(vec![pod("a", 1, 1024)], vec![cheap.clone(), reliable]);
+
+        let unpenalised = reconcile_pod_demand(default_state(
+            state.demands.clone(),
+            state.offerings.clone(),
+        ));
+        assert_eq!(
+            unpenalised.demands[0].target_offering.instance_type.0,
+            "flaky"
+        );
+
+        state.offering_penalties = HashMap::from([(cheap.stats_key(), 1.0)]);
+        let result = reconcile_pod_demand(state);
+        assert_eq!(result.demands[0].target_offering.instance_type.0, "steady");
+    }
+
+    #[test]
+    fn quota_is_shared_across_pools() {
+        use crate::offering::Region;
+
+        let cpx = offering("cpx22", 2, 4096, 0.01);
+        let mut gpu_pool = default_pool(vec![("cpx22", 100)]);
+        gpu_pool.name = "gpu".into();
+        let mut state = default_state(
+            vec![
+                pod("a", 2, 1024),
+                pod("b", 2, 1024),
+                pod_with_pool("c", 2, 1024, "gpu"),
+            ],
+            vec![cpx],
+        );
+        state.pools.push(gpu_pool);
+        state.quotas = vec![Quota {
+            provider: "fake".into(),
+            region: Region("eu-central".into()),
+            vcpus: Some(5),
+            instances: None,
+        }];
+
+        // Room for two 2-vCPU nodes in total, whichever pools ask first.
+        let result = reconcile_pod_demand(state);
+        assert_eq!(result.demands.len(), 2);
+    }
+
+    #[test]
+    fn new_node_limit_is_shared_fairly_across_pools() {
+        let mut batch = default_pool(vec![("cpx22", 100)]);
+        batch.name = "batch".into();
+        let mut state = default_state(
+            vec![
+                pod("a1", 2, 1024),
+                pod("a2", 2, 1024),
+                pod("a3", 2, 1024),
+                pod_with_pool("b1", 2, 1024, "batch"),
+                pod_with_pool("b2", 2, 1024, "batch"),
+            ],
+            vec![offering("cpx22", 2, 4096, 0.01)],
+        );
+        state.pools.push(batch);
+        state.pools[0].solve_options.max_new_nodes = Some(2);
+        state.max_new_nodes = Some(3);
+
+        let result = reconcile_pod_demand(state);
+        let per_pool = |name: &str| result.demands.iter().filter(|d| d.pool == name).count();
+        // The default pool's own cap leaves a3 unmet; the global cap then
+        // takes turns between the pools.
+        assert_eq!(result.solver_stats["default"].unmet, 1);
+        assert_eq!(per_pool("batch"), 2);
+        assert_eq!(per_pool("default"), 1);
+    }
+
+    #[test]
+    fn max_nodes_caps_the_pool_across_types() {
+        let mut state = default_state(
+            vec![pod("a1", 2, 1024), pod("a2", 2, 1024), pod("a3", 2, 1024)],
+            vec![
+                offering("cpx22", 2, 4096, 0.01),
+                offering("cpx32", 2, 8192, 0.02),
+            ],
+        );
+        state.pools[0].max_nodes = Some(3);
+        state.occupied_counts = HashMap::from([(
+            "default".to_string(),
+            HashMap::from([("cpx32".to_string(), 1)]),
+        )]);
+
+        let result = reconcile_pod_demand(state);
+        // One node already counts against the pool's three.
+        assert_eq!(result.demands.len(), 2);
+        assert_eq!(result.unplaced.len(), 1);
+    }
+
+    #[test]
+    fn floor_meets_type_minimums_then_the_pool_minimum_cheaply() {
+        let mut pool = default_pool(vec![("cpx22", 10), ("cpx32", 10)]);
+        pool.server_types[1].min = 1;
+        pool.min_nodes = 3;
+        let offerings = vec![
+            offering("cpx22", 2, 4096, 0.01),
+            offering("cpx32", 4, 8192, 0.02),
+        ];
+        let occupied = HashMap::from([(
+            "default".to_string(),
+            HashMap::from([("cpx22".to_string(), 1)]),
+        )]);
+        let planned = |pool: &PoolConfig| -> Vec<String> {
+            floor_demands(std::slice::from_ref(pool), &offerings, &occupied)
+                .into_iter()
+                .inspect(|d| assert!(d.claimed_pods.is_empty()))
+                .map(|d| d.target_offering.instance_type.0)
+                .collect()
+        };
+
+        // cpx32 for its own minimum, then the cheapest type for the third node.
+        assert_eq!(planned(&pool), ["cpx32", "cpx22"]);
+
+        pool.max_nodes = Some(2);
+        assert_eq!(planned(&pool), ["cpx32"]);
+
+        pool.server_types[0].max = 1;
+        pool.max_nodes = None;
+        assert_eq!(planned(&pool), ["cpx32", "cpx32"]);
+    }
+
+    #[test]
+    fn headroom_placeholders_fill_spare_room_then_new_nodes() {
+        let offerings = vec![offering("cpx22", 2, 4096, 0.01)];
+        let mut state = default_state(vec![], offerings.clone());
+        state.pools[0].taints = vec![Taint {
+            key: "dedicated".into(),
+            effect: "NoSchedule".into(),
+            ..Default::default()
+        }];
+        state.pools[0].headroom = Some(Headroom {
+            replicas: 3,
+            cpu_millis: 1000,
+            memory_mib: 1024,
+        });
+        state.demands = headroom_demands(&state.pools);
+        assert_eq!(state.demands.len(), 3);
+        assert!(state.demands.iter().all(|d| d.priority == i32::MIN));
+
+        // Three 1-core placeholders need two 2-core nodes.
+        let result = reconcile_pod_demand(state);
+        assert_eq!(result.demands.len(), 2);
+
+        // A node already in flight takes two of them.
+        let mut state = default_state(vec![], offerings);
+        state.pools[0].headroom = Some(Headroom {
+            replicas: 3,
+            cpu_millis: 1000,
+            memory_mib: 1024,
+        });
+        state.demands = headroom_demands(&state.pools);
+        state.in_flight_nodes.insert(
+            "default".into(),
+            vec![ExistingNode {
+                resources: res(2, 4096),
+                labels: BTreeMap::new(),
+                taints: vec![],
+            }],
+        );
+        assert_eq!(reconcile_pod_demand(state).demands.len(), 1);
+    }
+
+    #[test]
+    fn bounded_solve_concurrency_plans_the_same_nodes() {
+        let state = |solve_concurrency| {
+            let mut state = default_state(
+                vec![
+                    pod("a1", 2, 1024),
+                    pod_with_pool("b1", 2, 1024, "batch"),
+                    pod_with_pool("b2", 2, 1024, "batch"),
+                    pod_with_pool("c1", 2, 1024, "ci"),
+                ],
+                vec![offering("cpx22", 2, 4096, 0.01)],
+            );
+            for name in ["batch", "ci"] {
+                let mut pool = default_pool(vec![("cpx22", 100)]);
+                pool.name = name.into();
+                state.pools.push(pool);
+            }
+            state.max_new_nodes = Some(3);
+            state.solve_concurrency = solve_concurrency;
+            state
+        };
+        let planned = |result: ReconcileResult| -> Vec<(String, Vec<String>)> {
+            result
+                .demands
+                .into_iter()
+                .map(|d| (d.pool, d.claimed_pods))
+                .collect()
+        };
+
+        let unbounded = planned(reconcile_pod_demand(state(0)));
+        assert_eq!(unbounded.len(), 3);
+        for workers in [1, 2] {
+            assert_eq!(planned(reconcile_pod_demand(state(workers))), unbounded);
+        }
+    }
+
+    #[test]
+    fn pools_competing_for_quota_are_solved_together() {
+        use crate::offering::Region;
+
+        let named = |name: &str, server_type: &str| {
+            let mut pool = default_pool(vec![(server_type, 100)]);
+            pool.name = name.into();
+            pool
+        };
+        let mut arm = offering("cax11", 2, 4096, 0.01);
+        arm.location.region = Region("us-east".into());
+        let offerings = vec![offering("cpx22", 2, 4096, 0.01), arm];
+        let (a, b, c) = (
+            named("a", "cpx22"),
+            named("b", "cpx22"),
+            named("c", "cax11"),
+        );
+        let pools = [("a", &a), ("b", &b), ("c", &c)];
+        let names = |groups: Vec<PoolGroup>| -> Vec<Vec<String>> {
+            groups
+                .into_iter()
+                .map(|g| g.into_iter().map(|(n, _)| n.to_string()).collect())
+                .collect()
+        };
+
+        // Without quota every pool stands alone.
+        let unlimited = QuotaBudget::unlimited();
+        assert_eq!(
+            names(partition_pools(&pools, &offerings, &unlimited)),
+            [vec!["a"], vec!["b"], vec!["c"]]
+        );
+
+        // A quota on eu-central ties the two cpx22 pools together.
+        let quotas = QuotaBudget::new(&[Quota {
+            provider: "fake".into(),
+            region: Region("eu-central".into()),
+            vcpus: Some(4),
+            instances: None,
+        }]);
+        assert_eq!(
+            names(partition_pools(&pools, &offerings, &quotas)),
+            [vec!["a", "b"], vec!["c"]]
+        );
+    }
+
     #[test]
     fn no_matching_offerings_produces_zero_demands() {
         // Pool references "nonexistent" server type, but only "cpx22" offerings exist.
@@ -907,13 +2275,22 @@ mod tests {
         let pool = PoolConfig {
             name: "default".to_string(),
             uid: "uid".to_string(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             server_types: vec![ServerTypeConfig {
                 name: "nonexistent".to_string(),
                 max: 100,
                 min: 0,
             }],
             labels: BTreeMap::new(),
+            taints: vec![],
             locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
         };
         let state = ClusterState {
             demands: vec![pod("a", 1, 1024)],
@@ -921,6 +2298,13 @@ mod tests {
             occupied_counts: HashMap::new(),
             pools: vec![pool],
             in_flight_nodes: HashMap::new(),
+            offering_penalties: HashMap::new(),
+            quotas: vec![],
+            daemonsets: vec![],
+            cluster_nodes: HashMap::new(),
+            solver: &ScoredSolver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
         };
         let result = reconcile_pod_demand(state);
         assert!(
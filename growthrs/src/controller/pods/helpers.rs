@@ -1,8 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::api::apps::v1::DaemonSet;
+use k8s_openapi::api::core::v1::{Node, PersistentVolume, Pod};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use k8s_openapi::jiff::Timestamp;
 
-use crate::offering::Offering;
+use tracing::warn;
+
+use crate::offering::{
+    IGNORE_ANNOTATION, NODE_REQUEST_LABEL, Offering, POOL_LABEL, PodResources, QuantityParseError,
+    STARTUP_TAINT_KEY, ZONE_LABEL, zones_from_selector_terms,
+};
+use crate::optimiser::ExistingNode;
+use crate::planner::Capacity;
 
 /// Check whether a Pod has the `PodScheduled=False/Unschedulable` condition.
 pub fn is_pod_unschedulable(pod: &Pod) -> bool {
@@ -19,6 +29,29 @@ pub fn is_pod_unschedulable(pod: &Pod) -> bool {
         .unwrap_or(false)
 }
 
+/// When the pod's `PodScheduled` condition last changed to `status`, if it
+/// currently has that status.
+pub fn pod_scheduled_since(pod: &Pod, status: &str) -> Option<Timestamp> {
+    pod.status
+        .as_ref()?
+        .conditions
+        .as_ref()?
+        .iter()
+        .find(|c| c.type_ == "PodScheduled" && c.status == status)?
+        .last_transition_time
+        .as_ref()
+        .map(|t| t.0)
+}
+
+/// Whether the pod opted out of provisioning with [`IGNORE_ANNOTATION`].
+pub fn is_pod_ignored(pod: &Pod) -> bool {
+    pod.metadata
+        .annotations
+        .as_ref()
+        .and_then(|a| a.get(IGNORE_ANNOTATION))
+        .is_some_and(|v| v == "true")
+}
+
 pub fn is_daemonset_pod(pod: &Pod) -> bool {
     pod.metadata
         .owner_references
@@ -27,6 +60,140 @@ pub fn is_daemonset_pod(pod: &Pod) -> bool {
         .unwrap_or(false)
 }
 
+/// The pod `ds` runs on each node it selects, for reserving its requests on
+/// new nodes. Unlike a pending pod's, its `node_selector` keeps every key
+/// (pool, zone, GPU model and architecture included), since it is matched
+/// against a node's labels rather than used to pick an offering.
+pub fn daemonset_pod_resources(ds: &DaemonSet) -> Result<PodResources, QuantityParseError> {
+    let template = ds
+        .spec
+        .as_ref()
+        .map(|s| s.template.clone())
+        .unwrap_or_default();
+    let pod = Pod {
+        metadata: ObjectMeta {
+            name: ds.metadata.name.clone(),
+            namespace: ds.metadata.namespace.clone(),
+            ..template.metadata.unwrap_or_default()
+        },
+        spec: template.spec,
+        status: None,
+    };
+    let mut resources = PodResources::from_pod(&pod)?;
+    resources.node_selector = pod
+        .spec
+        .as_ref()
+        .and_then(|s| s.node_selector.clone())
+        .unwrap_or_default();
+    Ok(resources)
+}
+
+/// Whether new pods can be scheduled onto `node`: it is Ready, not cordoned
+/// or being deleted, and past its startup taint.
+fn is_node_schedulable(node: &Node) -> bool {
+    let ready = node
+        .status
+        .as_ref()
+        .and_then(|s| s.conditions.as_ref())
+        .is_some_and(|conditions| {
+            conditions
+                .iter()
+                .any(|c| c.type_ == "Ready" && c.status == "True")
+        });
+    let spec = node.spec.as_ref();
+    let cordoned = spec.and_then(|s| s.unschedulable).unwrap_or(false);
+    let starting = spec
+        .and_then(|s| s.taints.as_ref())
+        .is_some_and(|taints| taints.iter().any(|t| t.key == STARTUP_TAINT_KEY));
+    ready && !cordoned && !starting && node.metadata.deletion_timestamp.is_none()
+}
+
+/// Spare capacity on each pool's schedulable nodes, for the solver to place
+/// pods on before buying new ones. Nodes of an in-flight NodeRequest (named
+/// in `in_flight_nrs`) are left out, as that NodeRequest already counts
+/// their whole capacity.
+pub fn free_capacity_by_pool(
+    nodes: &[Node],
+    bound_pods: &[Pod],
+    in_flight_nrs: &HashSet<String>,
+) -> HashMap<String, Vec<ExistingNode>> {
+    let mut by_pool: HashMap<String, Vec<ExistingNode>> = HashMap::new();
+    for node in nodes.iter().filter(|n| is_node_schedulable(n)) {
+        let labels = node.metadata.labels.as_ref();
+        let Some(pool) = labels.and_then(|l| l.get(POOL_LABEL)) else {
+            continue;
+        };
+        if labels
+            .and_then(|l| l.get(NODE_REQUEST_LABEL))
+            .is_some_and(|nr| in_flight_nrs.contains(nr))
+        {
+            continue;
+        }
+        let capacity = match Capacity::from_node(node, bound_pods) {
+            Ok(capacity) => capacity,
+            Err(e) => {
+                warn!(
+                    node = ?node.metadata.name,
+                    error = %e,
+                    "skipping node with unparseable requests"
+                );
+                continue;
+            }
+        };
+        by_pool.entry(pool.clone()).or_default().push(ExistingNode {
+            resources: capacity.resources,
+            labels: capacity.labels,
+            taints: node
+                .spec
+                .as_ref()
+                .and_then(|s| s.taints.clone())
+                .unwrap_or_default(),
+        });
+    }
+    by_pool
+}
+
+/// The node the scheduler has nominated `pod` for while it preempts
+/// lower-priority pods there to make room.
+pub fn nominated_node(pod: &Pod) -> Option<&str> {
+    pod.status
+        .as_ref()
+        .and_then(|s| s.nominated_node_name.as_deref())
+        .filter(|n| !n.is_empty())
+}
+
+/// Whether `pod` will be placed by an in-progress preemption rather than new
+/// capacity. A nomination onto a node that no longer exists is stale.
+pub fn is_awaiting_preemption(pod: &Pod, node_names: &HashSet<String>) -> bool {
+    nominated_node(pod).is_some_and(|n| node_names.contains(n))
+}
+
+/// Names of the PersistentVolumeClaims `pod` mounts.
+pub fn pod_claim_names(pod: &Pod) -> impl Iterator<Item = &str> {
+    pod.spec
+        .iter()
+        .flat_map(|s| s.volumes.iter().flatten())
+        .filter_map(|v| v.persistent_volume_claim.as_ref())
+        .map(|pvc| pvc.claim_name.as_str())
+}
+
+/// Zones a PersistentVolume can be attached in, from its required node
+/// affinity or, for older provisioners, its zone label.
+pub fn pv_zones(pv: &PersistentVolume) -> Option<Vec<String>> {
+    pv.spec
+        .as_ref()
+        .and_then(|s| s.node_affinity.as_ref())
+        .and_then(|na| na.required.as_ref())
+        .and_then(|sel| zones_from_selector_terms(&sel.node_selector_terms))
+        .or_else(|| {
+            pv.metadata
+                .labels
+                .as_ref()
+                .and_then(|l| l.get(ZONE_LABEL))
+                .map(|z| vec![z.clone()])
+        })
+}
+
 /// Merge NodeRequest in-flight counts and existing node counts into a single occupied map.
 pub fn merge_occupied_counts(
     nr_counts: HashMap<String, HashMap<String, u32>>,
@@ -58,7 +225,7 @@ mod tests {
     use k8s_openapi::api::core::v1::{PodCondition, PodStatus};
     use kube::api::ObjectMeta;
 
-    use crate::offering::{InstanceType, Location, Region, Resources, Zone};
+    use crate::testing::offering;
 
     fn pod_with_conditions(conditions: Option<Vec<PodCondition>>) -> Pod {
         Pod {
@@ -177,6 +344,60 @@ mod tests {
         assert!(!is_daemonset_pod(&pod));
     }
 
+    // --- is_awaiting_preemption ---
+
+    #[test]
+    fn nominated_pod_awaits_preemption_on_existing_node() {
+        let mut pod = pod_with_conditions(None);
+        let nodes = HashSet::from(["node-a".to_string()]);
+        assert!(!is_awaiting_preemption(&pod, &nodes));
+
+        pod.status.as_mut().unwrap().nominated_node_name = Some("node-a".into());
+        assert!(is_awaiting_preemption(&pod, &nodes));
+
+        // The nominated node has since been deleted.
+        pod.status.as_mut().unwrap().nominated_node_name = Some("node-b".into());
+        assert!(!is_awaiting_preemption(&pod, &nodes));
+    }
+
+    // --- pv_zones ---
+
+    #[test]
+    fn pv_zones_from_node_affinity_or_label() {
+        use k8s_openapi::api::core::v1::{
+            NodeSelector, NodeSelectorRequirement, NodeSelectorTerm, PersistentVolumeSpec,
+            VolumeNodeAffinity,
+        };
+        let term = |zones: &[&str]| NodeSelectorTerm {
+            match_expressions: Some(vec![NodeSelectorRequirement {
+                key: ZONE_LABEL.into(),
+                operator: "In".into(),
+                values: Some(zones.iter().map(|z| z.to_string()).collect()),
+            }]),
+            ..Default::default()
+        };
+        let mut pv = PersistentVolume {
+            spec: Some(PersistentVolumeSpec {
+                node_affinity: Some(VolumeNodeAffinity {
+                    required: Some(NodeSelector {
+                        node_selector_terms: vec![term(&["zone-a"]), term(&["zone-b"])],
+                    }),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            pv_zones(&pv),
+            Some(vec!["zone-a".to_string(), "zone-b".to_string()])
+        );
+
+        pv.spec = None;
+        assert_eq!(pv_zones(&pv), None);
+        pv.metadata.labels = Some([(ZONE_LABEL.to_string(), "zone-c".to_string())].into());
+        assert_eq!(pv_zones(&pv), Some(vec!["zone-c".to_string()]));
+    }
+
     // --- merge_occupied_counts ---
 
     #[test]
@@ -213,26 +434,13 @@ mod tests {
 
     // --- lookup_zone ---
 
-    fn test_offering(
-        name: &str,
-        region: &str,
-        zone: Option<&str>,
-    ) -> Offering {
-        Offering {
-            instance_type: InstanceType(name.into()),
-            resources: Resources {
-                cpu: 2,
-                memory_mib: 4096,
-                ephemeral_storage_gib: None,
-                gpu: 0,
-                gpu_model: None,
-            },
-            cost_per_hour: 0.01,
-            location: Location {
-                region: Region(region.into()),
-                zone: zone.map(|z| Zone(z.into())),
-            },
+    fn test_offering(name: &str, region: &str, zone: Option<&str>) -> Offering {
+        let offering = offering(name).with_region(region);
+        match zone {
+            Some(zone) => offering.with_zone(zone),
+            None => offering,
         }
+        .build()
     }
 
     #[test]
@@ -282,4 +490,108 @@ mod tests {
     fn lookup_zone_empty_offerings() {
         assert_eq!(lookup_zone(&[], "cpx22", "eu-central"), None);
     }
+
+    // --- free_capacity_by_pool ---
+
+    #[test]
+    fn free_capacity_skips_unschedulable_and_in_flight_nodes() {
+        use std::collections::BTreeMap;
+
+        use k8s_openapi::api::core::v1::{NodeCondition, NodeSpec, NodeStatus};
+        use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+        use crate::offering::{NODE_REQUEST_LABEL, POOL_LABEL};
+
+        let node = |name: &str, unschedulable: bool| Node {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                labels: Some(BTreeMap::from([
+                    (POOL_LABEL.into(), "default".into()),
+                    (NODE_REQUEST_LABEL.into(), format!("nr-{name}")),
+                ])),
+                ..Default::default()
+            },
+            spec: Some(NodeSpec {
+                unschedulable: Some(unschedulable),
+                ..Default::default()
+            }),
+            status: Some(NodeStatus {
+                allocatable: Some(BTreeMap::from([
+                    ("cpu".into(), Quantity("4".into())),
+                    ("memory".into(), Quantity("8Gi".into())),
+                ])),
+                conditions: Some(vec![NodeCondition {
+                    type_: "Ready".into(),
+                    status: "True".into(),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+        };
+        let nodes = vec![
+            node("free", false),
+            node("cordoned", true),
+            node("joining", false),
+        ];
+        let in_flight = HashSet::from(["nr-joining".to_string()]);
+
+        let by_pool = free_capacity_by_pool(&nodes, &[], &in_flight);
+        let free = &by_pool["default"];
+        assert_eq!(free.len(), 1);
+        assert_eq!(free[0].resources.cpu_millis, 4000);
+        assert_eq!(free[0].resources.memory_mib, 8192);
+    }
+
+    // --- daemonset_pod_resources ---
+
+    #[test]
+    fn daemonset_template_keeps_its_whole_selector() {
+        use std::collections::BTreeMap;
+
+        use k8s_openapi::api::apps::v1::DaemonSetSpec;
+        use k8s_openapi::api::core::v1::{
+            Container, PodSpec, PodTemplateSpec, ResourceRequirements,
+        };
+        use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+        use crate::offering::POOL_LABEL;
+
+        let ds = DaemonSet {
+            metadata: ObjectMeta {
+                name: Some("node-exporter".into()),
+                namespace: Some("monitoring".into()),
+                ..Default::default()
+            },
+            spec: Some(DaemonSetSpec {
+                template: PodTemplateSpec {
+                    metadata: None,
+                    spec: Some(PodSpec {
+                        containers: vec![Container {
+                            name: "exporter".into(),
+                            resources: Some(ResourceRequirements {
+                                requests: Some(BTreeMap::from([
+                                    ("cpu".into(), Quantity("1".into())),
+                                    ("memory".into(), Quantity("256Mi".into())),
+                                ])),
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        }],
+                        node_selector: Some(BTreeMap::from([
+                            (POOL_LABEL.into(), "gpu".into()),
+                            (ZONE_LABEL.into(), "fsn1-dc14".into()),
+                        ])),
+                        ..Default::default()
+                    }),
+                },
+                ..Default::default()
+            }),
+            status: None,
+        };
+        let resources = daemonset_pod_resources(&ds).unwrap();
+        assert_eq!(resources.id.name, "node-exporter");
+        assert_eq!(resources.resources.cpu_millis, 1000);
+        assert_eq!(resources.resources.memory_mib, 256);
+        assert_eq!(resources.node_selector.len(), 2);
+    }
 }
@@ -3,47 +3,68 @@ mod decision;
 mod helpers;
 pub(crate) mod watcher;
 pub use decision::*;
-pub use helpers::{is_daemonset_pod, is_pod_unschedulable};
+pub use helpers::{
+    daemonset_pod_resources, is_awaiting_preemption, is_daemonset_pod, is_pod_ignored,
+    is_pod_unschedulable, nominated_node, pod_claim_names, pod_scheduled_since, pv_zones,
+};
 
-use std::collections::{BTreeMap, HashMap, HashSet};
-use std::time::Duration;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::time::{Duration, Instant};
 
-use k8s_openapi::api::core::v1::{Node, Pod};
+use k8s_openapi::api::apps::v1::DaemonSet;
+use k8s_openapi::api::core::v1::{Node, PersistentVolume, Pod};
 use k8s_openapi::jiff::{SignedDuration, Timestamp};
 use kube::api::ListParams;
 use kube::{Api, Client};
-use tracing::{debug, instrument, warn};
+use tracing::{debug, info, instrument, warn};
 
+use crate::controller::audit::{AuditLog, SolveAudit};
+use crate::controller::claims::helpers::{set_nomination, update_claims};
+use crate::controller::cost_guard::{CostAnomaly, CostGuard};
 use crate::controller::errors::ControllerError;
-use crate::controller::node_requests::helpers::create_node_request;
-use crate::controller::node_requests::is_unmet_expired;
-use crate::offering::{INSTANCE_TYPE_LABEL, MANAGED_BY_SELECTOR, Offering, POOL_LABEL, Resources};
-use crate::optimiser::ExistingNode;
-use crate::providers::provider::Provider;
+use crate::controller::node_requests::helpers::{create_node_request, requested_offering};
+use crate::controller::pod_scope::PodScope;
+use crate::controller::provider_health::drop_unhealthy_offerings;
+use crate::controller::shard::Shard;
+use crate::offering::{
+    ALLOW_COST_SPIKE_ANNOTATION, INSTANCE_TYPE_LABEL, InstanceType, Location, MANAGED_BY_SELECTOR,
+    Offering, POOL_LABEL, PodId, PodResources, Resources, Zone, node_labels,
+};
+use crate::optimiser::{ExistingNode, SolveOptions, SolveStats, SolverBackend};
+use crate::providers::provider::{Provider, Quota};
 use crate::resources::node_pool::NodePool;
 use crate::resources::node_request::{NodeRequest, NodeRequestPhase, NodeRequestSpec};
 
-use helpers::{lookup_zone, merge_occupied_counts};
+use helpers::{free_capacity_by_pool, lookup_zone, merge_occupied_counts};
 
 /// Entries older than this are expired regardless of API state.
 const UNCONFIRMED_CREATES_TTL: Duration = Duration::from_secs(60);
 
+/// How long one reconcile's pool solves may run before the pods not yet
+/// placed are left for the next one.
+pub(crate) const SOLVE_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// In-flight NodeRequest capacity — enough data to construct an
 /// `ExistingNode` for the solver.
 #[derive(Debug, Clone)]
 struct InFlightCapacity {
+    /// Name of the NodeRequest.
+    nr_name: String,
     pool: String,
-    #[allow(dead_code)] // retained for debug logging
+    /// Provider the node is created on, matching `Offering::provider`.
+    provider: String,
     instance_type: String,
     location: String,
     zone: Option<String>,
     resources: Resources,
+    /// UIDs of the pods the NodeRequest was created for.
+    claimed_pods: Vec<String>,
+    /// Whether the provider has been asked for the node yet (Provisioning).
+    requested: bool,
 }
 
 /// A single NodeRequest we created but haven't yet seen in the API list.
 struct UnconfirmedCreate {
-    /// NodeRequest name (for drain matching against API list).
-    nr_name: String,
     capacity: InFlightCapacity,
     created_at: Timestamp,
 }
@@ -61,24 +82,30 @@ pub struct UnconfirmedCreates {
 
 impl UnconfirmedCreates {
     /// Record a recently-created NodeRequest.
+    #[allow(clippy::too_many_arguments)]
     pub fn record(
         &mut self,
         nr_name: String,
         pool: String,
+        provider: String,
         instance_type: String,
         location: String,
         zone: Option<String>,
         resources: Resources,
+        claimed_pods: Vec<String>,
         now: Timestamp,
     ) {
         self.entries.push(UnconfirmedCreate {
-            nr_name,
             capacity: InFlightCapacity {
+                nr_name,
                 pool,
+                provider,
                 instance_type,
                 location,
                 zone,
                 resources,
+                claimed_pods,
+                requested: false,
             },
             created_at: now,
         });
@@ -89,7 +116,7 @@ impl UnconfirmedCreates {
         let ttl = SignedDuration::from_secs(UNCONFIRMED_CREATES_TTL.as_secs() as i64);
         let before = self.entries.len();
         self.entries.retain(|e| {
-            !api_nr_names.contains(&e.nr_name) && now.duration_since(e.created_at) < ttl
+            !api_nr_names.contains(&e.capacity.nr_name) && now.duration_since(e.created_at) < ttl
         });
         let drained = before - self.entries.len();
         if drained > 0 {
@@ -101,6 +128,18 @@ impl UnconfirmedCreates {
         }
     }
 
+    /// Add `pods` to the claims of the unconfirmed NodeRequest `nr_name`,
+    /// so they are held on it until the API list reflects the patch.
+    fn claim(&mut self, nr_name: &str, pods: &[String]) {
+        for entry in self
+            .entries
+            .iter_mut()
+            .filter(|e| e.capacity.nr_name == nr_name)
+        {
+            entry.capacity.claimed_pods.extend(pods.iter().cloned());
+        }
+    }
+
     /// Get all cached in-flight capacity entries.
     fn entries(&self) -> impl Iterator<Item = &InFlightCapacity> {
         self.entries.iter().map(|e| &e.capacity)
@@ -123,6 +162,24 @@ struct InFlightScan {
     nr_counts: HashMap<String, HashMap<String, u32>>,
     /// Names of all NodeRequests seen, for draining UnconfirmedCreates.
     api_nr_names: HashSet<String>,
+    /// Names of the in-flight NodeRequests, whose nodes are already counted
+    /// through `in_flight` even once they have joined.
+    in_flight_nr_names: HashSet<String>,
+    /// Per pool, the offerings of its Unmet NodeRequests. They are deleted
+    /// once `unmet_ttl` has passed, which lifts the exclusion.
+    unmet_offerings: HashMap<String, Vec<(InstanceType, Location)>>,
+}
+
+impl InFlightScan {
+    /// Keep each pool's solve off the offerings its requests just came back
+    /// Unmet for.
+    fn exclude_unmet_offerings(&self, pools: &mut [PoolConfig]) {
+        for pool in pools {
+            if let Some(unmet) = self.unmet_offerings.get(&pool.name) {
+                pool.unmet_offerings.extend(unmet.iter().cloned());
+            }
+        }
+    }
 }
 
 /// Build an empty UnconfirmedCreates buffer.
@@ -136,24 +193,118 @@ pub fn init_unconfirmed_creates() -> UnconfirmedCreates {
 
 /// One-shot reconcile: gather state, solve, and create any needed NodeRequests.
 #[instrument(skip_all, fields(reconcile_id = %uuid::Uuid::new_v4()))]
-#[allow(unused_variables, unused_assignments)]
+#[allow(unused_variables, unused_assignments, clippy::too_many_arguments)]
 pub async fn reconcile_unschedulable_pods(
     client: Client,
-    provider: &Provider,
+    provider: &dyn Provider,
+    offering_penalties: HashMap<String, f64>,
+    unhealthy_providers: &HashSet<String>,
+    solver: &'static dyn SolverBackend,
+    max_new_nodes: Option<usize>,
+    solve_concurrency: usize,
     unconfirmed_creates: &mut UnconfirmedCreates,
-    unmet_ttl: Duration,
+    cost_guard: &CostGuard,
+    shard: &Shard,
+    pool_defaults: &SolveOptions,
+    scope: &PodScope,
+    audit: Option<&AuditLog>,
+    dry_run: bool,
     now: k8s_openapi::jiff::Timestamp,
-) -> Result<(), ControllerError> {
-    let state = gather_cluster_state(&client, provider, unconfirmed_creates, unmet_ttl, now)
-        .await
-        .map_err(|e| ControllerError::Other(e.into()))?;
-    let result = reconcile_pod_demand(state);
+) -> Result<BTreeMap<String, SolveStats>, ControllerError> {
+    let (mut state, mut in_flight) = gather_cluster_state(
+        &client,
+        provider,
+        solver,
+        unconfirmed_creates,
+        shard,
+        pool_defaults,
+        scope,
+        now,
+    )
+    .await
+    .map_err(|e| ControllerError::Other(e.into()))?;
+    state.offering_penalties = offering_penalties;
+    state.max_new_nodes = max_new_nodes;
+    state.solve_concurrency = solve_concurrency;
+    drop_unhealthy_offerings(&mut state.offerings, unhealthy_providers);
+    let exempt: HashSet<String> = state
+        .pools
+        .iter()
+        .filter(|p| p.allow_cost_spike)
+        .map(|p| p.name.clone())
+        .collect();
+    let audit_inputs = audit.map(|_| {
+        let pools = state.pools.iter().map(|p| p.name.clone()).collect();
+        (state.demands.clone(), pools, state.offerings.len())
+    });
+    let pending = if in_flight.is_empty() {
+        vec![]
+    } else {
+        state.demands.clone()
+    };
+    let pod_ids: HashMap<String, PodId> = state
+        .demands
+        .iter()
+        .map(|p| (p.uid.clone(), p.id.clone()))
+        .collect();
+    let result = solve_off_executor(state).await?;
+    let nominations = nominate_to_in_flight(&mut in_flight, &pending, &result);
 
     for err in &result.pod_errors {
         warn!(pod = %err.pod_id, reason = %err.reason, "pod could not be assigned to a pool");
     }
 
-    for (nr_creates, demand) in result.demands.into_iter().enumerate() {
+    let held = check_cost_guard(cost_guard, &exempt, &result.demands).await;
+    if let (Some(log), Some((pods, pools, offerings))) = (audit, audit_inputs) {
+        log.write(&SolveAudit::new(
+            now,
+            &pods,
+            pools,
+            offerings,
+            &result,
+            |d| held.is_some() && !exempt.contains(&d.pool),
+        ));
+    }
+
+    let mut demands = result.demands;
+    if held.is_some() {
+        demands.retain(|d| exempt.contains(&d.pool));
+    }
+    if dry_run {
+        for demand in &demands {
+            info!(
+                pool = %demand.pool,
+                instance_type = %demand.target_offering.instance_type,
+                region = %demand.target_offering.location.region,
+                cost_per_hour = demand.target_offering.cost_per_hour,
+                pods = demand.claimed_pods.len(),
+                "dry run: would create NodeRequest"
+            );
+        }
+        return Ok(result.solver_stats);
+    }
+
+    for (nr_name, pods) in &nominations {
+        info!(
+            nr = %nr_name,
+            pods = pods.len(),
+            "nominating pending pods to in-flight NodeRequest"
+        );
+        // Added to whatever the NodeRequest claims by the time it's written,
+        // so a release by claim GC in between isn't undone.
+        let nominate = |claims: &[String]| {
+            let added = pods.iter().filter(|uid| !claims.contains(uid));
+            claims.iter().chain(added).cloned().collect()
+        };
+        if let Err(e) = update_claims(&client, nr_name, false, nominate).await {
+            warn!(nr = %nr_name, error = %e, "failed to record pod nominations");
+            continue;
+        }
+        annotate_nominated(&client, nr_name, pods, &pod_ids).await;
+        unconfirmed_creates.claim(nr_name, pods);
+    }
+
+    for (nr_creates, demand) in demands.into_iter().enumerate() {
         fail::fail_point!("reconcile_after_nr_create", |_| {
             Err(ControllerError::FaultInjected(nr_creates))
         });
@@ -165,15 +316,23 @@ pub async fn reconcile_unschedulable_pods(
             NodeRequestSpec {
                 target_offering: demand.target_offering.instance_type.clone(),
                 location: demand.target_offering.location.region.clone(),
+                zone: demand.target_offering.location.zone.clone(),
                 resources: demand.target_offering.resources.clone(),
                 node_id: format!("growth-{}", uuid::Uuid::new_v4()),
+                provider: Some(demand.target_offering.provider.clone()),
+                claimed_pods: demand.claimed_pods.clone(),
+                labels: Some(demand.labels.clone()),
+                taints: Some(demand.taints.clone()),
             },
+            None,
         )
         .await?;
         let nr_name = created.metadata.name.unwrap_or_default();
+        annotate_nominated(&client, &nr_name, &demand.claimed_pods, &pod_ids).await;
         unconfirmed_creates.record(
             nr_name,
             demand.pool,
+            demand.target_offering.provider.clone(),
             demand.target_offering.instance_type.to_string(),
             demand.target_offering.location.region.0.clone(),
             demand
@@ -183,58 +342,118 @@ pub async fn reconcile_unschedulable_pods(
                 .as_ref()
                 .map(|z| z.0.clone()),
             demand.target_offering.resources,
+            demand.claimed_pods,
             now,
         );
     }
-    Ok(())
+    Ok(result.solver_stats)
 }
 
+/// Annotate the pods `uids` with the NodeRequest provisioning for them.
+/// Failures are only logged: the claims on the NodeRequest are what count,
+/// and a pod may be gone already.
+async fn annotate_nominated(
+    client: &Client,
+    nr_name: &str,
+    uids: &[String],
+    pod_ids: &HashMap<String, PodId>,
+) {
+    for pod in uids.iter().filter_map(|uid| pod_ids.get(uid)) {
+        if let Err(e) = set_nomination(client, pod, Some(nr_name)).await {
+            debug!(%pod, nr = %nr_name, error = %e, "failed to annotate nominated pod");
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn gather_cluster_state(
     client: &Client,
-    provider: &Provider,
+    provider: &dyn Provider,
+    solver: &'static dyn SolverBackend,
     unconfirmed_creates: &mut UnconfirmedCreates,
-    unmet_ttl: Duration,
+    shard: &Shard,
+    pool_defaults: &SolveOptions,
+    scope: &PodScope,
     now: k8s_openapi::jiff::Timestamp,
-) -> Result<ClusterState, ControllerError> {
-    let (unschedulable_pods, offerings, node_counts, pools) = tokio::try_join!(
-        get_unschedulable_pods(client.clone()),
+) -> Result<(ClusterState, Vec<InFlightCapacity>), ControllerError> {
+    let (unschedulable_pods, offerings, quotas, nodes, (all_pools, owned), daemonsets) = tokio::try_join!(
+        get_unschedulable_pods(client.clone(), scope),
         async { Ok(provider.offerings().await) },
-        get_node_counts(client.clone()),
-        get_node_pools(client.clone()),
+        async { Ok(get_quotas(provider).await) },
+        get_managed_nodes(client.clone()),
+        get_all_node_pools(client.clone(), shard, pool_defaults),
+        get_daemonsets(client.clone()),
     )?;
-    let scan = scan_node_requests(client.clone(), unmet_ttl, now, &offerings).await?;
+    let mut pools: Vec<PoolConfig> = all_pools
+        .iter()
+        .filter(|p| owned.contains(&p.name))
+        .cloned()
+        .collect();
+    let scan = scan_node_requests(client.clone(), &offerings).await?;
     unconfirmed_creates.drain_reflected(&scan.api_nr_names, now);
+    scan.exclude_unmet_offerings(&mut pools);
 
+    let volume_zones = get_volume_zones(client.clone(), &unschedulable_pods).await?;
     let demands: Vec<_> = unschedulable_pods
         .iter()
         .map(|p| {
-            crate::offering::PodResources::from_pod(p)
-                .map_err(|e| ControllerError::ConfigError(e.into()))
+            let mut demand = crate::offering::PodResources::from_pod(p)?;
+            for claim in pod_claim_names(p) {
+                let key = (demand.id.namespace.clone(), claim.to_string());
+                if let Some(zones) = volume_zones.get(&key) {
+                    demand.restrict_zones(zones.clone());
+                }
+            }
+            Ok(demand)
         })
-        .collect::<std::result::Result<Vec<_>, ControllerError>>()?;
+        .collect::<std::result::Result<Vec<_>, crate::offering::QuantityParseError>>()
+        .map_err(|e| ControllerError::ConfigError(e.into()))?;
+    // Pods for other shards' pools are theirs to solve and report.
+    let demands = if shard.is_sharded() {
+        let owned: HashSet<&str> = owned.iter().map(String::as_str).collect();
+        shard_demands(demands, &all_pools, &owned, &offerings)
+    } else {
+        demands
+    };
 
     // Build in-flight nodes per pool from API scan + UnconfirmedCreates.
-    let in_flight_count = scan.in_flight.len() + unconfirmed_creates.len();
+    let mut in_flight: Vec<InFlightCapacity> = scan
+        .in_flight
+        .iter()
+        .chain(unconfirmed_creates.entries())
+        .cloned()
+        .collect();
+    let in_flight_count = in_flight.len();
+    let quotas = reserve_unrequested_quota(quotas, &in_flight);
+    let demands = reserve_claimed_pods(&mut in_flight, demands);
     let mut in_flight_nodes: HashMap<String, Vec<ExistingNode>> = HashMap::new();
-    for entry in scan.in_flight.iter().chain(unconfirmed_creates.entries()) {
-        let mut labels = BTreeMap::new();
-        labels.insert(
-            "topology.kubernetes.io/region".into(),
-            entry.location.clone(),
+    for entry in &in_flight {
+        let labels = node_labels(
+            &entry.instance_type,
+            &entry.location,
+            entry.zone.as_deref(),
+            &entry.resources,
         );
-        if let Some(ref zone) = entry.zone {
-            labels.insert("topology.kubernetes.io/zone".into(), zone.clone());
-        }
         in_flight_nodes
             .entry(entry.pool.clone())
             .or_default()
             .push(ExistingNode {
                 resources: entry.resources.clone(),
                 labels,
+                taints: vec![],
             });
     }
 
-    let occupied_counts = merge_occupied_counts(scan.nr_counts, node_counts);
+    // Pods the scheduler has just given up on may still fit on a node that
+    // has since freed up; that capacity costs nothing.
+    let cluster_nodes = if demands.is_empty() {
+        HashMap::new()
+    } else {
+        let bound_pods = get_bound_pods(client.clone()).await?;
+        free_capacity_by_pool(&nodes, &bound_pods, &scan.in_flight_nr_names)
+    };
+
+    let occupied_counts = merge_occupied_counts(scan.nr_counts, count_nodes_by_pool(&nodes));
 
     debug!(
         total_unschedulable = unschedulable_pods.len(),
@@ -244,54 +463,554 @@ async fn gather_cluster_state(
         "cluster state gathered"
     );
 
-    Ok(ClusterState {
+    let state = ClusterState {
         demands,
         offerings,
         occupied_counts,
         pools,
         in_flight_nodes,
+        offering_penalties: HashMap::new(),
+        quotas,
+        daemonsets,
+        cluster_nodes,
+        solver,
+        max_new_nodes: None,
+        solve_concurrency: 0,
+    };
+    Ok((state, in_flight))
+}
+
+/// Hold pods on the in-flight nodes that claimed them: their requests come
+/// off that node's capacity and they leave the demand. Otherwise re-packing
+/// every pending pod from scratch could shuffle them across in-flight nodes
+/// and leave some without room, buying a duplicate node for a pod that
+/// already has one on the way.
+fn reserve_claimed_pods(
+    in_flight: &mut [InFlightCapacity],
+    demands: Vec<PodResources>,
+) -> Vec<PodResources> {
+    let mut claimed_by: HashMap<&str, usize> = HashMap::new();
+    for (idx, entry) in in_flight.iter().enumerate() {
+        for uid in &entry.claimed_pods {
+            claimed_by.entry(uid.as_str()).or_insert(idx);
+        }
+    }
+    let mut reserved: Vec<(usize, Resources)> = Vec::new();
+    let unclaimed = demands
+        .into_iter()
+        .filter(|d| match claimed_by.get(d.uid.as_str()) {
+            Some(&idx) => {
+                reserved.push((idx, d.resources.clone()));
+                false
+            }
+            None => true,
+        })
+        .collect();
+    for (idx, resources) in reserved {
+        in_flight[idx].resources.saturating_subtract(&resources);
+    }
+    unclaimed
+}
+
+/// Nominate the `pending` pods the solve placed on an in-flight NodeRequest's
+/// node to that NodeRequest. Each nominated pod is added to its entry's
+/// claims, and the new claims are returned by NodeRequest name.
+///
+/// The solver's placements are read back through
+/// [`ReconcileResult::in_flight_placements`], whose per-pool lists follow
+/// `in_flight` order (see [`gather_cluster_state`]), so nominations honour
+/// everything the solve did: taints, affinity and the pods already packed.
+///
+/// Once recorded on the NodeRequest, [`reserve_claimed_pods`] keeps the pod
+/// out of later solves until the request resolves (claim GC drops the claim
+/// once the pod is scheduled) or is deleted after timing out. Pods placed on
+/// free room on a running node need no gating.
+fn nominate_to_in_flight(
+    in_flight: &mut [InFlightCapacity],
+    pending: &[PodResources],
+    result: &ReconcileResult,
+) -> BTreeMap<String, Vec<String>> {
+    let by_id: HashMap<&PodId, &PodResources> = pending.iter().map(|p| (&p.id, p)).collect();
+    let mut nominations: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (pool, placements) in &result.in_flight_placements {
+        let entries = in_flight.iter_mut().filter(|e| &e.pool == pool);
+        for (entry, pods) in entries.zip(placements) {
+            for pod in pods.iter().filter_map(|id| by_id.get(id)) {
+                entry.resources.saturating_subtract(&pod.resources);
+                entry.claimed_pods.push(pod.uid.clone());
+                nominations
+                    .entry(entry.nr_name.clone())
+                    .or_default()
+                    .push(pod.uid.clone());
+            }
+        }
+    }
+    nominations
+}
+
+/// Check the new nodes of a plan against `cost_guard`, leaving out those of
+/// the `exempt` pools. A held plan is logged and recorded as an Event on
+/// each pool whose nodes it holds; the caller drops those nodes.
+async fn check_cost_guard(
+    cost_guard: &CostGuard,
+    exempt: &HashSet<String>,
+    demands: &[NodeRequestDemand],
+) -> Option<CostAnomaly> {
+    let guarded = demands.iter().filter(|d| !exempt.contains(&d.pool));
+    let planned_cost: f64 = guarded
+        .clone()
+        .map(|d| d.target_offering.cost_per_hour)
+        .sum();
+    let anomaly = cost_guard.admit(planned_cost).err()?;
+    warn!(
+        cost_per_hour = anomaly.cost,
+        limit = anomaly.limit,
+        "plan exceeds the cost anomaly limit, holding its NodeRequests until overridden"
+    );
+    let pools: BTreeSet<(&str, &str)> = guarded
+        .map(|d| (d.pool.as_str(), d.pool_uid.as_str()))
+        .collect();
+    cost_guard.record_hold(&anomaly, pools).await;
+    Some(anomaly)
+}
+
+/// Solve placement for pods displaced from evacuating nodes.
+///
+/// Pool limits still apply, but in-flight capacity is not pre-seeded: the
+/// displaced pods need dedicated replacements, and NodeRequests already in
+/// flight are spoken for by the pending pods that caused them. New nodes
+/// pass the cost guard like any other plan's; a held plan leaves only the
+/// nodes of pools exempt from it, and its anomaly is returned alongside.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn solve_displaced_pods(
+    client: &Client,
+    provider: &dyn Provider,
+    offering_penalties: HashMap<String, f64>,
+    unhealthy_providers: &HashSet<String>,
+    solver: &'static dyn SolverBackend,
+    displaced: Vec<PodResources>,
+    cost_guard: &CostGuard,
+    shard: &Shard,
+    pool_defaults: &SolveOptions,
+) -> Result<(ReconcileResult, Option<CostAnomaly>), ControllerError> {
+    let (mut offerings, quotas, nodes, mut pools, daemonsets) = tokio::try_join!(
+        async { Ok(provider.offerings().await) },
+        async { Ok(get_quotas(provider).await) },
+        get_managed_nodes(client.clone()),
+        get_node_pools(client.clone(), shard, pool_defaults),
+        get_daemonsets(client.clone()),
+    )?;
+    let scan = scan_node_requests(client.clone(), &offerings).await?;
+    scan.exclude_unmet_offerings(&mut pools);
+    drop_unhealthy_offerings(&mut offerings, unhealthy_providers);
+    let quotas = reserve_unrequested_quota(quotas, &scan.in_flight);
+    let exempt: HashSet<String> = pools
+        .iter()
+        .filter(|p| p.allow_cost_spike)
+        .map(|p| p.name.clone())
+        .collect();
+    let mut result = solve_off_executor(ClusterState {
+        demands: displaced,
+        offerings,
+        occupied_counts: merge_occupied_counts(scan.nr_counts, count_nodes_by_pool(&nodes)),
+        pools,
+        in_flight_nodes: HashMap::new(),
+        offering_penalties,
+        quotas,
+        daemonsets,
+        // Spare room on the remaining nodes is for the pending pods.
+        cluster_nodes: HashMap::new(),
+        solver,
+        max_new_nodes: None,
+        solve_concurrency: 0,
+    })
+    .await?;
+    let held = check_cost_guard(cost_guard, &exempt, &result.demands).await;
+    if held.is_some() {
+        result.demands.retain(|d| exempt.contains(&d.pool));
+    }
+    Ok((result, held))
+}
+
+/// Solve placement for the pods of each consolidation candidate node on its
+/// own: spare room on the pool's other schedulable nodes first, then new
+/// nodes. Results come back in candidate order.
+///
+/// As in [`solve_displaced_pods`], in-flight capacity is not pre-seeded.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn solve_consolidations(
+    client: &Client,
+    provider: &dyn Provider,
+    offerings: &[Offering],
+    offering_penalties: HashMap<String, f64>,
+    solver: &'static dyn SolverBackend,
+    candidates: Vec<(String, Vec<PodResources>)>,
+    shard: &Shard,
+    pool_defaults: &SolveOptions,
+) -> Result<Vec<ReconcileResult>, ControllerError> {
+    let (quotas, nodes, mut pools, daemonsets, bound_pods) = tokio::try_join!(
+        async { Ok(get_quotas(provider).await) },
+        get_managed_nodes(client.clone()),
+        get_node_pools(client.clone(), shard, pool_defaults),
+        get_daemonsets(client.clone()),
+        get_bound_pods(client.clone()),
+    )?;
+    let scan = scan_node_requests(client.clone(), offerings).await?;
+    scan.exclude_unmet_offerings(&mut pools);
+    let quotas = reserve_unrequested_quota(quotas, &scan.in_flight);
+    let occupied_counts = merge_occupied_counts(scan.nr_counts, count_nodes_by_pool(&nodes));
+
+    let mut results = Vec::with_capacity(candidates.len());
+    for (node_name, demands) in candidates {
+        let others: Vec<Node> = nodes
+            .iter()
+            .filter(|n| n.metadata.name.as_deref() != Some(node_name.as_str()))
+            .cloned()
+            .collect();
+        let result = solve_off_executor(ClusterState {
+            demands,
+            offerings: offerings.to_vec(),
+            occupied_counts: occupied_counts.clone(),
+            pools: pools.clone(),
+            in_flight_nodes: HashMap::new(),
+            offering_penalties: offering_penalties.clone(),
+            quotas: quotas.clone(),
+            daemonsets: daemonsets.clone(),
+            cluster_nodes: free_capacity_by_pool(&others, &bound_pods, &scan.in_flight_nr_names),
+            solver,
+            max_new_nodes: None,
+            solve_concurrency: 0,
+        })
+        .await?;
+        results.push(result);
+    }
+    Ok(results)
+}
+
+/// The nodes each pool needs to reach its floor of warm nodes (see
+/// [`floor_demands`]), from offerings of healthy providers.
+pub(crate) async fn plan_pool_floors(
+    client: &Client,
+    provider: &dyn Provider,
+    unhealthy_providers: &HashSet<String>,
+    shard: &Shard,
+    pool_defaults: &SolveOptions,
+) -> Result<Vec<NodeRequestDemand>, ControllerError> {
+    let mut pools: Vec<PoolConfig> = get_node_pools(client.clone(), shard, pool_defaults)
+        .await?
+        .into_iter()
+        .filter(|p| p.min_nodes > 0 || p.server_types.iter().any(|st| st.min > 0))
+        .collect();
+    if pools.is_empty() {
+        return Ok(vec![]);
+    }
+    let (mut offerings, nodes) = tokio::try_join!(
+        async { Ok(provider.offerings().await) },
+        get_managed_nodes(client.clone()),
+    )?;
+    let scan = scan_node_requests(client.clone(), &offerings).await?;
+    scan.exclude_unmet_offerings(&mut pools);
+    drop_unhealthy_offerings(&mut offerings, unhealthy_providers);
+    let occupied_counts = merge_occupied_counts(scan.nr_counts, count_nodes_by_pool(&nodes));
+    Ok(floor_demands(&pools, &offerings, &occupied_counts))
+}
+
+/// The nodes each pool needs for its headroom (see [`headroom_demands`]).
+/// The placeholders go on free room on the pool's nodes and on NodeRequests
+/// in flight that claim no pods (warm nodes and earlier headroom) first;
+/// NodeRequests in flight for pending pods are theirs. Planned nodes claim
+/// no pods.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn plan_pool_headroom(
+    client: &Client,
+    provider: &dyn Provider,
+    unhealthy_providers: &HashSet<String>,
+    solver: &'static dyn SolverBackend,
+    shard: &Shard,
+    pool_defaults: &SolveOptions,
+) -> Result<Vec<NodeRequestDemand>, ControllerError> {
+    let mut pools: Vec<PoolConfig> = get_node_pools(client.clone(), shard, pool_defaults)
+        .await?
+        .into_iter()
+        .filter(|p| p.headroom.as_ref().is_some_and(|h| h.replicas > 0))
+        .collect();
+    if pools.is_empty() {
+        return Ok(vec![]);
+    }
+    let (mut offerings, quotas, nodes, daemonsets, bound_pods) = tokio::try_join!(
+        async { Ok(provider.offerings().await) },
+        async { Ok(get_quotas(provider).await) },
+        get_managed_nodes(client.clone()),
+        get_daemonsets(client.clone()),
+        get_bound_pods(client.clone()),
+    )?;
+    let scan = scan_node_requests(client.clone(), &offerings).await?;
+    scan.exclude_unmet_offerings(&mut pools);
+    drop_unhealthy_offerings(&mut offerings, unhealthy_providers);
+    let quotas = reserve_unrequested_quota(quotas, &scan.in_flight);
+
+    let mut in_flight_nodes: HashMap<String, Vec<ExistingNode>> = HashMap::new();
+    for entry in scan.in_flight.iter().filter(|e| e.claimed_pods.is_empty()) {
+        in_flight_nodes
+            .entry(entry.pool.clone())
+            .or_default()
+            .push(ExistingNode {
+                resources: entry.resources.clone(),
+                labels: node_labels(
+                    &entry.instance_type,
+                    &entry.location,
+                    entry.zone.as_deref(),
+                    &entry.resources,
+                ),
+                taints: vec![],
+            });
+    }
+    let result = solve_off_executor(ClusterState {
+        demands: headroom_demands(&pools),
+        offerings,
+        occupied_counts: merge_occupied_counts(scan.nr_counts, count_nodes_by_pool(&nodes)),
+        pools,
+        in_flight_nodes,
+        offering_penalties: HashMap::new(),
+        quotas,
+        daemonsets,
+        cluster_nodes: free_capacity_by_pool(&nodes, &bound_pods, &scan.in_flight_nr_names),
+        solver,
+        max_new_nodes: None,
+        solve_concurrency: 0,
     })
+    .await?;
+    Ok(result
+        .demands
+        .into_iter()
+        .map(|demand| NodeRequestDemand {
+            claimed_pods: vec![],
+            ..demand
+        })
+        .collect())
+}
+
+/// Run [`reconcile_pod_demand`] on the blocking thread pool with a
+/// [`SOLVE_TIMEOUT`] deadline, so a long solve doesn't hold up the watch
+/// streams and other reconciles sharing the async executor.
+async fn solve_off_executor(mut state: ClusterState) -> Result<ReconcileResult, ControllerError> {
+    let deadline = Instant::now() + SOLVE_TIMEOUT;
+    for pool in &mut state.pools {
+        pool.solve_options.deadline = Some(deadline);
+    }
+    tokio::task::spawn_blocking(move || reconcile_pod_demand(state))
+        .await
+        .map_err(|e| ControllerError::Other(e.into()))
 }
 
-async fn get_unschedulable_pods(client: Client) -> Result<Vec<Pod>, ControllerError> {
+/// Take the in-flight nodes the provider hasn't been asked for yet off its
+/// remaining quotas. The provider only counts nodes it has created, so
+/// without this Pending NodeRequests (and ones created too recently to be
+/// listed) would be planned over again out of the same headroom.
+fn reserve_unrequested_quota(mut quotas: Vec<Quota>, in_flight: &[InFlightCapacity]) -> Vec<Quota> {
+    for entry in in_flight.iter().filter(|e| !e.requested) {
+        let Some(quota) = quotas
+            .iter_mut()
+            .find(|q| q.provider == entry.provider && q.region.0 == entry.location)
+        else {
+            continue;
+        };
+        if let Some(vcpus) = &mut quota.vcpus {
+            *vcpus = vcpus.saturating_sub(entry.resources.vcpus());
+        }
+        if let Some(instances) = &mut quota.instances {
+            *instances = instances.saturating_sub(1);
+        }
+    }
+    quotas
+}
+
+/// The provider's remaining quotas. A failed lookup is treated as no quota
+/// information rather than failing the reconcile.
+async fn get_quotas(provider: &dyn Provider) -> Vec<Quota> {
+    provider.quotas().await.unwrap_or_else(|e| {
+        warn!(error = %e, "failed to read provider quotas, solving without them");
+        Vec::new()
+    })
+}
+
+async fn get_unschedulable_pods(
+    client: Client,
+    scope: &PodScope,
+) -> Result<Vec<Pod>, ControllerError> {
     let pods: Api<Pod> = Api::all(client.clone());
-    let lp = ListParams::default().fields("status.phase=Pending");
-    Ok(pods
+    let mut lp = ListParams::default().fields("status.phase=Pending");
+    if let Some(selector) = scope.label_selector() {
+        lp = lp.labels(selector);
+    }
+    let unschedulable: Vec<Pod> = pods
         .list(&lp)
         .await?
         .into_iter()
         // DaemonSet pods target every node, including nodes that cannot
         // run them — we don't need to scale anything to satisfy them.
         .filter(|pod| is_pod_unschedulable(pod) && !is_daemonset_pod(pod))
-        .collect())
+        .filter(|pod| scope.admits(pod) && !is_pod_ignored(pod))
+        .collect();
+    if !unschedulable.iter().any(|p| nominated_node(p).is_some()) {
+        return Ok(unschedulable);
+    }
+
+    // The scheduler is evicting lower-priority pods to make room for
+    // nominated pods; buying a node for them would duplicate that capacity.
+    let node_names: HashSet<String> = Api::<Node>::all(client)
+        .list_metadata(&ListParams::default())
+        .await?
+        .into_iter()
+        .filter_map(|n| n.metadata.name)
+        .collect();
+    let (preempting, demand): (Vec<Pod>, Vec<Pod>) = unschedulable
+        .into_iter()
+        .partition(|p| is_awaiting_preemption(p, &node_names));
+    if !preempting.is_empty() {
+        debug!(
+            count = preempting.len(),
+            "skipping pods nominated for preemption"
+        );
+    }
+    Ok(demand)
 }
 
-/// Fetch all NodePool CRDs and map them to PoolConfig.
-async fn get_node_pools(client: Client) -> Result<Vec<PoolConfig>, ControllerError> {
-    let api: Api<NodePool> = Api::all(client);
-    let lp = ListParams::default();
+/// The pod templates of every DaemonSet, whose requests are reserved on the
+/// new nodes they will run on. Templates that fail to parse are skipped.
+async fn get_daemonsets(client: Client) -> Result<Vec<PodResources>, ControllerError> {
+    let api: Api<DaemonSet> = Api::all(client);
     Ok(api
-        .list(&lp)
+        .list(&ListParams::default())
         .await?
-        .into_iter()
-        .filter_map(|np| {
-            let name = np.metadata.name?;
-            let uid = np.metadata.uid?;
-            Some(PoolConfig {
-                name,
-                uid,
-                server_types: np.spec.server_types,
-                labels: np.spec.labels,
-                locations: np.spec.locations,
-            })
+        .iter()
+        .filter_map(|ds| match daemonset_pod_resources(ds) {
+            Ok(resources) => Some(resources),
+            Err(e) => {
+                warn!(
+                    daemonset = ?ds.metadata.name,
+                    error = %e,
+                    "skipping DaemonSet with unparseable requests"
+                );
+                None
+            }
         })
         .collect())
 }
 
+/// Zones of the bound PersistentVolumes claimed by `pods`, keyed by claim
+/// (namespace, name). Unbound claims, e.g. `WaitForFirstConsumer` ones, don't
+/// constrain placement.
+async fn get_volume_zones(
+    client: Client,
+    pods: &[Pod],
+) -> Result<HashMap<(String, String), Vec<String>>, ControllerError> {
+    if !pods.iter().any(|p| pod_claim_names(p).next().is_some()) {
+        return Ok(HashMap::new());
+    }
+    let volumes = Api::<PersistentVolume>::all(client)
+        .list(&ListParams::default())
+        .await?;
+    Ok(volumes
+        .iter()
+        .filter_map(|pv| {
+            let claim = pv.spec.as_ref()?.claim_ref.as_ref()?;
+            let key = (claim.namespace.clone()?, claim.name.clone()?);
+            Some((key, pv_zones(pv)?))
+        })
+        .collect())
+}
+
+/// The NodePools `shard` owns, with `defaults` filling in the solver
+/// options they leave unset.
+async fn get_node_pools(
+    client: Client,
+    shard: &Shard,
+    defaults: &SolveOptions,
+) -> Result<Vec<PoolConfig>, ControllerError> {
+    let (mut pools, owned) = get_all_node_pools(client, shard, defaults).await?;
+    pools.retain(|p| owned.contains(&p.name));
+    Ok(pools)
+}
+
+/// Every NodePool as [`get_node_pools`] maps it, and the names of those
+/// `shard` owns.
+async fn get_all_node_pools(
+    client: Client,
+    shard: &Shard,
+    defaults: &SolveOptions,
+) -> Result<(Vec<PoolConfig>, HashSet<String>), ControllerError> {
+    let api: Api<NodePool> = Api::all(client);
+    let node_pools = api.list(&ListParams::default()).await?.items;
+    let owned = shard
+        .owned_pools(&node_pools)
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    let pools = node_pools
+        .into_iter()
+        .filter_map(|np| pool_config(np, defaults))
+        .collect();
+    Ok((pools, owned))
+}
+
+fn pool_config(np: NodePool, defaults: &SolveOptions) -> Option<PoolConfig> {
+    let name = np.metadata.name?;
+    let uid = np.metadata.uid?;
+    Some(PoolConfig {
+        name,
+        uid,
+        min_nodes: np.spec.min_nodes,
+        max_nodes: np.spec.max_nodes,
+        headroom: np.spec.headroom,
+        server_types: np.spec.server_types,
+        labels: np.spec.labels,
+        taints: np.spec.taints,
+        locations: np.spec.locations,
+        evacuating_zones: np.spec.evacuation.map(|e| e.zones).unwrap_or_default(),
+        unmet_offerings: vec![],
+        allow_cost_spike: np
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|a| a.get(ALLOW_COST_SPIKE_ANNOTATION))
+            .is_some_and(|v| v == "true"),
+        solve_options: SolveOptions {
+            fragmentation_penalty: np
+                .spec
+                .fragmentation_penalty
+                .unwrap_or(defaults.fragmentation_penalty),
+            target_utilization: np
+                .spec
+                .target_utilization
+                .filter(|u| *u > 0.0)
+                .unwrap_or(defaults.target_utilization),
+            interruption_penalty: np
+                .spec
+                .interruption_penalty
+                .unwrap_or(defaults.interruption_penalty),
+            cost_objective: np.spec.cost_objective.unwrap_or(defaults.cost_objective),
+            max_additional_cost_per_hour: np
+                .spec
+                .max_additional_cost_per_hour
+                .or(defaults.max_additional_cost_per_hour),
+            max_new_nodes: np.spec.max_new_nodes.or(defaults.max_new_nodes),
+            deadline: None,
+            include_instance_types: np
+                .spec
+                .include_instance_types
+                .unwrap_or_else(|| defaults.include_instance_types.clone()),
+            exclude_instance_types: np
+                .spec
+                .exclude_instance_types
+                .unwrap_or_else(|| defaults.exclude_instance_types.clone()),
+        },
+        instance_families: np.spec.instance_families,
+    })
+}
+
 async fn scan_node_requests(
     client: Client,
-    unmet_ttl: Duration,
-    now: k8s_openapi::jiff::Timestamp,
     offerings: &[Offering],
 ) -> Result<InFlightScan, ControllerError> {
     let api: Api<NodeRequest> = Api::all(client);
@@ -299,6 +1018,8 @@ async fn scan_node_requests(
     let mut in_flight = Vec::new();
     let mut nr_counts: HashMap<String, HashMap<String, u32>> = HashMap::new();
     let mut api_nr_names = HashSet::new();
+    let mut in_flight_nr_names = HashSet::new();
+    let mut unmet_offerings: HashMap<String, Vec<(InstanceType, Location)>> = HashMap::new();
 
     for nr in api.list(&lp).await? {
         if let Some(name) = nr.metadata.name.as_ref() {
@@ -319,26 +1040,50 @@ async fn scan_node_requests(
             .ok_or(ControllerError::MissingName("NodeRequest"))?;
 
         let entry = InFlightCapacity {
+            nr_name: nr.metadata.name.clone().unwrap_or_default(),
             pool: pool_name.clone(),
+            provider: nr
+                .spec
+                .provider
+                .clone()
+                .or_else(|| requested_offering(&nr, offerings).map(|o| o.provider.clone()))
+                .unwrap_or_default(),
             instance_type: nr.spec.target_offering.0.clone(),
             location: nr.spec.location.0.clone(),
-            zone: lookup_zone(offerings, &nr.spec.target_offering.0, &nr.spec.location.0),
+            zone: nr.spec.zone.as_ref().map(|z| z.0.clone()).or_else(|| {
+                lookup_zone(offerings, &nr.spec.target_offering.0, &nr.spec.location.0)
+            }),
             resources: nr.spec.resources.clone(),
+            claimed_pods: nr.spec.claimed_pods.clone(),
+            requested: phase == NodeRequestPhase::Provisioning,
         };
 
         match phase {
             NodeRequestPhase::Pending | NodeRequestPhase::Provisioning => {
-                in_flight.push(entry);
+                in_flight_nr_names.extend(nr.metadata.name.clone());
+                // One being deleted keeps its slot until its finalizer has
+                // removed the node, but takes no more pods.
+                if nr.metadata.deletion_timestamp.is_none() {
+                    in_flight.push(entry);
+                }
                 *nr_counts
                     .entry(pool_name)
                     .or_default()
                     .entry(nr.spec.target_offering.0.clone())
                     .or_insert(0) += 1;
             }
+            // An Unmet NodeRequest's node is never coming: its capacity and
+            // claims are released, so its pods are solved for afresh, though
+            // not onto the same offering while the request remains.
             NodeRequestPhase::Unmet => {
-                if !is_unmet_expired(&nr, unmet_ttl, now) {
-                    in_flight.push(entry);
-                }
+                let location = Location {
+                    region: nr.spec.location.clone(),
+                    zone: entry.zone.map(Zone),
+                };
+                unmet_offerings
+                    .entry(pool_name)
+                    .or_default()
+                    .push((nr.spec.target_offering.clone(), location));
             }
             NodeRequestPhase::Ready => {}
         }
@@ -348,21 +1093,35 @@ async fn scan_node_requests(
         in_flight,
         nr_counts,
         api_nr_names,
+        in_flight_nr_names,
+        unmet_offerings,
     })
 }
 
+/// The nodes Growth manages.
+async fn get_managed_nodes(client: Client) -> Result<Vec<Node>, ControllerError> {
+    let nodes: Api<Node> = Api::all(client);
+    let lp = ListParams::default().labels(MANAGED_BY_SELECTOR);
+    Ok(nodes.list(&lp).await?.items)
+}
+
+/// Pods bound to a node and not yet finished, whose requests take up that
+/// node's allocatable resources.
+async fn get_bound_pods(client: Client) -> Result<Vec<Pod>, ControllerError> {
+    let pods: Api<Pod> = Api::all(client);
+    let lp = ListParams::default()
+        .fields("spec.nodeName!=,status.phase!=Succeeded,status.phase!=Failed");
+    Ok(pods.list(&lp).await?.items)
+}
+
 /// Count existing Growth-managed nodes per pool per instance type.
 ///
 /// Nodes are identified by the `growth.vettrdev.com/pool` and
 /// `growth.vettrdev.com/instance-type` labels set during provisioning.
-async fn get_node_counts(
-    client: Client,
-) -> Result<HashMap<String, HashMap<String, u32>>, ControllerError> {
-    let nodes: Api<Node> = Api::all(client);
-    let lp = ListParams::default().labels(MANAGED_BY_SELECTOR);
+fn count_nodes_by_pool(nodes: &[Node]) -> HashMap<String, HashMap<String, u32>> {
     let mut counts: HashMap<String, HashMap<String, u32>> = HashMap::new();
 
-    for node in nodes.list(&lp).await? {
+    for node in nodes {
         let labels = node.metadata.labels.as_ref();
         let Some(pool) = labels.and_then(|l| l.get(POOL_LABEL)) else {
             continue;
@@ -378,15 +1137,14 @@ async fn get_node_counts(
     }
 
     debug!(?counts, "existing node counts by pool");
-    Ok(counts)
+    counts
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
+    use std::collections::{BTreeMap, HashMap, HashSet};
     use std::sync::Arc;
     use std::sync::atomic::{AtomicUsize, Ordering};
-    use std::time::Duration;
 
     use http::{Request, Response};
 
@@ -398,9 +1156,13 @@ mod tests {
     use kube::api::ObjectMeta;
     use kube::client::Body;
 
-    use crate::offering::{InstanceType, Offering, Resources};
+    use crate::controller::cost_guard::CostGuard;
+    use crate::controller::pod_scope::PodScope;
+    use crate::controller::shard::Shard;
+    use crate::offering::{Offering, Resources};
+    use crate::optimiser::{ScoredSolver, SolveOptions};
     use crate::providers::fake::FakeProvider;
-    use crate::providers::provider::Provider;
+    use crate::testing::offering;
 
     use super::{UnconfirmedCreates, reconcile_unschedulable_pods};
 
@@ -576,27 +1338,16 @@ mod tests {
                 }]),
                 ..Default::default()
             }),
-            ..Default::default()
         }
     }
 
-    fn test_offering(name: &str, cpu: u32, memory_mib: u32, cost: f64) -> Offering {
-        use crate::offering::{Location, Region, Zone};
-        Offering {
-            instance_type: InstanceType(name.into()),
-            resources: Resources {
-                cpu,
-                memory_mib,
-                ephemeral_storage_gib: None,
-                gpu: 0,
-                gpu_model: None,
-            },
-            cost_per_hour: cost,
-            location: Location {
-                region: Region("eu-central".into()),
-                zone: Some(Zone("fsn1-dc14".into())),
-            },
-        }
+    fn test_offering(name: &str, cpu: u32, memory_mib: u64, cost: f64) -> Offering {
+        offering(name)
+            .with_cpus(u64::from(cpu))
+            .with_memory_mib(memory_mib)
+            .with_cost(cost)
+            .with_zone("fsn1-dc14")
+            .build()
     }
 
     fn node_list_response() -> Response<Body> {
@@ -612,7 +1363,20 @@ mod tests {
             .unwrap()
     }
 
-    /// Spawn a mock API server that handles pod-list, NodeRequest, NodePool, and Node requests.
+    fn daemonset_list_response() -> Response<Body> {
+        let list = serde_json::json!({
+            "apiVersion": "apps/v1",
+            "kind": "DaemonSetList",
+            "metadata": { "resourceVersion": "1" },
+            "items": []
+        });
+        Response::builder()
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(&list).unwrap()))
+            .unwrap()
+    }
+
+    /// Spawn a mock API server that handles pod-list, DaemonSet, NodeRequest, NodePool, and Node requests.
     ///
     /// Returns a counter tracking how many NodeRequests were created.
     fn spawn_mock_api(
@@ -636,7 +1400,17 @@ mod tests {
             while let Some((request, send)) = handle.next_request().await {
                 let path = request.uri().path().to_string();
                 let method = request.method().clone();
-                if path.contains("/pods") {
+                if path.contains("daemonsets") {
+                    send.send_response(daemonset_list_response());
+                } else if path.contains("/pods") && method == http::Method::PATCH {
+                    let pod = serde_json::to_vec(&pods[0]).unwrap();
+                    send.send_response(
+                        Response::builder()
+                            .header("content-type", "application/json")
+                            .body(Body::from(pod))
+                            .unwrap(),
+                    );
+                } else if path.contains("/pods") {
                     send.send_response(pod_list_response(pods.clone()));
                 } else if path.contains("nodepools") && method == http::Method::GET {
                     send.send_response(node_pool_list_response(&offering_names));
@@ -658,17 +1432,26 @@ mod tests {
     #[tokio::test]
     async fn no_pending_pods_does_nothing() {
         let (client, handle) = mock_client();
-        let provider = Provider::Fake(
-            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]),
-        );
+        let provider =
+            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]);
 
         let nr_count = spawn_mock_api(handle, vec![], vec!["cpx22"]);
 
         let result = reconcile_unschedulable_pods(
             client,
             &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
             &mut UnconfirmedCreates::default(),
-            Duration::from_secs(120),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
             k8s_openapi::jiff::Timestamp::now(),
         )
         .await;
@@ -679,9 +1462,8 @@ mod tests {
     #[tokio::test]
     async fn one_pending_pod_creates_one_node_request() {
         let (client, handle) = mock_client();
-        let provider = Provider::Fake(
-            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]),
-        );
+        let provider =
+            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]);
 
         let pod = make_pending_unschedulable_pod("test-pod", "1", "2048Mi");
         let nr_count = spawn_mock_api(handle, vec![pod], vec!["cpx22"]);
@@ -689,8 +1471,151 @@ mod tests {
         let result = reconcile_unschedulable_pods(
             client,
             &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
+            &mut UnconfirmedCreates::default(),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
+            k8s_openapi::jiff::Timestamp::now(),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(nr_count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn cost_guard_holds_replacements_for_displaced_pods() {
+        let pod = make_pending_unschedulable_pod("displaced", "1", "2048Mi");
+        let displaced = vec![crate::offering::PodResources::from_pod(&pod).unwrap()];
+        let solve = async |cost_guard: CostGuard| {
+            let (client, handle) = mock_client();
+            let provider =
+                FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]);
+            spawn_mock_api(handle, vec![], vec!["cpx22"]);
+            let (result, held) = super::solve_displaced_pods(
+                &client,
+                &provider,
+                HashMap::new(),
+                &HashSet::new(),
+                &ScoredSolver,
+                displaced.clone(),
+                &cost_guard,
+                &Shard::All,
+                &SolveOptions::default(),
+            )
+            .await
+            .unwrap();
+            (result.demands.len(), held.is_some())
+        };
+
+        assert_eq!(solve(CostGuard::disabled()).await, (1, false));
+        // $0.01/h is over a limit of 2 x $0.001/h.
+        assert_eq!(solve(CostGuard::new(2.0, 0.001, false)).await, (0, true));
+    }
+
+    #[tokio::test]
+    async fn dry_run_creates_no_node_request() {
+        let (client, handle) = mock_client();
+        let provider =
+            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]);
+
+        let pod = make_pending_unschedulable_pod("test-pod", "1", "2048Mi");
+        let nr_count = spawn_mock_api(handle, vec![pod], vec!["cpx22"]);
+
+        let mut unconfirmed = UnconfirmedCreates::default();
+        let stats = reconcile_unschedulable_pods(
+            client,
+            &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
+            &mut unconfirmed,
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            true,
+            k8s_openapi::jiff::Timestamp::now(),
+        )
+        .await
+        .unwrap();
+        // The plan is still made, just not carried out.
+        assert_eq!(stats.values().map(|s| s.new_nodes).sum::<usize>(), 1);
+        assert_eq!(nr_count.load(Ordering::SeqCst), 0);
+        assert!(unconfirmed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn ignored_pod_creates_no_node_request() {
+        let (client, handle) = mock_client();
+        let provider =
+            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]);
+
+        let mut pod = make_pending_unschedulable_pod("placeholder", "1", "2048Mi");
+        pod.metadata.annotations = Some(BTreeMap::from([(
+            crate::offering::IGNORE_ANNOTATION.into(),
+            "true".into(),
+        )]));
+        let nr_count = spawn_mock_api(handle, vec![pod], vec!["cpx22"]);
+
+        let result = reconcile_unschedulable_pods(
+            client,
+            &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
+            &mut UnconfirmedCreates::default(),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
+            k8s_openapi::jiff::Timestamp::now(),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(nr_count.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn stale_nomination_still_creates_node_request() {
+        let (client, handle) = mock_client();
+        let provider =
+            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]);
+
+        // Nominated onto a node the (empty) node list no longer contains.
+        let mut pod = make_pending_unschedulable_pod("test-pod", "1", "2048Mi");
+        pod.status.as_mut().unwrap().nominated_node_name = Some("deleted-node".into());
+        let nr_count = spawn_mock_api(handle, vec![pod], vec!["cpx22"]);
+
+        let result = reconcile_unschedulable_pods(
+            client,
+            &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
             &mut UnconfirmedCreates::default(),
-            Duration::from_secs(120),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
             k8s_openapi::jiff::Timestamp::now(),
         )
         .await;
@@ -704,7 +1629,7 @@ mod tests {
         // Offering fits 2 pods (2 cpu, 4Gi) but each pod needs 1 cpu + 2Gi.
         // 3 pods → need 2 nodes.
         let offering = test_offering("cpx22", 2, 4096, 0.01);
-        let provider = Provider::Fake(FakeProvider::new().with_offerings(vec![offering]));
+        let provider = FakeProvider::new().with_offerings(vec![offering]);
 
         let pods = vec![
             make_pending_unschedulable_pod("pod-a", "1", "2048Mi"),
@@ -716,8 +1641,18 @@ mod tests {
         let result = reconcile_unschedulable_pods(
             client,
             &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
             &mut UnconfirmedCreates::default(),
-            Duration::from_secs(120),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
             k8s_openapi::jiff::Timestamp::now(),
         )
         .await;
@@ -735,10 +1670,10 @@ mod tests {
         let small = test_offering("small", 1, 1024, 0.005);
         let large = test_offering("large", 4, 8192, 0.02);
 
-        let provider = Provider::Fake(FakeProvider::new().with_offerings_sequence(vec![
+        let provider = FakeProvider::new().with_offerings_sequence(vec![
             vec![small.clone()],
             vec![small.clone(), large.clone()],
-        ]));
+        ]);
 
         // First call — only small offerings available.
         let (client1, handle1) = mock_client();
@@ -747,8 +1682,18 @@ mod tests {
         reconcile_unschedulable_pods(
             client1,
             &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
             &mut UnconfirmedCreates::default(),
-            Duration::from_secs(120),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
             k8s_openapi::jiff::Timestamp::now(),
         )
         .await
@@ -762,8 +1707,18 @@ mod tests {
         reconcile_unschedulable_pods(
             client2,
             &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
             &mut UnconfirmedCreates::default(),
-            Duration::from_secs(120),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
             k8s_openapi::jiff::Timestamp::now(),
         )
         .await
@@ -776,9 +1731,8 @@ mod tests {
         // Two sequential calls share the same unconfirmed_creates. The mock API
         // always returns an empty NodeRequest list (simulating API lag), so only the
         // in-memory cache prevents the second call from creating duplicates.
-        let provider = Provider::Fake(
-            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]),
-        );
+        let provider =
+            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]);
         let pod = make_pending_unschedulable_pod("dup-pod", "1", "2048Mi");
 
         let mut unconfirmed_creates = UnconfirmedCreates::default();
@@ -789,8 +1743,18 @@ mod tests {
         reconcile_unschedulable_pods(
             client1,
             &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
             &mut unconfirmed_creates,
-            Duration::from_secs(120),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
             k8s_openapi::jiff::Timestamp::now(),
         )
         .await
@@ -812,8 +1776,18 @@ mod tests {
         reconcile_unschedulable_pods(
             client2,
             &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
             &mut unconfirmed_creates,
-            Duration::from_secs(120),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
             k8s_openapi::jiff::Timestamp::now(),
         )
         .await
@@ -827,9 +1801,8 @@ mod tests {
 
     #[tokio::test]
     async fn unconfirmed_creates_drained_when_api_catches_up() {
-        let provider = Provider::Fake(
-            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]),
-        );
+        let provider =
+            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]);
         let pod = make_pending_unschedulable_pod("drain-pod", "1", "2048Mi");
 
         let mut unconfirmed_creates = UnconfirmedCreates::default();
@@ -840,8 +1813,18 @@ mod tests {
         reconcile_unschedulable_pods(
             client1,
             &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
             &mut unconfirmed_creates,
-            Duration::from_secs(120),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
             k8s_openapi::jiff::Timestamp::now(),
         )
         .await
@@ -861,8 +1844,18 @@ mod tests {
         reconcile_unschedulable_pods(
             client2,
             &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
             &mut unconfirmed_creates,
-            Duration::from_secs(120),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
             k8s_openapi::jiff::Timestamp::now(),
         )
         .await
@@ -876,36 +1869,66 @@ mod tests {
 
     #[tokio::test]
     async fn unmet_nr_releases_pods_back_to_demand() {
-        let provider = Provider::Fake(
-            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]),
-        );
+        let provider = FakeProvider::new().with_offerings(vec![
+            test_offering("cpx22", 2, 4096, 0.01),
+            test_offering("cpx32", 4, 8192, 0.05),
+        ]);
         let pod = make_pending_unschedulable_pod("retry-pod", "1", "2048Mi");
+        let reconcile = |phase: &str| {
+            // The NodeRequest claims the pod, as it would once the solver
+            // placed it there.
+            let mut nr = make_nr_json("nr-claiming", phase);
+            nr["spec"]["claimedPods"] = serde_json::json!(["uid-retry-pod"]);
+            nr["metadata"]["creationTimestamp"] =
+                serde_json::json!(k8s_openapi::jiff::Timestamp::now().to_string());
+            let (client, handle) = mock_client();
+            let nr_count = spawn_mock_api_with_nrs(
+                handle,
+                vec![pod.clone()],
+                vec!["cpx22", "cpx32"],
+                vec![nr],
+            );
+            let provider = &provider;
+            async move {
+                let mut unconfirmed_creates = UnconfirmedCreates::default();
+                reconcile_unschedulable_pods(
+                    client,
+                    provider,
+                    HashMap::new(),
+                    &HashSet::new(),
+                    &ScoredSolver,
+                    None,
+                    0,
+                    &mut unconfirmed_creates,
+                    &CostGuard::disabled(),
+                    &Shard::All,
+                    &SolveOptions::default(),
+                    &PodScope::default(),
+                    None,
+                    false,
+                    k8s_openapi::jiff::Timestamp::now(),
+                )
+                .await
+                .unwrap();
+                let created: Vec<String> = unconfirmed_creates
+                    .entries()
+                    .map(|e| e.instance_type.clone())
+                    .collect();
+                (nr_count.load(Ordering::SeqCst), created)
+            }
+        };
 
-        let mut unconfirmed_creates = UnconfirmedCreates::default();
-
-        // An Unmet NodeRequest exists in the API. With unmet_ttl=0 it is immediately
-        // expired and does not contribute to in-flight capacity, so the pod re-enters demand.
-        let (client, handle) = mock_client();
-        let nr_items = vec![make_nr_json("nr-unmet", "Unmet")];
-        let nr_count = spawn_mock_api_with_nrs(handle, vec![pod], vec!["cpx22"], nr_items);
-        reconcile_unschedulable_pods(
-            client,
-            &provider,
-            &mut unconfirmed_creates,
-            Duration::from_secs(0),
-            k8s_openapi::jiff::Timestamp::now(),
-        )
-        .await
-        .unwrap();
+        // While the request is Pending the pod is held on it.
+        assert_eq!(reconcile("Pending").await.0, 0);
+        // Once it lands in Unmet, well within its TTL, the pod is released and
+        // gets a new NodeRequest, on an offering other than the stocked-out
+        // one even though that is cheaper.
+        let (count, created) = reconcile("Unmet").await;
         assert_eq!(
-            nr_count.load(Ordering::SeqCst),
-            1,
+            count, 1,
             "pod should re-enter demand and produce a new NodeRequest"
         );
-        assert!(
-            !unconfirmed_creates.is_empty(),
-            "new NodeRequest should populate unconfirmed_creates"
-        );
+        assert_eq!(created, vec!["cpx32".to_string()]);
     }
 
     /// When the only NodePool in the cluster has no metadata.name,
@@ -914,9 +1937,8 @@ mod tests {
     #[tokio::test]
     async fn nameless_pool_is_skipped() {
         let (client, mut handle) = mock_client();
-        let provider = Provider::Fake(
-            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]),
-        );
+        let provider =
+            FakeProvider::new().with_offerings(vec![test_offering("cpx22", 2, 4096, 0.01)]);
 
         let pod = make_pending_unschedulable_pod("orphan-pod", "1", "2048Mi");
         let nr_count = Arc::new(AtomicUsize::new(0));
@@ -927,7 +1949,9 @@ mod tests {
             while let Some((request, send)) = handle.next_request().await {
                 let path = request.uri().path().to_string();
                 let method = request.method().clone();
-                if path.contains("/pods") {
+                if path.contains("daemonsets") {
+                    send.send_response(daemonset_list_response());
+                } else if path.contains("/pods") {
                     send.send_response(pod_list_response(vec![pod.clone()]));
                 } else if path.contains("nodepools") && method == http::Method::GET {
                     // Pool with name omitted — should be skipped by filter_map.
@@ -969,8 +1993,18 @@ mod tests {
         let result = reconcile_unschedulable_pods(
             client,
             &provider,
+            HashMap::new(),
+            &HashSet::new(),
+            &ScoredSolver,
+            None,
+            0,
             &mut UnconfirmedCreates::default(),
-            Duration::from_secs(120),
+            &CostGuard::disabled(),
+            &Shard::All,
+            &SolveOptions::default(),
+            &PodScope::default(),
+            None,
+            false,
             k8s_openapi::jiff::Timestamp::now(),
         )
         .await;
@@ -996,31 +2030,41 @@ mod tests {
         uc.record(
             "nr-old".into(),
             "pool".into(),
+            "fake".into(),
             "cpx22".into(),
             "fsn1".into(),
             None,
             Resources {
-                cpu: 2,
+                cpu_millis: 2000,
                 memory_mib: 4096,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
+            vec![],
             t0,
         );
         uc.record(
             "nr-fresh".into(),
             "pool".into(),
+            "fake".into(),
             "cpx22".into(),
             "fsn1".into(),
             None,
             Resources {
-                cpu: 2,
+                cpu_millis: 2000,
                 memory_mib: 4096,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
+            vec![],
             t0,
         );
         assert_eq!(uc.len(), 2);
@@ -1046,16 +2090,21 @@ mod tests {
         uc.record(
             "nr-young".into(),
             "pool".into(),
+            "fake".into(),
             "cpx22".into(),
             "fsn1".into(),
             None,
             Resources {
-                cpu: 2,
+                cpu_millis: 2000,
                 memory_mib: 4096,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
+            vec![],
             t0,
         );
 
@@ -1065,4 +2114,153 @@ mod tests {
         uc.drain_reflected(&empty_api, t1);
         assert_eq!(uc.len(), 1, "entry within TTL should be retained");
     }
+
+    #[test]
+    fn unrequested_in_flight_nodes_come_off_the_quota() {
+        use super::{InFlightCapacity, reserve_unrequested_quota};
+        use crate::offering::Region;
+        use crate::providers::provider::Quota;
+
+        let entry = |provider: &str, requested: bool| InFlightCapacity {
+            nr_name: "nr".into(),
+            pool: "default".into(),
+            provider: provider.into(),
+            instance_type: "cpx22".into(),
+            location: "nbg1".into(),
+            zone: None,
+            resources: offering("cpx22").build().resources,
+            claimed_pods: vec![],
+            requested,
+        };
+        let quota = |provider: &str| Quota {
+            provider: provider.into(),
+            region: Region("nbg1".into()),
+            vcpus: Some(8),
+            instances: Some(3),
+        };
+        let quotas = reserve_unrequested_quota(
+            vec![quota("fake"), quota("hetzner")],
+            &[
+                entry("fake", false),
+                entry("fake", false),
+                // Already created: the provider's own count includes it.
+                entry("fake", true),
+            ],
+        );
+        assert_eq!((quotas[0].vcpus, quotas[0].instances), (Some(4), Some(1)));
+        assert_eq!(quotas[1], quota("hetzner"));
+    }
+
+    #[test]
+    fn claimed_pods_are_held_on_their_in_flight_node() {
+        use super::{InFlightCapacity, reserve_claimed_pods};
+        use crate::offering::PodResources;
+
+        let in_flight = |claimed: &[&str]| InFlightCapacity {
+            nr_name: "nr".into(),
+            pool: "default".into(),
+            provider: "fake".into(),
+            instance_type: "cpx22".into(),
+            location: "eu-central".into(),
+            zone: None,
+            resources: Resources {
+                cpu_millis: 2000,
+                memory_mib: 4096,
+                ephemeral_storage_gib: None,
+                gpu: 0,
+                gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
+            },
+            claimed_pods: claimed.iter().map(|c| c.to_string()).collect(),
+            requested: true,
+        };
+        let mut entries = vec![in_flight(&["uid-a"]), in_flight(&["uid-b", "uid-gone"])];
+        let demands = ["a", "b", "c"]
+            .iter()
+            .map(|name| {
+                PodResources::from_pod(&make_pending_unschedulable_pod(name, "1", "1024Mi"))
+                    .unwrap()
+            })
+            .collect();
+
+        let unclaimed = reserve_claimed_pods(&mut entries, demands);
+        assert_eq!(unclaimed.len(), 1);
+        assert_eq!(unclaimed[0].uid, "uid-c");
+        // Each node keeps what its own claim didn't use.
+        assert_eq!(entries[0].resources.cpu_millis, 1000);
+        assert_eq!(entries[0].resources.memory_mib, 3072);
+        assert_eq!(entries[1].resources.cpu_millis, 1000);
+    }
+
+    #[test]
+    fn pods_placed_on_in_flight_room_are_nominated_to_it() {
+        use super::{InFlightCapacity, ReconcileResult, nominate_to_in_flight};
+        use crate::offering::{PodId, PodResources};
+
+        let entry = |nr_name: &str, pool: &str| InFlightCapacity {
+            nr_name: nr_name.into(),
+            pool: pool.into(),
+            provider: "fake".into(),
+            instance_type: "cpx22".into(),
+            location: "eu-central".into(),
+            zone: None,
+            resources: Resources {
+                cpu_millis: 2000,
+                memory_mib: 4096,
+                ephemeral_storage_gib: None,
+                gpu: 0,
+                gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
+            },
+            claimed_pods: vec!["uid-claimed".into()],
+            requested: true,
+        };
+        let mut entries = vec![
+            entry("nr-1", "default"),
+            entry("nr-gpu", "gpu"),
+            entry("nr-2", "default"),
+        ];
+        let pending: Vec<PodResources> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| {
+                PodResources::from_pod(&make_pending_unschedulable_pod(name, "1", "1024Mi"))
+                    .unwrap()
+            })
+            .collect();
+        let result = ReconcileResult {
+            demands: vec![],
+            pod_errors: vec![],
+            unplaced: vec![PodId::new("default", "b")],
+            solver_stats: BTreeMap::new(),
+            // The default pool's placements are in its own entries' order,
+            // skipping nr-gpu.
+            in_flight_placements: BTreeMap::from([
+                (
+                    "default".to_string(),
+                    vec![
+                        vec![PodId::new("default", "c")],
+                        vec![PodId::new("default", "a")],
+                    ],
+                ),
+                ("gpu".to_string(), vec![vec![]]),
+            ]),
+        };
+
+        let nominations = nominate_to_in_flight(&mut entries, &pending, &result);
+        // b stayed unmet and d was placed on a running node.
+        assert_eq!(
+            nominations,
+            BTreeMap::from([
+                ("nr-1".to_string(), vec!["uid-c".into()]),
+                ("nr-2".to_string(), vec!["uid-a".into()]),
+            ])
+        );
+        assert_eq!(entries[0].claimed_pods, ["uid-claimed", "uid-c"]);
+        assert_eq!(entries[0].resources.cpu_millis, 1000);
+        assert_eq!(entries[1].claimed_pods, ["uid-claimed"]);
+    }
 }
@@ -1,50 +1,158 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
 use futures_util::StreamExt;
+use futures_util::stream::BoxStream;
 use k8s_openapi::api::core::v1::Pod;
 use kube::Api;
 use kube::runtime::watcher;
-use tokio::time::{Instant, sleep};
-use tracing::{info, warn};
+use tokio::time::{Instant, sleep, sleep_until};
+use tracing::{debug, info, warn};
 
+use crate::config::BatchWindow;
 use crate::controller::pods;
 use crate::controller::{ControllerContext, ControllerError};
+use crate::resources::node_request::{NodeRequest, NodeRequestPhase};
 
-const TIMEOUT: Duration = Duration::from_millis(500);
-const MAX_WINDOW: Duration = Duration::from_secs(10);
+/// Pending-pod events waiting for the next batched reconcile.
+///
+/// The batch is due once no event has arrived for `window.quiet`, or
+/// `window.max` after its first event, whichever comes first, so a steady
+/// trickle of pods still gets solved.
+#[derive(Debug)]
+struct Batch {
+    window: BatchWindow,
+    events: usize,
+    first: Option<Instant>,
+    last: Option<Instant>,
+}
+
+impl Batch {
+    fn new(window: BatchWindow) -> Self {
+        Self {
+            window,
+            events: 0,
+            first: None,
+            last: None,
+        }
+    }
+
+    fn record(&mut self, now: Instant) {
+        self.events += 1;
+        self.first.get_or_insert(now);
+        self.last = Some(now);
+    }
+
+    /// When the batch should be reconciled, if anything is waiting.
+    fn due(&self) -> Option<Instant> {
+        let quiet = self.last? + self.window.quiet;
+        let max = self.first? + self.window.max;
+        Some(quiet.min(max))
+    }
+
+    /// Empty the batch, returning how many events it coalesced.
+    fn take(&mut self) -> usize {
+        self.first = None;
+        self.last = None;
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Last seen phase of each NodeRequest, to tell when one gives up its pods.
+#[derive(Debug, Default)]
+struct NodeRequestPhases {
+    phases: HashMap<String, NodeRequestPhase>,
+}
+
+impl NodeRequestPhases {
+    /// Pods `event` hands back to demand: those claimed by a NodeRequest that
+    /// just landed in Unmet, or that was deleted before its node was Ready.
+    /// Their pods stay Pending and unchanged, so no pod event would bring
+    /// them back before something else triggered a solve.
+    fn released_pods(&mut self, event: &watcher::Event<NodeRequest>) -> usize {
+        match event {
+            watcher::Event::Apply(nr) | watcher::Event::InitApply(nr) => {
+                let Some(name) = nr.metadata.name.clone() else {
+                    return 0;
+                };
+                let phase = nr.phase();
+                let landed = phase == NodeRequestPhase::Unmet
+                    && self.phases.get(&name) != Some(&NodeRequestPhase::Unmet);
+                self.phases.insert(name, phase);
+                if landed {
+                    nr.spec.claimed_pods.len()
+                } else {
+                    0
+                }
+            }
+            watcher::Event::Delete(nr) => {
+                if let Some(name) = &nr.metadata.name {
+                    self.phases.remove(name);
+                }
+                if nr.phase() == NodeRequestPhase::Ready {
+                    0
+                } else {
+                    nr.spec.claimed_pods.len()
+                }
+            }
+            watcher::Event::Init | watcher::Event::InitDone => 0,
+        }
+    }
+}
 
 /// Watch Pending pods and reconcile in batched windows, producing NodeRequests.
 ///
-/// Events are coalesced so that a burst of pods becoming unschedulable
-/// produces a single reconcile against fresh API state, avoiding duplicate
-/// NodeRequests from stale informer caches.
+/// Events are coalesced so that a burst of pods becoming unschedulable (a
+/// Deployment scaling by hundreds of replicas) produces a single reconcile
+/// against fresh API state, avoiding both a solve per pod and duplicate
+/// NodeRequests from stale informer caches. Only pods the scheduler has
+/// marked unschedulable count; other Pending pod updates don't start a solve.
+///
+/// NodeRequests are watched too: pods claimed by one that lands in Unmet or
+/// is deleted unfulfilled join the batch, so they are reconsidered at once.
+///
+/// When the [`Tunables`](crate::config::Tunables) change, the next solve
+/// uses them; a new label selector restarts the watch, keeping the batch.
 pub async fn run_pod_watcher(ctx: Arc<ControllerContext>) -> Result<(), ControllerError> {
-    let pods: Api<Pod> = Api::all(ctx.client.clone());
-    let config = watcher::Config::default().fields("status.phase=Pending");
-    let mut stream = std::pin::pin!(watcher::watcher(pods, config));
-
-    // When an event is received, wait up to `TIMEOUT` without another event arriving.
-    let mut delay = ::std::pin::pin!(sleep(TIMEOUT));
-    // When an event is received, set max_delay, ensuring we act at least every `MAX_WINDOW`.
-    let mut max_delay = ::std::pin::pin!(sleep(Duration::from_millis(0))); // Immediately expire this.
-
-    let mut pending = false;
-    let mut trigger: bool = false;
+    let mut tunables_changed = ctx.tunables.subscribe();
+    let mut tunables = ctx.tunables.current();
+    let mut selector = tunables.pod_scope.label_selector().map(String::from);
+    let mut stream = watch_pending_pods(&ctx, selector.as_deref());
+    let mut node_requests = std::pin::pin!(watcher::watcher(
+        Api::<NodeRequest>::all(ctx.client.clone()),
+        watcher::Config::default(),
+    ));
+    let mut nr_phases = NodeRequestPhases::default();
 
+    let mut batch = Batch::new(tunables.pod_batch);
     let mut unconfirmed_creates = pods::init_unconfirmed_creates();
 
     loop {
+        let due = batch.due();
         tokio::select! {
+            Ok(()) = tunables_changed.changed() => {
+                tunables = tunables_changed.borrow_and_update().clone();
+                batch.window = tunables.pod_batch;
+                let wanted = tunables.pod_scope.label_selector();
+                if wanted != selector.as_deref() {
+                    info!(selector = ?wanted, "pod selector changed, restarting pod watch");
+                    selector = wanted.map(String::from);
+                    stream = watch_pending_pods(&ctx, selector.as_deref());
+                }
+                continue;
+            }
             item = stream.next() => {
                 match item {
                     Some(Ok(event)) => {
-                        if matches!(event, watcher::Event::Apply(_) | watcher::Event::InitApply(_)) {
-                            if !pending {
-                                max_delay.as_mut().reset(Instant::now() + MAX_WINDOW);
+                        if let watcher::Event::Apply(pod) | watcher::Event::InitApply(pod) = event {
+                            ctx.metrics.demand_latency.track(&pod);
+                            if pods::is_pod_unschedulable(&pod)
+                                && tunables.pod_scope.admits(&pod)
+                                && !pods::is_pod_ignored(&pod)
+                            {
+                                batch.record(Instant::now());
                             }
-                            pending = true;
-                            delay.as_mut().reset(Instant::now() + TIMEOUT);
                         }
                     },
                     Some(Err(e)) => {
@@ -52,42 +160,177 @@ pub async fn run_pod_watcher(ctx: Arc<ControllerContext>) -> Result<(), Controll
                     }
                     None => break,
                 }
+                continue;
             }
-            _ = &mut delay, if pending => {
-                pending = false;
-                info!(trigger = "batch_timeout", "starting pod reconciliation");
-                trigger = true;
-            }
-            _ = &mut max_delay, if pending => {
-                pending = false;
-                info!(trigger = "max_window", "starting pod reconciliation");
-                trigger = true;
-                delay.as_mut().reset(Instant::now() + TIMEOUT);
+            Some(event) = node_requests.next() => {
+                match event {
+                    Ok(event) => {
+                        let released = nr_phases.released_pods(&event);
+                        if released > 0 {
+                            debug!(pods = released, "NodeRequest gave up its pods, reconsidering them");
+                            let now = Instant::now();
+                            for _ in 0..released {
+                                batch.record(now);
+                            }
+                        }
+                    }
+                    Err(e) => warn!(error = %e, "NodeRequest watcher stream error"),
+                }
+                continue;
             }
+            _ = sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {}
         }
-        if trigger {
-            match pods::reconcile_unschedulable_pods(
-                ctx.client.clone(),
-                &ctx.provider,
-                &mut unconfirmed_creates,
-                ctx.scale_down.unmet_ttl,
-                ctx.clock.now(),
-            )
-            .await
-            {
-                Ok(()) => {}
-                Err(ControllerError::FaultInjected(n)) => {
-                    warn!(n, "fault injection triggered, exiting watcher");
-                    return Ok(());
-                }
-                Err(e) => {
-                    warn!(error = %e, "pod reconciliation failed");
-                    sleep(Duration::from_secs(5)).await;
-                }
+
+        let events = batch.take();
+        info!(events, "starting pod reconciliation");
+        let unhealthy_providers = ctx.provider_health.unhealthy_providers();
+        let reconcile = pods::reconcile_unschedulable_pods(
+            ctx.client.clone(),
+            ctx.provider.as_ref(),
+            ctx.offering_stats.penalties(),
+            &unhealthy_providers,
+            tunables.solver,
+            tunables.max_new_nodes,
+            tunables.solve_concurrency,
+            &mut unconfirmed_creates,
+            &ctx.cost_guard,
+            &ctx.shard,
+            &tunables.pool_defaults,
+            &tunables.pod_scope,
+            ctx.audit_log.as_ref(),
+            ctx.dry_run,
+            ctx.clock.now(),
+        );
+        match ctx.metrics.reconciles.measure("pods", reconcile).await {
+            Ok(stats) => ctx.metrics.solver.record(&stats),
+            Err(ControllerError::FaultInjected(n)) => {
+                warn!(n, "fault injection triggered, exiting watcher");
+                return Ok(());
+            }
+            Err(e) => {
+                warn!(error = %e, "pod reconciliation failed");
+                sleep(Duration::from_secs(5)).await;
             }
-            // Reset trigger after successful reconciliation
-            trigger = false;
         }
     }
     Ok(())
 }
+
+/// Pending pods, limited to `selector` when pods are scoped by label.
+fn watch_pending_pods(
+    ctx: &ControllerContext,
+    selector: Option<&str>,
+) -> BoxStream<'static, watcher::Result<watcher::Event<Pod>>> {
+    let pods: Api<Pod> = Api::all(ctx.client.clone());
+    let mut config = watcher::Config::default().fields("status.phase=Pending");
+    if let Some(selector) = selector {
+        config = config.labels(selector);
+    }
+    watcher::watcher(pods, config).boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::offering::{InstanceType, Region, Resources};
+    use crate::resources::node_request::{NodeRequestSpec, NodeRequestStatus};
+
+    fn window() -> BatchWindow {
+        BatchWindow {
+            quiet: Duration::from_millis(500),
+            max: Duration::from_secs(10),
+        }
+    }
+
+    #[test]
+    fn bursts_are_due_once_quiet() {
+        let start = Instant::now();
+        let mut batch = Batch::new(window());
+        assert_eq!(batch.due(), None);
+
+        for i in 0..200 {
+            batch.record(start + Duration::from_millis(i));
+        }
+        assert_eq!(batch.due(), Some(start + Duration::from_millis(199 + 500)));
+        assert_eq!(batch.take(), 200);
+        assert_eq!(batch.due(), None);
+    }
+
+    #[test]
+    fn a_steady_trickle_is_cut_off_by_the_max_window() {
+        let start = Instant::now();
+        let mut batch = Batch::new(window());
+        for i in 0..100 {
+            batch.record(start + Duration::from_millis(i * 400));
+        }
+        assert_eq!(batch.due(), Some(start + Duration::from_secs(10)));
+    }
+
+    fn nr(phase: NodeRequestPhase, claims: &[&str]) -> NodeRequest {
+        let mut nr = NodeRequest::new(
+            "nr-1",
+            NodeRequestSpec {
+                target_offering: InstanceType("cpx22".into()),
+                location: Region("eu-central".into()),
+                zone: None,
+                resources: Resources {
+                    cpu_millis: 2000,
+                    memory_mib: 4096,
+                    ephemeral_storage_gib: None,
+                    gpu: 0,
+                    gpu_model: None,
+                    arch: None,
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
+                },
+                node_id: "growth-nr-1".into(),
+                provider: None,
+                claimed_pods: claims.iter().map(|s| s.to_string()).collect(),
+                labels: None,
+                taints: None,
+            },
+        );
+        nr.status = Some(NodeRequestStatus {
+            phase,
+            ..Default::default()
+        });
+        nr
+    }
+
+    #[test]
+    fn node_requests_giving_up_their_pods_release_them() {
+        use watcher::Event::{Apply, Delete};
+
+        let mut phases = NodeRequestPhases::default();
+        let claims = ["uid-a", "uid-b"];
+        assert_eq!(
+            phases.released_pods(&Apply(nr(NodeRequestPhase::Provisioning, &claims))),
+            0
+        );
+        assert_eq!(
+            phases.released_pods(&Apply(nr(NodeRequestPhase::Unmet, &claims))),
+            2
+        );
+        // Further updates while Unmet don't trigger again.
+        assert_eq!(
+            phases.released_pods(&Apply(nr(NodeRequestPhase::Unmet, &claims))),
+            0
+        );
+        assert_eq!(
+            phases.released_pods(&Delete(nr(NodeRequestPhase::Unmet, &claims))),
+            2
+        );
+
+        // A Ready NodeRequest's pods have their node.
+        assert_eq!(
+            phases.released_pods(&Delete(nr(NodeRequestPhase::Ready, &claims))),
+            0
+        );
+        assert_eq!(
+            phases.released_pods(&Delete(nr(NodeRequestPhase::Pending, &claims))),
+            2
+        );
+    }
+}
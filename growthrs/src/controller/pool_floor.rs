@@ -0,0 +1,105 @@
+//! Warm node floors.
+//!
+//! A NodePool's `minNodes`, and each of its server types' `min`, are kept
+//! provisioned whether or not anything is pending: every requeue interval the
+//! pools short of their floor get NodeRequests for the missing nodes. They
+//! claim no pods, so claim GC leaves them alone, and the idle scanner never
+//! removes a pool's nodes below the same floor. A pool with no floor scales
+//! down to zero nodes once idle.
+//!
+//! A pool's `headroom` is kept the same way: its placeholders are solved for
+//! on the pool's free room, and the nodes they still need are provisioned
+//! with NodeRequests labelled [`HEADROOM_LABEL`]. The floor's warm nodes
+//! count towards the headroom, and the idle scanner keeps enough idle nodes
+//! in the pool to hold it.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use tracing::{info, warn};
+
+use crate::controller::node_requests::helpers::create_node_request;
+use crate::controller::pods::{NodeRequestDemand, plan_pool_floors, plan_pool_headroom};
+use crate::offering::HEADROOM_LABEL;
+use crate::resources::node_request::NodeRequestSpec;
+
+use super::{ControllerContext, ControllerError};
+
+/// Periodically provision the nodes pools need to reach their floor.
+pub(super) async fn run_pool_floor_scanner(
+    ctx: Arc<ControllerContext>,
+) -> Result<(), ControllerError> {
+    loop {
+        tokio::time::sleep(ctx.requeue.interval).await;
+        let scan = scan_pool_floors(&ctx);
+        if let Err(e) = ctx.metrics.reconciles.measure("pool_floor", scan).await {
+            warn!(error = %e, "pool floor scan failed, will retry next interval");
+        }
+    }
+}
+
+async fn scan_pool_floors(ctx: &ControllerContext) -> Result<(), ControllerError> {
+    let tunables = ctx.tunables.current();
+    let demands = plan_pool_floors(
+        &ctx.client,
+        ctx.provider.as_ref(),
+        &ctx.provider_health.unhealthy_providers(),
+        &ctx.shard,
+        &tunables.pool_defaults,
+    )
+    .await?;
+    for demand in demands {
+        info!(
+            pool = %demand.pool,
+            offering = %demand.target_offering.instance_type,
+            "pool below its minimum, provisioning a warm node"
+        );
+        provision(ctx, demand, None).await?;
+    }
+
+    let demands = plan_pool_headroom(
+        &ctx.client,
+        ctx.provider.as_ref(),
+        &ctx.provider_health.unhealthy_providers(),
+        tunables.solver,
+        &ctx.shard,
+        &tunables.pool_defaults,
+    )
+    .await?;
+    for demand in demands {
+        info!(
+            pool = %demand.pool,
+            offering = %demand.target_offering.instance_type,
+            "pool short of its headroom, provisioning a node"
+        );
+        let label = BTreeMap::from([(HEADROOM_LABEL.to_string(), demand.pool.clone())]);
+        provision(ctx, demand, Some(label)).await?;
+    }
+    Ok(())
+}
+
+async fn provision(
+    ctx: &ControllerContext,
+    demand: NodeRequestDemand,
+    labels: Option<BTreeMap<String, String>>,
+) -> Result<(), ControllerError> {
+    create_node_request(
+        ctx.client.clone(),
+        &demand.pool,
+        &demand.pool_uid,
+        NodeRequestSpec {
+            target_offering: demand.target_offering.instance_type.clone(),
+            location: demand.target_offering.location.region.clone(),
+            zone: demand.target_offering.location.zone.clone(),
+            resources: demand.target_offering.resources.clone(),
+            node_id: format!("growth-{}", uuid::Uuid::new_v4()),
+            provider: Some(demand.target_offering.provider.clone()),
+            claimed_pods: vec![],
+            labels: Some(demand.labels),
+            taints: Some(demand.taints),
+        },
+        labels,
+    )
+    .await?;
+    Ok(())
+}
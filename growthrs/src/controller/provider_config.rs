@@ -0,0 +1,236 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use kube::runtime::watcher;
+use kube::{Api, Client, ResourceExt};
+use tracing::{info, warn};
+
+use crate::controller::helpers::read_secret_key;
+use crate::controller::status::{StatusWrite, update_status};
+use crate::controller::{ControllerContext, ControllerError};
+use crate::providers::provider::{Provider, ProviderConfig, ProviderError, ProviderRegistry};
+use crate::providers::reloadable::ReloadableProvider;
+use crate::resources::provider_config::{
+    ProviderConfig as ProviderConfigResource, ProviderConfigSpec, ProviderConfigStatus,
+};
+use crate::resources::user_data::UserDataError;
+
+const CONTROLLER: &str = "provider_config";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderConfigError {
+    #[error("failed to read credentials: {0}")]
+    Credentials(#[from] UserDataError),
+    #[error(transparent)]
+    Provider(#[from] ProviderError),
+}
+
+/// Rebuilds the controller's provider from a `ProviderConfig` resource.
+///
+/// Holds everything needed to construct providers after startup: the
+/// registry, the environment-derived defaults to fall back to when the
+/// resource is deleted, and the [`ReloadableProvider`] the controller calls.
+pub struct ProviderReloader {
+    /// Name of the watched cluster-scoped ProviderConfig.
+    pub resource_name: String,
+    registry: ProviderRegistry,
+    /// `GROWTH_PROVIDER`, used when no ProviderConfig exists.
+    default_provider: String,
+    /// Construction config from the environment (`HCLOUD_TOKEN`).
+    base: ProviderConfig,
+    target: Arc<ReloadableProvider>,
+    /// Generation of the ProviderConfig last applied successfully. Status
+    /// patches re-trigger the watch, so unchanged generations are skipped;
+    /// a failed generation stays unrecorded and is retried.
+    observed: Mutex<Option<i64>>,
+    /// Whether a ProviderConfig (rather than the environment) is in use.
+    active: Mutex<bool>,
+}
+
+impl ProviderReloader {
+    pub fn new(
+        resource_name: String,
+        registry: ProviderRegistry,
+        default_provider: String,
+        base: ProviderConfig,
+        target: Arc<ReloadableProvider>,
+    ) -> Self {
+        Self {
+            resource_name,
+            registry,
+            default_provider,
+            base,
+            target,
+            observed: Mutex::new(None),
+            active: Mutex::new(false),
+        }
+    }
+
+    async fn build(
+        &self,
+        client: &Client,
+        spec: &ProviderConfigSpec,
+    ) -> Result<Arc<dyn Provider>, ProviderConfigError> {
+        let mut config = self.base.clone();
+        if let Some(cred) = &spec.credentials_ref {
+            let token = read_secret_key(client, &cred.namespace, &cred.name, &cred.key).await?;
+            config.hcloud_token = Some(token);
+        }
+        Ok(self.registry.build_list(&spec.provider, &config)?)
+    }
+
+    /// Apply `pc` unless its generation was already applied. On failure the
+    /// current provider is kept and the error returned so the caller retries.
+    async fn apply(
+        &self,
+        client: &Client,
+        pc: &ProviderConfigResource,
+    ) -> Result<(), ProviderConfigError> {
+        let generation = pc.metadata.generation;
+        if generation.is_some() && *self.observed.lock().unwrap() == generation {
+            return Ok(());
+        }
+        let result = self.build(client, &pc.spec).await;
+        let (active, message) = match &result {
+            Ok(provider) => {
+                let spec = pc.spec.clone();
+                self.target
+                    .replace(provider.clone(), Some(Box::new(move |o| spec.apply(o))));
+                *self.observed.lock().unwrap() = generation;
+                *self.active.lock().unwrap() = true;
+                let offerings = self.target.offerings().await.len();
+                info!(provider = %pc.spec.provider, offerings, "applied ProviderConfig");
+                if offerings == 0 {
+                    warn!("ProviderConfig filters exclude every offering");
+                }
+                (true, format!("{offerings} offerings available"))
+            }
+            Err(e) => {
+                warn!(error = %e, "failed to apply ProviderConfig, keeping current provider");
+                (false, e.to_string())
+            }
+        };
+        // Written against the latest status, so the health monitor's
+        // `unhealthyProviders` is kept rather than overwritten from `pc`.
+        let api: Api<ProviderConfigResource> = Api::all(client.clone());
+        let written = update_status(&api, &pc.name_any(), |status: &mut ProviderConfigStatus| {
+            status.observed_generation = generation;
+            status.active = active;
+            status.message = Some(message.clone());
+        })
+        .await;
+        match written {
+            Ok(StatusWrite::Written) => {}
+            Ok(StatusWrite::Stale) => warn!("ProviderConfig status kept changing, not updated"),
+            Err(e) => warn!(error = %e, "failed to update ProviderConfig status"),
+        }
+        result.map(drop)
+    }
+
+    /// Apply the ProviderConfig if it already exists, so startup validation
+    /// runs against the configured provider rather than the env default.
+    pub async fn load(&self, client: &Client) -> Result<(), kube::Error> {
+        let api: Api<ProviderConfigResource> = Api::all(client.clone());
+        if let Some(pc) = api.get_opt(&self.resource_name).await? {
+            // A failure is retried by the watcher once it starts.
+            let _ = self.apply(client, &pc).await;
+        }
+        Ok(())
+    }
+
+    /// Return to the environment-configured provider, if a ProviderConfig is
+    /// currently applied.
+    fn reset(&self) {
+        *self.observed.lock().unwrap() = None;
+        if !std::mem::take(&mut *self.active.lock().unwrap()) {
+            return;
+        }
+        match self.registry.build_list(&self.default_provider, &self.base) {
+            Ok(provider) => {
+                self.target.replace(provider, None);
+                info!(provider = %self.default_provider, "ProviderConfig removed, reverted to GROWTH_PROVIDER");
+            }
+            Err(e) => warn!(error = %e, "failed to rebuild default provider, keeping current"),
+        }
+    }
+}
+
+/// Watch the configured ProviderConfig and reconfigure the provider as it
+/// changes. Never resolves when reloading is disabled.
+pub async fn run_provider_config_watcher(
+    ctx: Arc<ControllerContext>,
+) -> Result<(), ControllerError> {
+    let Some(reloader) = &ctx.provider_reloader else {
+        return std::future::pending().await;
+    };
+    let api: Api<ProviderConfigResource> = Api::all(ctx.client.clone());
+    let config =
+        watcher::Config::default().fields(&format!("metadata.name={}", reloader.resource_name));
+    let mut stream = std::pin::pin!(watcher::watcher(api, config));
+
+    // Tracks whether the object survived a relist, so a delete missed while
+    // the watch was down still reverts to the defaults.
+    let mut seen_in_relist = false;
+    // A ProviderConfig whose apply failed, retried with backoff until it
+    // succeeds or a newer event supersedes it.
+    let mut retry: Option<(ProviderConfigResource, Duration)> = None;
+    loop {
+        let event = match &retry {
+            Some((_, delay)) => tokio::select! {
+                event = stream.next() => event,
+                () = tokio::time::sleep(*delay) => {
+                    let (pc, _) = retry.take().expect("retry is pending");
+                    retry = apply(&ctx, reloader, pc).await;
+                    continue;
+                }
+            },
+            None => stream.next().await,
+        };
+        let Some(event) = event else {
+            return Ok(());
+        };
+        match event {
+            Ok(watcher::Event::Init) => seen_in_relist = false,
+            Ok(watcher::Event::InitApply(pc)) => {
+                seen_in_relist = true;
+                retry = apply(&ctx, reloader, pc).await;
+            }
+            Ok(watcher::Event::InitDone) => {
+                if !seen_in_relist {
+                    retry = None;
+                    reloader.reset();
+                }
+            }
+            Ok(watcher::Event::Apply(pc)) => retry = apply(&ctx, reloader, pc).await,
+            Ok(watcher::Event::Delete(_)) => {
+                retry = None;
+                reloader.reset();
+            }
+            Err(e) => warn!(error = %e, "ProviderConfig watcher stream error"),
+        }
+    }
+}
+
+/// Apply `pc`, returning it with a backoff delay if it should be retried.
+async fn apply(
+    ctx: &ControllerContext,
+    reloader: &ProviderReloader,
+    pc: ProviderConfigResource,
+) -> Option<(ProviderConfigResource, Duration)> {
+    match reloader.apply(&ctx.client, &pc).await {
+        Ok(()) => {
+            ctx.requeue
+                .errors
+                .succeeded(CONTROLLER, &reloader.resource_name);
+            None
+        }
+        Err(_) => {
+            let delay = ctx
+                .requeue
+                .errors
+                .failed(CONTROLLER, &reloader.resource_name);
+            Some((pc, delay))
+        }
+    }
+}
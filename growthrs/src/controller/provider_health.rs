@@ -0,0 +1,205 @@
+//! Periodic provider health probes.
+//!
+//! Each interval the controller calls `Provider::health()`. Providers that
+//! fail are recorded here and their offerings are dropped before solving, so
+//! new NodeRequests go to the remaining providers (or wait, if none are
+//! left). The current set is surfaced on the ProviderConfig status as
+//! `unhealthyProviders`.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::jiff::Timestamp;
+use kube::{Api, Client};
+use tracing::{info, warn};
+
+use crate::config::ControllerContext;
+use crate::controller::status::{StatusWrite, update_status};
+use crate::controller::{ControllerError, is_kube_not_found};
+use crate::offering::Offering;
+use crate::providers::provider::ProviderHealth;
+use crate::resources::provider_config::{ProviderConfig, UnhealthyProvider};
+
+/// How often every provider is probed.
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Providers currently failing their health probe.
+#[derive(Default)]
+pub struct ProviderHealthMonitor {
+    /// Provider name → why and since when it is unhealthy.
+    unhealthy: Mutex<BTreeMap<String, UnhealthyProvider>>,
+}
+
+impl ProviderHealthMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names of the providers to plan no new nodes on.
+    pub fn unhealthy_providers(&self) -> HashSet<String> {
+        self.unhealthy.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// The unhealthy providers, as written to the ProviderConfig status.
+    pub fn unhealthy(&self) -> Vec<UnhealthyProvider> {
+        self.unhealthy.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Apply a round of probe results. Providers missing from `reports`
+    /// (e.g. removed by a ProviderConfig change) are forgotten. Returns
+    /// whether the set of unhealthy providers changed.
+    pub fn record(&self, reports: &[ProviderHealth], now: Timestamp) -> bool {
+        let mut unhealthy = self.unhealthy.lock().unwrap();
+        let before: HashSet<String> = unhealthy.keys().cloned().collect();
+        unhealthy.retain(|name, _| {
+            reports
+                .iter()
+                .any(|r| &r.provider == name && !r.is_healthy())
+        });
+        for report in reports {
+            let Some(error) = &report.error else {
+                if before.contains(&report.provider) {
+                    info!(provider = %report.provider, "provider recovered");
+                }
+                continue;
+            };
+            match unhealthy.get_mut(&report.provider) {
+                Some(entry) => entry.reason = error.clone(),
+                None => {
+                    warn!(
+                        provider = %report.provider,
+                        %error,
+                        "provider unhealthy, planning no new nodes on it"
+                    );
+                    unhealthy.insert(
+                        report.provider.clone(),
+                        UnhealthyProvider {
+                            provider: report.provider.clone(),
+                            reason: error.clone(),
+                            since: Time(now),
+                        },
+                    );
+                }
+            }
+        }
+        unhealthy.keys().cloned().collect::<HashSet<_>>() != before
+    }
+}
+
+/// Drop offerings from unhealthy providers before solving.
+pub(crate) fn drop_unhealthy_offerings(offerings: &mut Vec<Offering>, unhealthy: &HashSet<String>) {
+    if unhealthy.is_empty() {
+        return;
+    }
+    let before = offerings.len();
+    offerings.retain(|o| !unhealthy.contains(&o.provider));
+    if offerings.len() < before {
+        warn!(
+            dropped = before - offerings.len(),
+            providers = ?unhealthy,
+            "skipping offerings from unhealthy providers"
+        );
+    }
+}
+
+/// Probe the providers every [`HEALTH_CHECK_INTERVAL`] and keep the
+/// ProviderConfig status up to date.
+pub(super) async fn run_provider_health_monitor(
+    ctx: Arc<ControllerContext>,
+) -> Result<(), ControllerError> {
+    let mut publish = true;
+    loop {
+        let reports = ctx.provider.health().await;
+        publish |= ctx.provider_health.record(&reports, ctx.clock.now());
+        if publish && let Some(reloader) = &ctx.provider_reloader {
+            match patch_unhealthy(
+                &ctx.client,
+                &reloader.resource_name,
+                ctx.provider_health.unhealthy(),
+            )
+            .await
+            {
+                Ok(StatusWrite::Written) => publish = false,
+                // Overtaken by the reloader every time; try again next round.
+                Ok(StatusWrite::Stale) => {}
+                // Nothing to report on without a ProviderConfig; try again
+                // on the next change.
+                Err(e) if is_kube_not_found(&e) => publish = false,
+                Err(e) => warn!(error = %e, "failed to update ProviderConfig health status"),
+            }
+        }
+        tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+    }
+}
+
+/// Replace the ProviderConfig's `unhealthyProviders`, keeping the rest of
+/// its status. An empty list clears recovered providers.
+async fn patch_unhealthy(
+    client: &Client,
+    name: &str,
+    unhealthy: Vec<UnhealthyProvider>,
+) -> Result<StatusWrite, kube::Error> {
+    let api: Api<ProviderConfig> = Api::all(client.clone());
+    update_status(&api, name, |status| {
+        status.unhealthy_providers = unhealthy.clone();
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::offering;
+
+    fn report(provider: &str, error: Option<&str>) -> ProviderHealth {
+        ProviderHealth {
+            provider: provider.into(),
+            error: error.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn unhealthy_set_tracks_probe_results() {
+        let monitor = ProviderHealthMonitor::new();
+        let t0 = Timestamp::from_second(1_000).unwrap();
+        let t1 = Timestamp::from_second(1_030).unwrap();
+
+        assert!(monitor.record(&[report("a", Some("timeout")), report("b", None)], t0));
+        assert_eq!(
+            monitor.unhealthy_providers(),
+            HashSet::from(["a".to_string()])
+        );
+
+        // Still failing: the reason updates, `since` and the set don't.
+        assert!(!monitor.record(&[report("a", Some("503")), report("b", None)], t1));
+        let entry = &monitor.unhealthy()[0];
+        assert_eq!(entry.reason, "503");
+        assert_eq!(entry.since, Time(t0));
+
+        assert!(monitor.record(&[report("a", None), report("b", None)], t1));
+        assert!(monitor.unhealthy_providers().is_empty());
+    }
+
+    #[test]
+    fn removed_providers_are_forgotten() {
+        let monitor = ProviderHealthMonitor::new();
+        let now = Timestamp::from_second(1_000).unwrap();
+        monitor.record(&[report("a", Some("down"))], now);
+        assert!(monitor.record(&[report("b", None)], now));
+        assert!(monitor.unhealthy().is_empty());
+    }
+
+    #[test]
+    fn offerings_from_unhealthy_providers_are_dropped() {
+        let mut offerings = vec![
+            offering("t").with_provider("hetzner").build(),
+            offering("t").with_provider("kwok").build(),
+            offering("t").with_provider("hetzner").build(),
+        ];
+        drop_unhealthy_offerings(&mut offerings, &HashSet::from(["hetzner".to_string()]));
+        assert_eq!(offerings.len(), 1);
+        assert_eq!(offerings[0].provider, "kwok");
+    }
+}
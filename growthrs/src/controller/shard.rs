@@ -0,0 +1,241 @@
+//! Sharding the controller by NodePool.
+//!
+//! Each replica owns a subset of NodePools and only acts on pending pods,
+//! NodeRequests, NodeRemovalRequests and nodes attributed to those pools.
+//! Pools are split either by a stable hash of their name across
+//! `GROWTH_SHARD_COUNT` replicas, or by the `growth.vettrdev.com/shard` label.
+//! Every replica must use the same strategy, and with label sharding every
+//! NodePool must carry the label, or no replica will manage it.
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use k8s_openapi::api::core::v1::Node;
+use kube::{Api, Client};
+
+use crate::controller::errors::ConfigError;
+use crate::controller::node_requests::pool_name_from_nr;
+use crate::offering::{POOL_LABEL, SHARD_LABEL};
+use crate::resources::node_pool::NodePool;
+use crate::resources::node_request::NodeRequest;
+
+use super::ControllerError;
+
+/// How often an object owned by another shard is looked at again, so a pool
+/// relabelled onto this shard is picked up.
+pub(crate) const UNOWNED_REQUEUE: Duration = Duration::from_secs(300);
+
+/// The NodePools this replica is responsible for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Shard {
+    /// Unsharded: every pool.
+    #[default]
+    All,
+    /// Pools whose name hashes to `index` modulo `count`.
+    Hash { index: u32, count: u32 },
+    /// Pools labelled `growth.vettrdev.com/shard=<name>`.
+    Label(String),
+}
+
+impl Shard {
+    /// Build from the `GROWTH_SHARD*` settings. A shard label takes the place
+    /// of hash sharding, so setting both is an error.
+    pub fn from_config(label: Option<String>, index: u32, count: u32) -> Result<Self, ConfigError> {
+        match (label, count) {
+            (Some(_), c) if c > 1 => Err(ConfigError::Other(
+                "GROWTH_SHARD and GROWTH_SHARD_COUNT are mutually exclusive".into(),
+            )),
+            (Some(label), _) => Ok(Shard::Label(label)),
+            (None, 0) => Err(ConfigError::Other(
+                "GROWTH_SHARD_COUNT must be at least 1".into(),
+            )),
+            (None, 1) => Ok(Shard::All),
+            (None, count) if index >= count => Err(ConfigError::Other(format!(
+                "GROWTH_SHARD_INDEX {index} out of range for {count} shards"
+            ))),
+            (None, count) => Ok(Shard::Hash { index, count }),
+        }
+    }
+
+    /// Identifier for per-shard state, e.g. `"1-of-3"`. `None` when unsharded.
+    pub fn id(&self) -> Option<String> {
+        match self {
+            Shard::All => None,
+            Shard::Hash { index, count } => Some(format!("{index}-of-{count}")),
+            Shard::Label(label) => Some(label.clone()),
+        }
+    }
+
+    pub fn is_sharded(&self) -> bool {
+        *self != Shard::All
+    }
+
+    /// Whether this replica owns `pool`.
+    pub fn owns(&self, pool: &NodePool) -> bool {
+        match self {
+            Shard::All => true,
+            Shard::Hash { .. } => pool
+                .metadata
+                .name
+                .as_deref()
+                .is_some_and(|name| self.owns_name(name)),
+            Shard::Label(label) => pool
+                .metadata
+                .labels
+                .as_ref()
+                .and_then(|l| l.get(SHARD_LABEL))
+                .is_some_and(|v| v == label),
+        }
+    }
+
+    /// Whether this replica owns the pool called `name`, fetching the NodePool
+    /// only when ownership depends on its labels. A missing pool is unowned.
+    pub(crate) async fn owns_pool_named(
+        &self,
+        client: &Client,
+        name: &str,
+    ) -> Result<bool, kube::Error> {
+        match self {
+            Shard::All => Ok(true),
+            Shard::Hash { .. } => Ok(self.owns_name(name)),
+            Shard::Label(_) => Ok(Api::<NodePool>::all(client.clone())
+                .get_opt(name)
+                .await?
+                .is_some_and(|pool| self.owns(&pool))),
+        }
+    }
+
+    /// Whether this replica owns the pool that `nr` belongs to.
+    pub(crate) async fn owns_node_request(
+        &self,
+        client: &Client,
+        nr: &NodeRequest,
+    ) -> Result<bool, ControllerError> {
+        if !self.is_sharded() {
+            return Ok(true);
+        }
+        Ok(self
+            .owns_pool_named(client, &pool_name_from_nr(nr)?)
+            .await?)
+    }
+
+    /// Names of the pools in `pools` this replica owns.
+    pub fn owned_pools<'a>(&self, pools: &'a [NodePool]) -> HashSet<&'a str> {
+        pools
+            .iter()
+            .filter(|p| self.owns(p))
+            .filter_map(|p| p.metadata.name.as_deref())
+            .collect()
+    }
+
+    /// Drop Growth nodes belonging to pools this replica doesn't own.
+    pub fn retain_owned_nodes(&self, nodes: &mut Vec<Node>, pools: &[NodePool]) {
+        if !self.is_sharded() {
+            return;
+        }
+        let owned = self.owned_pools(pools);
+        nodes.retain(|n| {
+            n.metadata
+                .labels
+                .as_ref()
+                .and_then(|l| l.get(POOL_LABEL))
+                .is_some_and(|pool| owned.contains(pool.as_str()))
+        });
+    }
+
+    /// Hash ownership by name alone. Label shards can't tell from a name.
+    fn owns_name(&self, name: &str) -> bool {
+        match self {
+            Shard::All => true,
+            Shard::Hash { index, count } => fnv1a(name) % u64::from(*count) == u64::from(*index),
+            Shard::Label(_) => false,
+        }
+    }
+}
+
+/// FNV-1a, which unlike `DefaultHasher` is stable across builds, so replicas
+/// running different binaries still agree on ownership.
+pub(crate) fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::resources::node_pool::NodePoolSpec;
+
+    fn pool(name: &str, shard: Option<&str>) -> NodePool {
+        let mut pool = NodePool::new(
+            name,
+            NodePoolSpec {
+                server_types: vec![],
+                min_nodes: 0,
+                max_nodes: None,
+                headroom: None,
+                labels: BTreeMap::new(),
+                taints: vec![],
+                kubelet_args: vec![],
+                join_strategy: None,
+                locations: None,
+                node_class_ref: None,
+                evacuation: None,
+                consolidation: None,
+                disruption_budget: None,
+                fragmentation_penalty: None,
+                target_utilization: None,
+                interruption_penalty: None,
+                cost_objective: None,
+                instance_families: None,
+                max_additional_cost_per_hour: None,
+                max_new_nodes: None,
+                include_instance_types: None,
+                exclude_instance_types: None,
+            },
+        );
+        pool.metadata.labels = shard.map(|s| BTreeMap::from([(SHARD_LABEL.into(), s.into())]));
+        pool
+    }
+
+    #[test]
+    fn hash_shards_partition_pools() {
+        let pools: Vec<NodePool> = (0..50).map(|i| pool(&format!("pool-{i}"), None)).collect();
+        let shards: Vec<Shard> = (0..3)
+            .map(|index| Shard::Hash { index, count: 3 })
+            .collect();
+        for p in &pools {
+            assert_eq!(shards.iter().filter(|s| s.owns(p)).count(), 1);
+        }
+        // Every shard gets some of the pools.
+        assert!(shards.iter().all(|s| pools.iter().any(|p| s.owns(p))));
+    }
+
+    #[test]
+    fn label_shards_own_only_labelled_pools() {
+        let shard = Shard::Label("gpu".into());
+        assert!(shard.owns(&pool("training", Some("gpu"))));
+        assert!(!shard.owns(&pool("web", Some("general"))));
+        assert!(!shard.owns(&pool("batch", None)));
+        assert!(Shard::All.owns(&pool("batch", None)));
+    }
+
+    #[test]
+    fn config_is_validated() {
+        assert_eq!(Shard::from_config(None, 0, 1).unwrap(), Shard::All);
+        assert_eq!(
+            Shard::from_config(None, 2, 3).unwrap(),
+            Shard::Hash { index: 2, count: 3 }
+        );
+        assert_eq!(
+            Shard::from_config(Some("gpu".into()), 0, 1).unwrap(),
+            Shard::Label("gpu".into())
+        );
+        assert!(Shard::from_config(None, 3, 3).is_err());
+        assert!(Shard::from_config(None, 0, 0).is_err());
+        assert!(Shard::from_config(Some("gpu".into()), 0, 2).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Status writes for growth-rs's own resources.
+//!
+//! Statuses are written whole, through the status subresource, with
+//! server-side apply as the `growthrs` field manager. Ownership is forced:
+//! the controller is the only writer of these statuses, so a field last set
+//! by `kubectl edit` or an older merge patch is taken over rather than
+//! reported as a conflict. Writes based on an earlier read can pin that
+//! read's resourceVersion with [`apply_status_at`], turning a write made in
+//! between into [`StatusWrite::Stale`] instead of an overwrite. Statuses with
+//! more than one writer are changed through [`update_status`], which re-reads
+//! and retries until its write lands on the version it read.
+
+use std::fmt::Debug;
+
+use kube::api::{Patch, PatchParams};
+use kube::core::object::HasStatus;
+use kube::{Api, Resource};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Field manager every status write is made as.
+pub const FIELD_MANAGER: &str = "growthrs";
+
+/// Reads [`update_status`] makes before leaving a status that keeps changing
+/// under it to the caller's next attempt.
+const UPDATE_ATTEMPTS: usize = 5;
+
+/// Outcome of a status write pinned to a resourceVersion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusWrite {
+    Written,
+    /// The object changed since it was read; nothing was written.
+    Stale,
+}
+
+fn status_patch<K>(status: &K::Status, resource_version: Option<&str>) -> serde_json::Value
+where
+    K: Resource<DynamicType = ()> + HasStatus,
+    K::Status: Serialize,
+{
+    let mut patch = serde_json::json!({
+        "apiVersion": K::api_version(&()),
+        "kind": K::kind(&()),
+        "status": status,
+    });
+    if let Some(resource_version) = resource_version {
+        patch["metadata"] = serde_json::json!({ "resourceVersion": resource_version });
+    }
+    patch
+}
+
+/// Replace the status of `name` with `status`.
+pub async fn apply_status<K>(
+    api: &Api<K>,
+    name: &str,
+    status: &K::Status,
+) -> Result<(), kube::Error>
+where
+    K: Resource<DynamicType = ()> + HasStatus + Clone + DeserializeOwned + Debug,
+    K::Status: Serialize,
+{
+    api.patch_status(
+        name,
+        &PatchParams::apply(FIELD_MANAGER).force(),
+        &Patch::Apply(status_patch::<K>(status, None)),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Replace the status of `name` with `status` if the object is still at
+/// `resource_version`.
+pub async fn apply_status_at<K>(
+    api: &Api<K>,
+    name: &str,
+    resource_version: &str,
+    status: &K::Status,
+) -> Result<StatusWrite, kube::Error>
+where
+    K: Resource<DynamicType = ()> + HasStatus + Clone + DeserializeOwned + Debug,
+    K::Status: Serialize,
+{
+    match api
+        .patch_status(
+            name,
+            &PatchParams::apply(FIELD_MANAGER).force(),
+            &Patch::Apply(status_patch::<K>(status, Some(resource_version))),
+        )
+        .await
+    {
+        Ok(_) => Ok(StatusWrite::Written),
+        Err(kube::Error::Api(resp)) if resp.code == 409 => Ok(StatusWrite::Stale),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read `name`, change its status with `update` and write it back pinned to
+/// the read's resourceVersion, re-reading while other writers get in between.
+/// Returns [`StatusWrite::Stale`] if every one of [`UPDATE_ATTEMPTS`] reads
+/// was overtaken.
+pub async fn update_status<K>(
+    api: &Api<K>,
+    name: &str,
+    mut update: impl FnMut(&mut K::Status),
+) -> Result<StatusWrite, kube::Error>
+where
+    K: Resource<DynamicType = ()> + HasStatus + Clone + DeserializeOwned + Debug,
+    K::Status: Serialize + Clone + Default,
+{
+    for _ in 0..UPDATE_ATTEMPTS {
+        let obj = api.get(name).await?;
+        let mut status = obj.status().cloned().unwrap_or_default();
+        update(&mut status);
+        let resource_version = obj.meta().resource_version.clone().unwrap_or_default();
+        if apply_status_at(api, name, &resource_version, &status).await? == StatusWrite::Written {
+            return Ok(StatusWrite::Written);
+        }
+    }
+    Ok(StatusWrite::Stale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::node_request::{NodeRequest, NodeRequestPhase, NodeRequestStatus};
+
+    #[tokio::test]
+    async fn stale_writes_are_reported_not_raised() {
+        let (mock_svc, mut handle) = tower_test::mock::pair::<
+            http::Request<kube::client::Body>,
+            http::Response<kube::client::Body>,
+        >();
+        let server = tokio::spawn(async move {
+            let (request, send) = handle.next_request().await.unwrap();
+            assert_eq!(request.method(), http::Method::PATCH);
+            assert_eq!(
+                request.uri().path(),
+                "/apis/growth.vettrdev.com/v1alpha1/noderequests/nr-a/status"
+            );
+            let query = request.uri().query().unwrap().to_string();
+            assert!(query.contains("fieldManager=growthrs") && query.contains("force=true"));
+            assert_eq!(
+                request.headers()[http::header::CONTENT_TYPE],
+                "application/apply-patch+yaml"
+            );
+            let body = request.into_body().collect_bytes().await.unwrap();
+            let patch: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(patch["kind"], "NodeRequest");
+            assert_eq!(patch["metadata"]["resourceVersion"], "41");
+            assert_eq!(patch["status"]["phase"], "Ready");
+            send.send_response(
+                http::Response::builder()
+                    .status(409)
+                    .body(kube::client::Body::from(
+                        serde_json::to_vec(&serde_json::json!({
+                            "kind": "Status", "apiVersion": "v1", "status": "Failure",
+                            "reason": "Conflict", "message": "object was modified", "code": 409
+                        }))
+                        .unwrap(),
+                    ))
+                    .unwrap(),
+            );
+        });
+
+        let api: Api<NodeRequest> = Api::all(kube::Client::new(mock_svc, "default"));
+        let status = NodeRequestStatus {
+            phase: NodeRequestPhase::Ready,
+            ..Default::default()
+        };
+        let written = apply_status_at(&api, "nr-a", "41", &status).await.unwrap();
+        server.await.unwrap();
+        assert_eq!(written, StatusWrite::Stale);
+    }
+
+    #[tokio::test]
+    async fn updates_are_retried_on_the_version_they_overtook() {
+        let (mock_svc, mut handle) = tower_test::mock::pair::<
+            http::Request<kube::client::Body>,
+            http::Response<kube::client::Body>,
+        >();
+        let object = |resource_version: &str, node_id: &str| {
+            serde_json::to_vec(&serde_json::json!({
+                "apiVersion": "growth.vettrdev.com/v1alpha1",
+                "kind": "NodeRequest",
+                "metadata": { "name": "nr-a", "resourceVersion": resource_version },
+                "spec": {
+                    "nodeID": "growth-a",
+                    "targetOffering": "cpx22",
+                    "location": "eu-central",
+                    "resources": { "cpuMillis": 2000, "memoryMib": 4096, "gpu": 0 },
+                },
+                "status": { "phase": "Pending", "nodeID": node_id },
+            }))
+            .unwrap()
+        };
+        let server = tokio::spawn(async move {
+            let mut written = vec![];
+            for (resource_version, node_id, code) in [("41", "n-1", 409), ("42", "n-2", 200)] {
+                let (request, send) = handle.next_request().await.unwrap();
+                assert_eq!(request.method(), http::Method::GET);
+                send.send_response(
+                    http::Response::builder()
+                        .body(kube::client::Body::from(object(resource_version, node_id)))
+                        .unwrap(),
+                );
+                let (request, send) = handle.next_request().await.unwrap();
+                assert_eq!(request.method(), http::Method::PATCH);
+                let body = request.into_body().collect_bytes().await.unwrap();
+                let patch: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                assert_eq!(patch["metadata"]["resourceVersion"], resource_version);
+                written.push(patch["status"].clone());
+                let body = if code == 409 {
+                    serde_json::to_vec(&serde_json::json!({
+                        "kind": "Status", "apiVersion": "v1", "status": "Failure",
+                        "reason": "Conflict", "message": "object was modified", "code": 409
+                    }))
+                    .unwrap()
+                } else {
+                    object(resource_version, node_id)
+                };
+                send.send_response(
+                    http::Response::builder()
+                        .status(code)
+                        .body(kube::client::Body::from(body))
+                        .unwrap(),
+                );
+            }
+            written
+        });
+
+        let api: Api<NodeRequest> = Api::all(kube::Client::new(mock_svc, "default"));
+        let written = update_status(&api, "nr-a", |status| {
+            status.phase = NodeRequestPhase::Ready;
+        })
+        .await
+        .unwrap();
+        let patches = server.await.unwrap();
+        assert_eq!(written, StatusWrite::Written);
+        // The retry keeps what the other writer set in between.
+        assert_eq!(patches[1]["phase"], "Ready");
+        assert_eq!(patches[1]["nodeID"], "n-2");
+    }
+}
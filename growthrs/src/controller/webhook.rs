@@ -0,0 +1,653 @@
+//! Validating admission webhook for NodePools and NodeRequests, and the
+//! conversion webhook between NodeRequest versions.
+//!
+//! Specs that could only fail later, in reconciliation, are rejected when
+//! they are applied: server types or target offerings the provider doesn't
+//! sell, a pool's `max` lowered below the nodes it already has, and taints
+//! the API server would refuse on the node. Checks that need the provider or
+//! the cluster are skipped, not failed, when those can't be reached, so an
+//! outage never blocks edits. Updates that leave `spec` alone and objects
+//! being deleted are admitted unchecked, so the controller's finalizer and
+//! claim patches never depend on today's catalog.
+//!
+//! `POST /convert` converts NodeRequests between `v1alpha1` and `v1beta1`
+//! for the API server once the CRD is installed with a conversion webhook
+//! (`GROWTH_CONVERSION_SERVICE`).
+//!
+//! The API server only calls webhooks over TLS; the certificate and key are
+//! read from the files named by `GROWTH_WEBHOOK_CERT` and `GROWTH_WEBHOOK_KEY`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::routing::post;
+use axum::{Json, Router};
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use k8s_openapi::api::core::v1::{Node, Taint};
+use kube::Api;
+use kube::api::ListParams;
+use kube::core::DynamicObject;
+use kube::core::Status;
+use kube::core::admission::{AdmissionRequest, AdmissionResponse, AdmissionReview, Operation};
+use kube::core::conversion::{ConversionRequest, ConversionResponse, ConversionReview};
+use serde::de::DeserializeOwned;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tracing::{debug, info, warn};
+
+use crate::config::ControllerContext;
+use crate::offering::{INSTANCE_TYPE_LABEL, Offering, POOL_LABEL};
+use crate::resources::node_pool::NodePool;
+use crate::resources::node_request::{NodeRequest, v1beta1};
+
+/// Where the webhook listens and the TLS files it serves with.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub port: u16,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+/// Why `pool` would fail later: server types `offerings` lack, limits that
+/// contradict each other or are below `usage` (the pool's current nodes by
+/// instance type), and malformed taints. Empty `offerings` skips the
+/// catalog checks.
+pub fn node_pool_problems(
+    pool: &NodePool,
+    offerings: &[Offering],
+    usage: &HashMap<String, u32>,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    for st in &pool.spec.server_types {
+        if !offerings.is_empty() && !offerings.iter().any(|o| o.instance_type.0 == st.name) {
+            problems.push(format!(
+                "server type {:?} is not offered by the provider",
+                st.name
+            ));
+        }
+        if st.min > st.max {
+            problems.push(format!(
+                "server type {:?} has min {} above max {}",
+                st.name, st.min, st.max
+            ));
+        }
+        let current = usage.get(&st.name).copied().unwrap_or(0);
+        if st.max < current {
+            problems.push(format!(
+                "server type {:?} has max {} but the pool already has {current} such nodes",
+                st.name, st.max
+            ));
+        }
+    }
+    if let Some(max_nodes) = pool.spec.max_nodes {
+        let type_mins: u32 = pool.spec.server_types.iter().map(|st| st.min).sum();
+        let floor = pool.spec.min_nodes.max(type_mins);
+        if floor > max_nodes {
+            problems.push(format!(
+                "pool keeps at least {floor} nodes but maxNodes is {max_nodes}"
+            ));
+        }
+        let current: u32 = usage.values().sum();
+        if max_nodes < current {
+            problems.push(format!(
+                "maxNodes is {max_nodes} but the pool already has {current} nodes"
+            ));
+        }
+    }
+    if let Some(headroom) = &pool.spec.headroom
+        && headroom.replicas > 0
+        && !offerings.is_empty()
+        && !offerings.iter().any(|o| {
+            pool.spec
+                .server_types
+                .iter()
+                .any(|st| st.name == o.instance_type.0)
+                && o.resources.cpu_millis >= headroom.cpu_millis
+                && o.resources.memory_mib >= headroom.memory_mib
+        })
+    {
+        problems.push(format!(
+            "headroom replicas of {}m CPU and {}Mi memory fit none of the pool's server types",
+            headroom.cpu_millis, headroom.memory_mib
+        ));
+    }
+    if let Some(locations) = &pool.spec.locations
+        && !offerings.is_empty()
+    {
+        for loc in locations {
+            if !offerings.iter().any(|o| o.location.region.0 == loc.region) {
+                problems.push(format!(
+                    "region {:?} is not offered by the provider",
+                    loc.region
+                ));
+            }
+        }
+    }
+    problems.extend(taint_problems(&pool.spec.taints));
+    problems
+}
+
+/// Why `nr` would fail to provision: a target offering `offerings` lack in
+/// its region, or malformed taints. Empty `offerings` skips the catalog check.
+pub fn node_request_problems(nr: &NodeRequest, offerings: &[Offering]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let spec = &nr.spec;
+    if !nr
+        .metadata
+        .owner_references
+        .as_ref()
+        .is_some_and(|refs| refs.iter().any(|r| r.kind == "NodePool"))
+    {
+        problems.push("NodeRequest has no NodePool owner reference".to_string());
+    }
+    if !offerings.is_empty()
+        && !offerings
+            .iter()
+            .any(|o| o.instance_type == spec.target_offering && o.location.region == spec.location)
+    {
+        problems.push(format!(
+            "offering {:?} in {:?} is not offered by the provider",
+            spec.target_offering.0, spec.location.0
+        ));
+    }
+    problems.extend(taint_problems(spec.taints.as_deref().unwrap_or_default()));
+    problems
+}
+
+/// Taints the API server would reject on a Node.
+fn taint_problems(taints: &[Taint]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for taint in taints {
+        if !matches!(
+            taint.effect.as_str(),
+            "NoSchedule" | "PreferNoSchedule" | "NoExecute"
+        ) {
+            problems.push(format!(
+                "taint {:?} has effect {:?}, expected NoSchedule, PreferNoSchedule or NoExecute",
+                taint.key, taint.effect
+            ));
+        }
+        if !is_qualified_name(&taint.key) {
+            problems.push(format!(
+                "taint key {:?} is not a valid qualified name",
+                taint.key
+            ));
+        }
+        if let Some(value) = taint.value.as_deref()
+            && !value.is_empty()
+            && !is_name_part(value)
+        {
+            problems.push(format!("taint {:?} has invalid value {value:?}", taint.key));
+        }
+    }
+    problems
+}
+
+/// `[prefix/]name`, as for label keys: a DNS subdomain prefix and a name of
+/// at most 63 alphanumerics, `-`, `_` or `.`, starting and ending alphanumeric.
+fn is_qualified_name(key: &str) -> bool {
+    let (prefix, name) = match key.rsplit_once('/') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, key),
+    };
+    let prefix_ok = prefix.is_none_or(|p| {
+        !p.is_empty()
+            && p.len() <= 253
+            && p.split('.').all(|label| {
+                !label.is_empty()
+                    && label.len() <= 63
+                    && label
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+            })
+    });
+    prefix_ok && is_name_part(name)
+}
+
+fn is_name_part(name: &str) -> bool {
+    let alnum = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+    name.len() <= 63
+        && alnum(name.chars().next())
+        && alnum(name.chars().last())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// The object under review as a `K`.
+fn object<K: DeserializeOwned>(req: &AdmissionRequest<DynamicObject>) -> Result<Option<K>, String> {
+    req.object
+        .as_ref()
+        .map(|obj| {
+            serde_json::to_value(obj)
+                .and_then(serde_json::from_value)
+                .map_err(|e| format!("invalid {}: {e}", req.kind.kind))
+        })
+        .transpose()
+}
+
+/// Nodes the pool named `pool` has now, by instance type.
+async fn pool_usage(
+    client: &kube::Client,
+    pool: &str,
+) -> Result<HashMap<String, u32>, kube::Error> {
+    let nodes: Api<Node> = Api::all(client.clone());
+    let lp = ListParams::default().labels(&format!("{POOL_LABEL}={pool}"));
+    let mut usage = HashMap::new();
+    for node in nodes.list(&lp).await? {
+        if let Some(instance_type) = node
+            .metadata
+            .labels
+            .as_ref()
+            .and_then(|l| l.get(INSTANCE_TYPE_LABEL))
+        {
+            *usage.entry(instance_type.clone()).or_insert(0) += 1;
+        }
+    }
+    Ok(usage)
+}
+
+/// Whether `req` leaves nothing to review: an object being deleted, or an
+/// update that doesn't touch `spec` beyond a NodeRequest's claimed pods, e.g.
+/// the controller removing its finalizer or nominating pods. The spec was
+/// checked when it was applied, and re-checking it against today's catalog
+/// would block those writes once its offering is no longer sold.
+fn spec_unchanged_or_deleting(req: &AdmissionRequest<DynamicObject>) -> bool {
+    let Some(obj) = req.object.as_ref() else {
+        return true;
+    };
+    if obj.metadata.deletion_timestamp.is_some() {
+        return true;
+    }
+    matches!(req.operation, Operation::Update)
+        && req
+            .old_object
+            .as_ref()
+            .is_some_and(|old| reviewed_spec(old) == reviewed_spec(obj))
+}
+
+/// `obj`'s spec without the pod claims the controller keeps updating
+/// (`claimedPods` in `v1alpha1`, `nominatedPods` in `v1beta1`).
+fn reviewed_spec(obj: &DynamicObject) -> Option<serde_json::Value> {
+    let mut spec = obj.data.get("spec")?.clone();
+    if let Some(fields) = spec.as_object_mut() {
+        fields.remove("claimedPods");
+        fields.remove("nominatedPods");
+    }
+    Some(spec)
+}
+
+async fn review_problems(
+    ctx: &ControllerContext,
+    req: &AdmissionRequest<DynamicObject>,
+) -> Result<Vec<String>, String> {
+    if matches!(req.operation, Operation::Delete | Operation::Connect)
+        || spec_unchanged_or_deleting(req)
+    {
+        return Ok(vec![]);
+    }
+    match req.kind.kind.as_str() {
+        "NodePool" => {
+            let Some(pool) = object::<NodePool>(req)? else {
+                return Ok(vec![]);
+            };
+            let name = pool.metadata.name.as_deref().unwrap_or(&req.name);
+            let usage = pool_usage(&ctx.client, name).await.unwrap_or_else(|e| {
+                warn!(pool = name, error = %e, "could not count the pool's nodes, not checking limits against them");
+                HashMap::new()
+            });
+            let offerings = ctx.provider.offerings().await;
+            Ok(node_pool_problems(&pool, &offerings, &usage))
+        }
+        "NodeRequest" => {
+            let mut problems = Vec::new();
+            let nr = match req.kind.version.as_str() {
+                "v1beta1" => object::<v1beta1::NodeRequest>(req)?.map(|nr| {
+                    problems.extend(v1beta1::pool_ref_problem(&nr));
+                    NodeRequest::from(nr)
+                }),
+                _ => object::<NodeRequest>(req)?,
+            };
+            let Some(nr) = nr else {
+                return Ok(vec![]);
+            };
+            let offerings = ctx.provider.offerings().await;
+            problems.extend(node_request_problems(&nr, &offerings));
+            Ok(problems)
+        }
+        _ => Ok(vec![]),
+    }
+}
+
+async fn validate_handler(
+    State(ctx): State<Arc<ControllerContext>>,
+    Json(review): Json<AdmissionReview<DynamicObject>>,
+) -> Json<AdmissionReview<DynamicObject>> {
+    let req: AdmissionRequest<DynamicObject> = match review.try_into() {
+        Ok(req) => req,
+        Err(e) => {
+            warn!(error = %e, "malformed admission review");
+            return Json(AdmissionResponse::invalid(e.to_string()).into_review());
+        }
+    };
+    let response = AdmissionResponse::from(&req);
+    let response = match review_problems(&ctx, &req).await {
+        Ok(problems) if problems.is_empty() => response,
+        Ok(problems) => {
+            info!(kind = %req.kind.kind, name = %req.name, ?problems, "rejecting invalid spec");
+            response.deny(problems.join("; "))
+        }
+        Err(e) => response.deny(e),
+    };
+    Json(response.into_review())
+}
+
+async fn convert_handler(Json(review): Json<ConversionReview>) -> Json<ConversionReview> {
+    let mut req = match ConversionRequest::from_review(review) {
+        Ok(req) => req,
+        Err(e) => {
+            warn!(error = %e, "malformed conversion review");
+            let status = Status::failure(&e.to_string(), "BadRequest");
+            return Json(ConversionResponse::invalid(status).into_review());
+        }
+    };
+    let converted = std::mem::take(&mut req.objects)
+        .into_iter()
+        .map(|object| v1beta1::convert(object, &req.desired_api_version))
+        .collect::<Result<Vec<_>, _>>();
+    let response = ConversionResponse::for_request(req);
+    let response = match converted {
+        Ok(objects) => response.success(objects),
+        Err(e) => {
+            warn!(error = %e, "NodeRequest conversion failed");
+            response.failure(Status::failure(&e, "ConversionFailed"))
+        }
+    };
+    Json(response.into_review())
+}
+
+fn tls_config(config: &WebhookConfig) -> anyhow::Result<ServerConfig> {
+    let certs = CertificateDer::pem_file_iter(&config.cert)?.collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(&config.key)?;
+    Ok(
+        ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?,
+    )
+}
+
+/// Serve `POST /validate` and `POST /convert` over TLS. Never resolves when the webhook is not
+/// configured.
+pub async fn webhook(ctx: Arc<ControllerContext>) -> anyhow::Result<()> {
+    let Some(config) = ctx.webhook.clone() else {
+        return std::future::pending().await;
+    };
+    let acceptor = TlsAcceptor::from(Arc::new(tls_config(&config)?));
+    let app = Router::new()
+        .route("/validate", post(validate_handler))
+        .route("/convert", post(convert_handler))
+        .with_state(ctx);
+    let listener = TcpListener::bind(("0.0.0.0", config.port)).await?;
+    info!(port = config.port, "admission webhook listening");
+    loop {
+        let (tcp, peer) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            let tls = match acceptor.accept(tcp).await {
+                Ok(tls) => tls,
+                Err(e) => {
+                    debug!(%peer, error = %e, "webhook TLS handshake failed");
+                    return;
+                }
+            };
+            if let Err(e) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(tls), service)
+                .await
+            {
+                debug!(%peer, error = %e, "webhook connection failed");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+
+    use crate::offering::{InstanceType, Region, Resources};
+    use crate::resources::node_pool::{LocationConstraint, NodePoolSpec, ServerTypeConfig};
+    use crate::resources::node_request::NodeRequestSpec;
+    use crate::testing::offering;
+
+    fn resources() -> Resources {
+        Resources {
+            cpu_millis: 2000,
+            memory_mib: 4096,
+            ephemeral_storage_gib: None,
+            gpu: 0,
+            gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
+        }
+    }
+
+    fn taint(key: &str, effect: &str) -> Taint {
+        Taint {
+            key: key.into(),
+            effect: effect.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pool_wide_limits_must_hold_the_floor_and_current_nodes() {
+        let spec: NodePoolSpec = serde_json::from_value(serde_json::json!({
+            "serverTypes": [{ "name": "cpx22", "max": 5, "min": 2 }],
+            "minNodes": 3,
+            "maxNodes": 2,
+        }))
+        .unwrap();
+        let usage = HashMap::from([("cpx22".to_string(), 4)]);
+        let problems = node_pool_problems(&NodePool::new("web", spec), &[], &usage);
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        assert!(problems[0].contains("at least 3 nodes but maxNodes is 2"));
+        assert!(problems[1].contains("already has 4 nodes"));
+    }
+
+    #[test]
+    fn pools_with_unknown_types_short_limits_or_bad_taints_are_rejected() {
+        let offerings = [offering("cpx22").build()];
+        let mut spec: NodePoolSpec = serde_json::from_value(serde_json::json!({
+            "serverTypes": [{ "name": "cpx22", "max": 5 }],
+        }))
+        .unwrap();
+        let pool = |spec: &NodePoolSpec| NodePool::new("web", spec.clone());
+        assert!(node_pool_problems(&pool(&spec), &offerings, &HashMap::new()).is_empty());
+
+        spec.server_types.push(ServerTypeConfig {
+            name: "cpx99".into(),
+            max: 1,
+            min: 2,
+        });
+        spec.locations = Some(vec![LocationConstraint {
+            region: "us-west".into(),
+            zones: None,
+        }]);
+        spec.taints = vec![
+            taint("dedicated", "NoSchedule"),
+            taint("growth.vettrdev.com/gpu", "Sometimes"),
+            taint("-bad", "NoExecute"),
+        ];
+        let usage = HashMap::from([("cpx22".to_string(), 6)]);
+        let problems = node_pool_problems(&pool(&spec), &offerings, &usage);
+        assert_eq!(problems.len(), 6, "{problems:?}");
+        assert!(problems[0].contains("already has 6"));
+        assert!(problems[1].contains("\"cpx99\" is not offered"));
+        assert!(problems[2].contains("min 2 above max 1"));
+        assert!(problems[3].contains("us-west"));
+        assert!(problems[4].contains("Sometimes"));
+        assert!(problems[5].contains("\"-bad\""));
+
+        // Without a catalog only the spec itself is checked.
+        assert_eq!(
+            node_pool_problems(&pool(&spec), &[], &HashMap::new()).len(),
+            3
+        );
+    }
+
+    #[test]
+    fn headroom_must_fit_a_server_type() {
+        let offerings = [offering("cpx22").build()];
+        let spec = |cpu_millis: u64| -> NodePoolSpec {
+            serde_json::from_value(serde_json::json!({
+                "serverTypes": [{ "name": "cpx22", "max": 5 }],
+                "headroom": { "replicas": 2, "cpuMillis": cpu_millis, "memoryMib": 1024 },
+            }))
+            .unwrap()
+        };
+        let problems =
+            |spec| node_pool_problems(&NodePool::new("web", spec), &offerings, &HashMap::new());
+        assert!(problems(spec(2000)).is_empty());
+        let too_big = problems(spec(4000));
+        assert_eq!(too_big.len(), 1, "{too_big:?}");
+        assert!(too_big[0].contains("4000m CPU"));
+    }
+
+    #[test]
+    fn node_requests_must_target_an_offering_in_its_region() {
+        let nr = |instance_type: &str, region: &str| {
+            let mut nr = NodeRequest::new(
+                "nr-1",
+                NodeRequestSpec {
+                    target_offering: InstanceType(instance_type.into()),
+                    location: Region(region.into()),
+                    zone: None,
+                    resources: resources(),
+                    node_id: "growth-nr-1".into(),
+                    provider: None,
+                    claimed_pods: vec![],
+                    labels: None,
+                    taints: Some(vec![taint("example.com/team", "NoSchedule")]),
+                },
+            );
+            nr.metadata.owner_references = Some(vec![OwnerReference {
+                api_version: "growth.vettrdev.com/v1alpha1".into(),
+                kind: "NodePool".into(),
+                name: "default".into(),
+                uid: "pool-uid".into(),
+                controller: Some(true),
+                block_owner_deletion: Some(true),
+            }]);
+            nr
+        };
+        let offerings = [offering("cpx22").build()];
+        assert!(node_request_problems(&nr("cpx22", "eu-central"), &offerings).is_empty());
+        let mut orphan = nr("cpx22", "eu-central");
+        orphan.metadata.owner_references = None;
+        assert_eq!(node_request_problems(&orphan, &offerings).len(), 1);
+        assert_eq!(
+            node_request_problems(&nr("cpx22", "us-west"), &offerings).len(),
+            1
+        );
+        assert_eq!(
+            node_request_problems(&nr("cpx99", "eu-central"), &offerings).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn updates_that_only_drop_a_finalizer_are_not_reviewed() {
+        let review = |operation: &str,
+                      new_offering: &str,
+                      claimed: &[&str],
+                      deleting: bool|
+         -> AdmissionRequest<DynamicObject> {
+            let spec = |offering: &str, claimed: &[&str]| {
+                serde_json::json!({
+                    "targetOffering": offering,
+                    "claimedPods": claimed,
+                    "location": "retired-region",
+                    "resources": { "cpuMillis": 2000, "memoryMib": 4096 },
+                    "nodeId": "growth-nr-1",
+                })
+            };
+            let mut metadata = serde_json::json!({ "name": "nr-1" });
+            if deleting {
+                metadata["deletionTimestamp"] = "2026-01-01T00:00:00Z".into();
+            }
+            let review: AdmissionReview<DynamicObject> =
+                serde_json::from_value(serde_json::json!({
+                    "apiVersion": "admission.k8s.io/v1",
+                    "kind": "AdmissionReview",
+                    "request": {
+                        "uid": "uid-1",
+                        "kind": { "group": "growth.vettrdev.com", "version": "v1alpha1", "kind": "NodeRequest" },
+                        "resource": { "group": "growth.vettrdev.com", "version": "v1alpha1", "resource": "noderequests" },
+                        "name": "nr-1",
+                        "operation": operation,
+                        "userInfo": {},
+                        "object": {
+                            "apiVersion": "growth.vettrdev.com/v1alpha1",
+                            "kind": "NodeRequest",
+                            "metadata": metadata,
+                            "spec": spec(new_offering, claimed),
+                        },
+                        "oldObject": {
+                            "apiVersion": "growth.vettrdev.com/v1alpha1",
+                            "kind": "NodeRequest",
+                            "metadata": { "name": "nr-1", "finalizers": ["growth.vettrdev.com/node-cleanup"] },
+                            "spec": spec("retired", &[]),
+                        },
+                    },
+                }))
+                .unwrap();
+            review.try_into().unwrap()
+        };
+        // The controller dropping its finalizer, or nominating pods.
+        assert!(spec_unchanged_or_deleting(&review(
+            "UPDATE",
+            "retired",
+            &[],
+            false
+        )));
+        assert!(spec_unchanged_or_deleting(&review(
+            "UPDATE",
+            "retired",
+            &["uid-1"],
+            false
+        )));
+        assert!(spec_unchanged_or_deleting(&review(
+            "UPDATE",
+            "cpx22",
+            &[],
+            true
+        )));
+        assert!(!spec_unchanged_or_deleting(&review(
+            "UPDATE",
+            "cpx22",
+            &[],
+            false
+        )));
+        assert!(!spec_unchanged_or_deleting(&review(
+            "CREATE",
+            "retired",
+            &[],
+            false
+        )));
+    }
+}
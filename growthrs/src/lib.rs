@@ -3,9 +3,11 @@ pub mod config;
 pub mod controller;
 pub mod offering;
 pub mod optimiser;
+pub mod planner;
 pub mod providers;
 pub mod resources;
+pub mod simulation;
 
-/// Shared test helpers for integration tests and the `test_pod` binary.
-#[cfg(feature = "testing")]
+/// Shared test helpers for unit and integration tests and the `test_pod` binary.
+#[cfg(any(test, feature = "testing"))]
 pub mod testing;
@@ -1,14 +1,211 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Duration;
 
-use growthrs::controller::errors::ControllerError;
-use growthrs::controller::healthcheck;
-use growthrs::{config::ControllerContext, controller};
+use anyhow::{Context, bail};
+use clap::{Parser, Subcommand};
+use growthrs::config::{ControllerContext, layered_settings};
+use growthrs::controller;
+use growthrs::controller::errors::{ConfigError, ControllerError};
+use growthrs::controller::{PoolConfig, healthcheck, webhook};
+use growthrs::offering::Offering;
+use growthrs::optimiser::{SolveOptions, solver_by_name};
+use growthrs::planner::k8s::pods_from_manifests;
+use growthrs::planner::{CatalogEntry, Demand, PlanInput, plan};
+use growthrs::providers::fake::FakeProvider;
+use growthrs::providers::kwok::{kwok_offerings, parse_catalog};
+use growthrs::providers::provider::ProviderRegistry;
+use growthrs::resources::node_pool::ServerTypeConfig;
+use growthrs::simulation::trace::read_trace;
+use growthrs::simulation::{SimulationConfig, replay};
 use kube::Client;
 
-async fn start_controller() -> Result<(), ControllerError> {
-    let client = Client::try_default().await?;
-    let controller_context = Arc::new(ControllerContext::new(client)?);
+/// Kubernetes node autoscaler.
+///
+/// Settings not given as flags are read from the `GROWTH_*` environment
+/// variables, then from the config file.
+#[derive(Parser)]
+#[command(name = "growthrs", version)]
+struct Cli {
+    /// YAML config file; the environment and flags override its settings
+    #[arg(long, global = true, env = "GROWTH_CONFIG")]
+    config: Option<PathBuf>,
+    /// Provider(s) to use, comma-separated: kwok, fake, hetzner or chaos
+    /// (overrides GROWTH_PROVIDER)
+    #[arg(long, global = true)]
+    provider: Option<String>,
+    /// Namespace the controller runs in (overrides GROWTH_NAMESPACE)
+    #[arg(long, global = true)]
+    namespace: Option<String>,
+    /// Log filter, e.g. `growthrs=debug`
+    #[arg(long, global = true, env = "RUST_LOG", default_value = "growthrs=info")]
+    log_level: String,
+    #[command(flatten)]
+    provider_options: ProviderOptions,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Options of individual providers, each overriding the setting it names.
+#[derive(clap::Args)]
+#[command(next_help_heading = "Provider options")]
+struct ProviderOptions {
+    /// Hetzner Cloud API token
+    #[arg(long, global = true, env = "HCLOUD_TOKEN", hide_env_values = true)]
+    hcloud_token: Option<String>,
+    /// KWOK offering catalogue: a YAML file, or
+    /// `configmap:<namespace>/<name>[/<key>]` (GROWTH_KWOK_CATALOG)
+    #[arg(long, global = true)]
+    kwok_catalog: Option<String>,
+    /// Seconds before a KWOK node joins (GROWTH_KWOK_JOIN_DELAY)
+    #[arg(long, global = true)]
+    kwok_join_delay: Option<u64>,
+    /// Percentage of KWOK nodes that never join
+    /// (GROWTH_KWOK_JOIN_FAILURE_PERCENT)
+    #[arg(long, global = true)]
+    kwok_join_failure_percent: Option<f64>,
+    /// Seed of the chaos provider's failures (GROWTH_CHAOS_SEED)
+    #[arg(long, global = true)]
+    chaos_seed: Option<u64>,
+    /// Probability a chaos create reports no capacity
+    /// (GROWTH_CHAOS_UNAVAILABLE_RATE)
+    #[arg(long, global = true)]
+    chaos_unavailable_rate: Option<f64>,
+    /// Probability a chaos node joins late (GROWTH_CHAOS_DELAYED_JOIN_RATE)
+    #[arg(long, global = true)]
+    chaos_delayed_join_rate: Option<f64>,
+    /// Probability a chaos node disappears (GROWTH_CHAOS_DISAPPEAR_RATE)
+    #[arg(long, global = true)]
+    chaos_disappear_rate: Option<f64>,
+}
+
+impl ProviderOptions {
+    fn overlay(&self, settings: &mut HashMap<String, String>) {
+        let options = [
+            ("HCLOUD_TOKEN", self.hcloud_token.clone()),
+            ("GROWTH_KWOK_CATALOG", self.kwok_catalog.clone()),
+            (
+                "GROWTH_KWOK_JOIN_DELAY",
+                self.kwok_join_delay.map(|v| v.to_string()),
+            ),
+            (
+                "GROWTH_KWOK_JOIN_FAILURE_PERCENT",
+                self.kwok_join_failure_percent.map(|v| v.to_string()),
+            ),
+            ("GROWTH_CHAOS_SEED", self.chaos_seed.map(|v| v.to_string())),
+            (
+                "GROWTH_CHAOS_UNAVAILABLE_RATE",
+                self.chaos_unavailable_rate.map(|v| v.to_string()),
+            ),
+            (
+                "GROWTH_CHAOS_DELAYED_JOIN_RATE",
+                self.chaos_delayed_join_rate.map(|v| v.to_string()),
+            ),
+            (
+                "GROWTH_CHAOS_DISAPPEAR_RATE",
+                self.chaos_disappear_rate.map(|v| v.to_string()),
+            ),
+        ];
+        for (key, value) in options {
+            if let Some(value) = value {
+                settings.insert(key.into(), value);
+            }
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the controller (the default)
+    Run,
+    /// Print the CustomResourceDefinitions as YAML, or apply them to the
+    /// cluster
+    Crd(CrdArgs),
+    /// List the provider's offerings and their prices
+    Offerings,
+    /// Plan nodes offline: replay a recorded pending-pod trace against the
+    /// solver and a fake provider, printing a CSV timeline, or plan once for
+    /// a set of pod manifests, printing the nodes and their hourly cost
+    Simulate(SimulateArgs),
+}
+
+#[derive(clap::Args)]
+#[group(multiple = false)]
+struct CrdArgs {
+    /// Install or update the CRDs in the current cluster
+    #[arg(long)]
+    apply: bool,
+    /// Print the CRDs as YAML (the default)
+    #[arg(long)]
+    print: bool,
+}
+
+#[derive(clap::Args)]
+struct SimulateArgs {
+    /// Trace recorded with `record_trace`, to replay
+    #[arg(long, required_unless_present = "pods", conflicts_with = "pods")]
+    trace: Option<PathBuf>,
+    /// Pod, Deployment, ReplicaSet, StatefulSet and Job manifests to plan
+    /// nodes for
+    #[arg(long)]
+    pods: Option<PathBuf>,
+    /// Offering catalog, in the `GROWTH_KWOK_CATALOG` YAML format (default:
+    /// the KWOK catalogue)
+    #[arg(long)]
+    offerings: Option<PathBuf>,
+    /// Pool to replay the trace with, as `<name>=<type:max>,...`
+    /// (repeatable; default: "default" with every offered type, max 100).
+    /// This and the options below only apply to `--trace`
+    #[arg(long = "pool", value_parser = parse_pool)]
+    pools: Vec<PoolConfig>,
+    /// Virtual seconds between reconciles
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    tick: u64,
+    /// Create-to-Ready time, in seconds
+    #[arg(long, default_value_t = 60)]
+    join_delay: u64,
+    /// Idle time before a node is removed, in seconds
+    #[arg(long, default_value_t = 15)]
+    idle: u64,
+    /// Keep simulating this many seconds after the last event
+    #[arg(long, default_value_t = 600)]
+    drain: u64,
+    /// Placement algorithm: scored or first-fit
+    #[arg(long, default_value = "scored")]
+    solver: String,
+}
+
+impl Cli {
+    /// The config file and environment, with the flags that override them
+    /// laid over them.
+    fn settings(&self) -> Result<HashMap<String, String>, ConfigError> {
+        let mut settings = layered_settings(self.config.as_deref())?;
+        if let Some(provider) = &self.provider {
+            settings.insert("GROWTH_PROVIDER".into(), provider.clone());
+        }
+        if let Some(namespace) = &self.namespace {
+            settings.insert("GROWTH_NAMESPACE".into(), namespace.clone());
+        }
+        self.provider_options.overlay(&mut settings);
+        Ok(settings)
+    }
+
+    async fn context(&self) -> Result<ControllerContext, ControllerError> {
+        let client = Client::try_default().await?;
+        Ok(ControllerContext::from_settings(
+            client,
+            ProviderRegistry::default(),
+            &self.settings()?,
+        )?)
+    }
+}
+
+async fn start_controller(cli: &Cli) -> Result<(), ControllerError> {
+    let controller_context = Arc::new(cli.context().await?);
 
     // TODO: Gracefully drain in-flight NodeRequests, cancel pending provisions,
     // and clean up resources.
@@ -16,28 +213,225 @@ async fn start_controller() -> Result<(), ControllerError> {
         _ = tokio::signal::ctrl_c() => {
             tracing::info!("Received shutdown signal, stopping controllers");
         }
-        res = controller::run(controller_context) => res?,
-        res = healthcheck::healthcheck() => {
-            res.map_err(|e| ControllerError::Other(e))?;
+        res = controller::run(controller_context.clone()) => res?,
+        res = healthcheck::healthcheck(controller_context.clone()) => {
+            res.map_err(ControllerError::Other)?;
+        }
+        res = webhook::webhook(controller_context) => {
+            res.map_err(|e| ControllerError::Other(e.context("admission webhook failed")))?;
         }
     }
     Ok(())
 }
 
+async fn crd(args: CrdArgs) -> anyhow::Result<()> {
+    if !args.apply {
+        print!("{}", growthrs::resources::crds_yaml());
+        return Ok(());
+    }
+    let client = Client::try_default().await?;
+    for name in growthrs::resources::apply_crds(&client, None).await? {
+        println!("customresourcedefinition/{name} applied");
+    }
+    Ok(())
+}
+
+async fn list_offerings(cli: &Cli) -> anyhow::Result<()> {
+    let ctx = cli.context().await?;
+    let mut offerings = ctx.provider.offerings().await;
+    offerings.sort_by(|a, b| a.cost_per_hour.total_cmp(&b.cost_per_hour));
+    println!(
+        "{:<10} {:<16} {:<14} {:<12} {:>6} {:>10} {:>4} {:>9}",
+        "PROVIDER", "TYPE", "REGION", "ZONE", "CPU", "MEMORY", "GPU", "$/HOUR"
+    );
+    for o in offerings {
+        println!(
+            "{:<10} {:<16} {:<14} {:<12} {:>6} {:>8}Mi {:>4} {:>9.4}",
+            o.provider,
+            o.instance_type,
+            o.location.region,
+            o.location.zone.as_ref().map_or("-", |z| z.0.as_str()),
+            format!("{}m", o.resources.cpu_millis),
+            o.resources.memory_mib,
+            o.resources.gpu,
+            o.cost_per_hour,
+        );
+    }
+    Ok(())
+}
+
+/// Parse `name=type:max,type:max`.
+fn parse_pool(spec: &str) -> anyhow::Result<PoolConfig> {
+    let Some((name, types)) = spec.split_once('=') else {
+        bail!("pool must look like <name>=<type:max>,...: {spec}");
+    };
+    let server_types = types
+        .split(',')
+        .map(|t| {
+            let Some((ty, max)) = t.split_once(':') else {
+                bail!("server type must look like <type:max>: {t}");
+            };
+            Ok(ServerTypeConfig {
+                name: ty.to_string(),
+                max: max.parse().with_context(|| format!("bad max in {t}"))?,
+                min: 0,
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok(sim_pool(name, server_types))
+}
+
+fn sim_pool(name: &str, server_types: Vec<ServerTypeConfig>) -> PoolConfig {
+    PoolConfig {
+        name: name.to_string(),
+        uid: format!("sim-{name}"),
+        min_nodes: 0,
+        max_nodes: None,
+        headroom: None,
+        server_types,
+        labels: BTreeMap::new(),
+        taints: vec![],
+        locations: None,
+        evacuating_zones: vec![],
+        unmet_offerings: vec![],
+        allow_cost_spike: false,
+        solve_options: SolveOptions::default(),
+        instance_families: None,
+    }
+}
+
+fn default_pool(offerings: &[Offering]) -> PoolConfig {
+    let mut names: Vec<String> = offerings
+        .iter()
+        .map(|o| o.instance_type.0.clone())
+        .collect();
+    names.sort();
+    names.dedup();
+    let server_types = names
+        .into_iter()
+        .map(|name| ServerTypeConfig {
+            name,
+            max: 100,
+            min: 0,
+        })
+        .collect();
+    sim_pool("default", server_types)
+}
+
+async fn simulate(args: SimulateArgs) -> anyhow::Result<()> {
+    let offerings = match &args.offerings {
+        Some(path) => parse_catalog(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("reading {}", path.display()))?,
+        )
+        .with_context(|| format!("parsing {}", path.display()))?,
+        None => kwok_offerings(),
+    };
+    let Some(trace) = &args.trace else {
+        let pods = args
+            .pods
+            .as_deref()
+            .expect("clap requires --trace or --pods");
+        return plan_manifests(pods, &offerings);
+    };
+
+    let events = read_trace(BufReader::new(
+        File::open(trace).with_context(|| format!("opening {}", trace.display()))?,
+    ))?;
+    let mut pools = args.pools;
+    if pools.is_empty() {
+        pools.push(default_pool(&offerings));
+    }
+    let config = SimulationConfig {
+        pools,
+        tick: Duration::from_secs(args.tick),
+        join_delay: Duration::from_secs(args.join_delay),
+        idle_timeout: Duration::from_secs(args.idle),
+        drain: Duration::from_secs(args.drain),
+        solver: solver_by_name(&args.solver)
+            .with_context(|| format!("unknown --solver {}", args.solver))?,
+    };
+
+    let provider = FakeProvider::new().with_offerings(offerings);
+    let report = replay(&events, &config, &provider).await;
+
+    report.write_csv(std::io::stdout().lock())?;
+    eprintln!("events:          {}", events.len());
+    eprintln!("nodes created:   {}", report.nodes_created);
+    eprintln!("create failures: {}", report.create_failures);
+    eprintln!("skipped pods:    {}", report.skipped_pods);
+    eprintln!("peak pending:    {}", report.peak_pending);
+    eprintln!("unmet pod-secs:  {:.0}", report.unmet_pod_seconds);
+    eprintln!("total cost:      ${:.4}", report.total_cost);
+    Ok(())
+}
+
+/// Plan nodes for the pods `path`'s manifests would run, from `offerings`,
+/// and print them with their hourly cost.
+fn plan_manifests(path: &Path, offerings: &[Offering]) -> anyhow::Result<()> {
+    let yaml =
+        std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let pods = pods_from_manifests(&yaml).with_context(|| format!("parsing {}", path.display()))?;
+    let input = PlanInput {
+        demands: pods
+            .iter()
+            .map(Demand::try_from)
+            .collect::<Result<_, _>>()?,
+        capacities: vec![],
+        catalog: offerings
+            .iter()
+            .map(|o| CatalogEntry::from_offering(o, None))
+            .collect(),
+    };
+    let plan = plan(&input);
+
+    println!(
+        "{:<16} {:<14} {:<12} {:>9}  PODS",
+        "TYPE", "REGION", "ZONE", "$/HOUR"
+    );
+    for node in &plan.nodes {
+        println!(
+            "{:<16} {:<14} {:<12} {:>9.4}  {}",
+            node.instance_type,
+            node.region,
+            node.zone.as_deref().unwrap_or("-"),
+            node.cost_per_hour,
+            node.demands.join(","),
+        );
+    }
+    println!();
+    println!("pods:      {}", input.demands.len());
+    println!("nodes:     {}", plan.nodes.len());
+    println!("cost/hour: ${:.4}", plan.cost_per_hour());
+    if !plan.unplaced.is_empty() {
+        println!("unplaced:  {}", plan.unplaced.join(","));
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> ExitCode {
+    let mut cli = Cli::parse();
     tracing_subscriber::fmt()
         .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
+            tracing_subscriber::EnvFilter::try_new(&cli.log_level)
                 .unwrap_or_else(|_| "growthrs=info".parse().unwrap()),
         )
         .compact()
         .with_target(false)
         .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        // Stdout is left to command output and the `-` audit log.
+        .with_writer(std::io::stderr)
         .init();
 
-    if let Err(e) = start_controller().await {
-        tracing::error!("GrowthRS exiting: {e}");
+    let result = match cli.command.take().unwrap_or(Command::Run) {
+        Command::Run => start_controller(&cli).await.map_err(anyhow::Error::from),
+        Command::Crd(args) => crd(args).await,
+        Command::Offerings => list_offerings(&cli).await,
+        Command::Simulate(args) => simulate(args).await,
+    };
+    if let Err(e) = result {
+        tracing::error!("GrowthRS exiting: {e:#}");
         return ExitCode::FAILURE;
     }
     ExitCode::SUCCESS
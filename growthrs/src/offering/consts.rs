@@ -1,5 +1,7 @@
 /// Label key used to match pods to NodePools via nodeSelector.
 pub const POOL_LABEL: &str = "growth.vettrdev.com/pool";
+/// NodePool label naming the controller shard that manages the pool.
+pub const SHARD_LABEL: &str = "growth.vettrdev.com/shard";
 pub const NODE_REQUEST_LABEL: &str = "growth.vettrdev.com/node-request";
 pub const INSTANCE_TYPE_LABEL: &str = "growth.vettrdev.com/instance-type";
 /// Label identifying nodes managed by the growth operator.
@@ -9,6 +11,14 @@ pub const MANAGED_BY_VALUE: &str = "growth";
 pub const MANAGED_BY_SELECTOR: &str = "growth.vettrdev.com/managed-by=growth";
 /// NVIDIA GPU Feature Discovery label for the GPU product/model.
 pub const GPU_PRODUCT_LABEL: &str = "nvidia.com/gpu.product";
+/// Prefix of the per-profile resources the NVIDIA device plugin exposes for
+/// MIG slices under the `mixed` strategy, e.g. `nvidia.com/mig-1g.5gb`.
+pub const MIG_RESOURCE_PREFIX: &str = "nvidia.com/mig-";
+/// NodePool annotation (`"true"`) exempting the pool from the cost anomaly guardrail.
+pub const ALLOW_COST_SPIKE_ANNOTATION: &str = "growth.vettrdev.com/allow-cost-spike";
+/// Pod annotation: the most, in $/h, a new node opened for the pod may cost.
+/// Pricier nodes leave it pending instead.
+pub const UNMET_PENALTY_ANNOTATION: &str = "growth.vettrdev.com/unmet-penalty";
 /// Annotation set on nodes that are candidates for removal.
 pub const REMOVAL_CANDIDATE_ANNOTATION: &str = "growth.vettrdev.com/removal-candidate";
 /// Taint key applied to nodes being scaled down (NoSchedule effect).
@@ -18,5 +28,54 @@ pub const SCALE_DOWN_TAINT_KEY: &str = "growth.vettrdev.com/scale-down";
 pub const STARTUP_TAINT_KEY: &str = "growth.vettrdev.com/unregistered";
 /// Annotation recording when a node is scheduled for deletion (RFC 3339 timestamp).
 pub const DELETE_AT_ANNOTATION: &str = "growth.vettrdev.com/delete-at";
+/// Well-known node label for the node's region.
+pub const REGION_LABEL: &str = "topology.kubernetes.io/region";
+/// Well-known node label for the node's zone.
+pub const ZONE_LABEL: &str = "topology.kubernetes.io/zone";
+/// Well-known node label for the node's CPU architecture.
+pub const ARCH_LABEL: &str = "kubernetes.io/arch";
+/// Well-known node label for the node's operating system.
+pub const OS_LABEL: &str = "kubernetes.io/os";
+/// Annotation set on nodes cordoned by an evacuation, so only those are uncordoned when it ends.
+pub const EVACUATING_ANNOTATION: &str = "growth.vettrdev.com/evacuating";
+/// Label on NodeRequests created to replace evacuated nodes; the value is
+/// `{pool}.{generation}` (see `evacuation::decision::evacuation_id`).
+pub const EVACUATION_LABEL: &str = "growth.vettrdev.com/evacuation";
+/// Annotation set on nodes cordoned for consolidation; the value is the plan,
+/// `replace` or `delete`.
+pub const CONSOLIDATING_ANNOTATION: &str = "growth.vettrdev.com/consolidating";
+/// Label on NodeRequests created to replace a consolidated node; the value is the node name.
+pub const CONSOLIDATION_LABEL: &str = "growth.vettrdev.com/consolidation";
+/// Label on NodeRequests created for a pool's headroom; the value is the pool name.
+pub const HEADROOM_LABEL: &str = "growth.vettrdev.com/headroom";
+/// Pod annotation (`"true"`) for pods that are pending on purpose (suspended
+/// Jobs, placeholders); they never trigger provisioning.
+pub const IGNORE_ANNOTATION: &str = "growth.vettrdev.com/ignore";
+/// Pod and node annotation (`"true"`): consolidation, evacuation and idle
+/// removal never evict the pod, or disrupt the node.
+pub const DO_NOT_DISRUPT_ANNOTATION: &str = "growth.vettrdev.com/do-not-disrupt";
+/// Cluster-autoscaler's pod annotation; `"false"` keeps consolidation from
+/// evicting the pod, like [`DO_NOT_DISRUPT_ANNOTATION`].
+pub const SAFE_TO_EVICT_ANNOTATION: &str = "cluster-autoscaler.kubernetes.io/safe-to-evict";
+/// Cluster-autoscaler's node annotation; `"true"` keeps consolidation off the
+/// node, like [`DO_NOT_DISRUPT_ANNOTATION`].
+pub const SCALE_DOWN_DISABLED_ANNOTATION: &str =
+    "cluster-autoscaler.kubernetes.io/scale-down-disabled";
+/// Taint AWS Node Termination Handler puts on a spot node given a rebalance
+/// recommendation.
+pub const REBALANCE_TAINT_KEY: &str = "aws-node-termination-handler/rebalance-recommendation";
+/// Node condition type for a rebalance recommendation, as node-problem-detector
+/// style monitors report it.
+pub const REBALANCE_CONDITION: &str = "RebalanceRecommendation";
+/// Annotation on NodeRequests whose claimed pods all went elsewhere; the node
+/// is removed as soon as it joins, if still idle.
+pub const UNCLAIMED_ANNOTATION: &str = "growth.vettrdev.com/unclaimed";
+/// Pod annotation naming the NodeRequest whose node the pod is waiting for.
+/// Set when the request is created for, or nominated, the pod; removed by
+/// claim GC once the request no longer claims it.
+pub const NOMINATED_ANNOTATION: &str = "growth.vettrdev.com/nominated-node-request";
 /// Finalizer added to NodeRemovalRequests to guarantee provider cleanup before deletion.
 pub const NRR_FINALIZER: &str = "growth.vettrdev.com/provider-cleanup";
+/// Finalizer added to NodeRequests so deleting one (or its NodePool) drains
+/// and deletes the node it created.
+pub const NR_FINALIZER: &str = "growth.vettrdev.com/node-cleanup";
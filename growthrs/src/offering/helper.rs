@@ -2,61 +2,176 @@ use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 
 use crate::offering::QuantityParseError;
 
-/// Parse a Kubernetes CPU quantity into whole vCPU count (rounds up).
-/// Handles: bare integers ("4"), millicores ("500m").
-pub(crate) fn parse_cpu(q: &Quantity) -> Result<u32, QuantityParseError> {
-    let s = &q.0;
-    let map_err = |e| QuantityParseError {
-        raw: s.clone(),
-        source: e,
-    };
-    if let Some(millis) = s.strip_suffix('m') {
-        let m: u32 = millis.parse().map_err(map_err)?;
-        Ok(m.div_ceil(1000))
-    } else {
-        Ok(s.parse().map_err(map_err)?)
+const TOO_LARGE: &str = "quantity too large";
+
+/// The error for a quantity, or a sum of them, too large to represent.
+pub(crate) fn too_large(q: &Quantity) -> QuantityParseError {
+    QuantityParseError {
+        raw: q.0.clone(),
+        reason: TOO_LARGE,
+    }
+}
+
+/// A quantity's exact value, `digits × 10^exp10 × 2^exp2`.
+struct Exact {
+    digits: u128,
+    exp10: i32,
+    exp2: i32,
+}
+
+impl Exact {
+    /// The value in units of `10^unit10 × 2^unit2`, rounded up, and whether
+    /// no rounding was needed. Values beyond `u64::MAX` units are an error.
+    fn in_units(&self, unit10: i32, unit2: i32) -> Result<(u64, bool), &'static str> {
+        if self.digits == 0 {
+            return Ok((0, true));
+        }
+        let (e10, e2) = (self.exp10.saturating_sub(unit10), self.exp2 - unit2);
+        let scale = |e: i32, base: u128| base.checked_pow(e.unsigned_abs());
+        let mut num = Some(self.digits);
+        let mut den = Some(1u128);
+        for (e, base) in [(e10, 10), (e2, 2)] {
+            let side = if e >= 0 { &mut num } else { &mut den };
+            *side = side.and_then(|n| n.checked_mul(scale(e, base)?));
+        }
+        match (num, den) {
+            (None, _) => Err(TOO_LARGE),
+            // Far smaller than one unit.
+            (Some(_), None) => Ok((1, false)),
+            (Some(num), Some(den)) => Ok((
+                u64::try_from(num.div_ceil(den)).map_err(|_| TOO_LARGE)?,
+                num % den == 0,
+            )),
+        }
+    }
+}
+
+/// Parse a Kubernetes quantity: a decimal number (`4`, `1.5`, `.5`) with a
+/// binary suffix (`Ki` to `Ei`), a decimal one (`n`, `u`, `m`, `k`, `M` to
+/// `E`) or an exponent (`1e3`, `5E-1`). `E` alone is exa.
+fn parse_exact(s: &str) -> Result<Exact, &'static str> {
+    let s = s.strip_prefix('+').unwrap_or(s);
+    if s.starts_with('-') {
+        return Err("negative quantity");
+    }
+    let number_len = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(number_len);
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err("not a number");
     }
+    let mut digits: u128 = 0;
+    for c in whole.chars().chain(fraction.chars()) {
+        if c == '.' {
+            return Err("not a number");
+        }
+        digits = digits
+            .checked_mul(10)
+            .and_then(|d| d.checked_add(u128::from(c as u8 - b'0')))
+            .ok_or("too many digits")?;
+    }
+    let mut exp10 = -(fraction.len() as i32);
+    let mut exp2 = 0;
+    match suffix {
+        "Ki" => exp2 = 10,
+        "Mi" => exp2 = 20,
+        "Gi" => exp2 = 30,
+        "Ti" => exp2 = 40,
+        "Pi" => exp2 = 50,
+        "Ei" => exp2 = 60,
+        "n" => exp10 -= 9,
+        "u" => exp10 -= 6,
+        "m" => exp10 -= 3,
+        "" => {}
+        "k" => exp10 += 3,
+        "M" => exp10 += 6,
+        "G" => exp10 += 9,
+        "T" => exp10 += 12,
+        "P" => exp10 += 15,
+        "E" => exp10 += 18,
+        _ => {
+            let exponent = suffix
+                .strip_prefix(['e', 'E'])
+                .and_then(|e| e.parse::<i32>().ok())
+                .ok_or("unknown suffix")?;
+            exp10 = exp10.saturating_add(exponent);
+        }
+    }
+    Ok(Exact {
+        digits,
+        exp10,
+        exp2,
+    })
+}
+
+/// Parse `q` and express it in units of `10^unit10 × 2^unit2`.
+fn parse_in_units(
+    q: &Quantity,
+    unit10: i32,
+    unit2: i32,
+) -> Result<(u64, bool), QuantityParseError> {
+    parse_exact(&q.0)
+        .and_then(|exact| exact.in_units(unit10, unit2))
+        .map_err(|reason| QuantityParseError {
+            raw: q.0.clone(),
+            reason,
+        })
+}
+
+/// Parse a Kubernetes CPU quantity into millicores (rounds up), e.g. `4`,
+/// `500m`, `0.5` or `100u`.
+pub(crate) fn parse_cpu_millis(q: &Quantity) -> Result<u64, QuantityParseError> {
+    Ok(parse_in_units(q, -3, 0)?.0)
 }
 
-/// Parse a Kubernetes memory quantity into MiB (rounds up).
-/// Handles: Gi, Mi, Ki, and bare bytes.
-pub(crate) fn parse_memory_mib(q: &Quantity) -> Result<u32, QuantityParseError> {
-    let s = &q.0;
-    let map_err = |e| QuantityParseError {
-        raw: s.clone(),
-        source: e,
+/// Parse a whole-number quantity, e.g. `nvidia.com/gpu`.
+pub(crate) fn parse_count(q: &Quantity) -> Result<u32, QuantityParseError> {
+    let err = |reason| QuantityParseError {
+        raw: q.0.clone(),
+        reason,
     };
-    if let Some(v) = s.strip_suffix("Gi") {
-        let n: u32 = v.parse().map_err(map_err)?;
-        Ok(n * 1024)
-    } else if let Some(v) = s.strip_suffix("Mi") {
-        Ok(v.parse().map_err(map_err)?)
-    } else if let Some(v) = s.strip_suffix("Ki") {
-        let n: u32 = v.parse().map_err(map_err)?;
-        Ok(n.div_ceil(1024))
-    } else {
-        let n: u64 = s.parse().map_err(map_err)?;
-        Ok((n.div_ceil(1024 * 1024)) as u32)
+    match parse_in_units(q, 0, 0)? {
+        (n, true) => u32::try_from(n).map_err(|_| too_large(q)),
+        (_, false) => Err(err("not a whole number")),
     }
 }
 
+/// Parse a Kubernetes memory quantity into MiB (rounds up), e.g. `512Mi`,
+/// `1.5Gi`, `500M` or bare bytes.
+pub(crate) fn parse_memory_mib(q: &Quantity) -> Result<u64, QuantityParseError> {
+    Ok(parse_in_units(q, 0, 20)?.0)
+}
+
 /// Parse a Kubernetes ephemeral-storage quantity into GiB (rounds up).
-pub(crate) fn parse_storage_gib(q: &Quantity) -> Result<u32, QuantityParseError> {
-    let s = &q.0;
-    let map_err = |e| QuantityParseError {
-        raw: s.clone(),
-        source: e,
-    };
-    if let Some(v) = s.strip_suffix("Gi") {
-        Ok(v.parse().map_err(map_err)?)
-    } else if let Some(v) = s.strip_suffix("Mi") {
-        let n: u32 = v.parse().map_err(map_err)?;
-        Ok(n.div_ceil(1024))
-    } else if let Some(v) = s.strip_suffix("Ki") {
-        let n: u64 = v.parse().map_err(map_err)?;
-        Ok((n.div_ceil(1024 * 1024)) as u32)
-    } else {
-        let n: u64 = s.parse().map_err(map_err)?;
-        Ok((n.div_ceil(1024 * 1024 * 1024)) as u32)
+pub(crate) fn parse_storage_gib(q: &Quantity) -> Result<u64, QuantityParseError> {
+    Ok(parse_in_units(q, 0, 30)?.0)
+}
+
+/// Match `text` against a shell-style pattern where `*` stands for any run
+/// of characters (including none) and `?` for exactly one.
+pub(crate) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen, and the text position it was tried at.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((sp, st)) = star {
+            // Let the last `*` swallow one more character and retry.
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
     }
+    pattern[p..].iter().all(|&c| c == '*')
 }
@@ -1,7 +1,8 @@
 use std::collections::BTreeMap;
-use std::num::ParseIntError;
 
-use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::api::core::v1::{
+    NodeSelectorRequirement, NodeSelectorTerm, Pod, Taint, Toleration,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -10,27 +11,36 @@ use tracing::warn;
 pub(crate) mod helper;
 pub(crate) mod consts;
 mod node;
+pub mod stats;
 
 pub use consts::*;
 pub use node::NodeReference;
 
-use helper::{parse_cpu, parse_memory_mib, parse_storage_gib};
+use helper::{
+    parse_count, parse_cpu_millis, parse_memory_mib, parse_storage_gib, too_large, wildcard_match,
+};
 
 
 #[derive(Debug, Error)]
-#[error("failed to parse quantity \"{raw}\": {source}")]
+#[error("failed to parse quantity \"{raw}\": {reason}")]
 pub struct QuantityParseError {
     raw: String,
-    source: ParseIntError,
+    reason: &'static str,
 }
 
 /// (Instance) Offering
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Offering {
     pub instance_type: InstanceType,
+    /// Name of the provider serving this offering, e.g. "hetzner".
+    pub provider: String,
     pub resources: Resources,
     /// Hourly cost in USD.
     pub cost_per_hour: f64,
+    /// How likely a node of this offering is to be reclaimed by the
+    /// provider, from 0 (on-demand) to 1, for spot/preemptible capacity.
+    #[serde(default)]
+    pub interruption_risk: f64,
     /// Where this offering physically lives (region + optional zone).
     pub location: Location,
 }
@@ -38,7 +48,7 @@ pub struct Offering {
 /// Where the instance physically lives.
 /// Both fields are provider-specific strings, but they're separate types
 /// so you can't accidentally swap them.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Location {
     pub region: Region,
     /// Zone within the region. Not all providers/offerings have zones. (Hetzner doesn't, which is why we're not bothering with it for now)
@@ -54,14 +64,42 @@ impl std::fmt::Display for Region {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub struct Zone(pub String);
 
+impl std::fmt::Display for Zone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 /// The provider's native identifier for this instance type.
 /// Opaque to the caller — only the provider adapter interprets it.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub struct InstanceType(pub String);
 
+impl InstanceType {
+    /// The family this type belongs to, by the usual naming conventions:
+    /// the part before the first `-` or `.` (`gpu-a100-1` → `gpu`,
+    /// `m5.large` → `m5`), or else the leading letters (`ccx13` → `ccx`).
+    pub fn family(&self) -> &str {
+        let name = self.0.as_str();
+        if let Some((family, _)) = name.split_once(['-', '.']) {
+            return family;
+        }
+        let end = name
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(name.len());
+        &name[..end]
+    }
+
+    /// Whether a pattern like `ccx*` or `gpu-*` (`*` and `?` wildcards)
+    /// matches this type's name or its [`family`](Self::family).
+    pub fn matches(&self, pattern: &str) -> bool {
+        wildcard_match(pattern, &self.0) || wildcard_match(pattern, self.family())
+    }
+}
+
 impl std::fmt::Display for InstanceType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(&self.0)
@@ -102,6 +140,76 @@ pub struct PodResources {
     pub pod_labels: BTreeMap<String, String>,
     /// Parsed affinity/anti-affinity constraints from the pod spec.
     pub affinity_constraints: Vec<AffinityConstraint>,
+    /// Zones the pod may run in, from a zone nodeSelector or required node
+    /// affinity, narrowed by its bound volumes. `None` allows any zone.
+    pub zones: Option<Vec<String>>,
+    /// Tolerations from the pod spec; the pod only fits nodes whose taints
+    /// they cover.
+    pub tolerations: Vec<Toleration>,
+    /// The pod's nodeSelector, less the pool, zone, GPU model and
+    /// architecture keys already covered by `pool`, `zones`,
+    /// `Resources::gpu_model` and `Resources::arch`.
+    pub node_selector: BTreeMap<String, String>,
+    /// Required node affinity terms, ORed. Empty means no requirement.
+    pub node_affinity: Vec<NodeSelectorTerm>,
+    /// The pod's priority, as admission resolved it from its
+    /// `priorityClassName`; 0 if unset. When not every pod can be placed,
+    /// lower priorities are the ones left unmet.
+    pub priority: i32,
+    /// What leaving the pod unmet is worth avoiding, in $/h, from its
+    /// [`UNMET_PENALTY_ANNOTATION`]: no new node costing more is opened for
+    /// it, and among pods of equal priority higher penalties are placed
+    /// first. `None` means any cost is worth it.
+    pub unmet_penalty: Option<f64>,
+}
+
+/// Zones allowed by a set of ORed node selector terms: the union of each
+/// term's `topology.kubernetes.io/zone In` values. `None` if any term leaves
+/// the zone open.
+pub fn zones_from_selector_terms(terms: &[NodeSelectorTerm]) -> Option<Vec<String>> {
+    let mut zones: Vec<String> = Vec::new();
+    for term in terms {
+        let term_zones = term
+            .match_expressions
+            .iter()
+            .flatten()
+            .find(|e| e.key == ZONE_LABEL && e.operator == "In")
+            .and_then(|e| e.values.clone())?;
+        for zone in term_zones {
+            if !zones.contains(&zone) {
+                zones.push(zone);
+            }
+        }
+    }
+    (!terms.is_empty()).then_some(zones)
+}
+
+/// Zones a pod is pinned to by its nodeSelector and required node affinity.
+pub fn pod_zones(pod: &Pod) -> Option<Vec<String>> {
+    let spec = pod.spec.as_ref()?;
+    let selected = spec
+        .node_selector
+        .as_ref()
+        .and_then(|sel| sel.get(ZONE_LABEL))
+        .map(|z| vec![z.clone()]);
+    let affinity = spec
+        .affinity
+        .as_ref()
+        .and_then(|a| a.node_affinity.as_ref())
+        .and_then(|na| {
+            na.required_during_scheduling_ignored_during_execution
+                .as_ref()
+        })
+        .and_then(|sel| zones_from_selector_terms(&sel.node_selector_terms));
+    match (selected, affinity) {
+        (Some(selected), Some(affinity)) => Some(
+            selected
+                .into_iter()
+                .filter(|z| affinity.contains(z))
+                .collect(),
+        ),
+        (selected, affinity) => selected.or(affinity),
+    }
 }
 
 /// Read the pool selector from a pod's nodeSelector.
@@ -114,24 +222,117 @@ pub fn pod_pool_selector(pod: &Pod) -> Option<&str> {
 }
 
 /// Resources available on an instance type.
-/// This is what lets you write `offerings.iter().filter(|o| o.resources.cpu >= 4)`
+/// This is what lets you write `offerings.iter().filter(|o| o.resources.vcpus() >= 4)`
 /// instead of looking up "e2-medium" in a spreadsheet.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase", from = "ResourcesRepr")]
 pub struct Resources {
-    /// vCPU count.
-    pub cpu: u32,
+    /// CPU in millicores, so ten 100m pods add up to one core rather than
+    /// ten.
+    pub cpu_millis: u64,
     /// Memory in MiB. MiB not GiB — avoids the 0.5GiB rounding problem
     /// (e.g. t3.nano = 512 MiB, not 0.5 GiB).
-    pub memory_mib: u32,
+    pub memory_mib: u64,
     /// Included ephemeral storage in GiB. None if not applicable (e.g. Hetzner
     /// bundles it into server_type but it's not separately configurable).
-    pub ephemeral_storage_gib: Option<u32>,
+    pub ephemeral_storage_gib: Option<u64>,
     /// GPU count. 0 for non-GPU instances.
     pub gpu: u32,
     /// GPU model identifier when gpu > 0.
     #[schemars(with = "Option<String>")]
     pub gpu_model: Option<GpuModel>,
+    /// CPU architecture. On a demand, `None` runs anywhere; on an offering,
+    /// it is unknown and only suits demands without an architecture.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<Arch>,
+    /// MIG slices by profile (`1g.5gb` → 7 for an A100 cut into sevenths),
+    /// as exposed under [`MIG_RESOURCE_PREFIX`]. Counted separately from
+    /// whole GPUs, so pods asking for a slice can share a sliced A100.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mig: BTreeMap<String, u32>,
+    /// Any other extended resource (FPGAs, smarter-devices, vendor
+    /// accelerators such as `xilinx.com/u30`), by resource name. Matched
+    /// like the built-in resources: a node must have at least what a pod
+    /// requests of each.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extended: BTreeMap<String, u64>,
+}
+
+/// How [`Resources`] is read back. NodeRequests written before millicore
+/// precision carry a whole-vCPU `cpu` instead of `cpuMillis`.
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct ResourcesRepr {
+    /// CPU in millicores.
+    #[serde(default)]
+    cpu_millis: Option<u64>,
+    /// vCPU count; superseded by `cpuMillis`.
+    #[serde(default)]
+    cpu: Option<u32>,
+    /// Memory in MiB.
+    memory_mib: u64,
+    /// Included ephemeral storage in GiB, if known.
+    #[serde(default)]
+    ephemeral_storage_gib: Option<u64>,
+    /// GPU count.
+    gpu: u32,
+    /// GPU model identifier when gpu > 0.
+    #[serde(default)]
+    #[schemars(with = "Option<String>")]
+    gpu_model: Option<GpuModel>,
+    /// CPU architecture.
+    #[serde(default)]
+    arch: Option<Arch>,
+    /// MIG slices by profile.
+    #[serde(default)]
+    mig: BTreeMap<String, u32>,
+    /// Other extended resources by name.
+    #[serde(default)]
+    extended: BTreeMap<String, u64>,
+}
+
+impl From<ResourcesRepr> for Resources {
+    fn from(repr: ResourcesRepr) -> Self {
+        Resources {
+            cpu_millis: repr
+                .cpu_millis
+                .unwrap_or_else(|| u64::from(repr.cpu.unwrap_or(0)) * 1000),
+            memory_mib: repr.memory_mib,
+            ephemeral_storage_gib: repr.ephemeral_storage_gib,
+            gpu: repr.gpu,
+            gpu_model: repr.gpu_model,
+            arch: repr.arch,
+            mig: repr.mig,
+            extended: repr.extended,
+        }
+    }
+}
+
+/// CPU architecture, as in the `kubernetes.io/arch` node label.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    Amd64,
+    Arm64,
+}
+
+impl Arch {
+    /// Parse a `kubernetes.io/arch` label value; `None` for architectures
+    /// no provider offers.
+    pub fn from_label(value: &str) -> Option<Arch> {
+        match value {
+            "amd64" => Some(Arch::Amd64),
+            "arm64" => Some(Arch::Arm64),
+            _ => None,
+        }
+    }
+
+    pub fn as_label(self) -> &'static str {
+        match self {
+            Arch::Amd64 => "amd64",
+            Arch::Arm64 => "arm64",
+        }
+    }
 }
 
 impl Resources {
@@ -143,15 +344,29 @@ impl Resources {
             .as_ref()
             .is_none_or(|needed| self.gpu_model.as_ref() == Some(needed));
 
+        let arch_ok = need.arch.is_none_or(|needed| self.arch == Some(needed));
+
         let storage_ok = need
             .ephemeral_storage_gib
             .is_none_or(|req| self.ephemeral_storage_gib.is_some_and(|avail| avail >= req));
 
-        self.cpu >= need.cpu
+        let mig_ok = need
+            .mig
+            .iter()
+            .all(|(profile, &n)| self.mig.get(profile).copied().unwrap_or(0) >= n);
+        let extended_ok = need
+            .extended
+            .iter()
+            .all(|(name, &n)| self.extended.get(name).copied().unwrap_or(0) >= n);
+
+        self.cpu_millis >= need.cpu_millis
             && self.memory_mib >= need.memory_mib
             && self.gpu >= need.gpu
             && gpu_model_ok
+            && arch_ok
             && storage_ok
+            && mig_ok
+            && extended_ok
     }
 
     /// Subtract consumed resources from available capacity.
@@ -159,7 +374,7 @@ impl Resources {
     /// Caller must check `satisfies()` first — this will underflow if
     /// consumed exceeds available.
     pub fn subtract(&mut self, consumed: &Resources) {
-        self.cpu -= consumed.cpu;
+        self.cpu_millis -= consumed.cpu_millis;
         self.memory_mib -= consumed.memory_mib;
         if let (Some(avail), Some(used)) = (
             &mut self.ephemeral_storage_gib,
@@ -168,6 +383,45 @@ impl Resources {
             *avail -= used;
         }
         self.gpu -= consumed.gpu;
+        for (profile, n) in &consumed.mig {
+            if let Some(avail) = self.mig.get_mut(profile) {
+                *avail -= n;
+            }
+        }
+        for (name, n) in &consumed.extended {
+            if let Some(avail) = self.extended.get_mut(name) {
+                *avail -= n;
+            }
+        }
+    }
+
+    /// CPU in whole vCPUs, rounded up, as providers count it for quota.
+    pub fn vcpus(&self) -> u32 {
+        u32::try_from(self.cpu_millis.div_ceil(1000)).unwrap_or(u32::MAX)
+    }
+
+    /// Like [`Resources::subtract`], but stops at zero instead of
+    /// underflowing.
+    pub fn saturating_subtract(&mut self, consumed: &Resources) {
+        self.cpu_millis = self.cpu_millis.saturating_sub(consumed.cpu_millis);
+        self.memory_mib = self.memory_mib.saturating_sub(consumed.memory_mib);
+        if let (Some(avail), Some(used)) = (
+            &mut self.ephemeral_storage_gib,
+            consumed.ephemeral_storage_gib,
+        ) {
+            *avail = avail.saturating_sub(used);
+        }
+        self.gpu = self.gpu.saturating_sub(consumed.gpu);
+        for (profile, n) in &consumed.mig {
+            if let Some(avail) = self.mig.get_mut(profile) {
+                *avail = avail.saturating_sub(*n);
+            }
+        }
+        for (name, n) in &consumed.extended {
+            if let Some(avail) = self.extended.get_mut(name) {
+                *avail = avail.saturating_sub(*n);
+            }
+        }
     }
 }
 
@@ -175,6 +429,16 @@ impl Offering {
     pub fn satisfies(&self, need: &Resources) -> bool {
         self.resources.satisfies(need)
     }
+
+    /// See [`node_labels`].
+    pub fn labels(&self) -> BTreeMap<String, String> {
+        node_labels(
+            &self.instance_type.0,
+            &self.location.region.0,
+            self.location.zone.as_ref().map(|z| z.0.as_str()),
+            &self.resources,
+        )
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -242,12 +506,55 @@ pub struct AffinityConstraint {
 
 
 
+/// The pod's [`UNMET_PENALTY_ANNOTATION`], ignoring (with a warning) values
+/// that aren't a non-negative number.
+fn pod_unmet_penalty(pod: &Pod) -> Option<f64> {
+    let value = pod
+        .metadata
+        .annotations
+        .as_ref()?
+        .get(UNMET_PENALTY_ANNOTATION)?;
+    match value.parse::<f64>() {
+        Ok(penalty) if penalty >= 0.0 => Some(penalty),
+        _ => {
+            warn!(
+                pod = pod.metadata.name.as_deref().unwrap_or_default(),
+                value = %value,
+                "ignoring invalid {UNMET_PENALTY_ANNOTATION} annotation"
+            );
+            None
+        }
+    }
+}
+
 impl PodResources {
     /// Build a `PodResources` from a Kubernetes Pod, extracting name/namespace
     /// and summing resource requests across all containers.
     pub fn from_pod(pod: &Pod) -> Result<PodResources, QuantityParseError> {
         let pod_labels = pod.metadata.labels.clone().unwrap_or_default();
         let affinity_constraints = parse_affinity_constraints(pod);
+        let mut node_selector = pod
+            .spec
+            .as_ref()
+            .and_then(|s| s.node_selector.clone())
+            .unwrap_or_default();
+        node_selector.retain(|k, v| {
+            let covered = [POOL_LABEL, ZONE_LABEL, GPU_PRODUCT_LABEL].contains(&k.as_str())
+                // Known architectures are matched through `Resources::arch`.
+                || (k == ARCH_LABEL && Arch::from_label(v).is_some());
+            !covered
+        });
+        let node_affinity = pod
+            .spec
+            .as_ref()
+            .and_then(|s| s.affinity.as_ref())
+            .and_then(|a| a.node_affinity.as_ref())
+            .and_then(|na| {
+                na.required_during_scheduling_ignored_during_execution
+                    .as_ref()
+            })
+            .map(|sel| sel.node_selector_terms.clone())
+            .unwrap_or_default();
         Ok(PodResources {
             id: PodId {
                 namespace: pod.metadata.namespace.clone().unwrap_or_default(),
@@ -258,8 +565,140 @@ impl PodResources {
             pool: pod_pool_selector(pod).map(|s| s.to_string()),
             pod_labels,
             affinity_constraints,
+            zones: pod_zones(pod),
+            tolerations: pod
+                .spec
+                .as_ref()
+                .and_then(|s| s.tolerations.clone())
+                .unwrap_or_default(),
+            node_selector,
+            node_affinity,
+            priority: pod.spec.as_ref().and_then(|s| s.priority).unwrap_or(0),
+            unmet_penalty: pod_unmet_penalty(pod),
         })
     }
+
+    /// Whether a node carrying `labels` satisfies the pod's nodeSelector and
+    /// required node affinity.
+    pub fn allows_labels(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.node_selector
+            .iter()
+            .all(|(k, v)| labels.get(k) == Some(v))
+            && (self.node_affinity.is_empty()
+                || self.node_affinity.iter().any(|t| term_matches(t, labels)))
+    }
+
+    /// Whether the pod tolerates every taint in `taints` that keeps pods
+    /// off a node. `PreferNoSchedule` taints only discourage placement, so
+    /// they are ignored.
+    pub fn tolerates(&self, taints: &[Taint]) -> bool {
+        taints
+            .iter()
+            .filter(|t| t.effect != "PreferNoSchedule")
+            .all(|t| {
+                self.tolerations
+                    .iter()
+                    .any(|tol| toleration_matches(tol, t))
+            })
+    }
+
+    /// Whether a node in `zone` is acceptable. Zoneless nodes only suit pods
+    /// without a zone requirement.
+    pub fn allows_zone(&self, zone: Option<&str>) -> bool {
+        match &self.zones {
+            None => true,
+            Some(zones) => zone.is_some_and(|z| zones.iter().any(|a| a == z)),
+        }
+    }
+
+    /// Narrow the pod's zones to `zones`, e.g. the zone of a bound volume.
+    pub fn restrict_zones(&mut self, zones: Vec<String>) {
+        self.zones = Some(match self.zones.take() {
+            None => zones,
+            Some(current) => current.into_iter().filter(|z| zones.contains(z)).collect(),
+        });
+    }
+}
+
+/// Whether `labels` satisfy every expression of a node selector term. Terms
+/// using `matchFields` pin pods to named nodes, which a new node never is.
+fn term_matches(term: &NodeSelectorTerm, labels: &BTreeMap<String, String>) -> bool {
+    let expressions = term.match_expressions.as_deref().unwrap_or_default();
+    if term.match_fields.as_ref().is_some_and(|f| !f.is_empty()) || expressions.is_empty() {
+        return false;
+    }
+    expressions
+        .iter()
+        .all(|req| requirement_matches(req, labels))
+}
+
+pub(crate) fn requirement_matches(
+    req: &NodeSelectorRequirement,
+    labels: &BTreeMap<String, String>,
+) -> bool {
+    let value = labels.get(&req.key);
+    let values = req.values.as_deref().unwrap_or_default();
+    match req.operator.as_str() {
+        "In" => value.is_some_and(|v| values.contains(v)),
+        "NotIn" => value.is_none_or(|v| !values.contains(v)),
+        "Exists" => value.is_some(),
+        "DoesNotExist" => value.is_none(),
+        op @ ("Gt" | "Lt") => {
+            let (Some(v), Some(bound)) = (
+                value.and_then(|v| v.parse::<i64>().ok()),
+                values.first().and_then(|b| b.parse::<i64>().ok()),
+            ) else {
+                return false;
+            };
+            if op == "Gt" { v > bound } else { v < bound }
+        }
+        _ => false,
+    }
+}
+
+/// Labels a node is known to carry once it joins, for matching pods'
+/// nodeSelectors and node affinity. Pool labels come on top.
+pub fn node_labels(
+    instance_type: &str,
+    region: &str,
+    zone: Option<&str>,
+    resources: &Resources,
+) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::from([
+        (INSTANCE_TYPE_LABEL.to_string(), instance_type.to_string()),
+        (REGION_LABEL.to_string(), region.to_string()),
+        (OS_LABEL.to_string(), "linux".to_string()),
+    ]);
+    if let Some(zone) = zone {
+        labels.insert(ZONE_LABEL.into(), zone.into());
+    }
+    if let Some(model) = &resources.gpu_model {
+        labels.insert(GPU_PRODUCT_LABEL.into(), model.clone().into());
+    }
+    if let Some(arch) = resources.arch {
+        labels.insert(ARCH_LABEL.into(), arch.as_label().into());
+    }
+    labels
+}
+
+/// Kubernetes toleration matching: an empty effect matches every effect,
+/// `Exists` ignores the value (and with no key matches every taint), and
+/// `Equal`, the default, needs key and value to match.
+fn toleration_matches(toleration: &Toleration, taint: &Taint) -> bool {
+    let effect_ok = toleration
+        .effect
+        .as_deref()
+        .is_none_or(|e| e.is_empty() || e == taint.effect);
+    let key = toleration.key.as_deref().unwrap_or_default();
+    let key_value_ok = match toleration.operator.as_deref() {
+        Some("Exists") => key.is_empty() || key == taint.key,
+        _ => {
+            key == taint.key
+                && toleration.value.as_deref().unwrap_or_default()
+                    == taint.value.as_deref().unwrap_or_default()
+        }
+    };
+    effect_ok && key_value_ok
 }
 
 /// Parse affinity and anti-affinity rules from a pod spec into `AffinityConstraint`s.
@@ -396,10 +835,12 @@ impl Resources {
     // TODO: Account for init containers. Kubernetes effective request is
     // max(max(each init container), sum(regular containers)) per resource dimension.
     pub fn from_pod(pod: &Pod) -> Result<Resources, QuantityParseError> {
-        let mut cpu = 0u32;
-        let mut memory_mib = 0u32;
+        let mut cpu_millis = 0u64;
+        let mut memory_mib = 0u64;
         let mut gpu = 0u32;
         let mut ephemeral_storage_gib = None;
+        let mut mig = BTreeMap::new();
+        let mut extended = BTreeMap::new();
 
         let containers = pod
             .spec
@@ -415,41 +856,74 @@ impl Resources {
                 continue;
             };
 
+            // Summed requests too large to represent are an error, not a
+            // wrapped-around small request.
             if let Some(q) = requests.get("cpu") {
-                cpu += parse_cpu(q)?;
+                cpu_millis = cpu_millis
+                    .checked_add(parse_cpu_millis(q)?)
+                    .ok_or_else(|| too_large(q))?;
             }
             if let Some(q) = requests.get("memory") {
-                memory_mib += parse_memory_mib(q)?;
+                memory_mib = memory_mib
+                    .checked_add(parse_memory_mib(q)?)
+                    .ok_or_else(|| too_large(q))?;
             }
             if let Some(q) = requests.get("nvidia.com/gpu") {
-                gpu += q.0.parse::<u32>().map_err(|e| QuantityParseError {
-                    raw: q.0.clone(),
-                    source: e,
-                })?;
+                gpu = gpu
+                    .checked_add(parse_count(q)?)
+                    .ok_or_else(|| too_large(q))?;
             }
             if let Some(q) = requests.get("ephemeral-storage") {
-                let gib = parse_storage_gib(q)?;
-                *ephemeral_storage_gib.get_or_insert(0) += gib;
+                let total = ephemeral_storage_gib.get_or_insert(0u64);
+                *total = total
+                    .checked_add(parse_storage_gib(q)?)
+                    .ok_or_else(|| too_large(q))?;
+            }
+            for (name, q) in requests {
+                if let Some(profile) = name.strip_prefix(MIG_RESOURCE_PREFIX) {
+                    let total = mig.entry(profile.to_string()).or_insert(0u32);
+                    *total = total
+                        .checked_add(parse_count(q)?)
+                        .ok_or_else(|| too_large(q))?;
+                } else if is_extended_resource(name) {
+                    let total = extended.entry(name.clone()).or_insert(0u64);
+                    *total = total
+                        .checked_add(u64::from(parse_count(q)?))
+                        .ok_or_else(|| too_large(q))?;
+                }
             }
         }
 
-        let gpu_model = pod
-            .spec
-            .as_ref()
-            .and_then(|s| s.node_selector.as_ref())
+        let node_selector = pod.spec.as_ref().and_then(|s| s.node_selector.as_ref());
+        let gpu_model = node_selector
             .and_then(|sel| sel.get(GPU_PRODUCT_LABEL))
             .map(|s| GpuModel::from(s.clone()));
+        let arch = node_selector
+            .and_then(|sel| sel.get(ARCH_LABEL))
+            .and_then(|a| Arch::from_label(a));
 
         Ok(Resources {
-            cpu,
+            cpu_millis,
             memory_mib,
             ephemeral_storage_gib,
             gpu,
             gpu_model,
+            arch,
+            mig,
+            extended,
         })
     }
 }
 
+/// Whether a resource name is an extended resource: domain-prefixed, and
+/// not one of the GPU resources [`Resources`] models on its own.
+pub fn is_extended_resource(name: &str) -> bool {
+    name.contains('/')
+        && name != "nvidia.com/gpu"
+        && !name.starts_with(MIG_RESOURCE_PREFIX)
+        && !name.starts_with("hugepages-")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -462,27 +936,49 @@ mod tests {
         Quantity(s.to_string())
     }
 
+    #[test]
+    fn instance_family_follows_naming_conventions() {
+        let family = |name: &str| InstanceType(name.into()).family().to_string();
+        assert_eq!(family("ccx13"), "ccx");
+        assert_eq!(family("cax41"), "cax");
+        assert_eq!(family("gpu-a100-4"), "gpu");
+        assert_eq!(family("m5.large"), "m5");
+    }
+
+    #[test]
+    fn instance_type_matches_name_or_family_patterns() {
+        let t = |name: &str| InstanceType(name.into());
+        assert!(t("ccx13").matches("ccx*"));
+        assert!(t("ccx13").matches("ccx"));
+        assert!(t("ccx13").matches("c?x13"));
+        assert!(t("gpu-a100-4").matches("gpu-*"));
+        assert!(t("gpu-a100-4").matches("*a100*"));
+        assert!(!t("cpx22").matches("ccx*"));
+        assert!(!t("cpx22").matches("cp"));
+        assert!(!t("m5.large").matches("m5.x*"));
+    }
+
     #[test]
     fn parse_cpu_whole_cores() {
-        assert_eq!(parse_cpu(&q("4")).unwrap(), 4);
-        assert_eq!(parse_cpu(&q("1")).unwrap(), 1);
-        assert_eq!(parse_cpu(&q("0")).unwrap(), 0);
+        assert_eq!(parse_cpu_millis(&q("4")).unwrap(), 4000);
+        assert_eq!(parse_cpu_millis(&q("1")).unwrap(), 1000);
+        assert_eq!(parse_cpu_millis(&q("0")).unwrap(), 0);
     }
 
     #[test]
     fn parse_cpu_millicores() {
-        assert_eq!(parse_cpu(&q("1000m")).unwrap(), 1);
-        assert_eq!(parse_cpu(&q("500m")).unwrap(), 1); // rounds up
-        assert_eq!(parse_cpu(&q("250m")).unwrap(), 1); // rounds up
-        assert_eq!(parse_cpu(&q("1500m")).unwrap(), 2); // rounds up
-        assert_eq!(parse_cpu(&q("2000m")).unwrap(), 2);
+        assert_eq!(parse_cpu_millis(&q("1000m")).unwrap(), 1000);
+        assert_eq!(parse_cpu_millis(&q("500m")).unwrap(), 500);
+        assert_eq!(parse_cpu_millis(&q("250m")).unwrap(), 250);
+        assert_eq!(parse_cpu_millis(&q("1500m")).unwrap(), 1500);
+        assert_eq!(parse_cpu_millis(&q("2000m")).unwrap(), 2000);
     }
 
     #[test]
     fn parse_cpu_invalid_is_err() {
-        assert!(parse_cpu(&q("abc")).is_err());
-        assert!(parse_cpu(&q("")).is_err());
-        assert!(parse_cpu(&q("xm")).is_err());
+        assert!(parse_cpu_millis(&q("abc")).is_err());
+        assert!(parse_cpu_millis(&q("")).is_err());
+        assert!(parse_cpu_millis(&q("xm")).is_err());
     }
 
     #[test]
@@ -510,11 +1006,61 @@ mod tests {
         assert_eq!(parse_memory_mib(&q("0")).unwrap(), 0);
     }
 
+    #[test]
+    fn parse_memory_beyond_u32_mib() {
+        // 24 TiB bare-metal nodes, as the kubelet reports them.
+        assert_eq!(parse_memory_mib(&q("24Ti")).unwrap(), 24 * 1024 * 1024);
+        assert_eq!(
+            parse_memory_mib(&q("25769803776Ki")).unwrap(),
+            24 * 1024 * 1024
+        );
+        assert_eq!(parse_storage_gib(&q("16Ti")).unwrap(), 16 * 1024);
+    }
+
     #[test]
     fn parse_memory_invalid_is_err() {
         assert!(parse_memory_mib(&q("abc")).is_err());
         assert!(parse_memory_mib(&q("")).is_err());
         assert!(parse_memory_mib(&q("xGi")).is_err());
+        assert!(parse_memory_mib(&q("1.2.3Gi")).is_err());
+        assert!(parse_memory_mib(&q("-1Gi")).is_err());
+        assert!(parse_memory_mib(&q("1Zi")).is_err());
+    }
+
+    #[test]
+    fn parse_decimal_si_suffixes() {
+        assert_eq!(parse_memory_mib(&q("500M")).unwrap(), 477); // 476.8 rounds up
+        assert_eq!(parse_memory_mib(&q("4G")).unwrap(), 3815);
+        assert_eq!(parse_memory_mib(&q("1T")).unwrap(), 953_675);
+        assert_eq!(parse_memory_mib(&q("1048576k")).unwrap(), 1000);
+        assert_eq!(parse_storage_gib(&q("100G")).unwrap(), 94);
+        assert_eq!(parse_cpu_millis(&q("100u")).unwrap(), 1); // rounds up
+        assert_eq!(parse_cpu_millis(&q("2k")).unwrap(), 2_000_000);
+    }
+
+    #[test]
+    fn parse_fractional_values() {
+        assert_eq!(parse_cpu_millis(&q("0.5")).unwrap(), 500);
+        assert_eq!(parse_cpu_millis(&q(".25")).unwrap(), 250);
+        assert_eq!(parse_cpu_millis(&q("1.5")).unwrap(), 1500);
+        assert_eq!(parse_cpu_millis(&q("0.1m")).unwrap(), 1);
+        assert_eq!(parse_memory_mib(&q("1.5Gi")).unwrap(), 1536);
+        assert_eq!(parse_memory_mib(&q("0.5Mi")).unwrap(), 1);
+        assert_eq!(parse_count(&q("2.0")).unwrap(), 2);
+        assert!(parse_count(&q("1.5")).is_err());
+    }
+
+    #[test]
+    fn parse_exponents() {
+        assert_eq!(parse_cpu_millis(&q("1e3")).unwrap(), 1_000_000);
+        assert_eq!(parse_cpu_millis(&q("5E-1")).unwrap(), 500);
+        assert_eq!(parse_memory_mib(&q("1048576e0")).unwrap(), 1);
+        assert_eq!(parse_memory_mib(&q("1e9")).unwrap(), 954);
+        assert_eq!(parse_count(&q("1e1")).unwrap(), 10);
+        // `E` alone is exa, not an exponent.
+        assert_eq!(parse_storage_gib(&q("1E")).unwrap(), 931_322_575);
+        let err = parse_cpu_millis(&q("1e400")).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{err}");
     }
 
     fn make_container(cpu: &str, memory: &str) -> Container {
@@ -545,13 +1091,40 @@ mod tests {
     fn from_pod_single_container() {
         let pod = make_pod(vec![make_container("2", "4Gi")]);
         let r = Resources::from_pod(&pod).unwrap();
-        assert_eq!(r.cpu, 2);
+        assert_eq!(r.cpu_millis, 2000);
         assert_eq!(r.memory_mib, 4096);
         assert_eq!(r.gpu, 0);
         assert_eq!(r.ephemeral_storage_gib, None);
         assert_eq!(r.gpu_model, None);
     }
 
+    #[test]
+    fn from_pod_reads_priority() {
+        let mut pod = make_pod(vec![make_container("1", "1Gi")]);
+        assert_eq!(PodResources::from_pod(&pod).unwrap().priority, 0);
+        pod.spec.as_mut().unwrap().priority = Some(100_000);
+        assert_eq!(PodResources::from_pod(&pod).unwrap().priority, 100_000);
+    }
+
+    #[test]
+    fn from_pod_reads_unmet_penalty() {
+        let mut pod = make_pod(vec![make_container("1", "1Gi")]);
+        assert_eq!(PodResources::from_pod(&pod).unwrap().unmet_penalty, None);
+        pod.metadata.annotations = Some(BTreeMap::from([(
+            UNMET_PENALTY_ANNOTATION.to_string(),
+            "0.25".to_string(),
+        )]));
+        assert_eq!(
+            PodResources::from_pod(&pod).unwrap().unmet_penalty,
+            Some(0.25)
+        );
+        pod.metadata.annotations = Some(BTreeMap::from([(
+            UNMET_PENALTY_ANNOTATION.to_string(),
+            "lots".to_string(),
+        )]));
+        assert_eq!(PodResources::from_pod(&pod).unwrap().unmet_penalty, None);
+    }
+
     #[test]
     fn from_pod_multi_container_sums() {
         let pod = make_pod(vec![
@@ -559,15 +1132,26 @@ mod tests {
             make_container("1", "512Mi"),
         ]);
         let r = Resources::from_pod(&pod).unwrap();
-        assert_eq!(r.cpu, 3);
+        assert_eq!(r.cpu_millis, 3000);
         assert_eq!(r.memory_mib, 1024 + 512);
     }
 
+    #[test]
+    fn from_pod_sum_overflow_is_err() {
+        // Each fits on its own; together they would wrap around to 1999m.
+        let pod = make_pod(vec![
+            make_container("18446744073709551615m", "1Gi"),
+            make_container("2", "1Gi"),
+        ]);
+        let err = Resources::from_pod(&pod).unwrap_err();
+        assert!(err.to_string().contains("too large"), "{err}");
+    }
+
     #[test]
     fn from_pod_no_spec_returns_zero() {
         let pod = Pod::default();
         let r = Resources::from_pod(&pod).unwrap();
-        assert_eq!(r.cpu, 0);
+        assert_eq!(r.cpu_millis, 0);
         assert_eq!(r.memory_mib, 0);
     }
 
@@ -578,7 +1162,7 @@ mod tests {
             ..Default::default()
         }]);
         let r = Resources::from_pod(&pod).unwrap();
-        assert_eq!(r.cpu, 0);
+        assert_eq!(r.cpu_millis, 0);
         assert_eq!(r.memory_mib, 0);
     }
 
@@ -598,11 +1182,188 @@ mod tests {
         };
         let pod = make_pod(vec![container]);
         let r = Resources::from_pod(&pod).unwrap();
-        assert_eq!(r.cpu, 4);
+        assert_eq!(r.cpu_millis, 4000);
         assert_eq!(r.memory_mib, 8192);
         assert_eq!(r.gpu, 2);
     }
 
+    #[test]
+    fn mig_slices_are_counted_by_profile() {
+        let mut container = make_container("1", "2Gi");
+        let requests = container
+            .resources
+            .as_mut()
+            .unwrap()
+            .requests
+            .as_mut()
+            .unwrap();
+        requests.insert("nvidia.com/mig-1g.5gb".to_string(), q("2"));
+        let pod = make_pod(vec![container.clone(), container]);
+        let need = Resources::from_pod(&pod).unwrap();
+        assert_eq!(need.gpu, 0);
+        assert_eq!(need.mig, BTreeMap::from([("1g.5gb".to_string(), 4)]));
+
+        let mut node = Resources {
+            cpu_millis: 8000,
+            memory_mib: 16_384,
+            mig: BTreeMap::from([("1g.5gb".to_string(), 7), ("3g.20gb".to_string(), 1)]),
+            ..Resources::default()
+        };
+        assert!(node.satisfies(&need));
+        node.subtract(&need);
+        assert_eq!(node.mig["1g.5gb"], 3);
+        assert!(!node.satisfies(&need));
+        // A whole GPU is no substitute for a slice.
+        let whole = Resources {
+            cpu_millis: 8000,
+            memory_mib: 16_384,
+            gpu: 1,
+            ..Resources::default()
+        };
+        assert!(!whole.satisfies(&need));
+    }
+
+    #[test]
+    fn zones_from_selector_and_node_affinity() {
+        use k8s_openapi::api::core::v1::{
+            Affinity, NodeAffinity, NodeSelector, NodeSelectorRequirement,
+        };
+        let mut pod = make_pod(vec![make_container("1", "1Gi")]);
+        assert_eq!(PodResources::from_pod(&pod).unwrap().zones, None);
+
+        let spec = pod.spec.as_mut().unwrap();
+        spec.affinity = Some(Affinity {
+            node_affinity: Some(NodeAffinity {
+                required_during_scheduling_ignored_during_execution: Some(NodeSelector {
+                    node_selector_terms: vec![NodeSelectorTerm {
+                        match_expressions: Some(vec![NodeSelectorRequirement {
+                            key: ZONE_LABEL.into(),
+                            operator: "In".into(),
+                            values: Some(vec!["zone-a".into(), "zone-b".into()]),
+                        }]),
+                        ..Default::default()
+                    }],
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let mut demand = PodResources::from_pod(&pod).unwrap();
+        assert!(demand.allows_zone(Some("zone-b")));
+        assert!(!demand.allows_zone(Some("zone-c")));
+        assert!(!demand.allows_zone(None));
+
+        // A bound volume narrows it further.
+        demand.restrict_zones(vec!["zone-a".into()]);
+        assert_eq!(demand.zones, Some(vec!["zone-a".to_string()]));
+
+        pod.spec.as_mut().unwrap().node_selector =
+            Some(BTreeMap::from([(ZONE_LABEL.into(), "zone-b".into())]));
+        assert_eq!(
+            PodResources::from_pod(&pod).unwrap().zones,
+            Some(vec!["zone-b".to_string()])
+        );
+    }
+
+    #[test]
+    fn node_selector_and_affinity_match_labels() {
+        use k8s_openapi::api::core::v1::{Affinity, NodeAffinity, NodeSelector};
+        let expr = |key: &str, operator: &str, values: &[&str]| NodeSelectorRequirement {
+            key: key.into(),
+            operator: operator.into(),
+            values: Some(values.iter().map(|v| v.to_string()).collect()),
+        };
+        let mut pod = make_pod(vec![make_container("1", "1Gi")]);
+        let spec = pod.spec.as_mut().unwrap();
+        spec.node_selector = Some(BTreeMap::from([
+            ("disktype".into(), "ssd".into()),
+            // Covered by `pool`, so not matched against labels.
+            (POOL_LABEL.into(), "fast".into()),
+        ]));
+        spec.affinity = Some(Affinity {
+            node_affinity: Some(NodeAffinity {
+                required_during_scheduling_ignored_during_execution: Some(NodeSelector {
+                    node_selector_terms: vec![
+                        NodeSelectorTerm {
+                            match_expressions: Some(vec![
+                                expr(INSTANCE_TYPE_LABEL, "NotIn", &["cx22"]),
+                                expr("cores", "Gt", &["4"]),
+                            ]),
+                            ..Default::default()
+                        },
+                        NodeSelectorTerm {
+                            match_expressions: Some(vec![expr("dedicated", "Exists", &[])]),
+                            ..Default::default()
+                        },
+                    ],
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+        let demand = PodResources::from_pod(&pod).unwrap();
+        let labels = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        assert!(demand.allows_labels(&labels(&[
+            ("disktype", "ssd"),
+            (INSTANCE_TYPE_LABEL, "cx42"),
+            ("cores", "8"),
+        ])));
+        // Second term alone is enough.
+        assert!(demand.allows_labels(&labels(&[("disktype", "ssd"), ("dedicated", "")])));
+        assert!(!demand.allows_labels(&labels(&[
+            ("disktype", "ssd"),
+            (INSTANCE_TYPE_LABEL, "cx22"),
+            ("cores", "8"),
+        ])));
+        assert!(!demand.allows_labels(&labels(&[("disktype", "ssd"), ("cores", "2")])));
+        assert!(!demand.allows_labels(&labels(&[("dedicated", ""), ("cores", "8")])));
+    }
+
+    #[test]
+    fn tolerations_cover_taints() {
+        let taint = |key: &str, value: Option<&str>, effect: &str| Taint {
+            key: key.into(),
+            value: value.map(Into::into),
+            effect: effect.into(),
+            ..Default::default()
+        };
+        let gpu = taint("gpu", Some("true"), "NoSchedule");
+        let mut pod = make_pod(vec![make_container("1", "1Gi")]);
+        let demand = PodResources::from_pod(&pod).unwrap();
+        assert!(demand.tolerates(&[]));
+        assert!(!demand.tolerates(std::slice::from_ref(&gpu)));
+        // Soft taints never block placement.
+        assert!(demand.tolerates(&[taint("spot", None, "PreferNoSchedule")]));
+
+        pod.spec.as_mut().unwrap().tolerations = Some(vec![Toleration {
+            key: Some("gpu".into()),
+            operator: Some("Equal".into()),
+            value: Some("true".into()),
+            effect: Some("NoSchedule".into()),
+            ..Default::default()
+        }]);
+        let demand = PodResources::from_pod(&pod).unwrap();
+        assert!(demand.tolerates(std::slice::from_ref(&gpu)));
+        assert!(!demand.tolerates(&[taint("gpu", Some("false"), "NoSchedule")]));
+        assert!(!demand.tolerates(&[taint("gpu", Some("true"), "NoExecute")]));
+
+        // A bare `Exists` tolerates everything.
+        let everything = PodResources {
+            tolerations: vec![Toleration {
+                operator: Some("Exists".into()),
+                ..Default::default()
+            }],
+            ..demand
+        };
+        assert!(everything.tolerates(&[gpu, taint("dedicated", Some("db"), "NoExecute")]));
+    }
+
     #[test]
     fn from_pod_with_gpu_model() {
         let mut requests = BTreeMap::new();
@@ -631,12 +1392,80 @@ mod tests {
             ..Default::default()
         };
         let r = Resources::from_pod(&pod).unwrap();
-        assert_eq!(r.cpu, 4);
+        assert_eq!(r.cpu_millis, 4000);
         assert_eq!(r.memory_mib, 8192);
         assert_eq!(r.gpu, 1);
         assert_eq!(r.gpu_model, Some(GpuModel::NvidiaA100));
     }
 
+    #[test]
+    fn arch_selector_sets_demand_arch() {
+        let mut pod = make_pod(vec![make_container("1", "1Gi")]);
+        pod.spec.as_mut().unwrap().node_selector =
+            Some(BTreeMap::from([(ARCH_LABEL.into(), "amd64".into())]));
+        let demand = PodResources::from_pod(&pod).unwrap();
+        assert_eq!(demand.resources.arch, Some(Arch::Amd64));
+        assert!(demand.node_selector.is_empty());
+
+        let arm = Offering {
+            instance_type: InstanceType("cax11".into()),
+            provider: "fake".into(),
+            resources: Resources {
+                cpu_millis: 2000,
+                memory_mib: 4096,
+                ephemeral_storage_gib: None,
+                gpu: 0,
+                gpu_model: None,
+                arch: Some(Arch::Arm64),
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
+            },
+            cost_per_hour: 0.0074,
+            interruption_risk: 0.0,
+            location: test_location(),
+        };
+        assert!(!arm.satisfies(&demand.resources));
+        assert_eq!(arm.labels()[ARCH_LABEL], "arm64");
+
+        // Architectures no provider offers stay in the selector, so they
+        // match nothing.
+        pod.spec.as_mut().unwrap().node_selector =
+            Some(BTreeMap::from([(ARCH_LABEL.into(), "s390x".into())]));
+        let demand = PodResources::from_pod(&pod).unwrap();
+        assert_eq!(demand.resources.arch, None);
+        assert!(!demand.allows_labels(&arm.labels()));
+    }
+
+    #[test]
+    fn extended_resource_requests_must_be_met() {
+        let mut container = make_container("4", "8Gi");
+        let resources = container.resources.as_mut().unwrap();
+        let requests = resources.requests.as_mut().unwrap();
+        requests.insert("xilinx.com/u30".to_string(), q("2"));
+        requests.insert("nvidia.com/gpu".to_string(), q("1"));
+        let pod = make_pod(vec![container]);
+
+        let need = Resources::from_pod(&pod).unwrap();
+        assert_eq!(need.gpu, 1);
+        assert_eq!(
+            need.extended,
+            BTreeMap::from([("xilinx.com/u30".to_string(), 2)])
+        );
+
+        let mut node = Resources {
+            cpu_millis: 8000,
+            memory_mib: 16_384,
+            gpu: 1,
+            extended: BTreeMap::from([("xilinx.com/u30".to_string(), 2)]),
+            ..Resources::default()
+        };
+        assert!(node.satisfies(&need));
+        node.subtract(&need);
+        assert_eq!(node.extended["xilinx.com/u30"], 0);
+        node.gpu = 1;
+        assert!(!node.satisfies(&need));
+    }
+
     #[test]
     fn from_pod_invalid_cpu_is_err() {
         let pod = make_pod(vec![make_container("garbage", "4Gi")]);
@@ -660,22 +1489,30 @@ mod tests {
     fn satisfies_exact_match() {
         let offering = Offering {
             instance_type: InstanceType("cx21".to_string()),
+            provider: "fake".into(),
             resources: Resources {
-                cpu: 2,
+                cpu_millis: 2000,
                 memory_mib: 4096,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             cost_per_hour: 0.0066,
+            interruption_risk: 0.0,
             location: test_location(),
         };
         let demand = Resources {
-            cpu: 2,
+            cpu_millis: 2000,
             memory_mib: 4096,
             ephemeral_storage_gib: None,
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         };
         assert!(offering.satisfies(&demand));
     }
@@ -684,22 +1521,30 @@ mod tests {
     fn satisfies_offering_larger_than_demand() {
         let offering = Offering {
             instance_type: InstanceType("cx31".to_string()),
+            provider: "fake".into(),
             resources: Resources {
-                cpu: 4,
+                cpu_millis: 4000,
                 memory_mib: 8192,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             cost_per_hour: 0.0106,
+            interruption_risk: 0.0,
             location: test_location(),
         };
         let demand = Resources {
-            cpu: 2,
+            cpu_millis: 2000,
             memory_mib: 4096,
             ephemeral_storage_gib: None,
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         };
         assert!(offering.satisfies(&demand));
     }
@@ -708,22 +1553,30 @@ mod tests {
     fn satisfies_rejects_insufficient_cpu() {
         let offering = Offering {
             instance_type: InstanceType("cx11".to_string()),
+            provider: "fake".into(),
             resources: Resources {
-                cpu: 1,
+                cpu_millis: 1000,
                 memory_mib: 2048,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             cost_per_hour: 0.0044,
+            interruption_risk: 0.0,
             location: test_location(),
         };
         let demand = Resources {
-            cpu: 2,
+            cpu_millis: 2000,
             memory_mib: 1024,
             ephemeral_storage_gib: None,
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         };
         assert!(!offering.satisfies(&demand));
     }
@@ -735,26 +1588,36 @@ mod tests {
 
         let good_offering = Offering {
             instance_type: InstanceType("cx31".to_string()),
+            provider: "fake".into(),
             resources: Resources {
-                cpu: 4,
+                cpu_millis: 4000,
                 memory_mib: 8192,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             cost_per_hour: 0.0106,
+            interruption_risk: 0.0,
             location: test_location(),
         };
         let small_offering = Offering {
             instance_type: InstanceType("cx11".to_string()),
+            provider: "fake".into(),
             resources: Resources {
-                cpu: 1,
+                cpu_millis: 1000,
                 memory_mib: 2048,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             cost_per_hour: 0.0044,
+            interruption_risk: 0.0,
             location: test_location(),
         };
 
@@ -762,45 +1625,89 @@ mod tests {
         assert!(!small_offering.satisfies(&demand));
     }
 
+    #[test]
+    fn fractional_cpu_requests_add_up_exactly() {
+        let pod = make_pod(vec![make_container("100m", "64Mi")]);
+        let demand = Resources::from_pod(&pod).unwrap();
+        let mut node = Resources {
+            cpu_millis: 1000,
+            memory_mib: 1024,
+            ephemeral_storage_gib: None,
+            gpu: 0,
+            gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
+        };
+        for _ in 0..10 {
+            assert!(node.satisfies(&demand));
+            node.subtract(&demand);
+        }
+        assert_eq!(node.cpu_millis, 0);
+        assert!(!node.satisfies(&demand));
+    }
+
+    #[test]
+    fn legacy_whole_cpu_deserializes_as_millicores() {
+        let legacy: Resources =
+            serde_json::from_str(r#"{"cpu": 2, "memoryMib": 4096, "gpu": 0}"#).unwrap();
+        assert_eq!(legacy.cpu_millis, 2000);
+        let current: Resources =
+            serde_json::from_str(r#"{"cpuMillis": 1500, "memoryMib": 4096, "gpu": 0}"#).unwrap();
+        assert_eq!(current.cpu_millis, 1500);
+    }
+
     #[test]
     fn subtract_basic_cpu_memory() {
         let mut capacity = Resources {
-            cpu: 4,
+            cpu_millis: 4000,
             memory_mib: 8192,
             ephemeral_storage_gib: None,
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         };
         let consumed = Resources {
-            cpu: 1,
+            cpu_millis: 1000,
             memory_mib: 2048,
             ephemeral_storage_gib: None,
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         };
         capacity.subtract(&consumed);
-        assert_eq!(capacity.cpu, 3);
+        assert_eq!(capacity.cpu_millis, 3000);
         assert_eq!(capacity.memory_mib, 6144);
     }
 
     #[test]
     fn subtract_to_zero() {
         let mut capacity = Resources {
-            cpu: 2,
+            cpu_millis: 2000,
             memory_mib: 4096,
             ephemeral_storage_gib: Some(40),
             gpu: 1,
             gpu_model: Some(GpuModel::NvidiaT4),
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         };
         let consumed = Resources {
-            cpu: 2,
+            cpu_millis: 2000,
             memory_mib: 4096,
             ephemeral_storage_gib: Some(40),
             gpu: 1,
             gpu_model: Some(GpuModel::NvidiaT4),
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         };
         capacity.subtract(&consumed);
-        assert_eq!(capacity.cpu, 0);
+        assert_eq!(capacity.cpu_millis, 0);
         assert_eq!(capacity.memory_mib, 0);
         assert_eq!(capacity.ephemeral_storage_gib, Some(0));
         assert_eq!(capacity.gpu, 0);
@@ -809,18 +1716,24 @@ mod tests {
     #[test]
     fn subtract_ephemeral_storage_none_consumed() {
         let mut capacity = Resources {
-            cpu: 4,
+            cpu_millis: 4000,
             memory_mib: 8192,
             ephemeral_storage_gib: Some(80),
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         };
         let consumed = Resources {
-            cpu: 1,
+            cpu_millis: 1000,
             memory_mib: 2048,
             ephemeral_storage_gib: None,
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         };
         capacity.subtract(&consumed);
         // ephemeral_storage_gib unchanged when consumed is None
@@ -0,0 +1,174 @@
+//! Historical create/join statistics per offering.
+//!
+//! Pure data + scoring. Loading and persisting the stats (ConfigMap) lives
+//! in `controller::offering_stats`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use super::Offering;
+
+/// Samples needed before an offering's record contributes a penalty.
+/// Avoids punishing an instance type for a single unlucky create.
+pub const MIN_SAMPLES: u32 = 3;
+
+/// Stable key identifying an offering across solves: `provider/type/region`.
+pub fn offering_key(provider: &str, instance_type: &str, region: &str) -> String {
+    format!("{provider}/{instance_type}/{region}")
+}
+
+impl Offering {
+    /// Key under which this offering's statistics are recorded.
+    pub fn stats_key(&self) -> String {
+        offering_key(
+            &self.provider,
+            &self.instance_type.0,
+            &self.location.region.0,
+        )
+    }
+}
+
+/// Counters for a single offering.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OfferingRecord {
+    pub creates_succeeded: u32,
+    pub creates_failed: u32,
+    /// Nodes that joined the cluster after a successful create.
+    pub joins: u32,
+    /// Sum of create-to-Ready durations for `joins`, in seconds.
+    pub total_join_secs: u64,
+}
+
+impl OfferingRecord {
+    /// Fraction of creates that failed, or `None` below [`MIN_SAMPLES`].
+    pub fn failure_rate(&self) -> Option<f64> {
+        let total = self.creates_succeeded + self.creates_failed;
+        (total >= MIN_SAMPLES).then(|| self.creates_failed as f64 / total as f64)
+    }
+
+    /// Mean create-to-Ready time in seconds, or `None` below [`MIN_SAMPLES`].
+    pub fn mean_join_secs(&self) -> Option<f64> {
+        (self.joins >= MIN_SAMPLES).then(|| self.total_join_secs as f64 / self.joins as f64)
+    }
+}
+
+/// Weights turning an [`OfferingRecord`] into a solver penalty.
+///
+/// Both default to 0, which disables penalties entirely.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PenaltyWeights {
+    /// Penalty per unit of failure rate (0.0–1.0).
+    pub failure: f64,
+    /// Penalty per minute of mean join time.
+    pub join_minutes: f64,
+}
+
+impl PenaltyWeights {
+    pub fn is_enabled(&self) -> bool {
+        self.failure > 0.0 || self.join_minutes > 0.0
+    }
+}
+
+/// All offering records, keyed by [`offering_key`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OfferingStats {
+    pub records: BTreeMap<String, OfferingRecord>,
+}
+
+impl OfferingStats {
+    pub fn record_create(&mut self, key: String, succeeded: bool) {
+        let record = self.records.entry(key).or_default();
+        if succeeded {
+            record.creates_succeeded += 1;
+        } else {
+            record.creates_failed += 1;
+        }
+    }
+
+    pub fn record_join(&mut self, key: String, join_secs: u64) {
+        let record = self.records.entry(key).or_default();
+        record.joins += 1;
+        record.total_join_secs += join_secs;
+    }
+
+    /// Per-offering penalties for the solver. Offerings without enough
+    /// history, or with a zero penalty, are omitted.
+    pub fn penalties(&self, weights: PenaltyWeights) -> HashMap<String, f64> {
+        if !weights.is_enabled() {
+            return HashMap::new();
+        }
+        self.records
+            .iter()
+            .filter_map(|(key, record)| {
+                let failure = record.failure_rate().unwrap_or(0.0) * weights.failure;
+                let join = record.mean_join_secs().unwrap_or(0.0) / 60.0 * weights.join_minutes;
+                let penalty = failure + join;
+                (penalty > 0.0).then(|| (key.clone(), penalty))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "hetzner/cpx22/nbg1";
+
+    fn weights(failure: f64, join_minutes: f64) -> PenaltyWeights {
+        PenaltyWeights {
+            failure,
+            join_minutes,
+        }
+    }
+
+    #[test]
+    fn no_penalty_below_min_samples() {
+        let mut stats = OfferingStats::default();
+        stats.record_create(KEY.into(), false);
+        stats.record_create(KEY.into(), false);
+        assert!(stats.penalties(weights(1.0, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn failure_rate_scales_penalty() {
+        let mut stats = OfferingStats::default();
+        stats.record_create(KEY.into(), true);
+        stats.record_create(KEY.into(), false);
+        stats.record_create(KEY.into(), false);
+        stats.record_create(KEY.into(), true);
+        let penalties = stats.penalties(weights(2.0, 0.0));
+        assert!((penalties[KEY] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn join_time_scales_penalty() {
+        let mut stats = OfferingStats::default();
+        for secs in [60, 120, 180] {
+            stats.record_join(KEY.into(), secs);
+        }
+        let penalties = stats.penalties(weights(0.0, 0.5));
+        assert!((penalties[KEY] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zero_weights_disable_penalties() {
+        let mut stats = OfferingStats::default();
+        for _ in 0..5 {
+            stats.record_create(KEY.into(), false);
+        }
+        assert!(stats.penalties(PenaltyWeights::default()).is_empty());
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut stats = OfferingStats::default();
+        stats.record_create(KEY.into(), true);
+        stats.record_join(KEY.into(), 42);
+        let json = serde_json::to_string(&stats).unwrap();
+        let back: OfferingStats = serde_json::from_str(&json).unwrap();
+        assert_eq!(stats, back);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::offering::PodResources;
+
+use super::{
+    BoundedOffering, ExistingNode, Packing, QuotaBudget, SolveOptions, SolveOutcome, solve_packed,
+};
+
+/// A placement algorithm the controller can be configured with.
+///
+/// Backends are stateless; the controller picks one at startup from
+/// `GROWTH_SOLVER` (see [`solver_by_name`]) and uses it for every pool.
+pub trait SolverBackend: std::fmt::Debug + Send + Sync {
+    /// Name the backend is selected by.
+    fn name(&self) -> &'static str;
+
+    /// Place `demands` on `existing` capacity and new nodes from `bounded`,
+    /// deducting activated nodes from `quotas`, and report how it went.
+    fn solve(
+        &self,
+        demands: &[PodResources],
+        bounded: &[BoundedOffering],
+        existing: &[ExistingNode],
+        quotas: &mut QuotaBudget,
+        options: &SolveOptions,
+    ) -> SolveOutcome;
+}
+
+/// The default filter-score solver: every pod goes to the best-scoring
+/// existing node or new offering.
+#[derive(Debug)]
+pub struct ScoredSolver;
+
+impl SolverBackend for ScoredSolver {
+    fn name(&self) -> &'static str {
+        "scored"
+    }
+
+    fn solve(
+        &self,
+        demands: &[PodResources],
+        bounded: &[BoundedOffering],
+        existing: &[ExistingNode],
+        quotas: &mut QuotaBudget,
+        options: &SolveOptions,
+    ) -> SolveOutcome {
+        solve_packed(demands, bounded, existing, quotas, options, Packing::Scored)
+    }
+}
+
+/// First-fit decreasing: a pod goes to the first node it fits on, and
+/// offerings are only scored when none does. Cheaper on large batches, at
+/// the cost of ignoring preferred affinity and bin-pack tightness between
+/// nodes that already exist.
+#[derive(Debug)]
+pub struct FirstFitSolver;
+
+impl SolverBackend for FirstFitSolver {
+    fn name(&self) -> &'static str {
+        "first-fit"
+    }
+
+    fn solve(
+        &self,
+        demands: &[PodResources],
+        bounded: &[BoundedOffering],
+        existing: &[ExistingNode],
+        quotas: &mut QuotaBudget,
+        options: &SolveOptions,
+    ) -> SolveOutcome {
+        solve_packed(
+            demands,
+            bounded,
+            existing,
+            quotas,
+            options,
+            Packing::FirstFit,
+        )
+    }
+}
+
+/// Every built-in backend, default first.
+pub const SOLVERS: &[&dyn SolverBackend] = &[&ScoredSolver, &FirstFitSolver];
+
+/// Look a backend up by its [`SolverBackend::name`].
+pub fn solver_by_name(name: &str) -> Option<&'static dyn SolverBackend> {
+    SOLVERS.iter().copied().find(|s| s.name() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backends_are_found_by_name() {
+        assert_eq!(solver_by_name("scored").unwrap().name(), "scored");
+        assert_eq!(solver_by_name("first-fit").unwrap().name(), "first-fit");
+        assert!(solver_by_name("highs").is_none());
+    }
+}
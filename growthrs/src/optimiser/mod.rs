@@ -7,22 +7,30 @@
 //!
 //! | Kube phase   | Solver equivalent                          |
 //! |-------------|--------------------------------------------|
-//! | **Filter**  | `Resources::satisfies`, affinity, budget    |
+//! | **Filter**  | `Resources::satisfies`, GPU model, node selectors, taints, affinity, budget |
 //! | **Score**   | `score_existing_node`, `score_new_offering` |
 //! | **Reserve** | `InstanceBudget::activate`                  |
 //! | **Bind**    | *not here — kube-scheduler does this*       |
 //!
 //! [scheduling framework]: https://kubernetes.io/docs/concepts/scheduling-eviction/scheduling-framework/
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
 
+use k8s_openapi::api::core::v1::Taint;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use tracing::{debug, info, instrument, warn};
 
 mod affinity;
+mod backend;
+mod quota;
 
-use crate::offering::{Offering, PodId, PodResources, Resources};
+use crate::offering::{InstanceType, Offering, PodId, PodResources, Resources, ZONE_LABEL};
 
 use affinity::{check_affinity_required, check_anti_affinity_required, preferred_affinity_score};
+pub use backend::{FirstFitSolver, SOLVERS, ScoredSolver, SolverBackend, solver_by_name};
+pub use quota::QuotaBudget;
 
 /// An offering paired with the maximum number of instances the pool allogrowthrs\src\optimiser\mod.rsws.
 #[derive(Debug, Clone, PartialEq)]
@@ -36,15 +44,98 @@ pub struct BoundedOffering {
     /// Used when the same instance type appears in multiple zones — the pool's
     /// `max` is a total across all location variants.
     pub type_group: Option<String>,
+    /// Reliability penalty from historical create/join stats. Scales the
+    /// cost term as `cost * (1 + penalty)`; 0 means no penalty.
+    pub penalty: f64,
+    /// Taints nodes of this offering register with (from the owning
+    /// NodePool). Only pods tolerating them are placed here.
+    pub taints: Vec<Taint>,
+    /// Requests of the DaemonSet pods every node of this offering will run,
+    /// held back from the pods being placed.
+    pub overhead: Resources,
+}
+
+impl BoundedOffering {
+    /// What a node of this offering has left for pods once its DaemonSets
+    /// are running.
+    fn allocatable(&self) -> Resources {
+        let mut allocatable = self.offering.resources.clone();
+        allocatable.saturating_subtract(&self.overhead);
+        allocatable
+    }
+
+    /// How many nodes of this offering `demands` could possibly need: enough
+    /// to hold the total demand that fits on one, plus slack for packing
+    /// losses, and never more than one per fitting demand.
+    pub fn demand_bound(&self, demands: &[PodResources]) -> u32 {
+        let allocatable = self.allocatable();
+        let fitting: Vec<&Resources> = demands
+            .iter()
+            .map(|d| &d.resources)
+            .filter(|r| allocatable.satisfies(r))
+            .collect();
+        if fitting.is_empty() {
+            return 0;
+        }
+        let nodes_for = |total: u64, per_node: u64| {
+            if per_node == 0 {
+                0
+            } else {
+                total.div_ceil(per_node)
+            }
+        };
+        let lower = [
+            nodes_for(
+                fitting.iter().map(|r| r.cpu_millis).sum(),
+                allocatable.cpu_millis,
+            ),
+            nodes_for(
+                fitting.iter().map(|r| r.memory_mib).sum(),
+                allocatable.memory_mib,
+            ),
+            nodes_for(
+                fitting.iter().map(|r| u64::from(r.gpu)).sum(),
+                u64::from(allocatable.gpu),
+            ),
+            nodes_for(
+                fitting.iter().filter_map(|r| r.ephemeral_storage_gib).sum(),
+                allocatable.ephemeral_storage_gib.unwrap_or(0),
+            ),
+        ]
+        .into_iter()
+        .chain(allocatable.mig.iter().map(|(profile, &per_node)| {
+            nodes_for(
+                fitting
+                    .iter()
+                    .filter_map(|r| r.mig.get(profile))
+                    .map(|&n| u64::from(n))
+                    .sum(),
+                u64::from(per_node),
+            )
+        }))
+        .chain(allocatable.extended.iter().map(|(name, &per_node)| {
+            nodes_for(
+                fitting.iter().filter_map(|r| r.extended.get(name)).sum(),
+                per_node,
+            )
+        }))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+        let bound = (lower + lower / 2 + 1).min(fitting.len() as u64);
+        u32::try_from(bound).unwrap_or(u32::MAX)
+    }
 }
 
 /// A node that already exists or is in-flight. The solver treats it as
 /// pre-seeded capacity: pods can be placed on it, but it is not included
-/// in the output (it's already committed).
+/// in the new nodes of the output (it's already committed). The pods placed
+/// on it are reported in [`SolveOutcome::existing_pods`].
 #[derive(Debug, Clone)]
 pub struct ExistingNode {
     pub resources: Resources,
     pub labels: BTreeMap<String, String>,
+    pub taints: Vec<Taint>,
 }
 
 /// A node the solver decided to provision.
@@ -70,12 +161,149 @@ pub enum PlacementSolution {
     },
 }
 
+/// What a new node's price is measured against when comparing offerings.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CostObjective {
+    /// Hourly cost per vCPU offered.
+    #[default]
+    PerVcpu,
+    /// Hourly cost of the node, whatever its size.
+    HourlyCost,
+    /// Price-performance: hourly cost per share of the pending demand the
+    /// node would actually hold, in whichever of CPU and memory runs out
+    /// first. Memory-heavy pods are priced by the GiB they use and
+    /// CPU-heavy pods by the vCPU, and capacity beyond the demand counts
+    /// for nothing.
+    PricePerformance,
+}
+
+/// Tuning for the placement objective, set per pool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolveOptions {
+    /// How much a new node's expected idle capacity counts against it. At 1
+    /// offerings are compared by cost per CPU the remaining demand would
+    /// actually use, so a thin tail of pods gets a small node instead of a
+    /// big, cheap-per-CPU one that would sit mostly empty and be awkward to
+    /// consolidate later. 0 compares by cost per CPU alone.
+    pub fragmentation_penalty: f64,
+    /// Share of a new node's CPU and memory the solver packs pods into, in
+    /// (0, 1]. The rest is left free for restarts and bursts. A pod too big
+    /// for that share still gets a node of its own. Existing and in-flight
+    /// capacity is used as-is.
+    pub target_utilization: f64,
+    /// How much an offering's [`Offering::interruption_risk`] counts against
+    /// its price: cost is scaled by `1 + interruption_penalty * risk`. Raise
+    /// it for long-running workloads so the cheapest spot capacity isn't
+    /// always chosen. 0 compares on price alone.
+    pub interruption_penalty: f64,
+    pub cost_objective: CostObjective,
+    /// Most the new nodes of one solve may add to the hourly bill. Pods
+    /// that would need more are left unmet. `None` means no cap.
+    pub max_additional_cost_per_hour: Option<f64>,
+    /// Most new nodes one solve may propose. Pods that would need more are
+    /// left unmet. `None` means no cap.
+    pub max_new_nodes: Option<u32>,
+    /// When to stop placing pods. Pods not reached by then are left unmet,
+    /// so a solve that runs long gives back what it has instead of holding
+    /// up the reconcile. `None` runs to completion.
+    pub deadline: Option<Instant>,
+    /// Instance-type patterns (`ccx*`, `gpu-*`; see
+    /// [`InstanceType::matches`]) new nodes must match one of. Empty allows
+    /// every type.
+    pub include_instance_types: Vec<String>,
+    /// Instance-type patterns new nodes must match none of. Wins over
+    /// `include_instance_types`.
+    pub exclude_instance_types: Vec<String>,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        Self {
+            fragmentation_penalty: 0.0,
+            target_utilization: 1.0,
+            interruption_penalty: 0.0,
+            cost_objective: CostObjective::PerVcpu,
+            max_additional_cost_per_hour: None,
+            max_new_nodes: None,
+            deadline: None,
+            include_instance_types: Vec::new(),
+            exclude_instance_types: Vec::new(),
+        }
+    }
+}
+
+impl SolveOptions {
+    /// The part of `allocatable` pods are packed into on a new node.
+    fn usable(&self, allocatable: &Resources) -> Resources {
+        let derate = |v: u64| (v as f64 * self.target_utilization.clamp(0.0, 1.0)) as u64;
+        Resources {
+            cpu_millis: derate(allocatable.cpu_millis),
+            memory_mib: derate(allocatable.memory_mib),
+            ..allocatable.clone()
+        }
+    }
+
+    /// Whether new nodes of `instance_type` may be proposed at all.
+    pub fn admits(&self, instance_type: &InstanceType) -> bool {
+        (self.include_instance_types.is_empty()
+            || self
+                .include_instance_types
+                .iter()
+                .any(|p| instance_type.matches(p)))
+            && !self
+                .exclude_instance_types
+                .iter()
+                .any(|p| instance_type.matches(p))
+    }
+}
+
+/// How a solve went, for logs and metrics.
+///
+/// The greedy solver has no time limit or optimality gap to report: it
+/// always runs to completion, and its running time grows with
+/// `candidates_scored`, so that and `duration` are what to watch as a
+/// cluster grows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolveStats {
+    /// Name of the [`SolverBackend`] that ran.
+    pub backend: &'static str,
+    pub duration: Duration,
+    pub demands: usize,
+    pub offerings: usize,
+    /// Pre-seeded nodes (in-flight or spare cluster capacity).
+    pub existing: usize,
+    /// Node/offering candidates scored across every pod.
+    pub candidates_scored: usize,
+    /// Offerings another offering beats on every resource and on cost. They
+    /// are only scored once everything dominating them is used up.
+    pub dominated: usize,
+    /// Distinct demand shapes among `demands` (see [`demand_classes`]).
+    pub demand_classes: usize,
+    /// Whether [`SolveOptions::deadline`] passed before every pod was
+    /// considered.
+    pub timed_out: bool,
+    pub new_nodes: usize,
+    pub unmet: usize,
+}
+
+/// A [`PlacementSolution`] with the [`SolveStats`] of the run behind it.
+#[derive(Debug, PartialEq)]
+pub struct SolveOutcome {
+    pub solution: PlacementSolution,
+    /// Pods placed on each of the solve's existing nodes, in the order they
+    /// were passed.
+    pub existing_pods: Vec<Vec<PodId>>,
+    pub stats: SolveStats,
+}
+
 /// A candidate node that has been activated by the scheduler.
 struct ActiveNode {
     /// Index into the `bounded` slice this node was created from.
     /// For pre-seeded nodes this is 0 and must never be read.
     type_idx: usize,
-    /// Total resources this node provides (used for scoring).
+    /// Resources this node provides to pods, DaemonSet overhead and
+    /// target-utilization headroom aside (used for scoring).
     total_resources: Resources,
     /// Remaining resources on this node.
     remaining: Resources,
@@ -84,6 +312,23 @@ struct ActiveNode {
     /// Topology values for this node (e.g. zone, region) — copied from
     /// `BoundedOffering.labels`.
     topo: BTreeMap<String, String>,
+    taints: Vec<Taint>,
+    /// [`demand_classes`] of the pods placed here, sorted.
+    classes: Vec<usize>,
+}
+
+impl ActiveNode {
+    /// Whether `self` and `other` are copies of the same node in the same
+    /// state: every pod would score them alike, so only the first needs
+    /// scoring. Pods are compared by class, since affinity only sees their
+    /// labels.
+    fn is_twin(&self, other: &ActiveNode) -> bool {
+        self.remaining == other.remaining
+            && self.total_resources == other.total_resources
+            && self.classes == other.classes
+            && self.topo == other.topo
+            && self.taints == other.taints
+    }
 }
 
 /// Tracks how many instances of each type (and type_group) have been activated.
@@ -137,6 +382,163 @@ impl InstanceBudget {
     }
 }
 
+/// Whether a node with topology labels `topo` lies in a zone `pod` may use
+/// and matches its nodeSelector and required node affinity.
+fn zone_allowed(pod: &PodResources, topo: &BTreeMap<String, String>) -> bool {
+    pod.allows_zone(topo.get(ZONE_LABEL).map(String::as_str)) && pod.allows_labels(topo)
+}
+
+/// Whether `a` is at least as good a new node as `b` for any pod: the same
+/// provider, location, pool labels and taints, the same value for every
+/// label key in `selected` (the keys pending pods select nodes by), at least
+/// as much of every resource, and no more cost, interruption risk or
+/// reliability penalty. Exact equals are broken by position, so two
+/// identical offerings don't dominate each other.
+fn dominates(
+    a: (usize, &BoundedOffering),
+    b: (usize, &BoundedOffering),
+    selected: &BTreeSet<&str>,
+) -> bool {
+    let ((ai, a), (bi, b)) = (a, b);
+    let (ao, bo) = (&a.offering, &b.offering);
+    if ai == bi
+        || ao.provider != bo.provider
+        || ao.location != bo.location
+        || pool_labels(a) != pool_labels(b)
+        || selected
+            .iter()
+            .any(|k| a.labels.get(*k) != b.labels.get(*k))
+        || a.taints != b.taints
+        || ao.cost_per_hour > bo.cost_per_hour
+        || ao.interruption_risk > bo.interruption_risk
+        || a.penalty > b.penalty
+    {
+        return false;
+    }
+    let (a_alloc, b_alloc) = (a.allocatable(), b.allocatable());
+    if !a_alloc.satisfies(&b_alloc) {
+        return false;
+    }
+    let equal = a_alloc == b_alloc
+        && ao.cost_per_hour == bo.cost_per_hour
+        && ao.interruption_risk == bo.interruption_risk
+        && a.penalty == b.penalty;
+    !equal || ai < bi
+}
+
+/// `bo`'s labels that come from its pool rather than from the offering
+/// itself (instance type, arch, GPU product, topology), which always differ
+/// between instance types. A pool label overriding one of those counts as
+/// the pool's.
+fn pool_labels(bo: &BoundedOffering) -> BTreeMap<&str, &str> {
+    let own = bo.offering.labels();
+    bo.labels
+        .iter()
+        .filter(|&(k, v)| own.get(k) != Some(v))
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect()
+}
+
+/// For each admitted offering, the admitted offerings that dominate it (see
+/// [`dominates`]). An offering a pod could select by one of its own labels,
+/// e.g. its instance type, stays a candidate unless its dominator carries
+/// the same value.
+fn dominators(
+    bounded: &[BoundedOffering],
+    admitted: &[bool],
+    demands: &[PodResources],
+) -> Vec<Vec<usize>> {
+    let selected: BTreeSet<&str> = demands
+        .iter()
+        .flat_map(|d| {
+            d.node_selector.keys().map(String::as_str).chain(
+                d.node_affinity
+                    .iter()
+                    .flat_map(|term| term.match_expressions.iter().flatten())
+                    .map(|e| e.key.as_str()),
+            )
+        })
+        .collect();
+    bounded
+        .iter()
+        .enumerate()
+        .map(|(bi, b)| {
+            if !admitted[bi] {
+                return vec![];
+            }
+            bounded
+                .iter()
+                .enumerate()
+                .filter(|&(ai, a)| admitted[ai] && dominates((ai, a), (bi, b), &selected))
+                .map(|(ai, _)| ai)
+                .collect()
+        })
+        .collect()
+}
+
+/// Whether two demands are interchangeable: everything but the pod's
+/// identity matches, so the solver would treat them exactly alike.
+fn same_shape(a: &PodResources, b: &PodResources) -> bool {
+    // Destructured so a new field can't be forgotten here.
+    let PodResources {
+        id: _,
+        uid: _,
+        resources,
+        pool,
+        pod_labels,
+        affinity_constraints,
+        zones,
+        tolerations,
+        node_selector,
+        node_affinity,
+        priority,
+        unmet_penalty,
+    } = a;
+    *resources == b.resources
+        && *pool == b.pool
+        && *pod_labels == b.pod_labels
+        && *affinity_constraints == b.affinity_constraints
+        && *zones == b.zones
+        && *tolerations == b.tolerations
+        && *node_selector == b.node_selector
+        && *node_affinity == b.node_affinity
+        && *priority == b.priority
+        && *unmet_penalty == b.unmet_penalty
+}
+
+/// Group `demands` into classes of interchangeable pods (e.g. the replicas
+/// of one Deployment). Returns each demand's class and the class count.
+fn demand_classes(demands: &[PodResources]) -> (Vec<usize>, usize) {
+    // First demand of each class.
+    let mut representatives: Vec<usize> = Vec::new();
+    let classes = demands
+        .iter()
+        .enumerate()
+        .map(|(i, d)| {
+            representatives
+                .iter()
+                .position(|&r| same_shape(&demands[r], d))
+                .unwrap_or_else(|| {
+                    representatives.push(i);
+                    representatives.len() - 1
+                })
+        })
+        .collect();
+    (classes, representatives.len())
+}
+
+/// Whether a node whose hardware is `node` carries the GPU model `pod` asks
+/// for. Pods naming no model take any node. Checked on its own rather than
+/// left to `Resources::satisfies`, since a pod can pin a model through its
+/// nodeSelector without requesting a GPU, and a node's model is fixed even
+/// once its GPUs are all reserved.
+fn gpu_model_allowed(pod: &PodResources, node: &Resources) -> bool {
+    pod.resources
+        .gpu_model
+        .as_ref()
+        .is_none_or(|model| node.gpu_model.as_ref() == Some(model))
+}
+
 /// Score an existing active node for placing `pod`. Returns `None` if the node
 /// fails the filter (capacity, required affinity/anti-affinity), or `Some(score)`
 /// where higher is better.
@@ -148,7 +550,11 @@ fn score_existing_node(
     placed_demand_indices: &[Vec<usize>],
     all_placed: &[bool],
 ) -> Option<f64> {
-    if !node.remaining.satisfies(&pod.resources) {
+    if !node.remaining.satisfies(&pod.resources)
+        || !gpu_model_allowed(pod, &node.total_resources)
+        || !zone_allowed(pod, &node.topo)
+        || !pod.tolerates(&node.taints)
+    {
         return None;
     }
     if check_anti_affinity_required(pod, &node.topo, active_nodes, demands, placed_demand_indices)
@@ -170,17 +576,55 @@ fn score_existing_node(
     // Existing nodes have zero marginal cost — always prefer them over
     // activating a new node.  Tiebreak by tightness (prefer filling fuller
     // nodes first for better bin-packing).  Use the most-constrained
-    // dimension (CPU or memory) so memory-bottlenecked nodes are also
-    // preferred when nearly full.
-    let cpu_frac = node.remaining.cpu as f64 / node.total_resources.cpu.max(1) as f64;
+    // dimension (CPU, memory or disk) so memory- or disk-bottlenecked nodes
+    // are also preferred when nearly full.
+    let cpu_frac =
+        node.remaining.cpu_millis as f64 / node.total_resources.cpu_millis.max(1) as f64;
     let mem_frac =
         node.remaining.memory_mib as f64 / node.total_resources.memory_mib.max(1) as f64;
-    let remaining_frac = cpu_frac.max(mem_frac);
+    let disk_frac = match (
+        node.remaining.ephemeral_storage_gib,
+        node.total_resources.ephemeral_storage_gib,
+    ) {
+        (Some(remaining), Some(total)) if pod.resources.ephemeral_storage_gib.is_some() => {
+            remaining as f64 / total.max(1) as f64
+        }
+        _ => 0.0,
+    };
+    let remaining_frac = cpu_frac.max(mem_frac).max(disk_frac);
     Some(affinity * 1000.0 + 100.0 - remaining_frac)
 }
 
+/// Fraction of `allocatable` the not-yet-placed demand could fill, in its
+/// most-used dimension. An estimate: some of that demand may go elsewhere.
+fn expected_fill(allocatable: &Resources, unplaced: &Resources) -> f64 {
+    let frac = |demand: u64, capacity: u64| {
+        if capacity == 0 {
+            0.0
+        } else {
+            (demand as f64 / capacity as f64).min(1.0)
+        }
+    };
+    frac(unplaced.cpu_millis, allocatable.cpu_millis)
+        .max(frac(unplaced.memory_mib, allocatable.memory_mib))
+}
+
+/// Share of the `unplaced` demand a node with `usable` capacity would hold,
+/// limited by whichever of CPU and memory runs out first.
+fn demand_share(usable: &Resources, unplaced: &Resources) -> f64 {
+    let share = |capacity: u64, demand: u64| {
+        if demand == 0 {
+            1.0
+        } else {
+            (capacity as f64 / demand as f64).min(1.0)
+        }
+    };
+    share(usable.cpu_millis, unplaced.cpu_millis).min(share(usable.memory_mib, unplaced.memory_mib))
+}
+
 /// Score a new offering for placing `pod`. Returns `None` if the offering fails
 /// the filter (resources, budget, required affinity/anti-affinity), or `Some(score)`.
+#[allow(clippy::too_many_arguments)]
 fn score_new_offering(
     pod: &PodResources,
     bo: &BoundedOffering,
@@ -191,8 +635,14 @@ fn score_new_offering(
     demands: &[PodResources],
     placed_demand_indices: &[Vec<usize>],
     all_placed: &[bool],
+    unplaced: &Resources,
+    options: &SolveOptions,
 ) -> Option<f64> {
-    if !bo.offering.satisfies(&pod.resources) {
+    if !bo.allocatable().satisfies(&pod.resources)
+        || !gpu_model_allowed(pod, &bo.offering.resources)
+        || !zone_allowed(pod, &bo.labels)
+        || !pod.tolerates(&bo.taints)
+    {
         return None;
     }
     if !budget.can_activate(type_idx, bounded) {
@@ -214,11 +664,26 @@ fn score_new_offering(
     }
     let affinity =
         preferred_affinity_score(pod, &bo.labels, active_nodes, demands, placed_demand_indices);
-    // Score by cost-per-CPU so the solver prefers cost-efficient offerings
-    // over tiny cheap ones.  Larger nodes with good cost-per-CPU ratios pack
-    // more pods and produce fewer nodes.
-    let cost_per_cpu = bo.offering.cost_per_hour / bo.offering.resources.cpu.max(1) as f64;
-    Some(affinity * 1000.0 - cost_per_cpu)
+    // By default score by cost-per-CPU so the solver prefers cost-efficient
+    // offerings over tiny cheap ones.  Larger nodes with good cost-per-CPU
+    // ratios pack more pods and produce fewer nodes.
+    // Unreliable offerings look proportionally more expensive.
+    let usable = options.usable(&bo.allocatable());
+    let cost = match options.cost_objective {
+        CostObjective::PerVcpu => {
+            bo.offering.cost_per_hour / bo.offering.resources.vcpus().max(1) as f64
+        }
+        CostObjective::HourlyCost => bo.offering.cost_per_hour,
+        CostObjective::PricePerformance => {
+            bo.offering.cost_per_hour / demand_share(&usable, unplaced).max(f64::EPSILON)
+        }
+    };
+    // Nodes the remaining demand would leave mostly idle look more expensive,
+    // up to cost per used CPU at a penalty of 1.
+    let fill = expected_fill(&usable, unplaced).max(f64::EPSILON);
+    let fragmentation = 1.0 + options.fragmentation_penalty * (1.0 / fill - 1.0);
+    let interruption = 1.0 + options.interruption_penalty * bo.offering.interruption_risk;
+    Some(affinity * 1000.0 - cost * (1.0 + bo.penalty) * fragmentation * interruption)
 }
 
 /// Filter-score scheduler. For each demand (sorted cheapest-fit-first),
@@ -227,36 +692,133 @@ fn score_new_offering(
 /// 1. **Filter**: eliminate offerings that can't satisfy resources.
 /// 2. **Score**: rank by cost (cheapest first), affinity, bin-pack tightness.
 /// 3. **Place**: assign demand to best candidate.
-#[instrument(skip_all, fields(demands = demands.len(), bounded_offerings = bounded.len(), existing = existing.len()))]
 pub fn solve(
     demands: &[PodResources],
     bounded: &[BoundedOffering],
     existing: &[ExistingNode],
 ) -> PlacementSolution {
+    solve_with_quotas(demands, bounded, existing, &mut QuotaBudget::unlimited())
+}
+
+/// [`solve`], never activating a node that would exceed the provider quota
+/// left in `quotas`. Activated nodes are deducted from `quotas`, so pools
+/// solved in turn share it.
+pub fn solve_with_quotas(
+    demands: &[PodResources],
+    bounded: &[BoundedOffering],
+    existing: &[ExistingNode],
+    quotas: &mut QuotaBudget,
+) -> PlacementSolution {
+    solve_packed(
+        demands,
+        bounded,
+        existing,
+        quotas,
+        &SolveOptions::default(),
+        Packing::Scored,
+    )
+    .solution
+}
+
+/// How a pod's node is picked among the candidates that pass the filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Packing {
+    /// Score every active node and offering and take the best.
+    Scored,
+    /// Take the first active node the pod fits on; score offerings only
+    /// when there is none.
+    FirstFit,
+}
+
+impl Packing {
+    fn backend_name(self) -> &'static str {
+        match self {
+            Packing::Scored => ScoredSolver.name(),
+            Packing::FirstFit => FirstFitSolver.name(),
+        }
+    }
+}
+
+#[instrument(skip_all, fields(demands = demands.len(), bounded_offerings = bounded.len(), existing = existing.len(), ?packing))]
+fn solve_packed(
+    demands: &[PodResources],
+    bounded: &[BoundedOffering],
+    existing: &[ExistingNode],
+    quotas: &mut QuotaBudget,
+    options: &SolveOptions,
+    packing: Packing,
+) -> SolveOutcome {
+    let started = Instant::now();
+    let mut stats = SolveStats {
+        backend: packing.backend_name(),
+        demands: demands.len(),
+        offerings: bounded.len(),
+        existing: existing.len(),
+        ..SolveStats::default()
+    };
     if demands.is_empty() {
         debug!("no demands to solve");
-        return PlacementSolution::NoDemands;
+        return SolveOutcome {
+            solution: PlacementSolution::NoDemands,
+            existing_pods: vec![Vec::new(); existing.len()],
+            stats,
+        };
     }
     if bounded.is_empty() {
         warn!(
             demands = demands.len(),
             "no offerings available, all demands will be unmet"
         );
-        return PlacementSolution::IncompletePlacement {
-            nodes: vec![],
-            unmet: demands.to_vec(),
+        stats.unmet = demands.len();
+        stats.duration = started.elapsed();
+        return SolveOutcome {
+            solution: PlacementSolution::IncompletePlacement {
+                nodes: vec![],
+                unmet: demands.to_vec(),
+            },
+            existing_pods: vec![Vec::new(); existing.len()],
+            stats,
         };
     }
 
     let offerings: Vec<Offering> = bounded.iter().map(|bo| bo.offering.clone()).collect();
+    // Banned instance types are never candidates for a new node.
+    let admitted: Vec<bool> = bounded
+        .iter()
+        .map(|bo| options.admits(&bo.offering.instance_type))
+        .collect();
+    let dominators = dominators(bounded, &admitted, demands);
+    stats.dominated = dominators.iter().filter(|d| !d.is_empty()).count();
+    if stats.dominated > 0 {
+        debug!(
+            dominated = stats.dominated,
+            "skipping dominated offerings while their dominators have room"
+        );
+    }
+
+    let (class_of, class_count) = demand_classes(demands);
+    stats.demand_classes = class_count;
 
-    // Sort demands largest-first (first-fit-decreasing bin packing).
+    // Sort demands highest-priority, then largest, first
+    // (first-fit-decreasing bin packing). When budget or capacity runs out,
+    // it's the low-priority pods left over.
     let mut demand_order: Vec<usize> = (0..demands.len()).collect();
     demand_order.sort_by(|&a, &b| {
         let ra = &demands[a].resources;
         let rb = &demands[b].resources;
-        // Primary: CPU descending, secondary: memory descending.
-        rb.cpu.cmp(&ra.cpu).then(rb.memory_mib.cmp(&ra.memory_mib))
+        // Then highest unmet penalty, CPU descending, memory descending,
+        // then ephemeral storage descending so scratch-heavy pods claim the
+        // larger disks first.
+        let penalty = |i: usize| demands[i].unmet_penalty.unwrap_or(f64::INFINITY);
+        demands[b]
+            .priority
+            .cmp(&demands[a].priority)
+            .then(penalty(b).total_cmp(&penalty(a)))
+            .then(rb.cpu_millis.cmp(&ra.cpu_millis))
+            .then(rb.memory_mib.cmp(&ra.memory_mib))
+            .then(rb.ephemeral_storage_gib.cmp(&ra.ephemeral_storage_gib))
+            // Keeps each class's pods together.
+            .then(class_of[a].cmp(&class_of[b]))
     });
 
     // Pre-seed active nodes from existing in-flight capacity.
@@ -270,6 +832,8 @@ pub fn solve(
             remaining: e.resources.clone(),
             pods: Vec::new(),
             topo: e.labels.clone(),
+            taints: e.taints.clone(),
+            classes: Vec::new(),
         })
         .collect();
     // For each active node, which demand indices have been placed on it.
@@ -278,9 +842,27 @@ pub fn solve(
     let mut budget = InstanceBudget::new(bounded);
     let mut unmet: Vec<PodResources> = Vec::new();
     let mut all_placed: Vec<bool> = vec![false; demands.len()];
+    let mut added_cost = 0.0;
+    // Classes a pod has already failed to place in. Failing changes nothing,
+    // so the rest of the class would fail the same way and isn't scored.
+    let mut class_unmet = vec![false; class_count];
+    // Demand still to be placed, this pod included.
+    let mut unplaced = Resources {
+        cpu_millis: demands.iter().map(|d| d.resources.cpu_millis).sum(),
+        memory_mib: demands.iter().map(|d| d.resources.memory_mib).sum(),
+        ..Resources::default()
+    };
 
     for &demand_idx in &demand_order {
         let pod = &demands[demand_idx];
+        if !stats.timed_out && options.deadline.is_some_and(|d| Instant::now() >= d) {
+            stats.timed_out = true;
+        }
+        if stats.timed_out || class_unmet[class_of[demand_idx]] {
+            unplaced.saturating_subtract(&pod.resources);
+            unmet.push(pod.clone());
+            continue;
+        }
 
         // Score both existing nodes and new offerings in one pass so that
         // preferred affinity/anti-affinity can correctly prefer a new node
@@ -292,7 +874,15 @@ pub fn solve(
 
         let mut best: Option<(Candidate, f64)> = None;
 
+        // Nodes scored for this pod; later twins of these are skipped, as
+        // they can't score higher than the first (ties go to the earlier).
+        let mut scored: Vec<usize> = Vec::new();
         for (node_idx, node) in active_nodes.iter().enumerate() {
+            if scored.iter().any(|&s| active_nodes[s].is_twin(node)) {
+                continue;
+            }
+            scored.push(node_idx);
+            stats.candidates_scored += 1;
             if let Some(score) = score_existing_node(
                 pod,
                 node,
@@ -303,10 +893,38 @@ pub fn solve(
             )
                 && best.as_ref().is_none_or(|(_, s)| score > *s) {
                     best = Some((Candidate::Existing(node_idx), score));
+                    if packing == Packing::FirstFit {
+                        break;
+                    }
                 }
         }
 
+        let new_nodes_left = options
+            .max_new_nodes
+            .is_none_or(|max| active_nodes.len() - pre_seeded_count < max as usize);
         for (type_idx, bo) in bounded.iter().enumerate() {
+            if !new_nodes_left || packing == Packing::FirstFit && best.is_some() {
+                break;
+            }
+            if !admitted[type_idx] || !quotas.allows(&bo.offering) {
+                continue;
+            }
+            if dominators[type_idx]
+                .iter()
+                .any(|&d| budget.can_activate(d, bounded) && quotas.allows(&bounded[d].offering))
+            {
+                continue;
+            }
+            if options
+                .max_additional_cost_per_hour
+                .is_some_and(|cap| added_cost + bo.offering.cost_per_hour > cap)
+                || pod
+                    .unmet_penalty
+                    .is_some_and(|worth| bo.offering.cost_per_hour > worth)
+            {
+                continue;
+            }
+            stats.candidates_scored += 1;
             if let Some(score) = score_new_offering(
                 pod,
                 bo,
@@ -317,11 +935,14 @@ pub fn solve(
                 demands,
                 &placed_demand_indices,
                 &all_placed,
+                &unplaced,
+                options,
             )
                 && best.as_ref().is_none_or(|(_, s)| score > *s) {
                     best = Some((Candidate::New(type_idx), score));
                 }
         }
+        unplaced.saturating_subtract(&pod.resources);
 
         match best {
             Some((Candidate::Existing(node_idx), _)) => {
@@ -329,6 +950,7 @@ pub fn solve(
                     &mut active_nodes[node_idx],
                     &mut placed_demand_indices[node_idx],
                     demand_idx,
+                    class_of[demand_idx],
                     pod,
                 );
                 all_placed[demand_idx] = true;
@@ -336,10 +958,12 @@ pub fn solve(
             Some((Candidate::New(type_idx), _)) => {
                 let bo = &bounded[type_idx];
                 budget.activate(type_idx, bounded);
+                quotas.consume(&bo.offering);
+                added_cost += bo.offering.cost_per_hour;
 
-                let total_resources = bo.offering.resources.clone();
+                let total_resources = options.usable(&bo.allocatable());
                 let mut remaining = total_resources.clone();
-                remaining.subtract(&pod.resources);
+                remaining.saturating_subtract(&pod.resources);
 
                 active_nodes.push(ActiveNode {
                     type_idx,
@@ -347,26 +971,36 @@ pub fn solve(
                     remaining,
                     pods: vec![pod.id.clone()],
                     topo: bo.labels.clone(),
+                    taints: bo.taints.clone(),
+                    classes: vec![class_of[demand_idx]],
                 });
                 placed_demand_indices.push(vec![demand_idx]);
                 all_placed[demand_idx] = true;
             }
             None => {
                 debug!(pod = %pod.id, "pod unschedulable — no offering fits or budget exhausted");
+                class_unmet[class_of[demand_idx]] = true;
                 unmet.push(pod.clone());
             }
         }
     }
+    if stats.timed_out {
+        warn!(
+            considered = demands.len() - unmet.len(),
+            demands = demands.len(),
+            "solve deadline passed, leaving remaining pods unmet"
+        );
+    }
 
     let new_nodes = &active_nodes[pre_seeded_count..];
     let total_placed: usize = new_nodes.iter().map(|n| n.pods.len()).sum();
-    let total_cpu_capacity: u32 = new_nodes
+    let total_cpu_capacity: u64 = new_nodes
         .iter()
-        .map(|n| n.total_resources.cpu)
+        .map(|n| n.total_resources.cpu_millis)
         .sum();
-    let total_cpu_used: u32 = new_nodes
+    let total_cpu_used: u64 = new_nodes
         .iter()
-        .map(|n| n.total_resources.cpu - n.remaining.cpu)
+        .map(|n| n.total_resources.cpu_millis - n.remaining.cpu_millis)
         .sum();
     let utilisation_pct = if total_cpu_capacity > 0 {
         (total_cpu_used as f64 / total_cpu_capacity as f64) * 100.0
@@ -375,20 +1009,20 @@ pub fn solve(
     };
 
     // Per-type breakdown (new nodes only).
-    let mut type_counts: BTreeMap<&str, (u32, u32, u32)> = BTreeMap::new(); // (nodes, pods, cpu_used)
+    let mut type_counts: BTreeMap<&str, (u32, u32, u64)> = BTreeMap::new(); // (nodes, pods, cpu_millis_used)
     for node in new_nodes {
         let name = offerings[node.type_idx].instance_type.0.as_str();
         let entry = type_counts.entry(name).or_insert((0, 0, 0));
         entry.0 += 1;
         entry.1 += node.pods.len() as u32;
-        entry.2 += node.total_resources.cpu - node.remaining.cpu;
+        entry.2 += node.total_resources.cpu_millis - node.remaining.cpu_millis;
     }
     for (name, (count, pods, cpu)) in &type_counts {
         debug!(
             instance_type = name,
             nodes = count,
             pods = pods,
-            cpu_used = cpu,
+            cpu_millis_used = cpu,
             "offering breakdown",
         );
     }
@@ -398,9 +1032,13 @@ pub fn solve(
         .map(|n| n.pods.len())
         .sum();
 
+    let mut active_nodes = active_nodes.into_iter();
+    let existing_pods: Vec<Vec<PodId>> = active_nodes
+        .by_ref()
+        .take(pre_seeded_count)
+        .map(|n| n.pods)
+        .collect();
     let nodes: Vec<PotentialNode> = active_nodes
-        .into_iter()
-        .skip(pre_seeded_count)
         .map(|n| PotentialNode {
             offering: offerings[n.type_idx].clone(),
             pods: n.pods,
@@ -408,7 +1046,13 @@ pub fn solve(
         .collect();
 
     let total_cost: f64 = nodes.iter().map(|n| n.offering.cost_per_hour).sum();
+    stats.new_nodes = nodes.len();
+    stats.unmet = unmet.len();
+    stats.duration = started.elapsed();
     info!(
+        backend = stats.backend,
+        duration_ms = stats.duration.as_millis() as u64,
+        candidates_scored = stats.candidates_scored,
         demands = demands.len(),
         offering_types = bounded.len(),
         pre_seeded = pre_seeded_count,
@@ -423,10 +1067,15 @@ pub fn solve(
         "solve complete",
     );
 
-    if unmet.is_empty() {
+    let solution = if unmet.is_empty() {
         PlacementSolution::AllPlaced(nodes)
     } else {
         PlacementSolution::IncompletePlacement { nodes, unmet }
+    };
+    SolveOutcome {
+        solution,
+        existing_pods,
+        stats,
     }
 }
 
@@ -434,11 +1083,14 @@ fn place_on_node(
     node: &mut ActiveNode,
     placed: &mut Vec<usize>,
     demand_idx: usize,
+    class: usize,
     pod: &PodResources,
 ) {
     node.remaining.subtract(&pod.resources);
     node.pods.push(pod.id.clone());
     placed.push(demand_idx);
+    let at = node.classes.partition_point(|&c| c <= class);
+    node.classes.insert(at, class);
 }
 
 #[cfg(test)]
@@ -448,6 +1100,7 @@ mod tests {
         AffinityConstraint, AffinityKind, AffinityStrength, GpuModel, InstanceType, Location,
         PodId, Region, Resources, Zone,
     };
+    use crate::testing;
 
     fn test_location() -> Location {
         Location {
@@ -456,7 +1109,7 @@ mod tests {
         }
     }
 
-    fn demand(name: &str, cpu: u32, memory_mib: u32) -> PodResources {
+    fn demand(name: &str, cpu: u32, memory_mib: u64) -> PodResources {
         PodResources {
             id: PodId {
                 namespace: "default".into(),
@@ -464,31 +1117,34 @@ mod tests {
             },
             uid: format!("uid-{name}"),
             resources: Resources {
-                cpu,
+                cpu_millis: u64::from(cpu) * 1000,
                 memory_mib,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             pool: None,
             pod_labels: BTreeMap::new(),
             affinity_constraints: vec![],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
         }
     }
 
-    fn offering(name: &str, cpu: u32, memory_mib: u32, cost_per_hour: f64) -> Offering {
-        Offering {
-            instance_type: InstanceType(name.into()),
-            resources: Resources {
-                cpu,
-                memory_mib,
-                ephemeral_storage_gib: None,
-                gpu: 0,
-                gpu_model: None,
-            },
-            cost_per_hour,
-            location: test_location(),
-        }
+    fn offering(name: &str, cpu: u32, memory_mib: u64, cost_per_hour: f64) -> Offering {
+        testing::offering(name)
+            .with_cpus(u64::from(cpu))
+            .with_memory_mib(memory_mib)
+            .with_cost(cost_per_hour)
+            .with_zone("fsn1-dc14")
+            .build()
     }
 
     fn bounded(o: Offering, max: u32) -> BoundedOffering {
@@ -497,6 +1153,9 @@ mod tests {
             max_instances: max,
             labels: BTreeMap::new(),
             type_group: None,
+            penalty: 0.0,
+            taints: vec![],
+            overhead: Resources::default(),
         }
     }
 
@@ -508,6 +1167,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn demand_bound_follows_total_demand() {
+        let bo = bounded(offering("cpx32", 4, 8192, 0.02), 0);
+        let few: Vec<_> = (0..3).map(|i| demand(&format!("p{i}"), 1, 1024)).collect();
+        // One node holds them all; slack allows one more.
+        assert_eq!(bo.demand_bound(&few), 2);
+
+        let many: Vec<_> = (0..100)
+            .map(|i| demand(&format!("p{i}"), 1, 1024))
+            .collect();
+        // 100 vCPUs over 4-vCPU nodes, plus half again.
+        assert_eq!(bo.demand_bound(&many), 38);
+
+        // Never more nodes than fitting demands, and none for demands that
+        // cannot fit at all.
+        let wide: Vec<_> = (0..5).map(|i| demand(&format!("p{i}"), 3, 1024)).collect();
+        assert_eq!(bo.demand_bound(&wide), 5);
+        assert_eq!(bo.demand_bound(&[demand("huge", 8, 1024)]), 0);
+    }
+
     #[test]
     fn empty_offerings() {
         let demands = vec![demand("pod-a", 2, 4096)];
@@ -523,7 +1202,7 @@ mod tests {
     #[test]
     fn single_demand_single_offering() {
         let demands = vec![demand("pod-a", 2, 4096)];
-        let offerings = vec![offering("cpx22", 2, 4096, 0.01)];
+        let offerings = [offering("cpx22", 2, 4096, 0.01)];
         assert_eq!(
             solve(&demands, &[bounded(offerings[0].clone(), 10)], &[]),
             PlacementSolution::AllPlaced(vec![PotentialNode {
@@ -554,7 +1233,7 @@ mod tests {
     fn gpu_demand(
         name: &str,
         cpu: u32,
-        memory_mib: u32,
+        memory_mib: u64,
         gpu: u32,
         model: GpuModel,
     ) -> PodResources {
@@ -565,36 +1244,50 @@ mod tests {
             },
             uid: format!("uid-{name}"),
             resources: Resources {
-                cpu,
+                cpu_millis: u64::from(cpu) * 1000,
                 memory_mib,
                 ephemeral_storage_gib: None,
                 gpu,
                 gpu_model: Some(model),
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             pool: None,
             pod_labels: BTreeMap::new(),
             affinity_constraints: vec![],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
         }
     }
 
     fn gpu_offering(
         name: &str,
         cpu: u32,
-        memory_mib: u32,
+        memory_mib: u64,
         gpu: u32,
         model: GpuModel,
         cost: f64,
     ) -> Offering {
         Offering {
             instance_type: InstanceType(name.into()),
+            provider: "fake".into(),
             resources: Resources {
-                cpu,
+                cpu_millis: u64::from(cpu) * 1000,
                 memory_mib,
                 ephemeral_storage_gib: None,
                 gpu,
                 gpu_model: Some(model),
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             cost_per_hour: cost,
+            interruption_risk: 0.0,
             location: test_location(),
         }
     }
@@ -605,7 +1298,7 @@ mod tests {
             gpu_demand("a100-pod", 4, 8192, 1, GpuModel::NvidiaA100),
             gpu_demand("t4-pod", 4, 8192, 1, GpuModel::NvidiaT4),
         ];
-        let offerings = vec![
+        let offerings = [
             gpu_offering("gpu-t4", 8, 16384, 1, GpuModel::NvidiaT4, 0.50),
             gpu_offering("gpu-a100", 8, 16384, 1, GpuModel::NvidiaA100, 2.00),
         ];
@@ -632,7 +1325,7 @@ mod tests {
     #[test]
     fn gpu_model_mismatch_leaves_pod_unmet() {
         let demands = vec![gpu_demand("a100-pod", 4, 8192, 1, GpuModel::NvidiaA100)];
-        let offerings = vec![gpu_offering(
+        let offerings = [gpu_offering(
             "gpu-t4",
             8,
             16384,
@@ -650,12 +1343,38 @@ mod tests {
         assert_eq!(unmet[0].id.name, "a100-pod");
     }
 
+    #[test]
+    fn gpu_model_pods_skip_in_flight_nodes_of_another_model() {
+        let demands = vec![
+            gpu_demand("a100-pod", 2, 4096, 1, GpuModel::NvidiaA100),
+            // Pinned to T4 nodes without requesting a GPU.
+            gpu_demand("t4-sidecar", 1, 1024, 0, GpuModel::NvidiaT4),
+        ];
+        let t4 = gpu_offering("gpu-t4", 8, 16384, 1, GpuModel::NvidiaT4, 0.50);
+        let a100 = gpu_offering("gpu-a100", 8, 16384, 1, GpuModel::NvidiaA100, 2.00);
+        // A T4 node is already on its way, with room to spare.
+        let existing = vec![ExistingNode {
+            resources: t4.resources.clone(),
+            labels: BTreeMap::new(),
+            taints: vec![],
+        }];
+
+        let result = solve(&demands, &[bounded(t4, 2), bounded(a100, 2)], &existing);
+        let PlacementSolution::AllPlaced(nodes) = result else {
+            panic!("expected AllPlaced, got {result:?}");
+        };
+        // The sidecar shares the in-flight T4 node; the A100 pod gets its own.
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].offering.instance_type.0, "gpu-a100");
+        assert_eq!(nodes[0].pods[0].name, "a100-pod");
+    }
+
     // ── Affinity test helpers ───────────────────────────────────────
 
     fn demand_with_anti_affinity(
         name: &str,
         cpu: u32,
-        memory_mib: u32,
+        memory_mib: u64,
         app_label: &str,
         topology_key: &str,
     ) -> PodResources {
@@ -672,7 +1391,7 @@ mod tests {
     fn demand_with_anti_affinity_strength(
         name: &str,
         cpu: u32,
-        memory_mib: u32,
+        memory_mib: u64,
         app_label: &str,
         topology_key: &str,
         strength: AffinityStrength,
@@ -684,11 +1403,14 @@ mod tests {
             },
             uid: format!("uid-{name}"),
             resources: Resources {
-                cpu,
+                cpu_millis: u64::from(cpu) * 1000,
                 memory_mib,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             pool: None,
             pod_labels: BTreeMap::from([("app".to_string(), app_label.to_string())]),
@@ -698,13 +1420,19 @@ mod tests {
                 topology_key: topology_key.to_string(),
                 match_labels: BTreeMap::from([("app".to_string(), app_label.to_string())]),
             }],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
         }
     }
 
     fn demand_with_affinity(
         name: &str,
         cpu: u32,
-        memory_mib: u32,
+        memory_mib: u64,
         app_label: &str,
         topology_key: &str,
     ) -> PodResources {
@@ -721,7 +1449,7 @@ mod tests {
     fn demand_with_affinity_strength(
         name: &str,
         cpu: u32,
-        memory_mib: u32,
+        memory_mib: u64,
         app_label: &str,
         topology_key: &str,
         strength: AffinityStrength,
@@ -733,11 +1461,14 @@ mod tests {
             },
             uid: format!("uid-{name}"),
             resources: Resources {
-                cpu,
+                cpu_millis: u64::from(cpu) * 1000,
                 memory_mib,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             pool: None,
             pod_labels: BTreeMap::from([("app".to_string(), app_label.to_string())]),
@@ -747,6 +1478,12 @@ mod tests {
                 topology_key: topology_key.to_string(),
                 match_labels: BTreeMap::from([("app".to_string(), app_label.to_string())]),
             }],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
         }
     }
 
@@ -760,6 +1497,9 @@ mod tests {
             max_instances: max,
             labels,
             type_group: None,
+            penalty: 0.0,
+            taints: vec![],
+            overhead: Resources::default(),
         }
     }
 
@@ -767,6 +1507,27 @@ mod tests {
         BTreeMap::from([("topology.kubernetes.io/zone".to_string(), zone.to_string())])
     }
 
+    #[test]
+    fn zone_pinned_pod_uses_offering_in_its_zone() {
+        let mut pinned = demand("db-0", 1, 1024);
+        pinned.zones = Some(vec!["zone-b".into()]);
+        let bounded_offerings = vec![
+            bounded_with_labels(offering("cpx22-a", 2, 4096, 0.01), 5, zone_labels("zone-a")),
+            bounded_with_labels(offering("cpx22-b", 2, 4096, 0.02), 5, zone_labels("zone-b")),
+        ];
+        let PlacementSolution::AllPlaced(nodes) = solve(&[pinned.clone()], &bounded_offerings, &[])
+        else {
+            panic!("expected AllPlaced");
+        };
+        assert_eq!(nodes[0].offering.instance_type.0, "cpx22-b");
+
+        pinned.zones = Some(vec!["zone-c".into()]);
+        assert!(matches!(
+            solve(&[pinned], &bounded_offerings, &[]),
+            PlacementSolution::IncompletePlacement { .. }
+        ));
+    }
+
     // ── Affinity unit tests ─────────────────────────────────────────
 
     #[test]
@@ -865,15 +1626,24 @@ mod tests {
             },
             uid: "uid-api-0".into(),
             resources: Resources {
-                cpu: 1,
+                cpu_millis: 1000,
                 memory_mib: 1024,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             pool: None,
             pod_labels: BTreeMap::from([("app".to_string(), "api".to_string())]),
             affinity_constraints: vec![],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
         });
 
         let bounded_offerings = vec![
@@ -922,11 +1692,18 @@ mod tests {
 
     /// Helper: compute CPU utilisation across all nodes.
     fn cpu_utilisation(nodes: &[PotentialNode], demands: &[PodResources]) -> f64 {
-        let total_capacity: u32 = nodes.iter().map(|n| n.offering.resources.cpu).sum();
-        let total_used: u32 = nodes
+        let total_capacity: u64 = nodes.iter().map(|n| n.offering.resources.cpu_millis).sum();
+        let total_used: u64 = nodes
             .iter()
             .flat_map(|n| &n.pods)
-            .map(|pid| demands.iter().find(|d| d.id == *pid).unwrap().resources.cpu)
+            .map(|pid| {
+                demands
+                    .iter()
+                    .find(|d| d.id == *pid)
+                    .unwrap()
+                    .resources
+                    .cpu_millis
+            })
             .sum();
         if total_capacity == 0 {
             return 0.0;
@@ -1175,7 +1952,7 @@ mod tests {
 
         // Verify no node is memory-overcommitted.
         for (i, node) in nodes.iter().enumerate() {
-            let mem_used: u32 = node
+            let mem_used: u64 = node
                 .pods
                 .iter()
                 .map(|pid| {
@@ -1223,6 +2000,9 @@ mod tests {
             max_instances: max,
             labels,
             type_group: Some(group.to_string()),
+            penalty: 0.0,
+            taints: vec![],
+            overhead: Resources::default(),
         }
     }
 
@@ -1468,11 +2248,14 @@ mod tests {
                 },
                 uid: format!("uid-web-{i}"),
                 resources: Resources {
-                    cpu: 1,
+                    cpu_millis: 1000,
                     memory_mib: 1024,
                     ephemeral_storage_gib: None,
                     gpu: 0,
                     gpu_model: None,
+                    arch: None,
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
                 },
                 pool: None,
                 pod_labels: BTreeMap::from([("app".to_string(), "web".to_string())]),
@@ -1490,6 +2273,12 @@ mod tests {
                         match_labels: BTreeMap::from([("app".to_string(), "web".to_string())]),
                     },
                 ],
+                zones: None,
+                tolerations: vec![],
+                node_selector: BTreeMap::new(),
+                node_affinity: vec![],
+                priority: 0,
+                unmet_penalty: None,
             })
             .collect();
 
@@ -1552,16 +2341,20 @@ mod tests {
 
     // ── Pre-seeded existing node tests ──────────────────────────────
 
-    fn existing(cpu: u32, memory_mib: u32) -> ExistingNode {
+    fn existing(cpu: u32, memory_mib: u64) -> ExistingNode {
         ExistingNode {
             resources: Resources {
-                cpu,
+                cpu_millis: u64::from(cpu) * 1000,
                 memory_mib,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             labels: zone_labels("zone-a"),
+            taints: vec![],
         }
     }
 
@@ -1580,6 +2373,486 @@ mod tests {
         assert!(nodes.is_empty(), "all demand absorbed by pre-seeded node");
     }
 
+    #[test]
+    fn pre_seeded_placements_are_reported_per_node() {
+        let demands = vec![demand("a", 1, 1024), demand("big", 8, 1024)];
+        let bounded_offerings = vec![bounded(offering("cpx22", 2, 4096, 0.01), 10)];
+        let tainted = ExistingNode {
+            taints: vec![Taint {
+                key: "gpu".into(),
+                effect: "NoSchedule".into(),
+                ..Default::default()
+            }],
+            ..existing(4, 8192)
+        };
+        let existing = vec![tainted, existing(2, 4096)];
+
+        let outcome = ScoredSolver.solve(
+            &demands,
+            &bounded_offerings,
+            &existing,
+            &mut QuotaBudget::unlimited(),
+            &SolveOptions::default(),
+        );
+        // The roomier node is skipped for its taint; the pod nothing fits is
+        // on neither.
+        assert_eq!(
+            outcome.existing_pods,
+            vec![vec![], vec![PodId::new("default", "a")]]
+        );
+    }
+
+    #[test]
+    fn first_fit_takes_the_first_node_that_fits() {
+        let demands = vec![demand("big", 3, 1024), demand("small", 1, 1024)];
+        let bounded_offerings = vec![bounded(offering("cpx32", 4, 8192, 0.02), 10)];
+        let existing = vec![existing(2, 4096)];
+        let pods_on_new_node = |backend: &dyn SolverBackend| {
+            let outcome = backend.solve(
+                &demands,
+                &bounded_offerings,
+                &existing,
+                &mut QuotaBudget::unlimited(),
+                &SolveOptions::default(),
+            );
+            assert_eq!(outcome.stats.backend, backend.name());
+            assert_eq!(outcome.stats.new_nodes, 1);
+            assert!(outcome.stats.candidates_scored > 0);
+            let PlacementSolution::AllPlaced(nodes) = outcome.solution else {
+                panic!("expected AllPlaced, got {:?}", outcome.solution);
+            };
+            assert_eq!(nodes.len(), 1);
+            nodes[0].pods.len()
+        };
+
+        // Scored packs the small pod next to the big one on the fuller new
+        // node; first-fit drops it on the pre-seeded node it finds first.
+        assert_eq!(pods_on_new_node(&ScoredSolver), 2);
+        assert_eq!(pods_on_new_node(&FirstFitSolver), 1);
+    }
+
+    #[test]
+    fn fragmentation_penalty_avoids_mostly_idle_nodes() {
+        let demands: Vec<_> = (0..3).map(|i| demand(&format!("p{i}"), 1, 1024)).collect();
+        let bounded_offerings = vec![
+            bounded(offering("small", 2, 4096, 0.02), 10),
+            bounded(offering("big", 16, 32768, 0.08), 10),
+        ];
+        let solve_with = |fragmentation_penalty: f64| {
+            let outcome = ScoredSolver.solve(
+                &demands,
+                &bounded_offerings,
+                &[],
+                &mut QuotaBudget::unlimited(),
+                &SolveOptions {
+                    fragmentation_penalty,
+                    ..SolveOptions::default()
+                },
+            );
+            let PlacementSolution::AllPlaced(nodes) = outcome.solution else {
+                panic!("expected AllPlaced, got {:?}", outcome.solution);
+            };
+            type_counts(&nodes)
+        };
+
+        // Cheapest per CPU, but 13 of its 16 CPUs would sit idle.
+        assert_eq!(solve_with(0.0), BTreeMap::from([("big".to_string(), 1)]));
+        assert_eq!(solve_with(1.0), BTreeMap::from([("small".to_string(), 2)]));
+    }
+
+    #[test]
+    fn target_utilization_leaves_headroom_on_new_nodes() {
+        let bounded_offerings = vec![bounded(offering("cpx32", 4, 8192, 0.02), 10)];
+        let options = SolveOptions {
+            target_utilization: 0.75,
+            ..SolveOptions::default()
+        };
+        let solve_with = |demands: &[PodResources], options: &SolveOptions| {
+            let outcome = ScoredSolver.solve(
+                demands,
+                &bounded_offerings,
+                &[],
+                &mut QuotaBudget::unlimited(),
+                options,
+            );
+            let PlacementSolution::AllPlaced(nodes) = outcome.solution else {
+                panic!("expected AllPlaced, got {:?}", outcome.solution);
+            };
+            nodes.iter().map(|n| n.pods.len()).collect::<Vec<_>>()
+        };
+
+        let small: Vec<_> = (0..4).map(|i| demand(&format!("p{i}"), 1, 1024)).collect();
+        assert_eq!(solve_with(&small, &SolveOptions::default()), vec![4]);
+        assert_eq!(solve_with(&small, &options), vec![3, 1]);
+        // Bigger than 75% of any offering, but still placed on its own.
+        assert_eq!(solve_with(&[demand("whole", 4, 1024)], &options), vec![1]);
+    }
+
+    #[test]
+    fn interruption_penalty_weighs_spot_risk_against_price() {
+        let spot = Offering {
+            interruption_risk: 0.5,
+            ..offering("spot", 4, 8192, 0.010)
+        };
+        let bounded_offerings = vec![
+            bounded(spot, 10),
+            bounded(offering("on-demand", 4, 8192, 0.014), 10),
+        ];
+        let demands = vec![demand("a", 2, 1024)];
+        let chosen = |interruption_penalty: f64| {
+            let outcome = ScoredSolver.solve(
+                &demands,
+                &bounded_offerings,
+                &[],
+                &mut QuotaBudget::unlimited(),
+                &SolveOptions {
+                    interruption_penalty,
+                    ..SolveOptions::default()
+                },
+            );
+            let PlacementSolution::AllPlaced(nodes) = outcome.solution else {
+                panic!("expected AllPlaced, got {:?}", outcome.solution);
+            };
+            nodes[0].offering.instance_type.0.clone()
+        };
+
+        assert_eq!(chosen(0.0), "spot");
+        // 0.010 * (1 + 1.0 * 0.5) = 0.015 > 0.014.
+        assert_eq!(chosen(1.0), "on-demand");
+    }
+
+    #[test]
+    fn hourly_cost_cap_leaves_excess_demand_unmet() {
+        let bounded_offerings = vec![bounded(offering("cpx22", 2, 4096, 0.01), 10)];
+        let demands: Vec<_> = (0..5).map(|i| demand(&format!("p{i}"), 2, 1024)).collect();
+        let outcome = ScoredSolver.solve(
+            &demands,
+            &bounded_offerings,
+            &[],
+            &mut QuotaBudget::unlimited(),
+            &SolveOptions {
+                max_additional_cost_per_hour: Some(0.035),
+                ..SolveOptions::default()
+            },
+        );
+        let PlacementSolution::IncompletePlacement { nodes, unmet } = outcome.solution else {
+            panic!("expected IncompletePlacement, got {:?}", outcome.solution);
+        };
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(unmet.len(), 2);
+        assert!(total_cost(&nodes) <= 0.035);
+    }
+
+    #[test]
+    fn max_new_nodes_caps_one_solve() {
+        let bounded_offerings = vec![bounded(offering("cpx22", 2, 4096, 0.01), 10)];
+        let demands: Vec<_> = (0..5).map(|i| demand(&format!("p{i}"), 2, 1024)).collect();
+        let outcome = ScoredSolver.solve(
+            &demands,
+            &bounded_offerings,
+            &[existing(2, 4096)],
+            &mut QuotaBudget::unlimited(),
+            &SolveOptions {
+                max_new_nodes: Some(2),
+                ..SolveOptions::default()
+            },
+        );
+        // One pod fills the existing node, which doesn't count.
+        let PlacementSolution::IncompletePlacement { nodes, unmet } = outcome.solution else {
+            panic!("expected IncompletePlacement, got {:?}", outcome.solution);
+        };
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(unmet.len(), 2);
+    }
+
+    #[test]
+    fn mig_slices_are_packed_by_profile() {
+        let mig = |name: &str, slices: u32, cost: f64| {
+            let mut o = offering(name, 32, 131_072, cost);
+            o.resources.mig = BTreeMap::from([("1g.5gb".to_string(), slices)]);
+            o
+        };
+        let bounded_offerings = vec![
+            bounded(offering("cpx52", 16, 32_768, 0.05), 10),
+            bounded(mig("a100-mig7", 7, 2.2), 10),
+            bounded(mig("a100x2-mig14", 14, 4.4), 10),
+        ];
+        let demands: Vec<_> = (0..9)
+            .map(|i| PodResources {
+                resources: Resources {
+                    mig: BTreeMap::from([("1g.5gb".to_string(), 1)]),
+                    ..demand(&format!("p{i}"), 1, 1024).resources
+                },
+                ..demand(&format!("p{i}"), 1, 1024)
+            })
+            .collect();
+
+        // Nine single-slice pods need two 7-slice nodes (plus slack); the
+        // CPU-only type is never a candidate.
+        assert_eq!(bounded_offerings[1].demand_bound(&demands), 4);
+        assert_eq!(bounded_offerings[0].demand_bound(&demands), 0);
+        let PlacementSolution::AllPlaced(nodes) = solve(&demands, &bounded_offerings, &[]) else {
+            panic!("expected AllPlaced");
+        };
+        assert_eq!(nodes.iter().map(|n| n.pods.len()).sum::<usize>(), 9);
+        assert!(
+            nodes
+                .iter()
+                .all(|n| n.offering.instance_type.0.contains("mig"))
+        );
+        assert!(total_cost(&nodes) <= 4.4 + 1e-9);
+    }
+
+    #[test]
+    fn extended_resources_are_honoured_generically() {
+        let mut fpga = offering("vt1", 8, 16_384, 1.2);
+        fpga.resources.extended = BTreeMap::from([("xilinx.com/u30".to_string(), 2)]);
+        let bounded_offerings = vec![
+            bounded(offering("cpx52", 16, 32_768, 0.05), 10),
+            bounded(fpga, 10),
+        ];
+        let transcode = |name: &str| PodResources {
+            resources: Resources {
+                extended: BTreeMap::from([("xilinx.com/u30".to_string(), 1)]),
+                ..demand(name, 1, 1024).resources
+            },
+            ..demand(name, 1, 1024)
+        };
+        let demands = vec![transcode("t1"), transcode("t2"), transcode("t3")];
+
+        let PlacementSolution::AllPlaced(nodes) = solve(&demands, &bounded_offerings, &[]) else {
+            panic!("expected AllPlaced");
+        };
+        // Two devices per node: three pods need two FPGA nodes.
+        assert_eq!(
+            type_counts(&nodes),
+            BTreeMap::from([("vt1".to_string(), 2)])
+        );
+    }
+
+    #[test]
+    fn dominated_offerings_wait_for_their_dominators() {
+        let bounded_offerings = vec![
+            // Smaller and dearer than cx32: not worth scoring while cx32
+            // has room.
+            bounded(offering("cpx32", 2, 4096, 0.03), 10),
+            bounded(offering("cx32", 4, 8192, 0.02), 1),
+        ];
+        let one = [demand("p0", 2, 1024)];
+        let outcome = ScoredSolver.solve(
+            &one,
+            &bounded_offerings,
+            &[],
+            &mut QuotaBudget::unlimited(),
+            &SolveOptions::default(),
+        );
+        assert_eq!(outcome.stats.dominated, 1);
+        assert_eq!(outcome.stats.candidates_scored, 1);
+
+        // cx32 is capped at one node, so the third pod falls back to the
+        // dominated type.
+        let demands: Vec<_> = (0..3).map(|i| demand(&format!("p{i}"), 2, 1024)).collect();
+        let PlacementSolution::AllPlaced(nodes) = solve(&demands, &bounded_offerings, &[]) else {
+            panic!("expected AllPlaced");
+        };
+        assert_eq!(
+            type_counts(&nodes),
+            BTreeMap::from([("cpx32".to_string(), 1), ("cx32".to_string(), 1)])
+        );
+    }
+
+    #[test]
+    fn identical_replicas_stop_being_scored_once_one_fails() {
+        let bounded_offerings = vec![bounded(offering("cpx22", 2, 4096, 0.01), 1)];
+        let mut demands: Vec<_> = (0..1000)
+            .map(|i| demand(&format!("web-{i}"), 2, 1024))
+            .collect();
+        demands.push(demand("batch", 1, 512));
+        let outcome = ScoredSolver.solve(
+            &demands,
+            &bounded_offerings,
+            &[],
+            &mut QuotaBudget::unlimited(),
+            &SolveOptions::default(),
+        );
+        assert_eq!(outcome.stats.demand_classes, 2);
+        // web-0 scores the offering; web-1 the new node and the spent
+        // offering. The other 998 replicas aren't scored, and `batch` scores
+        // the same two again.
+        assert_eq!(outcome.stats.candidates_scored, 1 + 2 + 2);
+        assert_eq!(outcome.stats.unmet, 1000);
+    }
+
+    #[test]
+    fn identical_node_copies_are_scored_once() {
+        let bounded_offerings = vec![bounded(offering("cpx32", 4, 8192, 0.02), 20)];
+        let demands: Vec<_> = (0..10).map(|i| demand(&format!("p{i}"), 3, 1024)).collect();
+        let outcome = ScoredSolver.solve(
+            &demands,
+            &bounded_offerings,
+            &[],
+            &mut QuotaBudget::unlimited(),
+            &SolveOptions::default(),
+        );
+        let PlacementSolution::AllPlaced(nodes) = &outcome.solution else {
+            panic!("expected AllPlaced, got {:?}", outcome.solution);
+        };
+        assert_eq!(nodes.len(), 10);
+        // Every new node is left in the same state, so each pod after the
+        // first scores one of them and the offering, not all of them.
+        assert_eq!(outcome.stats.candidates_scored, 1 + 9 * 2);
+    }
+
+    #[test]
+    fn passed_deadline_leaves_pods_unmet() {
+        let bounded_offerings = vec![bounded(offering("cpx32", 4, 8192, 0.02), 20)];
+        let demands: Vec<_> = (0..4).map(|i| demand(&format!("p{i}"), 1, 1024)).collect();
+        let outcome = ScoredSolver.solve(
+            &demands,
+            &bounded_offerings,
+            &[],
+            &mut QuotaBudget::unlimited(),
+            &SolveOptions {
+                deadline: Some(Instant::now()),
+                ..SolveOptions::default()
+            },
+        );
+        let PlacementSolution::IncompletePlacement { nodes, unmet } = &outcome.solution else {
+            panic!("expected IncompletePlacement, got {:?}", outcome.solution);
+        };
+        assert!(nodes.is_empty());
+        assert_eq!(unmet.len(), 4);
+        assert!(outcome.stats.timed_out);
+        assert_eq!(outcome.stats.candidates_scored, 0);
+    }
+
+    #[test]
+    fn instance_type_patterns_filter_new_nodes() {
+        let bounded_offerings = vec![
+            bounded(offering("cx22", 2, 4096, 0.005), 10),
+            bounded(offering("ccx13", 2, 8192, 0.02), 10),
+            bounded(offering("gpu-a100-1", 8, 65536, 2.0), 10),
+        ];
+        let demands = vec![demand("a", 1, 1024)];
+        let chosen = |options: SolveOptions| {
+            let outcome = ScoredSolver.solve(
+                &demands,
+                &bounded_offerings,
+                &[],
+                &mut QuotaBudget::unlimited(),
+                &options,
+            );
+            let PlacementSolution::AllPlaced(nodes) = outcome.solution else {
+                return None;
+            };
+            Some(nodes[0].offering.instance_type.0.clone())
+        };
+
+        assert_eq!(chosen(SolveOptions::default()).as_deref(), Some("cx22"));
+        // Shared-CPU types banned: the dedicated one is next cheapest.
+        let no_shared = SolveOptions {
+            exclude_instance_types: vec!["cx*".into()],
+            ..SolveOptions::default()
+        };
+        assert_eq!(chosen(no_shared).as_deref(), Some("ccx13"));
+        // Exclusions win over inclusions.
+        let gpu_only = SolveOptions {
+            include_instance_types: vec!["gpu-*".into(), "ccx".into()],
+            exclude_instance_types: vec!["ccx*".into()],
+            ..SolveOptions::default()
+        };
+        assert_eq!(chosen(gpu_only).as_deref(), Some("gpu-a100-1"));
+        let nothing = SolveOptions {
+            include_instance_types: vec!["cax*".into()],
+            ..SolveOptions::default()
+        };
+        assert_eq!(chosen(nothing), None);
+    }
+
+    #[test]
+    fn low_priority_pods_are_left_unmet_first() {
+        let bounded_offerings = vec![bounded(offering("cpx32", 4, 8192, 0.02), 1)];
+        let batch = demand("batch", 4, 1024);
+        let web = PodResources {
+            priority: 1000,
+            ..demand("web", 2, 1024)
+        };
+        let result = solve(&[batch, web], &bounded_offerings, &[]);
+        // Largest-first alone would give the only node to `batch`.
+        let PlacementSolution::IncompletePlacement { nodes, unmet } = result else {
+            panic!("expected IncompletePlacement, got {result:?}");
+        };
+        assert_eq!(nodes[0].pods, vec![PodId::new("default", "web")]);
+        assert_eq!(unmet[0].id.name, "batch");
+    }
+
+    #[test]
+    fn unmet_penalty_bounds_what_a_pod_is_worth() {
+        let bounded_offerings = vec![bounded(offering("cpx32", 4, 8192, 0.02), 1)];
+        let cheap = |name: &str, cpu: u32, penalty: f64| PodResources {
+            unmet_penalty: Some(penalty),
+            ..demand(name, cpu, 1024)
+        };
+
+        // Not worth a node costing 0.02/h, but free capacity is fine.
+        let thrifty = [cheap("thrifty", 1, 0.01)];
+        let result = solve(&thrifty, &bounded_offerings, &[]);
+        assert!(
+            matches!(result, PlacementSolution::IncompletePlacement { ref nodes, .. } if nodes.is_empty())
+        );
+        let result = solve(&thrifty, &bounded_offerings, &[existing(2, 4096)]);
+        assert_eq!(result, PlacementSolution::AllPlaced(vec![]));
+
+        // With one node to give, the pod with more riding on it wins.
+        let result = solve(
+            &[cheap("big", 4, 0.03), cheap("urgent", 2, 0.5)],
+            &bounded_offerings,
+            &[],
+        );
+        let PlacementSolution::IncompletePlacement { nodes, unmet } = result else {
+            panic!("expected IncompletePlacement, got {result:?}");
+        };
+        assert_eq!(nodes[0].pods, vec![PodId::new("default", "urgent")]);
+        assert_eq!(unmet[0].id.name, "big");
+    }
+
+    #[test]
+    fn cost_objective_changes_which_offering_wins() {
+        let bounded_offerings = vec![
+            // Cheapest per vCPU, but short on memory.
+            bounded(offering("compute", 8, 8192, 0.040), 10),
+            bounded(offering("memory", 2, 16384, 0.030), 10),
+            // Cheapest per hour, but holds one pod.
+            bounded(offering("small", 1, 4096, 0.012), 10),
+        ];
+        let demands: Vec<_> = (0..4).map(|i| demand(&format!("m{i}"), 0, 4096)).collect();
+        let nodes_with = |cost_objective: CostObjective| {
+            let outcome = ScoredSolver.solve(
+                &demands,
+                &bounded_offerings,
+                &[],
+                &mut QuotaBudget::unlimited(),
+                &SolveOptions {
+                    cost_objective,
+                    ..SolveOptions::default()
+                },
+            );
+            let PlacementSolution::AllPlaced(nodes) = outcome.solution else {
+                panic!("expected AllPlaced, got {:?}", outcome.solution);
+            };
+            (type_counts(&nodes), total_cost(&nodes))
+        };
+
+        let (per_vcpu, per_vcpu_cost) = nodes_with(CostObjective::PerVcpu);
+        assert_eq!(per_vcpu, BTreeMap::from([("compute".to_string(), 2)]));
+        let (hourly, hourly_cost) = nodes_with(CostObjective::HourlyCost);
+        assert_eq!(hourly, BTreeMap::from([("small".to_string(), 4)]));
+        // 16 GiB of memory-bound demand: the memory type holds all of it.
+        let (price_perf, price_perf_cost) = nodes_with(CostObjective::PricePerformance);
+        assert_eq!(price_perf, BTreeMap::from([("memory".to_string(), 1)]));
+        assert!(price_perf_cost < hourly_cost && hourly_cost < per_vcpu_cost);
+    }
+
     #[test]
     fn pre_seeded_partial_coverage() {
         // T2: Pre-seeded absorbs 2 pods, 1 overflows to a new node.
@@ -1667,13 +2940,17 @@ mod tests {
         // Pre-seeded node in zone-a (only fits cache-0, not cache-1).
         let existing = vec![ExistingNode {
             resources: Resources {
-                cpu: 1,
+                cpu_millis: 1000,
                 memory_mib: 1024,
                 ephemeral_storage_gib: None,
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             labels: zone_labels("zone-a"),
+            taints: vec![],
         }];
 
         let result = solve(&demands, &bounded_offerings, &existing);
@@ -1726,13 +3003,17 @@ mod tests {
         )];
         let existing = vec![ExistingNode {
             resources: Resources {
-                cpu: 8,
+                cpu_millis: 8000,
                 memory_mib: 16384,
                 ephemeral_storage_gib: None,
                 gpu: 1,
                 gpu_model: Some(GpuModel::NvidiaT4),
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             labels: zone_labels("zone-a"),
+            taints: vec![],
         }];
 
         let result = solve(&demands, &bounded_offerings, &existing);
@@ -1758,29 +3039,43 @@ mod tests {
             },
             uid: format!("uid-{name}"),
             resources: Resources {
-                cpu: 1,
+                cpu_millis: 1000,
                 memory_mib: 1024,
                 ephemeral_storage_gib: Some(10),
                 gpu: 0,
                 gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
             },
             pool: None,
             pod_labels: BTreeMap::new(),
             affinity_constraints: vec![],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
         };
         let demands = vec![storage_demand("a"), storage_demand("b"), storage_demand("c")];
         let storage_resources = Resources {
-            cpu: 4,
+            cpu_millis: 4000,
             memory_mib: 8192,
             ephemeral_storage_gib: Some(20),
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         };
         let bounded_offerings = vec![bounded(
             Offering {
                 instance_type: InstanceType("cx32".into()),
+                provider: "fake".into(),
                 resources: storage_resources.clone(),
                 cost_per_hour: 0.01,
+                interruption_risk: 0.0,
                 location: test_location(),
             },
             10,
@@ -1788,6 +3083,7 @@ mod tests {
         let existing = vec![ExistingNode {
             resources: storage_resources,
             labels: zone_labels("zone-a"),
+            taints: vec![],
         }];
 
         let result = solve(&demands, &bounded_offerings, &existing);
@@ -1801,4 +3097,130 @@ mod tests {
             "third pod should overflow to new node (ephemeral storage limit)"
         );
     }
+
+    #[test]
+    fn scratch_heavy_pods_claim_large_disks_first() {
+        let storage_demand = |name: &str, gib: u64| PodResources {
+            id: PodId {
+                namespace: "default".into(),
+                name: name.into(),
+            },
+            uid: format!("uid-{name}"),
+            resources: Resources {
+                cpu_millis: 1000,
+                memory_mib: 1024,
+                ephemeral_storage_gib: Some(gib),
+                gpu: 0,
+                gpu_model: None,
+                arch: None,
+                mig: BTreeMap::new(),
+                extended: BTreeMap::new(),
+            },
+            pool: None,
+            pod_labels: BTreeMap::new(),
+            affinity_constraints: vec![],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
+        };
+        let resources = |disk: u64| Resources {
+            cpu_millis: 4000,
+            memory_mib: 8192,
+            ephemeral_storage_gib: Some(disk),
+            gpu: 0,
+            gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
+        };
+        // Listed small-first: placing it first would leave the in-flight
+        // node's disk too small for the 30 GiB pod, which no offering fits.
+        let demands = vec![storage_demand("small", 10), storage_demand("large", 30)];
+        let bounded_offerings = vec![bounded(
+            Offering {
+                instance_type: InstanceType("small-disk".into()),
+                provider: "fake".into(),
+                resources: resources(20),
+                cost_per_hour: 0.01,
+                interruption_risk: 0.0,
+                location: test_location(),
+            },
+            10,
+        )];
+        let existing = vec![ExistingNode {
+            resources: resources(30),
+            labels: zone_labels("zone-a"),
+            taints: vec![],
+        }];
+
+        let result = solve(&demands, &bounded_offerings, &existing);
+        let PlacementSolution::AllPlaced(nodes) = result else {
+            panic!("expected AllPlaced, got {result:?}");
+        };
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].pods[0].name, "small");
+    }
+
+    #[test]
+    fn taints_keep_intolerant_pods_off_nodes() {
+        use k8s_openapi::api::core::v1::Toleration;
+
+        let gpu_taint = Taint {
+            key: "gpu".into(),
+            effect: "NoSchedule".into(),
+            ..Default::default()
+        };
+        let mut tainted = bounded(offering("tainted", 4, 8192, 0.01), 10);
+        tainted.taints = vec![gpu_taint.clone()];
+        let plain = bounded(offering("plain", 4, 8192, 0.05), 10);
+        // An in-flight tainted node with room to spare.
+        let existing = vec![ExistingNode {
+            taints: vec![gpu_taint],
+            ..existing(4, 8192)
+        }];
+        let tolerating = PodResources {
+            tolerations: vec![Toleration {
+                key: Some("gpu".into()),
+                operator: Some("Exists".into()),
+                ..Default::default()
+            }],
+            ..demand("tolerating", 1, 1024)
+        };
+        let demands = vec![demand("intolerant", 1, 1024), tolerating];
+
+        let result = solve(&demands, &[tainted, plain], &existing);
+        let PlacementSolution::AllPlaced(nodes) = result else {
+            panic!("expected AllPlaced, got {result:?}");
+        };
+        // The tolerating pod shares the in-flight node; the other pod needs
+        // an untainted one, even though it costs more.
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].offering.instance_type.0, "plain");
+        assert_eq!(nodes[0].pods[0].name, "intolerant");
+    }
+
+    #[test]
+    fn amd64_pods_never_land_on_arm_offerings() {
+        use crate::offering::Arch;
+
+        let mut cax = offering("cax11", 2, 4096, 0.0074);
+        cax.resources.arch = Some(Arch::Arm64);
+        let mut cpx = offering("cpx22", 2, 4096, 0.0226);
+        cpx.resources.arch = Some(Arch::Amd64);
+        let mut amd64 = demand("amd64-only", 1, 1024);
+        amd64.resources.arch = Some(Arch::Amd64);
+        let demands = vec![amd64, demand("any-arch", 1, 1024)];
+
+        let result = solve(&demands, &[bounded(cax, 10), bounded(cpx, 10)], &[]);
+        let PlacementSolution::AllPlaced(nodes) = result else {
+            panic!("expected AllPlaced, got {result:?}");
+        };
+        // Both pods would share one cheap ARM node, were it not for the
+        // amd64-only image.
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].offering.instance_type.0, "cpx22");
+    }
 }
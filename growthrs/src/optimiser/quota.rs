@@ -0,0 +1,118 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::offering::Offering;
+use crate::providers::provider::Quota;
+
+/// Remaining provider quota, shared by every pool solved in one reconcile.
+///
+/// Offerings whose provider and region have no reported quota are
+/// unconstrained.
+#[derive(Debug, Clone, Default)]
+pub struct QuotaBudget {
+    /// (provider, region) → remaining (vCPUs, instances).
+    remaining: HashMap<(String, String), (Option<u32>, Option<u32>)>,
+}
+
+impl QuotaBudget {
+    /// A budget that never constrains anything.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    pub fn new(quotas: &[Quota]) -> Self {
+        let remaining = quotas
+            .iter()
+            .map(|q| {
+                (
+                    (q.provider.clone(), q.region.0.clone()),
+                    (q.vcpus, q.instances),
+                )
+            })
+            .collect();
+        Self { remaining }
+    }
+
+    fn key(offering: &Offering) -> (String, String) {
+        (
+            offering.provider.clone(),
+            offering.location.region.0.clone(),
+        )
+    }
+
+    /// The quota-constrained (provider, region) pairs `offerings` draw on.
+    /// Pools whose sets are disjoint never compete for quota.
+    pub fn constrained_keys<'a>(
+        &self,
+        offerings: impl IntoIterator<Item = &'a Offering>,
+    ) -> BTreeSet<(String, String)> {
+        offerings
+            .into_iter()
+            .map(Self::key)
+            .filter(|k| self.remaining.contains_key(k))
+            .collect()
+    }
+
+    /// Whether one more node of `offering` fits in the remaining quota.
+    pub fn allows(&self, offering: &Offering) -> bool {
+        let Some((vcpus, instances)) = self.remaining.get(&Self::key(offering)) else {
+            return true;
+        };
+        vcpus.is_none_or(|v| v >= offering.resources.vcpus()) && instances.is_none_or(|i| i >= 1)
+    }
+
+    /// Reserve quota for one node of `offering`.
+    pub fn consume(&mut self, offering: &Offering) {
+        if let Some((vcpus, instances)) = self.remaining.get_mut(&Self::key(offering)) {
+            if let Some(v) = vcpus {
+                *v = v.saturating_sub(offering.resources.vcpus());
+            }
+            if let Some(i) = instances {
+                *i = i.saturating_sub(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::offering::Region;
+    use crate::testing::offering;
+
+    #[test]
+    fn quota_is_consumed_per_provider_region() {
+        let mut budget = QuotaBudget::new(&[Quota {
+            provider: "fake".into(),
+            region: Region("nbg1".into()),
+            vcpus: Some(6),
+            instances: Some(2),
+        }]);
+        let small = offering("t")
+            .with_region("nbg1")
+            .with_cpus(2)
+            .with_memory_mib(1024)
+            .build();
+        let big = offering("t")
+            .with_region("nbg1")
+            .with_cpus(8)
+            .with_memory_mib(1024)
+            .build();
+
+        assert!(!budget.allows(&big));
+        assert!(budget.allows(&small));
+        budget.consume(&small);
+        budget.consume(&small);
+        // vCPUs remain, but the instance quota is spent.
+        assert!(!budget.allows(&small));
+        // Other regions are unconstrained.
+        assert!(
+            budget.allows(
+                &offering("t")
+                    .with_region("fsn1")
+                    .with_cpus(64)
+                    .with_memory_mib(1024)
+                    .build()
+            )
+        );
+    }
+}
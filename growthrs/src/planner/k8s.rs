@@ -0,0 +1,281 @@
+//! Conversions from Kubernetes objects into planner inputs.
+
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::apps::v1::{Deployment, ReplicaSet, StatefulSet};
+use k8s_openapi::api::batch::v1::Job;
+use k8s_openapi::api::core::v1::{Node, Pod, PodTemplateSpec};
+use kube::api::ObjectMeta;
+use serde::Deserialize;
+
+use crate::offering::helper::{parse_count, parse_cpu_millis, parse_memory_mib, parse_storage_gib};
+use crate::offering::{
+    ARCH_LABEL, Arch, GPU_PRODUCT_LABEL, GpuModel, MIG_RESOURCE_PREFIX, PodId, QuantityParseError,
+    Resources, is_extended_resource,
+};
+
+use super::{Capacity, Demand};
+
+impl TryFrom<&Pod> for Demand {
+    type Error = QuantityParseError;
+
+    /// A pod's summed container requests, identified as `namespace/name`.
+    fn try_from(pod: &Pod) -> Result<Self, Self::Error> {
+        let id = PodId::new(
+            pod.metadata.namespace.as_deref().unwrap_or("default"),
+            pod.metadata.name.as_deref().unwrap_or_default(),
+        );
+        Ok(Demand {
+            id: id.to_string(),
+            resources: Resources::from_pod(pod)?,
+        })
+    }
+}
+
+impl Capacity {
+    /// Spare capacity on `node`: its allocatable resources minus the requests
+    /// of running pods bound to it. `pods` may include pods on other nodes.
+    pub fn from_node(node: &Node, pods: &[Pod]) -> Result<Capacity, QuantityParseError> {
+        let node_name = node.metadata.name.as_deref();
+        let allocatable = node.status.as_ref().and_then(|s| s.allocatable.as_ref());
+        let labels = node.metadata.labels.clone().unwrap_or_default();
+
+        let mut resources = Resources {
+            cpu_millis: 0,
+            memory_mib: 0,
+            ephemeral_storage_gib: None,
+            gpu: 0,
+            gpu_model: labels.get(GPU_PRODUCT_LABEL).cloned().map(GpuModel::from),
+            arch: labels.get(ARCH_LABEL).and_then(|a| Arch::from_label(a)),
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
+        };
+        if let Some(allocatable) = allocatable {
+            // Allocatable CPU is often fractional ("3920m").
+            if let Some(q) = allocatable.get("cpu") {
+                resources.cpu_millis = parse_cpu_millis(q)?;
+            }
+            if let Some(q) = allocatable.get("memory") {
+                resources.memory_mib = parse_memory_mib(q)?;
+            }
+            if let Some(q) = allocatable.get("ephemeral-storage") {
+                resources.ephemeral_storage_gib = Some(parse_storage_gib(q)?);
+            }
+            if let Some(q) = allocatable.get("nvidia.com/gpu") {
+                resources.gpu = parse_count(q)?;
+            }
+            for (name, q) in allocatable {
+                if let Some(profile) = name.strip_prefix(MIG_RESOURCE_PREFIX) {
+                    resources.mig.insert(profile.to_string(), parse_count(q)?);
+                } else if is_extended_resource(name) {
+                    resources
+                        .extended
+                        .insert(name.clone(), u64::from(parse_count(q)?));
+                }
+            }
+        }
+
+        for pod in pods {
+            let on_node = node_name.is_some()
+                && pod.spec.as_ref().and_then(|s| s.node_name.as_deref()) == node_name;
+            let finished = pod
+                .status
+                .as_ref()
+                .and_then(|s| s.phase.as_deref())
+                .is_some_and(|p| p == "Succeeded" || p == "Failed");
+            if !on_node || finished {
+                continue;
+            }
+            resources.saturating_subtract(&Resources::from_pod(pod)?);
+        }
+
+        Ok(Capacity { resources, labels })
+    }
+}
+
+/// The pods a multi-document YAML stream of manifests would run. Pods are
+/// taken as they are; Deployments, ReplicaSets and StatefulSets contribute
+/// `spec.replicas` (default 1) copies of their pod template, named
+/// `<workload>-<n>`, and Jobs `spec.parallelism` (default 1). Other kinds
+/// are skipped.
+pub fn pods_from_manifests(yaml: &str) -> Result<Vec<Pod>, serde_yaml::Error> {
+    let mut pods = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(yaml) {
+        let value = serde_yaml::Value::deserialize(document)?;
+        let (metadata, template, replicas) = match value.get("kind").and_then(|k| k.as_str()) {
+            Some("Pod") => {
+                pods.push(serde_yaml::from_value(value)?);
+                continue;
+            }
+            Some("Deployment") => {
+                let d: Deployment = serde_yaml::from_value(value)?;
+                let spec = d.spec.unwrap_or_default();
+                (d.metadata, spec.template, spec.replicas)
+            }
+            Some("ReplicaSet") => {
+                let r: ReplicaSet = serde_yaml::from_value(value)?;
+                let spec = r.spec.unwrap_or_default();
+                (r.metadata, spec.template.unwrap_or_default(), spec.replicas)
+            }
+            Some("StatefulSet") => {
+                let s: StatefulSet = serde_yaml::from_value(value)?;
+                let spec = s.spec.unwrap_or_default();
+                (s.metadata, spec.template, spec.replicas)
+            }
+            Some("Job") => {
+                let j: Job = serde_yaml::from_value(value)?;
+                let spec = j.spec.unwrap_or_default();
+                (j.metadata, spec.template, spec.parallelism)
+            }
+            _ => continue,
+        };
+        pods.extend(replicate(&metadata, &template, replicas.unwrap_or(1)));
+    }
+    Ok(pods)
+}
+
+fn replicate<'a>(
+    owner: &'a ObjectMeta,
+    template: &'a PodTemplateSpec,
+    replicas: i32,
+) -> impl Iterator<Item = Pod> + 'a {
+    let name = owner.name.as_deref().unwrap_or("pod");
+    (0..replicas.max(0)).map(move |i| Pod {
+        metadata: ObjectMeta {
+            name: Some(format!("{name}-{i}")),
+            namespace: owner.namespace.clone(),
+            labels: template.metadata.as_ref().and_then(|m| m.labels.clone()),
+            ..Default::default()
+        },
+        spec: template.spec.clone(),
+        status: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::{
+        Container, NodeStatus, PodSpec, PodStatus, ResourceRequirements,
+    };
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    use kube::api::ObjectMeta;
+
+    fn pod(name: &str, node: Option<&str>, cpu: &str, memory: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                namespace: Some("jobs".into()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                node_name: node.map(Into::into),
+                containers: vec![Container {
+                    name: "main".into(),
+                    resources: Some(ResourceRequirements {
+                        requests: Some(BTreeMap::from([
+                            ("cpu".into(), Quantity(cpu.into())),
+                            ("memory".into(), Quantity(memory.into())),
+                        ])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            status: Some(PodStatus {
+                phase: Some("Running".into()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn manifests_expand_workloads_into_pods() {
+        let yaml = r#"
+apiVersion: v1
+kind: Pod
+metadata: {name: solo, namespace: jobs}
+spec:
+  containers: [{name: main, resources: {requests: {cpu: 250m}}}]
+---
+apiVersion: apps/v1
+kind: Deployment
+metadata: {name: web}
+spec:
+  replicas: 3
+  selector: {matchLabels: {app: web}}
+  template:
+    metadata: {labels: {app: web}}
+    spec:
+      containers: [{name: web, resources: {requests: {cpu: "1", memory: 1Gi}}}]
+---
+apiVersion: v1
+kind: Service
+metadata: {name: web}
+---
+"#;
+        let pods = pods_from_manifests(yaml).unwrap();
+        let names: Vec<&str> = pods
+            .iter()
+            .filter_map(|p| p.metadata.name.as_deref())
+            .collect();
+        assert_eq!(names, ["solo", "web-0", "web-1", "web-2"]);
+
+        let demand = Demand::try_from(&pods[2]).unwrap();
+        assert_eq!(demand.id, "default/web-1");
+        assert_eq!(demand.resources.cpu_millis, 1000);
+        assert_eq!(demand.resources.memory_mib, 1024);
+    }
+
+    #[test]
+    fn pod_converts_to_demand() {
+        let demand = Demand::try_from(&pod("train", None, "1500m", "2Gi")).unwrap();
+        assert_eq!(demand.id, "jobs/train");
+        assert_eq!(demand.resources.cpu_millis, 1500);
+        assert_eq!(demand.resources.memory_mib, 2048);
+    }
+
+    #[test]
+    fn node_capacity_subtracts_bound_pods() {
+        let node = Node {
+            metadata: ObjectMeta {
+                name: Some("n1".into()),
+                labels: Some(BTreeMap::from([(
+                    "topology.kubernetes.io/zone".into(),
+                    "fsn1-dc14".into(),
+                )])),
+                ..Default::default()
+            },
+            spec: None,
+            status: Some(NodeStatus {
+                allocatable: Some(BTreeMap::from([
+                    ("cpu".into(), Quantity("3920m".into())),
+                    ("memory".into(), Quantity("8Gi".into())),
+                ])),
+                ..Default::default()
+            }),
+        };
+        let mut done = pod("done", Some("n1"), "1", "1Gi");
+        done.status.as_mut().unwrap().phase = Some("Succeeded".into());
+        let pods = vec![
+            pod("here", Some("n1"), "1", "1Gi"),
+            pod("elsewhere", Some("n2"), "1", "1Gi"),
+            done,
+        ];
+
+        let capacity = Capacity::from_node(&node, &pods).unwrap();
+        assert_eq!(capacity.resources.cpu_millis, 2920);
+        assert_eq!(capacity.resources.memory_mib, 7168);
+        assert_eq!(
+            capacity
+                .labels
+                .get("topology.kubernetes.io/zone")
+                .map(String::as_str),
+            Some("fsn1-dc14")
+        );
+    }
+}
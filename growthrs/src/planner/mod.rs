@@ -0,0 +1,290 @@
+//! Plain-Rust planning API over the optimiser.
+//!
+//! Everything here is expressed in terms of demands, spare capacity and an
+//! instance catalog, with no Kubernetes types in the signatures, so batch
+//! schedulers or cost tools can ask "what should I buy to run this?" without
+//! modelling pods or NodePools. Conversions from Kubernetes objects live in
+//! [`k8s`] for callers that do have them.
+
+pub mod k8s;
+
+use std::collections::BTreeMap;
+
+use crate::offering::{
+    InstanceType, Location, Offering, PodId, PodResources, REGION_LABEL, Region, ZONE_LABEL, Zone,
+};
+use crate::optimiser::{BoundedOffering, ExistingNode, PlacementSolution, solve};
+
+pub use crate::offering::{GpuModel, Resources};
+
+/// A unit of work that needs a place to run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Demand {
+    /// Caller-chosen identifier, echoed back in the [`Plan`]. Must be unique.
+    pub id: String,
+    pub resources: Resources,
+}
+
+/// Spare capacity that already exists (or is on its way) and is used before
+/// anything new is bought.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capacity {
+    pub resources: Resources,
+    /// Topology labels, e.g. `topology.kubernetes.io/zone`.
+    pub labels: BTreeMap<String, String>,
+}
+
+/// An instance type that can be bought.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogEntry {
+    pub instance_type: String,
+    pub resources: Resources,
+    pub cost_per_hour: f64,
+    pub region: String,
+    pub zone: Option<String>,
+    /// Upper bound on how many of this entry the plan may use. `None`
+    /// allows as many as the demands could need.
+    pub max_instances: Option<u32>,
+}
+
+/// Everything the planner needs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlanInput {
+    pub demands: Vec<Demand>,
+    pub capacities: Vec<Capacity>,
+    pub catalog: Vec<CatalogEntry>,
+}
+
+/// An instance the plan says to buy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedNode {
+    pub instance_type: String,
+    pub region: String,
+    pub zone: Option<String>,
+    pub cost_per_hour: f64,
+    /// Ids of the demands placed on this node.
+    pub demands: Vec<String>,
+}
+
+/// Result of [`plan`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    pub nodes: Vec<PlannedNode>,
+    /// Ids of demands that fit neither existing capacity nor any catalog entry.
+    pub unplaced: Vec<String>,
+}
+
+impl Plan {
+    /// Hourly cost of every planned node.
+    pub fn cost_per_hour(&self) -> f64 {
+        self.nodes.iter().map(|n| n.cost_per_hour).sum()
+    }
+}
+
+impl CatalogEntry {
+    /// Catalog entry for a provider offering, e.g. from `Provider::offerings()`.
+    pub fn from_offering(offering: &Offering, max_instances: Option<u32>) -> Self {
+        Self {
+            instance_type: offering.instance_type.0.clone(),
+            resources: offering.resources.clone(),
+            cost_per_hour: offering.cost_per_hour,
+            region: offering.location.region.0.clone(),
+            zone: offering.location.zone.as_ref().map(|z| z.0.clone()),
+            max_instances,
+        }
+    }
+
+    fn to_bounded(&self, demands: &[PodResources]) -> BoundedOffering {
+        let mut labels = BTreeMap::from([(REGION_LABEL.to_string(), self.region.clone())]);
+        if let Some(zone) = &self.zone {
+            labels.insert(ZONE_LABEL.to_string(), zone.clone());
+        }
+        let mut bounded = BoundedOffering {
+            offering: Offering {
+                instance_type: InstanceType(self.instance_type.clone()),
+                provider: String::new(),
+                resources: self.resources.clone(),
+                cost_per_hour: self.cost_per_hour,
+                interruption_risk: 0.0,
+                location: Location {
+                    region: Region(self.region.clone()),
+                    zone: self.zone.clone().map(Zone),
+                },
+            },
+            max_instances: 0,
+            labels,
+            type_group: None,
+            penalty: 0.0,
+            taints: vec![],
+            overhead: Resources::default(),
+        };
+        bounded.max_instances = self
+            .max_instances
+            .unwrap_or_else(|| bounded.demand_bound(demands));
+        bounded
+    }
+}
+
+/// Place `input.demands` on existing capacity first, then on the cheapest
+/// mix of catalog entries, using the same optimiser as the controller.
+pub fn plan(input: &PlanInput) -> Plan {
+    let demands: Vec<PodResources> = input
+        .demands
+        .iter()
+        .map(|d| PodResources {
+            id: PodId::new("", d.id.clone()),
+            uid: d.id.clone(),
+            resources: d.resources.clone(),
+            pool: None,
+            pod_labels: BTreeMap::new(),
+            affinity_constraints: vec![],
+            zones: None,
+            tolerations: vec![],
+            node_selector: BTreeMap::new(),
+            node_affinity: vec![],
+            priority: 0,
+            unmet_penalty: None,
+        })
+        .collect();
+    let bounded: Vec<BoundedOffering> = input
+        .catalog
+        .iter()
+        .map(|c| c.to_bounded(&demands))
+        .collect();
+    let existing: Vec<ExistingNode> = input
+        .capacities
+        .iter()
+        .map(|c| ExistingNode {
+            resources: c.resources.clone(),
+            labels: c.labels.clone(),
+            taints: vec![],
+        })
+        .collect();
+
+    let (nodes, unmet) = match solve(&demands, &bounded, &existing) {
+        PlacementSolution::NoDemands => return Plan::default(),
+        PlacementSolution::AllPlaced(nodes) => (nodes, vec![]),
+        PlacementSolution::IncompletePlacement { nodes, unmet } => (nodes, unmet),
+    };
+
+    Plan {
+        nodes: nodes
+            .into_iter()
+            .map(|n| PlannedNode {
+                instance_type: n.offering.instance_type.0,
+                region: n.offering.location.region.0,
+                zone: n.offering.location.zone.map(|z| z.0),
+                cost_per_hour: n.offering.cost_per_hour,
+                demands: n.pods.into_iter().map(|p| p.name).collect(),
+            })
+            .collect(),
+        unplaced: unmet.into_iter().map(|d| d.id.name).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn res(cpu: u32, memory_mib: u64) -> Resources {
+        Resources {
+            cpu_millis: u64::from(cpu) * 1000,
+            memory_mib,
+            ephemeral_storage_gib: None,
+            gpu: 0,
+            gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
+        }
+    }
+
+    fn demand(id: &str, cpu: u32, memory_mib: u64) -> Demand {
+        Demand {
+            id: id.into(),
+            resources: res(cpu, memory_mib),
+        }
+    }
+
+    fn entry(name: &str, cpu: u32, memory_mib: u64, cost: f64) -> CatalogEntry {
+        CatalogEntry {
+            instance_type: name.into(),
+            resources: res(cpu, memory_mib),
+            cost_per_hour: cost,
+            region: "eu-central".into(),
+            zone: None,
+            max_instances: None,
+        }
+    }
+
+    #[test]
+    fn packs_demands_onto_cheapest_catalog_entries() {
+        let input = PlanInput {
+            demands: vec![demand("a", 1, 1024), demand("b", 1, 1024)],
+            capacities: vec![],
+            catalog: vec![
+                entry("small", 2, 4096, 0.01),
+                entry("large", 8, 16384, 0.08),
+            ],
+        };
+        let plan = plan(&input);
+        assert_eq!(plan.nodes.len(), 1);
+        assert_eq!(plan.nodes[0].instance_type, "small");
+        let mut placed = plan.nodes[0].demands.clone();
+        placed.sort();
+        assert_eq!(placed, ["a", "b"]);
+        assert!(plan.unplaced.is_empty());
+        assert_eq!(plan.cost_per_hour(), 0.01);
+    }
+
+    #[test]
+    fn existing_capacity_is_used_first() {
+        let input = PlanInput {
+            demands: vec![demand("a", 1, 1024)],
+            capacities: vec![Capacity {
+                resources: res(2, 4096),
+                labels: BTreeMap::new(),
+            }],
+            catalog: vec![entry("small", 2, 4096, 0.01)],
+        };
+        assert_eq!(plan(&input), Plan::default());
+    }
+
+    #[test]
+    fn unbounded_entries_scale_with_the_batch() {
+        let input = PlanInput {
+            demands: (0..40).map(|i| demand(&format!("d{i}"), 2, 1024)).collect(),
+            capacities: vec![],
+            catalog: vec![entry("small", 2, 4096, 0.01)],
+        };
+        let plan = plan(&input);
+        assert_eq!(plan.nodes.len(), 40);
+        assert!(plan.unplaced.is_empty());
+    }
+
+    #[test]
+    fn explicit_max_instances_still_caps_the_plan() {
+        let mut small = entry("small", 2, 4096, 0.01);
+        small.max_instances = Some(3);
+        let input = PlanInput {
+            demands: (0..5).map(|i| demand(&format!("d{i}"), 2, 1024)).collect(),
+            capacities: vec![],
+            catalog: vec![small],
+        };
+        let plan = plan(&input);
+        assert_eq!(plan.nodes.len(), 3);
+        assert_eq!(plan.unplaced.len(), 2);
+    }
+
+    #[test]
+    fn oversized_demands_are_reported_unplaced() {
+        let input = PlanInput {
+            demands: vec![demand("huge", 64, 1024)],
+            capacities: vec![],
+            catalog: vec![entry("small", 2, 4096, 0.01)],
+        };
+        let plan = plan(&input);
+        assert!(plan.nodes.is_empty());
+        assert_eq!(plan.unplaced, ["huge"]);
+    }
+}
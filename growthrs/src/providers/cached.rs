@@ -0,0 +1,196 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tracing::debug;
+
+use crate::offering::Offering;
+use crate::providers::provider::{
+    InstanceConfig, NodeId, Provider, ProviderCreateConfig, ProviderError, ProviderHealth,
+    ProviderStatus, Quota,
+};
+
+struct CachedOfferings {
+    offerings: Vec<Offering>,
+    fetched_at: Instant,
+}
+
+/// Caches another provider's `offerings()` for a fixed TTL.
+///
+/// Once the TTL has passed, callers keep getting the stale list while a
+/// single background task refreshes it, so a burst of reconciles costs at
+/// most one pricing API call. Only the first call ever waits on the provider.
+/// Empty results are not cached, since providers return them on API errors.
+pub struct CachedProvider {
+    inner: Arc<dyn Provider>,
+    ttl: Duration,
+    cache: Arc<Mutex<Option<CachedOfferings>>>,
+    refreshing: Arc<AtomicBool>,
+}
+
+impl CachedProvider {
+    pub fn new(inner: Arc<dyn Provider>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            cache: Arc::new(Mutex::new(None)),
+            refreshing: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn store(cache: &Mutex<Option<CachedOfferings>>, offerings: &[Offering]) {
+        if offerings.is_empty() {
+            return;
+        }
+        *cache.lock().unwrap() = Some(CachedOfferings {
+            offerings: offerings.to_vec(),
+            fetched_at: Instant::now(),
+        });
+    }
+
+    fn spawn_refresh(&self) {
+        if self.refreshing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let inner = self.inner.clone();
+        let cache = self.cache.clone();
+        let refreshing = self.refreshing.clone();
+        tokio::spawn(async move {
+            let offerings = inner.offerings().await;
+            debug!(
+                provider = inner.name(),
+                count = offerings.len(),
+                "refreshed offering cache"
+            );
+            Self::store(&cache, &offerings);
+            refreshing.store(false, Ordering::Release);
+        });
+    }
+}
+
+#[async_trait]
+impl Provider for CachedProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn offerings(&self) -> Vec<Offering> {
+        let stale = {
+            let cache = self.cache.lock().unwrap();
+            match cache.as_ref() {
+                Some(c) if c.fetched_at.elapsed() < self.ttl => return c.offerings.clone(),
+                Some(c) => Some(c.offerings.clone()),
+                None => None,
+            }
+        };
+        match stale {
+            Some(offerings) => {
+                self.spawn_refresh();
+                offerings
+            }
+            None => {
+                let offerings = self.inner.offerings().await;
+                Self::store(&self.cache, &offerings);
+                offerings
+            }
+        }
+    }
+
+    async fn create(
+        &self,
+        node_id: String,
+        offering: &Offering,
+        config: &InstanceConfig,
+        provider_config: &ProviderCreateConfig,
+    ) -> Result<NodeId, ProviderError> {
+        self.inner
+            .create(node_id, offering, config, provider_config)
+            .await
+    }
+
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+        self.inner.delete(node_id).await
+    }
+
+    fn max_delete_batch(&self) -> usize {
+        self.inner.max_delete_batch()
+    }
+
+    async fn delete_batch(&self, node_ids: &[NodeId]) -> Vec<Result<(), ProviderError>> {
+        self.inner.delete_batch(node_ids).await
+    }
+
+    async fn quotas(&self) -> Result<Vec<Quota>, ProviderError> {
+        self.inner.quotas().await
+    }
+
+    async fn health(&self) -> Vec<ProviderHealth> {
+        self.inner.health().await
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+        self.inner.status(node_id).await
+    }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        self.inner.list_instances().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fake::FakeProvider;
+    use crate::testing::offering;
+
+    fn names(offerings: &[Offering]) -> Vec<&str> {
+        offerings
+            .iter()
+            .map(|o| o.instance_type.0.as_str())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn fresh_cache_is_served_without_calling_provider() {
+        let fake = FakeProvider::new().with_offerings_sequence(vec![
+            vec![offering("first").build()],
+            vec![offering("second").build()],
+        ]);
+        let cached = CachedProvider::new(Arc::new(fake), Duration::from_secs(60));
+
+        assert_eq!(names(&cached.offerings().await), ["first"]);
+        assert_eq!(names(&cached.offerings().await), ["first"]);
+    }
+
+    #[tokio::test]
+    async fn expired_cache_is_served_stale_then_refreshed() {
+        let fake = FakeProvider::new().with_offerings_sequence(vec![
+            vec![offering("first").build()],
+            vec![offering("second").build()],
+        ]);
+        let cached = CachedProvider::new(Arc::new(fake), Duration::from_millis(10));
+
+        assert_eq!(names(&cached.offerings().await), ["first"]);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(names(&cached.offerings().await), ["first"]);
+
+        for _ in 0..100 {
+            if !cached.refreshing.load(Ordering::Acquire) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(names(&cached.offerings().await), ["second"]);
+    }
+
+    #[tokio::test]
+    async fn empty_results_are_not_cached() {
+        let fake = FakeProvider::new()
+            .with_offerings_sequence(vec![vec![], vec![offering("recovered").build()]]);
+        let cached = CachedProvider::new(Arc::new(fake), Duration::from_secs(60));
+
+        assert!(cached.offerings().await.is_empty());
+        assert_eq!(names(&cached.offerings().await), ["recovered"]);
+    }
+}
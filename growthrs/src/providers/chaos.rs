@@ -0,0 +1,185 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tracing::info;
+
+use crate::offering::Offering;
+use crate::providers::provider::{
+    InstanceConfig, NodeId, Provider, ProviderCreateConfig, ProviderError, ProviderHealth,
+    ProviderStatus, Quota,
+};
+
+/// Failure rates for a [`ChaosProvider`]. Rates are probabilities per call,
+/// from 0 (never) to 1 (always); the default injects nothing.
+#[derive(Debug, Clone, Default)]
+pub struct ChaosConfig {
+    /// Seed for the failure RNG; the same seed and call order reproduce the
+    /// same failures.
+    pub seed: u64,
+    /// Chance a `create()` fails with `OfferingUnavailable`.
+    pub unavailable_rate: f64,
+    /// Chance a `create()` is held for `join_delay` before going through.
+    pub delayed_join_rate: f64,
+    pub join_delay: Duration,
+    /// Chance a `status()` check finds the node gone: it is deleted behind
+    /// the controller's back and reported `NotFound` from then on.
+    pub disappear_rate: f64,
+}
+
+/// Injects random failures into another provider's calls, for soak-testing
+/// the controller's error handling.
+///
+/// Registered as `chaos`, wrapping KWOK and configured from
+/// `GROWTH_CHAOS_*`.
+pub struct ChaosProvider {
+    inner: Arc<dyn Provider>,
+    config: ChaosConfig,
+    rng: Mutex<StdRng>,
+    /// Nodes that were made to disappear.
+    vanished: Mutex<HashSet<NodeId>>,
+}
+
+impl ChaosProvider {
+    pub fn new(inner: Arc<dyn Provider>, config: ChaosConfig) -> Self {
+        Self {
+            rng: Mutex::new(StdRng::seed_from_u64(config.seed)),
+            inner,
+            config,
+            vanished: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn roll(&self, rate: f64) -> bool {
+        rate > 0.0 && self.rng.lock().unwrap().gen_bool(rate.min(1.0))
+    }
+}
+
+#[async_trait]
+impl Provider for ChaosProvider {
+    fn name(&self) -> &str {
+        "chaos"
+    }
+
+    async fn offerings(&self) -> Vec<Offering> {
+        self.inner.offerings().await
+    }
+
+    async fn create(
+        &self,
+        node_id: String,
+        offering: &Offering,
+        config: &InstanceConfig,
+        provider_config: &ProviderCreateConfig,
+    ) -> Result<NodeId, ProviderError> {
+        if self.roll(self.config.unavailable_rate) {
+            info!(node_id, "chaos: failing create as unavailable");
+            return Err(ProviderError::OfferingUnavailable(format!(
+                "chaos: {} unavailable",
+                offering.instance_type
+            )));
+        }
+        if self.roll(self.config.delayed_join_rate) {
+            info!(node_id, delay = ?self.config.join_delay, "chaos: delaying join");
+            tokio::time::sleep(self.config.join_delay).await;
+        }
+        self.inner
+            .create(node_id, offering, config, provider_config)
+            .await
+    }
+
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+        if self.vanished.lock().unwrap().contains(node_id) {
+            return Ok(());
+        }
+        self.inner.delete(node_id).await
+    }
+
+    async fn quotas(&self) -> Result<Vec<Quota>, ProviderError> {
+        self.inner.quotas().await
+    }
+
+    async fn health(&self) -> Vec<ProviderHealth> {
+        self.inner.health().await
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+        if self.vanished.lock().unwrap().contains(node_id) {
+            return Ok(ProviderStatus::NotFound);
+        }
+        if self.roll(self.config.disappear_rate) {
+            info!(node_id = %node_id.0, "chaos: making node disappear");
+            self.inner.delete(node_id).await?;
+            self.vanished.lock().unwrap().insert(node_id.clone());
+            return Ok(ProviderStatus::NotFound);
+        }
+        self.inner.status(node_id).await
+    }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        let mut instances = self.inner.list_instances().await?;
+        let vanished = self.vanished.lock().unwrap();
+        instances.retain(|id| !vanished.contains(id));
+        Ok(instances)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fake::FakeProvider;
+    use crate::testing::offering;
+
+    async fn outcomes(config: ChaosConfig) -> Vec<bool> {
+        let chaos = ChaosProvider::new(Arc::new(FakeProvider::new()), config);
+        let mut outcomes = Vec::new();
+        for i in 0..20 {
+            let result = chaos
+                .create(
+                    format!("n{i}"),
+                    &offering("cpx22").build(),
+                    &InstanceConfig::default(),
+                    &ProviderCreateConfig::None,
+                )
+                .await;
+            outcomes.push(result.is_ok());
+        }
+        outcomes
+    }
+
+    #[tokio::test]
+    async fn failures_are_reproducible_by_seed() {
+        let config = ChaosConfig {
+            seed: 42,
+            unavailable_rate: 0.5,
+            ..Default::default()
+        };
+        let first = outcomes(config.clone()).await;
+        assert_eq!(first, outcomes(config).await);
+        assert!(first.contains(&true) && first.contains(&false));
+
+        let none = outcomes(ChaosConfig::default()).await;
+        assert!(none.iter().all(|ok| *ok));
+    }
+
+    #[tokio::test]
+    async fn disappeared_nodes_stay_gone() {
+        let fake = FakeProvider::new();
+        let chaos = ChaosProvider::new(
+            Arc::new(fake.clone()),
+            ChaosConfig {
+                disappear_rate: 1.0,
+                ..Default::default()
+            },
+        );
+        let node = NodeId("n1".into());
+        assert_eq!(chaos.status(&node).await.unwrap(), ProviderStatus::NotFound);
+        assert_eq!(fake.delete_calls().len(), 1);
+        // Deleting a vanished node succeeds without another provider call.
+        chaos.delete(&node).await.unwrap();
+        assert_eq!(fake.delete_calls().len(), 1);
+    }
+}
@@ -5,8 +5,17 @@ pub use types::*;
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::Node;
+use kube::api::{DeleteParams, PostParams};
+use kube::{Api, Client};
+
 use crate::offering::Offering;
-use crate::providers::provider::{InstanceConfig, NodeId, ProviderError, ProviderStatus};
+use crate::providers::kwok::kwok_node;
+use crate::providers::provider::{
+    InstanceConfig, NodeId, Provider, ProviderCreateConfig, ProviderError, ProviderHealth,
+    ProviderStatus, Quota,
+};
 
 /// A queue of scripted behaviors with a default fallback, plus a call log.
 ///
@@ -58,15 +67,32 @@ pub(crate) struct FakeProviderState {
     create: BehaviorQueue<CreateBehavior, CreateCall>,
     delete: BehaviorQueue<DeleteBehavior, DeleteCall>,
     status: BehaviorQueue<StatusBehavior, StatusCall>,
+    instances: Vec<NodeId>,
+    max_delete_batch: usize,
+    delete_batches: Vec<Vec<NodeId>>,
+    quotas: Vec<Quota>,
+    /// Reported by `health()`; `None` is healthy.
+    health_error: Option<String>,
 }
 
 /// A deterministic, in-memory provider for testing failure modes.
 ///
 /// Each call to `create()`/`delete()`/`status()` pops the next behavior from
 /// a queue. When the queue is empty, the configured default applies.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct FakeProvider {
     state: Arc<Mutex<FakeProviderState>>,
+    /// Set by [`FakeProvider::with_kwok_nodes`].
+    kube_client: Option<Client>,
+}
+
+impl std::fmt::Debug for FakeProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FakeProvider")
+            .field("state", &self.state)
+            .field("kwok_nodes", &self.kube_client.is_some())
+            .finish()
+    }
 }
 
 impl Default for FakeProvider {
@@ -83,7 +109,13 @@ impl FakeProvider {
                 create: BehaviorQueue::new(CreateBehavior::Succeed),
                 delete: BehaviorQueue::new(DeleteBehavior::Succeed),
                 status: BehaviorQueue::new(StatusBehavior::Return(ProviderStatus::Running)),
+                instances: Vec::new(),
+                max_delete_batch: 1,
+                delete_batches: Vec::new(),
+                quotas: Vec::new(),
+                health_error: None,
             })),
+            kube_client: None,
         }
     }
 }
@@ -129,6 +161,81 @@ impl FakeProvider {
         self.state.lock().unwrap().status.set_default(behavior);
         self
     }
+
+    /// Instances reported by `list_instances()`.
+    pub fn with_instances(self, instances: Vec<NodeId>) -> Self {
+        self.state.lock().unwrap().instances = instances;
+        self
+    }
+
+    /// Largest batch accepted by `delete_batch()`. Each id in a batch still
+    /// pops its own delete behavior.
+    pub fn with_max_delete_batch(self, n: usize) -> Self {
+        self.state.lock().unwrap().max_delete_batch = n;
+        self
+    }
+
+    /// Quotas reported by `quotas()`.
+    pub fn with_quotas(self, quotas: Vec<Quota>) -> Self {
+        self.state.lock().unwrap().quotas = quotas;
+        self
+    }
+
+    /// Back successful creates with a real KWOK Node, built as
+    /// `KwokProvider` would, so the node joins the cluster; successful
+    /// deletes remove it again. `SucceedButNodeNeverJoins` still creates
+    /// nothing.
+    pub fn with_kwok_nodes(mut self, client: Client) -> Self {
+        self.kube_client = Some(client);
+        self
+    }
+
+    /// Make `health()` report the provider unhealthy with `error`, or healthy
+    /// again with `None`. Takes effect on clones too.
+    pub fn set_unhealthy(&self, error: Option<&str>) {
+        self.state.lock().unwrap().health_error = error.map(Into::into);
+    }
+}
+
+impl FakeProvider {
+    /// Create the KWOK Node for a successful create, if enabled.
+    async fn materialize(
+        &self,
+        node_id: NodeId,
+        offering: &Offering,
+        config: &InstanceConfig,
+    ) -> Result<NodeId, ProviderError> {
+        let Some(client) = &self.kube_client else {
+            return Ok(node_id);
+        };
+        let nodes: Api<Node> = Api::all(client.clone());
+        nodes
+            .create(
+                &PostParams::default(),
+                &kwok_node(node_id.0.clone(), offering, config),
+            )
+            .await
+            .map_err(|e| ProviderError::CreationFailed {
+                message: e.to_string(),
+            })?;
+        Ok(node_id)
+    }
+
+    /// Delete the KWOK Node for a successful delete, if enabled. A Node that
+    /// never joined is already gone.
+    async fn dematerialize(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+        let Some(client) = &self.kube_client else {
+            return Ok(());
+        };
+        let nodes: Api<Node> = Api::all(client.clone());
+        match nodes.delete(&node_id.0, &DeleteParams::default()).await {
+            Ok(_) => Ok(()),
+            Err(e) if crate::controller::is_kube_not_found(&e) => Ok(()),
+            Err(e) => Err(ProviderError::DeletionFailed {
+                message: e.to_string(),
+            }),
+        }
+    }
 }
 
 impl FakeProvider {
@@ -140,13 +247,23 @@ impl FakeProvider {
         self.state.lock().unwrap().delete.calls()
     }
 
+    /// Node ids of each `delete_batch()` call, in call order.
+    pub fn delete_batch_calls(&self) -> Vec<Vec<NodeId>> {
+        self.state.lock().unwrap().delete_batches.clone()
+    }
+
     pub fn status_calls(&self) -> Vec<StatusCall> {
         self.state.lock().unwrap().status.calls()
     }
 }
 
-impl FakeProvider {
-    pub async fn offerings(&self) -> Vec<Offering> {
+#[async_trait]
+impl Provider for FakeProvider {
+    fn name(&self) -> &str {
+        "fake"
+    }
+
+    async fn offerings(&self) -> Vec<Offering> {
         let mut state = self.state.lock().unwrap();
         match &mut state.offerings_behavior {
             OfferingsBehavior::Static(v) => v.clone(),
@@ -161,20 +278,22 @@ impl FakeProvider {
         }
     }
 
-    pub async fn create(
+    async fn create(
         &self,
         node_id: String,
         offering: &Offering,
         config: &InstanceConfig,
+        _provider_config: &ProviderCreateConfig,
     ) -> Result<NodeId, ProviderError> {
         let behavior = self.state.lock().unwrap().create.next();
         let node_id = NodeId(node_id);
 
         let result = match behavior {
-            CreateBehavior::Succeed | CreateBehavior::SucceedButNodeNeverJoins => Ok(node_id),
+            CreateBehavior::SucceedButNodeNeverJoins => Ok(node_id),
+            CreateBehavior::Succeed => self.materialize(node_id, offering, config).await,
             CreateBehavior::SucceedAfterDelay(d) => {
                 tokio::time::sleep(d).await;
-                Ok(node_id)
+                self.materialize(node_id, offering, config).await
             }
             CreateBehavior::OfferingUnavailable => Err(ProviderError::OfferingUnavailable(
                 format!("{} not available", offering.instance_type),
@@ -186,6 +305,7 @@ impl FakeProvider {
             CreateBehavior::InternalError(msg) => {
                 Err(ProviderError::Internal(anyhow::anyhow!(msg)))
             }
+            CreateBehavior::RateLimited => Err(ProviderError::RateLimited { retry_after: None }),
         };
 
         let result_node_id = result.as_ref().ok().cloned();
@@ -198,7 +318,7 @@ impl FakeProvider {
         result
     }
 
-    pub async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
         let behavior = self.state.lock().unwrap().delete.next();
 
         self.state.lock().unwrap().delete.log(DeleteCall {
@@ -206,12 +326,41 @@ impl FakeProvider {
         });
 
         match behavior {
-            DeleteBehavior::Succeed | DeleteBehavior::Noop => Ok(()),
+            DeleteBehavior::Succeed => self.dematerialize(node_id).await,
+            DeleteBehavior::Noop => Ok(()),
             DeleteBehavior::Fail(msg) => Err(ProviderError::DeletionFailed { message: msg }),
         }
     }
 
-    pub async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+    fn max_delete_batch(&self) -> usize {
+        self.state.lock().unwrap().max_delete_batch
+    }
+
+    async fn delete_batch(&self, node_ids: &[NodeId]) -> Vec<Result<(), ProviderError>> {
+        self.state
+            .lock()
+            .unwrap()
+            .delete_batches
+            .push(node_ids.to_vec());
+        let mut results = Vec::with_capacity(node_ids.len());
+        for node_id in node_ids {
+            results.push(self.delete(node_id).await);
+        }
+        results
+    }
+
+    async fn quotas(&self) -> Result<Vec<Quota>, ProviderError> {
+        Ok(self.state.lock().unwrap().quotas.clone())
+    }
+
+    async fn health(&self) -> Vec<ProviderHealth> {
+        vec![ProviderHealth {
+            provider: self.name().to_string(),
+            error: self.state.lock().unwrap().health_error.clone(),
+        }]
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
         let behavior = self.state.lock().unwrap().status.next();
 
         self.state.lock().unwrap().status.log(StatusCall {
@@ -225,29 +374,19 @@ impl FakeProvider {
             }
         }
     }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        Ok(self.state.lock().unwrap().instances.clone())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::offering::{InstanceType, Location, Region, Resources, Zone};
+    use crate::testing::offering;
 
     fn test_offering() -> Offering {
-        Offering {
-            instance_type: InstanceType("test-instance".into()),
-            resources: Resources {
-                cpu: 2,
-                memory_mib: 4096,
-                ephemeral_storage_gib: None,
-                gpu: 0,
-                gpu_model: None,
-            },
-            cost_per_hour: 0.01,
-            location: Location {
-                region: Region("eu-central".into()),
-                zone: Some(Zone("fsn1-dc14".into())),
-            },
-        }
+        offering("test-instance").with_zone("fsn1-dc14").build()
     }
 
     // ── BehaviorQueue generic tests ────────────────────────────────────
@@ -293,7 +432,12 @@ mod tests {
     async fn create_succeed_returns_node_id() {
         let provider = FakeProvider::new();
         let result = provider
-            .create("my-node".into(), &test_offering(), &InstanceConfig::default())
+            .create(
+                "my-node".into(),
+                &test_offering(),
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
             .await;
         assert_eq!(result.unwrap().0, "my-node");
     }
@@ -302,7 +446,12 @@ mod tests {
     async fn create_offering_unavailable() {
         let provider = FakeProvider::new().on_next_create(CreateBehavior::OfferingUnavailable);
         let result = provider
-            .create("n".into(), &test_offering(), &InstanceConfig::default())
+            .create(
+                "n".into(),
+                &test_offering(),
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
             .await;
         assert!(matches!(result, Err(ProviderError::OfferingUnavailable(_))));
     }
@@ -312,7 +461,12 @@ mod tests {
         let provider =
             FakeProvider::new().on_next_create(CreateBehavior::CreationFailed("boom".into()));
         let result = provider
-            .create("n".into(), &test_offering(), &InstanceConfig::default())
+            .create(
+                "n".into(),
+                &test_offering(),
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
             .await;
         assert!(matches!(
             result,
@@ -324,7 +478,12 @@ mod tests {
     async fn create_join_timeout() {
         let provider = FakeProvider::new().on_next_create(CreateBehavior::JoinTimeout);
         let result = provider
-            .create("n".into(), &test_offering(), &InstanceConfig::default())
+            .create(
+                "n".into(),
+                &test_offering(),
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
             .await;
         assert!(matches!(result, Err(ProviderError::JoinTimeout { .. })));
     }
@@ -334,7 +493,12 @@ mod tests {
         let provider =
             FakeProvider::new().on_next_create(CreateBehavior::InternalError("fail".into()));
         let result = provider
-            .create("n".into(), &test_offering(), &InstanceConfig::default())
+            .create(
+                "n".into(),
+                &test_offering(),
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
             .await;
         assert!(matches!(result, Err(ProviderError::Internal(_))));
     }
@@ -344,11 +508,21 @@ mod tests {
         let provider = FakeProvider::new();
         let offering = test_offering();
         provider
-            .create("node-1".into(), &offering, &InstanceConfig::default())
+            .create(
+                "node-1".into(),
+                &offering,
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
             .await
             .unwrap();
         provider
-            .create("node-2".into(), &offering, &InstanceConfig::default())
+            .create(
+                "node-2".into(),
+                &offering,
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
             .await
             .unwrap();
 
@@ -384,6 +558,20 @@ mod tests {
         assert!(provider.delete(&NodeId("n".into())).await.is_ok());
     }
 
+    #[tokio::test]
+    async fn delete_batch_applies_per_node_behaviors() {
+        let provider = FakeProvider::new()
+            .with_max_delete_batch(10)
+            .on_next_delete(DeleteBehavior::Succeed)
+            .on_next_delete(DeleteBehavior::Fail("boom".into()));
+        let ids = vec![NodeId("a".into()), NodeId("b".into())];
+        let results = provider.delete_batch(&ids).await;
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert_eq!(provider.delete_batch_calls(), vec![ids]);
+        assert_eq!(provider.delete_calls().len(), 2);
+    }
+
     // ── Status: behavior → Result mapping ──────────────────────────────
 
     #[tokio::test]
@@ -422,4 +610,72 @@ mod tests {
         let third = provider.offerings().await;
         assert_eq!(third.len(), 0);
     }
+
+    // ── Instances ──────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn list_instances_returns_configured_ids() {
+        let provider =
+            FakeProvider::new().with_instances(vec![NodeId("a".into()), NodeId("b".into())]);
+        let ids = provider.list_instances().await.unwrap();
+        assert_eq!(ids, vec![NodeId("a".into()), NodeId("b".into())]);
+
+        let empty = FakeProvider::new().list_instances().await.unwrap();
+        assert!(empty.is_empty());
+    }
+
+    // ── KWOK nodes ─────────────────────────────────────────────────────
+
+    #[tokio::test]
+    async fn kwok_nodes_are_created_and_deleted() {
+        let (mock_svc, mut handle) = tower_test::mock::pair::<
+            http::Request<kube::client::Body>,
+            http::Response<kube::client::Body>,
+        >();
+        let provider = FakeProvider::new()
+            .on_next_create(CreateBehavior::SucceedButNodeNeverJoins)
+            .with_kwok_nodes(Client::new(mock_svc, "default"));
+        let server = tokio::spawn(async move {
+            let (request, send) = handle.next_request().await.expect("Node create");
+            assert_eq!(request.method(), http::Method::POST);
+            assert_eq!(request.uri().path(), "/api/v1/nodes");
+            let body = request.into_body().collect_bytes().await.unwrap();
+            let node: Node = serde_json::from_slice(&body).unwrap();
+            send.send_response(
+                http::Response::builder()
+                    .body(kube::client::Body::from(body.to_vec()))
+                    .unwrap(),
+            );
+
+            let (request, send) = handle.next_request().await.expect("Node delete");
+            assert_eq!(request.method(), http::Method::DELETE);
+            assert_eq!(request.uri().path(), "/api/v1/nodes/b");
+            let status = r#"{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","reason":"NotFound","code":404}"#;
+            send.send_response(
+                http::Response::builder()
+                    .status(404)
+                    .body(kube::client::Body::from(status.as_bytes().to_vec()))
+                    .unwrap(),
+            );
+            node
+        });
+
+        let (offering, config) = (test_offering(), InstanceConfig::default());
+        let create =
+            |id: &str| provider.create(id.into(), &offering, &config, &ProviderCreateConfig::None);
+        // Never joins: no Node is created.
+        create("a").await.unwrap();
+        create("b").await.unwrap();
+        // Already gone counts as deleted.
+        provider.delete(&NodeId("b".into())).await.unwrap();
+
+        let node = server.await.unwrap();
+        assert_eq!(node.metadata.name.as_deref(), Some("b"));
+        assert!(
+            node.metadata
+                .annotations
+                .unwrap()
+                .contains_key("kwok.x-k8s.io/node")
+        );
+    }
 }
@@ -21,6 +21,8 @@ pub enum CreateBehavior {
     JoinTimeout,
     /// Network/API blowup.
     InternalError(String),
+    /// The provider throttled the call (HTTP 429).
+    RateLimited,
 }
 
 /// What happens on the next `delete()` call.
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use tracing::debug;
+
+use crate::offering::Offering;
+use crate::providers::provider::{
+    InstanceConfig, NodeId, Provider, ProviderCreateConfig, ProviderError, ProviderHealth,
+    ProviderStatus, Quota,
+};
+
+/// Several providers behind a single `Provider`.
+///
+/// Offerings from every member are merged and stamped with the member's name,
+/// so the optimiser picks the cheapest satisfying offering across all of them.
+/// `create()` is routed by `Offering::provider`; `delete()`/`status()` are
+/// routed to whichever member owns the node, learned from creates and
+/// `list_instances()`.
+pub struct FederatedProvider {
+    members: Vec<(String, Arc<dyn Provider>)>,
+    /// NodeId → index into `members`.
+    owners: Mutex<HashMap<NodeId, usize>>,
+}
+
+impl FederatedProvider {
+    /// Federate the given named providers. Names must be unique.
+    pub fn new(members: Vec<(String, Arc<dyn Provider>)>) -> Result<Self, ProviderError> {
+        for (i, (name, _)) in members.iter().enumerate() {
+            if members[..i].iter().any(|(n, _)| n == name) {
+                return Err(ProviderError::Internal(anyhow::anyhow!(
+                    "provider {name:?} listed more than once"
+                )));
+            }
+        }
+        Ok(Self {
+            members,
+            owners: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Names of the federated providers, in configuration order.
+    pub fn member_names(&self) -> impl Iterator<Item = &str> {
+        self.members.iter().map(|(n, _)| n.as_str())
+    }
+
+    fn member_index(&self, name: &str) -> Option<usize> {
+        self.members.iter().position(|(n, _)| n == name)
+    }
+
+    /// Find the member that owns `node_id`, refreshing from every member's
+    /// inventory if it isn't already known.
+    async fn owner_of(&self, node_id: &NodeId) -> Result<Option<usize>, ProviderError> {
+        if let Some(&idx) = self.owners.lock().unwrap().get(node_id) {
+            return Ok(Some(idx));
+        }
+        self.list_instances().await?;
+        Ok(self.owners.lock().unwrap().get(node_id).copied())
+    }
+}
+
+#[async_trait]
+impl Provider for FederatedProvider {
+    fn name(&self) -> &str {
+        "federated"
+    }
+
+    async fn offerings(&self) -> Vec<Offering> {
+        let per_member = join_all(self.members.iter().map(|(_, p)| p.offerings())).await;
+        self.members
+            .iter()
+            .zip(per_member)
+            .flat_map(|((name, _), offerings)| {
+                offerings.into_iter().map(move |mut o| {
+                    o.provider = name.clone();
+                    o
+                })
+            })
+            .collect()
+    }
+
+    async fn create(
+        &self,
+        node_id: String,
+        offering: &Offering,
+        config: &InstanceConfig,
+        provider_config: &ProviderCreateConfig,
+    ) -> Result<NodeId, ProviderError> {
+        let idx = self
+            .member_index(&offering.provider)
+            .ok_or_else(|| ProviderError::UnknownProvider(offering.provider.clone()))?;
+        let created = self.members[idx]
+            .1
+            .create(node_id, offering, config, provider_config)
+            .await?;
+        self.owners.lock().unwrap().insert(created.clone(), idx);
+        Ok(created)
+    }
+
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+        let Some(idx) = self.owner_of(node_id).await? else {
+            return Err(ProviderError::DeletionFailed {
+                message: format!("no federated provider owns {}", node_id.0),
+            });
+        };
+        self.members[idx].1.delete(node_id).await?;
+        self.owners.lock().unwrap().remove(node_id);
+        Ok(())
+    }
+
+    fn max_delete_batch(&self) -> usize {
+        self.members
+            .iter()
+            .map(|(_, p)| p.max_delete_batch())
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Split the batch by owning member and delete each part with one
+    /// `delete_batch` call to that member.
+    async fn delete_batch(&self, node_ids: &[NodeId]) -> Vec<Result<(), ProviderError>> {
+        let mut results: Vec<Option<Result<(), ProviderError>>> =
+            node_ids.iter().map(|_| None).collect();
+        let mut by_member: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (i, node_id) in node_ids.iter().enumerate() {
+            match self.owner_of(node_id).await {
+                Ok(Some(idx)) => by_member.entry(idx).or_default().push(i),
+                Ok(None) => {
+                    results[i] = Some(Err(ProviderError::DeletionFailed {
+                        message: format!("no federated provider owns {}", node_id.0),
+                    }))
+                }
+                Err(e) => results[i] = Some(Err(e)),
+            }
+        }
+        for (idx, positions) in by_member {
+            let ids: Vec<NodeId> = positions.iter().map(|&i| node_ids[i].clone()).collect();
+            let outcomes = self.members[idx].1.delete_batch(&ids).await;
+            for (i, outcome) in positions.into_iter().zip(outcomes) {
+                if outcome.is_ok() {
+                    self.owners.lock().unwrap().remove(&node_ids[i]);
+                }
+                results[i] = Some(outcome);
+            }
+        }
+        results
+            .into_iter()
+            .map(|r| r.expect("every node has an outcome"))
+            .collect()
+    }
+
+    async fn quotas(&self) -> Result<Vec<Quota>, ProviderError> {
+        let mut all = Vec::new();
+        for (name, provider) in &self.members {
+            all.extend(provider.quotas().await?.into_iter().map(|mut q| {
+                q.provider = name.clone();
+                q
+            }));
+        }
+        Ok(all)
+    }
+
+    async fn health(&self) -> Vec<ProviderHealth> {
+        let per_member = join_all(self.members.iter().map(|(_, p)| p.health())).await;
+        self.members
+            .iter()
+            .zip(per_member)
+            .flat_map(|((name, _), health)| {
+                health.into_iter().map(move |mut h| {
+                    h.provider = name.clone();
+                    h
+                })
+            })
+            .collect()
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+        match self.owner_of(node_id).await? {
+            Some(idx) => self.members[idx].1.status(node_id).await,
+            None => Ok(ProviderStatus::NotFound),
+        }
+    }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        let mut all = Vec::new();
+        let mut owners = HashMap::new();
+        for (idx, (name, provider)) in self.members.iter().enumerate() {
+            let instances = provider.list_instances().await?;
+            debug!(provider = %name, count = instances.len(), "listed federated instances");
+            for id in instances {
+                owners.insert(id.clone(), idx);
+                all.push(id);
+            }
+        }
+        *self.owners.lock().unwrap() = owners;
+        Ok(all)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fake::{DeleteBehavior, FakeProvider};
+    use crate::testing::offering;
+
+    fn federate(a: &FakeProvider, b: &FakeProvider) -> FederatedProvider {
+        FederatedProvider::new(vec![
+            ("a".into(), Arc::new(a.clone())),
+            ("b".into(), Arc::new(b.clone())),
+        ])
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn offerings_are_merged_and_tagged() {
+        let a = FakeProvider::new().with_offerings(vec![offering("small").with_cost(0.01).build()]);
+        let b = FakeProvider::new().with_offerings(vec![offering("small").with_cost(0.02).build()]);
+        let fed = federate(&a, &b);
+
+        let offerings = fed.offerings().await;
+        let tags: Vec<_> = offerings.iter().map(|o| o.provider.as_str()).collect();
+        assert_eq!(tags, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn health_is_reported_per_member() {
+        let (a, b) = (FakeProvider::new(), FakeProvider::new());
+        b.set_unhealthy(Some("api down"));
+        let fed = federate(&a, &b);
+
+        let health = fed.health().await;
+        assert_eq!(
+            health,
+            vec![
+                ProviderHealth::healthy("a"),
+                ProviderHealth {
+                    provider: "b".into(),
+                    error: Some("api down".into()),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn create_routes_by_offering_provider() {
+        let a = FakeProvider::new();
+        let b = FakeProvider::new();
+        let fed = federate(&a, &b);
+
+        let mut o = offering("small").with_cost(0.01).build();
+        o.provider = "b".into();
+        fed.create(
+            "n1".into(),
+            &o,
+            &InstanceConfig::default(),
+            &ProviderCreateConfig::None,
+        )
+        .await
+        .unwrap();
+
+        assert!(a.create_calls().is_empty());
+        assert_eq!(b.create_calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn create_rejects_unknown_provider() {
+        let fed = federate(&FakeProvider::new(), &FakeProvider::new());
+        let err = fed
+            .create(
+                "n1".into(),
+                &offering("small").with_cost(0.01).build(),
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProviderError::UnknownProvider(ref n) if n == "fake"));
+    }
+
+    #[tokio::test]
+    async fn delete_and_status_route_to_owner_from_inventory() {
+        let a = FakeProvider::new();
+        let b = FakeProvider::new()
+            .with_instances(vec![NodeId("n-b".into())])
+            .with_default_delete(DeleteBehavior::Succeed);
+        let fed = federate(&a, &b);
+
+        assert_eq!(
+            fed.status(&NodeId("n-b".into())).await.unwrap(),
+            ProviderStatus::Running
+        );
+        fed.delete(&NodeId("n-b".into())).await.unwrap();
+        assert!(a.delete_calls().is_empty());
+        assert_eq!(b.delete_calls().len(), 1);
+
+        assert_eq!(
+            fed.status(&NodeId("unknown".into())).await.unwrap(),
+            ProviderStatus::NotFound
+        );
+        assert!(fed.delete(&NodeId("unknown".into())).await.is_err());
+    }
+
+    #[test]
+    fn duplicate_member_names_rejected() {
+        let p: Arc<dyn Provider> = Arc::new(FakeProvider::new());
+        let result = FederatedProvider::new(vec![("a".into(), p.clone()), ("a".into(), p)]);
+        assert!(result.is_err());
+    }
+}
@@ -1,12 +1,15 @@
 use std::collections::BTreeMap;
 
+use crate::resources::user_data::UserDataTemplate;
+
 /// Hetzner-specific instance configuration resolved from a HetznerNodeClass CRD.
 ///
 /// Contains all the fields needed to create a Hetzner Cloud server that are
-/// NOT part of the generic `InstanceConfig` (which only carries labels).
+/// NOT part of the generic `InstanceConfig`.
 pub struct HetznerCreateConfig {
-    /// Resolved user-data (cloud-init / Talos config) with all variables substituted.
-    pub user_data: Option<String>,
+    /// User-data template (cloud-init / Talos config) with its declared variables
+    /// read, rendered with the node's `InstanceConfig` at create time.
+    pub user_data: Option<UserDataTemplate>,
     /// OS image for the server (e.g. "ubuntu-24.04").
     pub image: String,
     /// Hetzner SSH key names to install on the server.
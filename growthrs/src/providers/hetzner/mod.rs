@@ -1,5 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
+use async_trait::async_trait;
 use hcloud::apis::configuration::Configuration;
 use hcloud::apis::server_types_api::{self, ListServerTypesParams};
 use hcloud::apis::servers_api::{self, CreateServerParams, DeleteServerParams, ListServersParams};
@@ -9,9 +10,8 @@ use hcloud::models::{
 };
 use tracing::{debug, error, info, warn};
 
-use crate::offering::{InstanceType, Location, Offering, Region, Resources};
-use crate::providers::{provider::{InstanceConfig, NodeId, ProviderError, ProviderStatus}};
-use crate::providers::hetzner::config::HetznerCreateConfig;
+use crate::offering::{Arch, InstanceType, Location, Offering, Region, Resources};
+use crate::providers::{provider::{InstanceConfig, NodeId, Provider, ProviderCreateConfig, ProviderError, ProviderHealth, ProviderStatus}};
 pub mod config;
 
 
@@ -37,8 +37,8 @@ pub fn convert_server_type(st: &ServerType) -> Vec<Offering> {
     }
 
     let cpu = st.cores as u32;
-    let memory_mib = (st.memory * 1024.0) as u32;
-    let disk_gib = st.disk as u32;
+    let memory_mib = (st.memory * 1024.0) as u64;
+    let disk_gib = st.disk as u64;
 
     // Build a location→price map from the prices array.
     let price_map: HashMap<&str, f64> = st
@@ -63,14 +63,22 @@ pub fn convert_server_type(st: &ServerType) -> Vec<Offering> {
             let cost = price_map.get(loc.name.as_str()).copied().unwrap_or(0.0);
             Offering {
                 instance_type: InstanceType(st.name.clone()),
+                provider: "hetzner".into(),
                 resources: Resources {
-                    cpu,
+                    cpu_millis: u64::from(cpu) * 1000,
                     memory_mib,
                     ephemeral_storage_gib: Some(disk_gib),
                     gpu: 0,
                     gpu_model: None,
+                    arch: Some(match st.architecture {
+                        hcloud::models::Architecture::Arm => Arch::Arm64,
+                        hcloud::models::Architecture::X86 => Arch::Amd64,
+                    }),
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
                 },
                 cost_per_hour: cost,
+                interruption_risk: 0.0,
                 location: Location {
                     region: Region(loc.name.clone()),
                     zone: None,
@@ -98,13 +106,60 @@ impl From<&Server> for ProviderStatus {
     }
 }
 
+/// Map an hcloud API error, keeping throttling and server/network failures
+/// retryable. Other HTTP errors are built by `otherwise`.
+fn classify_api_error<T>(
+    e: hcloud::apis::Error<T>,
+    context: &str,
+    otherwise: impl FnOnce(String) -> ProviderError,
+) -> ProviderError {
+    match &e {
+        hcloud::apis::Error::ResponseError(c) if c.status.as_u16() == 429 => {
+            ProviderError::RateLimited { retry_after: None }
+        }
+        hcloud::apis::Error::ResponseError(c) if c.status.is_server_error() => {
+            ProviderError::Transient(format!(
+                "{context}: Hetzner API error {}: {}",
+                c.status, c.content
+            ))
+        }
+        hcloud::apis::Error::ResponseError(_) => otherwise(format!("{context}: {e}")),
+        _ => ProviderError::Transient(format!("{context}: {e}")),
+    }
+}
+
 /// Map a Hetzner `Server` to a `ProviderStatus`.
 fn map_server_status(server: &Server) -> ProviderStatus {
     ProviderStatus::from(server)
 }
 
 impl HetznerProvider {
-    pub async fn offerings(&self) -> Vec<Offering> {
+    // Helper function for finding a server for deletion etc.
+    async fn resolve_server_by_name(&self, name: &str) -> Result<Option<Server>, ProviderError> {
+        let params = ListServersParams {
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+
+        let resp = servers_api::list_servers(&self.config, params)
+            .await
+            .map_err(|e| {
+                classify_api_error(e, "list_servers failed", |m| {
+                    ProviderError::Internal(anyhow::anyhow!(m))
+                })
+            })?;
+
+        Ok(resp.servers.into_iter().next())
+    }
+}
+
+#[async_trait]
+impl Provider for HetznerProvider {
+    fn name(&self) -> &str {
+        "hetzner"
+    }
+
+    async fn offerings(&self) -> Vec<Offering> {
         let mut all_offerings = Vec::new();
         let mut page = 1i64;
 
@@ -140,28 +195,27 @@ impl HetznerProvider {
         all_offerings
     }
 
-    // Helper function for finding a server for deletion etc.
-    async fn resolve_server_by_name(&self, name: &str) -> Result<Option<Server>, ProviderError> {
-        let params = ListServersParams {
-            name: Some(name.to_string()),
-            ..Default::default()
-        };
-
-        let resp = servers_api::list_servers(&self.config, params)
-            .await
-            .map_err(|e| ProviderError::Internal(anyhow::anyhow!("list_servers failed: {e}")))?;
-
-        Ok(resp.servers.into_iter().next())
-    }
-
-    pub async fn create(
+    async fn create(
         &self,
         node_id: String,
         offering: &Offering,
         config: &InstanceConfig,
-        hetzner_config: &HetznerCreateConfig,
+        provider_config: &ProviderCreateConfig,
     ) -> Result<NodeId, ProviderError> {
+        let ProviderCreateConfig::Hetzner(hetzner_config) = provider_config else {
+            return Err(ProviderError::MissingConfig {
+                field: "HetznerCreateConfig",
+            });
+        };
         let image = hetzner_config.image.clone();
+        let user_data = hetzner_config
+            .user_data
+            .as_ref()
+            .map(|t| t.render(config, offering))
+            .transpose()
+            .map_err(|e| ProviderError::CreationFailed {
+                message: format!("user-data: {e}"),
+            })?;
 
         let mut labels: HashMap<String, String> = hetzner_config
             .hetzner_labels
@@ -189,7 +243,7 @@ impl HetznerProvider {
             } else {
                 Some(hetzner_config.ssh_key_names.clone())
             },
-            user_data: hetzner_config.user_data.clone(),
+            user_data,
             labels: Some(labels),
             automount: None,
             datacenter: None,
@@ -270,19 +324,28 @@ impl HetznerProvider {
                         "{} unavailable in {} (location may be disabled by Hetzner)",
                         offering.instance_type, offering.location.region.0
                     )))
+                } else if content.status.as_u16() == 429 {
+                    Err(ProviderError::RateLimited { retry_after: None })
+                } else if content.status.is_server_error() {
+                    Err(ProviderError::Transient(format!(
+                        "Hetzner API error {}: {body}",
+                        content.status
+                    )))
                 } else {
                     Err(ProviderError::CreationFailed {
                         message: format!("Hetzner API error {}: {body}", content.status),
                     })
                 }
             }
-            Err(e) => Err(ProviderError::CreationFailed {
-                message: format!("Hetzner create_server failed: {e}"),
-            }),
+            // Network failures and timeouts: retryable, since a 409 on the
+            // retry turns a create that did land into a success.
+            Err(e) => Err(ProviderError::Transient(format!(
+                "Hetzner create_server failed: {e}"
+            ))),
         }
     }
 
-    pub async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
         info!(node_id = %node_id.0, "deleting Hetzner server");
 
         let server = self.resolve_server_by_name(&node_id.0).await?;
@@ -294,21 +357,72 @@ impl HetznerProvider {
         let params = DeleteServerParams { id: server.id };
         servers_api::delete_server(&self.config, params)
             .await
-            .map_err(|e| ProviderError::DeletionFailed {
-                message: format!("Hetzner delete_server failed: {e}"),
+            .map_err(|e| {
+                classify_api_error(e, "Hetzner delete_server failed", |message| {
+                    ProviderError::DeletionFailed { message }
+                })
             })?;
 
         debug!(node_id = %node_id.0, server_id = server.id, "Hetzner server deleted");
         Ok(())
     }
 
-    pub async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+    /// Lists a single server type: cheap, but authenticated, so a revoked
+    /// token shows up as unhealthy too.
+    async fn health(&self) -> Vec<ProviderHealth> {
+        let params = ListServerTypesParams {
+            name: None,
+            page: Some(1),
+            per_page: Some(1),
+        };
+        let probe = server_types_api::list_server_types(&self.config, params)
+            .await
+            .map_err(|e| {
+                classify_api_error(e, "list_server_types failed", |m| {
+                    ProviderError::Internal(anyhow::anyhow!(m))
+                })
+            });
+        vec![ProviderHealth::from_probe(self.name(), probe)]
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
         let server = self.resolve_server_by_name(&node_id.0).await?;
         match server {
             Some(s) => Ok(map_server_status(&s)),
             None => Ok(ProviderStatus::NotFound),
         }
     }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        let mut instances = Vec::new();
+        let mut page = 1i64;
+
+        loop {
+            let params = ListServersParams {
+                label_selector: Some("managed-by=growth".to_string()),
+                page: Some(page),
+                per_page: Some(50),
+                ..Default::default()
+            };
+
+            let resp = servers_api::list_servers(&self.config, params)
+                .await
+                .map_err(|e| {
+                    classify_api_error(e, "list_servers failed", |m| {
+                        ProviderError::Internal(anyhow::anyhow!(m))
+                    })
+                })?;
+            let count = resp.servers.len();
+            instances.extend(resp.servers.into_iter().map(|s| NodeId(s.name)));
+            if count < 50 {
+                break;
+            }
+            page += 1;
+        }
+
+        debug!(count = instances.len(), "listed Hetzner servers");
+        Ok(instances)
+    }
 }
 
 #[cfg(test)]
@@ -356,7 +470,7 @@ mod tests {
         assert_eq!(offerings.len(), 1);
         let o = &offerings[0];
         assert_eq!(o.instance_type.0, "cpx22");
-        assert_eq!(o.resources.cpu, 2);
+        assert_eq!(o.resources.cpu_millis, 2000);
         assert_eq!(o.resources.memory_mib, 4096);
         assert_eq!(o.resources.ephemeral_storage_gib, Some(40));
         assert_eq!(o.location.region.0, "fsn1");
@@ -0,0 +1,224 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+
+use crate::offering::Offering;
+use crate::providers::provider::{
+    InstanceConfig, NodeId, Provider, ProviderCreateConfig, ProviderError, ProviderHealth,
+    ProviderStatus, Quota,
+};
+
+/// Buckets for provider call latency, in seconds: 50ms up to two minutes.
+const CALL_LATENCY_BUCKETS: &[f64] =
+    &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// Call counts and latencies for every provider, labelled by provider name
+/// and operation.
+pub struct ProviderMetrics {
+    /// Calls by `provider`, `operation` and `result` (`ok`, or the
+    /// [`ProviderError::class`] of the failure).
+    calls: IntCounterVec,
+    latency: HistogramVec,
+}
+
+impl ProviderMetrics {
+    pub fn new() -> Self {
+        let calls = IntCounterVec::new(
+            Opts::new(
+                "growth_provider_calls_total",
+                "Provider calls by provider, operation and result",
+            ),
+            &["provider", "operation", "result"],
+        )
+        .expect("valid counter options");
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "growth_provider_call_duration_seconds",
+                "Latency of individual provider calls",
+            )
+            .buckets(CALL_LATENCY_BUCKETS.to_vec()),
+            &["provider", "operation"],
+        )
+        .expect("valid histogram options");
+        Self { calls, latency }
+    }
+
+    pub fn register(&self, registry: &Registry) -> prometheus::Result<()> {
+        registry.register(Box::new(self.calls.clone()))?;
+        registry.register(Box::new(self.latency.clone()))
+    }
+
+    fn observe(&self, provider: &str, operation: &str, result: &str, started: Instant) {
+        self.calls
+            .with_label_values(&[provider, operation, result])
+            .inc();
+        self.latency
+            .with_label_values(&[provider, operation])
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    /// Calls recorded so far for one label set.
+    pub fn calls(&self, provider: &str, operation: &str, result: &str) -> u64 {
+        self.calls
+            .with_label_values(&[provider, operation, result])
+            .get()
+    }
+}
+
+impl Default for ProviderMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn result_label<T>(result: &Result<T, ProviderError>) -> &'static str {
+    match result {
+        Ok(_) => "ok",
+        Err(e) => e.class(),
+    }
+}
+
+/// Records the count, outcome and latency of another provider's
+/// `offerings()`, `create()` and `delete()` calls.
+///
+/// Sits directly around each registered provider, inside the retries, so
+/// every attempt is measured on its own and slow providers can be told apart
+/// from a slow solve.
+pub struct InstrumentedProvider {
+    name: String,
+    inner: Arc<dyn Provider>,
+    metrics: Arc<ProviderMetrics>,
+}
+
+impl InstrumentedProvider {
+    /// Record calls to `inner` under the provider label `name`.
+    pub fn new(name: &str, inner: Arc<dyn Provider>, metrics: Arc<ProviderMetrics>) -> Self {
+        Self {
+            name: name.to_string(),
+            inner,
+            metrics,
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for InstrumentedProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    /// Providers return an empty list when their API fails, so that is
+    /// recorded as `empty` rather than `ok`.
+    async fn offerings(&self) -> Vec<Offering> {
+        let started = Instant::now();
+        let offerings = self.inner.offerings().await;
+        let result = if offerings.is_empty() { "empty" } else { "ok" };
+        self.metrics
+            .observe(&self.name, "offerings", result, started);
+        offerings
+    }
+
+    async fn create(
+        &self,
+        node_id: String,
+        offering: &Offering,
+        config: &InstanceConfig,
+        provider_config: &ProviderCreateConfig,
+    ) -> Result<NodeId, ProviderError> {
+        let started = Instant::now();
+        let result = self
+            .inner
+            .create(node_id, offering, config, provider_config)
+            .await;
+        self.metrics
+            .observe(&self.name, "create", result_label(&result), started);
+        result
+    }
+
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+        let started = Instant::now();
+        let result = self.inner.delete(node_id).await;
+        self.metrics
+            .observe(&self.name, "delete", result_label(&result), started);
+        result
+    }
+
+    fn max_delete_batch(&self) -> usize {
+        self.inner.max_delete_batch()
+    }
+
+    /// Recorded as one `delete` per node, each with the latency of the whole
+    /// batch.
+    async fn delete_batch(&self, node_ids: &[NodeId]) -> Vec<Result<(), ProviderError>> {
+        let started = Instant::now();
+        let results = self.inner.delete_batch(node_ids).await;
+        for result in &results {
+            self.metrics
+                .observe(&self.name, "delete", result_label(result), started);
+        }
+        results
+    }
+
+    async fn quotas(&self) -> Result<Vec<Quota>, ProviderError> {
+        self.inner.quotas().await
+    }
+
+    async fn health(&self) -> Vec<ProviderHealth> {
+        self.inner.health().await
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+        self.inner.status(node_id).await
+    }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        self.inner.list_instances().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fake::{CreateBehavior, DeleteBehavior, FakeProvider};
+    use crate::testing::offering;
+
+    #[tokio::test]
+    async fn calls_are_counted_by_operation_and_error_class() {
+        let fake = FakeProvider::new()
+            .with_offerings(vec![offering("cpx22").build()])
+            .on_next_create(CreateBehavior::CreationFailed("quota".into()))
+            .on_next_delete(DeleteBehavior::Succeed)
+            .on_next_delete(DeleteBehavior::Fail("boom".into()));
+        let metrics = Arc::new(ProviderMetrics::new());
+        let provider = InstrumentedProvider::new("hetzner", Arc::new(fake), metrics.clone());
+
+        provider.offerings().await;
+        let _ = provider
+            .create(
+                "n1".into(),
+                &offering("cpx22").build(),
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
+            .await;
+        provider
+            .delete_batch(&[NodeId("a".into()), NodeId("b".into())])
+            .await;
+
+        assert_eq!(metrics.calls("hetzner", "offerings", "ok"), 1);
+        assert_eq!(metrics.calls("hetzner", "create", "creation_failed"), 1);
+        assert_eq!(metrics.calls("hetzner", "delete", "ok"), 1);
+        assert_eq!(metrics.calls("hetzner", "delete", "deletion_failed"), 1);
+
+        let registry = Registry::new();
+        metrics.register(&registry).unwrap();
+        let families = registry.gather();
+        let latency = families
+            .iter()
+            .find(|f| f.name() == "growth_provider_call_duration_seconds")
+            .unwrap();
+        assert_eq!(latency.get_metric().len(), 3);
+    }
+}
@@ -1,16 +1,177 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use k8s_openapi::api::core::v1::{Node, NodeSpec, NodeStatus, Taint};
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::{ConfigMap, Node, NodeSpec, NodeStatus, Taint};
 use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
-use kube::api::{DeleteParams, ObjectMeta, PostParams};
+use kube::api::{DeleteParams, ListParams, ObjectMeta, PostParams};
 use kube::{Api, Client};
-use tracing::{debug, info};
+use rand::Rng;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
 
 use crate::offering::{
-    GpuModel, InstanceType, Location, MANAGED_BY_LABEL, MANAGED_BY_VALUE, Offering, Region,
-    Resources, STARTUP_TAINT_KEY, Zone,
+    ARCH_LABEL, Arch, GpuModel, InstanceType, Location, MANAGED_BY_LABEL, MANAGED_BY_SELECTOR,
+    MANAGED_BY_VALUE, MIG_RESOURCE_PREFIX, Offering, REGION_LABEL, Region, Resources,
+    STARTUP_TAINT_KEY, ZONE_LABEL, Zone,
 };
-use crate::providers::provider::{InstanceConfig, NodeId, ProviderError, ProviderStatus};
+use crate::providers::provider::{
+    InstanceConfig, NodeId, Provider, ProviderCreateConfig, ProviderError, ProviderHealth,
+    ProviderStatus,
+};
+
+/// Well-known node label holding the node's hostname; KWOK nodes carry their
+/// name here so a batch can be selected by label.
+const HOSTNAME_LABEL: &str = "kubernetes.io/hostname";
+/// Nodes deleted per `delete_collection` call, keeping the selector short.
+const KWOK_DELETE_BATCH: usize = 100;
+
+/// Simulated provisioning behaviour for [`KwokProvider`]. The default
+/// creates every node immediately.
+#[derive(Debug, Clone, Default)]
+pub struct KwokOptions {
+    /// How long after `create()` the Node appears, like a VM booting.
+    pub join_delay: Duration,
+    /// Percentage (0–100) of creates whose Node never appears, as if the
+    /// VM failed to join.
+    pub join_failure_percent: f64,
+    /// Where to read offerings from instead of the built-in catalogue.
+    pub catalog: Option<KwokCatalog>,
+}
+
+/// Default ConfigMap key holding a catalog.
+const CATALOG_KEY: &str = "catalog.yaml";
+
+/// A replacement offering catalog for [`KwokProvider`], in the YAML format
+/// of [`parse_catalog`]. Re-read on every `offerings()` call, so edits apply
+/// once the offerings cache expires.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KwokCatalog {
+    File(PathBuf),
+    ConfigMap {
+        namespace: String,
+        name: String,
+        key: String,
+    },
+}
+
+impl KwokCatalog {
+    /// Parse `configmap:<namespace>/<name>[/<key>]` (key defaults to
+    /// `catalog.yaml`), or anything else as a file path.
+    pub fn parse(s: &str) -> Option<Self> {
+        let Some(reference) = s.strip_prefix("configmap:") else {
+            return Some(Self::File(s.into()));
+        };
+        let mut parts = reference.split('/');
+        let (Some(namespace), Some(name)) = (parts.next(), parts.next()) else {
+            return None;
+        };
+        let key = parts.next().unwrap_or(CATALOG_KEY);
+        if namespace.is_empty() || name.is_empty() || parts.next().is_some() {
+            return None;
+        }
+        Some(Self::ConfigMap {
+            namespace: namespace.into(),
+            name: name.into(),
+            key: key.into(),
+        })
+    }
+}
+
+/// One instance type in a catalog.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CatalogEntry {
+    instance_type: String,
+    cpu: u32,
+    memory_mib: u64,
+    #[serde(default)]
+    ephemeral_storage_gib: Option<u64>,
+    #[serde(default)]
+    gpu: u32,
+    #[serde(default)]
+    gpu_model: Option<GpuModel>,
+    /// Defaults to amd64.
+    #[serde(default)]
+    arch: Option<Arch>,
+    /// MIG profile → slices per node, e.g. `{1g.5gb: 7}`.
+    #[serde(default)]
+    mig: BTreeMap<String, u32>,
+    /// Other extended resources per node, e.g. `{xilinx.com/u30: 2}`.
+    #[serde(default)]
+    extended: BTreeMap<String, u64>,
+    cost_per_hour: f64,
+    /// Marks the type as spot capacity with this interruption risk (0–1).
+    #[serde(default)]
+    interruption_risk: f64,
+    region: String,
+    /// One offering is listed per zone; none means a zoneless offering.
+    #[serde(default)]
+    zones: Vec<String>,
+}
+
+/// Parse a catalog: a YAML list of instance types, e.g.
+///
+/// ```yaml
+/// - instanceType: m5.large
+///   cpu: 2
+///   memoryMib: 8192
+///   costPerHour: 0.096
+///   region: us-east-1
+///   zones: [us-east-1a, us-east-1b]
+/// ```
+///
+/// `ephemeralStorageGib`, `gpu`, `gpuModel`, `arch` (`amd64` or `arm64`),
+/// `mig` (MIG profile → slices, e.g. `{1g.5gb: 7}`), `extended` (other
+/// extended resources, e.g. `{xilinx.com/u30: 2}`) and `interruptionRisk`
+/// (0–1, to simulate spot capacity) are optional.
+pub fn parse_catalog(yaml: &str) -> Result<Vec<Offering>, serde_yaml::Error> {
+    let entries: Vec<CatalogEntry> = serde_yaml::from_str(yaml)?;
+    let mut offerings = Vec::new();
+    for entry in entries {
+        let zones: Vec<Option<Zone>> = if entry.zones.is_empty() {
+            vec![None]
+        } else {
+            entry.zones.iter().map(|z| Some(Zone(z.clone()))).collect()
+        };
+        for zone in zones {
+            let offering = gpu_offering(
+                &entry.instance_type,
+                Resources {
+                    cpu_millis: u64::from(entry.cpu) * 1000,
+                    memory_mib: entry.memory_mib,
+                    ephemeral_storage_gib: entry.ephemeral_storage_gib,
+                    gpu: entry.gpu,
+                    gpu_model: entry.gpu_model.clone(),
+                    arch: Some(entry.arch.unwrap_or(Arch::Amd64)),
+                    mig: entry.mig.clone(),
+                    extended: entry.extended.clone(),
+                },
+                entry.cost_per_hour,
+                Location {
+                    region: Region(entry.region.clone()),
+                    zone,
+                },
+            );
+            offerings.push(Offering {
+                interruption_risk: entry.interruption_risk,
+                ..offering
+            });
+        }
+    }
+    Ok(offerings)
+}
+
+/// A node `create()` accepted that isn't in the API server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Simulated {
+    /// Waiting out `join_delay`.
+    Joining,
+    /// Will never join.
+    Failed,
+}
 
 /// Hetzner-like zone names used by the KWOK provider for testing.
 const ZONES: &[(&str, &str)] = &[
@@ -22,21 +183,31 @@ const ZONES: &[(&str, &str)] = &[
 fn offering(
     name: &str,
     cpu: u32,
-    memory_mib: u32,
-    disk_gib: u32,
+    memory_mib: u64,
+    disk_gib: u64,
     cost_per_hour: f64,
     location: Location,
 ) -> Offering {
     Offering {
         instance_type: InstanceType(name.into()),
+        provider: "kwok".into(),
         resources: Resources {
-            cpu,
+            cpu_millis: u64::from(cpu) * 1000,
             memory_mib,
             ephemeral_storage_gib: Some(disk_gib),
             gpu: 0,
             gpu_model: None,
+            // Hetzner's CAX line is the only ARM one.
+            arch: Some(if name.starts_with("cax") {
+                Arch::Arm64
+            } else {
+                Arch::Amd64
+            }),
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         },
         cost_per_hour,
+        interruption_risk: 0.0,
         location,
     }
 }
@@ -49,15 +220,17 @@ fn gpu_offering(
 ) -> Offering {
     Offering {
         instance_type: InstanceType(name.into()),
+        provider: "kwok".into(),
         resources,
         cost_per_hour,
+        interruption_risk: 0.0,
         location,
     }
 }
 
 pub(crate) fn to_capacity(res: &Resources) -> BTreeMap<String, Quantity> {
     let mut cap = BTreeMap::from([
-        ("cpu".into(), Quantity(res.cpu.to_string())),
+        ("cpu".into(), Quantity(format!("{}m", res.cpu_millis))),
         ("memory".into(), Quantity(format!("{}Mi", res.memory_mib))),
     ]);
     if let Some(gib) = res.ephemeral_storage_gib {
@@ -66,145 +239,269 @@ pub(crate) fn to_capacity(res: &Resources) -> BTreeMap<String, Quantity> {
     if res.gpu > 0 {
         cap.insert("nvidia.com/gpu".into(), Quantity(res.gpu.to_string()));
     }
+    for (profile, n) in &res.mig {
+        cap.insert(
+            format!("{MIG_RESOURCE_PREFIX}{profile}"),
+            Quantity(n.to_string()),
+        );
+    }
+    for (name, n) in &res.extended {
+        cap.insert(name.clone(), Quantity(n.to_string()));
+    }
     cap
 }
 
+/// The static KWOK catalogue: Hetzner-like CPU types plus fictional GPU
+/// types, offered in every zone. Usable without a cluster (e.g. simulation).
+pub fn kwok_offerings() -> Vec<Offering> {
+    /// (name, cpu, mem_mib, disk_gib, cost/hr)
+    const CPU_TYPES: &[(&str, u32, u64, u64, f64)] = &[
+        // CX – Shared x86
+        ("cpx22", 2, 4_096, 40, 0.0066),
+        ("cx32", 4, 8_192, 80, 0.0106),
+        ("cx42", 8, 16_384, 160, 0.0170),
+        ("cx52", 16, 32_768, 320, 0.0314),
+        // CPX – Shared AMD
+        ("cpx12", 2, 2_048, 40, 0.0122),
+        ("cpx22", 3, 4_096, 80, 0.0226),
+        ("cpx32", 4, 8_192, 160, 0.0299),
+        ("cpx42", 8, 16_384, 256, 0.0362),
+        ("cpx52", 16, 32_768, 360, 0.0515),
+        // CAX – ARM (Ampere)
+        ("cax11", 2, 4_096, 40, 0.0074),
+        ("cax21", 4, 8_192, 80, 0.0122),
+        ("cax31", 8, 16_384, 160, 0.0226),
+        ("cax41", 16, 32_768, 320, 0.0443),
+        // CCX – Dedicated x86
+        ("ccx13", 2, 8_192, 80, 0.0386),
+        ("ccx23", 4, 16_384, 160, 0.0475),
+        ("ccx33", 8, 32_768, 240, 0.0900),
+        ("ccx43", 16, 65_536, 360, 0.1789),
+        ("ccx53", 32, 131_072, 600, 0.3568),
+        ("ccx63", 48, 196_608, 960, 0.5347),
+    ];
+
+    /// (name, cpu, mem_mib, disk_gib, gpu, model, cost/hr)
+    const GPU_TYPES: &[(&str, u32, u64, u64, u32, f64)] = &[
+        ("gpu-a100-1", 12, 131_072, 200, 1, 2.21),
+        ("gpu-a100-4", 48, 524_288, 800, 4, 8.84),
+    ];
+
+    let mut offerings = Vec::new();
+
+    for &(region, zone) in ZONES {
+        let loc = Location {
+            region: Region(region.into()),
+            zone: Some(Zone(zone.into())),
+        };
+        for &(name, cpu, mem, disk, cost) in CPU_TYPES {
+            offerings.push(offering(name, cpu, mem, disk, cost, loc.clone()));
+        }
+        for &(name, cpu, mem, disk, gpu, cost) in GPU_TYPES {
+            offerings.push(gpu_offering(
+                name,
+                Resources {
+                    cpu_millis: u64::from(cpu) * 1000,
+                    memory_mib: mem,
+                    ephemeral_storage_gib: Some(disk),
+                    gpu,
+                    gpu_model: Some(GpuModel::NvidiaA100),
+                    arch: Some(Arch::Amd64),
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
+                },
+                cost,
+                loc.clone(),
+            ));
+        }
+    }
+
+    offerings
+}
+
 /// Kubernetes With Out Kubelet Provider
 pub struct KwokProvider {
     client: Client,
+    options: KwokOptions,
+    /// Nodes accepted by `create()` that don't exist (yet).
+    simulated: Arc<Mutex<HashMap<String, Simulated>>>,
 }
 
 impl KwokProvider {
     pub fn new(client: Client) -> Self {
-        Self { client }
+        Self::with_options(client, KwokOptions::default())
     }
-}
 
-impl KwokProvider {
-    pub async fn offerings(&self) -> Vec<Offering> {
-        /// (name, cpu, mem_mib, disk_gib, cost/hr)
-        const CPU_TYPES: &[(&str, u32, u32, u32, f64)] = &[
-            // CX – Shared x86
-            ("cpx22", 2, 4_096, 40, 0.0066),
-            ("cx32", 4, 8_192, 80, 0.0106),
-            ("cx42", 8, 16_384, 160, 0.0170),
-            ("cx52", 16, 32_768, 320, 0.0314),
-            // CPX – Shared AMD
-            ("cpx12", 2, 2_048, 40, 0.0122),
-            ("cpx22", 3, 4_096, 80, 0.0226),
-            ("cpx32", 4, 8_192, 160, 0.0299),
-            ("cpx42", 8, 16_384, 256, 0.0362),
-            ("cpx52", 16, 32_768, 360, 0.0515),
-            // CAX – ARM (Ampere)
-            ("cax11", 2, 4_096, 40, 0.0074),
-            ("cax21", 4, 8_192, 80, 0.0122),
-            ("cax31", 8, 16_384, 160, 0.0226),
-            ("cax41", 16, 32_768, 320, 0.0443),
-            // CCX – Dedicated x86
-            ("ccx13", 2, 8_192, 80, 0.0386),
-            ("ccx23", 4, 16_384, 160, 0.0475),
-            ("ccx33", 8, 32_768, 240, 0.0900),
-            ("ccx43", 16, 65_536, 360, 0.1789),
-            ("ccx53", 32, 131_072, 600, 0.3568),
-            ("ccx63", 48, 196_608, 960, 0.5347),
-        ];
-
-        /// (name, cpu, mem_mib, disk_gib, gpu, model, cost/hr)
-        const GPU_TYPES: &[(&str, u32, u32, u32, u32, f64)] = &[
-            ("gpu-a100-1", 12, 131_072, 200, 1, 2.21),
-            ("gpu-a100-4", 48, 524_288, 800, 4, 8.84),
-        ];
-
-        let mut offerings = Vec::new();
-
-        for &(region, zone) in ZONES {
-            let loc = Location {
-                region: Region(region.into()),
-                zone: Some(Zone(zone.into())),
-            };
-            for &(name, cpu, mem, disk, cost) in CPU_TYPES {
-                offerings.push(offering(name, cpu, mem, disk, cost, loc.clone()));
-            }
-            for &(name, cpu, mem, disk, gpu, cost) in GPU_TYPES {
-                offerings.push(gpu_offering(
-                    name,
-                    Resources {
-                        cpu,
-                        memory_mib: mem,
-                        ephemeral_storage_gib: Some(disk),
-                        gpu,
-                        gpu_model: Some(GpuModel::NvidiaA100),
-                    },
-                    cost,
-                    loc.clone(),
-                ));
-            }
+    pub fn with_options(client: Client, options: KwokOptions) -> Self {
+        Self {
+            client,
+            options,
+            simulated: Arc::new(Mutex::new(HashMap::new())),
         }
+    }
+
+    fn simulated(&self, node_id: &NodeId) -> Option<Simulated> {
+        self.simulated.lock().unwrap().get(&node_id.0).copied()
+    }
 
-        offerings
+    async fn load_catalog(&self, catalog: &KwokCatalog) -> Result<Vec<Offering>, ProviderError> {
+        let yaml = match catalog {
+            KwokCatalog::File(path) => std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("reading {}: {e}", path.display()))?,
+            KwokCatalog::ConfigMap {
+                namespace,
+                name,
+                key,
+            } => {
+                let api: Api<ConfigMap> = Api::namespaced(self.client.clone(), namespace);
+                api.get(name)
+                    .await
+                    .map_err(|e| ProviderError::Internal(e.into()))?
+                    .data
+                    .and_then(|mut data| data.remove(key))
+                    .ok_or_else(|| anyhow::anyhow!("ConfigMap {namespace}/{name} has no {key}"))?
+            }
+        };
+        parse_catalog(&yaml).map_err(|e| ProviderError::Internal(e.into()))
     }
-    pub async fn create(
+}
+
+/// The fake Node KWOK manages for `node_id`, registered with its startup
+/// taint.
+pub(crate) fn kwok_node(node_id: String, offering: &Offering, config: &InstanceConfig) -> Node {
+    let mut capacity = to_capacity(&offering.resources);
+    capacity.insert("pods".into(), Quantity("110".into()));
+    let allocatable = capacity.clone();
+
+    let mut labels = BTreeMap::from([
+        ("type".into(), "kwok".into()),
+        (MANAGED_BY_LABEL.into(), MANAGED_BY_VALUE.into()),
+        (REGION_LABEL.into(), offering.location.region.0.clone()),
+        (HOSTNAME_LABEL.into(), node_id.clone()),
+    ]);
+    if let Some(zone) = &offering.location.zone {
+        labels.insert(ZONE_LABEL.into(), zone.0.clone());
+    }
+    if let Some(arch) = offering.resources.arch {
+        labels.insert(ARCH_LABEL.into(), arch.as_label().into());
+    }
+    labels.extend(config.labels.clone());
+
+    Node {
+        metadata: ObjectMeta {
+            name: Some(node_id),
+            labels: Some(labels),
+            annotations: Some(BTreeMap::from([(
+                "kwok.x-k8s.io/node".into(),
+                "fake".into(),
+            )])),
+            ..Default::default()
+        },
+        status: Some(NodeStatus {
+            capacity: Some(capacity),
+            allocatable: Some(allocatable),
+            ..Default::default()
+        }),
+        spec: Some(NodeSpec {
+            taints: Some(vec![Taint {
+                key: STARTUP_TAINT_KEY.into(),
+                value: None,
+                effect: "NoExecute".into(),
+                time_added: None,
+            }]),
+            ..Default::default()
+        }),
+    }
+}
+
+#[async_trait]
+impl Provider for KwokProvider {
+    fn name(&self) -> &str {
+        "kwok"
+    }
+
+    async fn offerings(&self) -> Vec<Offering> {
+        let Some(catalog) = &self.options.catalog else {
+            return kwok_offerings();
+        };
+        self.load_catalog(catalog).await.unwrap_or_else(|e| {
+            warn!(error = %e, ?catalog, "failed to load KWOK catalog");
+            Vec::new()
+        })
+    }
+    async fn create(
         &self,
         node_id: String,
         offering: &Offering,
         config: &InstanceConfig,
+        _provider_config: &ProviderCreateConfig,
     ) -> Result<NodeId, ProviderError> {
         info!(
             node_id = %node_id,
             instance_type = %offering.instance_type,
-            cpu = offering.resources.cpu,
+            cpu_millis = offering.resources.cpu_millis,
             memory_mib = offering.resources.memory_mib,
             "creating KWOK node"
         );
-        let mut capacity = to_capacity(&offering.resources);
-        capacity.insert("pods".into(), Quantity("110".into()));
-        let allocatable = capacity.clone();
-
-        let mut labels = BTreeMap::from([
-            ("type".into(), "kwok".into()),
-            (MANAGED_BY_LABEL.into(), MANAGED_BY_VALUE.into()),
-        ]);
-        labels.extend(config.labels.clone());
+        let failure = self.options.join_failure_percent / 100.0;
+        if failure > 0.0 && rand::thread_rng().gen_bool(failure.min(1.0)) {
+            info!(node_id = %node_id, "simulating a KWOK node that never joins");
+            self.simulated
+                .lock()
+                .unwrap()
+                .insert(node_id.clone(), Simulated::Failed);
+            return Ok(NodeId(node_id));
+        }
 
+        let node = kwok_node(node_id.clone(), offering, config);
         let nodes: Api<Node> = Api::all(self.client.clone());
-        let node = Node {
-            metadata: ObjectMeta {
-                name: Some(node_id),
-                labels: Some(labels),
-                annotations: Some(BTreeMap::from([(
-                    "kwok.x-k8s.io/node".into(),
-                    "fake".into(),
-                )])),
-                ..Default::default()
-            },
-            status: Some(NodeStatus {
-                capacity: Some(capacity),
-                allocatable: Some(allocatable),
-                ..Default::default()
-            }),
-            spec: Some(NodeSpec {
-                taints: Some(vec![Taint {
-                    key: STARTUP_TAINT_KEY.into(),
-                    value: None,
-                    effect: "NoExecute".into(),
-                    time_added: None,
-                }]),
-                ..Default::default()
-            }),
-        };
-        let created = nodes
-            .create(&PostParams::default(), &node)
-            .await
-            .map_err(|e| ProviderError::CreationFailed {
-                message: e.to_string(),
-            })?;
-        let name = created.metadata.name.unwrap();
-        debug!(node_id = %name, "KWOK node created");
-        Ok(NodeId(name))
+        if self.options.join_delay.is_zero() {
+            let created = nodes
+                .create(&PostParams::default(), &node)
+                .await
+                .map_err(|e| ProviderError::CreationFailed {
+                    message: e.to_string(),
+                })?;
+            let name = created.metadata.name.unwrap();
+            debug!(node_id = %name, "KWOK node created");
+            return Ok(NodeId(name));
+        }
+
+        self.simulated
+            .lock()
+            .unwrap()
+            .insert(node_id.clone(), Simulated::Joining);
+        let delay = self.options.join_delay;
+        let simulated = self.simulated.clone();
+        let id = node_id.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            // Deleted while joining: don't create it.
+            if !simulated.lock().unwrap().contains_key(&id) {
+                return;
+            }
+            let created = nodes.create(&PostParams::default(), &node).await;
+            let deleted = simulated.lock().unwrap().remove(&id).is_none();
+            match created {
+                // Deleted while the create was in flight: undo it.
+                Ok(_) if deleted => {
+                    debug!(node_id = %id, "delayed KWOK node deleted while joining, removing it");
+                    if let Err(e) = nodes.delete(&id, &DeleteParams::default()).await {
+                        warn!(node_id = %id, error = %e, "failed to remove delayed KWOK node");
+                    }
+                }
+                Ok(_) => debug!(node_id = %id, "delayed KWOK node created"),
+                Err(e) => warn!(node_id = %id, error = %e, "failed to create delayed KWOK node"),
+            }
+        });
+        Ok(NodeId(node_id))
     }
 
-    pub async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
         info!(node_id = %node_id.0, "deleting KWOK node");
+        if self.simulated.lock().unwrap().remove(&node_id.0).is_some() {
+            return Ok(());
+        }
         let nodes: Api<Node> = Api::all(self.client.clone());
         nodes
             .delete(&node_id.0, &DeleteParams::default())
@@ -215,7 +512,92 @@ impl KwokProvider {
         Ok(())
     }
 
-    pub async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+    fn max_delete_batch(&self) -> usize {
+        KWOK_DELETE_BATCH
+    }
+
+    /// Delete the batch with one `delete_collection` call selecting nodes by
+    /// hostname. Any the selector missed are deleted one by one.
+    async fn delete_batch(&self, node_ids: &[NodeId]) -> Vec<Result<(), ProviderError>> {
+        info!(nodes = node_ids.len(), "deleting KWOK nodes in batch");
+        // Simulated nodes have no Node object to delete.
+        let simulated: HashSet<&str> = {
+            let mut sim = self.simulated.lock().unwrap();
+            node_ids
+                .iter()
+                .filter(|id| sim.remove(&id.0).is_some())
+                .map(|id| id.0.as_str())
+                .collect()
+        };
+        let names: Vec<&str> = node_ids
+            .iter()
+            .map(|id| id.0.as_str())
+            .filter(|name| !simulated.contains(name))
+            .collect();
+        if names.is_empty() {
+            return node_ids.iter().map(|_| Ok(())).collect();
+        }
+        let nodes: Api<Node> = Api::all(self.client.clone());
+        let selector = format!(
+            "{MANAGED_BY_SELECTOR},{HOSTNAME_LABEL} in ({})",
+            names.join(",")
+        );
+        let deleted: HashSet<String> = match nodes
+            .delete_collection(
+                &DeleteParams::default(),
+                &ListParams::default().labels(&selector),
+            )
+            .await
+        {
+            Ok(result) => result
+                .left()
+                .map(|list| {
+                    list.items
+                        .into_iter()
+                        .filter_map(|n| n.metadata.name)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Err(e) => {
+                let message = e.to_string();
+                return node_ids
+                    .iter()
+                    .map(|_| {
+                        Err(ProviderError::DeletionFailed {
+                            message: message.clone(),
+                        })
+                    })
+                    .collect();
+            }
+        };
+        let mut results = Vec::with_capacity(node_ids.len());
+        for node_id in node_ids {
+            if deleted.contains(&node_id.0) || simulated.contains(node_id.0.as_str()) {
+                results.push(Ok(()));
+            } else {
+                results.push(self.delete(node_id).await);
+            }
+        }
+        results
+    }
+
+    /// KWOK nodes live in the API server, so the probe is a one-item list.
+    async fn health(&self) -> Vec<ProviderHealth> {
+        let nodes: Api<Node> = Api::all(self.client.clone());
+        let probe = nodes
+            .list(&ListParams::default().limit(1))
+            .await
+            .map_err(|e| ProviderError::Internal(e.into()));
+        vec![ProviderHealth::from_probe(self.name(), probe)]
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+        match self.simulated(node_id) {
+            Some(Simulated::Joining) => return Ok(ProviderStatus::Creating),
+            // The "VM" runs, it just never registers.
+            Some(Simulated::Failed) => return Ok(ProviderStatus::Running),
+            None => {}
+        }
         let nodes: Api<Node> = Api::all(self.client.clone());
         match nodes
             .get_opt(&node_id.0)
@@ -226,4 +608,165 @@ impl KwokProvider {
             None => Ok(ProviderStatus::NotFound),
         }
     }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        let nodes: Api<Node> = Api::all(self.client.clone());
+        let list = nodes
+            .list(&ListParams::default().labels(MANAGED_BY_SELECTOR))
+            .await
+            .map_err(|e| ProviderError::Internal(e.into()))?;
+        let mut instances: Vec<NodeId> = list
+            .into_iter()
+            .filter_map(|n| n.metadata.name.map(NodeId))
+            .collect();
+        for name in self.simulated.lock().unwrap().keys() {
+            if !instances.iter().any(|id| &id.0 == name) {
+                instances.push(NodeId(name.clone()));
+            }
+        }
+        Ok(instances)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(options: KwokOptions) -> KwokProvider {
+        // Any API call would hang on the unanswered mock.
+        let (mock_svc, _handle) = tower_test::mock::pair::<
+            http::Request<kube::client::Body>,
+            http::Response<kube::client::Body>,
+        >();
+        KwokProvider::with_options(kube::Client::new(mock_svc, "default"), options)
+    }
+
+    async fn create(kwok: &KwokProvider, node_id: &str) -> NodeId {
+        kwok.create(
+            node_id.into(),
+            &kwok_offerings()[0],
+            &InstanceConfig::default(),
+            &ProviderCreateConfig::None,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn catalog_lists_one_offering_per_zone() {
+        let offerings = parse_catalog(
+            "
+- instanceType: m5.large
+  cpu: 2
+  memoryMib: 8192
+  costPerHour: 0.096
+  interruptionRisk: 0.2
+  region: us-east-1
+  zones: [us-east-1a, us-east-1b]
+- instanceType: g5.xlarge
+  cpu: 4
+  memoryMib: 16384
+  gpu: 1
+  gpuModel: NvidiaA10G
+  costPerHour: 1.006
+  region: us-east-1
+",
+        )
+        .unwrap();
+        assert_eq!(offerings.len(), 3);
+        assert_eq!(offerings[1].location.zone, Some(Zone("us-east-1b".into())));
+        assert_eq!(offerings[2].resources.gpu_model, Some(GpuModel::NvidiaA10G));
+        assert_eq!(offerings[2].location.zone, None);
+        assert_eq!(offerings[0].interruption_risk, 0.2);
+        assert_eq!(offerings[2].interruption_risk, 0.0);
+        assert!(offerings.iter().all(|o| o.provider == "kwok"));
+    }
+
+    #[test]
+    fn catalog_reference_parses_file_or_configmap() {
+        assert_eq!(
+            KwokCatalog::parse("/etc/catalog.yaml"),
+            Some(KwokCatalog::File("/etc/catalog.yaml".into()))
+        );
+        assert_eq!(
+            KwokCatalog::parse("configmap:growth/aws"),
+            Some(KwokCatalog::ConfigMap {
+                namespace: "growth".into(),
+                name: "aws".into(),
+                key: CATALOG_KEY.into(),
+            })
+        );
+        assert_eq!(
+            KwokCatalog::parse("configmap:growth/aws/gcp.yaml").map(|c| match c {
+                KwokCatalog::ConfigMap { key, .. } => key,
+                KwokCatalog::File(_) => String::new(),
+            }),
+            Some("gcp.yaml".into())
+        );
+        assert_eq!(KwokCatalog::parse("configmap:growth"), None);
+    }
+
+    #[tokio::test]
+    async fn failed_join_runs_without_a_node() {
+        let kwok = provider(KwokOptions {
+            join_failure_percent: 100.0,
+            ..Default::default()
+        });
+        let id = create(&kwok, "growth-1").await;
+        assert_eq!(kwok.status(&id).await.unwrap(), ProviderStatus::Running);
+        kwok.delete(&id).await.unwrap();
+        assert!(kwok.simulated(&id).is_none());
+    }
+
+    #[tokio::test]
+    async fn delayed_node_is_creating_until_it_joins() {
+        let kwok = provider(KwokOptions {
+            join_delay: Duration::from_secs(3600),
+            ..Default::default()
+        });
+        let a = create(&kwok, "growth-a").await;
+        let b = create(&kwok, "growth-b").await;
+        assert_eq!(kwok.status(&a).await.unwrap(), ProviderStatus::Creating);
+        // Deleting joining nodes cancels them without an API call.
+        let results = kwok.delete_batch(&[a.clone(), b.clone()]).await;
+        assert!(results.iter().all(Result::is_ok));
+        assert!(kwok.simulated(&a).is_none() && kwok.simulated(&b).is_none());
+    }
+
+    #[tokio::test]
+    async fn node_deleted_during_delayed_create_is_removed() {
+        let (mock_svc, mut handle) = tower_test::mock::pair::<
+            http::Request<kube::client::Body>,
+            http::Response<kube::client::Body>,
+        >();
+        let kwok = KwokProvider::with_options(
+            kube::Client::new(mock_svc, "default"),
+            KwokOptions {
+                join_delay: Duration::from_millis(1),
+                ..Default::default()
+            },
+        );
+        let id = create(&kwok, "growth-1").await;
+
+        let (request, send) = handle.next_request().await.expect("Node create");
+        assert_eq!(request.method(), http::Method::POST);
+        // Deleted while the create is in flight.
+        kwok.delete(&id).await.unwrap();
+        let body = request.into_body().collect_bytes().await.unwrap();
+        send.send_response(
+            http::Response::builder()
+                .body(kube::client::Body::from(body.to_vec()))
+                .unwrap(),
+        );
+
+        let (request, send) = handle.next_request().await.expect("Node delete");
+        assert_eq!(request.method(), http::Method::DELETE);
+        assert_eq!(request.uri().path(), "/api/v1/nodes/growth-1");
+        send.send_response(
+            http::Response::builder()
+                .body(kube::client::Body::from(body.to_vec()))
+                .unwrap(),
+        );
+        assert!(kwok.simulated(&id).is_none());
+    }
 }
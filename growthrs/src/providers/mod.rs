@@ -1,4 +1,11 @@
+pub mod cached;
+pub mod chaos;
 pub mod fake;
+pub mod federated;
 pub mod hetzner;
+pub mod instrumented;
 pub mod kwok;
 pub mod provider;
+pub mod recording;
+pub mod reloadable;
+pub mod retry;
@@ -1,41 +1,85 @@
 use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::offering::Offering;
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::Taint;
+use serde::{Deserialize, Serialize};
+
+use crate::offering::{Offering, Region};
+use crate::providers::cached::CachedProvider;
+use crate::providers::chaos::{ChaosConfig, ChaosProvider};
 use crate::providers::fake::FakeProvider;
+use crate::providers::federated::FederatedProvider;
 use crate::providers::hetzner::HetznerProvider;
 use crate::providers::hetzner::config::HetznerCreateConfig;
-use crate::providers::kwok::KwokProvider;
+use crate::providers::instrumented::{InstrumentedProvider, ProviderMetrics};
+use crate::providers::kwok::{KwokOptions, KwokProvider};
+use crate::providers::retry::{RetryPolicy, RetryingProvider};
+use crate::resources::user_data::JoinStrategy;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct NodeId(pub String);
 
 /// Generic instance configuration shared across all providers.
 ///
 /// Contains only provider-agnostic fields. Provider-specific configuration
 /// (networking, images, SSH keys, etc.) lives in `ProviderCreateConfig`.
+/// Providers that bootstrap nodes from user-data render these fields into
+/// the template at create time (see `UserDataTemplate::render`).
+///
+/// Labels and taints should be applied by the provider where it can, so the
+/// node registers with them. Whatever the bootstrap couldn't set (labels the
+/// kubelet may not self-assign, or a template that omits `NODE_LABELS`) is
+/// patched onto the node by the controller once it joins, before the startup
+/// taint is lifted. A node must still register with `MANAGED_BY_LABEL` and
+/// `NODE_REQUEST_LABEL` for the controller to find it.
 #[derive(Default)]
 pub struct InstanceConfig {
-    /// Extra labels to apply to the created node.
+    /// Labels to apply to the created node.
     pub labels: BTreeMap<String, String>,
+    /// Taints the node registers with.
+    pub taints: Vec<Taint>,
+    /// Extra kubelet flags, e.g. `--max-pods=200`.
+    pub kubelet_args: Vec<String>,
+    /// Token the node joins the cluster with, when the controller supplies one.
+    pub join_token: Option<String>,
+    /// The pool's join strategy; `None` defers to the NodeClass.
+    pub join_strategy: Option<JoinStrategy>,
 }
 
 /// Provider-specific configuration resolved from CRDs at provision time.
 ///
 /// Each variant carries the configuration needed by a specific provider's
 /// `create()` method. Produced by the controller's NodeClass resolution
-/// and consumed by `Provider::create()`.
+/// and consumed by `Provider::create()`. Providers reject variants they
+/// don't understand with `ProviderError::MissingConfig`.
 pub enum ProviderCreateConfig {
     /// No provider-specific config needed (KWOK, Fake).
     None,
     /// Hetzner Cloud instance configuration (from HetznerNodeClass CRD).
-    Hetzner(HetznerCreateConfig),
+    Hetzner(Box<HetznerCreateConfig>),
 }
 
 /// Configuration needed to construct a `Provider`.
+#[derive(Clone)]
 pub struct ProviderConfig {
     pub kube_client: kube::Client,
     /// Hetzner Cloud API Token
     pub hcloud_token: Option<String>,
+    /// How long `offerings()` results are cached; zero disables caching.
+    pub offerings_ttl: Duration,
+    /// Retry policy for retryable errors from each provider.
+    pub retry: RetryPolicy,
+    /// Calls per second allowed to each provider; zero disables rate limiting.
+    pub requests_per_second: f64,
+    /// Where each provider's call counts and latencies are recorded; `None`
+    /// leaves providers uninstrumented.
+    pub metrics: Option<Arc<ProviderMetrics>>,
+    /// Simulated join delay and failures for the `kwok` provider.
+    pub kwok: KwokOptions,
+    /// Failure rates for the `chaos` provider.
+    pub chaos: ChaosConfig,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -67,16 +111,46 @@ pub enum ProviderError {
     #[error("unknown provider: {0}")]
     UnknownProvider(String),
 
+    /// The provider asked us to slow down (e.g. HTTP 429).
+    #[error("rate limited by provider")]
+    RateLimited { retry_after: Option<Duration> },
+
+    /// A failure expected to clear on its own: network errors, timeouts, 5xx.
+    #[error("transient provider error: {0}")]
+    Transient(String),
+
     /// Underlying API/network error.
     #[error(transparent)]
     Internal(#[from] anyhow::Error),
 }
 
+impl ProviderError {
+    /// Whether retrying the same call may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::RateLimited { .. } | Self::Transient(_))
+    }
+
+    /// Short, stable name of the error kind, used as a metric label.
+    pub fn class(&self) -> &'static str {
+        match self {
+            Self::CreationFailed { .. } => "creation_failed",
+            Self::DeletionFailed { .. } => "deletion_failed",
+            Self::JoinTimeout { .. } => "join_timeout",
+            Self::OfferingUnavailable(_) => "offering_unavailable",
+            Self::MissingConfig { .. } => "missing_config",
+            Self::UnknownProvider(_) => "unknown_provider",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::Transient(_) => "transient",
+            Self::Internal(_) => "internal",
+        }
+    }
+}
+
 /// Infrastructure-level status of a VM as reported by the provider.
 ///
 /// This says nothing about whether a K8s Node has joined the cluster —
 /// that's exclusively the Node Watcher's domain.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProviderStatus {
     /// VM is still being built/starting up.
     Creating,
@@ -90,98 +164,326 @@ pub enum ProviderStatus {
     Failed { reason: String },
 }
 
-/// Provide Nodes from a given Provider - i.e. Hetzner, KWOK
-/// The provider's responsibility is to join a node to the cluster, or for the joining to fail loudly.
-pub enum Provider {
-    /// [Kwok](https://kwok.sigs.k8s.io/) providers allow for testing on simulated nodes
-    Kwok(KwokProvider),
-    /// Fake Providers are used internally for rapid testing
-    Fake(FakeProvider),
-    /// [Hetzner](https://www.hetzner.com/cloud) Nodes
-    Hetzner(HetznerProvider),
+/// Remaining provider quota in one region. `None` fields are unlimited or
+/// unknown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    /// Provider the quota belongs to, matching `Offering::provider`.
+    pub provider: String,
+    pub region: Region,
+    /// vCPUs that can still be provisioned.
+    pub vcpus: Option<u32>,
+    /// Instances that can still be provisioned.
+    pub instances: Option<u32>,
 }
 
-pub(crate) enum ProviderName {
-    Kwok,
-    Fake,
-    Hetzner,
+/// Result of a provider health probe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderHealth {
+    /// Provider probed, matching `Offering::provider`.
+    pub provider: String,
+    /// Why the provider is unhealthy; `None` when healthy.
+    pub error: Option<String>,
 }
 
-impl ProviderName {
-    pub(crate) fn from_name(name: &str) -> Result<Self, ProviderError> {
-        match name.to_ascii_lowercase().as_str() {
-            "kwok" => Ok(ProviderName::Kwok),
-            "fake" => Ok(ProviderName::Fake),
-            "hetzner" => Ok(ProviderName::Hetzner),
-            _ => Err(ProviderError::UnknownProvider(name.to_string())),
+impl ProviderHealth {
+    pub fn healthy(provider: &str) -> Self {
+        Self {
+            provider: provider.to_string(),
+            error: None,
         }
     }
-}
 
-impl Provider {
-    /// Build a provider by name. Errors if the name is unrecognised.
-    pub fn from_name(name: &str, config: ProviderConfig) -> Result<Self, ProviderError> {
-        match name {
-            "kwok" => Ok(Self::Kwok(KwokProvider::new(config.kube_client))),
-            "hetzner" => {
-                let token = config.hcloud_token.ok_or(ProviderError::MissingConfig {
-                    field: "HCLOUD_TOKEN",
-                })?;
-                Ok(Self::Hetzner(HetznerProvider::new(token)))
-            }
-            other => Err(ProviderError::UnknownProvider(other.to_string())),
+    /// Health from the outcome of a cheap probe call.
+    pub fn from_probe<T>(provider: &str, probe: Result<T, ProviderError>) -> Self {
+        Self {
+            provider: provider.to_string(),
+            error: probe.err().map(|e| e.to_string()),
         }
     }
 
-    // TODO: This should be cached.
-    pub async fn offerings(&self) -> Vec<Offering> {
-        match self {
-            Self::Kwok(p) => p.offerings().await,
-            Self::Fake(p) => p.offerings().await,
-            Self::Hetzner(p) => p.offerings().await,
-        }
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none()
     }
+}
+
+/// Provide Nodes from a given Provider - i.e. Hetzner, KWOK
+/// The provider's responsibility is to join a node to the cluster, or for the joining to fail loudly.
+///
+/// Implementations are registered by name in a [`ProviderRegistry`] and used
+/// by the controller as `Arc<dyn Provider>`.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// Name this provider is registered under, e.g. "hetzner".
+    fn name(&self) -> &str;
+
+    // TODO: This should be cached.
+    async fn offerings(&self) -> Vec<Offering>;
 
     /// Asynchronously request a node be created.
-    pub async fn create(
+    async fn create(
         &self,
         node_id: String,
         offering: &Offering,
         config: &InstanceConfig,
         provider_config: &ProviderCreateConfig,
-    ) -> Result<NodeId, ProviderError> {
-        match self {
-            Self::Kwok(p) => p.create(node_id, offering, config).await,
-            Self::Fake(p) => p.create(node_id, offering, config).await,
-            Self::Hetzner(p) => {
-                let hetzner_config = match provider_config {
-                    ProviderCreateConfig::Hetzner(c) => c,
-                    _ => {
-                        return Err(ProviderError::MissingConfig {
-                            field: "HetznerCreateConfig",
-                        });
-                    }
-                };
-                p.create(node_id, offering, config, hetzner_config).await
-            }
-        }
-    }
+    ) -> Result<NodeId, ProviderError>;
 
     /// Delete a node by its ID
-    pub async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
-        match self {
-            Self::Kwok(p) => p.delete(node_id).await,
-            Self::Fake(p) => p.delete(node_id).await,
-            Self::Hetzner(p) => p.delete(node_id).await,
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError>;
+
+    /// Largest batch `delete_batch` handles in one provider call. Providers
+    /// without a bulk-delete API keep the default of 1.
+    fn max_delete_batch(&self) -> usize {
+        1
+    }
+
+    /// Delete several nodes, returning one result per ID, in order.
+    ///
+    /// The default deletes them one at a time; providers with a bulk-delete
+    /// API override this along with `max_delete_batch`.
+    async fn delete_batch(&self, node_ids: &[NodeId]) -> Vec<Result<(), ProviderError>> {
+        let mut results = Vec::with_capacity(node_ids.len());
+        for node_id in node_ids {
+            results.push(self.delete(node_id).await);
         }
+        results
+    }
+
+    /// Remaining vCPU/instance quota per region, so the solver can avoid
+    /// plans that would fail with quota errors. Regions not listed are
+    /// unconstrained; the default reports none.
+    async fn quotas(&self) -> Result<Vec<Quota>, ProviderError> {
+        Ok(Vec::new())
+    }
+
+    /// Probe whether the provider's API is reachable, one entry per backing
+    /// provider. The controller stops planning nodes on unhealthy ones. The
+    /// default always reports healthy.
+    async fn health(&self) -> Vec<ProviderHealth> {
+        vec![ProviderHealth::healthy(self.name())]
     }
 
     /// Query the infrastructure-level status of a VM.
-    pub async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
-        match self {
-            Self::Kwok(p) => p.status(node_id).await,
-            Self::Fake(p) => p.status(node_id).await,
-            Self::Hetzner(p) => p.status(node_id).await,
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError>;
+
+    /// List every instance the provider holds that is managed by Growth.
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError>;
+}
+
+/// Builds a provider from the shared [`ProviderConfig`].
+pub type ProviderFactory =
+    Box<dyn Fn(&ProviderConfig) -> Result<Arc<dyn Provider>, ProviderError> + Send + Sync>;
+
+/// Providers available to the controller, keyed by lowercase name.
+///
+/// `ProviderRegistry::default()` contains the built-in providers; out-of-tree
+/// providers can be added with [`ProviderRegistry::register`].
+pub struct ProviderRegistry {
+    factories: BTreeMap<String, ProviderFactory>,
+}
+
+impl ProviderRegistry {
+    /// An empty registry with no providers.
+    pub fn empty() -> Self {
+        Self {
+            factories: BTreeMap::new(),
         }
     }
+
+    /// Register a provider factory under `name`, replacing any existing entry.
+    pub fn register<F>(mut self, name: &str, factory: F) -> Self
+    where
+        F: Fn(&ProviderConfig) -> Result<Arc<dyn Provider>, ProviderError> + Send + Sync + 'static,
+    {
+        self.factories
+            .insert(name.to_ascii_lowercase(), Box::new(factory));
+        self
+    }
+
+    /// Names of all registered providers.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+
+    /// Build a provider by name. Errors if the name is unrecognised.
+    pub fn build(
+        &self,
+        name: &str,
+        config: &ProviderConfig,
+    ) -> Result<Arc<dyn Provider>, ProviderError> {
+        let factory = self
+            .factories
+            .get(&name.to_ascii_lowercase())
+            .ok_or_else(|| ProviderError::UnknownProvider(name.to_string()))?;
+        factory(config)
+    }
+
+    /// Build a provider by name, behind a [`RetryingProvider`]. With
+    /// `config.metrics` set, every attempt is recorded by an
+    /// [`InstrumentedProvider`] inside the retries.
+    fn build_retrying(
+        &self,
+        name: &str,
+        config: &ProviderConfig,
+    ) -> Result<Arc<dyn Provider>, ProviderError> {
+        let mut provider = self.build(name, config)?;
+        if let Some(metrics) = &config.metrics {
+            provider = Arc::new(InstrumentedProvider::new(name, provider, metrics.clone()));
+        }
+        Ok(Arc::new(RetryingProvider::new(
+            provider,
+            config.retry.clone(),
+            config.requests_per_second,
+        )))
+    }
+
+    /// Build the providers named in a comma-separated list, e.g. "hetzner,kwok".
+    ///
+    /// Each provider is wrapped in a [`RetryingProvider`] with its own rate
+    /// limiter. A single name yields that provider directly; several are
+    /// wrapped in a [`FederatedProvider`] so their offerings compete in one
+    /// solve. Either way the result sits behind a [`CachedProvider`] if
+    /// `config.offerings_ttl` is non-zero.
+    pub fn build_list(
+        &self,
+        names: &str,
+        config: &ProviderConfig,
+    ) -> Result<Arc<dyn Provider>, ProviderError> {
+        let names: Vec<String> = names
+            .split(',')
+            .map(|n| n.trim().to_ascii_lowercase())
+            .filter(|n| !n.is_empty())
+            .collect();
+        let provider: Arc<dyn Provider> = match names.as_slice() {
+            [] => return Err(ProviderError::UnknownProvider(String::new())),
+            [name] => self.build_retrying(name, config)?,
+            _ => {
+                let members = names
+                    .into_iter()
+                    .map(|name| Ok((name.clone(), self.build_retrying(&name, config)?)))
+                    .collect::<Result<Vec<_>, ProviderError>>()?;
+                Arc::new(FederatedProvider::new(members)?)
+            }
+        };
+        if config.offerings_ttl.is_zero() {
+            return Ok(provider);
+        }
+        Ok(Arc::new(CachedProvider::new(
+            provider,
+            config.offerings_ttl,
+        )))
+    }
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::empty()
+            .register("kwok", |config| {
+                Ok(Arc::new(KwokProvider::with_options(
+                    config.kube_client.clone(),
+                    config.kwok.clone(),
+                )))
+            })
+            .register("fake", |_| Ok(Arc::new(FakeProvider::new())))
+            .register("chaos", |config| {
+                let kwok = Arc::new(KwokProvider::with_options(
+                    config.kube_client.clone(),
+                    config.kwok.clone(),
+                ));
+                Ok(Arc::new(ChaosProvider::new(kwok, config.chaos.clone())))
+            })
+            .register("hetzner", |config| {
+                let token = config
+                    .hcloud_token
+                    .clone()
+                    .ok_or(ProviderError::MissingConfig {
+                        field: "HCLOUD_TOKEN",
+                    })?;
+                Ok(Arc::new(HetznerProvider::new(token)))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ProviderConfig {
+        let (mock_svc, _handle) = tower_test::mock::pair::<
+            http::Request<kube::client::Body>,
+            http::Response<kube::client::Body>,
+        >();
+        ProviderConfig {
+            kube_client: kube::Client::new(mock_svc, "default"),
+            hcloud_token: None,
+            offerings_ttl: Duration::ZERO,
+            retry: RetryPolicy::default(),
+            requests_per_second: 0.0,
+            metrics: None,
+            kwok: KwokOptions::default(),
+            chaos: ChaosConfig::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn default_registry_builds_builtins_case_insensitively() {
+        let registry = ProviderRegistry::default();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            vec!["chaos", "fake", "hetzner", "kwok"]
+        );
+        assert_eq!(registry.build("KWOK", &config()).unwrap().name(), "kwok");
+        assert_eq!(registry.build("fake", &config()).unwrap().name(), "fake");
+    }
+
+    #[tokio::test]
+    async fn unknown_provider_is_rejected() {
+        let err = ProviderRegistry::default()
+            .build("aws", &config())
+            .err()
+            .unwrap();
+        assert!(matches!(err, ProviderError::UnknownProvider(ref n) if n == "aws"));
+    }
+
+    #[tokio::test]
+    async fn hetzner_requires_token() {
+        let err = ProviderRegistry::default()
+            .build("hetzner", &config())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            ProviderError::MissingConfig {
+                field: "HCLOUD_TOKEN"
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn registered_provider_overrides_and_extends() {
+        let registry = ProviderRegistry::empty().register("custom", |_| {
+            Ok(Arc::new(
+                FakeProvider::new().with_instances(vec![NodeId("n".into())]),
+            ))
+        });
+        let provider = registry.build("Custom", &config()).unwrap();
+        assert_eq!(provider.list_instances().await.unwrap().len(), 1);
+        assert!(registry.build("kwok", &config()).is_err());
+    }
+
+    #[tokio::test]
+    async fn build_list_federates_multiple_names() {
+        let registry = ProviderRegistry::default();
+        assert_eq!(
+            registry.build_list("kwok", &config()).unwrap().name(),
+            "kwok"
+        );
+        assert_eq!(
+            registry.build_list("kwok, Fake", &config()).unwrap().name(),
+            "federated"
+        );
+        assert!(registry.build_list("kwok,aws", &config()).is_err());
+        assert!(registry.build_list(" , ", &config()).is_err());
+    }
 }
@@ -0,0 +1,504 @@
+//! Record provider traffic to a file and replay it later.
+//!
+//! [`RecordingProvider`] wraps a real provider and appends every call and
+//! its response to a JSON-lines file. [`ReplayProvider`] serves the same
+//! responses back without touching a cloud, so a provisioning bug seen
+//! against a real provider can be reproduced in a unit test.
+//!
+//! Responses are replayed per operation, in the order they were recorded.
+//! Arguments are not used to pick a response (node IDs are random per run),
+//! but calls whose arguments differ from the recording are noted in
+//! [`ReplayProvider::divergences`].
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::offering::{InstanceType, Offering, Region};
+use crate::providers::provider::{
+    InstanceConfig, NodeId, Provider, ProviderCreateConfig, ProviderError, ProviderHealth,
+    ProviderStatus, Quota,
+};
+
+/// A [`ProviderError`] as stored in a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedError {
+    /// [`ProviderError::class`] of the original error.
+    pub class: String,
+    pub message: String,
+}
+
+impl From<&ProviderError> for RecordedError {
+    fn from(e: &ProviderError) -> Self {
+        let message = match e {
+            ProviderError::CreationFailed { message }
+            | ProviderError::DeletionFailed { message } => message.clone(),
+            ProviderError::OfferingUnavailable(m)
+            | ProviderError::UnknownProvider(m)
+            | ProviderError::Transient(m) => m.clone(),
+            other => other.to_string(),
+        };
+        Self {
+            class: e.class().to_string(),
+            message,
+        }
+    }
+}
+
+impl From<RecordedError> for ProviderError {
+    /// Rebuild the error from its class. Details that aren't recorded
+    /// (`retry_after`, the join-timeout node, the missing config field) are
+    /// lost; an unknown or missing-config class comes back as `Internal`.
+    fn from(e: RecordedError) -> Self {
+        match e.class.as_str() {
+            "creation_failed" => Self::CreationFailed { message: e.message },
+            "deletion_failed" => Self::DeletionFailed { message: e.message },
+            "join_timeout" => Self::JoinTimeout { node_id: None },
+            "offering_unavailable" => Self::OfferingUnavailable(e.message),
+            "unknown_provider" => Self::UnknownProvider(e.message),
+            "rate_limited" => Self::RateLimited { retry_after: None },
+            "transient" => Self::Transient(e.message),
+            _ => Self::Internal(anyhow::anyhow!(e.message)),
+        }
+    }
+}
+
+type Recorded<T> = Result<T, RecordedError>;
+
+fn record<T: Clone>(result: &Result<T, ProviderError>) -> Recorded<T> {
+    match result {
+        Ok(value) => Ok(value.clone()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// One provider call and its response, a line in a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum RecordedCall {
+    Offerings {
+        offerings: Vec<Offering>,
+    },
+    Create {
+        node_id: String,
+        instance_type: InstanceType,
+        region: Region,
+        result: Recorded<NodeId>,
+    },
+    Delete {
+        node_id: NodeId,
+        result: Recorded<()>,
+    },
+    Status {
+        node_id: NodeId,
+        result: Recorded<ProviderStatus>,
+    },
+    ListInstances {
+        result: Recorded<Vec<NodeId>>,
+    },
+    Quotas {
+        result: Recorded<Vec<Quota>>,
+    },
+    Health {
+        reports: Vec<ProviderHealth>,
+    },
+}
+
+impl RecordedCall {
+    fn op(&self) -> &'static str {
+        match self {
+            Self::Offerings { .. } => "offerings",
+            Self::Create { .. } => "create",
+            Self::Delete { .. } => "delete",
+            Self::Status { .. } => "status",
+            Self::ListInstances { .. } => "list_instances",
+            Self::Quotas { .. } => "quotas",
+            Self::Health { .. } => "health",
+        }
+    }
+}
+
+/// Passes every call through to another provider and appends it, with its
+/// response, to a recording.
+pub struct RecordingProvider {
+    inner: Arc<dyn Provider>,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl RecordingProvider {
+    pub fn new(inner: Arc<dyn Provider>, out: Box<dyn Write + Send>) -> Self {
+        Self {
+            inner,
+            out: Mutex::new(out),
+        }
+    }
+
+    /// Record to `path`, appending if it already exists.
+    pub fn to_file(inner: Arc<dyn Provider>, path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(inner, Box::new(file)))
+    }
+
+    /// Lines are flushed as they are written, so a recording survives the
+    /// controller crashing. Write failures are logged, never returned.
+    fn write(&self, call: RecordedCall) {
+        let line = serde_json::to_string(&call).expect("recorded calls serialize");
+        let mut out = self.out.lock().unwrap();
+        if let Err(e) = writeln!(out, "{line}").and_then(|()| out.flush()) {
+            warn!(error = %e, op = call.op(), "failed to record provider call");
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for RecordingProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn offerings(&self) -> Vec<Offering> {
+        let offerings = self.inner.offerings().await;
+        self.write(RecordedCall::Offerings {
+            offerings: offerings.clone(),
+        });
+        offerings
+    }
+
+    async fn create(
+        &self,
+        node_id: String,
+        offering: &Offering,
+        config: &InstanceConfig,
+        provider_config: &ProviderCreateConfig,
+    ) -> Result<NodeId, ProviderError> {
+        let result = self
+            .inner
+            .create(node_id.clone(), offering, config, provider_config)
+            .await;
+        self.write(RecordedCall::Create {
+            node_id,
+            instance_type: offering.instance_type.clone(),
+            region: offering.location.region.clone(),
+            result: record(&result),
+        });
+        result
+    }
+
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+        let result = self.inner.delete(node_id).await;
+        self.write(RecordedCall::Delete {
+            node_id: node_id.clone(),
+            result: record(&result),
+        });
+        result
+    }
+
+    fn max_delete_batch(&self) -> usize {
+        self.inner.max_delete_batch()
+    }
+
+    /// Recorded as one `delete` per node, so a recording replays the same
+    /// whether or not the replaying code batches.
+    async fn delete_batch(&self, node_ids: &[NodeId]) -> Vec<Result<(), ProviderError>> {
+        let results = self.inner.delete_batch(node_ids).await;
+        for (node_id, result) in node_ids.iter().zip(&results) {
+            self.write(RecordedCall::Delete {
+                node_id: node_id.clone(),
+                result: record(result),
+            });
+        }
+        results
+    }
+
+    async fn quotas(&self) -> Result<Vec<Quota>, ProviderError> {
+        let result = self.inner.quotas().await;
+        self.write(RecordedCall::Quotas {
+            result: record(&result),
+        });
+        result
+    }
+
+    async fn health(&self) -> Vec<ProviderHealth> {
+        let reports = self.inner.health().await;
+        self.write(RecordedCall::Health {
+            reports: reports.clone(),
+        });
+        reports
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+        let result = self.inner.status(node_id).await;
+        self.write(RecordedCall::Status {
+            node_id: node_id.clone(),
+            result: record(&result),
+        });
+        result
+    }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        let result = self.inner.list_instances().await;
+        self.write(RecordedCall::ListInstances {
+            result: record(&result),
+        });
+        result
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("failed to read recording: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid recording at line {line}: {source}")]
+    Parse {
+        line: usize,
+        source: serde_json::Error,
+    },
+}
+
+/// Serves the responses of a recording, per operation, in recorded order.
+///
+/// Calls beyond the recording fail with `ProviderError::Internal`, except
+/// `offerings()` and `health()`, which keep returning their last recorded
+/// response.
+pub struct ReplayProvider {
+    /// Remaining calls per operation.
+    calls: Mutex<HashMap<&'static str, VecDeque<RecordedCall>>>,
+    /// The last `offerings()`/`health()` response served.
+    last: Mutex<HashMap<&'static str, RecordedCall>>,
+    divergences: Mutex<Vec<String>>,
+}
+
+impl ReplayProvider {
+    pub fn new(calls: Vec<RecordedCall>) -> Self {
+        let mut by_op: HashMap<&'static str, VecDeque<RecordedCall>> = HashMap::new();
+        for call in calls {
+            by_op.entry(call.op()).or_default().push_back(call);
+        }
+        Self {
+            calls: Mutex::new(by_op),
+            last: Mutex::new(HashMap::new()),
+            divergences: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, ReplayError> {
+        let mut calls = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let call = serde_json::from_str(&line).map_err(|source| ReplayError::Parse {
+                line: i + 1,
+                source,
+            })?;
+            calls.push(call);
+        }
+        Ok(Self::new(calls))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ReplayError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Calls whose arguments didn't match the recorded call they were
+    /// answered from, e.g. a create for a different instance type.
+    pub fn divergences(&self) -> Vec<String> {
+        self.divergences.lock().unwrap().clone()
+    }
+
+    fn next(&self, op: &'static str) -> Option<RecordedCall> {
+        let call = self.calls.lock().unwrap().get_mut(op)?.pop_front();
+        match call {
+            Some(call) => {
+                self.last.lock().unwrap().insert(op, call.clone());
+                Some(call)
+            }
+            None => self.last.lock().unwrap().get(op).cloned(),
+        }
+    }
+
+    fn next_or_err(&self, op: &'static str) -> Result<RecordedCall, ProviderError> {
+        self.calls
+            .lock()
+            .unwrap()
+            .get_mut(op)
+            .and_then(VecDeque::pop_front)
+            .ok_or_else(|| {
+                ProviderError::Internal(anyhow::anyhow!("recording has no {op} calls left"))
+            })
+    }
+
+    fn diverged(&self, op: &str, recorded: &str, actual: &str) {
+        if recorded != actual {
+            self.divergences
+                .lock()
+                .unwrap()
+                .push(format!("{op}: recorded {recorded}, called with {actual}"));
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for ReplayProvider {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    async fn offerings(&self) -> Vec<Offering> {
+        match self.next("offerings") {
+            Some(RecordedCall::Offerings { offerings }) => offerings,
+            _ => Vec::new(),
+        }
+    }
+
+    async fn create(
+        &self,
+        _node_id: String,
+        offering: &Offering,
+        _config: &InstanceConfig,
+        _provider_config: &ProviderCreateConfig,
+    ) -> Result<NodeId, ProviderError> {
+        let RecordedCall::Create {
+            instance_type,
+            region,
+            result,
+            ..
+        } = self.next_or_err("create")?
+        else {
+            unreachable!("calls are grouped by operation");
+        };
+        self.diverged(
+            "create",
+            &format!("{instance_type}/{}", region.0),
+            &format!("{}/{}", offering.instance_type, offering.location.region.0),
+        );
+        result.map_err(Into::into)
+    }
+
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+        let RecordedCall::Delete {
+            node_id: recorded,
+            result,
+        } = self.next_or_err("delete")?
+        else {
+            unreachable!("calls are grouped by operation");
+        };
+        self.diverged("delete", &recorded.0, &node_id.0);
+        result.map_err(Into::into)
+    }
+
+    async fn quotas(&self) -> Result<Vec<Quota>, ProviderError> {
+        let RecordedCall::Quotas { result } = self.next_or_err("quotas")? else {
+            unreachable!("calls are grouped by operation");
+        };
+        result.map_err(Into::into)
+    }
+
+    async fn health(&self) -> Vec<ProviderHealth> {
+        match self.next("health") {
+            Some(RecordedCall::Health { reports }) => reports,
+            _ => vec![ProviderHealth::healthy(self.name())],
+        }
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+        let RecordedCall::Status {
+            node_id: recorded,
+            result,
+        } = self.next_or_err("status")?
+        else {
+            unreachable!("calls are grouped by operation");
+        };
+        self.diverged("status", &recorded.0, &node_id.0);
+        result.map_err(Into::into)
+    }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        let RecordedCall::ListInstances { result } = self.next_or_err("list_instances")? else {
+            unreachable!("calls are grouped by operation");
+        };
+        result.map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fake::{CreateBehavior, FakeProvider, StatusBehavior};
+    use crate::testing::offering;
+
+    async fn create(provider: &dyn Provider, name: &str) -> Result<NodeId, ProviderError> {
+        provider
+            .create(
+                "growth-1".into(),
+                &offering(name).build(),
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn replay_reproduces_recorded_responses() {
+        let path = std::env::temp_dir().join(format!("growth-{}.jsonl", uuid::Uuid::new_v4()));
+        let fake = FakeProvider::new()
+            .with_offerings(vec![offering("cpx22").build()])
+            .on_next_create(CreateBehavior::Succeed)
+            .on_next_create(CreateBehavior::OfferingUnavailable)
+            .on_next_status(StatusBehavior::Return(ProviderStatus::Creating));
+        let recorder = RecordingProvider::to_file(Arc::new(fake), &path).unwrap();
+        let offerings = recorder.offerings().await;
+        let created = create(&recorder, "cpx22").await.unwrap();
+        let unavailable = create(&recorder, "cpx32").await;
+        let status = recorder.status(&created).await.unwrap();
+        drop(recorder);
+
+        let replay = ReplayProvider::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replay.offerings().await, offerings);
+        // `offerings()` keeps serving the last recorded list.
+        assert_eq!(replay.offerings().await, offerings);
+        assert_eq!(create(&replay, "cpx22").await.unwrap(), created);
+        assert!(matches!(
+            (unavailable, create(&replay, "cpx42").await),
+            (
+                Err(ProviderError::OfferingUnavailable(_)),
+                Err(ProviderError::OfferingUnavailable(_))
+            )
+        ));
+        assert_eq!(replay.status(&created).await.unwrap(), status);
+        assert_eq!(
+            replay.divergences(),
+            vec!["create: recorded cpx32/eu-central, called with cpx42/eu-central"]
+        );
+
+        // Calls beyond the recording fail.
+        assert!(matches!(
+            replay.delete(&created).await,
+            Err(ProviderError::Internal(_))
+        ));
+    }
+
+    #[test]
+    fn errors_round_trip_through_their_class() {
+        for error in [
+            ProviderError::CreationFailed {
+                message: "no capacity".into(),
+            },
+            ProviderError::Transient("502".into()),
+            ProviderError::RateLimited { retry_after: None },
+        ] {
+            let recorded = RecordedError::from(&error);
+            let replayed = ProviderError::from(recorded.clone());
+            assert_eq!(replayed.class(), error.class());
+            assert_eq!(replayed.to_string(), error.to_string());
+        }
+    }
+}
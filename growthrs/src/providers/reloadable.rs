@@ -0,0 +1,147 @@
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+
+use crate::offering::Offering;
+use crate::providers::provider::{
+    InstanceConfig, NodeId, Provider, ProviderCreateConfig, ProviderError, ProviderHealth,
+    ProviderStatus, Quota,
+};
+
+/// Filters and rewrites each offering; `None` drops it.
+type OfferingTransform = Box<dyn Fn(Offering) -> Option<Offering> + Send + Sync>;
+
+/// A `Provider` whose backing provider and offering transform can be replaced
+/// at runtime.
+///
+/// Used by the ProviderConfig watcher to reconfigure the controller without a
+/// restart. Every call goes to whichever provider is current when it starts;
+/// nodes created by a replaced provider are only reachable through the new one
+/// if it can see them (e.g. the same cloud account).
+pub struct ReloadableProvider {
+    current: RwLock<Arc<dyn Provider>>,
+    transform: RwLock<Option<OfferingTransform>>,
+}
+
+impl ReloadableProvider {
+    pub fn new(initial: Arc<dyn Provider>) -> Self {
+        Self {
+            current: RwLock::new(initial),
+            transform: RwLock::new(None),
+        }
+    }
+
+    /// Swap in a new provider and offering transform. `None` passes every
+    /// offering through unchanged.
+    pub fn replace(&self, provider: Arc<dyn Provider>, transform: Option<OfferingTransform>) {
+        *self.current.write().unwrap() = provider;
+        *self.transform.write().unwrap() = transform;
+    }
+
+    /// The provider currently backing this one.
+    pub fn current(&self) -> Arc<dyn Provider> {
+        self.current.read().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Provider for ReloadableProvider {
+    fn name(&self) -> &str {
+        "reloadable"
+    }
+
+    async fn offerings(&self) -> Vec<Offering> {
+        let offerings = self.current().offerings().await;
+        match self.transform.read().unwrap().as_ref() {
+            Some(transform) => offerings.into_iter().filter_map(transform).collect(),
+            None => offerings,
+        }
+    }
+
+    async fn create(
+        &self,
+        node_id: String,
+        offering: &Offering,
+        config: &InstanceConfig,
+        provider_config: &ProviderCreateConfig,
+    ) -> Result<NodeId, ProviderError> {
+        self.current()
+            .create(node_id, offering, config, provider_config)
+            .await
+    }
+
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+        self.current().delete(node_id).await
+    }
+
+    fn max_delete_batch(&self) -> usize {
+        self.current().max_delete_batch()
+    }
+
+    async fn delete_batch(&self, node_ids: &[NodeId]) -> Vec<Result<(), ProviderError>> {
+        self.current().delete_batch(node_ids).await
+    }
+
+    async fn quotas(&self) -> Result<Vec<Quota>, ProviderError> {
+        self.current().quotas().await
+    }
+
+    async fn health(&self) -> Vec<ProviderHealth> {
+        self.current().health().await
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+        self.current().status(node_id).await
+    }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        self.current().list_instances().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fake::FakeProvider;
+    use crate::testing::offering;
+
+    #[tokio::test]
+    async fn replace_switches_backing_provider() {
+        let old = FakeProvider::new().with_offerings(vec![offering("old").build()]);
+        let new = FakeProvider::new().with_offerings(vec![offering("new").build()]);
+        let reloadable = ReloadableProvider::new(Arc::new(old.clone()));
+
+        reloadable.replace(Arc::new(new.clone()), None);
+        let offerings = reloadable.offerings().await;
+        assert_eq!(offerings[0].instance_type.0, "new");
+
+        reloadable
+            .create(
+                "n1".into(),
+                &offerings[0],
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
+            .await
+            .unwrap();
+        assert!(old.create_calls().is_empty());
+        assert_eq!(new.create_calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn filter_restricts_offerings() {
+        let fake =
+            FakeProvider::new().with_offerings(vec![offering("a").build(), offering("b").build()]);
+        let reloadable = ReloadableProvider::new(Arc::new(fake.clone()));
+        reloadable.replace(
+            Arc::new(fake),
+            Some(Box::new(|o: Offering| {
+                (o.instance_type.0 == "b").then_some(o)
+            })),
+        );
+
+        let offerings = reloadable.offerings().await;
+        assert_eq!(offerings.len(), 1);
+        assert_eq!(offerings[0].instance_type.0, "b");
+    }
+}
@@ -0,0 +1,354 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use rand::Rng;
+use tracing::warn;
+
+use crate::offering::Offering;
+use crate::providers::provider::{
+    InstanceConfig, NodeId, Provider, ProviderCreateConfig, ProviderError, ProviderHealth,
+    ProviderStatus, Quota,
+};
+
+/// How retryable provider errors are retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; zero disables retrying.
+    pub max_retries: u32,
+    /// Upper bound of the first backoff, doubled on every further retry.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Backoff before retry number `attempt` (zero-based), with full jitter:
+    /// uniformly random up to the exponential bound, so controllers that
+    /// failed together don't retry together.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let bound = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        if bound.is_zero() {
+            return bound;
+        }
+        rand::thread_rng().gen_range(Duration::ZERO..=bound)
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// Token bucket shared by every call to one provider.
+///
+/// Refills at `per_second` up to a burst of the same size. Callers that find
+/// it empty reserve a token anyway and sleep until it would have arrived, so
+/// waiters are served in order.
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: f64) -> Self {
+        let burst = per_second.ceil().max(1.0);
+        Self {
+            per_second,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Wait until a call is allowed.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * self.per_second;
+            bucket.tokens = (bucket.tokens + refill).min(self.burst);
+            bucket.refilled_at = now;
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / self.per_second)
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Rate-limits calls to another provider and retries the ones that fail with
+/// a retryable [`ProviderError`] (see [`ProviderError::is_retryable`]).
+///
+/// `offerings()` is only rate-limited: it has no error to inspect, and the
+/// offerings cache already smooths over a failed refresh.
+pub struct RetryingProvider {
+    inner: Arc<dyn Provider>,
+    limiter: Option<RateLimiter>,
+    policy: RetryPolicy,
+}
+
+impl RetryingProvider {
+    /// `requests_per_second` of zero disables rate limiting.
+    pub fn new(inner: Arc<dyn Provider>, policy: RetryPolicy, requests_per_second: f64) -> Self {
+        Self {
+            inner,
+            limiter: (requests_per_second > 0.0).then(|| RateLimiter::new(requests_per_second)),
+            policy,
+        }
+    }
+
+    async fn throttle(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+    }
+
+    async fn with_retry<T, F, Fut>(&self, op: &str, mut call: F) -> Result<T, ProviderError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ProviderError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            self.throttle().await;
+            match call().await {
+                Err(e) if e.is_retryable() && attempt < self.policy.max_retries => {
+                    let delay = match &e {
+                        ProviderError::RateLimited {
+                            retry_after: Some(after),
+                        } => *after,
+                        _ => self.policy.backoff(attempt),
+                    };
+                    attempt += 1;
+                    warn!(
+                        provider = self.inner.name(),
+                        op,
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %e,
+                        "retryable provider error, backing off"
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for RetryingProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn offerings(&self) -> Vec<Offering> {
+        self.throttle().await;
+        self.inner.offerings().await
+    }
+
+    async fn create(
+        &self,
+        node_id: String,
+        offering: &Offering,
+        config: &InstanceConfig,
+        provider_config: &ProviderCreateConfig,
+    ) -> Result<NodeId, ProviderError> {
+        self.with_retry("create", || {
+            self.inner
+                .create(node_id.clone(), offering, config, provider_config)
+        })
+        .await
+    }
+
+    async fn delete(&self, node_id: &NodeId) -> Result<(), ProviderError> {
+        self.with_retry("delete", || self.inner.delete(node_id))
+            .await
+    }
+
+    fn max_delete_batch(&self) -> usize {
+        self.inner.max_delete_batch()
+    }
+
+    /// Without a bulk API every delete is its own call, so each is throttled
+    /// and retried alone. With one, a batch is one call, and only the nodes
+    /// that failed retryably are retried.
+    async fn delete_batch(&self, node_ids: &[NodeId]) -> Vec<Result<(), ProviderError>> {
+        if self.inner.max_delete_batch() <= 1 {
+            let mut results = Vec::with_capacity(node_ids.len());
+            for node_id in node_ids {
+                results.push(self.delete(node_id).await);
+            }
+            return results;
+        }
+
+        let mut results: Vec<Option<Result<(), ProviderError>>> =
+            node_ids.iter().map(|_| None).collect();
+        let mut pending: Vec<usize> = (0..node_ids.len()).collect();
+        let mut attempt = 0;
+        while !pending.is_empty() {
+            self.throttle().await;
+            let ids: Vec<NodeId> = pending.iter().map(|&i| node_ids[i].clone()).collect();
+            let outcomes = self.inner.delete_batch(&ids).await;
+            let mut retry = Vec::new();
+            let mut delay = Duration::ZERO;
+            for (i, outcome) in pending.into_iter().zip(outcomes) {
+                match outcome {
+                    Err(e) if e.is_retryable() && attempt < self.policy.max_retries => {
+                        delay = delay.max(match &e {
+                            ProviderError::RateLimited {
+                                retry_after: Some(after),
+                            } => *after,
+                            _ => self.policy.backoff(attempt),
+                        });
+                        retry.push(i);
+                    }
+                    outcome => results[i] = Some(outcome),
+                }
+            }
+            if !retry.is_empty() {
+                attempt += 1;
+                warn!(
+                    provider = self.inner.name(),
+                    op = "delete_batch",
+                    attempt,
+                    nodes = retry.len(),
+                    delay_ms = delay.as_millis() as u64,
+                    "retryable provider errors in batch, backing off"
+                );
+                tokio::time::sleep(delay).await;
+            }
+            pending = retry;
+        }
+        results
+            .into_iter()
+            .map(|r| r.expect("every node has an outcome"))
+            .collect()
+    }
+
+    async fn quotas(&self) -> Result<Vec<Quota>, ProviderError> {
+        self.with_retry("quotas", || self.inner.quotas()).await
+    }
+
+    /// Not retried: a probe that needs retries is reporting trouble.
+    async fn health(&self) -> Vec<ProviderHealth> {
+        self.inner.health().await
+    }
+
+    async fn status(&self, node_id: &NodeId) -> Result<ProviderStatus, ProviderError> {
+        self.with_retry("status", || self.inner.status(node_id))
+            .await
+    }
+
+    async fn list_instances(&self) -> Result<Vec<NodeId>, ProviderError> {
+        self.with_retry("list_instances", || self.inner.list_instances())
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::fake::{CreateBehavior, FakeProvider};
+    use crate::testing::offering;
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+        }
+    }
+
+    async fn create(provider: &RetryingProvider) -> Result<NodeId, ProviderError> {
+        provider
+            .create(
+                "node-1".into(),
+                &offering("small").build(),
+                &InstanceConfig::default(),
+                &ProviderCreateConfig::None,
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn rate_limited_create_is_retried() {
+        let fake = Arc::new(
+            FakeProvider::new()
+                .on_next_create(CreateBehavior::RateLimited)
+                .on_next_create(CreateBehavior::RateLimited),
+        );
+        let provider = RetryingProvider::new(fake.clone(), fast_policy(3), 0.0);
+
+        assert!(create(&provider).await.is_ok());
+        assert_eq!(fake.create_calls().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_are_not_retried() {
+        let fake = Arc::new(
+            FakeProvider::new().on_next_create(CreateBehavior::CreationFailed("quota".into())),
+        );
+        let provider = RetryingProvider::new(fake.clone(), fast_policy(3), 0.0);
+
+        assert!(matches!(
+            create(&provider).await,
+            Err(ProviderError::CreationFailed { .. })
+        ));
+        assert_eq!(fake.create_calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let fake = Arc::new(FakeProvider::new().with_default_create(CreateBehavior::RateLimited));
+        let provider = RetryingProvider::new(fake.clone(), fast_policy(2), 0.0);
+
+        assert!(matches!(
+            create(&provider).await,
+            Err(ProviderError::RateLimited { .. })
+        ));
+        assert_eq!(fake.create_calls().len(), 3);
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        };
+        for attempt in 0..10 {
+            let bound = Duration::from_millis(100 * 2u64.pow(attempt)).min(Duration::from_secs(1));
+            assert!(policy.backoff(attempt) <= bound);
+        }
+    }
+
+    #[tokio::test]
+    async fn limiter_spaces_calls_beyond_the_burst() {
+        let limiter = RateLimiter::new(50.0);
+        let start = Instant::now();
+        // The first 50 are the burst; the next 5 wait 20ms apiece.
+        for _ in 0..55 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+}
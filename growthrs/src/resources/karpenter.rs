@@ -0,0 +1,111 @@
+//! Karpenter's `karpenter.sh/v1` `NodePool` and `NodeClaim`, as far as the
+//! compatibility controller reads and writes them.
+//!
+//! These are Karpenter's resources, not growth-rs's: their CRDs come from a
+//! Karpenter install and are never part of [`crds`](super::crds). Fields
+//! growth-rs has no use for are left out and ignored when reading.
+
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::{NodeSelectorRequirement, Taint};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Label Karpenter puts on NodeClaims and nodes naming their NodePool.
+pub const NODEPOOL_LABEL: &str = "karpenter.sh/nodepool";
+/// Node label for on-demand or spot capacity.
+pub const CAPACITY_TYPE_LABEL: &str = "karpenter.sh/capacity-type";
+/// Well-known node label for the instance type, as Karpenter requirements
+/// name it.
+pub const WELL_KNOWN_INSTANCE_TYPE_LABEL: &str = "node.kubernetes.io/instance-type";
+
+/// Spec of a Karpenter NodePool.
+#[derive(CustomResource, Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[kube(group = "karpenter.sh", version = "v1", kind = "NodePool")]
+#[serde(rename_all = "camelCase")]
+pub struct NodePoolSpec {
+    #[serde(default)]
+    pub template: NodeClaimTemplate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disruption: Option<Disruption>,
+    /// Caps on the pool's total resources, e.g. `cpu: "100"`, and `nodes`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub limits: BTreeMap<String, Quantity>,
+}
+
+/// The NodeClaims a Karpenter NodePool makes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeClaimTemplate {
+    #[serde(default)]
+    pub metadata: TemplateMetadata,
+    #[serde(default)]
+    pub spec: NodeClaimSpec,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateMetadata {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// When a Karpenter NodePool's nodes may be disrupted.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Disruption {
+    /// `WhenEmpty` or `WhenEmptyOrUnderutilized`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consolidation_policy: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub budgets: Vec<DisruptionBudget>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DisruptionBudget {
+    /// Nodes that may be disrupted at once: a count or a percentage.
+    pub nodes: String,
+}
+
+/// Spec of a Karpenter NodeClaim: the node Karpenter wants, as constraints
+/// rather than an instance type.
+#[derive(CustomResource, Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[kube(group = "karpenter.sh", version = "v1", kind = "NodeClaim")]
+#[kube(status = "NodeClaimStatus")]
+#[serde(rename_all = "camelCase")]
+pub struct NodeClaimSpec {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requirements: Vec<NodeSelectorRequirement>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub taints: Vec<Taint>,
+    #[serde(default)]
+    pub resources: ResourceRequests,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceRequests {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub requests: BTreeMap<String, Quantity>,
+}
+
+/// Status of a Karpenter NodeClaim, as growth-rs reports it.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeClaimStatus {
+    #[serde(
+        rename = "providerID",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub provider_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_name: Option<String>,
+    /// `Launched`, `Registered`, `Initialized` and `Ready`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<Condition>,
+}
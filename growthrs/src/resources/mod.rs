@@ -1,6 +1,185 @@
 /// Resources - Primarily Custom Resource Definitions (CRDs), but also configuration files like UserData
 pub mod hetzner_node_class;
+pub mod karpenter;
 pub mod node_pool;
 pub mod node_removal_request;
 pub mod node_request;
+pub mod provider_config;
 pub(crate) mod user_data;
+
+use k8s_openapi::ByteString;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+    CustomResourceConversion, CustomResourceDefinition, ServiceReference, WebhookClientConfig,
+    WebhookConversion,
+};
+use kube::api::{Patch, PatchParams};
+use kube::core::crd::merge_crds;
+use kube::{Api, Client, CustomResourceExt};
+
+/// The Service the API server calls to convert NodeRequests between
+/// `v1alpha1` and `v1beta1`, on port 443 at `/convert`.
+#[derive(Debug, Clone)]
+pub struct ConversionService {
+    pub namespace: String,
+    pub name: String,
+    /// PEM bundle of the CA that signed the webhook's certificate.
+    pub ca_bundle: Vec<u8>,
+}
+
+/// Every CustomResourceDefinition growth-rs defines, without a conversion
+/// webhook: NodeRequest `v1beta1` is defined but not served.
+pub fn crds() -> Vec<CustomResourceDefinition> {
+    crds_with(None)
+}
+
+/// [`crds`], with NodeRequest `v1beta1` served through `conversion` when
+/// it's given.
+pub fn crds_with(conversion: Option<&ConversionService>) -> Vec<CustomResourceDefinition> {
+    vec![
+        node_request_crd(conversion),
+        node_pool::NodePool::crd(),
+        node_removal_request::NodeRemovalRequest::crd(),
+        hetzner_node_class::HetznerNodeClass::crd(),
+        provider_config::ProviderConfig::crd(),
+    ]
+}
+
+/// NodeRequest's CRD: `v1alpha1`, the storage version, and `v1beta1`.
+/// Without a conversion webhook the API server would hand out stored
+/// `v1alpha1` objects relabelled as `v1beta1`, so that version is only served
+/// with one.
+fn node_request_crd(conversion: Option<&ConversionService>) -> CustomResourceDefinition {
+    let mut crd = merge_crds(
+        vec![
+            node_request::NodeRequest::crd(),
+            node_request::v1beta1::NodeRequest::crd(),
+        ],
+        "v1alpha1",
+    )
+    .expect("NodeRequest versions share group, kind and scope");
+    for version in &mut crd.spec.versions {
+        if version.name == "v1beta1" {
+            version.served = conversion.is_some();
+        }
+    }
+    crd.spec.conversion = conversion.map(|c| CustomResourceConversion {
+        strategy: "Webhook".into(),
+        webhook: Some(WebhookConversion {
+            client_config: Some(WebhookClientConfig {
+                service: Some(ServiceReference {
+                    namespace: c.namespace.clone(),
+                    name: c.name.clone(),
+                    path: Some("/convert".into()),
+                    port: Some(443),
+                }),
+                ca_bundle: Some(ByteString(c.ca_bundle.clone())),
+                url: None,
+            }),
+            conversion_review_versions: vec!["v1".into()],
+        }),
+    });
+    crd
+}
+
+/// [`crds`] as one multi-document YAML stream.
+pub fn crds_yaml() -> String {
+    crds()
+        .iter()
+        .map(|crd| serde_yaml::to_string(crd).expect("CRDs serialize"))
+        .collect::<Vec<_>>()
+        .join("---\n")
+}
+
+/// Install or update every CRD in [`crds_with`] with server-side apply,
+/// taking over fields last set by kubectl or Helm. Returns the CRDs' names.
+pub async fn apply_crds(
+    client: &Client,
+    conversion: Option<&ConversionService>,
+) -> Result<Vec<String>, kube::Error> {
+    let api: Api<CustomResourceDefinition> = Api::all(client.clone());
+    let params = PatchParams::apply("growthrs").force();
+    let mut applied = Vec::new();
+    for crd in crds_with(conversion) {
+        let name = crd.metadata.name.clone().unwrap_or_default();
+        api.patch(&name, &params, &Patch::Apply(&crd)).await?;
+        applied.push(name);
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn every_crd_is_applied() {
+        let (mock_svc, mut handle) = tower_test::mock::pair::<
+            http::Request<kube::client::Body>,
+            http::Response<kube::client::Body>,
+        >();
+        let server = tokio::spawn(async move {
+            let mut paths = Vec::new();
+            while let Some((request, send)) = handle.next_request().await {
+                assert_eq!(request.method(), http::Method::PATCH);
+                assert!(request.uri().query().unwrap().contains("force=true"));
+                paths.push(request.uri().path().to_string());
+                let body = request.into_body().collect_bytes().await.unwrap();
+                send.send_response(
+                    http::Response::builder()
+                        .body(kube::client::Body::from(body.to_vec()))
+                        .unwrap(),
+                );
+            }
+            paths
+        });
+
+        let applied = apply_crds(&Client::new(mock_svc, "default"), None)
+            .await
+            .unwrap();
+        let paths = server.await.unwrap();
+
+        assert_eq!(applied.len(), crds().len());
+        assert_eq!(
+            paths[0],
+            "/apis/apiextensions.k8s.io/v1/customresourcedefinitions/noderequests.growth.vettrdev.com"
+        );
+    }
+
+    #[test]
+    fn v1beta1_is_only_served_with_a_conversion_webhook() {
+        let served = |crd: &CustomResourceDefinition| {
+            crd.spec
+                .versions
+                .iter()
+                .map(|v| (v.name.clone(), v.served, v.storage))
+                .collect::<Vec<_>>()
+        };
+
+        let plain = node_request_crd(None);
+        assert_eq!(
+            served(&plain),
+            [
+                ("v1alpha1".to_string(), true, true),
+                ("v1beta1".to_string(), false, false)
+            ]
+        );
+        assert!(plain.spec.conversion.is_none());
+
+        let converting = node_request_crd(Some(&ConversionService {
+            namespace: "growth".into(),
+            name: "growth-webhook".into(),
+            ca_bundle: b"ca".to_vec(),
+        }));
+        assert_eq!(
+            served(&converting),
+            [
+                ("v1alpha1".to_string(), true, true),
+                ("v1beta1".to_string(), true, false)
+            ]
+        );
+        let conversion = converting.spec.conversion.unwrap();
+        assert_eq!(conversion.strategy, "Webhook");
+        let service = conversion.webhook.unwrap().client_config.unwrap().service;
+        assert_eq!(service.unwrap().path.as_deref(), Some("/convert"));
+    }
+}
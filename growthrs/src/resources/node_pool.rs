@@ -1,9 +1,13 @@
 use std::collections::BTreeMap;
 
+use k8s_openapi::api::core::v1::Taint;
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::optimiser::CostObjective;
+use crate::resources::user_data::JoinStrategy;
+
 /// Reference to a provider-specific NodeClass (e.g. HetznerNodeClass).
 ///
 /// `group` and `kind` identify which CRD type to look up. Defaults to
@@ -36,14 +40,40 @@ fn default_node_class_kind() -> String {
 /// Pods are matched to pools via the `growth.vettrdev.com/pool` nodeSelector label.
 #[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[kube(group = "growth.vettrdev.com", version = "v1alpha1", kind = "NodePool", shortname = "np")]
+#[kube(status = "NodePoolStatus")]
 #[serde(rename_all = "camelCase")]
 pub struct NodePoolSpec {
     /// Server types available in this pool, each with scaling limits.
     pub server_types: Vec<ServerTypeConfig>,
+    /// Nodes the pool keeps at all times: missing ones are provisioned ahead
+    /// of demand and idle ones are not removed below it. With 0, the default,
+    /// an idle pool scales down to no nodes at all.
+    #[serde(default)]
+    pub min_nodes: u32,
+    /// Most nodes the pool may have across all its server types, on top of
+    /// each type's own `max`. `None` means no pool-wide limit.
+    #[serde(default)]
+    pub max_nodes: Option<u32>,
+    /// Spare capacity kept provisioned ahead of demand, so bursts land on
+    /// warm nodes instead of waiting for new ones. `None` keeps none.
+    #[serde(default)]
+    pub headroom: Option<Headroom>,
     /// Labels applied to every node provisioned from this pool.
     /// Commonly used for topology labels (e.g. `topology.kubernetes.io/zone`).
     #[serde(default)]
     pub labels: BTreeMap<String, String>,
+    /// Taints applied to every node provisioned from this pool. Only pods
+    /// tolerating all of them are provisioned for here.
+    #[serde(default)]
+    pub taints: Vec<Taint>,
+    /// Extra kubelet flags for every node in this pool (e.g. `--max-pods=200`),
+    /// available to user-data templates as `{{ KUBELET_ARGS }}`.
+    #[serde(default)]
+    pub kubelet_args: Vec<String>,
+    /// How this pool's nodes join the cluster (`kubeadm`, `k3s` or `talos`),
+    /// overriding the NodeClass's user-data setting.
+    #[serde(default)]
+    pub join_strategy: Option<JoinStrategy>,
     /// If set, only offerings matching at least one entry are eligible.
     /// Each entry scopes allowed zones to a specific region.
     /// `None` means all regions and zones.
@@ -52,6 +82,108 @@ pub struct NodePoolSpec {
     /// Optional reference to a provider-specific NodeClass for instance config.
     #[serde(default)]
     pub node_class_ref: Option<NodeClassRef>,
+    /// Evacuate nodes from this pool: cordon them, provision replacements
+    /// elsewhere, then drain and remove them. Remove the field to end it.
+    #[serde(default)]
+    pub evacuation: Option<Evacuation>,
+    /// Replace underused nodes with cheaper ones, or remove them when their
+    /// pods fit on the pool's other nodes. `None` leaves busy nodes alone.
+    #[serde(default)]
+    pub consolidation: Option<Consolidation>,
+    /// Most of this pool's nodes that may be deprovisioning at once.
+    /// Further removals wait in `Pending`. `None` means no limit.
+    #[serde(default)]
+    pub disruption_budget: Option<u32>,
+    /// How strongly to avoid new nodes the pending pods would leave mostly
+    /// idle. At 1, offerings are compared by cost per CPU actually used
+    /// rather than per CPU offered, favouring fewer, fuller nodes that are
+    /// cheaper to consolidate later. `None` means 0.
+    #[serde(default)]
+    pub fragmentation_penalty: Option<f64>,
+    /// Share of each new node's CPU and memory to pack pods into, leaving
+    /// the rest free for restarts and bursts (e.g. `0.85`). `None` packs
+    /// nodes full.
+    #[serde(default)]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub target_utilization: Option<f64>,
+    /// How much an offering's interruption risk (spot/preemptible capacity)
+    /// counts against its price: cost is scaled by `1 + penalty * risk`.
+    /// Set it for long-running workloads. `None` means 0, price alone.
+    #[serde(default)]
+    pub interruption_penalty: Option<f64>,
+    /// What offerings' prices are compared by: `perVcpu` (the default),
+    /// `hourlyCost`, or `pricePerformance` — cost per share of the pending
+    /// CPU or memory a node would actually hold.
+    #[serde(default)]
+    pub cost_objective: Option<CostObjective>,
+    /// Instance families this pool may provision (e.g. `ccx`, `gpu`), so a
+    /// GPU pool only ever gets GPU nodes and a general pool never does.
+    /// Server types from other families are ignored. `None` allows any.
+    #[serde(default)]
+    pub instance_families: Option<Vec<String>>,
+    /// Most one reconcile's new nodes for this pool may add to the hourly
+    /// bill, in USD. Pods that would need more stay pending until the next
+    /// reconcile. `None` means no cap.
+    #[serde(default)]
+    pub max_additional_cost_per_hour: Option<f64>,
+    /// Most NodeRequests one reconcile may create for this pool, against
+    /// runaway scale-ups from misconfigured workloads. `None` means no cap.
+    #[serde(default)]
+    pub max_new_nodes: Option<u32>,
+    /// Instance-type patterns new nodes must match one of, against the type
+    /// name or its family (`ccx*`, `gpu-*`; `*` and `?` wildcards). `None`
+    /// allows every type.
+    #[serde(default)]
+    pub include_instance_types: Option<Vec<String>>,
+    /// Instance-type patterns new nodes must match none of, e.g. `cx*` to
+    /// ban shared-CPU servers. Wins over `includeInstanceTypes`.
+    #[serde(default)]
+    pub exclude_instance_types: Option<Vec<String>>,
+}
+
+/// Spare capacity for a pool: room for `replicas` placeholder pods of
+/// `cpuMillis`/`memoryMib` each, on top of what its pods use.
+///
+/// The placeholders are never created; they are solved for like
+/// lowest-priority pending pods, on the pool's free room first, and the
+/// nodes they need are provisioned. Real pods take that room as they come,
+/// and the headroom is provisioned again.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Headroom {
+    pub replicas: u32,
+    #[serde(default)]
+    pub cpu_millis: u64,
+    #[serde(default)]
+    pub memory_mib: u64,
+}
+
+/// An operator-requested evacuation of a pool, or some of its zones.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Evacuation {
+    /// Zones (`topology.kubernetes.io/zone`) to evacuate. Replacements are
+    /// never placed in these zones. Empty evacuates every node in the pool.
+    #[serde(default)]
+    pub zones: Vec<String>,
+}
+
+impl Evacuation {
+    /// Whether a node in zone `zone` is being evacuated.
+    pub fn covers(&self, zone: Option<&str>) -> bool {
+        self.zones.is_empty() || zone.is_some_and(|z| self.zones.iter().any(|e| e == z))
+    }
+}
+
+/// Cost consolidation settings for a pool.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Consolidation {
+    /// Share of a node's hourly cost its replacement must save (e.g. `0.2`)
+    /// before the node is replaced. `None` means any saving.
+    #[serde(default)]
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub min_savings: Option<f64>,
 }
 
 /// A region with optional zone restrictions.
@@ -67,6 +199,15 @@ pub struct LocationConstraint {
     pub zones: Option<Vec<String>>,
 }
 
+/// Status of a NodePool.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodePoolStatus {
+    /// Nodes the pool has, by instance type, as of the last idle-node scan.
+    #[serde(default)]
+    pub nodes: BTreeMap<String, u32>,
+}
+
 /// Configuration for a single server type within a NodePool.
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -75,7 +216,8 @@ pub struct ServerTypeConfig {
     pub name: String,
     /// Maximum number of nodes of this type the pool may provision.
     pub max: u32,
-    /// Minimum number of nodes to keep warm. Enforcement is a follow-up.
+    /// Minimum number of nodes of this type to keep warm, counting towards
+    /// the pool's `minNodes`.
     #[serde(default)]
     pub min: u32,
 }
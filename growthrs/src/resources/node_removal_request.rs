@@ -42,6 +42,14 @@ impl NodeRemovalRequest {
             .map(|s| s.phase.clone())
             .unwrap_or_default()
     }
+
+    /// Whether the node's provider delete has been issued, which may be
+    /// before the phase says `Deprovisioning`.
+    pub fn delete_requested(&self) -> bool {
+        self.status
+            .as_ref()
+            .is_some_and(|s| s.delete_requested_at.is_some())
+    }
 }
 
 /// Phase of a NodeRemovalRequest through its lifecycle.
@@ -80,4 +88,9 @@ pub struct NodeRemovalRequestStatus {
     /// Timestamp of the last phase transition.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub last_transition_time: Option<Time>,
+    /// When the node's provider delete was issued. Set before the delete goes
+    /// out, so a restarted controller moves on to polling the provider instead
+    /// of tainting and deleting again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delete_requested_at: Option<Time>,
 }
@@ -1,8 +1,15 @@
+pub mod v1beta1;
+
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::Taint;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use k8s_openapi::jiff::Timestamp;
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::offering::{InstanceType, Region, Resources};
+use crate::offering::{InstanceType, Region, Resources, Zone};
 
 /// Spec for a NodeRequest — a request to provision a single node.
 ///
@@ -17,7 +24,9 @@ use crate::offering::{InstanceType, Region, Resources};
     kind = "NodeRequest",
     shortname = "nr",
     printcolumn = r#"{"name": "Phase", "type": "string", "jsonPath": ".status.phase"}"#,
-    printcolumn = r#"{"name": "Offering", "type": "string", "jsonPath": ".spec.targetOffering"}"#
+    printcolumn = r#"{"name": "Ready", "type": "string", "jsonPath": ".status.conditions[?(@.type==\"Ready\")].status"}"#,
+    printcolumn = r#"{"name": "Offering", "type": "string", "jsonPath": ".spec.targetOffering"}"#,
+    printcolumn = r#"{"name": "Provider", "type": "string", "jsonPath": ".spec.provider"}"#
 )]
 #[kube(status = "NodeRequestStatus")]
 #[serde(rename_all = "camelCase")]
@@ -29,8 +38,29 @@ pub struct NodeRequestSpec {
     pub target_offering: InstanceType,
     /// Provider location/region to provision in, e.g. "nbg1".
     pub location: Region,
+    /// Zone within `location` the solver placed this node in, if the offering
+    /// has one. Absent on NodeRequests created before zone-aware placement.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zone: Option<Zone>,
     /// Snapshot of the resources this offering provides, captured at creation time.
     pub resources: Resources,
+    /// Provider the offering was chosen from, e.g. "hetzner".
+    /// Absent on NodeRequests created before multi-provider support.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// UIDs of the pending pods the solver placed on this node. Claims are
+    /// released as those pods are deleted or scheduled elsewhere.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub claimed_pods: Vec<String>,
+    /// The pool's labels when this NodeRequest was created, applied to its
+    /// node. Absent on NodeRequests created before they were recorded; the
+    /// owning NodePool's current labels are used instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+    /// The pool's taints when this NodeRequest was created, applied to its
+    /// node. Falls back to the NodePool's like `labels`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taints: Option<Vec<Taint>>,
 }
 
 impl NodeRequest {
@@ -41,8 +71,110 @@ impl NodeRequest {
             .map(|s| s.phase.clone())
             .unwrap_or_default()
     }
+
+    /// Whether condition `kind` is currently `True`.
+    pub fn condition_is_true(&self, kind: NodeRequestConditionType) -> bool {
+        self.status
+            .as_ref()
+            .is_some_and(|s| s.condition_is_true(kind))
+    }
+
+    /// The conditions with `kind` set `True` at `now`, for progress made
+    /// without a phase change.
+    pub fn conditions_with(
+        &self,
+        kind: NodeRequestConditionType,
+        reason: &str,
+        message: &str,
+        now: Timestamp,
+    ) -> Vec<Condition> {
+        let mut status = self.status.clone().unwrap_or_default();
+        status.set_condition(kind, "True", reason, message, self.metadata.generation, now);
+        status.conditions
+    }
+
+    /// The conditions with `Accepted` set `False` at `now`, for a request
+    /// held back before reaching the provider.
+    pub fn held_conditions(&self, reason: &str, message: &str, now: Timestamp) -> Vec<Condition> {
+        let mut status = self.status.clone().unwrap_or_default();
+        status.set_condition(
+            NodeRequestConditionType::Accepted,
+            "False",
+            reason,
+            message,
+            self.metadata.generation,
+            now,
+        );
+        status.conditions
+    }
+
+    /// The status after moving to `phase` at `now`: the transition time is
+    /// reset and an event named after the phase, with `reason`, is appended
+    /// to the log. Only the last [`MAX_NODE_REQUEST_EVENTS`] are kept. The
+    /// conditions follow the phase; see [`NodeRequestConditionType`].
+    pub fn transitioned(
+        &self,
+        phase: NodeRequestPhase,
+        reason: Option<String>,
+        now: Timestamp,
+    ) -> NodeRequestStatus {
+        let mut status = self.status.clone().unwrap_or_default();
+        let message = reason.as_deref().unwrap_or_default().to_string();
+        status.events.push(NodeRequestEvent {
+            at: Time(now),
+            name: phase.to_string(),
+            reason,
+        });
+        let excess = status.events.len().saturating_sub(MAX_NODE_REQUEST_EVENTS);
+        status.events.drain(..excess);
+        status.set_phase_conditions(&phase, &message, self.metadata.generation, now);
+        status.phase = phase;
+        status.last_transition_time = Some(Time(now));
+        status
+    }
+}
+
+/// Conditions on a NodeRequest, in the order they become `True`.
+///
+/// - `Accepted` — the provider accepted the create. `False` on a Pending
+///   request held back before reaching the provider, e.g. by the budget.
+/// - `Launched` — the provider reports the instance running.
+/// - `Registered` — a Node object for it joined the cluster.
+/// - `Ready` — that Node is Ready and the NodeRequest is done.
+///
+/// Leaving `Pending` sets all four; those not yet reached are `Unknown`.
+/// When the request goes `Unmet`, every condition not yet `True` turns
+/// `False`, so `kubectl wait --for=condition=Ready` can tell failure from
+/// progress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeRequestConditionType {
+    Accepted,
+    Launched,
+    Registered,
+    Ready,
 }
 
+impl NodeRequestConditionType {
+    pub const ALL: [Self; 4] = [
+        Self::Accepted,
+        Self::Launched,
+        Self::Registered,
+        Self::Ready,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Accepted => "Accepted",
+            Self::Launched => "Launched",
+            Self::Registered => "Registered",
+            Self::Ready => "Ready",
+        }
+    }
+}
+
+/// Most events kept on a NodeRequest's status; older ones are dropped.
+pub const MAX_NODE_REQUEST_EVENTS: usize = 16;
+
 /// Phase of a NodeRequest through its lifecycle.
 ///
 /// - `Pending` — initial state, waiting to be sent to the provider.
@@ -91,4 +223,189 @@ pub struct NodeRequestStatus {
     /// Timestamp of the most recent phase transition.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub last_transition_time: Option<k8s_openapi::apimachinery::pkg::apis::meta::v1::Time>,
+    /// ID the provider returned when it accepted the create.
+    #[serde(rename = "nodeID", default, skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    /// `Accepted`, `Launched`, `Registered` and `Ready` conditions, each with
+    /// the generation it was set for.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<Condition>,
+}
+
+impl NodeRequestStatus {
+    pub fn condition(&self, kind: NodeRequestConditionType) -> Option<&Condition> {
+        self.conditions.iter().find(|c| c.type_ == kind.as_str())
+    }
+
+    pub fn condition_is_true(&self, kind: NodeRequestConditionType) -> bool {
+        self.condition(kind).is_some_and(|c| c.status == "True")
+    }
+
+    /// Set one condition. Its transition time only moves when its status
+    /// changes.
+    fn set_condition(
+        &mut self,
+        kind: NodeRequestConditionType,
+        status: &str,
+        reason: &str,
+        message: &str,
+        generation: Option<i64>,
+        now: Timestamp,
+    ) {
+        let last_transition_time = match self.condition(kind) {
+            Some(c) if c.status == status => c.last_transition_time.clone(),
+            _ => Time(now),
+        };
+        let condition = Condition {
+            type_: kind.as_str().to_string(),
+            status: status.to_string(),
+            reason: reason.to_string(),
+            message: message.to_string(),
+            observed_generation: generation,
+            last_transition_time,
+        };
+        match self
+            .conditions
+            .iter_mut()
+            .find(|c| c.type_ == condition.type_)
+        {
+            Some(existing) => *existing = condition,
+            None => self.conditions.push(condition),
+        }
+    }
+
+    /// Bring the conditions in line with a move to `phase`.
+    fn set_phase_conditions(
+        &mut self,
+        phase: &NodeRequestPhase,
+        message: &str,
+        generation: Option<i64>,
+        now: Timestamp,
+    ) {
+        use NodeRequestConditionType::*;
+        for kind in NodeRequestConditionType::ALL {
+            let (status, reason) = match (phase, kind) {
+                (NodeRequestPhase::Ready, _) => ("True", "NodeReady"),
+                (NodeRequestPhase::Provisioning, Accepted) => ("True", "ProviderAccepted"),
+                _ if self.condition_is_true(kind) => continue,
+                (NodeRequestPhase::Unmet, _) => ("False", "Unmet"),
+                (_, Ready) => ("False", "Provisioning"),
+                _ => ("Unknown", "Provisioning"),
+            };
+            self.set_condition(kind, status, reason, message, generation, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nr() -> NodeRequest {
+        NodeRequest::new(
+            "nr-a",
+            NodeRequestSpec {
+                node_id: "growth-a".into(),
+                target_offering: InstanceType("cpx22".into()),
+                location: Region("eu-central".into()),
+                zone: None,
+                resources: Resources {
+                    cpu_millis: 2000,
+                    memory_mib: 4096,
+                    ephemeral_storage_gib: None,
+                    gpu: 0,
+                    gpu_model: None,
+                    arch: None,
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
+                },
+                provider: None,
+                claimed_pods: vec![],
+                labels: None,
+                taints: None,
+            },
+        )
+    }
+
+    #[test]
+    fn transitions_append_to_a_bounded_event_log() {
+        let mut nr = nr();
+        let t0 = Timestamp::from_second(1_000).unwrap();
+        let status = nr.transitioned(NodeRequestPhase::Provisioning, None, t0);
+        assert_eq!(status.phase, NodeRequestPhase::Provisioning);
+        assert_eq!(status.last_transition_time, Some(Time(t0)));
+        assert_eq!(status.events.len(), 1);
+        assert_eq!(status.events[0].name, "Provisioning");
+
+        for i in 0..MAX_NODE_REQUEST_EVENTS {
+            nr.status =
+                Some(nr.transitioned(NodeRequestPhase::Unmet, Some(format!("attempt {i}")), t0));
+        }
+        let events = &nr.status.as_ref().unwrap().events;
+        assert_eq!(events.len(), MAX_NODE_REQUEST_EVENTS);
+        assert_eq!(events[0].reason.as_deref(), Some("attempt 0"));
+        let last = nr.transitioned(NodeRequestPhase::Ready, None, t0);
+        assert_eq!(last.events.len(), MAX_NODE_REQUEST_EVENTS);
+        assert_eq!(last.events[0].reason.as_deref(), Some("attempt 1"));
+        assert_eq!(last.events.last().unwrap().name, "Ready");
+    }
+
+    #[test]
+    fn conditions_follow_the_phase() {
+        use NodeRequestConditionType::*;
+        let statuses = |s: &NodeRequestStatus| {
+            NodeRequestConditionType::ALL.map(|k| s.condition(k).unwrap().status.clone())
+        };
+        let mut nr = nr();
+        nr.metadata.generation = Some(3);
+        let (t0, t1) = (
+            Timestamp::from_second(1_000).unwrap(),
+            Timestamp::from_second(2_000).unwrap(),
+        );
+
+        let provisioning = nr.transitioned(NodeRequestPhase::Provisioning, None, t0);
+        assert_eq!(
+            statuses(&provisioning),
+            ["True", "Unknown", "Unknown", "False"]
+        );
+        assert_eq!(
+            provisioning.condition(Ready).unwrap().observed_generation,
+            Some(3)
+        );
+        nr.status = Some(provisioning);
+
+        let launched = nr.conditions_with(Launched, "InstanceRunning", "running", t1);
+        nr.status.as_mut().unwrap().conditions = launched;
+        assert!(nr.condition_is_true(Launched));
+
+        // A timed-out request keeps what it reached and fails the rest.
+        let unmet = nr.transitioned(NodeRequestPhase::Unmet, Some("timed out".into()), t1);
+        assert_eq!(statuses(&unmet), ["True", "True", "False", "False"]);
+        let accepted = unmet.condition(Accepted).unwrap();
+        assert_eq!(accepted.last_transition_time, Time(t0));
+        assert_eq!(unmet.condition(Registered).unwrap().message, "timed out");
+
+        let ready = nr.transitioned(NodeRequestPhase::Ready, None, t1);
+        assert_eq!(statuses(&ready), ["True"; 4]);
+    }
+
+    #[test]
+    fn held_requests_are_not_accepted_until_provisioned() {
+        use NodeRequestConditionType::*;
+        let mut nr = nr();
+        let t0 = Timestamp::from_second(1_000).unwrap();
+        nr.status = Some(NodeRequestStatus {
+            conditions: nr.held_conditions("PendingBudget", "over budget", t0),
+            ..Default::default()
+        });
+        let held = nr.status.as_ref().unwrap().condition(Accepted).unwrap();
+        assert_eq!(
+            (held.status.as_str(), held.reason.as_str()),
+            ("False", "PendingBudget")
+        );
+        assert_eq!(nr.phase(), NodeRequestPhase::Pending);
+
+        let provisioning = nr.transitioned(NodeRequestPhase::Provisioning, None, t0);
+        assert_eq!(provisioning.condition(Accepted).unwrap().status, "True");
+    }
 }
@@ -0,0 +1,335 @@
+//! `v1beta1` NodeRequest: the same request as `v1alpha1`, with the pool it
+//! belongs to, the node it asks for and the pods it was made for spelled out
+//! in the spec instead of spread over owner references and flat fields.
+//!
+//! `v1alpha1` stays the storage version and the one the controller reads and
+//! writes; `v1beta1` is only served once the API server can reach the
+//! conversion webhook (see [`convert`]). Until `v1beta1` becomes the storage
+//! version, what `v1alpha1` has no field for doesn't survive a write: pod
+//! names are dropped and `poolRef` is rebuilt from the owner references. The
+//! API server keeps only label and annotation changes from a conversion, so
+//! an owner reference can't be made up from `poolRef` here; `v1beta1` writes
+//! must carry the NodePool owner reference themselves, and the validating
+//! webhook refuses them without one or with a `poolRef` naming another pool
+//! (see [`pool_ref_problem`]).
+
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::Taint;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::{CustomResource, Resource};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::offering::{InstanceType, Region, Resources, Zone};
+use crate::resources::node_request as v1alpha1;
+use crate::resources::node_request::NodeRequestStatus;
+
+/// Spec for a `v1beta1` NodeRequest — a request to provision a single node.
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "growth.vettrdev.com",
+    version = "v1beta1",
+    kind = "NodeRequest",
+    shortname = "nr",
+    printcolumn = r#"{"name": "Phase", "type": "string", "jsonPath": ".status.phase"}"#,
+    printcolumn = r#"{"name": "Ready", "type": "string", "jsonPath": ".status.conditions[?(@.type==\"Ready\")].status"}"#,
+    printcolumn = r#"{"name": "Pool", "type": "string", "jsonPath": ".spec.poolRef.name"}"#,
+    printcolumn = r#"{"name": "Offering", "type": "string", "jsonPath": ".spec.targetOffering"}"#,
+    printcolumn = r#"{"name": "Provider", "type": "string", "jsonPath": ".spec.provider"}"#
+)]
+#[kube(status = "NodeRequestStatus")]
+#[serde(rename_all = "camelCase")]
+pub struct NodeRequestSpec {
+    /// The pre-determined name for this upcoming node.
+    #[serde(rename = "nodeID")]
+    pub node_id: String,
+    /// The NodePool this request was made for. Mirrors the controlling
+    /// owner reference, which is what garbage collection follows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool_ref: Option<PoolRef>,
+    /// The offering (instance type) to provision, e.g. "hetzner-cax11".
+    pub target_offering: InstanceType,
+    /// Provider location/region to provision in, e.g. "nbg1".
+    pub location: Region,
+    /// Zone within `location` the solver placed this node in, if the offering
+    /// has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zone: Option<Zone>,
+    /// Provider the offering was chosen from, e.g. "hetzner".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// What the node was planned as, captured at creation time.
+    pub snapshot: NodeSnapshot,
+    /// The pending pods the solver placed on this node.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nominated_pods: Vec<PodNomination>,
+}
+
+/// The NodePool a NodeRequest belongs to.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+pub struct PoolRef {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<String>,
+}
+
+/// The resources, labels and taints a NodeRequest's node was planned with.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct NodeSnapshot {
+    /// Resources the offering provides.
+    pub resources: Resources,
+    /// The pool's labels, applied to the node. Absent when they weren't
+    /// recorded; the pool's current ones are used instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+    /// The pool's taints, applied to the node. Falls back like `labels`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub taints: Option<Vec<Taint>>,
+}
+
+/// A pending pod the solver placed on a NodeRequest's node.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+pub struct PodNomination {
+    pub uid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+fn pool_ref(owners: Option<&[OwnerReference]>) -> Option<PoolRef> {
+    owners?
+        .iter()
+        .find(|r| r.kind == "NodePool")
+        .map(|r| PoolRef {
+            name: r.name.clone(),
+            uid: Some(r.uid.clone()),
+        })
+}
+
+impl From<v1alpha1::NodeRequest> for NodeRequest {
+    fn from(nr: v1alpha1::NodeRequest) -> Self {
+        let spec = nr.spec;
+        let mut converted = NodeRequest::new(
+            "",
+            NodeRequestSpec {
+                node_id: spec.node_id,
+                pool_ref: pool_ref(nr.metadata.owner_references.as_deref()),
+                target_offering: spec.target_offering,
+                location: spec.location,
+                zone: spec.zone,
+                provider: spec.provider,
+                snapshot: NodeSnapshot {
+                    resources: spec.resources,
+                    labels: spec.labels,
+                    taints: spec.taints,
+                },
+                nominated_pods: spec
+                    .claimed_pods
+                    .into_iter()
+                    .map(|uid| PodNomination {
+                        uid,
+                        namespace: None,
+                        name: None,
+                    })
+                    .collect(),
+            },
+        );
+        converted.metadata = nr.metadata;
+        converted.status = nr.status;
+        converted
+    }
+}
+
+impl From<NodeRequest> for v1alpha1::NodeRequest {
+    fn from(nr: NodeRequest) -> Self {
+        let spec = nr.spec;
+        let mut converted = v1alpha1::NodeRequest::new(
+            "",
+            v1alpha1::NodeRequestSpec {
+                node_id: spec.node_id,
+                target_offering: spec.target_offering,
+                location: spec.location,
+                zone: spec.zone,
+                resources: spec.snapshot.resources,
+                provider: spec.provider,
+                claimed_pods: spec.nominated_pods.into_iter().map(|p| p.uid).collect(),
+                labels: spec.snapshot.labels,
+                taints: spec.snapshot.taints,
+            },
+        );
+        converted.metadata = nr.metadata;
+        converted.status = nr.status;
+        converted
+    }
+}
+
+/// Why `nr`'s `poolRef` would be lost when it is stored: it names a pool
+/// other than its NodePool owner reference, which is what `poolRef` is
+/// rebuilt from.
+pub fn pool_ref_problem(nr: &NodeRequest) -> Option<String> {
+    let wanted = nr.spec.pool_ref.as_ref()?;
+    let owner = pool_ref(nr.metadata.owner_references.as_deref());
+    let matches = owner.as_ref().is_some_and(|owner| {
+        owner.name == wanted.name
+            && wanted
+                .uid
+                .as_ref()
+                .is_none_or(|uid| owner.uid.as_ref() == Some(uid))
+    });
+    (!matches).then(|| {
+        format!(
+            "poolRef {:?} does not match the NodePool owner reference",
+            wanted.name
+        )
+    })
+}
+
+fn parse<K: serde::de::DeserializeOwned>(object: Value) -> Result<K, String> {
+    serde_json::from_value(object).map_err(|e| format!("invalid NodeRequest: {e}"))
+}
+
+fn to_value<K: Serialize>(object: &K) -> Result<Value, String> {
+    serde_json::to_value(object).map_err(|e| format!("NodeRequest did not serialize: {e}"))
+}
+
+/// Convert a NodeRequest, as the API server sends it to the conversion
+/// webhook, to `desired_api_version`.
+pub fn convert(object: Value, desired_api_version: &str) -> Result<Value, String> {
+    let alpha = v1alpha1::NodeRequest::api_version(&());
+    let beta = NodeRequest::api_version(&());
+    let from = object
+        .get("apiVersion")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    match (from.as_str(), desired_api_version) {
+        (from, to) if from == to => Ok(object),
+        (from, to) if from == alpha && to == beta => {
+            to_value(&NodeRequest::from(parse::<v1alpha1::NodeRequest>(object)?))
+        }
+        (from, to) if from == beta && to == alpha => {
+            to_value(&v1alpha1::NodeRequest::from(parse::<NodeRequest>(object)?))
+        }
+        (from, to) => Err(format!(
+            "cannot convert NodeRequest from {from:?} to {to:?}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alpha() -> v1alpha1::NodeRequest {
+        let mut nr = v1alpha1::NodeRequest::new(
+            "nr-a",
+            v1alpha1::NodeRequestSpec {
+                node_id: "growth-a".into(),
+                target_offering: InstanceType("cpx22".into()),
+                location: Region("eu-central".into()),
+                zone: Some(Zone("fsn1-dc14".into())),
+                resources: Resources {
+                    cpu_millis: 2000,
+                    memory_mib: 4096,
+                    ephemeral_storage_gib: None,
+                    gpu: 0,
+                    gpu_model: None,
+                    arch: None,
+                    mig: BTreeMap::new(),
+                    extended: BTreeMap::new(),
+                },
+                provider: Some("hetzner".into()),
+                claimed_pods: vec!["uid-1".into(), "uid-2".into()],
+                labels: Some(BTreeMap::from([("team".into(), "a".into())])),
+                taints: None,
+            },
+        );
+        nr.metadata.owner_references = Some(vec![OwnerReference {
+            api_version: "growth.vettrdev.com/v1alpha1".into(),
+            kind: "NodePool".into(),
+            name: "default".into(),
+            uid: "pool-uid".into(),
+            controller: Some(true),
+            block_owner_deletion: Some(true),
+        }]);
+        nr
+    }
+
+    #[test]
+    fn alpha_round_trips_through_beta() {
+        let original = serde_json::to_value(alpha()).unwrap();
+
+        let beta = convert(original.clone(), "growth.vettrdev.com/v1beta1").unwrap();
+        assert_eq!(beta["apiVersion"], "growth.vettrdev.com/v1beta1");
+        assert_eq!(beta["metadata"], original["metadata"]);
+        assert_eq!(beta["spec"]["poolRef"]["name"], "default");
+        assert_eq!(beta["spec"]["snapshot"]["resources"]["cpuMillis"], 2000);
+        assert_eq!(beta["spec"]["nominatedPods"][1]["uid"], "uid-2");
+
+        let back = convert(beta, "growth.vettrdev.com/v1alpha1").unwrap();
+        assert_eq!(back, original);
+    }
+
+    /// What the API server keeps of a conversion: `converted` with every
+    /// metadata field but labels and annotations restored from `sent`.
+    fn as_stored(sent: &Value, converted: Value) -> Value {
+        let mut kept = converted;
+        let mut metadata = sent["metadata"].clone();
+        for field in ["labels", "annotations"] {
+            match kept["metadata"].get(field) {
+                Some(value) => metadata[field] = value.clone(),
+                None => {
+                    metadata.as_object_mut().unwrap().remove(field);
+                }
+            }
+        }
+        kept["metadata"] = metadata;
+        kept
+    }
+
+    #[test]
+    fn beta_round_trips_through_storage() {
+        let beta = serde_json::to_value(NodeRequest::from(alpha())).unwrap();
+        let stored = as_stored(
+            &beta,
+            convert(beta.clone(), "growth.vettrdev.com/v1alpha1").unwrap(),
+        );
+        let served = as_stored(
+            &stored,
+            convert(stored.clone(), "growth.vettrdev.com/v1beta1").unwrap(),
+        );
+        assert_eq!(served, beta);
+        assert_eq!(served["spec"]["poolRef"]["uid"], "pool-uid");
+    }
+
+    #[test]
+    fn pool_ref_must_match_the_pool_owner() {
+        let mut nr = NodeRequest::from(alpha());
+        assert_eq!(pool_ref_problem(&nr), None);
+        nr.spec.pool_ref.as_mut().unwrap().uid = None;
+        assert_eq!(pool_ref_problem(&nr), None);
+
+        nr.spec.pool_ref.as_mut().unwrap().name = "gpu".into();
+        assert!(pool_ref_problem(&nr).is_some());
+        // Converting can't add the owner a poolRef stands for; the webhook
+        // refuses the write instead.
+        nr.spec.pool_ref.as_mut().unwrap().name = "default".into();
+        nr.metadata.owner_references = None;
+        assert!(pool_ref_problem(&nr).is_some());
+        let stored = v1alpha1::NodeRequest::from(nr);
+        assert_eq!(stored.metadata.owner_references, None);
+    }
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        let original = serde_json::to_value(alpha()).unwrap();
+        assert_eq!(
+            convert(original.clone(), "growth.vettrdev.com/v1alpha1").unwrap(),
+            original
+        );
+        assert!(convert(original, "growth.vettrdev.com/v2").is_err());
+    }
+}
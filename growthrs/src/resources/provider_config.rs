@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::offering::Offering;
+use crate::resources::user_data::SecretKeyRef;
+
+/// Spec for a ProviderConfig — which provider the controller uses and which
+/// of its offerings are eligible.
+///
+/// Cluster-scoped. The controller watches the ProviderConfig named by
+/// `GROWTH_PROVIDER_CONFIG` (default `default`) and swaps its provider when
+/// the spec changes. Without one, `GROWTH_PROVIDER` and `HCLOUD_TOKEN` apply.
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[kube(
+    group = "growth.vettrdev.com",
+    version = "v1alpha1",
+    kind = "ProviderConfig",
+    shortname = "pcfg",
+    printcolumn = r#"{"name": "Provider", "type": "string", "jsonPath": ".spec.provider"}"#,
+    printcolumn = r#"{"name": "Active", "type": "boolean", "jsonPath": ".status.active"}"#
+)]
+#[kube(status = "ProviderConfigStatus")]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderConfigSpec {
+    /// Provider(s) to use, comma-separated, as for `GROWTH_PROVIDER` (e.g. "hetzner" or "kwok,hetzner").
+    pub provider: String,
+    /// Regions offerings may be placed in. Empty means every region the provider offers.
+    #[serde(default)]
+    pub regions: Vec<String>,
+    /// Secret key holding the provider API token (the Hetzner Cloud token for `hetzner`).
+    /// Falls back to `HCLOUD_TOKEN` when unset.
+    #[serde(default)]
+    pub credentials_ref: Option<SecretKeyRef>,
+    /// Restrictions on which offerings the solver may choose.
+    #[serde(default)]
+    pub offerings: OfferingFilter,
+    /// Device-plugin resources the provider's catalogue doesn't describe, and
+    /// which instance types carry them.
+    #[serde(default)]
+    pub devices: Vec<DeviceMapping>,
+}
+
+/// Offering restrictions applied on top of the provider's catalogue.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OfferingFilter {
+    /// Instance types that may be used. Empty allows all.
+    #[serde(default)]
+    pub instance_types: Vec<String>,
+    /// Upper bound on hourly cost per instance.
+    #[serde(default)]
+    pub max_cost_per_hour: Option<f64>,
+}
+
+/// Maps a device-plugin resource (e.g. `xilinx.com/u30`, `habana.ai/gaudi`)
+/// onto the offerings that provide it.
+///
+/// Mapped offerings advertise the devices as an extended resource of that
+/// name, so pods requesting it are placed on them.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceMapping {
+    /// Extended resource name as requested by pods.
+    pub resource: String,
+    /// Instance type → number of devices each instance exposes.
+    pub instance_types: BTreeMap<String, u32>,
+}
+
+impl ProviderConfigSpec {
+    /// Whether `offering` is allowed by this config's regions and filters.
+    pub fn admits(&self, offering: &Offering) -> bool {
+        let region_ok =
+            self.regions.is_empty() || self.regions.contains(&offering.location.region.0);
+        let type_ok = self.offerings.instance_types.is_empty()
+            || self
+                .offerings
+                .instance_types
+                .contains(&offering.instance_type.0);
+        let cost_ok = self
+            .offerings
+            .max_cost_per_hour
+            .is_none_or(|max| offering.cost_per_hour <= max);
+        region_ok && type_ok && cost_ok
+    }
+
+    /// Drop offerings this config doesn't admit and attach mapped devices to
+    /// the rest.
+    pub fn apply(&self, mut offering: Offering) -> Option<Offering> {
+        if !self.admits(&offering) {
+            return None;
+        }
+        for device in &self.devices {
+            if let Some(&count) = device.instance_types.get(&offering.instance_type.0) {
+                offering
+                    .resources
+                    .extended
+                    .insert(device.resource.clone(), u64::from(count));
+            }
+        }
+        Some(offering)
+    }
+}
+
+/// Status of a ProviderConfig, written by the controller.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderConfigStatus {
+    /// Generation of the spec last acted on.
+    #[serde(default)]
+    pub observed_generation: Option<i64>,
+    /// Whether the controller is currently using this config.
+    #[serde(default)]
+    pub active: bool,
+    /// Why the config could not be applied, or a summary of what was.
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Providers failing their health probe. No NodeRequests are planned on
+    /// them until they recover.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unhealthy_providers: Vec<UnhealthyProvider>,
+}
+
+/// A provider failing its health probe.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnhealthyProvider {
+    pub provider: String,
+    /// Error from the most recent probe.
+    pub reason: String,
+    /// When the provider was first seen unhealthy.
+    pub since: k8s_openapi::apimachinery::pkg::apis::meta::v1::Time,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::offering;
+
+    fn spec(regions: &[&str], types: &[&str], max_cost: Option<f64>) -> ProviderConfigSpec {
+        ProviderConfigSpec {
+            provider: "fake".into(),
+            regions: regions.iter().map(|r| r.to_string()).collect(),
+            credentials_ref: None,
+            offerings: OfferingFilter {
+                instance_types: types.iter().map(|t| t.to_string()).collect(),
+                max_cost_per_hour: max_cost,
+            },
+            devices: vec![],
+        }
+    }
+
+    #[test]
+    fn empty_filters_admit_everything() {
+        assert!(
+            spec(&[], &[], None)
+                .admits(&offering("cx22").with_region("nbg1").with_cost(9.0).build())
+        );
+    }
+
+    #[test]
+    fn regions_types_and_cost_each_restrict() {
+        let s = spec(&["nbg1"], &["cx22", "cx32"], Some(0.02));
+        assert!(s.admits(&offering("cx22").with_region("nbg1").with_cost(0.01).build()));
+        assert!(!s.admits(&offering("cx22").with_region("fsn1").with_cost(0.01).build()));
+        assert!(!s.admits(&offering("cx42").with_region("nbg1").with_cost(0.01).build()));
+        assert!(!s.admits(&offering("cx32").with_region("nbg1").with_cost(0.03).build()));
+    }
+
+    #[test]
+    fn devices_are_attached_to_mapped_offerings() {
+        let mut s = spec(&[], &[], None);
+        s.devices = vec![DeviceMapping {
+            resource: "habana.ai/gaudi".into(),
+            instance_types: BTreeMap::from([("dl1".into(), 8)]),
+        }];
+
+        let mapped = s
+            .apply(offering("dl1").with_region("nbg1").with_cost(9.0).build())
+            .unwrap();
+        assert_eq!(mapped.resources.extended["habana.ai/gaudi"], 8);
+        let plain = s
+            .apply(offering("cx22").with_region("nbg1").with_cost(0.01).build())
+            .unwrap();
+        assert!(plain.resources.extended.is_empty());
+    }
+}
@@ -1,9 +1,9 @@
-use std::collections::BTreeMap;
-
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
 
-use crate::offering::{Offering, STARTUP_TAINT_KEY};
+use crate::offering::Offering;
+use crate::providers::provider::InstanceConfig;
 
 /// Dynamic variable names injected per-node at provision time.
 /// User-declared variables must not collide with these.
@@ -12,8 +12,31 @@ pub const RESERVED_DYNAMIC_VARS: &[&str] = &[
     "INSTANCE_TYPE",
     "NODE_LABELS",
     "NODE_TAINTS",
+    "KUBELET_ARGS",
 ];
 
+/// Injected from `InstanceConfig::join_token` when set, unless a variable of
+/// the same name is declared.
+pub const JOIN_TOKEN_VAR: &str = "JOIN_TOKEN";
+/// k3s agent join variables, injected under the `k3s` join strategy.
+pub const K3S_URL_VAR: &str = "K3S_URL";
+pub const K3S_TOKEN_VAR: &str = "K3S_TOKEN";
+
+/// How a provisioned node joins the cluster, which decides the form of the
+/// join variables and node flags injected into its user-data.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JoinStrategy {
+    /// `kubeadm join` with a bootstrap token; flags are kubelet flags.
+    #[default]
+    Kubeadm,
+    /// k3s agent with `K3S_URL`/`K3S_TOKEN`; flags are k3s agent flags.
+    K3s,
+    /// Talos Linux: the template is a worker machineconfig, which is patched
+    /// with the node's labels, taints, kubelet args and join token.
+    Talos,
+}
+
 /// Reference to a specific key in a Kubernetes ConfigMap.
 ///
 /// Used for non-sensitive data like user-data templates. Namespace must be
@@ -55,6 +78,33 @@ pub struct UserDataConfig {
     /// Defaults - [[RESERVED_DYNAMIC_VARS]]
     #[serde(default)]
     pub variables: Vec<TemplateVariable>,
+    /// kubeadm bootstrap token substituted for `{{ JOIN_TOKEN }}` (and
+    /// `{{ K3S_TOKEN }}` under k3s, which accepts the same tokens).
+    #[serde(default)]
+    pub bootstrap_token: Option<BootstrapTokenConfig>,
+    /// Join strategy for nodes of this class; a NodePool's own overrides it.
+    #[serde(default)]
+    pub join_strategy: Option<JoinStrategy>,
+    /// k3s server URL substituted for `{{ K3S_URL }}`, e.g. `https://10.0.0.2:6443`.
+    #[serde(default)]
+    pub k3s_server_url: Option<String>,
+}
+
+/// Where the kubeadm bootstrap token for a node's `JOIN_TOKEN` comes from.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BootstrapTokenConfig {
+    /// Read a pre-provisioned `<id>.<secret>` token from this Secret key.
+    /// When unset, a fresh token is minted in `kube-system` for each node.
+    #[serde(default)]
+    pub secret_ref: Option<SecretKeyRef>,
+    /// Lifetime of minted tokens in seconds.
+    #[serde(default = "default_bootstrap_token_ttl")]
+    pub ttl_seconds: u64,
+}
+
+fn default_bootstrap_token_ttl() -> u64 {
+    3600
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -82,6 +132,10 @@ pub enum UserDataError {
         reserved = RESERVED_DYNAMIC_VARS.join(", ")
     )]
     ReservedNameCollision { name: String },
+    #[error("failed to mint bootstrap token: {reason}")]
+    BootstrapTokenFailed { reason: String },
+    #[error("invalid Talos machineconfig: {reason}")]
+    InvalidMachineConfig { reason: String },
 }
 
 /// Replace all `{{ VARIABLE }}` placeholders in the template.
@@ -126,24 +180,190 @@ pub fn resolve_template(
     Ok(result)
 }
 
-/// Build the per-node dynamic variables from the offering and labels.
+/// A user-data template with its declared variables already read, rendered
+/// by the provider once the node's `InstanceConfig` and offering are known.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserDataTemplate {
+    /// Raw template, e.g. cloud-init YAML.
+    pub template: String,
+    /// Declared variables and their values.
+    pub variables: Vec<(String, String)>,
+    /// The NodeClass's join strategy, used when the node's config has none.
+    pub join_strategy: Option<JoinStrategy>,
+    pub k3s_server_url: Option<String>,
+}
+
+impl UserDataTemplate {
+    /// Substitute the declared variables and the per-node dynamic ones. Dynamic
+    /// variables are only injected where the template uses them. Under the
+    /// Talos strategy the result is then patched as a machineconfig.
+    pub fn render(
+        &self,
+        config: &InstanceConfig,
+        offering: &Offering,
+    ) -> Result<String, UserDataError> {
+        let mut vars = self.variables.clone();
+        let strategy = config
+            .join_strategy
+            .or(self.join_strategy)
+            .unwrap_or_default();
+        let mut dynamic = build_dynamic_vars(offering, config, strategy);
+        if strategy == JoinStrategy::K3s
+            && let Some(url) = &self.k3s_server_url
+        {
+            dynamic.push((K3S_URL_VAR.to_string(), url.clone()));
+        }
+        for (name, value) in dynamic {
+            if self.takes_dynamic(&name) {
+                vars.push((name, value));
+            }
+        }
+        let rendered = resolve_template(&self.template, &vars)?;
+        match strategy {
+            JoinStrategy::Talos => patch_talos_machine_config(&rendered, config),
+            JoinStrategy::Kubeadm | JoinStrategy::K3s => Ok(rendered),
+        }
+    }
+
+    /// Whether rendering under the node's `join_strategy` (or the template's)
+    /// would use a join token: a Talos machineconfig always takes one, other
+    /// templates only where they reference `{{ JOIN_TOKEN }}` (or
+    /// `{{ K3S_TOKEN }}` under k3s) without declaring it themselves.
+    pub fn uses_join_token(&self, join_strategy: Option<JoinStrategy>) -> bool {
+        match join_strategy.or(self.join_strategy).unwrap_or_default() {
+            JoinStrategy::Talos => true,
+            JoinStrategy::K3s => {
+                self.takes_dynamic(JOIN_TOKEN_VAR) || self.takes_dynamic(K3S_TOKEN_VAR)
+            }
+            JoinStrategy::Kubeadm => self.takes_dynamic(JOIN_TOKEN_VAR),
+        }
+    }
+
+    /// Whether dynamic variable `name` is substituted: the template uses it
+    /// and no declared variable shadows it.
+    fn takes_dynamic(&self, name: &str) -> bool {
+        self.template.contains(&format!("{{{{ {name} }}}}"))
+            && !self.variables.iter().any(|(n, _)| n == name)
+    }
+}
+
+/// Merge the node's labels, taints and kubelet args into a Talos
+/// machineconfig, and set `cluster.token` to its join token.
+///
+/// Only the first YAML document (the `v1alpha1` config) is patched; any
+/// further documents in a multi-document config pass through unchanged.
+/// Labels, taints and args already in the template are kept unless the node
+/// sets the same key.
+fn patch_talos_machine_config(
+    rendered: &str,
+    config: &InstanceConfig,
+) -> Result<String, UserDataError> {
+    let invalid = |reason: String| UserDataError::InvalidMachineConfig { reason };
+    let mut docs = serde_yaml::Deserializer::from_str(rendered)
+        .map(Value::deserialize)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(e.to_string()))?;
+    let Some(Value::Mapping(root)) = docs.first_mut() else {
+        return Err(invalid("first document is not a mapping".into()));
+    };
+
+    let machine = section(root, "machine")?;
+    let labels = section(machine, "nodeLabels")?;
+    for (k, v) in &config.labels {
+        labels.insert(k.as_str().into(), v.as_str().into());
+    }
+    let taints = section(machine, "nodeTaints")?;
+    for t in &config.taints {
+        let value = format!("{}:{}", t.value.as_deref().unwrap_or_default(), t.effect);
+        taints.insert(t.key.as_str().into(), value.into());
+    }
+    let extra_args = section(section(machine, "kubelet")?, "extraArgs")?;
+    for arg in &config.kubelet_args {
+        let arg = arg.trim_start_matches("--");
+        let (k, v) = arg.split_once('=').unwrap_or((arg, "true"));
+        extra_args.insert(k.into(), v.into());
+    }
+    if let Some(token) = &config.join_token {
+        section(root, "cluster")?.insert("token".into(), token.as_str().into());
+    }
+
+    let mut out = String::new();
+    for (i, doc) in docs.iter().enumerate() {
+        if i > 0 {
+            out.push_str("---\n");
+        }
+        out.push_str(&serde_yaml::to_string(doc).map_err(|e| invalid(e.to_string()))?);
+    }
+    Ok(out)
+}
+
+/// The mapping under `key`, created if absent or null.
+fn section<'a>(parent: &'a mut Mapping, key: &str) -> Result<&'a mut Mapping, UserDataError> {
+    let value = parent
+        .entry(key.into())
+        .or_insert_with(|| Value::Mapping(Mapping::new()));
+    if value.is_null() {
+        *value = Value::Mapping(Mapping::new());
+    }
+    value
+        .as_mapping_mut()
+        .ok_or_else(|| UserDataError::InvalidMachineConfig {
+            reason: format!("`{key}` is not a mapping"),
+        })
+}
+
+/// Build the per-node dynamic variables from the offering and instance config.
 ///
 /// These are the reserved dynamic variables (REGION, INSTANCE_TYPE,
-/// NODE_LABELS, NODE_TAINTS) that are injected at provision time regardless
-/// of provider.
+/// NODE_LABELS, NODE_TAINTS, KUBELET_ARGS) that are injected at provision
+/// time regardless of provider, plus JOIN_TOKEN (and K3S_TOKEN under k3s)
+/// when the config carries one. Flags are formatted for the join strategy:
+/// kubelet flags for kubeadm, k3s agent flags for k3s.
 pub(crate) fn build_dynamic_vars(
     offering: &Offering,
-    labels: &BTreeMap<String, String>,
+    config: &InstanceConfig,
+    strategy: JoinStrategy,
 ) -> Vec<(String, String)> {
-    let node_labels_str = labels
+    let node_labels_str = config
+        .labels
         .iter()
         .map(|(k, v)| format!("--node-label={k}={v}"))
         .collect::<Vec<_>>()
         .join(" ");
 
-    let node_taints_str = format!("--register-with-taints={STARTUP_TAINT_KEY}=:NoExecute");
+    let taints = config.taints.iter().map(|t| {
+        format!(
+            "{}={}:{}",
+            t.key,
+            t.value.as_deref().unwrap_or_default(),
+            t.effect
+        )
+    });
+    let (node_taints_str, kubelet_args_str) = match strategy {
+        JoinStrategy::Kubeadm | JoinStrategy::Talos => {
+            let taints = taints.collect::<Vec<_>>().join(",");
+            let node_taints = if taints.is_empty() {
+                String::new()
+            } else {
+                format!("--register-with-taints={taints}")
+            };
+            (node_taints, config.kubelet_args.join(" "))
+        }
+        JoinStrategy::K3s => (
+            taints
+                .map(|t| format!("--node-taint={t}"))
+                .collect::<Vec<_>>()
+                .join(" "),
+            config
+                .kubelet_args
+                .iter()
+                .map(|a| format!("--kubelet-arg={}", a.trim_start_matches("--")))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+    };
 
-    vec![
+    let mut vars = vec![
         ("REGION".to_string(), offering.location.region.0.clone()),
         (
             "INSTANCE_TYPE".to_string(),
@@ -151,14 +371,26 @@ pub(crate) fn build_dynamic_vars(
         ),
         ("NODE_LABELS".to_string(), node_labels_str),
         ("NODE_TAINTS".to_string(), node_taints_str),
-    ]
+        ("KUBELET_ARGS".to_string(), kubelet_args_str),
+    ];
+    if let Some(token) = &config.join_token {
+        vars.push((JOIN_TOKEN_VAR.to_string(), token.clone()));
+        if strategy == JoinStrategy::K3s {
+            vars.push((K3S_TOKEN_VAR.to_string(), token.clone()));
+        }
+    }
+    vars
 }
 
-
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use k8s_openapi::api::core::v1::Taint;
+
+    use crate::offering::STARTUP_TAINT_KEY;
+    use crate::testing::offering;
+
     #[test]
     fn resolve_template_success() {
         let template = "server={{ SERVER_URL }}\ntoken={{ JOIN_TOKEN }}\n";
@@ -199,27 +431,13 @@ mod tests {
 
     #[test]
     fn build_dynamic_vars_includes_all_reserved() {
-        use crate::offering::{InstanceType, Location, Offering, Region, Resources};
-
-        let offering = Offering {
-            instance_type: InstanceType("cpx22".into()),
-            resources: Resources {
-                cpu: 2,
-                memory_mib: 4096,
-                ephemeral_storage_gib: None,
-                gpu: 0,
-                gpu_model: None,
-            },
-            cost_per_hour: 0.01,
-            location: Location {
-                region: Region("fsn1".into()),
-                zone: None,
-            },
-        };
-        let mut labels = std::collections::BTreeMap::new();
-        labels.insert("growth.vettrdev.com/pool".into(), "default".into());
+        let offering = offering("cpx22").with_region("fsn1").build();
+        let mut config = InstanceConfig::default();
+        config
+            .labels
+            .insert("growth.vettrdev.com/pool".into(), "default".into());
 
-        let vars = build_dynamic_vars(&offering, &labels);
+        let vars = build_dynamic_vars(&offering, &config, JoinStrategy::Kubeadm);
         let names: Vec<&str> = vars.iter().map(|(n, _)| n.as_str()).collect();
 
         assert_eq!(
@@ -240,26 +458,17 @@ mod tests {
 
     #[test]
     fn build_dynamic_vars_node_taints_has_register_with_taints_flag() {
-        use crate::offering::{InstanceType, Location, Offering, Region, Resources};
-
-        let offering = Offering {
-            instance_type: InstanceType("cax11".into()),
-            resources: Resources {
-                cpu: 2,
-                memory_mib: 4096,
-                ephemeral_storage_gib: None,
-                gpu: 0,
-                gpu_model: None,
-            },
-            cost_per_hour: 0.01,
-            location: Location {
-                region: Region("fsn1".into()),
-                zone: None,
-            },
+        let offering = offering("cax11").with_region("fsn1").build();
+        let config = InstanceConfig {
+            taints: vec![Taint {
+                key: STARTUP_TAINT_KEY.into(),
+                effect: "NoExecute".into(),
+                ..Default::default()
+            }],
+            ..Default::default()
         };
-        let labels = std::collections::BTreeMap::new();
 
-        let vars = build_dynamic_vars(&offering, &labels);
+        let vars = build_dynamic_vars(&offering, &config, JoinStrategy::Kubeadm);
         let node_taints = vars.iter().find(|(n, _)| n == "NODE_TAINTS").unwrap();
         assert_eq!(
             node_taints.1,
@@ -267,6 +476,168 @@ mod tests {
         );
     }
 
+    #[test]
+    fn render_injects_instance_config() {
+        let offering = offering("cx22").with_region("nbg1").build();
+        let config = InstanceConfig {
+            taints: vec![Taint {
+                key: "gpu".into(),
+                value: Some("true".into()),
+                effect: "NoSchedule".into(),
+                ..Default::default()
+            }],
+            kubelet_args: vec!["--max-pods=200".into()],
+            join_token: Some("abcdef.0123456789abcdef".into()),
+            ..Default::default()
+        };
+        let template = UserDataTemplate {
+            template:
+                "kubelet {{ NODE_TAINTS }} {{ KUBELET_ARGS }}\njoin {{ SERVER }} {{ JOIN_TOKEN }}\n"
+                    .into(),
+            variables: vec![("SERVER".into(), "10.0.0.1:6443".into())],
+            join_strategy: None,
+            k3s_server_url: None,
+        };
+
+        assert_eq!(
+            template.render(&config, &offering).unwrap(),
+            "kubelet --register-with-taints=gpu=true:NoSchedule --max-pods=200\n\
+             join 10.0.0.1:6443 abcdef.0123456789abcdef\n"
+        );
+
+        // A declared JOIN_TOKEN wins over the controller's.
+        let declared = UserDataTemplate {
+            template: "{{ JOIN_TOKEN }}".into(),
+            variables: vec![("JOIN_TOKEN".into(), "from-secret".into())],
+            join_strategy: None,
+            k3s_server_url: None,
+        };
+        assert_eq!(declared.render(&config, &offering).unwrap(), "from-secret");
+    }
+
+    #[test]
+    fn render_k3s_agent_payload() {
+        let offering = offering("cx22").with_region("nbg1").build();
+        let mut config = InstanceConfig {
+            taints: vec![
+                Taint {
+                    key: "gpu".into(),
+                    value: Some("true".into()),
+                    effect: "NoSchedule".into(),
+                    ..Default::default()
+                },
+                Taint {
+                    key: STARTUP_TAINT_KEY.into(),
+                    effect: "NoExecute".into(),
+                    ..Default::default()
+                },
+            ],
+            kubelet_args: vec!["--max-pods=200".into()],
+            join_token: Some("abcdef.0123456789abcdef".into()),
+            ..Default::default()
+        };
+        let template = UserDataTemplate {
+            template: "K3S_URL={{ K3S_URL }} K3S_TOKEN={{ K3S_TOKEN }} \
+                       k3s agent {{ NODE_TAINTS }} {{ KUBELET_ARGS }}"
+                .into(),
+            variables: vec![],
+            join_strategy: Some(JoinStrategy::K3s),
+            k3s_server_url: Some("https://10.0.0.2:6443".into()),
+        };
+
+        assert_eq!(
+            template.render(&config, &offering).unwrap(),
+            "K3S_URL=https://10.0.0.2:6443 K3S_TOKEN=abcdef.0123456789abcdef \
+             k3s agent --node-taint=gpu=true:NoSchedule \
+             --node-taint=growth.vettrdev.com/unregistered=:NoExecute --kubelet-arg=max-pods=200"
+        );
+
+        // A pool that selects kubeadm gets no k3s variables.
+        config.join_strategy = Some(JoinStrategy::Kubeadm);
+        assert!(matches!(
+            template.render(&config, &offering),
+            Err(UserDataError::UnresolvedPlaceholder { name }) if name == K3S_URL_VAR
+        ));
+    }
+
+    #[test]
+    fn join_token_only_used_where_the_template_takes_it() {
+        let tpl = |template: &str, variables: Vec<(String, String)>| UserDataTemplate {
+            template: template.into(),
+            variables,
+            join_strategy: None,
+            k3s_server_url: None,
+        };
+        assert!(tpl("join {{ JOIN_TOKEN }}", vec![]).uses_join_token(None));
+        assert!(!tpl("join --discovery-file", vec![]).uses_join_token(None));
+        assert!(
+            !tpl(
+                "join {{ JOIN_TOKEN }}",
+                vec![("JOIN_TOKEN".into(), "from-secret".into())]
+            )
+            .uses_join_token(None)
+        );
+        let k3s = tpl("K3S_TOKEN={{ K3S_TOKEN }}", vec![]);
+        assert!(!k3s.uses_join_token(None));
+        assert!(k3s.uses_join_token(Some(JoinStrategy::K3s)));
+        assert!(tpl("machine: {}", vec![]).uses_join_token(Some(JoinStrategy::Talos)));
+    }
+
+    #[test]
+    fn render_talos_patches_machine_config() {
+        let offering = offering("cx22").with_region("nbg1").build();
+        let config = InstanceConfig {
+            labels: [("growth.vettrdev.com/pool".to_string(), "gpu".to_string())].into(),
+            taints: vec![Taint {
+                key: STARTUP_TAINT_KEY.into(),
+                effect: "NoExecute".into(),
+                ..Default::default()
+            }],
+            kubelet_args: vec!["--max-pods=200".into()],
+            join_token: Some("abcdef.0123456789abcdef".into()),
+            join_strategy: Some(JoinStrategy::Talos),
+        };
+        let template = UserDataTemplate {
+            template: "version: v1alpha1\n\
+                       machine:\n  type: worker\n  token: {{ TRUSTD_TOKEN }}\n  \
+                       nodeLabels:\n    topology.kubernetes.io/region: {{ REGION }}\n\
+                       cluster:\n  controlPlane:\n    endpoint: https://10.0.0.2:6443\n\
+                       ---\napiVersion: v1alpha1\nkind: HostnameConfig\nauto: stable\n"
+                .into(),
+            variables: vec![("TRUSTD_TOKEN".into(), "trustd.token".into())],
+            join_strategy: None,
+            k3s_server_url: None,
+        };
+
+        let rendered = template.render(&config, &offering).unwrap();
+        let docs: Vec<Value> = serde_yaml::Deserializer::from_str(&rendered)
+            .map(Value::deserialize)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(docs.len(), 2);
+        let machine = &docs[0]["machine"];
+        assert_eq!(machine["token"], "trustd.token");
+        assert_eq!(
+            machine["nodeLabels"]["topology.kubernetes.io/region"],
+            "nbg1"
+        );
+        assert_eq!(machine["nodeLabels"]["growth.vettrdev.com/pool"], "gpu");
+        assert_eq!(machine["nodeTaints"][STARTUP_TAINT_KEY], ":NoExecute");
+        assert_eq!(machine["kubelet"]["extraArgs"]["max-pods"], "200");
+        assert_eq!(docs[0]["cluster"]["token"], "abcdef.0123456789abcdef");
+        assert_eq!(docs[1]["kind"], "HostnameConfig");
+
+        let broken = UserDataTemplate {
+            template: "- not a machineconfig\n".into(),
+            variables: vec![],
+            ..template
+        };
+        assert!(matches!(
+            broken.render(&config, &offering),
+            Err(UserDataError::InvalidMachineConfig { .. })
+        ));
+    }
+
     #[test]
     fn resolve_template_unresolved_placeholder_errors() {
         let template = "token={{ JOIN_TOKEN }}\nserver={{ SERVER_URL }}\n";
@@ -0,0 +1,477 @@
+//! Offline workload simulation against recorded pending-pod traces.
+//!
+//! `replay()` steps a virtual clock through a [`trace`], running the same
+//! `reconcile_pod_demand` the controller uses and provisioning through a
+//! `Provider` (normally `FakeProvider`). Nothing touches a cluster, so a
+//! day-long trace replays in well under a second — useful for evaluating
+//! pool or solver option changes before rolling them out.
+//!
+//! The model is deliberately simple: nodes become Ready a fixed
+//! `join_delay` after creation, pending pods are bound first-fit to Ready
+//! nodes in their pool, and empty nodes are removed after `idle_timeout`.
+
+pub mod trace;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+use crate::controller::pods::{ClusterState, PoolConfig, reconcile_pod_demand};
+use crate::offering::{Offering, PodResources};
+use crate::optimiser::{ExistingNode, ScoredSolver, SolverBackend};
+use crate::providers::provider::{InstanceConfig, NodeId, Provider, ProviderCreateConfig};
+
+use trace::TraceEvent;
+
+/// Knobs for a simulation run.
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+    /// Pools available to the solver.
+    pub pools: Vec<PoolConfig>,
+    /// Virtual time between reconciles.
+    pub tick: Duration,
+    /// Time from a successful create until the node is Ready.
+    pub join_delay: Duration,
+    /// Ready nodes without pods for this long are removed.
+    pub idle_timeout: Duration,
+    /// How long to keep simulating after the last trace event.
+    pub drain: Duration,
+    /// Placement algorithm, as `GROWTH_SOLVER` picks for the controller.
+    pub solver: &'static dyn SolverBackend,
+}
+
+impl SimulationConfig {
+    pub fn new(pools: Vec<PoolConfig>) -> Self {
+        Self {
+            pools,
+            tick: Duration::from_secs(10),
+            join_delay: Duration::from_secs(60),
+            idle_timeout: Duration::from_secs(15),
+            drain: Duration::from_secs(600),
+            solver: &ScoredSolver,
+        }
+    }
+}
+
+/// Cluster state at one tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineSample {
+    pub at: Duration,
+    /// Pods not bound to a Ready node.
+    pub pending_pods: usize,
+    pub ready_nodes: usize,
+    pub provisioning_nodes: usize,
+    /// Hourly cost of every node that exists (Ready or provisioning).
+    pub cost_per_hour: f64,
+}
+
+/// Outcome of a simulation run.
+#[derive(Debug, Clone, Default)]
+pub struct SimulationReport {
+    pub timeline: Vec<TimelineSample>,
+    /// Total spend over the simulated period, in USD.
+    pub total_cost: f64,
+    pub nodes_created: usize,
+    pub create_failures: usize,
+    /// Sum over ticks of pending pods × tick length.
+    pub unmet_pod_seconds: f64,
+    pub peak_pending: usize,
+    /// Trace pods whose resource requests could not be parsed.
+    pub skipped_pods: usize,
+}
+
+impl SimulationReport {
+    /// Write the timeline as CSV with a header row.
+    pub fn write_csv(&self, mut w: impl Write) -> std::io::Result<()> {
+        writeln!(
+            w,
+            "seconds,pending_pods,ready_nodes,provisioning_nodes,cost_per_hour"
+        )?;
+        for s in &self.timeline {
+            writeln!(
+                w,
+                "{},{},{},{},{:.4}",
+                s.at.as_secs(),
+                s.pending_pods,
+                s.ready_nodes,
+                s.provisioning_nodes,
+                s.cost_per_hour
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct SimPod {
+    key: (String, String),
+    resources: PodResources,
+    node: Option<u64>,
+}
+
+struct SimNode {
+    pool: String,
+    offering: Offering,
+    ready_at: Duration,
+    idle_since: Option<Duration>,
+}
+
+impl SimNode {
+    fn is_ready(&self, now: Duration) -> bool {
+        self.ready_at <= now
+    }
+}
+
+fn pool_of(pod: &PodResources) -> &str {
+    pod.pool.as_deref().unwrap_or("default")
+}
+
+fn topology_labels(offering: &Offering) -> BTreeMap<String, String> {
+    let mut labels = BTreeMap::from([(
+        "topology.kubernetes.io/region".to_string(),
+        offering.location.region.0.clone(),
+    )]);
+    if let Some(zone) = &offering.location.zone {
+        labels.insert("topology.kubernetes.io/zone".into(), zone.0.clone());
+    }
+    labels
+}
+
+/// Replay `events` (sorted by time) and report cost and unmet demand over time.
+pub async fn replay(
+    events: &[TraceEvent],
+    config: &SimulationConfig,
+    provider: &dyn Provider,
+) -> SimulationReport {
+    let mut report = SimulationReport::default();
+    if config.tick.is_zero() {
+        warn!("simulation tick must be positive, nothing simulated");
+        return report;
+    }
+    let mut pods: Vec<SimPod> = Vec::new();
+    let mut nodes: BTreeMap<u64, SimNode> = BTreeMap::new();
+    let mut next_node = 0u64;
+    let mut next_event = 0usize;
+
+    let end = Duration::from_millis(events.last().map_or(0, TraceEvent::at_ms)) + config.drain;
+    let tick_secs = config.tick.as_secs_f64();
+    let mut now = Duration::ZERO;
+
+    while now <= end {
+        // 1. Apply trace events up to `now`.
+        while let Some(event) = events.get(next_event) {
+            if Duration::from_millis(event.at_ms()) > now {
+                break;
+            }
+            next_event += 1;
+            match event {
+                TraceEvent::Arrive { pod, .. } => match PodResources::from_pod(pod) {
+                    Ok(resources) => pods.push(SimPod {
+                        key: (resources.id.namespace.clone(), resources.id.name.clone()),
+                        resources,
+                        node: None,
+                    }),
+                    Err(e) => {
+                        warn!(error = %e, "skipping trace pod with unparseable requests");
+                        report.skipped_pods += 1;
+                    }
+                },
+                TraceEvent::Depart {
+                    namespace, name, ..
+                } => pods.retain(|p| p.key.0 != *namespace || p.key.1 != *name),
+            }
+        }
+
+        // 2. Bind pending pods first-fit onto Ready nodes in their pool.
+        for idx in 0..pods.len() {
+            if pods[idx].node.is_some() {
+                continue;
+            }
+            let pod = &pods[idx].resources;
+            let target = nodes.iter().find_map(|(&id, node)| {
+                if !node.is_ready(now) || node.pool != pool_of(pod) {
+                    return None;
+                }
+                let mut remaining = node.offering.resources.clone();
+                for bound in pods.iter().filter(|p| p.node == Some(id)) {
+                    remaining.subtract(&bound.resources.resources);
+                }
+                remaining.satisfies(&pod.resources).then_some(id)
+            });
+            pods[idx].node = target;
+        }
+
+        // 3. Remove nodes that have been idle long enough.
+        let mut expired = Vec::new();
+        for (&id, node) in nodes.iter_mut() {
+            if !node.is_ready(now) || pods.iter().any(|p| p.node == Some(id)) {
+                node.idle_since = None;
+                continue;
+            }
+            let since = *node.idle_since.get_or_insert(now);
+            if now - since >= config.idle_timeout {
+                expired.push(id);
+            }
+        }
+        for id in expired {
+            if let Err(e) = provider.delete(&NodeId(format!("sim-{id}"))).await {
+                warn!(error = %e, node = id, "simulated delete failed");
+            }
+            nodes.remove(&id);
+        }
+
+        // 4. Solve for whatever is still pending and provision.
+        let pending: Vec<PodResources> = pods
+            .iter()
+            .filter(|p| p.node.is_none())
+            .map(|p| p.resources.clone())
+            .collect();
+        if !pending.is_empty() {
+            let mut occupied_counts: HashMap<String, HashMap<String, u32>> = HashMap::new();
+            let mut in_flight_nodes: HashMap<String, Vec<ExistingNode>> = HashMap::new();
+            for node in nodes.values() {
+                *occupied_counts
+                    .entry(node.pool.clone())
+                    .or_default()
+                    .entry(node.offering.instance_type.0.clone())
+                    .or_default() += 1;
+                if !node.is_ready(now) {
+                    in_flight_nodes
+                        .entry(node.pool.clone())
+                        .or_default()
+                        .push(ExistingNode {
+                            resources: node.offering.resources.clone(),
+                            labels: topology_labels(&node.offering),
+                            taints: vec![],
+                        });
+                }
+            }
+            let result = reconcile_pod_demand(ClusterState {
+                demands: pending,
+                offerings: provider.offerings().await,
+                occupied_counts,
+                pools: config.pools.clone(),
+                in_flight_nodes,
+                offering_penalties: HashMap::new(),
+                quotas: vec![],
+                daemonsets: vec![],
+                cluster_nodes: HashMap::new(),
+                solver: config.solver,
+                max_new_nodes: None,
+                solve_concurrency: 0,
+            });
+            for demand in result.demands {
+                let id = next_node;
+                next_node += 1;
+                match provider
+                    .create(
+                        format!("sim-{id}"),
+                        &demand.target_offering,
+                        &InstanceConfig::default(),
+                        &ProviderCreateConfig::None,
+                    )
+                    .await
+                {
+                    Ok(_) => {
+                        report.nodes_created += 1;
+                        nodes.insert(
+                            id,
+                            SimNode {
+                                pool: demand.pool,
+                                offering: demand.target_offering,
+                                ready_at: now + config.join_delay,
+                                idle_since: None,
+                            },
+                        );
+                    }
+                    Err(e) => {
+                        debug!(error = %e, "simulated create failed");
+                        report.create_failures += 1;
+                    }
+                }
+            }
+        }
+
+        // 5. Sample.
+        let pending_pods = pods.iter().filter(|p| p.node.is_none()).count();
+        let ready_nodes = nodes.values().filter(|n| n.is_ready(now)).count();
+        let cost_per_hour: f64 = nodes.values().map(|n| n.offering.cost_per_hour).sum();
+        report.total_cost += cost_per_hour * tick_secs / 3600.0;
+        report.unmet_pod_seconds += pending_pods as f64 * tick_secs;
+        report.peak_pending = report.peak_pending.max(pending_pods);
+        report.timeline.push(TimelineSample {
+            at: now,
+            pending_pods,
+            ready_nodes,
+            provisioning_nodes: nodes.len() - ready_nodes,
+            cost_per_hour,
+        });
+
+        now += config.tick;
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimiser::SolveOptions;
+    use crate::providers::fake::{CreateBehavior, FakeProvider};
+    use crate::resources::node_pool::ServerTypeConfig;
+    use crate::testing;
+    use k8s_openapi::api::core::v1::{Container, Pod, PodSpec, ResourceRequirements};
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn offering() -> Offering {
+        testing::offering("cpx22").with_cost(3.6).build()
+    }
+
+    fn pool() -> PoolConfig {
+        PoolConfig {
+            name: "default".into(),
+            uid: "uid".into(),
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
+            server_types: vec![ServerTypeConfig {
+                name: "cpx22".into(),
+                max: 10,
+                min: 0,
+            }],
+            labels: BTreeMap::new(),
+            taints: vec![],
+            locations: None,
+            evacuating_zones: vec![],
+            unmet_offerings: vec![],
+            allow_cost_spike: false,
+            solve_options: SolveOptions::default(),
+            instance_families: None,
+        }
+    }
+
+    fn pod(name: &str, cpu: &str) -> Box<Pod> {
+        Box::new(Pod {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                namespace: Some("default".into()),
+                uid: Some(format!("uid-{name}")),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                containers: vec![Container {
+                    name: "c".into(),
+                    resources: Some(ResourceRequirements {
+                        requests: Some(BTreeMap::from([
+                            ("cpu".into(), Quantity(cpu.into())),
+                            ("memory".into(), Quantity("512Mi".into())),
+                        ])),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }),
+            status: None,
+        })
+    }
+
+    fn config() -> SimulationConfig {
+        SimulationConfig {
+            tick: Duration::from_secs(10),
+            join_delay: Duration::from_secs(20),
+            idle_timeout: Duration::from_secs(10),
+            drain: Duration::from_secs(60),
+            ..SimulationConfig::new(vec![pool()])
+        }
+    }
+
+    #[tokio::test]
+    async fn arrivals_are_provisioned_then_scaled_down() {
+        let provider = FakeProvider::new().with_offerings(vec![offering()]);
+        let events = vec![
+            TraceEvent::Arrive {
+                at_ms: 0,
+                pod: pod("a", "1"),
+            },
+            TraceEvent::Arrive {
+                at_ms: 0,
+                pod: pod("b", "1"),
+            },
+            TraceEvent::Depart {
+                at_ms: 40_000,
+                namespace: "default".into(),
+                name: "a".into(),
+            },
+            TraceEvent::Depart {
+                at_ms: 40_000,
+                namespace: "default".into(),
+                name: "b".into(),
+            },
+        ];
+
+        let report = replay(&events, &config(), &provider).await;
+
+        // Both pods fit on one 2-CPU node, Ready at t=20s.
+        assert_eq!(report.nodes_created, 1);
+        let at = |secs| {
+            report
+                .timeline
+                .iter()
+                .find(|s| s.at == Duration::from_secs(secs))
+                .unwrap()
+        };
+        assert_eq!(at(0).pending_pods, 2);
+        assert_eq!(at(0).provisioning_nodes, 1);
+        assert_eq!(at(20).pending_pods, 0);
+        assert_eq!(at(20).ready_nodes, 1);
+        // Idle from t=40s, removed once idle_timeout elapses.
+        assert_eq!(at(50).ready_nodes, 0);
+        assert_eq!(provider.delete_calls().len(), 1);
+
+        assert_eq!(report.peak_pending, 2);
+        assert!((report.unmet_pod_seconds - 40.0).abs() < 1e-9);
+        // 5 ticks × 10s at $3.60/h.
+        assert!((report.total_cost - 0.05).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn create_failures_leave_demand_unmet() {
+        let provider = FakeProvider::new()
+            .with_offerings(vec![offering()])
+            .with_default_create(CreateBehavior::OfferingUnavailable);
+        let events = vec![TraceEvent::Arrive {
+            at_ms: 0,
+            pod: pod("a", "1"),
+        }];
+
+        let report = replay(&events, &config(), &provider).await;
+
+        assert_eq!(report.nodes_created, 0);
+        assert_eq!(report.create_failures, report.timeline.len());
+        assert!(report.timeline.iter().all(|s| s.pending_pods == 1));
+        assert_eq!(report.total_cost, 0.0);
+    }
+
+    #[test]
+    fn csv_has_header_and_rows() {
+        let report = SimulationReport {
+            timeline: vec![TimelineSample {
+                at: Duration::from_secs(10),
+                pending_pods: 1,
+                ready_nodes: 2,
+                provisioning_nodes: 0,
+                cost_per_hour: 0.5,
+            }],
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        report.write_csv(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "seconds,pending_pods,ready_nodes,provisioning_nodes,cost_per_hour\n10,1,2,0,0.5000\n"
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Pending-pod traces: the on-disk format and a recorder that derives trace
+//! events from pod watch events.
+//!
+//! A trace is JSON Lines, one [`TraceEvent`] per line, ordered by `at_ms`.
+
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+
+use k8s_openapi::api::core::v1::Pod;
+use k8s_openapi::jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::controller::pods::is_pod_unschedulable;
+
+/// A pod entering or leaving the workload, relative to the start of the trace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum TraceEvent {
+    /// A pod became Pending. The full pod is kept so replay parses requests,
+    /// pool selectors and affinity exactly as the controller would.
+    Arrive { at_ms: u64, pod: Box<Pod> },
+    /// A previously arrived pod was deleted or finished.
+    Depart {
+        at_ms: u64,
+        namespace: String,
+        name: String,
+    },
+}
+
+impl TraceEvent {
+    pub fn at_ms(&self) -> u64 {
+        match self {
+            Self::Arrive { at_ms, .. } | Self::Depart { at_ms, .. } => *at_ms,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TraceError {
+    #[error("failed to read trace: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid trace event on line {line}: {source}")]
+    Parse {
+        line: usize,
+        source: serde_json::Error,
+    },
+}
+
+/// Read a JSON Lines trace. Blank lines are skipped; events are returned
+/// sorted by time.
+pub fn read_trace(reader: impl BufRead) -> Result<Vec<TraceEvent>, TraceError> {
+    let mut events = Vec::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&line).map_err(|source| TraceError::Parse {
+            line: idx + 1,
+            source,
+        })?;
+        events.push(event);
+    }
+    events.sort_by_key(TraceEvent::at_ms);
+    Ok(events)
+}
+
+/// Append a single event as one JSON line.
+pub fn write_event(mut writer: impl Write, event: &TraceEvent) -> std::io::Result<()> {
+    serde_json::to_writer(&mut writer, event)?;
+    writer.write_all(b"\n")
+}
+
+/// Turns pod watch events into trace events.
+///
+/// A pod *arrives* the first time the scheduler reports it unschedulable
+/// (`PodScheduled=False/Unschedulable`), i.e. when it would ask for capacity,
+/// and *departs* when it is deleted or reaches a terminal phase. Pods that
+/// schedule onto existing nodes, or were already scheduled when recording
+/// starts, are never recorded.
+pub struct TraceRecorder {
+    started: Timestamp,
+    seen: HashSet<(String, String)>,
+}
+
+impl TraceRecorder {
+    pub fn new(started: Timestamp) -> Self {
+        Self {
+            started,
+            seen: HashSet::new(),
+        }
+    }
+
+    fn offset_ms(&self, now: Timestamp) -> u64 {
+        now.duration_since(self.started).as_millis().max(0) as u64
+    }
+
+    /// Observe a pod that was added or modified.
+    pub fn observe_apply(&mut self, pod: &Pod, now: Timestamp) -> Option<TraceEvent> {
+        let key = pod_key(pod)?;
+        let phase = pod.status.as_ref().and_then(|s| s.phase.as_deref());
+        match phase {
+            Some("Pending") if is_pod_unschedulable(pod) && !self.seen.contains(&key) => {
+                self.seen.insert(key);
+                Some(TraceEvent::Arrive {
+                    at_ms: self.offset_ms(now),
+                    pod: Box::new(strip_pod(pod)),
+                })
+            }
+            Some("Succeeded") | Some("Failed") => self.depart(key, now),
+            _ => None,
+        }
+    }
+
+    /// Observe a pod that was deleted.
+    pub fn observe_delete(&mut self, pod: &Pod, now: Timestamp) -> Option<TraceEvent> {
+        let key = pod_key(pod)?;
+        self.depart(key, now)
+    }
+
+    fn depart(&mut self, key: (String, String), now: Timestamp) -> Option<TraceEvent> {
+        if !self.seen.remove(&key) {
+            return None;
+        }
+        let (namespace, name) = key;
+        Some(TraceEvent::Depart {
+            at_ms: self.offset_ms(now),
+            namespace,
+            name,
+        })
+    }
+}
+
+fn pod_key(pod: &Pod) -> Option<(String, String)> {
+    Some((
+        pod.metadata.namespace.clone().unwrap_or_default(),
+        pod.metadata.name.clone()?,
+    ))
+}
+
+/// Keep only what replay needs: identity, labels and spec.
+fn strip_pod(pod: &Pod) -> Pod {
+    Pod {
+        metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+            name: pod.metadata.name.clone(),
+            namespace: pod.metadata.namespace.clone(),
+            uid: pod.metadata.uid.clone(),
+            labels: pod.metadata.labels.clone(),
+            ..Default::default()
+        },
+        spec: pod.spec.clone(),
+        status: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{PodCondition, PodStatus};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    fn pod(name: &str, phase: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                namespace: Some("default".into()),
+                managed_fields: Some(vec![]),
+                ..Default::default()
+            },
+            spec: None,
+            status: Some(PodStatus {
+                phase: Some(phase.into()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn unschedulable(name: &str) -> Pod {
+        let mut pod = pod(name, "Pending");
+        pod.status.as_mut().unwrap().conditions = Some(vec![PodCondition {
+            type_: "PodScheduled".into(),
+            status: "False".into(),
+            reason: Some("Unschedulable".into()),
+            ..Default::default()
+        }]);
+        pod
+    }
+
+    fn at(secs: i64) -> Timestamp {
+        Timestamp::from_second(1_700_000_000 + secs).unwrap()
+    }
+
+    #[test]
+    fn records_arrival_once_and_departure_on_delete() {
+        let mut rec = TraceRecorder::new(at(0));
+        let arrive = rec.observe_apply(&unschedulable("a"), at(2)).unwrap();
+        assert_eq!(arrive.at_ms(), 2000);
+        if let TraceEvent::Arrive { pod, .. } = &arrive {
+            assert!(pod.status.is_none());
+            assert!(pod.metadata.managed_fields.is_none());
+        }
+        assert!(rec.observe_apply(&unschedulable("a"), at(3)).is_none());
+        assert!(rec.observe_apply(&pod("a", "Running"), at(4)).is_none());
+
+        let depart = rec.observe_delete(&pod("a", "Running"), at(9)).unwrap();
+        assert!(matches!(depart, TraceEvent::Depart { at_ms: 9000, ref name, .. } if name == "a"));
+    }
+
+    #[test]
+    fn terminal_phase_departs() {
+        let mut rec = TraceRecorder::new(at(0));
+        rec.observe_apply(&unschedulable("job"), at(0));
+        assert!(rec.observe_apply(&pod("job", "Succeeded"), at(5)).is_some());
+        assert!(
+            rec.observe_delete(&pod("job", "Succeeded"), at(6))
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn pods_never_seen_unschedulable_are_ignored() {
+        let mut rec = TraceRecorder::new(at(0));
+        assert!(rec.observe_apply(&pod("old", "Running"), at(1)).is_none());
+        assert!(rec.observe_delete(&pod("old", "Running"), at(2)).is_none());
+
+        // Pending only until the scheduler bound it to an existing node.
+        assert!(rec.observe_apply(&pod("fits", "Pending"), at(3)).is_none());
+        assert!(rec.observe_apply(&pod("fits", "Running"), at(4)).is_none());
+        assert!(rec.observe_delete(&pod("fits", "Running"), at(5)).is_none());
+    }
+
+    #[test]
+    fn trace_round_trips_and_sorts() {
+        let mut buf = Vec::new();
+        let late = TraceEvent::Depart {
+            at_ms: 500,
+            namespace: "default".into(),
+            name: "a".into(),
+        };
+        let early = TraceEvent::Arrive {
+            at_ms: 100,
+            pod: Box::new(strip_pod(&pod("a", "Pending"))),
+        };
+        write_event(&mut buf, &late).unwrap();
+        buf.extend_from_slice(b"\n");
+        write_event(&mut buf, &early).unwrap();
+
+        let events = read_trace(buf.as_slice()).unwrap();
+        assert_eq!(
+            events.iter().map(TraceEvent::at_ms).collect::<Vec<_>>(),
+            [100, 500]
+        );
+    }
+
+    #[test]
+    fn parse_error_reports_line() {
+        let err = read_trace("\n{\"event\":\"bogus\"}\n".as_bytes()).unwrap_err();
+        assert!(matches!(err, TraceError::Parse { line: 2, .. }));
+    }
+}
@@ -1,4 +1,5 @@
-//! Shared test helpers for both integration tests and the `test_pod` binary.
+//! Shared test helpers for unit and integration tests and the `test_pod`
+//! binary.
 //!
 //! Gated behind `#[cfg(any(test, feature = "testing"))]` in `lib.rs`.
 
@@ -14,7 +15,15 @@ use kube::{Api, Client, Config};
 use crate::resources::node_pool::{NodePool, NodePoolSpec, ServerTypeConfig};
 
 use crate::config::ControllerContext;
-use crate::offering::{MANAGED_BY_LABEL, MANAGED_BY_SELECTOR, POOL_LABEL, Resources};
+use crate::controller::cost_guard::CostGuard;
+use crate::controller::metrics::Metrics;
+use crate::controller::node_removal::executor::RemovalExecutor;
+use crate::controller::offering_stats::OfferingStatsStore;
+use crate::controller::provider_health::ProviderHealthMonitor;
+use crate::offering::{
+    InstanceType, Location, MANAGED_BY_LABEL, MANAGED_BY_SELECTOR, Offering, POOL_LABEL, Region,
+    Resources, Zone,
+};
 use crate::providers::kwok::to_capacity;
 use crate::providers::provider::Provider;
 use crate::resources::node_removal_request::NodeRemovalRequest;
@@ -69,12 +78,29 @@ pub fn init_tracing() {
 ///
 /// Uses the given provider and default timeouts / scale-down config.
 /// Tests needing custom `ScaleDownConfig` should construct `ControllerContext` directly.
-pub fn make_test_ctx(client: Client, provider: Provider) -> Arc<ControllerContext> {
+pub fn make_test_ctx(client: Client, provider: Arc<dyn Provider>) -> Arc<ControllerContext> {
     Arc::new(ControllerContext {
         client,
         provider,
         provisioning_timeout: Duration::from_secs(300),
+        max_inventory_drift: 5,
+        offering_stats: OfferingStatsStore::disabled(),
+        cost_guard: CostGuard::disabled(),
+        budget_admissions: Default::default(),
+        metrics: Metrics::new(),
+        provider_reloader: None,
+        provider_health: ProviderHealthMonitor::new(),
+        shard: crate::controller::shard::Shard::All,
         scale_down: crate::config::ScaleDownConfig::default(),
+        removals: RemovalExecutor::new(Duration::ZERO),
+        requeue: Default::default(),
+        crds: Default::default(),
+        webhook: None,
+        tunables: Default::default(),
+        config_reloader: None,
+        audit_log: None,
+        dry_run: false,
+        karpenter_compat: false,
         clock: Arc::new(crate::clock::SystemClock),
     })
 }
@@ -88,6 +114,76 @@ pub fn has_taint(node: &Node, key: &str, effect: &str) -> bool {
         .unwrap_or(false)
 }
 
+/// Builder for test [`Offering`]s, started by [`offering`].
+#[derive(Debug, Clone)]
+pub struct TestOffering(Offering);
+
+/// A `fake` provider offering of `instance_type` with 2 CPUs and 4096 MiB,
+/// in `eu-central` without a zone, at 0.01 an hour.
+pub fn offering(instance_type: &str) -> TestOffering {
+    TestOffering(Offering {
+        instance_type: InstanceType(instance_type.into()),
+        provider: "fake".into(),
+        resources: Resources {
+            cpu_millis: 2000,
+            memory_mib: 4096,
+            ephemeral_storage_gib: None,
+            gpu: 0,
+            gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
+        },
+        cost_per_hour: 0.01,
+        interruption_risk: 0.0,
+        location: Location {
+            region: Region("eu-central".into()),
+            zone: None,
+        },
+    })
+}
+
+impl TestOffering {
+    pub fn with_cpus(mut self, cpus: u64) -> Self {
+        self.0.resources.cpu_millis = cpus * 1000;
+        self
+    }
+
+    pub fn with_memory_mib(mut self, memory_mib: u64) -> Self {
+        self.0.resources.memory_mib = memory_mib;
+        self
+    }
+
+    pub fn with_resources(mut self, resources: Resources) -> Self {
+        self.0.resources = resources;
+        self
+    }
+
+    pub fn with_cost(mut self, cost_per_hour: f64) -> Self {
+        self.0.cost_per_hour = cost_per_hour;
+        self
+    }
+
+    pub fn with_provider(mut self, provider: &str) -> Self {
+        self.0.provider = provider.into();
+        self
+    }
+
+    pub fn with_region(mut self, region: &str) -> Self {
+        self.0.location.region = Region(region.into());
+        self
+    }
+
+    pub fn with_zone(mut self, zone: &str) -> Self {
+        self.0.location.zone = Some(Zone(zone.into()));
+        self
+    }
+
+    pub fn build(self) -> Offering {
+        self.0
+    }
+}
+
 /// Validate that a string looks like a Kubernetes resource quantity.
 pub fn validate_quantity(value: &str, field: &str) -> Result<()> {
     anyhow::ensure!(
@@ -205,9 +301,27 @@ pub async fn create_node_pool(
         name,
         NodePoolSpec {
             server_types,
+            min_nodes: 0,
+            max_nodes: None,
+            headroom: None,
             labels,
+            taints: vec![],
+            kubelet_args: vec![],
+            join_strategy: None,
             locations: None,
             node_class_ref: None,
+            evacuation: None,
+            consolidation: None,
+            disruption_budget: None,
+            fragmentation_penalty: None,
+            target_utilization: None,
+            interruption_penalty: None,
+            cost_objective: None,
+            instance_families: None,
+            max_additional_cost_per_hour: None,
+            max_new_nodes: None,
+            include_instance_types: None,
+            exclude_instance_types: None,
         },
     );
     api.create(&PostParams::default(), &np).await?;
@@ -279,12 +393,27 @@ pub async fn nuke(client: Client) -> Result<()> {
     }
     println!("deleted {nrr_count} NodeRemovalRequests");
 
-    // Delete all NodeRequests
+    // Delete all NodeRequests (finalizers stripped, their nodes are already gone)
     let nrs: Api<NodeRequest> = Api::all(client.clone());
     let nr_list = nrs.list(&ListParams::default()).await?;
     let nr_count = nr_list.items.len();
     for nr in &nr_list {
         let name = nr.metadata.name.as_deref().unwrap_or("?");
+        if nr
+            .metadata
+            .finalizers
+            .as_ref()
+            .is_some_and(|f| !f.is_empty())
+        {
+            let patch = serde_json::json!({ "metadata": { "finalizers": null } });
+            let _ = nrs
+                .patch(
+                    name,
+                    &kube::api::PatchParams::apply("growthrs-test"),
+                    &kube::api::Patch::Merge(patch),
+                )
+                .await;
+        }
         let _ = nrs.delete(name, &DeleteParams::default()).await;
     }
     println!("deleted {nr_count} NodeRequests");
@@ -376,7 +505,6 @@ pub async fn create_kwok_node(
                 last_transition_time: Some(now),
                 reason: Some("KwokReady".into()),
                 message: Some("KWOK test node".into()),
-                ..Default::default()
             }]),
             ..Default::default()
         }),
@@ -393,6 +521,7 @@ pub async fn create_kwok_node(
 /// `app_label` is set as both a pod label and the matchLabels selector.
 /// `topology_key` is typically `topology.kubernetes.io/zone`.
 /// If `anti` is true, creates anti-affinity; otherwise affinity.
+#[allow(clippy::too_many_arguments)]
 pub async fn create_pod_with_affinity(
     client: Client,
     name: &str,
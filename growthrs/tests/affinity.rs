@@ -11,11 +11,14 @@ use growthrs::testing;
 
 fn cpx22_resources() -> Resources {
     Resources {
-        cpu: 2,
+        cpu_millis: 2000,
         memory_mib: 4096,
         ephemeral_storage_gib: Some(40),
         gpu: 0,
         gpu_model: None,
+        arch: None,
+        mig: BTreeMap::new(),
+        extended: BTreeMap::new(),
     }
 }
 
@@ -34,13 +34,18 @@ use k8s_openapi::api::core::v1::Node;
 use kube::api::Api;
 
 use growthrs::config::ControllerContext;
+use growthrs::controller::cost_guard::CostGuard;
+use growthrs::controller::metrics::Metrics;
+use growthrs::controller::node_removal::executor::RemovalExecutor;
 use growthrs::controller::node_removal::{reconcile_node_removal_request, scan_idle_nodes};
+use growthrs::controller::offering_stats::OfferingStatsStore;
+use growthrs::controller::provider_health::ProviderHealthMonitor;
+use growthrs::controller::shard::Shard;
 use growthrs::offering::{
     DELETE_AT_ANNOTATION, INSTANCE_TYPE_LABEL, MANAGED_BY_LABEL, MANAGED_BY_VALUE, POOL_LABEL,
     REMOVAL_CANDIDATE_ANNOTATION,
 };
 use growthrs::providers::kwok::KwokProvider;
-use growthrs::providers::provider::Provider;
 use growthrs::resources::node_pool::ServerTypeConfig;
 use growthrs::resources::node_removal_request::NodeRemovalRequest;
 use growthrs::testing;
@@ -48,13 +53,32 @@ use growthrs::testing;
 fn make_ctx(client: kube::Client) -> Arc<ControllerContext> {
     Arc::new(ControllerContext {
         client: client.clone(),
-        provider: Provider::Kwok(KwokProvider::new(client)),
+        provider: Arc::new(KwokProvider::new(client)),
         provisioning_timeout: Duration::from_secs(300),
+        max_inventory_drift: 5,
+        offering_stats: OfferingStatsStore::disabled(),
+        cost_guard: CostGuard::disabled(),
+        budget_admissions: Default::default(),
+        metrics: Metrics::new(),
+        provider_reloader: None,
+        provider_health: ProviderHealthMonitor::new(),
+        shard: Shard::All,
         scale_down: growthrs::config::ScaleDownConfig {
             cooling_off_duration: Duration::from_secs(0),
             max_removal_attempts: 3,
             unmet_ttl: Duration::from_secs(120),
+            ready_ttl: None,
+            rebalance_replacement: false,
         },
+        removals: RemovalExecutor::new(Duration::ZERO),
+        requeue: Default::default(),
+        crds: Default::default(),
+        webhook: None,
+        tunables: Default::default(),
+        config_reloader: None,
+        audit_log: None,
+        dry_run: false,
+        karpenter_compat: false,
         clock: Arc::new(growthrs::clock::SystemClock),
     })
 }
@@ -82,11 +106,14 @@ async fn setup(client: kube::Client) {
         client.clone(),
         "cancel-test-node",
         &growthrs::offering::Resources {
-            cpu: 2,
+            cpu_millis: 2000,
             memory_mib: 4096,
             ephemeral_storage_gib: None,
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         },
         BTreeMap::from([
             (MANAGED_BY_LABEL.into(), MANAGED_BY_VALUE.into()),
@@ -6,7 +6,8 @@ use k8s_openapi::api::core::v1::{
 use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use kube::api::ObjectMeta;
 
-use growthrs::offering::{InstanceType, Location, Offering, Region, Resources, Zone};
+use growthrs::offering::Offering;
+use growthrs::testing::offering;
 
 pub fn pending_pod(name: &str, cpu: &str, memory: &str) -> Pod {
     Pod {
@@ -47,19 +48,10 @@ pub fn pending_pod(name: &str, cpu: &str, memory: &str) -> Pod {
 }
 
 pub fn test_offering(name: &str, cpu: u32, memory_mib: u32, cost: f64) -> Offering {
-    Offering {
-        instance_type: InstanceType(name.into()),
-        resources: Resources {
-            cpu,
-            memory_mib,
-            ephemeral_storage_gib: None,
-            gpu: 0,
-            gpu_model: None,
-        },
-        cost_per_hour: cost,
-        location: Location {
-            region: Region("eu-central".into()),
-            zone: Some(Zone("fsn1-dc14".into())),
-        },
-    }
+    offering(name)
+        .with_cpus(u64::from(cpu))
+        .with_memory_mib(u64::from(memory_mib))
+        .with_cost(cost)
+        .with_zone("fsn1-dc14")
+        .build()
 }
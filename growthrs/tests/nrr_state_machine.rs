@@ -29,10 +29,16 @@ use kube::runtime::controller::Action;
 
 use growthrs::clock::{Clock, TestClock};
 use growthrs::config::{ControllerContext, ScaleDownConfig};
+use growthrs::controller::cost_guard::CostGuard;
+use growthrs::controller::metrics::Metrics;
+use growthrs::controller::node_removal::executor::RemovalExecutor;
 use growthrs::controller::node_removal::reconcile_node_removal_request;
+use growthrs::controller::offering_stats::OfferingStatsStore;
+use growthrs::controller::provider_health::ProviderHealthMonitor;
+use growthrs::controller::shard::Shard;
 use growthrs::offering::{INSTANCE_TYPE_LABEL, MANAGED_BY_LABEL, MANAGED_BY_VALUE, POOL_LABEL};
 use growthrs::providers::fake::{DeleteBehavior, FakeProvider, StatusBehavior};
-use growthrs::providers::provider::{Provider, ProviderStatus};
+use growthrs::providers::provider::ProviderStatus;
 use growthrs::resources::node_removal_request::{
     NodeRemovalRequest, NodeRemovalRequestPhase, NodeRemovalRequestStatus,
 };
@@ -52,13 +58,32 @@ fn make_ctx(
 ) -> Arc<ControllerContext> {
     Arc::new(ControllerContext {
         client: client.clone(),
-        provider: Provider::Fake(provider),
+        provider: Arc::new(provider),
         provisioning_timeout: Duration::from_secs(300),
+        max_inventory_drift: 5,
+        offering_stats: OfferingStatsStore::disabled(),
+        cost_guard: CostGuard::disabled(),
+        budget_admissions: Default::default(),
+        metrics: Metrics::new(),
+        provider_reloader: None,
+        provider_health: ProviderHealthMonitor::new(),
+        shard: Shard::All,
         scale_down: ScaleDownConfig {
             cooling_off_duration: cooling_off,
             max_removal_attempts,
             unmet_ttl: Duration::from_secs(120),
+            ready_ttl: None,
+            rebalance_replacement: false,
         },
+        removals: RemovalExecutor::new(Duration::ZERO),
+        requeue: Default::default(),
+        crds: Default::default(),
+        webhook: None,
+        tunables: Default::default(),
+        config_reloader: None,
+        audit_log: None,
+        dry_run: false,
+        karpenter_compat: false,
         clock,
     })
 }
@@ -76,11 +101,14 @@ async fn create_idle_node(client: kube::Client, name: &str) {
         client,
         name,
         &growthrs::offering::Resources {
-            cpu: 2,
+            cpu_millis: 2000,
             memory_mib: 4096,
             ephemeral_storage_gib: None,
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         },
         growth_labels(),
     )
@@ -118,6 +146,7 @@ async fn patch_nrr_status(
         phase,
         removal_attempts,
         last_transition_time: None,
+        delete_requested_at: None,
     };
     let patch = serde_json::json!({ "status": status });
     api.patch_status(
@@ -154,7 +183,13 @@ async fn e2e_cooling_off_not_elapsed() {
     // Cooling-off = 5 minutes → reconcile should requeue, not delete.
     let provider = FakeProvider::new();
     let clock = Arc::new(TestClock::new());
-    let ctx = make_ctx(client.clone(), provider, Duration::from_secs(300), clock, 3);
+    let ctx = make_ctx(
+        client.clone(),
+        provider.clone(),
+        Duration::from_secs(300),
+        clock,
+        3,
+    );
 
     let nrr = get_nrr(&client, "nrr-co-node").await.unwrap();
     let action = reconcile_node_removal_request(Arc::new(nrr), ctx.clone())
@@ -169,12 +204,10 @@ async fn e2e_cooling_off_not_elapsed() {
     assert_eq!(nrr.phase(), NodeRemovalRequestPhase::Pending);
 
     // Provider should not have been called.
-    if let Provider::Fake(ref f) = ctx.provider {
-        assert!(
-            f.delete_calls().is_empty(),
-            "provider.delete should not be called during cooling-off"
-        );
-    }
+    assert!(
+        provider.delete_calls().is_empty(),
+        "provider.delete should not be called during cooling-off"
+    );
 
     testing::nuke(client).await.unwrap();
 }
@@ -201,7 +234,7 @@ async fn e2e_idle_node_removal() {
     let clock = Arc::new(TestClock::new());
     let ctx = make_ctx(
         client.clone(),
-        provider,
+        provider.clone(),
         Duration::from_secs(300),
         clock.clone(),
         3,
@@ -219,12 +252,10 @@ async fn e2e_idle_node_removal() {
     assert_eq!(nrr.phase(), NodeRemovalRequestPhase::Deprovisioning);
 
     // Provider should not have been called.
-    if let Provider::Fake(ref f) = ctx.provider {
-        assert!(
-            !f.delete_calls().is_empty(),
-            "provider.delete should be called to delete the node"
-        );
-    }
+    assert!(
+        !provider.delete_calls().is_empty(),
+        "provider.delete should be called to delete the node"
+    );
 
     testing::nuke(client).await.unwrap();
 }
@@ -248,7 +279,13 @@ async fn e2e_cooling_off_elapsed_to_deprovisioning() {
     // Cooling-off = 0 → should proceed immediately.
     let provider = FakeProvider::new().with_default_delete(DeleteBehavior::Succeed);
     let clock = Arc::new(TestClock::new());
-    let ctx = make_ctx(client.clone(), provider, Duration::from_secs(0), clock, 3);
+    let ctx = make_ctx(
+        client.clone(),
+        provider.clone(),
+        Duration::from_secs(0),
+        clock,
+        3,
+    );
 
     let nrr = get_nrr(&client, "nrr-deprov-node").await.unwrap();
     reconcile_node_removal_request(Arc::new(nrr), ctx.clone())
@@ -261,9 +298,7 @@ async fn e2e_cooling_off_elapsed_to_deprovisioning() {
     assert_eq!(nrr.status.as_ref().unwrap().removal_attempts, 1);
 
     // Provider.delete should have been called.
-    if let Provider::Fake(ref f) = ctx.provider {
-        assert_eq!(f.delete_calls().len(), 1);
-    }
+    assert_eq!(provider.delete_calls().len(), 1);
 
     testing::nuke(client).await.unwrap();
 }
@@ -285,7 +320,13 @@ async fn e2e_node_404_triggers_provider_delete() {
 
     let provider = FakeProvider::new().with_default_delete(DeleteBehavior::Succeed);
     let clock = Arc::new(TestClock::new());
-    let ctx = make_ctx(client.clone(), provider, Duration::from_secs(0), clock, 3);
+    let ctx = make_ctx(
+        client.clone(),
+        provider.clone(),
+        Duration::from_secs(0),
+        clock,
+        3,
+    );
 
     let nrr = get_nrr(&client, "nrr-ghost-node").await.unwrap();
     reconcile_node_removal_request(Arc::new(nrr), ctx.clone())
@@ -293,13 +334,11 @@ async fn e2e_node_404_triggers_provider_delete() {
         .unwrap();
 
     // Provider.delete should have been called (to clean up the VM).
-    if let Provider::Fake(ref f) = ctx.provider {
-        assert_eq!(
-            f.delete_calls().len(),
-            1,
-            "provider.delete must be called when K8s Node is 404"
-        );
-    }
+    assert_eq!(
+        provider.delete_calls().len(),
+        1,
+        "provider.delete must be called when K8s Node is 404"
+    );
 
     // NRR should be deleted (finalizer removed, then NRR deleted).
     let nrr = get_nrr(&client, "nrr-ghost-node").await;
@@ -498,7 +537,13 @@ async fn could_not_remove_is_terminal() {
 
     let provider = FakeProvider::new();
     let clock = Arc::new(TestClock::new());
-    let ctx = make_ctx(client.clone(), provider, Duration::from_secs(0), clock, 3);
+    let ctx = make_ctx(
+        client.clone(),
+        provider.clone(),
+        Duration::from_secs(0),
+        clock,
+        3,
+    );
 
     let nrr = get_nrr(&client, "nrr-terminal-node").await.unwrap();
     let action = reconcile_node_removal_request(Arc::new(nrr), ctx.clone())
@@ -513,10 +558,8 @@ async fn could_not_remove_is_terminal() {
     );
 
     // No provider calls.
-    if let Provider::Fake(ref f) = ctx.provider {
-        assert!(f.delete_calls().is_empty());
-        assert!(f.status_calls().is_empty());
-    }
+    assert!(provider.delete_calls().is_empty());
+    assert!(provider.status_calls().is_empty());
 
     testing::nuke(client).await.unwrap();
 }
@@ -21,21 +21,26 @@ use growthrs::offering::{
 };
 use growthrs::providers::fake::FakeProvider;
 use growthrs::providers::kwok::KwokProvider;
-use growthrs::providers::provider::{InstanceConfig, Provider};
+use growthrs::providers::provider::{InstanceConfig, Provider, ProviderCreateConfig};
 use growthrs::resources::node_request::{NodeRequest, NodeRequestPhase, NodeRequestSpec};
 use growthrs::testing;
 
 fn cpx22_offering() -> Offering {
     Offering {
         instance_type: InstanceType("cpx22".into()),
+        provider: "kwok".into(),
         resources: Resources {
-            cpu: 2,
+            cpu_millis: 2000,
             memory_mib: 4096,
             ephemeral_storage_gib: Some(40),
             gpu: 0,
             gpu_model: None,
+            arch: None,
+            mig: BTreeMap::new(),
+            extended: BTreeMap::new(),
         },
         cost_per_hour: 0.01,
+        interruption_risk: 0.0,
         location: Location {
             region: Region("eu-central".into()),
             zone: Some(Zone("fsn1-dc14".into())),
@@ -60,11 +65,17 @@ async fn e2e_kwok_node_has_startup_taint_removed_on_ready() {
             (INSTANCE_TYPE_LABEL.into(), "cpx22".into()),
             (NODE_REQUEST_LABEL.into(), "nr-taint-test".into()),
         ]),
+        ..Default::default()
     };
     let offering = cpx22_offering();
-    kwok.create("taint-test-node".into(), &offering, &config)
-        .await
-        .unwrap();
+    kwok.create(
+        "taint-test-node".into(),
+        &offering,
+        &config,
+        &ProviderCreateConfig::None,
+    )
+    .await
+    .unwrap();
 
     // Verify the node was created with the startup taint.
     let nodes: Api<Node> = Api::all(client.clone());
@@ -86,7 +97,12 @@ async fn e2e_kwok_node_has_startup_taint_removed_on_ready() {
             node_id: "taint-test-node".into(),
             target_offering: InstanceType("cpx22".into()),
             location: Region("eu-central".into()),
+            zone: None,
             resources: offering.resources,
+            provider: None,
+            claimed_pods: vec![],
+            labels: None,
+            taints: None,
         },
         status: None,
     };
@@ -112,7 +128,7 @@ async fn e2e_kwok_node_has_startup_taint_removed_on_ready() {
         .unwrap();
 
     // Call the reconciler directly (simulates what the node watcher mapper triggers).
-    let ctx = testing::make_test_ctx(client.clone(), Provider::Fake(FakeProvider::new()));
+    let ctx = testing::make_test_ctx(client.clone(), Arc::new(FakeProvider::new()));
     let nr_obj = Arc::new(nr_api.get("nr-taint-test").await.unwrap());
     let action = reconcile_node_request(nr_obj, ctx).await.unwrap();
     assert_eq!(action, Action::await_change());
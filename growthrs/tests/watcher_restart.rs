@@ -22,7 +22,6 @@ use std::time::Duration;
 use growthrs::config::ControllerContext;
 use growthrs::controller::run_pod_watcher;
 use growthrs::providers::kwok::KwokProvider;
-use growthrs::providers::provider::Provider;
 use growthrs::resources::node_request::NodeRequest;
 use growthrs::testing;
 
@@ -31,7 +30,7 @@ const EXPECTED_NRS: usize = 30;
 const POD_COUNT: u32 = 60;
 
 fn make_ctx(client: kube::Client) -> Arc<ControllerContext> {
-    testing::make_test_ctx(client.clone(), Provider::Kwok(KwokProvider::new(client)))
+    testing::make_test_ctx(client.clone(), Arc::new(KwokProvider::new(client)))
 }
 
 /// Clean slate: nuke, create pool + pods, wait for all pods Unschedulable.